@@ -0,0 +1,14 @@
+#!/bin/bash
+# Checks that channels_sv2 works without `std`:
+# - builds it for a bare metal target, where `std` doesn't exist
+# - runs its unit tests with the collections used without `std`
+#
+# Building for the bare metal target needs a C cross compiler for secp256k1 (pulled in by the
+# `bitcoin` crate), e.g. `gcc-arm-none-eabi` on Debian/Ubuntu.
+set -e
+
+TARGET="${TARGET:-thumbv7em-none-eabihf}"
+
+rustup target add "$TARGET"
+cargo build -p channels_sv2 --no-default-features --features alloc --target "$TARGET"
+cargo test -p channels_sv2 --no-default-features --features alloc
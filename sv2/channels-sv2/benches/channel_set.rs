@@ -0,0 +1,101 @@
+// Propagation of a new template to 10k synthetic channels, serially and (with the `rayon`
+// feature) across threads:
+//
+// cargo bench -p channels_sv2 --features rayon
+use bitcoin::{transaction::TxOut, Amount, ScriptBuf};
+use channels_sv2::server::{
+    channel_set::ChannelSet,
+    jobs::{job_store::DefaultJobStore, standard::StandardJob},
+    standard::{StandardChannel, StandardChannelConfig},
+};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use mining_sv2::Target;
+use std::convert::TryInto;
+use template_distribution_sv2::{NewTemplate, SetNewPrevHash};
+
+const CHANNELS: u32 = 10_000;
+const SATS_AVAILABLE_IN_TEMPLATE: u64 = 5000000000;
+
+// Channels with a chain tip already set, so that they accept non-future templates.
+fn channel_set() -> ChannelSet<'static> {
+    let mut channel_set = ChannelSet::new();
+    for channel_id in 1..=CHANNELS {
+        let mut extranonce_prefix = vec![0; 32];
+        extranonce_prefix[28..].copy_from_slice(&channel_id.to_be_bytes());
+        let channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(channel_id)
+                .user_identity("user_identity".to_string())
+                .extranonce_prefix(extranonce_prefix)
+                .requested_max_target(Target::MAX)
+                .nominal_hashrate(10.0)
+                .share_batch_size(100)
+                .expected_share_per_minute(1.0),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        channel_set.insert(channel);
+    }
+    channel_set.on_new_template(template(1, true), coinbase_reward_outputs());
+    channel_set.on_set_new_prev_hash(SetNewPrevHash {
+        template_id: 1,
+        prev_hash: [0xaa; 32].into(),
+        header_timestamp: 1747092633,
+        n_bits: 503543726,
+        target: [0xff; 32].into(),
+    });
+    channel_set
+}
+
+fn template(template_id: u64, future_template: bool) -> NewTemplate<'static> {
+    NewTemplate {
+        template_id,
+        future_template,
+        version: 536870912,
+        coinbase_tx_version: 2,
+        coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
+        coinbase_tx_input_sequence: 4294967294,
+        coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
+        coinbase_tx_outputs_count: 0,
+        coinbase_tx_outputs: vec![].try_into().unwrap(),
+        coinbase_tx_locktime: 158,
+        merkle_path: vec![[0x11; 32].into(); 12].try_into().unwrap(),
+    }
+}
+
+fn coinbase_reward_outputs() -> Vec<TxOut> {
+    let mut script = vec![0, 20];
+    script.extend_from_slice(&[0x22; 20]);
+    vec![TxOut {
+        value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
+        script_pubkey: ScriptBuf::from(script),
+    }]
+}
+
+fn on_new_template(c: &mut Criterion) {
+    let mut group = c.benchmark_group("on_new_template");
+    group.sample_size(10);
+    group.bench_function("serial", |b| {
+        b.iter_batched_ref(
+            channel_set,
+            |channel_set| {
+                channel_set.on_new_template(template(2, false), coinbase_reward_outputs())
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    #[cfg(feature = "rayon")]
+    group.bench_function("parallel", |b| {
+        b.iter_batched_ref(
+            channel_set,
+            |channel_set| {
+                channel_set.par_on_new_template(template(2, false), coinbase_reward_outputs())
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, on_new_template);
+criterion_main!(benches);
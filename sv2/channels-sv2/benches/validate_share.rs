@@ -0,0 +1,88 @@
+// Hot path of a Standard Channel: share validation and target updates, with log statements
+// forwarded to `tracing` (debug level disabled, as no subscriber is installed) or compiled out:
+//
+// cargo bench -p channels_sv2 --bench validate_share
+// cargo bench -p channels_sv2 --bench validate_share --features no-trace
+use bitcoin::{Amount, ScriptBuf};
+use channels_sv2::prelude::*;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::convert::TryInto;
+
+const SATS_AVAILABLE_IN_TEMPLATE: u64 = 5000000000;
+
+// A channel with an active job and a target low enough for shares to (practically) never meet
+// it, so that the same share can be validated over and over.
+fn channel() -> StandardChannel<'static> {
+    let mut channel = StandardChannel::from_config(
+        StandardChannelConfig::default()
+            .channel_id(1)
+            .user_identity("user_identity")
+            .extranonce_prefix(vec![0; 32])
+            .requested_max_target(Target::MAX)
+            .nominal_hashrate(1e18)
+            .expected_share_per_minute(1.0),
+        Box::new(DefaultJobStore::<StandardJob>::new()),
+    )
+    .unwrap();
+
+    let mut script = vec![0, 20];
+    script.extend_from_slice(&[0x22; 20]);
+    channel
+        .on_new_template(
+            NewTemplate {
+                template_id: 1,
+                future_template: true,
+                version: 536870912,
+                coinbase_tx_version: 2,
+                coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
+                coinbase_tx_input_sequence: 4294967294,
+                coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
+                coinbase_tx_outputs_count: 0,
+                coinbase_tx_outputs: vec![].try_into().unwrap(),
+                coinbase_tx_locktime: 158,
+                merkle_path: vec![[0x11; 32].into(); 12].try_into().unwrap(),
+            },
+            vec![TxOut {
+                value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
+                script_pubkey: ScriptBuf::from(script),
+            }],
+        )
+        .unwrap();
+    channel
+        .on_set_new_prev_hash(SetNewPrevHashTdp {
+            template_id: 1,
+            prev_hash: [0xaa; 32].into(),
+            header_timestamp: 1747092633,
+            n_bits: 503543726,
+            target: [0xff; 32].into(),
+        })
+        .unwrap();
+    channel
+}
+
+fn hot_path(c: &mut Criterion) {
+    let mut channel = channel();
+    let share = SubmitSharesStandard {
+        channel_id: 1,
+        sequence_number: 0,
+        job_id: 1,
+        nonce: 0,
+        ntime: 1747092633,
+        version: 536870912,
+    };
+    c.bench_function("validate_share", |b| {
+        b.iter(|| channel.validate_share(share.clone()))
+    });
+
+    let mut nominal_hashrate = 1e18;
+    c.bench_function("update_channel", |b| {
+        b.iter(|| {
+            // alternate between two hashrates, so that the target actually changes
+            nominal_hashrate = 3e18 - nominal_hashrate;
+            channel.update_channel(nominal_hashrate, None)
+        })
+    });
+}
+
+criterion_group!(benches, hot_path);
+criterion_main!(benches);
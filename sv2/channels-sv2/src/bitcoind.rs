@@ -0,0 +1,315 @@
+//! # Bitcoin Core compatibility
+//!
+//! Conversions from the `getblocktemplate` RPC of Bitcoin Core, for solo setups running channels
+//! against a local `bitcoind` instead of a Template Provider.
+//!
+//! The RPC prints hashes in the byte order of block explorers, i.e. reversed with respect to the
+//! order they are hashed and sent on the wire in. All the conversions below take care of it.
+//!
+//! A response on top of a new `previousblockhash` is fed to the channels as a future template,
+//! followed by the `SetNewPrevHash` activating it:
+//!
+//! ```ignore
+//! let template = NewTemplate {
+//!     future_template: true,
+//!     ..NewTemplate::try_from_gbt(&gbt, template_id)?
+//! };
+//! channel.on_new_template(template, coinbase_reward_outputs)?;
+//! channel.on_set_new_prev_hash(SetNewPrevHash::try_from_gbt(&gbt, template_id)?)?;
+//! ```
+//!
+//! Later responses on the same `previousblockhash` are fed as they are.
+//!
+//! Only available with the `bitcoind-compat` feature.
+use crate::chain_tip::ChainTip;
+use alloc::{string::String, vec::Vec};
+use bitcoin::{
+    consensus::Encodable,
+    hashes::{sha256d, Hash},
+    script::Builder,
+    transaction::TxOut,
+    Amount, BlockHash, CompactTarget, ScriptBuf, Target, Txid,
+};
+use core::{convert::TryInto, str::FromStr};
+use serde::Deserialize;
+use template_distribution_sv2::{NewTemplate, SetNewPrevHash};
+
+/// The version of the coinbase transaction of the templates, as set by Bitcoin Core.
+const COINBASE_TX_VERSION: u32 = 2;
+
+/// The subset of a `getblocktemplate` response needed to build templates.
+///
+/// Fields are named as in the RPC, other fields of the response are ignored.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GbtResponse {
+    pub version: u32,
+    pub previousblockhash: String,
+    pub transactions: Vec<GbtTransaction>,
+    pub coinbasevalue: u64,
+    pub curtime: u32,
+    pub bits: String,
+    pub height: u32,
+    /// Absent when no transaction of the template has a witness.
+    #[serde(default)]
+    pub default_witness_commitment: Option<String>,
+}
+
+/// A transaction of a [`GbtResponse`], other than the coinbase.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GbtTransaction {
+    pub txid: String,
+}
+
+/// The ways a [`GbtResponse`] can fail to convert.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GbtError {
+    /// `previousblockhash` is not a 32 bytes hex string.
+    InvalidPrevHash,
+    /// `bits` is not a 4 bytes hex string.
+    InvalidBits,
+    /// `default_witness_commitment` is not a hex string.
+    InvalidWitnessCommitment,
+    /// The `txid` of the transaction at this index of `transactions` is not a 32 bytes hex
+    /// string.
+    InvalidTxid(usize),
+}
+
+impl ChainTip {
+    /// Builds the chain tip a `getblocktemplate` response is on top of, out of its
+    /// `previousblockhash`, `bits` and `curtime`.
+    pub fn from_gbt(prev_hash_hex: &str, bits_hex: &str, curtime: u32) -> Result<Self, GbtError> {
+        ChainTip::new(
+            parse_prev_hash(prev_hash_hex)?,
+            parse_bits(bits_hex)?,
+            curtime,
+        )
+        .map_err(|_| GbtError::InvalidPrevHash)
+    }
+}
+
+/// Conversion of a `getblocktemplate` response into a Template Distribution message.
+///
+/// The `template_id` is up to the caller, as Bitcoin Core doesn't assign any.
+pub trait TryFromGbt: Sized {
+    fn try_from_gbt(gbt: &GbtResponse, template_id: u64) -> Result<Self, GbtError>;
+}
+
+/// A non-future template, whose coinbase pays `coinbasevalue` on top of the witness commitment.
+///
+/// The coinbase follows Bitcoin Core: its script sig starts with the BIP34 height, and its lock
+/// time is the height of the previous block.
+impl TryFromGbt for NewTemplate<'static> {
+    fn try_from_gbt(gbt: &GbtResponse, template_id: u64) -> Result<Self, GbtError> {
+        let mut coinbase_tx_outputs = vec![];
+        if let Some(commitment) = &gbt.default_witness_commitment {
+            let output = TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::from_hex(commitment)
+                    .map_err(|_| GbtError::InvalidWitnessCommitment)?,
+            };
+            output
+                .consensus_encode(&mut coinbase_tx_outputs)
+                .expect("writing to a Vec can't fail");
+        }
+        let coinbase_tx_outputs_count = !coinbase_tx_outputs.is_empty() as u32;
+
+        let txids = gbt
+            .transactions
+            .iter()
+            .enumerate()
+            .map(|(index, transaction)| {
+                Txid::from_str(&transaction.txid)
+                    .map(|txid| txid.to_byte_array())
+                    .map_err(|_| GbtError::InvalidTxid(index))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let coinbase_prefix = Builder::new()
+            .push_int(gbt.height as i64)
+            .into_script()
+            .into_bytes();
+
+        Ok(NewTemplate {
+            template_id,
+            future_template: false,
+            version: gbt.version,
+            coinbase_tx_version: COINBASE_TX_VERSION,
+            coinbase_prefix: coinbase_prefix
+                .try_into()
+                .expect("a BIP34 height is at most 5 bytes"),
+            coinbase_tx_input_sequence: u32::MAX - 1,
+            coinbase_tx_value_remaining: gbt.coinbasevalue,
+            coinbase_tx_outputs_count,
+            coinbase_tx_outputs: coinbase_tx_outputs
+                .try_into()
+                .expect("a witness commitment is 38 bytes"),
+            coinbase_tx_locktime: gbt.height.saturating_sub(1),
+            merkle_path: coinbase_merkle_path(txids)
+                .into_iter()
+                .map(Into::into)
+                .collect::<Vec<_>>()
+                .into(),
+        })
+    }
+}
+
+/// The `SetNewPrevHash` activating the template `template_id` on top of `previousblockhash`.
+impl TryFromGbt for SetNewPrevHash<'static> {
+    fn try_from_gbt(gbt: &GbtResponse, template_id: u64) -> Result<Self, GbtError> {
+        let n_bits = parse_bits(&gbt.bits)?;
+        let target = Target::from_compact(CompactTarget::from_consensus(n_bits));
+        Ok(SetNewPrevHash {
+            template_id,
+            prev_hash: parse_prev_hash(&gbt.previousblockhash)?,
+            header_timestamp: gbt.curtime,
+            n_bits,
+            target: target.to_le_bytes().into(),
+        })
+    }
+}
+
+fn parse_prev_hash(prev_hash_hex: &str) -> Result<binary_sv2::U256<'static>, GbtError> {
+    BlockHash::from_str(prev_hash_hex)
+        .map(|hash| hash.to_byte_array().into())
+        .map_err(|_| GbtError::InvalidPrevHash)
+}
+
+fn parse_bits(bits_hex: &str) -> Result<u32, GbtError> {
+    if bits_hex.len() != 8 {
+        return Err(GbtError::InvalidBits);
+    }
+    u32::from_str_radix(bits_hex, 16).map_err(|_| GbtError::InvalidBits)
+}
+
+// The merkle path of the coinbase in a block with `txids` after it, i.e. the sibling of the
+// coinbase branch at every level of the tree.
+fn coinbase_merkle_path(txids: Vec<[u8; 32]>) -> Vec<[u8; 32]> {
+    let mut path = vec![];
+    // the coinbase is not known yet, nor are the nodes of its branch, which are never part of
+    // the path: zeroes stand in for them
+    let mut level = Vec::with_capacity(txids.len() + 1);
+    level.push([0; 32]);
+    level.extend(txids);
+    while level.len() > 1 {
+        path.push(level[1]);
+        if level.len() % 2 == 1 {
+            level.push(level[level.len() - 1]);
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| sha256d::Hash::hash(&[pair[0], pair[1]].concat()).to_byte_array())
+            .collect();
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{merkle_root::merkle_root_from_path_, template::deserialize_template_outputs};
+    use bitcoin::hex::FromHex;
+
+    // A response rebuilt from mainnet block 702861 (000000000000000000000c835b2adcaedc20fdf6ee4400
+    // 09c249452c726dafae): the transactions, fees and witness commitment it was mined with.
+    const GBT: &str = include_str!("../test_data/mainnet_gbt_702861.json");
+
+    // The coinbase of block 702861, and the merkle root of its header.
+    const COINBASE_TXID: &str = "764b60c3d9a2c3c5bb6fe7141d9ca6e6778122df75f19366a2c5cb948d1d7d84";
+    const MERKLE_ROOT: &str = "407d72768cec1a244b7599af79f554055c72d6b2356c890f8c25abf797679022";
+
+    fn gbt() -> GbtResponse {
+        serde_json::from_str(GBT).unwrap()
+    }
+
+    #[test]
+    fn test_chain_tip_from_gbt() {
+        let gbt = gbt();
+        let chain_tip = ChainTip::from_gbt(&gbt.previousblockhash, &gbt.bits, gbt.curtime).unwrap();
+        assert_eq!(
+            chain_tip.prev_block_hash().to_string(),
+            gbt.previousblockhash
+        );
+        // the wire order is the reverse of the RPC one
+        assert_eq!(
+            chain_tip.prev_hash().inner_as_ref()[..4],
+            [0x5f, 0x3b, 0x21, 0x19]
+        );
+        assert_eq!(chain_tip.nbits(), 0x170ed0eb);
+        assert_eq!(chain_tip.min_ntime(), 1633002641);
+
+        assert_eq!(
+            ChainTip::from_gbt("00", &gbt.bits, gbt.curtime).unwrap_err(),
+            GbtError::InvalidPrevHash
+        );
+        assert_eq!(
+            ChainTip::from_gbt(&gbt.previousblockhash, "0ed0eb", gbt.curtime).unwrap_err(),
+            GbtError::InvalidBits
+        );
+    }
+
+    #[test]
+    fn test_new_template_from_gbt() {
+        let gbt = gbt();
+        let template = NewTemplate::try_from_gbt(&gbt, 7).unwrap();
+        assert_eq!(template.template_id, 7);
+        assert!(!template.future_template);
+        assert_eq!(template.version, 0x3fffe004);
+        assert_eq!(template.coinbase_tx_value_remaining, 629948405);
+        assert_eq!(template.coinbase_tx_locktime, 702860);
+        // the script sig of the coinbase of block 702861 starts with the same height push
+        assert_eq!(
+            template.coinbase_prefix.inner_as_ref(),
+            [0x03, 0x8d, 0xb9, 0x0a]
+        );
+
+        let outputs = deserialize_template_outputs(
+            template.coinbase_tx_outputs.to_vec(),
+            template.coinbase_tx_outputs_count,
+        )
+        .unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].value, Amount::ZERO);
+        assert_eq!(
+            outputs[0].script_pubkey.to_hex_string(),
+            gbt.default_witness_commitment.unwrap()
+        );
+
+        // the path of the actual coinbase leads to the merkle root of the block
+        let path: Vec<Vec<u8>> = template
+            .merkle_path
+            .to_vec()
+            .into_iter()
+            .map(|node| node.to_vec())
+            .collect();
+        assert_eq!(path.len(), 12);
+        let coinbase_txid = Txid::from_str(COINBASE_TXID).unwrap().to_byte_array();
+        let mut merkle_root = merkle_root_from_path_(coinbase_txid, &path);
+        merkle_root.reverse();
+        assert_eq!(merkle_root.to_vec(), Vec::from_hex(MERKLE_ROOT).unwrap());
+    }
+
+    #[test]
+    fn test_set_new_prev_hash_from_gbt() {
+        let gbt = gbt();
+        let set_new_prev_hash = SetNewPrevHash::try_from_gbt(&gbt, 7).unwrap();
+        let chain_tip = ChainTip::from_gbt(&gbt.previousblockhash, &gbt.bits, gbt.curtime).unwrap();
+        assert_eq!(set_new_prev_hash.template_id, 7);
+        assert_eq!(set_new_prev_hash.prev_hash, chain_tip.prev_hash());
+        assert_eq!(set_new_prev_hash.header_timestamp, gbt.curtime);
+        assert_eq!(set_new_prev_hash.n_bits, 0x170ed0eb);
+        // 0x0ed0eb * 256^(0x17 - 3), little-endian
+        let mut target = [0; 32];
+        target[20..23].copy_from_slice(&[0xeb, 0xd0, 0x0e]);
+        assert_eq!(set_new_prev_hash.target.inner_as_ref(), target);
+    }
+
+    #[test]
+    fn test_invalid_txid() {
+        let mut gbt = gbt();
+        gbt.transactions[3].txid.pop();
+        assert_eq!(
+            NewTemplate::try_from_gbt(&gbt, 0).unwrap_err(),
+            GbtError::InvalidTxid(3)
+        );
+    }
+}
@@ -1,34 +1,168 @@
 //! # Chain Tip
+use crate::target::WireU256;
 use binary_sv2::U256;
+use bitcoin::{
+    blockdata::block::{Header, Version},
+    hash_types::{BlockHash, TxMerkleNode},
+    hashes::Hash,
+    CompactTarget, Target as BitcoinTarget,
+};
+use core::{convert::TryFrom, fmt};
+use mining_sv2::TargetError;
 
 /// An abstraction over the chain tip, carrying information from `SetNewPrevHash` messages.
 ///
 /// Used while creating non-future jobs.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ChainTip {
-    prev_hash: U256<'static>,
+    prev_hash: WireU256,
+    // `prev_hash` converted once on creation, so that it doesn't need to be converted on every
+    // share validation
+    prev_block_hash: BlockHash,
     nbits: u32,
     min_ntime: u32,
 }
 
 impl ChainTip {
-    pub fn new(prev_hash: U256<'static>, nbits: u32, min_ntime: u32) -> Self {
+    /// Creates a chain tip out of the fields of a Template Distribution Protocol
+    /// `SetNewPrevHash`, where `min_ntime` is its `header_timestamp`.
+    ///
+    /// For the Mining Protocol `SetNewPrevHash`, see [`ChainTip::from_mining_prev_hash`].
+    ///
+    /// Fails if `prev_hash` doesn't hold exactly 32 bytes.
+    pub fn new(prev_hash: U256<'static>, nbits: u32, min_ntime: u32) -> Result<Self, TargetError> {
+        Ok(Self::from_wire(
+            WireU256::try_from(&prev_hash)?,
+            nbits,
+            min_ntime,
+        ))
+    }
+
+    fn from_wire(prev_hash: WireU256, nbits: u32, min_ntime: u32) -> Self {
         Self {
             prev_hash,
+            prev_block_hash: prev_hash.into(),
             nbits,
             min_ntime,
         }
     }
 
+    /// Creates a chain tip out of the fields of a Mining Protocol `SetNewPrevHash`, as received
+    /// by proxies and mining clients without a Template Distribution Protocol connection.
+    ///
+    /// Its `min_ntime` has the same meaning as the `header_timestamp` taken by
+    /// [`ChainTip::new`]: both are the smallest `ntime` of a block on top of `prev_hash`.
+    pub fn from_mining_prev_hash(
+        prev_hash: U256<'static>,
+        nbits: u32,
+        min_ntime: u32,
+    ) -> Result<Self, TargetError> {
+        Self::new(prev_hash, nbits, min_ntime)
+    }
+
     pub fn prev_hash(&self) -> U256<'static> {
-        self.prev_hash.clone()
+        self.prev_hash.into()
+    }
+
+    /// Returns the previous block hash as a [`BlockHash`].
+    ///
+    /// Unlike the raw [`U256`] returned by [`ChainTip::prev_hash`], its `Display` implementation
+    /// prints the conventional big-endian hex used by block explorers.
+    pub fn prev_block_hash(&self) -> BlockHash {
+        self.prev_block_hash
     }
 
     pub fn nbits(&self) -> u32 {
         self.nbits
     }
 
+    /// The smallest `ntime` of a block on top of this chain tip, i.e. the `header_timestamp` of a
+    /// Template Distribution Protocol `SetNewPrevHash`, or the `min_ntime` of a Mining Protocol
+    /// one.
+    ///
+    /// It's the floor of the header timestamp of the jobs built on this chain tip, and the `ntime`
+    /// of their header templates when the job has no `min_ntime` of its own, e.g. a job that was
+    /// created as a future job.
     pub fn min_ntime(&self) -> u32 {
         self.min_ntime
     }
+
+    /// Returns the network difficulty encoded by `nbits`, relative to the genesis block target.
+    pub fn network_difficulty(&self) -> f64 {
+        BitcoinTarget::from_compact(CompactTarget::from_consensus(self.nbits)).difficulty_float()
+    }
+
+    // Builds the header of a block on top of this chain tip, with a zero nonce.
+    pub(crate) fn header_template(
+        &self,
+        version: u32,
+        merkle_root: WireU256,
+        ntime: u32,
+    ) -> Header {
+        Header {
+            version: Version::from_consensus(version as i32),
+            prev_blockhash: self.prev_block_hash,
+            merkle_root: TxMerkleNode::from_byte_array(merkle_root.to_wire_bytes()),
+            time: ntime,
+            bits: CompactTarget::from_consensus(self.nbits),
+            nonce: 0,
+        }
+    }
+
+    /// Takes a snapshot of the chain tip, so it can be restored elsewhere.
+    pub fn to_state(&self) -> ChainTipState {
+        ChainTipState {
+            prev_hash: self.prev_hash,
+            nbits: self.nbits,
+            min_ntime: self.min_ntime,
+        }
+    }
+
+    /// Restores a chain tip from a snapshot taken with [`ChainTip::to_state`].
+    pub fn from_state(state: ChainTipState) -> Self {
+        Self::from_wire(state.prev_hash, state.nbits, state.min_ntime)
+    }
+}
+
+/// A serializable snapshot of a [`ChainTip`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChainTipState {
+    pub prev_hash: WireU256,
+    pub nbits: u32,
+    pub min_ntime: u32,
+}
+
+impl fmt::Display for ChainTip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "prev_hash: {}, nbits: {:#010x}, min_ntime: {}",
+            self.prev_block_hash, self.nbits, self.min_ntime
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::target::u256_from_hex_be;
+
+    #[test]
+    fn test_chain_tip_display_genesis() {
+        let genesis_hash = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+        let chain_tip = ChainTip::new(
+            u256_from_hex_be(genesis_hash).unwrap(),
+            0x1d00ffff,
+            1231006505,
+        )
+        .unwrap();
+
+        assert_eq!(chain_tip.prev_block_hash().to_string(), genesis_hash);
+        assert_eq!(
+            chain_tip.to_string(),
+            format!("prev_hash: {genesis_hash}, nbits: 0x1d00ffff, min_ntime: 1231006505")
+        );
+        assert_eq!(chain_tip.network_difficulty(), 1.0);
+    }
 }
@@ -1,13 +1,43 @@
+use mining_sv2::TargetError;
+
 #[derive(Debug)]
 pub enum ExtendedChannelError {
     NewExtranoncePrefixTooLarge,
     JobIdNotFound,
+    InvalidSubExtranonce,
+    /// The `prev_hash` of a `SetNewPrevHash` doesn't hold 32 bytes.
+    InvalidPrevHash(TargetError),
 }
 
 #[derive(Debug)]
 pub enum StandardChannelError {
     JobIdNotFound,
     NewExtranoncePrefixTooLarge,
+    ChainTipNotSet,
+    /// A message addressed to another channel.
+    InvalidChannelId,
+    /// A `SetTarget` beyond the [`TargetBounds`](crate::client::standard::TargetBounds) of the
+    /// channel, whose target was left untouched.
+    TargetOutOfBounds {
+        current_difficulty: f64,
+        difficulty: f64,
+    },
+    /// The `prev_hash` of a `SetNewPrevHash` doesn't hold 32 bytes.
+    InvalidPrevHash(TargetError),
+    /// The `merkle_root` of a job doesn't hold 32 bytes.
+    InvalidMerkleRoot(TargetError),
+}
+
+/// Why [`StandardChannel::build_share`](crate::client::standard::StandardChannel::build_share)
+/// refused to build a share.
+#[derive(Debug)]
+pub enum ShareBuildError {
+    /// The job is not the channel's active job.
+    JobNotActive(u32),
+    /// The share `ntime` is lower than the job's `min_ntime`.
+    NtimeTooLow { min_ntime: u32, ntime: u32 },
+    /// The share version differs from the job version outside of the BIP320 bits.
+    VersionRollingNotAllowed,
 }
 
 #[derive(Debug)]
@@ -5,28 +5,57 @@ use crate::{
     client::{
         error::ExtendedChannelError,
         share_accounting::{ShareAccounting, ShareValidationError, ShareValidationResult},
+        submit_stats::SubmitStats,
     },
+    collections::HashMap,
+    extranonce::{ExtranonceLayout, ExtranonceLayoutError},
     merkle_root::merkle_root_from_path,
-    target::{bytes_to_hex, target_to_difficulty, u256_to_block_hash},
+    redact::{RedactedExtranoncePrefix, RedactedIdentity},
+    target::{target_to_difficulty, DisplayU256, WireU256},
+    trace::{debug, debug_enabled},
 };
-use binary_sv2::{self, Sv2Option};
+use alloc::{string::String, vec::Vec};
+use binary_sv2::Sv2Option;
 use bitcoin::{
     blockdata::block::{Header, Version},
     hashes::sha256d::Hash,
     CompactTarget, Target as BitcoinTarget,
 };
+use core::{convert::TryInto, fmt};
 use mining_sv2::{
-    NewExtendedMiningJob, SetNewPrevHash as SetNewPrevHashMp, SubmitSharesExtended, Target,
-    MAX_EXTRANONCE_LEN,
+    NewExtendedMiningJob, NewMiningJob, SetNewPrevHash as SetNewPrevHashMp, SubmitSharesError,
+    SubmitSharesExtended, SubmitSharesStandard, SubmitSharesSuccess, Target, MAX_EXTRANONCE_LEN,
 };
-use std::{collections::HashMap, convert::TryInto};
-use tracing::debug;
 
 // ExtendedJob is a tuple of:
 // - the NewExtendedMiningJob message
 // - the extranonce_prefix associated with the channel at the time of job creation
 pub type ExtendedJob<'a> = (NewExtendedMiningJob<'a>, Vec<u8>);
 
+/// Ties a standard job created via [`ExtendedChannel::derive_standard_job`] to the extended job
+/// it was derived from, so that shares for it can be translated back via
+/// [`ExtendedChannel::translate_share`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivedJobHandle {
+    upstream_job_id: u32,
+    downstream_job_id: u32,
+    sub_extranonce: Vec<u8>,
+}
+
+impl DerivedJobHandle {
+    pub fn get_upstream_job_id(&self) -> u32 {
+        self.upstream_job_id
+    }
+
+    pub fn get_downstream_job_id(&self) -> u32 {
+        self.downstream_job_id
+    }
+
+    pub fn get_sub_extranonce(&self) -> &[u8] {
+        &self.sub_extranonce
+    }
+}
+
 /// Mining Client abstraction over the state of a Sv2 Extended Channel.
 ///
 /// It keeps track of:
@@ -45,8 +74,11 @@ pub type ExtendedJob<'a> = (NewExtendedMiningJob<'a>, Vec<u8>);
 /// - the channel's stale jobs (which were past and active jobs under the previous chain tip,
 ///   indexed by `job_id`)
 /// - the channel's share accounting (as seen by the client)
+/// - the upstream responses to the shares submitted on the channel
 /// - the channel's chain tip
-#[derive(Clone, Debug)]
+/// - the last `job_id` assigned to a standard job derived via
+///   [`ExtendedChannel::derive_standard_job`]
+#[derive(Clone)]
 pub struct ExtendedChannel<'a> {
     channel_id: u32,
     user_identity: String,
@@ -63,7 +95,34 @@ pub struct ExtendedChannel<'a> {
     // stale jobs are indexed with job_id (u32)
     stale_jobs: HashMap<u32, ExtendedJob<'a>>,
     share_accounting: ShareAccounting,
+    submit_stats: SubmitStats,
     chain_tip: Option<ChainTip>,
+    last_derived_job_id: u32,
+}
+
+impl fmt::Debug for ExtendedChannel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtendedChannel")
+            .field("channel_id", &self.channel_id)
+            .field("user_identity", &RedactedIdentity(&self.user_identity))
+            .field(
+                "extranonce_prefix",
+                &RedactedExtranoncePrefix(&self.extranonce_prefix),
+            )
+            .field("rollable_extranonce_size", &self.rollable_extranonce_size)
+            .field("target", &self.target)
+            .field("nominal_hashrate", &self.nominal_hashrate)
+            .field("version_rolling", &self.version_rolling)
+            .field("future_jobs", &self.future_jobs)
+            .field("active_job", &self.active_job)
+            .field("past_jobs", &self.past_jobs)
+            .field("stale_jobs", &self.stale_jobs)
+            .field("share_accounting", &self.share_accounting)
+            .field("submit_stats", &self.submit_stats)
+            .field("chain_tip", &self.chain_tip)
+            .field("last_derived_job_id", &self.last_derived_job_id)
+            .finish()
+    }
 }
 
 impl<'a> ExtendedChannel<'a> {
@@ -89,7 +148,9 @@ impl<'a> ExtendedChannel<'a> {
             past_jobs: HashMap::new(),
             stale_jobs: HashMap::new(),
             share_accounting: ShareAccounting::new(),
+            submit_stats: SubmitStats::default(),
             chain_tip: None,
+            last_derived_job_id: 0,
         }
     }
 
@@ -174,6 +235,38 @@ impl<'a> ExtendedChannel<'a> {
         &self.share_accounting
     }
 
+    pub fn get_submit_stats(&self) -> &SubmitStats {
+        &self.submit_stats
+    }
+
+    /// Called when a share is submitted upstream, so that the upstream response to it can be
+    /// accounted for in [`SubmitStats`].
+    pub fn on_share_submitted(&mut self, sequence_number: u32) {
+        self.submit_stats.on_share_submitted(sequence_number);
+    }
+
+    /// Called when a `SubmitShares.Success` message is received from upstream.
+    pub fn on_submit_shares_success(&mut self, success: &SubmitSharesSuccess) {
+        self.submit_stats.on_submit_shares_success(success);
+    }
+
+    /// Called when a `SubmitShares.Error` message is received from upstream.
+    pub fn on_submit_shares_error(&mut self, error: &SubmitSharesError) {
+        self.submit_stats.on_submit_shares_error(error);
+    }
+
+    /// The share of the last `window` shares answered by the upstream that were rejected as
+    /// stale, see [`SubmitStats::stale_rate`].
+    pub fn stale_rate(&self, window: usize) -> Option<f64> {
+        self.submit_stats.stale_rate(window)
+    }
+
+    /// The share of the last `window` shares answered by the upstream that were rejected, see
+    /// [`SubmitStats::reject_rate`].
+    pub fn reject_rate(&self, window: usize) -> Option<f64> {
+        self.submit_stats.reject_rate(window)
+    }
+
     /// Called when a `NewExtendedMiningJob` message is received from upstream.
     pub fn on_new_extended_mining_job(
         &mut self,
@@ -210,6 +303,14 @@ impl<'a> ExtendedChannel<'a> {
         &mut self,
         set_new_prev_hash: SetNewPrevHashMp<'a>,
     ) -> Result<(), ExtendedChannelError> {
+        let set_new_prev_hash = set_new_prev_hash.into_static();
+        let new_chain_tip = ChainTip::from_mining_prev_hash(
+            set_new_prev_hash.prev_hash,
+            set_new_prev_hash.nbits,
+            set_new_prev_hash.min_ntime,
+        )
+        .map_err(ExtendedChannelError::InvalidPrevHash)?;
+
         match self.future_jobs.remove(&set_new_prev_hash.job_id) {
             Some(mut activated_job) => {
                 activated_job.0.min_ntime = Sv2Option::new(Some(set_new_prev_hash.min_ntime));
@@ -232,17 +333,110 @@ impl<'a> ExtendedChannel<'a> {
         // clear seen shares, as shares for past chain tip will be rejected as stale
         self.share_accounting.flush_seen_shares();
 
-        let set_new_prev_hash_static = set_new_prev_hash.into_static();
-        let new_chain_tip = ChainTip::new(
-            set_new_prev_hash_static.prev_hash,
-            set_new_prev_hash_static.nbits,
-            set_new_prev_hash_static.min_ntime,
-        );
         self.chain_tip = Some(new_chain_tip);
 
         Ok(())
     }
 
+    /// Returns the layout of the full extranonce of the jobs derived via
+    /// [`ExtendedChannel::derive_standard_job`]: the channel's extranonce prefix, followed by a
+    /// sub-extranonce taking all the rollable space.
+    ///
+    /// Collision-free sub-extranonces can be handed out to downstream devices out of
+    /// [`ExtranonceLayout::new_extended_extranonce`], taking the [`ExtranonceLayout::rollable_range`]
+    /// of each `next_prefix_standard`.
+    pub fn derived_job_extranonce_layout(&self) -> Result<ExtranonceLayout, ExtranonceLayoutError> {
+        ExtranonceLayout::new(
+            self.extranonce_prefix.len(),
+            0,
+            self.rollable_extranonce_size as usize,
+        )
+    }
+
+    /// Derives a standard job for a header-only downstream device out of the active job, with
+    /// `sub_extranonce` spliced into the coinbase after the channel's extranonce prefix.
+    ///
+    /// `sub_extranonce` must take all the rollable space (see
+    /// [`ExtendedChannel::derived_job_extranonce_layout`]) and must be unique to the device, as
+    /// it's the only thing telling apart the work of different devices.
+    ///
+    /// The derived job gets its own `job_id`, and is addressed to `downstream_channel_id`. The
+    /// returned [`DerivedJobHandle`] is needed to translate the shares for it via
+    /// [`ExtendedChannel::translate_share`].
+    pub fn derive_standard_job(
+        &mut self,
+        downstream_channel_id: u32,
+        sub_extranonce: &[u8],
+    ) -> Result<(NewMiningJob<'static>, DerivedJobHandle), ExtendedChannelError> {
+        let (job, extranonce_prefix) = self
+            .active_job
+            .as_ref()
+            .ok_or(ExtendedChannelError::JobIdNotFound)?;
+
+        let full_extranonce = ExtranonceLayout::new(
+            extranonce_prefix.len(),
+            0,
+            self.rollable_extranonce_size as usize,
+        )
+        .and_then(|layout| layout.compose(extranonce_prefix, &[], sub_extranonce))
+        .map_err(|_| ExtendedChannelError::InvalidSubExtranonce)?;
+
+        let merkle_root: [u8; 32] = merkle_root_from_path(
+            job.coinbase_tx_prefix.inner_as_ref(),
+            job.coinbase_tx_suffix.inner_as_ref(),
+            full_extranonce.as_ref(),
+            &job.merkle_path.inner_as_ref(),
+        )
+        .ok_or(ExtendedChannelError::InvalidSubExtranonce)?
+        .try_into()
+        .map_err(|_| ExtendedChannelError::InvalidSubExtranonce)?;
+
+        self.last_derived_job_id += 1;
+        let handle = DerivedJobHandle {
+            upstream_job_id: job.job_id,
+            downstream_job_id: self.last_derived_job_id,
+            sub_extranonce: sub_extranonce.to_vec(),
+        };
+        let standard_job = NewMiningJob {
+            channel_id: downstream_channel_id,
+            job_id: handle.downstream_job_id,
+            min_ntime: job.min_ntime.clone().into_static(),
+            version: job.version,
+            merkle_root: merkle_root.into(),
+        };
+
+        Ok((standard_job, handle))
+    }
+
+    /// Translates a share for a job derived via [`ExtendedChannel::derive_standard_job`] into a
+    /// share for the upstream extended job, to be validated and submitted upstream.
+    ///
+    /// The sequence number is the downstream one, so it must be reassigned when the shares of
+    /// several downstream devices are submitted on this channel.
+    pub fn translate_share(
+        &self,
+        share: &SubmitSharesStandard,
+        handle: &DerivedJobHandle,
+    ) -> Result<SubmitSharesExtended<'static>, ExtendedChannelError> {
+        if share.job_id != handle.downstream_job_id {
+            return Err(ExtendedChannelError::JobIdNotFound);
+        }
+
+        Ok(SubmitSharesExtended {
+            channel_id: self.channel_id,
+            sequence_number: share.sequence_number,
+            job_id: handle.upstream_job_id,
+            nonce: share.nonce,
+            ntime: share.ntime,
+            version: share.version,
+            extranonce: handle
+                .sub_extranonce
+                .clone()
+                .try_into()
+                .map_err(|_| ExtendedChannelError::InvalidSubExtranonce)?,
+        })
+    }
+
     /// Validates a share, to be used before submission upstream.
     ///
     /// Updates the channel state with the result of the share validation.
@@ -257,33 +451,26 @@ impl<'a> ExtendedChannel<'a> {
     ) -> Result<ShareValidationResult, ShareValidationError> {
         let job_id = share.job_id;
 
-        // check if job_id is active job
-        let is_active_job = self
-            .active_job
-            .as_ref()
-            .is_some_and(|job| job.0.job_id == job_id);
-
-        // check if job_id is past job
-        let is_past_job = self.past_jobs.contains_key(&job_id);
-
-        // check if job_id is stale job
-        let is_stale_job = self.stale_jobs.contains_key(&job_id);
-
-        if is_stale_job {
+        if self.stale_jobs.contains_key(&job_id) {
             return Err(ShareValidationError::Stale);
         }
 
-        let job = if is_active_job {
-            self.active_job.as_ref().expect("active job must exist")
-        } else if is_past_job {
-            self.past_jobs.get(&job_id).expect("past job must exist")
-        } else {
-            return Err(ShareValidationError::InvalidJobId);
+        // only the active job and the past jobs can be mined on
+        let job = match self
+            .active_job
+            .as_ref()
+            .filter(|job| job.0.job_id == job_id)
+            .or_else(|| self.past_jobs.get(&job_id))
+        {
+            Some(job) => job,
+            None => return Err(ShareValidationError::InvalidJobId),
         };
 
-        let mut full_extranonce = vec![];
-        full_extranonce.extend_from_slice(job.1.as_slice());
-        full_extranonce.extend_from_slice(share.extranonce.inner_as_ref());
+        // the share's extranonce must fill all the space left by the job's extranonce prefix, and
+        // is empty when the prefix takes all of it
+        let full_extranonce = ExtranonceLayout::from_prefix_len(job.1.len())
+            .and_then(|layout| layout.compose(&job.1, &[], share.extranonce.inner_as_ref()))
+            .map_err(|_| ShareValidationError::Invalid)?;
 
         // calculate the merkle root from:
         // - job coinbase_tx_prefix
@@ -298,14 +485,13 @@ impl<'a> ExtendedChannel<'a> {
         )
         .ok_or(ShareValidationError::Invalid)?
         .try_into()
-        .expect("merkle root must be 32 bytes");
+        .map_err(|_| ShareValidationError::Invalid)?;
 
         let chain_tip = self
             .chain_tip
             .as_ref()
             .ok_or(ShareValidationError::NoChainTip)?;
 
-        let prev_hash = chain_tip.prev_hash();
         let nbits: CompactTarget = CompactTarget::from_consensus(chain_tip.nbits());
 
         // validate when version rolling is not allowed
@@ -321,7 +507,7 @@ impl<'a> ExtendedChannel<'a> {
         // create the header for validation
         let header = Header {
             version: Version::from_consensus(share.version as i32),
-            prev_blockhash: u256_to_block_hash(prev_hash.clone()),
+            prev_blockhash: chain_tip.prev_block_hash(),
             merkle_root: (*Hash::from_bytes_ref(&merkle_root)).into(),
             time: share.ntime,
             bits: nbits,
@@ -330,26 +516,19 @@ impl<'a> ExtendedChannel<'a> {
 
         // convert the header hash to a target type for easy comparison
         let hash = header.block_hash();
-        let raw_hash: [u8; 32] = *hash.to_raw_hash().as_ref();
-        let hash_as_target: Target = raw_hash.into();
+        let hash_as_target: Target = WireU256::from(hash).into();
         let hash_as_diff = target_to_difficulty(hash_as_target.clone());
 
         let network_target = BitcoinTarget::from_compact(nbits);
 
-        // print hash_as_target and self.target as human readable hex
-        let hash_as_u256: binary_sv2::U256 = hash_as_target.clone().into();
-        let mut hash_bytes = hash_as_u256.to_vec();
-        hash_bytes.reverse(); // Convert to big-endian for display
-        let target_u256: binary_sv2::U256 = self.target.clone().into();
-        let mut target_bytes = target_u256.to_vec();
-        target_bytes.reverse(); // Convert to big-endian for display
-
-        debug!(
-            "share validation \nshare:\t\t{}\nchannel target:\t{}\nnetwork target:\t{}",
-            bytes_to_hex(&hash_bytes),
-            bytes_to_hex(&target_bytes),
-            format!("{:x}", network_target)
-        );
+        if debug_enabled!() {
+            debug!(
+                "share validation \nshare:\t\t{}\nchannel target:\t{}\nnetwork target:\t{}",
+                DisplayU256::from(&hash_as_target),
+                DisplayU256::from(&self.target),
+                format!("{:x}", network_target)
+            );
+        }
 
         // check if a block was found
         if network_target.is_met_by(hash) {
@@ -362,7 +541,7 @@ impl<'a> ExtendedChannel<'a> {
         }
 
         // check if the share hash meets the channel target
-        if hash_as_target < self.target {
+        if hash_as_target <= self.target {
             if self.share_accounting.is_share_seen(hash.to_raw_hash()) {
                 return Err(ShareValidationError::DuplicateShare);
             }
@@ -391,7 +570,7 @@ mod tests {
     };
     use binary_sv2::Sv2Option;
     use mining_sv2::{
-        NewExtendedMiningJob, SetNewPrevHash as SetNewPrevHashMp, SubmitSharesExtended,
+        NewExtendedMiningJob, SetNewPrevHash as SetNewPrevHashMp, SubmitSharesExtended, Target,
         MAX_EXTRANONCE_LEN,
     };
     use std::convert::TryInto;
@@ -405,7 +584,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let target = [0xff; 32].into();
+        let target = Target::MAX;
         let nominal_hashrate = 1.0;
         let version_rolling = true;
         let rollable_extranonce_size = (MAX_EXTRANONCE_LEN - extranonce_prefix.len()) as u16;
@@ -477,6 +656,80 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_empty_extranonce() {
+        // the extranonce prefix takes the whole extranonce, so shares roll the version alone
+        let extranonce_prefix = vec![0xab; MAX_EXTRANONCE_LEN];
+        let mut channel = ExtendedChannel::new(
+            1,
+            "user_identity".to_string(),
+            extranonce_prefix,
+            Target::MAX,
+            1.0,
+            true,
+            0,
+        );
+
+        let future_job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 1,
+            min_ntime: Sv2Option::new(None),
+            version: 536870912,
+            version_rolling_allowed: true,
+            coinbase_tx_prefix: vec![
+                2, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 255, 255, 34, 82, 0,
+            ]
+            .try_into()
+            .unwrap(),
+            coinbase_tx_suffix: vec![
+                255, 255, 255, 255, 2, 0, 242, 5, 42, 1, 0, 0, 0, 22, 0, 20, 235, 225, 183, 220,
+                194, 147, 204, 170, 14, 231, 67, 168, 111, 137, 223, 130, 88, 194, 8, 252, 0, 0, 0,
+                0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209, 222,
+                253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180, 139,
+                235, 216, 54, 151, 78, 140, 249, 1, 32, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            ]
+            .try_into()
+            .unwrap(),
+            merkle_path: vec![].try_into().unwrap(),
+        };
+        channel.on_new_extended_mining_job(future_job.clone());
+        channel
+            .on_set_new_prev_hash(SetNewPrevHashMp {
+                channel_id: 1,
+                job_id: future_job.job_id,
+                prev_hash: [0xaa; 32].into(),
+                nbits: 503543726,
+                min_ntime: 1746839905,
+            })
+            .unwrap();
+
+        let share = SubmitSharesExtended {
+            channel_id: 1,
+            sequence_number: 0,
+            job_id: future_job.job_id,
+            nonce: 0,
+            ntime: 1746839905,
+            version: 536870912,
+            extranonce: vec![].try_into().unwrap(),
+        };
+        assert!(matches!(
+            channel.validate_share(share.clone()),
+            Ok(ShareValidationResult::Valid) | Ok(ShareValidationResult::BlockFound)
+        ));
+
+        // there's no space left for a rolled extranonce
+        let share = SubmitSharesExtended {
+            extranonce: vec![0].try_into().unwrap(),
+            ..share
+        };
+        assert!(matches!(
+            channel.validate_share(share),
+            Err(ShareValidationError::Invalid)
+        ));
+    }
+
     #[test]
     fn test_past_jobs_flow() {
         let channel_id = 1;
@@ -486,7 +739,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let target = [0xff; 32].into();
+        let target = Target::MAX;
         let nominal_hashrate = 1.0;
         let version_rolling = true;
         let rollable_extranonce_size = (MAX_EXTRANONCE_LEN - extranonce_prefix.len()) as u16;
@@ -557,7 +810,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let target = [0xff; 32].into();
+        let target = Target::MAX;
         let nominal_hashrate = 1.0;
         let version_rolling = true;
         let rollable_extranonce_size = (MAX_EXTRANONCE_LEN - extranonce_prefix.len()) as u16;
@@ -839,4 +1092,114 @@ mod tests {
             ShareValidationError::DuplicateShare
         ));
     }
+
+    #[test]
+    fn test_derived_standard_job() {
+        use crate::merkle_root::merkle_root_from_path;
+        use mining_sv2::SubmitSharesStandard;
+
+        let extranonce_prefix = vec![0x42; 16];
+        let mut channel = ExtendedChannel::new(
+            1,
+            "user_identity".to_string(),
+            extranonce_prefix.clone(),
+            Target::MAX,
+            1.0,
+            true,
+            (MAX_EXTRANONCE_LEN - extranonce_prefix.len()) as u16,
+        );
+
+        // no active job to derive from yet
+        assert!(channel.derive_standard_job(7, &[0; 16]).is_err());
+
+        let upstream_job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 3,
+            min_ntime: Sv2Option::new(None),
+            version: 536870912,
+            version_rolling_allowed: true,
+            coinbase_tx_prefix: vec![
+                2, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 255, 255, 34, 82, 0,
+            ]
+            .try_into()
+            .unwrap(),
+            coinbase_tx_suffix: vec![
+                255, 255, 255, 255, 2, 0, 242, 5, 42, 1, 0, 0, 0, 22, 0, 20, 235, 225, 183, 220,
+                194, 147, 204, 170, 14, 231, 67, 168, 111, 137, 223, 130, 88, 194, 8, 252, 0, 0, 0,
+                0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209, 222,
+                253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180, 139,
+                235, 216, 54, 151, 78, 140, 249, 1, 32, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            ]
+            .try_into()
+            .unwrap(),
+            merkle_path: vec![[0x11; 32].into(), [0x22; 32].into()]
+                .try_into()
+                .unwrap(),
+        };
+        channel.on_new_extended_mining_job(upstream_job.clone());
+        channel
+            .on_set_new_prev_hash(SetNewPrevHashMp {
+                channel_id: 1,
+                job_id: 3,
+                prev_hash: [0xaa; 32].into(),
+                nbits: 503543726,
+                min_ntime: 1746839905,
+            })
+            .unwrap();
+
+        // hand out one sub-extranonce per device
+        let layout = channel.derived_job_extranonce_layout().unwrap();
+        let mut extended_extranonce = layout.new_extended_extranonce(None).unwrap();
+        let mut next_sub_extranonce = || {
+            extended_extranonce.next_prefix_standard().unwrap().to_vec()[layout.rollable_range()]
+                .to_vec()
+        };
+        let sub_extranonce_a = next_sub_extranonce();
+        let sub_extranonce_b = next_sub_extranonce();
+        assert_ne!(sub_extranonce_a, sub_extranonce_b);
+
+        let (job_a, handle_a) = channel.derive_standard_job(7, &sub_extranonce_a).unwrap();
+        let (job_b, handle_b) = channel.derive_standard_job(8, &sub_extranonce_b).unwrap();
+        assert_eq!((job_a.channel_id, job_a.job_id), (7, 1));
+        assert_eq!((job_b.channel_id, job_b.job_id), (8, 2));
+        assert_eq!(handle_a.get_upstream_job_id(), 3);
+        assert_ne!(job_a.merkle_root, job_b.merkle_root);
+        assert!(channel.derive_standard_job(9, &[0; 15]).is_err());
+
+        let downstream_share = SubmitSharesStandard {
+            channel_id: 7,
+            sequence_number: 0,
+            job_id: job_a.job_id,
+            nonce: 12345,
+            ntime: 1746839905,
+            version: 536870912,
+        };
+        assert!(channel
+            .translate_share(&downstream_share, &handle_b)
+            .is_err());
+        let upstream_share = channel
+            .translate_share(&downstream_share, &handle_a)
+            .unwrap();
+        assert_eq!(upstream_share.channel_id, 1);
+        assert_eq!(upstream_share.job_id, 3);
+        assert_eq!(upstream_share.nonce, 12345);
+
+        // the upstream recomputes the same merkle root the device mined on
+        let mut full_extranonce = extranonce_prefix;
+        full_extranonce.extend(upstream_share.extranonce.inner_as_ref());
+        let merkle_root = merkle_root_from_path(
+            upstream_job.coinbase_tx_prefix.inner_as_ref(),
+            upstream_job.coinbase_tx_suffix.inner_as_ref(),
+            &full_extranonce,
+            &upstream_job.merkle_path.inner_as_ref(),
+        )
+        .unwrap();
+        assert_eq!(merkle_root, job_a.merkle_root.inner_as_ref());
+        assert!(matches!(
+            channel.validate_share(upstream_share),
+            Ok(ShareValidationResult::Valid)
+        ));
+    }
 }
@@ -1,8 +1,9 @@
 //! Abstraction over the state of a Sv2 Group Channel, as seen by a Mining Client
 
-use crate::client::error::GroupChannelError;
-
-use std::collections::{HashMap, HashSet};
+use crate::{
+    client::error::GroupChannelError,
+    collections::{HashMap, HashSet},
+};
 
 use mining_sv2::{NewExtendedMiningJob, SetNewPrevHash as SetNewPrevHashMp};
 
@@ -5,3 +5,4 @@ pub mod extended;
 pub mod group;
 pub mod share_accounting;
 pub mod standard;
+pub mod submit_stats;
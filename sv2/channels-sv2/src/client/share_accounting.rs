@@ -1,7 +1,7 @@
 //! Abstractions for share validation for a Mining Client
 
+use crate::collections::HashSet;
 use bitcoin::hashes::sha256d::Hash;
-use std::collections::HashSet;
 
 /// The outcome of share validation, from the perspective of a Mining Client.
 #[derive(Debug)]
@@ -3,24 +3,86 @@
 use crate::{
     chain_tip::ChainTip,
     client::{
-        error::StandardChannelError,
+        error::{ShareBuildError, StandardChannelError},
         share_accounting::{ShareAccounting, ShareValidationError, ShareValidationResult},
+        submit_stats::SubmitStats,
     },
+    collections::{HashMap, HashSet},
     merkle_root::merkle_root_from_path,
-    target::{bytes_to_hex, target_to_difficulty, u256_to_block_hash},
+    redact::{RedactedExtranoncePrefix, RedactedIdentity},
+    server::share_validation::VERSION_ROLLING_MASK,
+    target::{target_to_difficulty, DisplayU256, WireU256},
+    trace::{debug, debug_enabled},
 };
-use binary_sv2::{self, Sv2Option};
+use alloc::{string::String, vec::Vec};
+use binary_sv2::Sv2Option;
 use bitcoin::{
     blockdata::block::{Header, Version},
     hashes::sha256d::Hash,
     CompactTarget, Target as BitcoinTarget,
 };
+use core::{
+    convert::{TryFrom, TryInto},
+    fmt,
+};
 use mining_sv2::{
-    NewExtendedMiningJob, NewMiningJob, SetNewPrevHash as SetNewPrevHashMp, SubmitSharesStandard,
-    Target, MAX_EXTRANONCE_LEN,
+    NewExtendedMiningJob, NewMiningJob, SetExtranoncePrefix, SetNewPrevHash as SetNewPrevHashMp,
+    SetTarget, SubmitSharesError, SubmitSharesStandard, SubmitSharesSuccess, Target,
+    MAX_EXTRANONCE_LEN,
 };
-use std::{collections::HashMap, convert::TryInto};
-use tracing::debug;
+
+/// Bounds on the targets [`StandardChannel::on_set_target`] accepts from upstream.
+///
+/// A buggy or malicious upstream could set a target so hard that the device never finds a share
+/// again, silently zeroing its reward. Targets beyond the bounds are refused with
+/// [`StandardChannelError::TargetOutOfBounds`], for the device to alert or disconnect. Easier
+/// targets are always accepted.
+///
+/// The default has no bound.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TargetBounds {
+    max_difficulty_increase: Option<f64>,
+    max_difficulty: Option<f64>,
+}
+
+impl TargetBounds {
+    /// Refuses targets more than `factor` times as hard as the current one.
+    pub fn with_max_difficulty_increase(mut self, factor: f64) -> Self {
+        self.max_difficulty_increase = Some(factor);
+        self
+    }
+
+    /// Refuses targets harder than `difficulty`.
+    pub fn with_max_difficulty(mut self, difficulty: f64) -> Self {
+        self.max_difficulty = Some(difficulty);
+        self
+    }
+
+    pub fn get_max_difficulty_increase(&self) -> Option<f64> {
+        self.max_difficulty_increase
+    }
+
+    pub fn get_max_difficulty(&self) -> Option<f64> {
+        self.max_difficulty
+    }
+
+    /// Whether a channel on `current` target may move to `target`.
+    pub fn allows(&self, current: &Target, target: &Target) -> bool {
+        if target >= current {
+            return true;
+        }
+        let difficulty = target.difficulty();
+        if let Some(max_difficulty) = self.max_difficulty {
+            if difficulty > max_difficulty {
+                return false;
+            }
+        }
+        match self.max_difficulty_increase {
+            Some(factor) => difficulty <= current.difficulty() * factor,
+            None => true,
+        }
+    }
+}
 
 /// Mining Client abstraction over the state of a Sv2 Standard Channel.
 ///
@@ -38,8 +100,10 @@ use tracing::debug;
 /// - the channel's stale jobs (which were past and active jobs under the previous chain tip,
 ///   indexed by `job_id`)
 /// - the channel's share accounting (as seen by the client)
+/// - the upstream responses to the shares submitted on the channel
+/// - the jobs received before the last change of extranonce prefix, which need to be refreshed
 /// - the channel's chain tip
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct StandardChannel<'a> {
     channel_id: u32,
     user_identity: String,
@@ -51,7 +115,35 @@ pub struct StandardChannel<'a> {
     past_jobs: HashMap<u32, NewMiningJob<'a>>,
     stale_jobs: HashMap<u32, NewMiningJob<'a>>,
     share_accounting: ShareAccounting,
+    submit_stats: SubmitStats,
+    // ids of the jobs committing to a previous extranonce prefix
+    jobs_requiring_refresh: HashSet<u32>,
     chain_tip: Option<ChainTip>,
+    target_bounds: TargetBounds,
+}
+
+impl fmt::Debug for StandardChannel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StandardChannel")
+            .field("channel_id", &self.channel_id)
+            .field("user_identity", &RedactedIdentity(&self.user_identity))
+            .field(
+                "extranonce_prefix",
+                &RedactedExtranoncePrefix(&self.extranonce_prefix),
+            )
+            .field("target", &self.target)
+            .field("nominal_hashrate", &self.nominal_hashrate)
+            .field("future_jobs", &self.future_jobs)
+            .field("active_job", &self.active_job)
+            .field("past_jobs", &self.past_jobs)
+            .field("stale_jobs", &self.stale_jobs)
+            .field("share_accounting", &self.share_accounting)
+            .field("submit_stats", &self.submit_stats)
+            .field("jobs_requiring_refresh", &self.jobs_requiring_refresh)
+            .field("chain_tip", &self.chain_tip)
+            .field("target_bounds", &self.target_bounds)
+            .finish()
+    }
 }
 
 impl<'a> StandardChannel<'a> {
@@ -73,7 +165,10 @@ impl<'a> StandardChannel<'a> {
             past_jobs: HashMap::new(),
             stale_jobs: HashMap::new(),
             share_accounting: ShareAccounting::new(),
+            submit_stats: SubmitStats::default(),
+            jobs_requiring_refresh: HashSet::new(),
             chain_tip: None,
+            target_bounds: TargetBounds::default(),
         }
     }
 
@@ -106,6 +201,50 @@ impl<'a> StandardChannel<'a> {
         &self.extranonce_prefix
     }
 
+    /// Called when a `SetExtranoncePrefix` message is received from upstream.
+    ///
+    /// The merkle root of every job received so far commits to the previous extranonce prefix,
+    /// so they are all marked as requiring a refresh (see
+    /// [`StandardChannel::requires_refresh`]) until the upstream sends jobs for the new one.
+    /// Shares for them can still be submitted, as the upstream validates them against the
+    /// extranonce prefix they were created with.
+    pub fn on_set_extranonce_prefix(
+        &mut self,
+        set_extranonce_prefix: SetExtranoncePrefix,
+    ) -> Result<(), StandardChannelError> {
+        if set_extranonce_prefix.channel_id != self.channel_id {
+            return Err(StandardChannelError::InvalidChannelId);
+        }
+        self.set_extranonce_prefix(
+            set_extranonce_prefix
+                .extranonce_prefix
+                .inner_as_ref()
+                .to_vec(),
+        )?;
+
+        self.jobs_requiring_refresh.extend(
+            self.future_jobs
+                .keys()
+                .chain(self.past_jobs.keys())
+                .chain(self.active_job.iter().map(|job| &job.job_id))
+                .copied(),
+        );
+        Ok(())
+    }
+
+    /// Whether the job with `job_id` was received before the last `SetExtranoncePrefix`, and
+    /// should be replaced by a job for the current extranonce prefix.
+    pub fn requires_refresh(&self, job_id: u32) -> bool {
+        self.jobs_requiring_refresh.contains(&job_id)
+    }
+
+    /// Whether the active job requires a refresh, see [`StandardChannel::requires_refresh`].
+    pub fn is_active_job_outdated(&self) -> bool {
+        self.active_job
+            .as_ref()
+            .is_some_and(|job| self.requires_refresh(job.job_id))
+    }
+
     pub fn get_target(&self) -> &Target {
         &self.target
     }
@@ -114,6 +253,41 @@ impl<'a> StandardChannel<'a> {
         self.target = target;
     }
 
+    /// Called when a `SetTarget` message is received from upstream.
+    ///
+    /// Fails with [`StandardChannelError::TargetOutOfBounds`], leaving the target untouched, if
+    /// the new target is beyond the [`TargetBounds`] of the channel.
+    pub fn on_set_target(&mut self, set_target: SetTarget) -> Result<(), StandardChannelError> {
+        if set_target.channel_id != self.channel_id {
+            return Err(StandardChannelError::InvalidChannelId);
+        }
+        let target: Target = set_target.maximum_target.into();
+        if !self.target_bounds.allows(&self.target, &target) {
+            return Err(StandardChannelError::TargetOutOfBounds {
+                current_difficulty: self.target.difficulty(),
+                difficulty: target.difficulty(),
+            });
+        }
+        if debug_enabled!() {
+            debug!(
+                "updating channel target \nold target:\t{}\nnew target:\t{}",
+                DisplayU256::from(&self.target),
+                DisplayU256::from(&target)
+            );
+        }
+        self.target = target;
+        Ok(())
+    }
+
+    pub fn get_target_bounds(&self) -> &TargetBounds {
+        &self.target_bounds
+    }
+
+    /// Sets the bounds on the targets accepted by [`StandardChannel::on_set_target`].
+    pub fn set_target_bounds(&mut self, target_bounds: TargetBounds) {
+        self.target_bounds = target_bounds;
+    }
+
     pub fn get_nominal_hashrate(&self) -> f32 {
         self.nominal_hashrate
     }
@@ -138,6 +312,67 @@ impl<'a> StandardChannel<'a> {
         &self.share_accounting
     }
 
+    pub fn get_submit_stats(&self) -> &SubmitStats {
+        &self.submit_stats
+    }
+
+    /// Called when a share is submitted upstream, so that the upstream response to it can be
+    /// accounted for in [`SubmitStats`].
+    pub fn on_share_submitted(&mut self, sequence_number: u32) {
+        self.submit_stats.on_share_submitted(sequence_number);
+    }
+
+    /// Called when a `SubmitShares.Success` message is received from upstream.
+    pub fn on_submit_shares_success(&mut self, success: &SubmitSharesSuccess) {
+        self.submit_stats.on_submit_shares_success(success);
+    }
+
+    /// Called when a `SubmitShares.Error` message is received from upstream.
+    pub fn on_submit_shares_error(&mut self, error: &SubmitSharesError) {
+        self.submit_stats.on_submit_shares_error(error);
+    }
+
+    /// The share of the last `window` shares answered by the upstream that were rejected as
+    /// stale, see [`SubmitStats::stale_rate`].
+    pub fn stale_rate(&self, window: usize) -> Option<f64> {
+        self.submit_stats.stale_rate(window)
+    }
+
+    /// The share of the last `window` shares answered by the upstream that were rejected, see
+    /// [`SubmitStats::reject_rate`].
+    pub fn reject_rate(&self, window: usize) -> Option<f64> {
+        self.submit_stats.reject_rate(window)
+    }
+
+    /// Returns the header to be hashed for the active or past job with `job_id`, e.g. by
+    /// header-only mining devices.
+    ///
+    /// The nonce is zeroed and `ntime` is the job's `min_ntime`, as with
+    /// [`crate::server::jobs::standard::StandardJob::header_template`].
+    ///
+    /// Fails if no such job was received, if no chain tip is set yet, or if the job's merkle root
+    /// doesn't hold 32 bytes.
+    pub fn header_template(&self, job_id: u32) -> Result<Header, StandardChannelError> {
+        let job = self
+            .active_job
+            .as_ref()
+            .filter(|job| job.job_id == job_id)
+            .or_else(|| self.past_jobs.get(&job_id))
+            .ok_or(StandardChannelError::JobIdNotFound)?;
+        let chain_tip = self
+            .chain_tip
+            .as_ref()
+            .ok_or(StandardChannelError::ChainTipNotSet)?;
+        let ntime = job
+            .min_ntime
+            .clone()
+            .into_inner()
+            .unwrap_or_else(|| chain_tip.min_ntime());
+        let merkle_root = WireU256::try_from(&job.merkle_root)
+            .map_err(StandardChannelError::InvalidMerkleRoot)?;
+        Ok(chain_tip.header_template(job.version, merkle_root, ntime))
+    }
+
     /// Called when the Group Channel receives a new extended job.
     ///
     /// Essentially converts the extended job into a standard job (with the current channel's
@@ -168,7 +403,6 @@ impl<'a> StandardChannel<'a> {
     pub fn on_new_mining_job(&mut self, new_mining_job: NewMiningJob<'a>) {
         match new_mining_job.min_ntime.clone().into_inner() {
             Some(_min_ntime) => {
-                println!();
                 if let Some(active_job) = self.active_job.as_ref() {
                     self.past_jobs.insert(active_job.job_id, active_job.clone());
                 }
@@ -196,6 +430,14 @@ impl<'a> StandardChannel<'a> {
         &mut self,
         set_new_prev_hash: SetNewPrevHashMp<'a>,
     ) -> Result<(), StandardChannelError> {
+        let set_new_prev_hash = set_new_prev_hash.into_static();
+        let new_chain_tip = ChainTip::from_mining_prev_hash(
+            set_new_prev_hash.prev_hash,
+            set_new_prev_hash.nbits,
+            set_new_prev_hash.min_ntime,
+        )
+        .map_err(StandardChannelError::InvalidPrevHash)?;
+
         match self.future_jobs.remove(&set_new_prev_hash.job_id) {
             Some(mut activated_job) => {
                 activated_job.min_ntime = Sv2Option::new(Some(set_new_prev_hash.min_ntime));
@@ -216,12 +458,12 @@ impl<'a> StandardChannel<'a> {
         // clear seen shares, as shares for past chain tip will be rejected as stale
         self.share_accounting.flush_seen_shares();
 
-        let set_new_prev_hash_static = set_new_prev_hash.into_static();
-        let new_chain_tip = ChainTip::new(
-            set_new_prev_hash_static.prev_hash,
-            set_new_prev_hash_static.nbits,
-            set_new_prev_hash_static.min_ntime,
-        );
+        // forget the jobs requiring a refresh that were just discarded
+        let active_job_id = self.active_job.as_ref().map(|job| job.job_id);
+        let stale_jobs = &self.stale_jobs;
+        self.jobs_requiring_refresh
+            .retain(|job_id| Some(*job_id) == active_job_id || stale_jobs.contains_key(job_id));
+
         self.chain_tip = Some(new_chain_tip);
 
         Ok(())
@@ -241,75 +483,38 @@ impl<'a> StandardChannel<'a> {
     ) -> Result<ShareValidationResult, ShareValidationError> {
         let job_id = share.job_id;
 
-        // check if job_id is active job
-        let is_active_job = self
-            .active_job
-            .as_ref()
-            .is_some_and(|job| job.job_id == job_id);
-
-        // check if job_id is past job
-        let is_past_job = self.past_jobs.contains_key(&job_id);
-
-        // check if job_id is stale job
-        let is_stale_job = self.stale_jobs.contains_key(&job_id);
-
-        if is_stale_job {
+        if self.stale_jobs.contains_key(&job_id) {
             return Err(ShareValidationError::Stale);
         }
 
-        let job = if is_active_job {
-            self.active_job.as_ref().expect("active job must exist")
-        } else if is_past_job {
-            self.past_jobs.get(&job_id).expect("past job must exist")
-        } else {
-            return Err(ShareValidationError::InvalidJobId);
-        };
-
-        let merkle_root: [u8; 32] = job
-            .merkle_root
-            .inner_as_ref()
-            .try_into()
-            .expect("merkle root must be 32 bytes");
-
-        let chain_tip = self
-            .chain_tip
+        // only the active job and the past jobs can be mined on
+        let job = match self
+            .active_job
             .as_ref()
-            .ok_or(ShareValidationError::NoChainTip)?;
-
-        let prev_hash = chain_tip.prev_hash();
-        let nbits = CompactTarget::from_consensus(chain_tip.nbits());
-
-        // create the header for validation
-        let header = Header {
-            version: Version::from_consensus(share.version as i32),
-            prev_blockhash: u256_to_block_hash(prev_hash.clone()),
-            merkle_root: (*Hash::from_bytes_ref(&merkle_root)).into(),
-            time: share.ntime,
-            bits: nbits,
-            nonce: share.nonce,
+            .filter(|job| job.job_id == job_id)
+            .or_else(|| self.past_jobs.get(&job_id))
+        {
+            Some(job) => job,
+            None => return Err(ShareValidationError::InvalidJobId),
         };
 
+        let header = self.share_header(job, &share)?;
+        let nbits = header.bits;
+
         // convert the header hash to a target type for easy comparison
         let hash = header.block_hash();
-        let raw_hash: [u8; 32] = *hash.to_raw_hash().as_ref();
-        let hash_as_target: Target = raw_hash.into();
+        let hash_as_target: Target = WireU256::from(hash).into();
         let hash_as_diff = target_to_difficulty(hash_as_target.clone());
         let network_target = BitcoinTarget::from_compact(nbits);
 
-        // print hash_as_target and self.target as human readable hex
-        let hash_as_u256: binary_sv2::U256 = hash_as_target.clone().into();
-        let mut hash_bytes = hash_as_u256.to_vec();
-        hash_bytes.reverse(); // Convert to big-endian for display
-        let target_u256: binary_sv2::U256 = self.target.clone().into();
-        let mut target_bytes = target_u256.to_vec();
-        target_bytes.reverse(); // Convert to big-endian for display
-
-        debug!(
-            "share validation \nshare:\t\t{}\nchannel target:\t{}\nnetwork target:\t{}",
-            bytes_to_hex(&hash_bytes),
-            bytes_to_hex(&target_bytes),
-            format!("{:x}", network_target)
-        );
+        if debug_enabled!() {
+            debug!(
+                "share validation \nshare:\t\t{}\nchannel target:\t{}\nnetwork target:\t{}",
+                DisplayU256::from(&hash_as_target),
+                DisplayU256::from(&self.target),
+                format!("{:x}", network_target)
+            );
+        }
 
         // check if a block was found
         if network_target.is_met_by(hash) {
@@ -322,7 +527,7 @@ impl<'a> StandardChannel<'a> {
         }
 
         // check if the share hash meets the channel target
-        if hash_as_target < self.target {
+        if hash_as_target <= self.target {
             if self.share_accounting.is_share_seen(hash.to_raw_hash()) {
                 return Err(ShareValidationError::DuplicateShare);
             }
@@ -341,16 +546,113 @@ impl<'a> StandardChannel<'a> {
 
         Err(ShareValidationError::DoesNotMeetTarget)
     }
+
+    /// Builds a `SubmitSharesStandard` for `job`, checking the fields the upstream would reject
+    /// it for.
+    ///
+    /// The share is refused if `job` is not the active job, if `ntime` is lower than the job's
+    /// `min_ntime`, or if `version` differs from the job version outside of the BIP320 bits
+    /// (which standard jobs always allow to be rolled).
+    ///
+    /// The share is not checked against the channel target, see
+    /// [`StandardChannel::precheck_against_target`].
+    pub fn build_share(
+        &self,
+        job: &NewMiningJob,
+        nonce: u32,
+        ntime: u32,
+        version: u32,
+        sequence_number: u32,
+    ) -> Result<SubmitSharesStandard, ShareBuildError> {
+        if self.active_job.as_ref().map(|active_job| active_job.job_id) != Some(job.job_id) {
+            return Err(ShareBuildError::JobNotActive(job.job_id));
+        }
+
+        if let Some(min_ntime) = job.min_ntime.clone().into_inner() {
+            if ntime < min_ntime {
+                return Err(ShareBuildError::NtimeTooLow { min_ntime, ntime });
+            }
+        }
+
+        if (job.version ^ version) & !VERSION_ROLLING_MASK != 0 {
+            return Err(ShareBuildError::VersionRollingNotAllowed);
+        }
+
+        Ok(SubmitSharesStandard {
+            channel_id: self.channel_id,
+            sequence_number,
+            job_id: job.job_id,
+            nonce,
+            ntime,
+            version,
+        })
+    }
+
+    /// Checks whether `share` meets the channel target, without updating the channel state.
+    ///
+    /// Allows a mining client to discard low-diff shares before calling
+    /// [`StandardChannel::validate_share`].
+    pub fn precheck_against_target(
+        &self,
+        share: &SubmitSharesStandard,
+    ) -> Result<(), ShareValidationError> {
+        let job = self
+            .active_job
+            .as_ref()
+            .filter(|job| job.job_id == share.job_id)
+            .or_else(|| self.past_jobs.get(&share.job_id))
+            .ok_or(ShareValidationError::InvalidJobId)?;
+
+        let hash = self.share_header(job, share)?.block_hash();
+        let hash_as_target: Target = WireU256::from(hash).into();
+        if hash_as_target <= self.target {
+            Ok(())
+        } else {
+            Err(ShareValidationError::DoesNotMeetTarget)
+        }
+    }
+
+    // Creates the header hashed by `share`, under the current chain tip.
+    fn share_header(
+        &self,
+        job: &NewMiningJob,
+        share: &SubmitSharesStandard,
+    ) -> Result<Header, ShareValidationError> {
+        let merkle_root: [u8; 32] = job
+            .merkle_root
+            .inner_as_ref()
+            .try_into()
+            .map_err(|_| ShareValidationError::Invalid)?;
+
+        let chain_tip = self
+            .chain_tip
+            .as_ref()
+            .ok_or(ShareValidationError::NoChainTip)?;
+
+        Ok(Header {
+            version: Version::from_consensus(share.version as i32),
+            prev_blockhash: chain_tip.prev_block_hash(),
+            merkle_root: (*Hash::from_bytes_ref(&merkle_root)).into(),
+            time: share.ntime,
+            bits: CompactTarget::from_consensus(chain_tip.nbits()),
+            nonce: share.nonce,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::client::{
+        error::ShareBuildError,
         share_accounting::{ShareValidationError, ShareValidationResult},
         standard::StandardChannel,
     };
     use binary_sv2::Sv2Option;
-    use mining_sv2::{NewMiningJob, SetNewPrevHash as SetNewPrevHashMp, SubmitSharesStandard};
+    use core::convert::TryInto;
+    use mining_sv2::{
+        NewMiningJob, SetNewPrevHash as SetNewPrevHashMp, SubmitSharesError, SubmitSharesStandard,
+        SubmitSharesSuccess, Target,
+    };
 
     #[test]
     fn test_future_job_activation_flow() {
@@ -361,7 +663,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let target = [0xff; 32].into();
+        let target = Target::MAX;
         let nominal_hashrate = 1.0;
 
         let mut channel = StandardChannel::new(
@@ -421,7 +723,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let target = [0xff; 32].into();
+        let target = Target::MAX;
         let nominal_hashrate = 1.0;
 
         let mut channel = StandardChannel::new(
@@ -469,7 +771,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let target = [0xff; 32].into();
+        let target = Target::MAX;
         let nominal_hashrate = 1.0;
 
         let mut channel = StandardChannel::new(
@@ -678,4 +980,256 @@ mod tests {
 
         assert!(matches!(res, Ok(ShareValidationResult::Valid)));
     }
+
+    #[test]
+    fn test_build_share() {
+        let channel_id = 1;
+        let mut channel = StandardChannel::new(
+            channel_id,
+            "user_identity".to_string(),
+            vec![0; 8],
+            Target::MAX,
+            1.0,
+        );
+
+        let future_job = NewMiningJob {
+            channel_id,
+            job_id: 1,
+            merkle_root: [0x11; 32].into(),
+            version: 536870912,
+            min_ntime: Sv2Option::new(None),
+        };
+        channel.on_new_mining_job(future_job.clone());
+
+        let ntime: u32 = 1746839905;
+        channel
+            .on_set_new_prev_hash(SetNewPrevHashMp {
+                channel_id,
+                job_id: future_job.job_id,
+                prev_hash: [0xaa; 32].into(),
+                nbits: 503543726,
+                min_ntime: ntime,
+            })
+            .unwrap();
+        let past_job = channel.get_active_job().unwrap().clone();
+
+        let mut active_job = past_job.clone();
+        active_job.job_id = 2;
+        channel.on_new_mining_job(active_job.clone());
+
+        // the job is no longer active
+        assert!(matches!(
+            channel.build_share(&past_job, 0, ntime, 536870912, 0),
+            Err(ShareBuildError::JobNotActive(1))
+        ));
+
+        // ntime lower than the job's min_ntime
+        assert!(matches!(
+            channel.build_share(&active_job, 0, ntime - 1, 536870912, 0),
+            Err(ShareBuildError::NtimeTooLow {
+                min_ntime,
+                ntime: share_ntime,
+            }) if min_ntime == ntime && share_ntime == ntime - 1
+        ));
+
+        // version changed outside of the BIP320 bits
+        assert!(matches!(
+            channel.build_share(&active_job, 0, ntime, 536870912 | 1, 0),
+            Err(ShareBuildError::VersionRollingNotAllowed)
+        ));
+
+        // BIP320 bits can be rolled
+        let share = channel
+            .build_share(&active_job, 0, ntime + 1, 536870912 | 0x2000, 7)
+            .unwrap();
+        assert_eq!(share.channel_id, channel_id);
+        assert_eq!(share.job_id, 2);
+        assert_eq!(share.sequence_number, 7);
+        assert_eq!(share.version, 536870912 | 0x2000);
+
+        assert!(channel.precheck_against_target(&share).is_ok());
+        channel.set_target([0; 32].into());
+        assert!(matches!(
+            channel.precheck_against_target(&share),
+            Err(ShareValidationError::DoesNotMeetTarget)
+        ));
+        // the channel state is left untouched
+        assert_eq!(channel.get_share_accounting().get_shares_accepted(), 0);
+    }
+
+    #[test]
+    fn test_min_ntime_from_mining_prev_hash() {
+        use crate::{chain_tip::ChainTip, server::share_validation};
+
+        let channel_id = 1;
+        let mut channel = StandardChannel::new(
+            channel_id,
+            "user_identity".to_string(),
+            vec![0; 8],
+            Target::MAX,
+            1.0,
+        );
+        let future_job = NewMiningJob {
+            channel_id,
+            job_id: 1,
+            merkle_root: [0x11; 32].into(),
+            version: 536870912,
+            min_ntime: Sv2Option::new(None),
+        };
+        channel.on_new_mining_job(future_job.clone());
+
+        let min_ntime: u32 = 1746839905;
+        channel
+            .on_set_new_prev_hash(SetNewPrevHashMp {
+                channel_id,
+                job_id: future_job.job_id,
+                prev_hash: [0xaa; 32].into(),
+                nbits: 503543726,
+                min_ntime,
+            })
+            .unwrap();
+        let chain_tip = channel.get_chain_tip().unwrap();
+        assert_eq!(
+            chain_tip,
+            &ChainTip::from_mining_prev_hash([0xaa; 32].into(), 503543726, min_ntime).unwrap()
+        );
+        assert_eq!(chain_tip.min_ntime(), min_ntime);
+
+        // the header template starts at min_ntime
+        let header = channel.header_template(future_job.job_id).unwrap();
+        assert_eq!(header.time, min_ntime);
+
+        // a share at min_ntime passes the ntime checks, one below doesn't
+        let job = channel.get_active_job().unwrap().clone();
+        let share = channel
+            .build_share(&job, 0, min_ntime, job.version, 0)
+            .unwrap();
+        assert_eq!(
+            share_validation::check_share(
+                job.version,
+                chain_tip.min_ntime(),
+                share.version,
+                share.ntime,
+                true
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            share_validation::check_share(
+                job.version,
+                chain_tip.min_ntime(),
+                share.version,
+                min_ntime - 1,
+                true
+            ),
+            Err(share_validation::ShareValidationError::InvalidNtime)
+        );
+    }
+
+    #[test]
+    fn test_submit_stats() {
+        let mut channel =
+            StandardChannel::new(1, "user_identity".to_string(), vec![0; 8], Target::MAX, 1.0);
+        assert_eq!(channel.stale_rate(10), None);
+
+        let success = |last_sequence_number, new_submits_accepted_count| SubmitSharesSuccess {
+            channel_id: 1,
+            last_sequence_number,
+            new_submits_accepted_count,
+            new_shares_sum: 0,
+        };
+        let stale = |sequence_number| SubmitSharesError {
+            channel_id: 1,
+            sequence_number,
+            error_code: SubmitSharesError::stale_share_error_code()
+                .to_string()
+                .try_into()
+                .unwrap(),
+        };
+
+        // a batch of 4 shares, all accepted
+        for sequence_number in 0..4 {
+            channel.on_share_submitted(sequence_number);
+        }
+        channel.on_submit_shares_success(&success(3, 4));
+        assert_eq!(channel.stale_rate(10), Some(0.0));
+
+        // the upstream moved to a new chain tip before the last 2 shares of the next batch
+        for sequence_number in 4..8 {
+            channel.on_share_submitted(sequence_number);
+        }
+        channel.on_submit_shares_error(&stale(6));
+        channel.on_submit_shares_error(&stale(7));
+        channel.on_submit_shares_success(&success(5, 2));
+
+        assert_eq!(channel.get_submit_stats().get_pending(), 0);
+        assert_eq!(channel.get_submit_stats().get_accepted(), 6);
+        assert_eq!(channel.get_submit_stats().get_stale(), 2);
+        assert_eq!(channel.stale_rate(8), Some(0.25));
+        assert_eq!(channel.reject_rate(8), Some(0.25));
+        // over the second batch only
+        assert_eq!(channel.stale_rate(4), Some(0.5));
+        // the local share accounting is left untouched
+        assert_eq!(channel.get_share_accounting().get_shares_accepted(), 0);
+    }
+
+    #[test]
+    fn test_on_set_target() {
+        use super::TargetBounds;
+        use crate::client::error::StandardChannelError;
+        use mining_sv2::SetTarget;
+
+        let set_target = |channel_id, difficulty| SetTarget {
+            channel_id,
+            maximum_target: Target::from_difficulty(difficulty).to_le_bytes().into(),
+        };
+        let mut channel = StandardChannel::new(
+            1,
+            "user_identity".to_string(),
+            vec![0; 8],
+            Target::from_difficulty(1.0),
+            1.0,
+        );
+
+        // without bounds, any target is accepted
+        channel.on_set_target(set_target(1, 1e9)).unwrap();
+        assert_eq!(*channel.get_target(), Target::from_difficulty(1e9));
+        channel.on_set_target(set_target(1, 1.0)).unwrap();
+        assert!(matches!(
+            channel.on_set_target(set_target(2, 2.0)),
+            Err(StandardChannelError::InvalidChannelId)
+        ));
+
+        channel.set_target_bounds(
+            TargetBounds::default()
+                .with_max_difficulty_increase(4.0)
+                .with_max_difficulty(1e7),
+        );
+        // gradual increases are followed
+        let mut difficulty = 1.0;
+        for _ in 0..20 {
+            difficulty *= 2.0;
+            channel.on_set_target(set_target(1, difficulty)).unwrap();
+        }
+        assert_eq!(*channel.get_target(), Target::from_difficulty(difficulty));
+
+        // a 10^6x jump is not
+        match channel.on_set_target(set_target(1, difficulty * 1e6)) {
+            Err(StandardChannelError::TargetOutOfBounds {
+                current_difficulty,
+                difficulty: refused,
+            }) => {
+                assert!((current_difficulty / difficulty - 1.0).abs() < 1e-6);
+                assert!((refused / (difficulty * 1e6) - 1.0).abs() < 1e-6);
+            }
+            other => panic!("expected TargetOutOfBounds, got {:?}", other),
+        }
+        assert_eq!(*channel.get_target(), Target::from_difficulty(difficulty));
+
+        // neither is a target past the maximum difficulty, while easier targets always are
+        channel.on_set_target(set_target(1, 4e6)).unwrap();
+        assert!(channel.on_set_target(set_target(1, 1.2e7)).is_err());
+        channel.on_set_target(set_target(1, 1.0)).unwrap();
+        assert_eq!(*channel.get_target(), Target::from_difficulty(1.0));
+    }
 }
@@ -0,0 +1,314 @@
+//! Statistics on the shares a Mining Client submitted upstream, out of the upstream responses.
+//!
+//! Unlike [`ShareAccounting`](crate::client::share_accounting::ShareAccounting), which follows the
+//! local validation of shares, [`SubmitStats`] follows what the upstream made of them: every
+//! submitted share is resolved as accepted by a `SubmitShares.Success` covering its sequence
+//! number, or as rejected by a `SubmitShares.Error` for it.
+
+use crate::collections::VecDeque;
+use mining_sv2::{SubmitSharesError, SubmitSharesSuccess};
+
+/// Number of resolved submits [`SubmitStats::default`] keeps, i.e. the largest window rates can
+/// be computed on.
+pub const DEFAULT_SUBMIT_HISTORY_LEN: usize = 1000;
+
+/// Why the upstream rejected a share, out of the `error_code` of `SubmitShares.Error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubmitRejectReason {
+    InvalidChannelId,
+    Stale,
+    DifficultyTooLow,
+    InvalidJobId,
+    /// An error code not defined by the spec.
+    Other,
+}
+
+impl SubmitRejectReason {
+    pub fn from_error_code(error_code: &str) -> Self {
+        if error_code == SubmitSharesError::invalid_channel_error_code() {
+            Self::InvalidChannelId
+        } else if error_code == SubmitSharesError::stale_share_error_code() {
+            Self::Stale
+        } else if error_code == SubmitSharesError::difficulty_too_low_error_code() {
+            Self::DifficultyTooLow
+        } else if error_code == SubmitSharesError::invalid_job_id_error_code() {
+            Self::InvalidJobId
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// What the upstream made of a submitted share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubmitOutcome {
+    Accepted,
+    Rejected(SubmitRejectReason),
+}
+
+/// Tracks the shares submitted on a channel until the upstream responds to them.
+///
+/// Shares are registered with [`SubmitStats::on_share_submitted`], in submission order, and
+/// resolved by [`SubmitStats::on_submit_shares_success`] and
+/// [`SubmitStats::on_submit_shares_error`]. Responses for sequence numbers that were never
+/// registered are ignored.
+///
+/// The last `history_len` outcomes are kept for [`SubmitStats::stale_rate`] and
+/// [`SubmitStats::reject_rate`], and at most `history_len` submits are awaited: past that, the
+/// oldest pending submit is forgotten, as an upstream that fell that far behind is unlikely to
+/// respond to it.
+#[derive(Debug, Clone)]
+pub struct SubmitStats {
+    history_len: usize,
+    // sequence numbers of the submits awaiting a response, in submission order
+    pending: VecDeque<u32>,
+    // the most recent outcomes, the latest at the back
+    history: VecDeque<SubmitOutcome>,
+    submitted: u64,
+    accepted: u64,
+    rejected: u64,
+    stale: u64,
+}
+
+impl Default for SubmitStats {
+    fn default() -> Self {
+        Self::new(DEFAULT_SUBMIT_HISTORY_LEN)
+    }
+}
+
+impl SubmitStats {
+    pub fn new(history_len: usize) -> Self {
+        Self {
+            history_len,
+            pending: VecDeque::new(),
+            history: VecDeque::new(),
+            submitted: 0,
+            accepted: 0,
+            rejected: 0,
+            stale: 0,
+        }
+    }
+
+    /// Registers a share submitted upstream with `sequence_number`.
+    pub fn on_share_submitted(&mut self, sequence_number: u32) {
+        if self.history_len == 0 {
+            return;
+        }
+        if self.pending.len() == self.history_len {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(sequence_number);
+        self.submitted += 1;
+    }
+
+    /// Resolves every pending submit up to `last_sequence_number` as accepted.
+    ///
+    /// Submits rejected with a `SubmitShares.Error` are expected to be reported before the
+    /// `SubmitShares.Success` of their batch, as the spec lets the upstream acknowledge a batch
+    /// with the sequence number of its last accepted share.
+    pub fn on_submit_shares_success(&mut self, success: &SubmitSharesSuccess) {
+        while let Some(&sequence_number) = self.pending.front() {
+            if sequence_number > success.last_sequence_number {
+                break;
+            }
+            self.pending.pop_front();
+            self.accepted += 1;
+            self.record(SubmitOutcome::Accepted);
+        }
+    }
+
+    /// Resolves the pending submit with the sequence number of `error` as rejected.
+    pub fn on_submit_shares_error(&mut self, error: &SubmitSharesError) {
+        let position = self
+            .pending
+            .iter()
+            .position(|sequence_number| *sequence_number == error.sequence_number);
+        if let Some(position) = position {
+            self.pending.remove(position);
+            let reason = SubmitRejectReason::from_error_code(&error.error_code.as_utf8_or_hex());
+            self.rejected += 1;
+            if reason == SubmitRejectReason::Stale {
+                self.stale += 1;
+            }
+            self.record(SubmitOutcome::Rejected(reason));
+        }
+    }
+
+    fn record(&mut self, outcome: SubmitOutcome) {
+        if self.history.len() == self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back(outcome);
+    }
+
+    /// The share of the last `window` resolved submits the upstream rejected as stale.
+    ///
+    /// `window` is capped to the history length. `None` if no submit was resolved yet.
+    pub fn stale_rate(&self, window: usize) -> Option<f64> {
+        self.rate(window, |outcome| {
+            outcome == SubmitOutcome::Rejected(SubmitRejectReason::Stale)
+        })
+    }
+
+    /// The share of the last `window` resolved submits the upstream rejected, for any reason.
+    ///
+    /// `window` is capped to the history length. `None` if no submit was resolved yet.
+    pub fn reject_rate(&self, window: usize) -> Option<f64> {
+        self.rate(window, |outcome| outcome != SubmitOutcome::Accepted)
+    }
+
+    fn rate(&self, window: usize, counts: impl Fn(SubmitOutcome) -> bool) -> Option<f64> {
+        let window = window.min(self.history.len());
+        if window == 0 {
+            return None;
+        }
+        let counted = self
+            .history
+            .iter()
+            .rev()
+            .take(window)
+            .filter(|outcome| counts(**outcome))
+            .count();
+        Some(counted as f64 / window as f64)
+    }
+
+    /// The most recent outcomes, the latest last.
+    pub fn get_history(&self) -> impl Iterator<Item = &SubmitOutcome> {
+        self.history.iter()
+    }
+
+    pub fn get_history_len(&self) -> usize {
+        self.history_len
+    }
+
+    /// Number of submits awaiting a response.
+    pub fn get_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn get_submitted(&self) -> u64 {
+        self.submitted
+    }
+
+    pub fn get_accepted(&self) -> u64 {
+        self.accepted
+    }
+
+    pub fn get_rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    pub fn get_stale(&self) -> u64 {
+        self.stale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use core::convert::TryInto;
+
+    fn success(last_sequence_number: u32, new_submits_accepted_count: u32) -> SubmitSharesSuccess {
+        SubmitSharesSuccess {
+            channel_id: 1,
+            last_sequence_number,
+            new_submits_accepted_count,
+            new_shares_sum: new_submits_accepted_count as u64,
+        }
+    }
+
+    fn error(sequence_number: u32, error_code: &str) -> SubmitSharesError<'static> {
+        SubmitSharesError {
+            channel_id: 1,
+            sequence_number,
+            error_code: error_code.to_string().try_into().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_submit_stats() {
+        let mut stats = SubmitStats::new(8);
+        assert_eq!(stats.stale_rate(8), None);
+        assert_eq!(stats.reject_rate(8), None);
+
+        for sequence_number in 0..6 {
+            stats.on_share_submitted(sequence_number);
+        }
+        assert_eq!(stats.get_pending(), 6);
+
+        // shares 1 and 4 are stale, reported before the batch they belong to
+        stats.on_submit_shares_error(&error(1, "stale-share"));
+        stats.on_submit_shares_success(&success(2, 2));
+        stats.on_submit_shares_error(&error(4, "stale-share"));
+        stats.on_submit_shares_error(&error(5, "difficulty-too-low"));
+        stats.on_submit_shares_success(&success(3, 1));
+        assert_eq!(stats.get_pending(), 0);
+
+        assert_eq!(
+            stats.get_history().copied().collect::<Vec<_>>(),
+            vec![
+                SubmitOutcome::Rejected(SubmitRejectReason::Stale),
+                SubmitOutcome::Accepted,
+                SubmitOutcome::Accepted,
+                SubmitOutcome::Rejected(SubmitRejectReason::Stale),
+                SubmitOutcome::Rejected(SubmitRejectReason::DifficultyTooLow),
+                SubmitOutcome::Accepted,
+            ]
+        );
+        assert_eq!(stats.get_submitted(), 6);
+        assert_eq!(stats.get_accepted(), 3);
+        assert_eq!(stats.get_rejected(), 3);
+        assert_eq!(stats.get_stale(), 2);
+
+        assert_eq!(stats.stale_rate(6), Some(2.0 / 6.0));
+        assert_eq!(stats.reject_rate(6), Some(0.5));
+        // windows beyond the history are capped
+        assert_eq!(stats.reject_rate(100), Some(0.5));
+        // the last three: stale, difficulty-too-low, accepted
+        assert_eq!(stats.stale_rate(3), Some(1.0 / 3.0));
+        assert_eq!(stats.reject_rate(3), Some(2.0 / 3.0));
+        assert_eq!(stats.reject_rate(1), Some(0.0));
+
+        // responses for unknown or already resolved submits are ignored
+        stats.on_submit_shares_error(&error(0, "stale-share"));
+        stats.on_submit_shares_error(&error(42, "stale-share"));
+        stats.on_submit_shares_success(&success(42, 1));
+        assert_eq!(stats.get_history().count(), 6);
+
+        // the history keeps the last 8 outcomes only
+        for sequence_number in 6..10 {
+            stats.on_share_submitted(sequence_number);
+        }
+        stats.on_submit_shares_success(&success(9, 4));
+        assert_eq!(stats.get_history().count(), 8);
+        assert_eq!(stats.stale_rate(8), Some(1.0 / 8.0));
+        assert_eq!(stats.reject_rate(8), Some(2.0 / 8.0));
+    }
+
+    #[test]
+    fn test_submit_stats_pending_limit() {
+        let mut stats = SubmitStats::new(4);
+        for sequence_number in 0..6 {
+            stats.on_share_submitted(sequence_number);
+        }
+        // submits 0 and 1 were forgotten
+        assert_eq!(stats.get_pending(), 4);
+        stats.on_submit_shares_error(&error(0, "stale-share"));
+        assert_eq!(stats.get_rejected(), 0);
+
+        stats.on_submit_shares_error(&error(2, "invalid-job-id"));
+        stats.on_submit_shares_error(&error(3, "unknown-error"));
+        stats.on_submit_shares_success(&success(5, 2));
+        assert_eq!(
+            stats.get_history().copied().collect::<Vec<_>>(),
+            vec![
+                SubmitOutcome::Rejected(SubmitRejectReason::InvalidJobId),
+                SubmitOutcome::Rejected(SubmitRejectReason::Other),
+                SubmitOutcome::Accepted,
+                SubmitOutcome::Accepted,
+            ]
+        );
+        assert_eq!(stats.stale_rate(4), Some(0.0));
+    }
+}
@@ -0,0 +1,59 @@
+//! Source of the current time for the time-dependent features of channels.
+//!
+//! Channels never read the system clock themselves, but the [`Clock`] set on them (e.g. via
+//! [`StandardChannel::set_clock`](crate::server::standard::StandardChannel::set_clock)). It
+//! defaults to the [`SystemClock`] with the `std` feature. Without it, there's no default clock:
+//! timestamps are left out, unless a clock is set.
+//!
+//! Tests can drive the time by hand with a
+//! [`TestClock`](crate::testing::clock::TestClock), with the `test-utils` feature.
+use alloc::sync::Arc;
+use core::fmt::Debug;
+
+/// Tells the current time.
+pub trait Clock: Send + Sync + Debug {
+    /// Returns the current time, as milliseconds since the Unix epoch.
+    fn now_millis(&self) -> u64;
+}
+
+/// The [`Clock`] reading the system time.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+// The clock of channels no clock was set on.
+#[cfg(feature = "std")]
+pub(crate) fn default_clock() -> Option<Arc<dyn Clock>> {
+    Some(Arc::new(SystemClock))
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn default_clock() -> Option<Arc<dyn Clock>> {
+    None
+}
+
+// The current time of `clock` as a Unix timestamp in seconds, if there's a clock.
+pub(crate) fn unix_timestamp(clock: Option<&Arc<dyn Clock>>) -> Option<u64> {
+    clock.map(|clock| clock.now_millis() / 1000)
+}
+
+// The current time of `clock`, or of the system if there's no clock, for the event log.
+#[cfg(feature = "event-log")]
+pub(crate) fn system_time(clock: Option<&Arc<dyn Clock>>) -> std::time::SystemTime {
+    match clock {
+        Some(clock) => {
+            std::time::UNIX_EPOCH + core::time::Duration::from_millis(clock.now_millis())
+        }
+        None => std::time::SystemTime::now(),
+    }
+}
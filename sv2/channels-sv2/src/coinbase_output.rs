@@ -0,0 +1,215 @@
+//! # Coinbase Output Template
+//!
+//! Turns a pool payout policy (e.g. "97% to the pool, 2% to the dev fund, 1% to the operator")
+//! into the coinbase reward outputs passed to the channels along with every `NewTemplate`.
+use alloc::vec::Vec;
+use bitcoin::{transaction::TxOut, Amount, ScriptBuf};
+
+/// Outputs below this value (in satoshis) are rejected by default, see
+/// [`CoinbaseOutputTemplate::with_dust_threshold`].
+pub const DEFAULT_DUST_THRESHOLD: u64 = 546;
+
+/// A script receiving a share of the coinbase reward proportional to its `weight`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeightedOutput {
+    pub script_pubkey: ScriptBuf,
+    pub weight: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoinbaseOutputTemplateError {
+    NoOutputs,
+    ZeroWeight,
+    // index of the output, value it would have received
+    DustOutput(usize, u64),
+}
+
+/// A list of [`WeightedOutput`]s splitting the coinbase reward.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoinbaseOutputTemplate {
+    outputs: Vec<WeightedOutput>,
+    dust_threshold: u64,
+}
+
+impl CoinbaseOutputTemplate {
+    /// Fails if `outputs` is empty or any of them has a zero weight.
+    pub fn new(outputs: Vec<WeightedOutput>) -> Result<Self, CoinbaseOutputTemplateError> {
+        if outputs.is_empty() {
+            return Err(CoinbaseOutputTemplateError::NoOutputs);
+        }
+        if outputs.iter().any(|output| output.weight == 0) {
+            return Err(CoinbaseOutputTemplateError::ZeroWeight);
+        }
+        Ok(Self {
+            outputs,
+            dust_threshold: DEFAULT_DUST_THRESHOLD,
+        })
+    }
+
+    /// Sets the value (in satoshis) below which an output is considered dust.
+    pub fn with_dust_threshold(mut self, dust_threshold: u64) -> Self {
+        self.dust_threshold = dust_threshold;
+        self
+    }
+
+    pub fn get_outputs(&self) -> &[WeightedOutput] {
+        &self.outputs
+    }
+
+    pub fn get_dust_threshold(&self) -> u64 {
+        self.dust_threshold
+    }
+
+    /// Splits `value_remaining` (usually `NewTemplate.coinbase_tx_value_remaining`) among the
+    /// outputs, proportionally to their weights.
+    ///
+    /// Uses largest-remainder rounding, so the values of the outputs always add up to
+    /// `value_remaining` exactly. Fails if any output would be below the dust threshold.
+    pub fn materialize(
+        &self,
+        value_remaining: u64,
+    ) -> Result<Vec<TxOut>, CoinbaseOutputTemplateError> {
+        let total_weight: u128 = self
+            .outputs
+            .iter()
+            .map(|output| output.weight as u128)
+            .sum();
+
+        // (floor of the exact share, remainder of the division)
+        let mut shares: Vec<(u64, u128)> = self
+            .outputs
+            .iter()
+            .map(|output| {
+                let exact = value_remaining as u128 * output.weight as u128;
+                ((exact / total_weight) as u64, exact % total_weight)
+            })
+            .collect();
+
+        // the floors lose less than one satoshi per output, hand those out to the outputs with
+        // the largest remainders (the first ones on ties)
+        let distributed: u64 = shares.iter().map(|(value, _)| value).sum();
+        let mut by_remainder: Vec<usize> = (0..shares.len()).collect();
+        by_remainder.sort_by(|a, b| shares[*b].1.cmp(&shares[*a].1));
+        for index in by_remainder
+            .into_iter()
+            .take((value_remaining - distributed) as usize)
+        {
+            shares[index].0 += 1;
+        }
+
+        shares
+            .into_iter()
+            .zip(self.outputs.iter())
+            .enumerate()
+            .map(|(index, ((value, _), output))| {
+                if value < self.dust_threshold {
+                    return Err(CoinbaseOutputTemplateError::DustOutput(index, value));
+                }
+                Ok(TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey: output.script_pubkey.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(weights: &[u32]) -> CoinbaseOutputTemplate {
+        let outputs = weights
+            .iter()
+            .enumerate()
+            .map(|(index, weight)| WeightedOutput {
+                script_pubkey: ScriptBuf::from(vec![index as u8]),
+                weight: *weight,
+            })
+            .collect();
+        CoinbaseOutputTemplate::new(outputs).unwrap()
+    }
+
+    fn values(outputs: &[TxOut]) -> Vec<u64> {
+        outputs.iter().map(|output| output.value.to_sat()).collect()
+    }
+
+    #[test]
+    fn test_materialize() {
+        // 97% to the pool, 2% to the dev fund, 1% to the operator
+        let template = template(&[97, 2, 1]);
+        let outputs = template.materialize(312_500_000).unwrap();
+        assert_eq!(values(&outputs), vec![303_125_000, 6_250_000, 3_125_000]);
+        assert_eq!(outputs[1].script_pubkey, ScriptBuf::from(vec![1]));
+
+        // 10 sats split in three: 3.33 each, the remaining satoshi goes to the first output
+        let template = self::template(&[1, 1, 1]).with_dust_threshold(0);
+        assert_eq!(values(&template.materialize(10).unwrap()), vec![4, 3, 3]);
+
+        // the largest remainder gets the satoshi, not the largest weight
+        let template = self::template(&[5, 3]).with_dust_threshold(0);
+        // exact shares: 6.25 and 3.75
+        assert_eq!(values(&template.materialize(10).unwrap()), vec![6, 4]);
+    }
+
+    #[test]
+    fn test_materialize_errors() {
+        assert_eq!(
+            CoinbaseOutputTemplate::new(vec![]),
+            Err(CoinbaseOutputTemplateError::NoOutputs)
+        );
+        assert_eq!(
+            CoinbaseOutputTemplate::new(vec![WeightedOutput {
+                script_pubkey: ScriptBuf::new(),
+                weight: 0,
+            }]),
+            Err(CoinbaseOutputTemplateError::ZeroWeight)
+        );
+
+        // 1% of 50_000 sats is below the default dust threshold
+        let template = template(&[99, 1]);
+        assert_eq!(
+            template.materialize(50_000),
+            Err(CoinbaseOutputTemplateError::DustOutput(1, 500))
+        );
+        assert!(template
+            .with_dust_threshold(500)
+            .materialize(50_000)
+            .is_ok());
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn test_materialize_preserves_value(weights: Vec<u32>, value_remaining: u64) -> bool {
+        let weights: Vec<u32> = weights.into_iter().filter(|weight| *weight != 0).collect();
+        if weights.is_empty() {
+            return true;
+        }
+        let template = template(&weights).with_dust_threshold(0);
+        let outputs = template.materialize(value_remaining).unwrap();
+        outputs.len() == weights.len() && values(&outputs).iter().sum::<u64>() == value_remaining
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn test_materialize_never_produces_dust(weights: Vec<u8>, value_remaining: u64) -> bool {
+        let weights: Vec<u32> = weights
+            .into_iter()
+            .filter(|weight| *weight != 0)
+            .map(u32::from)
+            .collect();
+        if weights.is_empty() {
+            return true;
+        }
+        match template(&weights).materialize(value_remaining) {
+            Ok(outputs) => {
+                values(&outputs).iter().sum::<u64>() == value_remaining
+                    && values(&outputs)
+                        .iter()
+                        .all(|value| *value >= DEFAULT_DUST_THRESHOLD)
+            }
+            Err(CoinbaseOutputTemplateError::DustOutput(_, value)) => {
+                value < DEFAULT_DUST_THRESHOLD
+            }
+            Err(_) => false,
+        }
+    }
+}
@@ -0,0 +1,9 @@
+//! The collections used across the crate, from `std` or, without it, from `alloc` and
+//! `hashbrown`.
+
+pub(crate) use alloc::collections::{BTreeMap, VecDeque};
+#[cfg(feature = "std")]
+pub(crate) use std::collections::{HashMap, HashSet};
+
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+pub(crate) use hashbrown::{HashMap, HashSet};
@@ -0,0 +1,288 @@
+//! # Connection Flags
+//!
+//! The `flags` field of a Mining Protocol `SetupConnection` message defines how the channels
+//! opened on that connection are expected to behave.
+//!
+//! [`ConnectionFlags`] gives a typed view over those bits, while [`ChannelFactory`] turns the
+//! negotiated flags into the kind of channel (and its version rolling policy) to be created for
+//! each `OpenStandardMiningChannel` / `OpenExtendedMiningChannel` request.
+//!
+//! ref: <https://github.com/stratum-mining/sv2-spec/blob/main/05-Mining-Protocol.md>
+use core::ops::BitOr;
+use mining_sv2::{OpenExtendedMiningChannel, OpenStandardMiningChannel};
+
+/// The flags of a Mining Protocol `SetupConnection` message.
+///
+/// Bits not defined by the spec are kept as they are, so [`ConnectionFlags::bits`] always returns
+/// the original value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ConnectionFlags(u32);
+
+impl ConnectionFlags {
+    /// The downstream requires standard jobs, and is unable to process extended jobs sent to
+    /// standard channels through a group channel.
+    pub const REQUIRES_STANDARD_JOBS: Self = Self(1 << 0);
+    /// The downstream requires version rolling, so upstream must not send jobs that don't allow
+    /// it.
+    pub const REQUIRES_VERSION_ROLLING: Self = Self(1 << 1);
+    /// The downstream requires custom work (via Job Declaration).
+    pub const REQUIRES_WORK_SELECTION: Self = Self(1 << 2);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Whether all the bits of `other` are set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+
+    pub const fn requires_standard_jobs(&self) -> bool {
+        self.contains(Self::REQUIRES_STANDARD_JOBS)
+    }
+
+    pub const fn requires_work_selection(&self) -> bool {
+        self.contains(Self::REQUIRES_WORK_SELECTION)
+    }
+
+    pub const fn requires_version_rolling(&self) -> bool {
+        self.contains(Self::REQUIRES_VERSION_ROLLING)
+    }
+}
+
+impl From<u32> for ConnectionFlags {
+    fn from(bits: u32) -> Self {
+        Self::from_bits(bits)
+    }
+}
+
+impl From<ConnectionFlags> for u32 {
+    fn from(flags: ConnectionFlags) -> Self {
+        flags.bits()
+    }
+}
+
+impl BitOr for ConnectionFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// A request to open a channel, as received by a Mining Server.
+#[derive(Debug, Clone, Copy)]
+pub enum OpenMiningChannel<'a, 'decoder> {
+    Standard(&'a OpenStandardMiningChannel<'decoder>),
+    Extended(&'a OpenExtendedMiningChannel<'decoder>),
+}
+
+/// The kind of channel to be created for an [`OpenMiningChannel`] request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelKind {
+    Standard,
+    Extended,
+}
+
+/// How a channel should be created, as decided by [`ChannelFactory::channel_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelPolicy {
+    pub kind: ChannelKind,
+    /// To be passed to the channel (see `set_version_rolling_allowed` on the server channels).
+    pub version_rolling_allowed: bool,
+    /// Whether the channel can be part of a group channel, i.e. receive extended jobs.
+    ///
+    /// Always `false` for extended channels.
+    pub groupable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelFactoryError {
+    /// An extended channel was requested on a connection that requires standard jobs.
+    StandardJobsRequired,
+    /// The connection requires version rolling, but the server doesn't allow it.
+    VersionRollingNotSupported,
+}
+
+/// Decides which channels are created on a connection, given the negotiated
+/// [`ConnectionFlags`].
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelFactory {
+    flags: ConnectionFlags,
+    version_rolling_allowed: bool,
+}
+
+impl ChannelFactory {
+    /// `version_rolling_allowed` is the server policy, applied to connections that don't set
+    /// [`ConnectionFlags::REQUIRES_VERSION_ROLLING`].
+    pub fn new(flags: ConnectionFlags, version_rolling_allowed: bool) -> Self {
+        Self {
+            flags,
+            version_rolling_allowed,
+        }
+    }
+
+    pub fn get_flags(&self) -> ConnectionFlags {
+        self.flags
+    }
+
+    /// Decides how the channel requested by `request` should be created.
+    pub fn channel_policy(
+        &self,
+        request: OpenMiningChannel,
+    ) -> Result<ChannelPolicy, ChannelFactoryError> {
+        if self.flags.requires_version_rolling() && !self.version_rolling_allowed {
+            return Err(ChannelFactoryError::VersionRollingNotSupported);
+        }
+
+        match request {
+            OpenMiningChannel::Standard(_) => Ok(ChannelPolicy {
+                kind: ChannelKind::Standard,
+                version_rolling_allowed: self.version_rolling_allowed,
+                groupable: !self.flags.requires_standard_jobs(),
+            }),
+            OpenMiningChannel::Extended(_) => {
+                if self.flags.requires_standard_jobs() {
+                    return Err(ChannelFactoryError::StandardJobsRequired);
+                }
+                Ok(ChannelPolicy {
+                    kind: ChannelKind::Extended,
+                    version_rolling_allowed: self.version_rolling_allowed,
+                    groupable: false,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common_messages_sv2::{has_requires_std_job, has_version_rolling, has_work_selection};
+    use std::convert::TryInto;
+
+    fn open_standard_channel() -> OpenStandardMiningChannel<'static> {
+        OpenStandardMiningChannel {
+            request_id: 1.into(),
+            user_identity: "user_identity".to_string().try_into().unwrap(),
+            nominal_hash_rate: 1_000.0,
+            max_target: [0xff; 32].into(),
+        }
+    }
+
+    fn open_extended_channel() -> OpenExtendedMiningChannel<'static> {
+        OpenExtendedMiningChannel {
+            request_id: 1,
+            user_identity: "user_identity".to_string().try_into().unwrap(),
+            nominal_hash_rate: 1_000.0,
+            max_target: [0xff; 32].into(),
+            min_extranonce_size: 8,
+        }
+    }
+
+    #[test]
+    fn test_connection_flags() {
+        let flags = ConnectionFlags::from_bits(0b111);
+        assert!(flags.requires_standard_jobs());
+        assert!(flags.requires_work_selection());
+        assert!(flags.requires_version_rolling());
+        assert_eq!(
+            flags,
+            ConnectionFlags::REQUIRES_STANDARD_JOBS
+                | ConnectionFlags::REQUIRES_WORK_SELECTION
+                | ConnectionFlags::REQUIRES_VERSION_ROLLING
+        );
+
+        let mut flags = ConnectionFlags::empty();
+        assert!(!flags.requires_standard_jobs());
+        assert!(!flags.requires_work_selection());
+        assert!(!flags.requires_version_rolling());
+        flags.insert(ConnectionFlags::REQUIRES_WORK_SELECTION);
+        assert_eq!(flags.bits(), 0b100);
+        flags.remove(ConnectionFlags::REQUIRES_WORK_SELECTION);
+        assert_eq!(flags, ConnectionFlags::empty());
+
+        // bits not defined by the spec are preserved
+        let flags: ConnectionFlags = 0x8000_0002.into();
+        assert!(flags.requires_version_rolling());
+        assert_eq!(u32::from(flags), 0x8000_0002);
+    }
+
+    #[test]
+    fn test_connection_flags_match_common_messages() {
+        for bits in (0..0b1000).chain([0x8000_0000, 0xffff_ffff]) {
+            let flags = ConnectionFlags::from_bits(bits);
+            assert_eq!(flags.requires_standard_jobs(), has_requires_std_job(bits));
+            assert_eq!(flags.requires_version_rolling(), has_version_rolling(bits));
+            assert_eq!(flags.requires_work_selection(), has_work_selection(bits));
+        }
+    }
+
+    #[test]
+    fn test_channel_policy_for_every_flag_combination() {
+        let open_standard_channel = open_standard_channel();
+        let open_extended_channel = open_extended_channel();
+
+        for bits in 0..0b1000 {
+            let flags = ConnectionFlags::from_bits(bits);
+            for version_rolling_allowed in [false, true] {
+                let factory = ChannelFactory::new(flags, version_rolling_allowed);
+
+                let standard =
+                    factory.channel_policy(OpenMiningChannel::Standard(&open_standard_channel));
+                let extended =
+                    factory.channel_policy(OpenMiningChannel::Extended(&open_extended_channel));
+
+                if flags.requires_version_rolling() && !version_rolling_allowed {
+                    assert_eq!(
+                        standard,
+                        Err(ChannelFactoryError::VersionRollingNotSupported)
+                    );
+                    assert_eq!(
+                        extended,
+                        Err(ChannelFactoryError::VersionRollingNotSupported)
+                    );
+                    continue;
+                }
+
+                assert_eq!(
+                    standard,
+                    Ok(ChannelPolicy {
+                        kind: ChannelKind::Standard,
+                        version_rolling_allowed,
+                        groupable: !flags.requires_standard_jobs(),
+                    })
+                );
+
+                if flags.requires_standard_jobs() {
+                    assert_eq!(extended, Err(ChannelFactoryError::StandardJobsRequired));
+                } else {
+                    assert_eq!(
+                        extended,
+                        Ok(ChannelPolicy {
+                            kind: ChannelKind::Extended,
+                            version_rolling_allowed,
+                            groupable: false,
+                        })
+                    );
+                }
+            }
+        }
+    }
+}
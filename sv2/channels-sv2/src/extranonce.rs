@@ -0,0 +1,396 @@
+//! # Extranonce Layout
+//!
+//! The full extranonce is at most [`MAX_EXTRANONCE_LEN`] bytes, split into:
+//! - the pool (or upstream) prefix
+//! - the per-channel prefix
+//! - the rollable part, which downstream is free to roll while mining
+//!
+//! [`ExtranonceLayout`] keeps the arithmetic over those segments in a single place.
+//!
+//! The extranonce prefix of a channel is the pool prefix and the per-channel prefix together,
+//! and whatever it leaves of the [`MAX_EXTRANONCE_LEN`] bytes is rollable. How much rollable
+//! space a channel needs depends on its downstream: none for Standard Channels, the minimum
+//! negotiated on `OpenExtendedMiningChannel` for Extended Channels, which check their prefix
+//! against it with [`ExtranonceLayout::for_channel`] when they are created. Typical layouts:
+//!
+//! | Downstream                                        | Prefix   | Rollable |
+//! |---------------------------------------------------|----------|----------|
+//! | Standard Channel of a header-only device          | up to 32 | 0        |
+//! | Extended Channel of a device rolling 8 bytes      | up to 24 | 8        |
+//! | Extended Channel of a proxy of Standard Channels  | up to 28 | 4        |
+//! | Extended Channel of a proxy of Extended Channels  | up to 20 | 12       |
+//!
+//! The proxies above hand out 4 bytes of per-channel prefix to each of their downstreams, out
+//! of their own rollable space, and leave 8 bytes rollable to Extended Channels.
+//!
+//! A prefix of [`MAX_EXTRANONCE_LEN`] bytes is only fit for Standard Channels: it leaves no
+//! room for a proxy to tell its own downstreams apart, whose jobs would then collide.
+use alloc::vec::Vec;
+use core::ops::Range;
+use mining_sv2::{ExtendedExtranonce, ExtendedExtranonceError, MAX_EXTRANONCE_LEN};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtranonceLayoutError {
+    /// The segments add up to more than [`MAX_EXTRANONCE_LEN`] bytes.
+    ExceedsMaxLength,
+    /// A segment doesn't have the size defined by the layout.
+    InvalidPartLength { expected: usize, actual: usize },
+    /// The extranonce prefix leaves less than the rollable space the channel needs.
+    InsufficientRollableSpace {
+        prefix_size: usize,
+        min_rollable_size: usize,
+    },
+    /// A full extranonce doesn't begin with the extranonce prefix of the channel.
+    PrefixMismatch,
+}
+
+/// The pool, channel and rolled parts of a full extranonce, as returned by
+/// [`ExtranonceLayout::split`].
+pub type ExtranonceParts<'a> = (&'a [u8], &'a [u8], &'a [u8]);
+
+/// The sizes of the segments of a full extranonce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtranonceLayout {
+    pool_prefix_size: usize,
+    channel_prefix_size: usize,
+    rollable_size: usize,
+}
+
+impl ExtranonceLayout {
+    /// Creates a new layout, failing if the segments add up to more than [`MAX_EXTRANONCE_LEN`].
+    pub fn new(
+        pool_prefix_size: usize,
+        channel_prefix_size: usize,
+        rollable_size: usize,
+    ) -> Result<Self, ExtranonceLayoutError> {
+        let total_size = pool_prefix_size
+            .checked_add(channel_prefix_size)
+            .and_then(|size| size.checked_add(rollable_size))
+            .ok_or(ExtranonceLayoutError::ExceedsMaxLength)?;
+        if total_size > MAX_EXTRANONCE_LEN {
+            return Err(ExtranonceLayoutError::ExceedsMaxLength);
+        }
+
+        Ok(Self {
+            pool_prefix_size,
+            channel_prefix_size,
+            rollable_size,
+        })
+    }
+
+    /// Creates the layout of a channel with an already assigned extranonce prefix, where all the
+    /// remaining space up to [`MAX_EXTRANONCE_LEN`] is rollable.
+    ///
+    /// The whole prefix is accounted as pool prefix.
+    pub fn from_prefix_len(extranonce_prefix_len: usize) -> Result<Self, ExtranonceLayoutError> {
+        let rollable_size = MAX_EXTRANONCE_LEN
+            .checked_sub(extranonce_prefix_len)
+            .ok_or(ExtranonceLayoutError::ExceedsMaxLength)?;
+        Self::new(extranonce_prefix_len, 0, rollable_size)
+    }
+
+    /// Creates the layout of a channel with an already assigned extranonce prefix, checking that
+    /// at least `min_rollable_size` bytes are left rollable: 0 for Standard Channels, the
+    /// negotiated minimum for Extended Channels.
+    ///
+    /// A prefix longer than [`MAX_EXTRANONCE_LEN`] fails with
+    /// [`ExtranonceLayoutError::ExceedsMaxLength`], one that fits but leaves too little rollable
+    /// space with [`ExtranonceLayoutError::InsufficientRollableSpace`].
+    pub fn for_channel(
+        extranonce_prefix_len: usize,
+        min_rollable_size: usize,
+    ) -> Result<Self, ExtranonceLayoutError> {
+        let layout = Self::from_prefix_len(extranonce_prefix_len)?;
+        if layout.rollable_size < min_rollable_size {
+            return Err(ExtranonceLayoutError::InsufficientRollableSpace {
+                prefix_size: extranonce_prefix_len,
+                min_rollable_size,
+            });
+        }
+        Ok(layout)
+    }
+
+    pub fn get_pool_prefix_size(&self) -> usize {
+        self.pool_prefix_size
+    }
+
+    pub fn get_channel_prefix_size(&self) -> usize {
+        self.channel_prefix_size
+    }
+
+    /// The size of the extranonce prefix sent to downstream (pool prefix + channel prefix).
+    pub fn get_prefix_size(&self) -> usize {
+        self.pool_prefix_size + self.channel_prefix_size
+    }
+
+    pub fn get_rollable_size(&self) -> usize {
+        self.rollable_size
+    }
+
+    pub fn get_total_size(&self) -> usize {
+        self.get_prefix_size() + self.rollable_size
+    }
+
+    /// The byte offsets of the pool prefix.
+    pub fn pool_prefix_range(&self) -> Range<usize> {
+        0..self.pool_prefix_size
+    }
+
+    /// The byte offsets of the channel prefix.
+    pub fn channel_prefix_range(&self) -> Range<usize> {
+        self.pool_prefix_size..self.get_prefix_size()
+    }
+
+    /// The byte offsets of the rollable part.
+    pub fn rollable_range(&self) -> Range<usize> {
+        self.get_prefix_size()..self.get_total_size()
+    }
+
+    /// Creates an [`ExtendedExtranonce`] (used to hand out extranonce prefixes) following this
+    /// layout.
+    pub fn new_extended_extranonce(
+        &self,
+        additional_coinbase_script_data: Option<Vec<u8>>,
+    ) -> Result<ExtendedExtranonce, ExtendedExtranonceError> {
+        ExtendedExtranonce::new(
+            self.pool_prefix_range(),
+            self.channel_prefix_range(),
+            self.rollable_range(),
+            additional_coinbase_script_data,
+        )
+    }
+
+    /// Builds a full extranonce from its parts, checking each of them has the expected size.
+    pub fn compose(
+        &self,
+        pool_part: &[u8],
+        channel_part: &[u8],
+        rolled_part: &[u8],
+    ) -> Result<Vec<u8>, ExtranonceLayoutError> {
+        check_part_len(self.pool_prefix_size, pool_part)?;
+        check_part_len(self.channel_prefix_size, channel_part)?;
+        check_part_len(self.rollable_size, rolled_part)?;
+
+        let mut extranonce = Vec::with_capacity(self.get_total_size());
+        extranonce.extend_from_slice(pool_part);
+        extranonce.extend_from_slice(channel_part);
+        extranonce.extend_from_slice(rolled_part);
+        Ok(extranonce)
+    }
+
+    /// Splits a full extranonce into its pool, channel and rolled parts.
+    pub fn split<'a>(
+        &self,
+        extranonce: &'a [u8],
+    ) -> Result<ExtranonceParts<'a>, ExtranonceLayoutError> {
+        check_part_len(self.get_total_size(), extranonce)?;
+
+        Ok((
+            &extranonce[self.pool_prefix_range()],
+            &extranonce[self.channel_prefix_range()],
+            &extranonce[self.rollable_range()],
+        ))
+    }
+
+    /// Decodes the extranonce of a share submitted on a channel with `extranonce_prefix` into the
+    /// full extranonce.
+    ///
+    /// Sv2 shares carry the rolled part only, which the prefix of the channel is prepended to. A
+    /// share carrying the full extranonce instead is only accepted if it begins with
+    /// `extranonce_prefix`, compared in constant time, so that a miner can't mine the search
+    /// space of another channel. An extranonce of any other size is invalid.
+    pub fn decode_share_extranonce(
+        &self,
+        extranonce_prefix: &[u8],
+        share_extranonce: &[u8],
+    ) -> Result<Vec<u8>, ExtranonceLayoutError> {
+        check_part_len(self.get_prefix_size(), extranonce_prefix)?;
+        if !extranonce_prefix.is_empty() && share_extranonce.len() == self.get_total_size() {
+            if !constant_time_eq(
+                &share_extranonce[..extranonce_prefix.len()],
+                extranonce_prefix,
+            ) {
+                return Err(ExtranonceLayoutError::PrefixMismatch);
+            }
+            return Ok(share_extranonce.to_vec());
+        }
+        check_part_len(self.rollable_size, share_extranonce)?;
+
+        let mut extranonce = Vec::with_capacity(self.get_total_size());
+        extranonce.extend_from_slice(extranonce_prefix);
+        extranonce.extend_from_slice(share_extranonce);
+        Ok(extranonce)
+    }
+}
+
+// Compares slices of the same length without returning early on the first difference.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let difference = a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b));
+    core::hint::black_box(difference) == 0 && a.len() == b.len()
+}
+
+fn check_part_len(expected: usize, part: &[u8]) -> Result<(), ExtranonceLayoutError> {
+    if part.len() != expected {
+        return Err(ExtranonceLayoutError::InvalidPartLength {
+            expected,
+            actual: part.len(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_boundaries() {
+        // the whole extranonce can be used by any single segment
+        assert!(ExtranonceLayout::new(MAX_EXTRANONCE_LEN, 0, 0).is_ok());
+        assert!(ExtranonceLayout::new(0, MAX_EXTRANONCE_LEN, 0).is_ok());
+        assert!(ExtranonceLayout::new(0, 0, MAX_EXTRANONCE_LEN).is_ok());
+        assert!(ExtranonceLayout::new(0, 0, 0).is_ok());
+
+        let layout = ExtranonceLayout::new(4, 8, 20).unwrap();
+        assert_eq!(layout.get_prefix_size(), 12);
+        assert_eq!(layout.get_total_size(), MAX_EXTRANONCE_LEN);
+        assert_eq!(layout.pool_prefix_range(), 0..4);
+        assert_eq!(layout.channel_prefix_range(), 4..12);
+        assert_eq!(layout.rollable_range(), 12..32);
+
+        let layout = ExtranonceLayout::from_prefix_len(MAX_EXTRANONCE_LEN).unwrap();
+        assert_eq!(layout.get_rollable_size(), 0);
+        let layout = ExtranonceLayout::from_prefix_len(0).unwrap();
+        assert_eq!(layout.get_rollable_size(), MAX_EXTRANONCE_LEN);
+    }
+
+    #[test]
+    fn test_channel_layouts() {
+        // a standard channel can take the whole extranonce
+        let layout = ExtranonceLayout::for_channel(MAX_EXTRANONCE_LEN, 0).unwrap();
+        assert_eq!(layout.get_rollable_size(), 0);
+        assert_eq!(
+            ExtranonceLayout::for_channel(MAX_EXTRANONCE_LEN + 1, 0),
+            Err(ExtranonceLayoutError::ExceedsMaxLength)
+        );
+
+        // an extended channel gets at least the rollable space it asked for
+        let layout = ExtranonceLayout::for_channel(MAX_EXTRANONCE_LEN - 8, 8).unwrap();
+        assert_eq!(layout.get_rollable_size(), 8);
+        let layout = ExtranonceLayout::for_channel(MAX_EXTRANONCE_LEN - 9, 8).unwrap();
+        assert_eq!(layout.get_rollable_size(), 9);
+        assert_eq!(
+            ExtranonceLayout::for_channel(MAX_EXTRANONCE_LEN - 7, 8),
+            Err(ExtranonceLayoutError::InsufficientRollableSpace {
+                prefix_size: MAX_EXTRANONCE_LEN - 7,
+                min_rollable_size: 8
+            })
+        );
+        assert_eq!(
+            ExtranonceLayout::for_channel(0, MAX_EXTRANONCE_LEN + 1),
+            Err(ExtranonceLayoutError::InsufficientRollableSpace {
+                prefix_size: 0,
+                min_rollable_size: MAX_EXTRANONCE_LEN + 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_layouts() {
+        assert_eq!(
+            ExtranonceLayout::new(4, 8, 21),
+            Err(ExtranonceLayoutError::ExceedsMaxLength)
+        );
+        assert_eq!(
+            ExtranonceLayout::new(usize::MAX, 1, 0),
+            Err(ExtranonceLayoutError::ExceedsMaxLength)
+        );
+        assert_eq!(
+            ExtranonceLayout::from_prefix_len(MAX_EXTRANONCE_LEN + 1),
+            Err(ExtranonceLayoutError::ExceedsMaxLength)
+        );
+    }
+
+    #[test]
+    fn test_compose_and_split() {
+        let layout = ExtranonceLayout::new(2, 3, 4).unwrap();
+        let extranonce = layout.compose(&[1, 2], &[3, 4, 5], &[6, 7, 8, 9]).unwrap();
+        assert_eq!(extranonce, vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        let (pool_part, channel_part, rolled_part) = layout.split(&extranonce).unwrap();
+        assert_eq!(pool_part, &[1, 2]);
+        assert_eq!(channel_part, &[3, 4, 5]);
+        assert_eq!(rolled_part, &[6, 7, 8, 9]);
+
+        assert_eq!(
+            layout.compose(&[1, 2], &[3, 4, 5], &[6, 7, 8]),
+            Err(ExtranonceLayoutError::InvalidPartLength {
+                expected: 4,
+                actual: 3
+            })
+        );
+        assert_eq!(
+            layout.split(&extranonce[1..]),
+            Err(ExtranonceLayoutError::InvalidPartLength {
+                expected: 9,
+                actual: 8
+            })
+        );
+    }
+
+    #[test]
+    fn test_new_extended_extranonce() {
+        let layout = ExtranonceLayout::new(2, 3, 4).unwrap();
+        let mut extended_extranonce = layout.new_extended_extranonce(None).unwrap();
+        assert_eq!(extended_extranonce.get_len(), layout.get_total_size());
+        assert_eq!(
+            extended_extranonce.get_prefix_len(),
+            layout.get_prefix_size()
+        );
+        assert_eq!(
+            extended_extranonce.get_range2_len(),
+            layout.get_rollable_size()
+        );
+
+        let prefix = extended_extranonce
+            .next_prefix_extended(layout.get_rollable_size())
+            .unwrap()
+            .to_vec();
+        assert_eq!(prefix.len(), layout.get_prefix_size());
+    }
+
+    #[test]
+    fn test_decode_share_extranonce() {
+        let layout = ExtranonceLayout::from_prefix_len(24).unwrap();
+        let extranonce_prefix = [0xab; 24];
+        let mut full_extranonce = extranonce_prefix.to_vec();
+        full_extranonce.extend_from_slice(&[0xcd; 8]);
+
+        // the rolled part alone, or the full extranonce with the right prefix
+        assert_eq!(
+            layout.decode_share_extranonce(&extranonce_prefix, &[0xcd; 8]),
+            Ok(full_extranonce.clone())
+        );
+        assert_eq!(
+            layout.decode_share_extranonce(&extranonce_prefix, &full_extranonce),
+            Ok(full_extranonce.clone())
+        );
+
+        // one byte off in the prefix
+        let mut poaching_extranonce = full_extranonce.clone();
+        poaching_extranonce[23] ^= 1;
+        assert_eq!(
+            layout.decode_share_extranonce(&extranonce_prefix, &poaching_extranonce),
+            Err(ExtranonceLayoutError::PrefixMismatch)
+        );
+
+        // a truncated full extranonce is neither of the two sizes
+        assert_eq!(
+            layout.decode_share_extranonce(&extranonce_prefix, &full_extranonce[..31]),
+            Err(ExtranonceLayoutError::InvalidPartLength {
+                expected: 8,
+                actual: 31
+            })
+        );
+    }
+}
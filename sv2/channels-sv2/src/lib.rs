@@ -1,6 +1,27 @@
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+compile_error!("channels_sv2 needs either the `std` or the `alloc` feature");
+
+#[macro_use]
+extern crate alloc;
+
+#[cfg(feature = "bitcoind-compat")]
+pub mod bitcoind;
 pub mod chain_tip;
 pub mod client;
+pub mod clock;
+pub mod coinbase_output;
+mod collections;
+pub mod connection;
+pub mod extranonce;
 mod merkle_root;
+pub mod prelude;
+mod redact;
 pub mod server;
-mod target;
+pub mod target;
 pub mod template;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod testing;
+mod trace;
+pub mod user_identity;
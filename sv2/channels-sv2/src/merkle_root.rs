@@ -1,9 +1,10 @@
+use crate::trace::error;
+use alloc::vec::Vec;
 use bitcoin::{
     consensus,
     hashes::{sha256d::Hash as DHash, Hash},
     Transaction,
 };
-use tracing::error;
 
 /// Computes the Merkle root from coinbase transaction components and a path of transaction hashes.
 ///
@@ -33,7 +34,6 @@ pub fn merkle_root_from_path<T: AsRef<[u8]>>(
         Ok(trans) => trans,
         Err(e) => {
             error!("ERROR: {}", e);
-            dbg!(e);
             return None;
         }
     };
@@ -0,0 +1,21 @@
+//! Re-exports of the types needed to run a [`StandardChannel`] on a Mining Server, so that
+//! `use channels_sv2::prelude::*;` is enough to create a channel, feed it templates and validate
+//! shares.
+//!
+//! The `SetNewPrevHash` messages of the Mining and Template Distribution protocols share a name,
+//! so they are re-exported as [`SetNewPrevHashMp`] and [`SetNewPrevHashTdp`].
+pub use crate::{
+    chain_tip::ChainTip,
+    server::{
+        channel_factory::{OpenChannelPolicy, ServerChannelFactory},
+        error::{OpenChannelError, StandardChannelError},
+        jobs::{job_store::DefaultJobStore, standard::StandardJob},
+        share_validation::{ShareValidationError, ShareValidationResult},
+        standard::{StandardChannel, StandardChannelConfig},
+    },
+};
+pub use bitcoin::transaction::TxOut;
+pub use mining_sv2::{
+    NewMiningJob, SetNewPrevHash as SetNewPrevHashMp, SubmitSharesStandard, Target,
+};
+pub use template_distribution_sv2::{NewTemplate, SetNewPrevHash as SetNewPrevHashTdp};
@@ -0,0 +1,73 @@
+//! Redaction of sensitive channel data from `Debug` output.
+//!
+//! User identities are PII and extranonce prefixes reveal how the server allocates them, so the
+//! `Debug` implementations of the types holding them only print a redacted version by default.
+//! The `debug-full` feature prints them in full, for development.
+use alloc::string::String;
+use bitcoin::hashes::{sha256, Hash};
+use core::fmt;
+
+// Number of characters of a user identity kept in the redacted output.
+const IDENTITY_PREFIX_LEN: usize = 3;
+// Number of bytes of an extranonce prefix kept in the redacted output.
+const EXTRANONCE_PREFIX_LEN: usize = 2;
+
+/// A user identity, printed as its first characters followed by a short hash of the whole
+/// identity, e.g. `"ali…#2bd806c9"`.
+pub(crate) struct RedactedIdentity<'a>(pub &'a str);
+
+impl fmt::Debug for RedactedIdentity<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if cfg!(feature = "debug-full") {
+            return fmt::Debug::fmt(self.0, f);
+        }
+        let prefix: String = self.0.chars().take(IDENTITY_PREFIX_LEN).collect();
+        let hash = sha256::Hash::hash(self.0.as_bytes()).to_byte_array();
+        write!(f, "\"{}…#", prefix)?;
+        for b in &hash[..4] {
+            write!(f, "{:02x}", b)?;
+        }
+        write!(f, "\"")
+    }
+}
+
+/// An extranonce prefix, printed as its first bytes followed by its length, e.g.
+/// `[53, 74, ..; 32]`.
+pub(crate) struct RedactedExtranoncePrefix<'a>(pub &'a [u8]);
+
+impl fmt::Debug for RedactedExtranoncePrefix<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if cfg!(feature = "debug-full") || self.0.len() <= EXTRANONCE_PREFIX_LEN {
+            return fmt::Debug::fmt(self.0, f);
+        }
+        write!(f, "[")?;
+        for b in &self.0[..EXTRANONCE_PREFIX_LEN] {
+            write!(f, "{}, ", b)?;
+        }
+        write!(f, "..; {}]", self.0.len())
+    }
+}
+
+#[cfg(all(test, not(feature = "debug-full")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacted_identity() {
+        let redacted = format!("{:?}", RedactedIdentity("alice.worker1"));
+        assert!(redacted.starts_with("\"ali…#"));
+        assert!(!redacted.contains("alice"));
+        // the hash tells identities apart
+        assert_ne!(redacted, format!("{:?}", RedactedIdentity("alice.worker2")));
+        assert_eq!(redacted, format!("{:?}", RedactedIdentity("alice.worker1")));
+    }
+
+    #[test]
+    fn test_redacted_extranonce_prefix() {
+        assert_eq!(
+            format!("{:?}", RedactedExtranoncePrefix(&[1, 2, 3, 4])),
+            "[1, 2, ..; 4]"
+        );
+        assert_eq!(format!("{:?}", RedactedExtranoncePrefix(&[1, 2])), "[1, 2]");
+    }
+}
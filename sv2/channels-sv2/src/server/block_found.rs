@@ -0,0 +1,87 @@
+//! Structured record of a share that found a block, for immediate forwarding to alerting systems.
+//!
+//! A [`BlockFoundEvent`] is returned along with every
+//! [`ShareValidationResult::BlockFound`](crate::server::share_validation::ShareValidationResult::BlockFound),
+//! and passed to [`SharePolicy::on_block_found`](crate::server::share_policy::SharePolicy::on_block_found)
+//! on Standard Channels.
+use crate::redact::RedactedIdentity;
+use alloc::string::{String, ToString};
+use bitcoin::{block::Header, hashes::Hash, BlockHash};
+use core::fmt;
+use mining_sv2::Target;
+
+/// A share that found a block, along with the channel it was submitted on.
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockFoundEvent {
+    pub channel_id: u32,
+    pub user_identity: String,
+    pub job_id: u32,
+    pub sequence_number: u32,
+    // `None` if the share is for a custom job
+    pub template_id: Option<u64>,
+    // the share hash, in internal byte order, see `BlockFoundEvent::block_hash`
+    pub block_hash: [u8; 32],
+    /// The difficulty of the network target at the time, as in [`Target::difficulty`].
+    pub network_difficulty: f64,
+    /// When the share was validated, as a Unix timestamp in seconds, as told by the clock of the
+    /// channel.
+    ///
+    /// `None` if the channel has no clock, i.e. without the `std` feature unless one was set.
+    pub timestamp: Option<u64>,
+    pub nonce: u32,
+    pub ntime: u32,
+    pub version: u32,
+}
+
+impl BlockFoundEvent {
+    // `header` is the header of the block, as rebuilt out of the share, and `block_hash` its hash
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        channel_id: u32,
+        user_identity: &str,
+        job_id: u32,
+        sequence_number: u32,
+        template_id: Option<u64>,
+        header: &Header,
+        block_hash: BlockHash,
+        timestamp: Option<u64>,
+    ) -> Self {
+        Self {
+            channel_id,
+            user_identity: user_identity.to_string(),
+            job_id,
+            sequence_number,
+            template_id,
+            block_hash: block_hash.to_byte_array(),
+            network_difficulty: Target::from_le_bytes(header.target().to_le_bytes()).difficulty(),
+            timestamp,
+            nonce: header.nonce,
+            ntime: header.time,
+            version: header.version.to_consensus() as u32,
+        }
+    }
+
+    /// The hash of the block, i.e. of the share.
+    pub fn block_hash(&self) -> BlockHash {
+        BlockHash::from_byte_array(self.block_hash)
+    }
+}
+
+impl fmt::Debug for BlockFoundEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockFoundEvent")
+            .field("channel_id", &self.channel_id)
+            .field("user_identity", &RedactedIdentity(&self.user_identity))
+            .field("job_id", &self.job_id)
+            .field("sequence_number", &self.sequence_number)
+            .field("template_id", &self.template_id)
+            .field("block_hash", &self.block_hash())
+            .field("network_difficulty", &self.network_difficulty)
+            .field("timestamp", &self.timestamp)
+            .field("nonce", &self.nonce)
+            .field("ntime", &self.ntime)
+            .field("version", &self.version)
+            .finish()
+    }
+}
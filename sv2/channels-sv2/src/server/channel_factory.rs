@@ -0,0 +1,368 @@
+//! Opening Standard Channels on a Mining Server.
+//!
+//! [`ServerChannelFactory::open_standard_channel`] covers the whole `OpenStandardMiningChannel`
+//! round trip: it assigns a channel id and an extranonce prefix, checks the request against an
+//! [`OpenChannelPolicy`], and returns both the [`StandardChannel`] and the
+//! `OpenStandardMiningChannel.Success` to be sent downstream, or the
+//! `OpenMiningChannel.Error` to be sent instead.
+//!
+//! Channel ids and extranonce prefixes are only consumed by successfully opened channels.
+use crate::server::{
+    error::OpenChannelError,
+    jobs::{job_store::DefaultJobStore, standard::StandardJob},
+    share_accounting::ShareAccountingConfig,
+    standard::{MaxTargetPolicy, StandardChannel, StandardChannelConfig},
+};
+use crate::{
+    extranonce::ExtranonceLayoutError,
+    user_identity::{UserIdentityError, UserIdentityRules},
+};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::convert::TryInto;
+use mining_sv2::{
+    ExtendedExtranonce, OpenMiningChannelError, OpenStandardMiningChannel,
+    OpenStandardMiningChannelSuccess, Target, MAX_EXTRANONCE_LEN,
+};
+
+/// How the requests of downstreams are checked, and the channels opened for them configured.
+///
+/// Built via [`OpenChannelPolicy::new`], the user identities are checked against the default
+/// [`UserIdentityRules`], targets above the requested max target are rejected
+/// ([`MaxTargetPolicy::Reject`]), and shares are accounted with
+/// [`ShareAccountingConfig::default`].
+#[derive(Debug, Clone)]
+pub struct OpenChannelPolicy {
+    expected_share_per_minute: f32,
+    user_identity_rules: UserIdentityRules,
+    max_target_policy: MaxTargetPolicy,
+    share_accounting_config: ShareAccountingConfig,
+}
+
+impl OpenChannelPolicy {
+    pub fn new(expected_share_per_minute: f32) -> Self {
+        Self {
+            expected_share_per_minute,
+            user_identity_rules: UserIdentityRules::default(),
+            max_target_policy: MaxTargetPolicy::default(),
+            share_accounting_config: ShareAccountingConfig::default(),
+        }
+    }
+
+    pub fn with_user_identity_rules(mut self, user_identity_rules: UserIdentityRules) -> Self {
+        self.user_identity_rules = user_identity_rules;
+        self
+    }
+
+    pub fn with_max_target_policy(mut self, max_target_policy: MaxTargetPolicy) -> Self {
+        self.max_target_policy = max_target_policy;
+        self
+    }
+
+    pub fn with_share_accounting_config(
+        mut self,
+        share_accounting_config: ShareAccountingConfig,
+    ) -> Self {
+        self.share_accounting_config = share_accounting_config;
+        self
+    }
+
+    pub fn get_expected_share_per_minute(&self) -> f32 {
+        self.expected_share_per_minute
+    }
+
+    pub fn get_max_target_policy(&self) -> MaxTargetPolicy {
+        self.max_target_policy
+    }
+}
+
+/// Assigns the channel ids and extranonce prefixes of the Standard Channels of a Mining Server.
+#[derive(Debug, Clone)]
+pub struct ServerChannelFactory {
+    next_channel_id: u32,
+    extranonce: ExtendedExtranonce,
+    group_channel_id: u32,
+}
+
+impl ServerChannelFactory {
+    /// Extranonce prefixes are drawn from `extranonce`, via
+    /// [`ExtendedExtranonce::next_prefix_standard`]. As Standard Channels don't roll any part of
+    /// the extranonce, its layout must span [`MAX_EXTRANONCE_LEN`] bytes.
+    ///
+    /// Channel ids start from 1, and channels are reported as part of group channel 0, see
+    /// [`ServerChannelFactory::with_group_channel_id`].
+    pub fn new(extranonce: ExtendedExtranonce) -> Result<Self, ExtranonceLayoutError> {
+        if extranonce.get_len() != MAX_EXTRANONCE_LEN {
+            return Err(ExtranonceLayoutError::InvalidPartLength {
+                expected: MAX_EXTRANONCE_LEN,
+                actual: extranonce.get_len(),
+            });
+        }
+        Ok(Self {
+            next_channel_id: 1,
+            extranonce,
+            group_channel_id: 0,
+        })
+    }
+
+    /// Sets the `group_channel_id` of the `OpenStandardMiningChannel.Success` messages, which
+    /// can be taken from [`ServerChannelFactory::allocate_channel_id`] so it doesn't clash with
+    /// the ids of the channels.
+    pub fn with_group_channel_id(mut self, group_channel_id: u32) -> Self {
+        self.group_channel_id = group_channel_id;
+        self
+    }
+
+    /// Takes the next channel id, e.g. for a group or an extended channel.
+    pub fn allocate_channel_id(&mut self) -> Option<u32> {
+        let channel_id = self.next_channel_id;
+        self.next_channel_id = channel_id.checked_add(1)?;
+        Some(channel_id)
+    }
+
+    /// Opens the Standard Channel requested by `request`.
+    ///
+    /// On failure, the `OpenMiningChannel.Error` to be sent downstream is returned along with the
+    /// reason.
+    pub fn open_standard_channel(
+        &mut self,
+        request: OpenStandardMiningChannel,
+        policy: &OpenChannelPolicy,
+    ) -> Result<
+        (
+            StandardChannel<'static>,
+            OpenStandardMiningChannelSuccess<'static>,
+        ),
+        (OpenMiningChannelError<'static>, OpenChannelError),
+    > {
+        let request_id = request.get_request_id_as_u32();
+        self.try_open_standard_channel(request, policy)
+            .map_err(|reason| {
+                let error = OpenMiningChannelError {
+                    request_id,
+                    error_code: String::from(reason.error_code())
+                        .try_into()
+                        .expect("error codes are shorter than 255 bytes"),
+                };
+                (error, reason)
+            })
+    }
+
+    fn try_open_standard_channel(
+        &mut self,
+        request: OpenStandardMiningChannel,
+        policy: &OpenChannelPolicy,
+    ) -> Result<
+        (
+            StandardChannel<'static>,
+            OpenStandardMiningChannelSuccess<'static>,
+        ),
+        OpenChannelError,
+    > {
+        let user_identity = String::from_utf8(request.user_identity.inner_as_ref().to_vec())
+            .map_err(|_| OpenChannelError::InvalidUserIdentity(UserIdentityError::InvalidUtf8))?;
+
+        // both are only committed once the channel is created
+        let channel_id = self.next_channel_id;
+        let next_channel_id = channel_id
+            .checked_add(1)
+            .ok_or(OpenChannelError::TooManyChannels)?;
+        let mut extranonce = self.extranonce.clone();
+        let extranonce_prefix: Vec<u8> = extranonce
+            .next_prefix_standard()
+            .map_err(|_| OpenChannelError::TooManyChannels)?
+            .into();
+
+        let config = StandardChannelConfig::default()
+            .channel_id(channel_id)
+            .user_identity(user_identity)
+            .user_identity_rules(policy.user_identity_rules)
+            .extranonce_prefix(extranonce_prefix.clone())
+            .requested_max_target(Target::from(request.max_target.clone()))
+            .nominal_hashrate(request.nominal_hash_rate)
+            .expected_share_per_minute(policy.expected_share_per_minute)
+            .share_accounting_config(policy.share_accounting_config.clone())
+            .max_target_policy(policy.max_target_policy);
+        let channel =
+            StandardChannel::from_config(config, Box::new(DefaultJobStore::<StandardJob>::new()))?;
+
+        self.next_channel_id = next_channel_id;
+        self.extranonce = extranonce;
+
+        let success = OpenStandardMiningChannelSuccess {
+            request_id: request.get_request_id_as_u32().into(),
+            channel_id,
+            target: channel.get_target().clone().into(),
+            extranonce_prefix: extranonce_prefix
+                .try_into()
+                .expect("extranonce prefixes are at most 32 bytes"),
+            group_channel_id: self.group_channel_id,
+        };
+        Ok((channel, success))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::error::StandardChannelError;
+
+    fn factory() -> ServerChannelFactory {
+        ServerChannelFactory::new(ExtendedExtranonce::new(0..0, 0..0, 0..32, None).unwrap())
+            .unwrap()
+    }
+
+    fn request(user_identity: &str, max_target: [u8; 32]) -> OpenStandardMiningChannel<'static> {
+        OpenStandardMiningChannel {
+            request_id: 7.into(),
+            user_identity: user_identity.to_string().try_into().unwrap(),
+            nominal_hash_rate: 1.0,
+            max_target: max_target.into(),
+        }
+    }
+
+    fn small_target() -> [u8; 32] {
+        let mut target = [0; 32];
+        target[0] = 0xff;
+        target
+    }
+
+    #[test]
+    fn test_open_standard_channel() {
+        let mut factory = factory().with_group_channel_id(100);
+        let policy = OpenChannelPolicy::new(1.0);
+
+        let (channel, success) = factory
+            .open_standard_channel(request("alice.worker1", [0xff; 32]), &policy)
+            .unwrap();
+        assert_eq!(success.get_request_id_as_u32(), 7);
+        assert_eq!(success.channel_id, 1);
+        assert_eq!(success.group_channel_id, 100);
+        assert_eq!(channel.get_channel_id(), 1);
+        assert_eq!(channel.get_user_identity(), "alice.worker1");
+        assert_eq!(Target::from(success.target.clone()), channel.get_target());
+        assert_eq!(
+            success.extranonce_prefix.inner_as_ref(),
+            channel.get_extranonce_prefix().as_slice()
+        );
+        assert_eq!(success.extranonce_prefix.inner_as_ref().len(), 32);
+
+        // every channel gets its own id and extranonce prefix
+        let (channel, second_success) = factory
+            .open_standard_channel(request("alice.worker2", [0xff; 32]), &policy)
+            .unwrap();
+        assert_eq!(channel.get_channel_id(), 2);
+        assert_ne!(second_success.extranonce_prefix, success.extranonce_prefix);
+        assert_eq!(factory.allocate_channel_id(), Some(3));
+    }
+
+    #[test]
+    fn test_unknown_user() {
+        let mut factory = factory();
+        let policy = OpenChannelPolicy::new(1.0);
+
+        let (error, reason) = factory
+            .open_standard_channel(request("", [0xff; 32]), &policy)
+            .unwrap_err();
+        assert_eq!(error.request_id, 7);
+        assert_eq!(error.error_code.as_utf8_or_hex(), "unknown-user");
+        assert!(matches!(
+            reason,
+            OpenChannelError::InvalidUserIdentity(UserIdentityError::Empty)
+        ));
+
+        let mut invalid_utf8 = request("alice", [0xff; 32]);
+        invalid_utf8.user_identity = vec![0xff, 0xfe].try_into().unwrap();
+        let (error, reason) = factory
+            .open_standard_channel(invalid_utf8, &policy)
+            .unwrap_err();
+        assert_eq!(error.error_code.as_utf8_or_hex(), "unknown-user");
+        assert!(matches!(
+            reason,
+            OpenChannelError::InvalidUserIdentity(UserIdentityError::InvalidUtf8)
+        ));
+
+        // the rules of the policy apply
+        let policy = policy.with_user_identity_rules(UserIdentityRules::default().with_max_len(4));
+        let (error, _) = factory
+            .open_standard_channel(request("alice", [0xff; 32]), &policy)
+            .unwrap_err();
+        assert_eq!(error.error_code.as_utf8_or_hex(), "unknown-user");
+
+        // failed requests don't consume channel ids
+        let (channel, _) = factory
+            .open_standard_channel(request("bob", [0xff; 32]), &policy)
+            .unwrap();
+        assert_eq!(channel.get_channel_id(), 1);
+    }
+
+    #[test]
+    fn test_max_target_out_of_range() {
+        let mut factory = factory();
+
+        let policy = OpenChannelPolicy::new(1.0);
+        let (error, reason) = factory
+            .open_standard_channel(request("alice", small_target()), &policy)
+            .unwrap_err();
+        assert_eq!(error.error_code.as_utf8_or_hex(), "max-target-out-of-range");
+        assert!(matches!(
+            reason,
+            OpenChannelError::RequestedMaxTargetOutOfRange
+        ));
+
+        let mut invalid_hashrate = request("alice", [0xff; 32]);
+        invalid_hashrate.nominal_hash_rate = f32::NAN;
+        let (error, reason) = factory
+            .open_standard_channel(invalid_hashrate, &policy)
+            .unwrap_err();
+        assert_eq!(error.error_code.as_utf8_or_hex(), "max-target-out-of-range");
+        assert!(matches!(reason, OpenChannelError::InvalidNominalHashrate));
+
+        // clamped to the requested max target instead
+        let policy = policy.with_max_target_policy(MaxTargetPolicy::ClampToRequestedMax);
+        let (channel, success) = factory
+            .open_standard_channel(request("alice", small_target()), &policy)
+            .unwrap();
+        assert_eq!(channel.get_target(), Target::from(small_target()));
+        assert_eq!(Target::from(success.target), Target::from(small_target()));
+    }
+
+    #[test]
+    fn test_server_side_failures() {
+        let policy = OpenChannelPolicy::new(0.0);
+        let (error, reason) = factory()
+            .open_standard_channel(request("alice", [0xff; 32]), &policy)
+            .unwrap_err();
+        assert_eq!(error.error_code.as_utf8_or_hex(), "internal-error");
+        assert!(matches!(
+            reason,
+            OpenChannelError::InvalidPolicy(StandardChannelError::InvalidExpectedSharePerMinute)
+        ));
+
+        // a single byte of extranonce prefixes to hand out
+        let mut factory = ServerChannelFactory::new(
+            ExtendedExtranonce::new(0..31, 31..31, 31..32, None).unwrap(),
+        )
+        .unwrap();
+        let policy = OpenChannelPolicy::new(1.0);
+        for _ in 0..255 {
+            factory
+                .open_standard_channel(request("alice", [0xff; 32]), &policy)
+                .unwrap();
+        }
+        let (error, reason) = factory
+            .open_standard_channel(request("alice", [0xff; 32]), &policy)
+            .unwrap_err();
+        assert_eq!(error.error_code.as_utf8_or_hex(), "too-many-channels");
+        assert!(matches!(reason, OpenChannelError::TooManyChannels));
+
+        // standard channels need the whole extranonce as prefix
+        assert_eq!(
+            ServerChannelFactory::new(ExtendedExtranonce::new(0..0, 0..0, 0..16, None).unwrap())
+                .unwrap_err(),
+            ExtranonceLayoutError::InvalidPartLength {
+                expected: 32,
+                actual: 16
+            }
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Reads of the frequently polled info of a channel from other threads.
+//!
+//! The target, the nominal hashrate and the share accounting counters of a channel live in cells
+//! shared with every [`ChannelInfoHandle`] cloned out of it (see
+//! [`StandardChannel::get_info_handle`](crate::server::standard::StandardChannel::get_info_handle)):
+//! the task owning the channel keeps writing them as they change, while e.g. a status endpoint
+//! reads them without locking the channel, and without ever blocking the owning task.
+//!
+//! Every value is read consistently on its own (a target is never half updated), but values read
+//! one after the other may come from different updates of the channel.
+//!
+//! The cells only take 32 bits atomics, which all targets with atomics have (unlike 64 bits
+//! ones), so 64 bits values are split into two words under a seqlock.
+use crate::server::share_accounting::ShareAccounting;
+use alloc::sync::Arc;
+use core::{
+    hint,
+    sync::atomic::{fence, AtomicU32, Ordering},
+};
+use mining_sv2::Target;
+
+// offsets of the values in `ChannelInfoCells::words`
+const TARGET: usize = 0;
+const NOMINAL_HASHRATE: usize = 8;
+const LAST_SHARE_SEQUENCE_NUMBER: usize = 10;
+const SHARES_ACCEPTED: usize = 11;
+const SHARE_WORK_SUM: usize = 12;
+const BEST_DIFF: usize = 14;
+const WORDS: usize = 16;
+
+// Only written by the task owning the channel.
+#[derive(Debug)]
+struct ChannelInfoCells {
+    // seqlock over `words`: odd while they are being written
+    sequence: AtomicU32,
+    // little endian words of the values, 64 bits values and the target spanning several
+    words: [AtomicU32; WORDS],
+}
+
+impl ChannelInfoCells {
+    // Called by the owning task only, as the seqlock allows a single writer.
+    fn write(&self, offset: usize, values: &[u32]) {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence
+            .store(sequence.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        for (word, value) in self.words[offset..].iter().zip(values) {
+            word.store(*value, Ordering::Relaxed);
+        }
+        self.sequence
+            .store(sequence.wrapping_add(2), Ordering::Release);
+    }
+
+    // Retries while the owning task is writing the words.
+    fn read<const N: usize>(&self, offset: usize) -> [u32; N] {
+        loop {
+            let sequence = self.sequence.load(Ordering::Acquire);
+            if sequence % 2 == 1 {
+                hint::spin_loop();
+                continue;
+            }
+            let mut values = [0; N];
+            for (value, word) in values.iter_mut().zip(&self.words[offset..]) {
+                *value = word.load(Ordering::Relaxed);
+            }
+            fence(Ordering::Acquire);
+            if self.sequence.load(Ordering::Relaxed) == sequence {
+                return values;
+            }
+        }
+    }
+
+    fn read_u64(&self, offset: usize) -> u64 {
+        let [low, high] = self.read::<2>(offset);
+        u64::from(low) | u64::from(high) << 32
+    }
+}
+
+fn split_u64(value: u64) -> [u32; 2] {
+    [value as u32, (value >> 32) as u32]
+}
+
+/// A cheaply cloneable, read only view on the target, the nominal hashrate and the share
+/// accounting counters of a channel, which can be sent to other threads.
+#[derive(Debug, Clone)]
+pub struct ChannelInfoHandle {
+    cells: Arc<ChannelInfoCells>,
+}
+
+impl ChannelInfoHandle {
+    pub(crate) fn new(
+        target: &Target,
+        nominal_hashrate: f64,
+        share_accounting: &ShareAccounting,
+    ) -> Self {
+        let handle = Self {
+            cells: Arc::new(ChannelInfoCells {
+                sequence: AtomicU32::new(0),
+                words: Default::default(),
+            }),
+        };
+        handle.store_target(target);
+        handle.store_nominal_hashrate(nominal_hashrate);
+        handle.store_share_accounting(share_accounting);
+        handle
+    }
+
+    pub(crate) fn store_target(&self, target: &Target) {
+        let mut words = [0; 8];
+        for (word, chunk) in words.iter_mut().zip(target.to_le_bytes().chunks_exact(4)) {
+            *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        self.cells.write(TARGET, &words);
+    }
+
+    pub(crate) fn store_nominal_hashrate(&self, nominal_hashrate: f64) {
+        self.cells
+            .write(NOMINAL_HASHRATE, &split_u64(nominal_hashrate.to_bits()));
+    }
+
+    pub(crate) fn store_share_accounting(&self, share_accounting: &ShareAccounting) {
+        let [work_low, work_high] = split_u64(share_accounting.get_share_work_sum());
+        let [diff_low, diff_high] = split_u64(share_accounting.get_best_diff().to_bits());
+        // the counters are contiguous, so they are always read from the same update
+        self.cells.write(
+            LAST_SHARE_SEQUENCE_NUMBER,
+            &[
+                share_accounting.get_last_share_sequence_number(),
+                share_accounting.get_shares_accepted(),
+                work_low,
+                work_high,
+                diff_low,
+                diff_high,
+            ],
+        );
+    }
+
+    /// Returns the target of the channel.
+    ///
+    /// Retries while the owning task is replacing the target.
+    pub fn get_target(&self) -> Target {
+        let words = self.cells.read::<8>(TARGET);
+        let mut bytes = [0; 32];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(&words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        Target::from_le_bytes(bytes)
+    }
+
+    pub fn get_nominal_hashrate(&self) -> f64 {
+        f64::from_bits(self.cells.read_u64(NOMINAL_HASHRATE))
+    }
+
+    pub fn get_last_share_sequence_number(&self) -> u32 {
+        self.cells.read::<1>(LAST_SHARE_SEQUENCE_NUMBER)[0]
+    }
+
+    pub fn get_shares_accepted(&self) -> u32 {
+        self.cells.read::<1>(SHARES_ACCEPTED)[0]
+    }
+
+    pub fn get_share_work_sum(&self) -> u64 {
+        self.cells.read_u64(SHARE_WORK_SUM)
+    }
+
+    pub fn get_best_diff(&self) -> f64 {
+        f64::from_bits(self.cells.read_u64(BEST_DIFF))
+    }
+}
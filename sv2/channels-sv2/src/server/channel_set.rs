@@ -0,0 +1,428 @@
+//! Bulk operations over the Standard Channels of a Mining Server.
+//!
+//! When a new template or a new chain tip arrives, every channel has to compute its own job
+//! (which involves one merkle root computation per channel). [`ChannelSet`] groups the channels
+//! so that this can be done in one call, either serially or (with the `rayon` feature) spread
+//! across threads via [`ChannelSet::par_on_new_template`] and
+//! [`ChannelSet::par_on_set_new_prev_hash`].
+//!
+//! Each channel owns its own job factory, so the `job_id` assigned to each channel's job is the
+//! same regardless of how channels are scheduled across threads.
+//!
+//! The template itself is converted once into a [`SharedTemplate`], which the jobs of all the
+//! channels point to, so a thousand channels don't hold a thousand copies of its merkle path.
+//!
+//! A channel whose job factory fails on a template doesn't stop the others from getting their
+//! job: its error is reported in the [`GroupTemplateResult`], and its shares are rejected with
+//! [`ShareValidationError::NoActiveJob`](crate::server::share_validation::ShareValidationError)
+//! until it gets a job for the current chain tip.
+//!
+//! A channel whose [`JobRateLimit`](crate::server::jobs::JobRateLimit) suppresses a template keeps
+//! its current job, and is listed apart in the [`GroupTemplateResult`].
+use crate::{
+    collections::BTreeMap,
+    server::{
+        error::StandardChannelError,
+        jobs::{SharedTemplate, TemplateOutcome},
+        standard::StandardChannel,
+    },
+};
+use alloc::{sync::Arc, vec::Vec};
+use bitcoin::transaction::TxOut;
+use mining_sv2::NewMiningJob;
+use template_distribution_sv2::{NewTemplate, SetNewPrevHash as SetNewPrevHashTdp};
+
+use core::iter::FromIterator;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+/// Per-channel outcome of a bulk operation, ordered by `channel_id`.
+pub type ChannelSetResults<T> = Vec<(u32, Result<T, StandardChannelError>)>;
+
+/// Outcome of applying a template to every channel of a [`ChannelSet`].
+///
+/// All lists are ordered by `channel_id`.
+#[derive(Debug, Default)]
+pub struct GroupTemplateResult<'a> {
+    /// The job message created by each channel, to be sent downstream.
+    pub jobs: Vec<(u32, NewMiningJob<'a>)>,
+    /// The channels that couldn't create a job for the template.
+    pub errors: Vec<(u32, StandardChannelError)>,
+    /// The channels whose job rate limit suppressed the template, with nothing to send
+    /// downstream.
+    pub suppressed: Vec<u32>,
+}
+
+impl<'a> GroupTemplateResult<'a> {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+// The job of a channel for a template, `None` if its job rate limit suppressed the template.
+type TemplateJobResult<'a> = Result<Option<NewMiningJob<'a>>, StandardChannelError>;
+
+impl<'a> FromIterator<(u32, TemplateJobResult<'a>)> for GroupTemplateResult<'a> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (u32, TemplateJobResult<'a>)>,
+    {
+        let mut result = Self::default();
+        for (channel_id, outcome) in iter {
+            match outcome {
+                Ok(Some(job)) => result.jobs.push((channel_id, job)),
+                Ok(None) => result.suppressed.push(channel_id),
+                Err(e) => result.errors.push((channel_id, e)),
+            }
+        }
+        result
+    }
+}
+
+/// A set of Standard Channels, indexed by `channel_id`.
+#[derive(Debug, Default)]
+pub struct ChannelSet<'a> {
+    channels: BTreeMap<u32, StandardChannel<'a>>,
+}
+
+impl<'a> ChannelSet<'a> {
+    pub fn new() -> Self {
+        Self {
+            channels: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a channel to the set.
+    ///
+    /// If a channel with the same `channel_id` was already in the set, it's replaced and returned.
+    pub fn insert(&mut self, channel: StandardChannel<'a>) -> Option<StandardChannel<'a>> {
+        self.channels.insert(channel.get_channel_id(), channel)
+    }
+
+    pub fn remove(&mut self, channel_id: u32) -> Option<StandardChannel<'a>> {
+        self.channels.remove(&channel_id)
+    }
+
+    pub fn get(&self, channel_id: u32) -> Option<&StandardChannel<'a>> {
+        self.channels.get(&channel_id)
+    }
+
+    pub fn get_mut(&mut self, channel_id: u32) -> Option<&mut StandardChannel<'a>> {
+        self.channels.get_mut(&channel_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &StandardChannel<'a>> {
+        self.channels.values()
+    }
+
+    /// Updates every channel with a new template, one channel after the other.
+    ///
+    /// Returns the job message created by each channel (to be sent downstream), and the error of
+    /// each channel that couldn't create one. An error on a channel doesn't affect the others.
+    pub fn on_new_template(
+        &mut self,
+        template: NewTemplate<'a>,
+        coinbase_reward_outputs: Vec<TxOut>,
+    ) -> GroupTemplateResult<'a> {
+        let template = Arc::new(template.into_static());
+        self.channels
+            .iter_mut()
+            .map(|(channel_id, channel)| {
+                let result = apply_new_template(channel, &template, &coinbase_reward_outputs);
+                (*channel_id, result)
+            })
+            .collect()
+    }
+
+    /// Updates every channel with a new `SetNewPrevHash` message, one channel after the other.
+    pub fn on_set_new_prev_hash(
+        &mut self,
+        set_new_prev_hash: SetNewPrevHashTdp<'a>,
+    ) -> ChannelSetResults<()> {
+        self.channels
+            .iter_mut()
+            .map(|(channel_id, channel)| {
+                (
+                    *channel_id,
+                    channel.on_set_new_prev_hash(set_new_prev_hash.clone()),
+                )
+            })
+            .collect()
+    }
+
+    /// Same as [`ChannelSet::on_new_template`], with channels split across the rayon thread pool.
+    #[cfg(feature = "rayon")]
+    pub fn par_on_new_template(
+        &mut self,
+        template: NewTemplate<'a>,
+        coinbase_reward_outputs: Vec<TxOut>,
+    ) -> GroupTemplateResult<'a> {
+        let template = Arc::new(template.into_static());
+        let results: Vec<(u32, TemplateJobResult<'a>)> = self
+            .channels
+            .par_iter_mut()
+            .map(|(channel_id, channel)| {
+                let result = apply_new_template(channel, &template, &coinbase_reward_outputs);
+                (*channel_id, result)
+            })
+            .collect();
+        results.into_iter().collect()
+    }
+
+    /// Same as [`ChannelSet::on_set_new_prev_hash`], with channels split across the rayon thread
+    /// pool.
+    #[cfg(feature = "rayon")]
+    pub fn par_on_set_new_prev_hash(
+        &mut self,
+        set_new_prev_hash: SetNewPrevHashTdp<'a>,
+    ) -> ChannelSetResults<()> {
+        self.channels
+            .par_iter_mut()
+            .map(|(channel_id, channel)| {
+                (
+                    *channel_id,
+                    channel.on_set_new_prev_hash(set_new_prev_hash.clone()),
+                )
+            })
+            .collect()
+    }
+}
+
+// Updates a single channel with a new template and returns the job message it created, if any.
+fn apply_new_template<'a>(
+    channel: &mut StandardChannel<'a>,
+    template: &SharedTemplate,
+    coinbase_reward_outputs: &[TxOut],
+) -> TemplateJobResult<'a> {
+    let outcome =
+        channel.on_new_shared_template(template.clone(), coinbase_reward_outputs.to_vec())?;
+    if outcome == TemplateOutcome::SuppressedByRateLimit {
+        return Ok(None);
+    }
+    let job = match template.future_template {
+        true => channel
+            .get_future_template_to_job_id()
+            .get(&template.template_id)
+            .and_then(|job_id| channel.get_future_jobs().get(job_id)),
+        false => channel.get_active_job(),
+    };
+    job.map(|job| Some(job.get_job_message().clone()))
+        .ok_or(StandardChannelError::TemplateIdNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{
+        jobs::{error::JobFactoryError, job_store::DefaultJobStore, standard::StandardJob},
+        share_validation::ShareValidationError,
+        standard::StandardChannelConfig,
+    };
+    use bitcoin::{Amount, ScriptBuf};
+    use mining_sv2::{SubmitSharesStandard, Target};
+    use std::convert::TryInto;
+
+    const SATS_AVAILABLE_IN_TEMPLATE: u64 = 5000000000;
+
+    fn channel_set(n: u32) -> ChannelSet<'static> {
+        let mut channel_set = ChannelSet::new();
+        for channel_id in 1..=n {
+            let mut extranonce_prefix = vec![0; 32];
+            extranonce_prefix[28..].copy_from_slice(&channel_id.to_be_bytes());
+            let channel = StandardChannel::from_config(
+                StandardChannelConfig::default()
+                    .channel_id(channel_id)
+                    .user_identity("user_identity".to_string())
+                    .extranonce_prefix(extranonce_prefix)
+                    .requested_max_target(Target::MAX)
+                    .nominal_hashrate(10.0)
+                    .share_batch_size(100)
+                    .expected_share_per_minute(1.0),
+                Box::new(DefaultJobStore::<StandardJob>::new()),
+            )
+            .unwrap();
+            channel_set.insert(channel);
+        }
+        channel_set
+    }
+
+    fn template(template_id: u64, future_template: bool) -> NewTemplate<'static> {
+        NewTemplate {
+            template_id,
+            future_template,
+            version: 536870912,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: 4294967294,
+            coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 158,
+            merkle_path: vec![].try_into().unwrap(),
+        }
+    }
+
+    fn coinbase_reward_outputs() -> Vec<TxOut> {
+        vec![TxOut {
+            value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
+            script_pubkey: ScriptBuf::from(vec![
+                0, 20, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
+            ]),
+        }]
+    }
+
+    fn set_new_prev_hash(template_id: u64) -> SetNewPrevHashTdp<'static> {
+        SetNewPrevHashTdp {
+            template_id,
+            prev_hash: [0xaa; 32].into(),
+            header_timestamp: 1747092633,
+            n_bits: 503543726,
+            target: [0xff; 32].into(),
+        }
+    }
+
+    #[test]
+    fn test_bulk_template_and_prev_hash() {
+        let mut channel_set = channel_set(4);
+
+        // non-future templates need a chain tip
+        let results = channel_set.on_new_template(template(1, false), coinbase_reward_outputs());
+        assert!(results.jobs.is_empty());
+        assert_eq!(results.errors.len(), 4);
+        assert!(results
+            .errors
+            .iter()
+            .all(|(_, e)| matches!(e, StandardChannelError::ChainTipNotSet)));
+
+        let results = channel_set.on_new_template(template(2, true), coinbase_reward_outputs());
+        assert!(results.is_ok());
+        let channel_ids: Vec<u32> = results.jobs.iter().map(|(id, _)| *id).collect();
+        assert_eq!(channel_ids, vec![1, 2, 3, 4]);
+        for (channel_id, job) in &results.jobs {
+            assert_eq!(job.channel_id, *channel_id);
+            assert_eq!(job.job_id, 1);
+        }
+        // each channel has its own extranonce prefix, hence its own merkle root
+        assert_ne!(results.jobs[0].1.merkle_root, results.jobs[1].1.merkle_root);
+
+        let results = channel_set.on_set_new_prev_hash(set_new_prev_hash(2));
+        assert!(results.iter().all(|(_, r)| r.is_ok()));
+        for channel in channel_set.iter() {
+            assert_eq!(channel.get_active_job().unwrap().get_job_id(), 1);
+        }
+
+        let results = channel_set.on_new_template(template(3, false), coinbase_reward_outputs());
+        assert!(results.is_ok());
+        for (channel_id, job) in results.jobs {
+            assert_eq!(job.job_id, 2);
+            assert_eq!(
+                channel_set
+                    .get(channel_id)
+                    .unwrap()
+                    .get_active_job()
+                    .unwrap()
+                    .get_job_message(),
+                &job
+            );
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_matches_serial() {
+        fn jobs(results: GroupTemplateResult) -> Vec<(u32, NewMiningJob)> {
+            assert!(results.is_ok());
+            results.jobs
+        }
+
+        let mut serial = channel_set(64);
+        let mut parallel = channel_set(64);
+
+        assert_eq!(
+            jobs(serial.on_new_template(template(1, true), coinbase_reward_outputs())),
+            jobs(parallel.par_on_new_template(template(1, true), coinbase_reward_outputs()))
+        );
+        assert!(parallel
+            .par_on_set_new_prev_hash(set_new_prev_hash(1))
+            .iter()
+            .all(|(_, r)| r.is_ok()));
+        assert!(serial
+            .on_set_new_prev_hash(set_new_prev_hash(1))
+            .iter()
+            .all(|(_, r)| r.is_ok()));
+        assert_eq!(
+            jobs(serial.on_new_template(template(2, false), coinbase_reward_outputs())),
+            jobs(parallel.par_on_new_template(template(2, false), coinbase_reward_outputs()))
+        );
+    }
+
+    #[test]
+    fn test_job_factory_error_on_one_channel() {
+        let mut channel_set = channel_set(3);
+        // the coinbase reserves room for a 32 bytes extranonce prefix
+        let channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(4)
+                .user_identity("user_identity".to_string())
+                .extranonce_prefix(vec![4; 8])
+                .requested_max_target(Target::MAX)
+                .nominal_hashrate(10.0)
+                .expected_share_per_minute(1.0),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        channel_set.insert(channel);
+
+        let results = channel_set.on_new_template(template(1, true), coinbase_reward_outputs());
+        let channel_ids: Vec<u32> = results.jobs.iter().map(|(id, _)| *id).collect();
+        assert_eq!(channel_ids, vec![1, 2, 3]);
+        assert_eq!(results.errors.len(), 1);
+        assert!(matches!(
+            results.errors[0],
+            (
+                4,
+                StandardChannelError::JobFactoryError(
+                    JobFactoryError::InvalidExtranoncePrefixLength(8)
+                )
+            )
+        ));
+
+        let results = channel_set.on_set_new_prev_hash(set_new_prev_hash(1));
+        assert!(results[..3].iter().all(|(_, r)| r.is_ok()));
+        for channel in channel_set.iter() {
+            assert_eq!(channel.is_job_missing(), channel.get_channel_id() == 4);
+        }
+
+        let results = channel_set.on_new_template(template(2, false), coinbase_reward_outputs());
+        assert_eq!(results.jobs.len(), 3);
+        assert_eq!(results.errors.len(), 1);
+        assert_eq!(results.errors[0].0, 4);
+
+        let share = |channel_id, job_id| SubmitSharesStandard {
+            channel_id,
+            sequence_number: 0,
+            job_id,
+            nonce: 0,
+            ntime: 1747092633,
+            version: 536870912,
+        };
+        // the other channels keep validating shares
+        assert!(!matches!(
+            channel_set.get_mut(1).unwrap().validate_share(share(1, 2)),
+            Err(ShareValidationError::NoActiveJob)
+        ));
+        assert!(matches!(
+            channel_set.get_mut(4).unwrap().validate_share(share(4, 1)),
+            Err(ShareValidationError::NoActiveJob)
+        ));
+    }
+}
@@ -1,8 +1,14 @@
-use crate::server::jobs::error::JobFactoryError;
+use crate::{
+    server::jobs::error::{JobFactoryError, JobStoreError},
+    user_identity::UserIdentityError,
+};
+use alloc::{string::String, vec::Vec};
+use mining_sv2::TargetError;
 
 #[derive(Debug)]
 pub enum ExtendedChannelError {
     JobFactoryError(JobFactoryError),
+    JobStoreError(JobStoreError),
     InvalidNominalHashrate,
     RequestedMaxTargetOutOfRange,
     ChainTipNotSet,
@@ -10,6 +16,11 @@ pub enum ExtendedChannelError {
     JobIdNotFound,
     RequestedMinExtranonceSizeTooLarge,
     NewExtranoncePrefixTooLarge,
+    InvalidShareBatchSize,
+    /// A template with this `template_id` already produced a job, but had different contents.
+    TemplateIdReusedWithDifferentContent(u64),
+    /// The `prev_hash` of a `SetNewPrevHash` doesn't hold 32 bytes.
+    InvalidPrevHash(TargetError),
 }
 
 #[derive(Debug)]
@@ -17,6 +28,9 @@ pub enum GroupChannelError {
     ChainTipNotSet,
     TemplateIdNotFound,
     JobFactoryError(JobFactoryError),
+    JobStoreError(JobStoreError),
+    /// The `prev_hash` of a `SetNewPrevHash` doesn't hold 32 bytes.
+    InvalidPrevHash(TargetError),
 }
 
 #[derive(Debug)]
@@ -26,5 +40,109 @@ pub enum StandardChannelError {
     RequestedMaxTargetOutOfRange,
     NewExtranoncePrefixTooLarge,
     JobFactoryError(JobFactoryError),
+    JobStoreError(JobStoreError),
     ChainTipNotSet,
+    UnsupportedStateVersion(u16),
+    InvalidState,
+    InvalidShareBatchSize,
+    InvalidExpectedSharePerMinute,
+    /// A field required by `StandardChannel::from_config` was not set.
+    MissingConfigField(&'static str),
+    InvalidUserIdentity(UserIdentityError),
+    /// A template with this `template_id` already produced a job, but had different contents.
+    TemplateIdReusedWithDifferentContent(u64),
+    /// The `prev_hash` of a `SetNewPrevHash` doesn't hold 32 bytes.
+    InvalidPrevHash(TargetError),
+}
+
+/// The reasons an `OpenStandardMiningChannel` request is rejected by
+/// [`ServerChannelFactory::open_standard_channel`](crate::server::channel_factory::ServerChannelFactory::open_standard_channel).
+#[derive(Debug)]
+pub enum OpenChannelError {
+    InvalidUserIdentity(UserIdentityError),
+    InvalidNominalHashrate,
+    /// The target derived from the nominal hashrate is above the requested max target, under
+    /// [`MaxTargetPolicy::Reject`](crate::server::standard::MaxTargetPolicy::Reject).
+    RequestedMaxTargetOutOfRange,
+    /// No channel id or extranonce prefix is left to be assigned.
+    TooManyChannels,
+    /// The channel couldn't be created out of the `OpenChannelPolicy`, e.g. because of an
+    /// invalid expected share rate.
+    InvalidPolicy(StandardChannelError),
+}
+
+impl OpenChannelError {
+    /// Returns the `error_code` to be sent on an `OpenMiningChannel.Error` message.
+    ///
+    /// The spec only defines `unknown-user` and `max-target-out-of-range`, failures on the
+    /// server side are reported as `too-many-channels` and `internal-error`.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            OpenChannelError::InvalidUserIdentity(e) => e.error_code(),
+            OpenChannelError::InvalidNominalHashrate
+            | OpenChannelError::RequestedMaxTargetOutOfRange => "max-target-out-of-range",
+            OpenChannelError::TooManyChannels => "too-many-channels",
+            OpenChannelError::InvalidPolicy(_) => "internal-error",
+        }
+    }
+}
+
+impl From<StandardChannelError> for OpenChannelError {
+    fn from(e: StandardChannelError) -> Self {
+        match e {
+            StandardChannelError::InvalidUserIdentity(e) => {
+                OpenChannelError::InvalidUserIdentity(e)
+            }
+            StandardChannelError::InvalidNominalHashrate => {
+                OpenChannelError::InvalidNominalHashrate
+            }
+            StandardChannelError::RequestedMaxTargetOutOfRange => {
+                OpenChannelError::RequestedMaxTargetOutOfRange
+            }
+            e => OpenChannelError::InvalidPolicy(e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PendingSolutionError {
+    UnknownTemplateId(u64),
+    TimedOut(u64),
+    /// A solution for the template is already waiting for its transaction data.
+    AlreadyPending(u64),
+    TransactionDataUnavailable {
+        template_id: u64,
+        error_code: String,
+    },
+    InvalidHeader,
+    InvalidCoinbase,
+    // position of the transaction in the transaction list
+    InvalidTransaction(usize),
+    MerkleRootMismatch,
+    /// Some transactions have witnesses, but the coinbase doesn't commit to them.
+    WitnessCommitmentMismatch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobDeclarationError {
+    InvalidMiningJobToken,
+    InvalidCoinbase,
+    CoinbaseOutputsSumOverflow,
+    InvalidCoinbaseOutputsSum,
+    UnknownTransactions(Vec<u16>),
+}
+
+impl JobDeclarationError {
+    /// Returns the `error_code` to be sent on a `DeclareMiningJob.Error` message.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            JobDeclarationError::InvalidMiningJobToken => "invalid-mining-job-token",
+            JobDeclarationError::InvalidCoinbase => "invalid-job-param-value-coinbase-prefix",
+            JobDeclarationError::CoinbaseOutputsSumOverflow
+            | JobDeclarationError::InvalidCoinbaseOutputsSum => {
+                "invalid-job-param-value-coinbase-suffix"
+            }
+            JobDeclarationError::UnknownTransactions(_) => "invalid-job-param-value-tx-ids-list",
+        }
+    }
 }
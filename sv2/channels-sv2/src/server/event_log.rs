@@ -0,0 +1,193 @@
+//! Bounded, in-memory log of the significant events of a channel.
+//!
+//! Meant to help with dispute resolution (e.g. "the pool didn't credit my block"), only available
+//! with the `event-log` feature.
+use crate::{
+    collections::VecDeque,
+    server::share_validation::{ShareValidationError, ShareValidationResult},
+    target::WireU256,
+};
+use std::time::SystemTime;
+
+/// The number of events kept by default by a [`ChannelEventLog`].
+pub const DEFAULT_CHANNEL_EVENT_LOG_CAPACITY: usize = 1024;
+
+/// A significant event in the life of a channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChannelEventKind {
+    TemplateAccepted {
+        template_id: u64,
+        future_template: bool,
+    },
+    JobActivated {
+        job_id: u32,
+    },
+    TargetChanged {
+        target: WireU256,
+    },
+    ShareAccepted {
+        job_id: u32,
+        sequence_number: u32,
+    },
+    ShareRejected {
+        job_id: u32,
+        sequence_number: u32,
+        reason: ShareValidationError,
+    },
+    BlockFound {
+        job_id: u32,
+        sequence_number: u32,
+        // `None` if the share is for a custom job
+        template_id: Option<u64>,
+    },
+    StaleBlockCandidate {
+        job_id: u32,
+        sequence_number: u32,
+        // `None` if the share is for a custom job
+        template_id: Option<u64>,
+    },
+    // a block found on a compact past job, see `ShareValidationResult::BlockFoundNeedsJobData`
+    BlockFoundNeedsJobData {
+        job_id: u32,
+        sequence_number: u32,
+    },
+}
+
+/// A [`ChannelEventKind`] along with the time it was recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelEvent {
+    pub timestamp: SystemTime,
+    pub kind: ChannelEventKind,
+}
+
+/// Append-only ring buffer of [`ChannelEvent`]s.
+///
+/// Once `capacity` events are recorded, every new event evicts the oldest one.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelEventLog {
+    capacity: usize,
+    events: VecDeque<ChannelEvent>,
+}
+
+impl ChannelEventLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Records an event which happened at `timestamp`, e.g. as told by the clock of the channel.
+    pub fn record_at(&mut self, timestamp: SystemTime, kind: ChannelEventKind) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(ChannelEvent { timestamp, kind });
+    }
+
+    /// Records the outcome of the validation of a share.
+    pub(crate) fn record_share(
+        &mut self,
+        timestamp: SystemTime,
+        job_id: u32,
+        sequence_number: u32,
+        result: &Result<ShareValidationResult, ShareValidationError>,
+    ) {
+        let kind = match result {
+            Ok(ShareValidationResult::Valid)
+            | Ok(ShareValidationResult::ValidWithAcknowledgement(..)) => {
+                ChannelEventKind::ShareAccepted {
+                    job_id,
+                    sequence_number,
+                }
+            }
+            Ok(ShareValidationResult::BlockFound(template_id, ..)) => {
+                ChannelEventKind::BlockFound {
+                    job_id,
+                    sequence_number,
+                    template_id: *template_id,
+                }
+            }
+            Ok(ShareValidationResult::StaleBlockCandidate(template_id, _)) => {
+                ChannelEventKind::StaleBlockCandidate {
+                    job_id,
+                    sequence_number,
+                    template_id: *template_id,
+                }
+            }
+            Ok(ShareValidationResult::BlockFoundNeedsJobData(_)) => {
+                ChannelEventKind::BlockFoundNeedsJobData {
+                    job_id,
+                    sequence_number,
+                }
+            }
+            Err(reason) => ChannelEventKind::ShareRejected {
+                job_id,
+                sequence_number,
+                reason: reason.clone(),
+            },
+        };
+        self.record_at(timestamp, kind);
+    }
+
+    /// Returns the recorded events, from the oldest to the newest.
+    pub fn events(&self) -> impl Iterator<Item = &ChannelEvent> {
+        self.events.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl Default for ChannelEventLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHANNEL_EVENT_LOG_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_log_is_bounded() {
+        let mut log = ChannelEventLog::new(2);
+        for job_id in 0..3 {
+            log.record_at(
+                SystemTime::UNIX_EPOCH,
+                ChannelEventKind::JobActivated { job_id },
+            );
+        }
+        assert_eq!(log.len(), 2);
+        let kinds: Vec<_> = log.events().map(|event| event.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ChannelEventKind::JobActivated { job_id: 1 },
+                ChannelEventKind::JobActivated { job_id: 2 },
+            ]
+        );
+
+        let mut log = ChannelEventLog::new(0);
+        log.record_at(
+            SystemTime::UNIX_EPOCH,
+            ChannelEventKind::JobActivated { job_id: 0 },
+        );
+        assert!(log.is_empty());
+    }
+}
@@ -2,25 +2,41 @@
 
 use crate::{
     chain_tip::ChainTip,
+    clock::{default_clock, unix_timestamp, Clock},
+    collections::{HashMap, HashSet},
+    extranonce::{ExtranonceLayout, ExtranonceLayoutError},
     merkle_root::merkle_root_from_path,
+    redact::{RedactedExtranoncePrefix, RedactedIdentity},
     server::{
+        block_found::BlockFoundEvent,
         error::ExtendedChannelError,
-        jobs::{extended::ExtendedJob, factory::JobFactory, job_store::JobStore, JobOrigin},
-        share_accounting::{ShareAccounting, ShareValidationError, ShareValidationResult},
+        header_hasher::{block_hash, DefaultHeaderHasher, HeaderHasher},
+        jobs::{
+            extended::ExtendedJob, factory::JobFactory, is_same_template, job_store::JobStore,
+            JobOrigin, TemplateReplayPolicy,
+        },
+        pending_solution::BlockSolution,
+        share_accounting::ShareAccounting,
+        share_validation::{
+            check_share, validate_share_job_id, InternalInconsistency, ShareValidationError,
+            ShareValidationResult,
+        },
+    },
+    target::{
+        compact_tolerance_bits, hash_rate_to_target, target_to_difficulty, DisplayU256, WireU256,
     },
-    target::{bytes_to_hex, hash_rate_to_target, target_to_difficulty, u256_to_block_hash},
+    trace::{debug, debug_enabled, error},
 };
-use binary_sv2::{self};
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
 use bitcoin::{
     blockdata::block::{Header, Version},
     hashes::sha256d::Hash,
     transaction::TxOut,
     CompactTarget, Target as BitcoinTarget,
 };
-use mining_sv2::{SetCustomMiningJob, SubmitSharesExtended, Target, MAX_EXTRANONCE_LEN};
-use std::{collections::HashMap, convert::TryInto};
+use core::{convert::TryInto, fmt};
+use mining_sv2::{SetCustomMiningJob, SubmitSharesExtended, Target};
 use template_distribution_sv2::{NewTemplate, SetNewPrevHash as SetNewPrevHashTdp};
-use tracing::debug;
 
 /// Mining Server abstraction of a Sv2 Extended Channel.
 ///
@@ -43,7 +59,8 @@ use tracing::debug;
 /// - the channel's share validation state
 /// - the channel's job factory
 /// - the channel's chain tip
-#[derive(Debug)]
+/// - the channel's previous chain tip, if it's retained (see
+///   [`ExtendedChannel::set_retain_previous_chain_tip`])
 pub struct ExtendedChannel<'a> {
     channel_id: u32,
     user_identity: String,
@@ -51,30 +68,73 @@ pub struct ExtendedChannel<'a> {
     rollable_extranonce_size: u16,
     requested_max_target: Target,
     target: Target, // todo: try to use Target from rust-bitcoin
-    nominal_hashrate: f32,
+    nominal_hashrate: f64,
     job_store: Box<dyn JobStore<ExtendedJob<'a>>>,
     job_factory: JobFactory,
     share_accounting: ShareAccounting,
     expected_share_per_minute: f32,
     chain_tip: Option<ChainTip>,
+    retain_previous_chain_tip: bool,
+    // the chain tip before the current one, along with the ids of the jobs mined on it
+    previous_chain_tip: Option<(ChainTip, HashSet<u32>)>,
+    template_replay_policy: TemplateReplayPolicy,
+    header_hasher: Arc<dyn HeaderHasher>,
+    // `None` without the `std` feature, unless set
+    clock: Option<Arc<dyn Clock>>,
+}
+
+impl fmt::Debug for ExtendedChannel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtendedChannel")
+            .field("channel_id", &self.channel_id)
+            .field("user_identity", &RedactedIdentity(&self.user_identity))
+            .field(
+                "extranonce_prefix",
+                &RedactedExtranoncePrefix(&self.extranonce_prefix),
+            )
+            .field("rollable_extranonce_size", &self.rollable_extranonce_size)
+            .field("requested_max_target", &self.requested_max_target)
+            .field("target", &self.target)
+            .field("nominal_hashrate", &self.nominal_hashrate)
+            .field("job_store", &self.job_store)
+            .field("job_factory", &self.job_factory)
+            .field("share_accounting", &self.share_accounting)
+            .field("expected_share_per_minute", &self.expected_share_per_minute)
+            .field("chain_tip", &self.chain_tip)
+            .field("retain_previous_chain_tip", &self.retain_previous_chain_tip)
+            .field("previous_chain_tip", &self.previous_chain_tip)
+            .field("template_replay_policy", &self.template_replay_policy)
+            .field("header_hasher", &self.header_hasher)
+            .field("clock", &self.clock)
+            .finish()
+    }
 }
 
 impl<'a> ExtendedChannel<'a> {
+    /// Creates a channel whose extranonce prefix leaves at least
+    /// `requested_min_rollable_extranonce_size` bytes rollable, see
+    /// [`ExtranonceLayout::for_channel`].
+    ///
+    /// Fails with [`ExtendedChannelError::NewExtranoncePrefixTooLarge`] if the prefix is longer
+    /// than `MAX_EXTRANONCE_LEN`, and with
+    /// [`ExtendedChannelError::RequestedMinExtranonceSizeTooLarge`] if it leaves less rollable
+    /// space than requested.
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         channel_id: u32,
         user_identity: String,
         extranonce_prefix: Vec<u8>,
         max_target: Target,
-        nominal_hashrate: f32,
+        nominal_hashrate: impl Into<f64>,
         version_rolling_allowed: bool,
         requested_min_rollable_extranonce_size: u16,
         share_batch_size: usize,
         expected_share_per_minute: f32,
         job_store: Box<dyn JobStore<ExtendedJob<'a>>>,
     ) -> Result<Self, ExtendedChannelError> {
+        let nominal_hashrate = nominal_hashrate.into();
         let target_u256 =
-            match hash_rate_to_target(nominal_hashrate.into(), expected_share_per_minute.into()) {
+            match hash_rate_to_target(nominal_hashrate, expected_share_per_minute.into()) {
                 Ok(target_u256) => target_u256,
                 Err(_) => {
                     return Err(ExtendedChannelError::InvalidNominalHashrate);
@@ -87,11 +147,17 @@ impl<'a> ExtendedChannel<'a> {
             return Err(ExtendedChannelError::RequestedMaxTargetOutOfRange);
         }
 
-        let available_rollable_extranonce_size =
-            (MAX_EXTRANONCE_LEN - extranonce_prefix.len()) as u16;
-        if requested_min_rollable_extranonce_size > available_rollable_extranonce_size {
-            return Err(ExtendedChannelError::RequestedMinExtranonceSizeTooLarge);
-        }
+        let available_rollable_extranonce_size = ExtranonceLayout::for_channel(
+            extranonce_prefix.len(),
+            requested_min_rollable_extranonce_size as usize,
+        )
+        .map_err(|e| match e {
+            ExtranonceLayoutError::InsufficientRollableSpace { .. } => {
+                ExtendedChannelError::RequestedMinExtranonceSizeTooLarge
+            }
+            _ => ExtendedChannelError::NewExtranoncePrefixTooLarge,
+        })?
+        .get_rollable_size() as u16;
 
         Ok(Self {
             channel_id,
@@ -106,6 +172,11 @@ impl<'a> ExtendedChannel<'a> {
             share_accounting: ShareAccounting::new(share_batch_size),
             expected_share_per_minute,
             chain_tip: None,
+            retain_previous_chain_tip: false,
+            previous_chain_tip: None,
+            template_replay_policy: TemplateReplayPolicy::default(),
+            header_hasher: Arc::new(DefaultHeaderHasher),
+            clock: default_clock(),
         })
     }
 
@@ -125,13 +196,102 @@ impl<'a> ExtendedChannel<'a> {
         self.chain_tip.as_ref()
     }
 
+    /// Returns the chain tip before the current one, if it's retained.
+    pub fn get_previous_chain_tip(&self) -> Option<&ChainTip> {
+        self.previous_chain_tip
+            .as_ref()
+            .map(|(chain_tip, _)| chain_tip)
+    }
+
+    /// Sets whether the chain tip before the current one is retained, which is off by default.
+    ///
+    /// A share for a job of the previous chain tip that meets the network target of that chain
+    /// tip is then returned as a [`ShareValidationResult::StaleBlockCandidate`] instead of being
+    /// rejected as stale, see
+    /// [`StandardChannel::set_retain_previous_chain_tip`](crate::server::standard::StandardChannel::set_retain_previous_chain_tip).
+    pub fn set_retain_previous_chain_tip(&mut self, retain_previous_chain_tip: bool) {
+        self.retain_previous_chain_tip = retain_previous_chain_tip;
+        if !retain_previous_chain_tip {
+            self.previous_chain_tip = None;
+        }
+    }
+
+    /// Sets the [`HeaderHasher`] the headers of shares are hashed with, see
+    /// [`StandardChannel::set_header_hasher`](crate::server::standard::StandardChannel::set_header_hasher).
+    pub fn set_header_hasher(&mut self, header_hasher: Arc<dyn HeaderHasher>) {
+        self.header_hasher = header_hasher;
+    }
+
+    /// Sets the [`Clock`] the timestamps of [`BlockFoundEvent`]s are taken from, replacing the
+    /// system clock.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = Some(clock);
+    }
+
+    pub fn get_template_replay_policy(&self) -> TemplateReplayPolicy {
+        self.template_replay_policy
+    }
+
+    /// Sets how templates whose `template_id` already produced a job are handled, see
+    /// [`TemplateReplayPolicy`].
+    pub fn set_template_replay_policy(&mut self, template_replay_policy: TemplateReplayPolicy) {
+        self.template_replay_policy = template_replay_policy;
+    }
+
+    /// Returns the future, active or past job created for the template with `template_id`.
+    pub fn get_job_for_template(&self, template_id: u64) -> Option<&ExtendedJob<'a>> {
+        let job_store = &self.job_store;
+        let is_for_template = |job: &&ExtendedJob| {
+            job_template(job).map(|template| template.template_id) == Some(template_id)
+        };
+        job_store
+            .get_future_template_to_job_id()
+            .get(&template_id)
+            .and_then(|job_id| job_store.get_future_jobs().get(job_id))
+            .or_else(|| job_store.get_active_job().filter(is_for_template))
+            .or_else(|| job_store.get_past_jobs().values().find(is_for_template))
+    }
+
+    // Whether `set_new_prev_hash` already made the current chain tip and active job.
+    fn is_current_prev_hash(&self, set_new_prev_hash: &SetNewPrevHashTdp) -> bool {
+        let chain_tip = match &self.chain_tip {
+            Some(chain_tip) => chain_tip,
+            None => return false,
+        };
+        chain_tip.prev_hash() == set_new_prev_hash.prev_hash
+            && chain_tip.nbits() == set_new_prev_hash.n_bits
+            && chain_tip.min_ntime() == set_new_prev_hash.header_timestamp
+            && self
+                .job_store
+                .get_active_job()
+                .and_then(job_template)
+                .map(|template| template.template_id)
+                == Some(set_new_prev_hash.template_id)
+    }
+
+    pub fn is_version_rolling_allowed(&self) -> bool {
+        self.job_factory.is_version_rolling_allowed()
+    }
+
+    /// Sets whether version rolling is allowed on jobs created from now on.
+    ///
+    /// Should follow the version rolling policy of the connection, as decided by
+    /// [`crate::connection::ChannelFactory`] from the `SetupConnection` flags.
+    ///
+    /// Jobs that were already created keep their own `version_rolling_allowed` value, which is
+    /// what shares are validated against.
+    pub fn set_version_rolling_allowed(&mut self, version_rolling_allowed: bool) {
+        self.job_factory
+            .set_version_rolling_allowed(version_rolling_allowed);
+    }
+
     pub fn get_shares_per_minute(&self) -> f32 {
         self.expected_share_per_minute
     }
 
     /// Only for testing purposes, not meant to be used in real apps.
-    #[cfg(test)]
-    fn set_chain_tip(&mut self, chain_tip: ChainTip) {
+    #[cfg(any(test, feature = "test-utils"))]
+    pub(crate) fn set_chain_tip(&mut self, chain_tip: ChainTip) {
         self.chain_tip = Some(chain_tip);
     }
 
@@ -145,7 +305,9 @@ impl<'a> ExtendedChannel<'a> {
         extranonce_prefix: Vec<u8>,
     ) -> Result<(), ExtendedChannelError> {
         let new_rollable_extranonce_size =
-            MAX_EXTRANONCE_LEN as u16 - extranonce_prefix.len() as u16;
+            ExtranonceLayout::from_prefix_len(extranonce_prefix.len())
+                .map_err(|_| ExtendedChannelError::NewExtranoncePrefixTooLarge)?
+                .get_rollable_size() as u16;
 
         // we return an error if the new extranonce_prefix would violate
         // min_rollable_extranonce_size that was already established with the client when the
@@ -180,24 +342,44 @@ impl<'a> ExtendedChannel<'a> {
         self.job_store.get_future_template_to_job_id()
     }
 
-    pub fn get_nominal_hashrate(&self) -> f32 {
+    pub fn get_nominal_hashrate(&self) -> f64 {
         self.nominal_hashrate
     }
 
-    pub fn set_nominal_hashrate(&mut self, hashrate: f32) {
-        self.nominal_hashrate = hashrate;
+    /// Changes the number of accepted shares between acknowledgements, e.g. to acknowledge
+    /// shares more often on unreliable connections.
+    ///
+    /// Shares accepted since the last acknowledgement still count towards the next one, see
+    /// [`ShareAccounting::set_share_batch_size`].
+    pub fn set_share_batch_size(
+        &mut self,
+        share_batch_size: usize,
+    ) -> Result<(), ExtendedChannelError> {
+        if share_batch_size == 0 {
+            return Err(ExtendedChannelError::InvalidShareBatchSize);
+        }
+        self.share_accounting.set_share_batch_size(share_batch_size);
+        Ok(())
+    }
+
+    pub fn set_nominal_hashrate(&mut self, hashrate: impl Into<f64>) {
+        self.nominal_hashrate = hashrate.into();
     }
 
     /// Updates the channel's nominal hashrate and target.
     ///
     /// If requested_max_target is None, we use the cached value in the channel state.
+    ///
+    /// The target is kept if the new one only differs below the precision of a compact `nbits`
+    /// target (see [`Target::approx_eq`]), so a `SetTarget` is only needed when it changes.
     pub fn update_channel(
         &mut self,
-        new_nominal_hashrate: f32,
+        new_nominal_hashrate: impl Into<f64>,
         requested_max_target: Option<Target>,
     ) -> Result<(), ExtendedChannelError> {
+        let new_nominal_hashrate = new_nominal_hashrate.into();
         let target_u256 = match hash_rate_to_target(
-            new_nominal_hashrate.into(),
+            new_nominal_hashrate,
             self.expected_share_per_minute.into(),
         ) {
             Ok(target_u256) => target_u256,
@@ -211,27 +393,14 @@ impl<'a> ExtendedChannel<'a> {
             None => self.requested_max_target.clone(),
         };
 
-        // debug hex of target_u256 and max_Target
-        // just like in share validation
-        let mut target_bytes = target_u256.to_vec();
-        target_bytes.reverse(); // Convert to big-endian for display
-        let max_target_u256: binary_sv2::U256 = requested_max_target.clone().into();
-        let mut max_target_bytes = max_target_u256.to_vec();
-        max_target_bytes.reverse(); // Convert to big-endian for display
-
-        // Get the old target for comparison on the debug log
-        // Not really needed for the actual method functionality
-        // But it's useful to have for debugging purposes
-        let old_target_u256: binary_sv2::U256 = self.target.clone().into();
-        let mut old_target_bytes = old_target_u256.to_vec();
-        old_target_bytes.reverse(); // Convert to big-endian for display
-
-        debug!(
-            "updating channel target \nold target:\t{}\nnew target:\t{}\nmax_target:\t{}",
-            bytes_to_hex(&old_target_bytes),
-            bytes_to_hex(&target_bytes),
-            bytes_to_hex(&max_target_bytes)
-        );
+        if debug_enabled!() {
+            debug!(
+                "updating channel target \nold target:\t{}\nnew target:\t{}\nmax_target:\t{}",
+                DisplayU256::from(&self.target),
+                DisplayU256::from(&Target::from(target_u256.clone())),
+                DisplayU256::from(&requested_max_target)
+            );
+        }
 
         let new_target: Target = target_u256.into();
 
@@ -240,7 +409,11 @@ impl<'a> ExtendedChannel<'a> {
         }
 
         self.nominal_hashrate = new_nominal_hashrate;
-        self.target = new_target;
+        // only replace the target if it changed beyond the precision of a compact target, so
+        // rounding noise doesn't cause unnecessary SetTarget messages
+        if !new_target.approx_eq(&self.target, compact_tolerance_bits(&self.target)) {
+            self.target = new_target;
+        }
         self.requested_max_target = requested_max_target;
 
         Ok(())
@@ -269,11 +442,36 @@ impl<'a> ExtendedChannel<'a> {
     ///
     /// Only meant for usage on a Sv2 Pool Server or a Sv2 Job Declaration Client,
     /// but not on mining clients such as Mining Devices or Proxies.
+    ///
+    /// A template whose `template_id` already produced a job is handled according to the
+    /// channel's [`TemplateReplayPolicy`]. When the existing job is kept, it can be retrieved via
+    /// [`ExtendedChannel::get_job_for_template`].
     pub fn on_new_template(
         &mut self,
         template: NewTemplate<'a>,
         coinbase_reward_outputs: Vec<TxOut>,
     ) -> Result<(), ExtendedChannelError> {
+        if self.template_replay_policy == TemplateReplayPolicy::ReuseJob {
+            if let Some(job) = self.get_job_for_template(template.template_id) {
+                if !job_template(job)
+                    .is_some_and(|job_template| is_same_template(job_template, &template))
+                {
+                    return Err(ExtendedChannelError::TemplateIdReusedWithDifferentContent(
+                        template.template_id,
+                    ));
+                }
+                // the existing job is kept, unless it was created for other reward outputs or
+                // another extranonce prefix
+                if job
+                    .get_coinbase_outputs()
+                    .starts_with(&coinbase_reward_outputs)
+                    && job.get_extranonce_prefix() == &self.extranonce_prefix
+                {
+                    return Ok(());
+                }
+            }
+        }
+
         match template.future_template {
             true => {
                 let new_job = self
@@ -286,7 +484,9 @@ impl<'a> ExtendedChannel<'a> {
                         coinbase_reward_outputs,
                     )
                     .map_err(ExtendedChannelError::JobFactoryError)?;
-                self.job_store.add_future_job(template.template_id, new_job);
+                self.job_store
+                    .add_future_job(template.template_id, new_job)
+                    .map_err(ExtendedChannelError::JobStoreError)?;
             }
             false => {
                 match self.chain_tip.clone() {
@@ -303,7 +503,9 @@ impl<'a> ExtendedChannel<'a> {
                                 coinbase_reward_outputs,
                             )
                             .map_err(ExtendedChannelError::JobFactoryError)?;
-                        self.job_store.add_active_job(new_job);
+                        self.job_store
+                            .add_active_job(new_job)
+                            .map_err(ExtendedChannelError::JobStoreError)?;
                     }
                 }
             }
@@ -317,39 +519,80 @@ impl<'a> ExtendedChannel<'a> {
     ///
     /// If there are no future jobs, returns an error.
     /// If there is some future job matching the `template_id`` that `SetNewPrevHash` points to,
-    /// this future job is "activated" and set as the active job.
+    /// this future job is "activated" and set as the active job. Otherwise (e.g. a template
+    /// superseded by the one of an earlier `SetNewPrevHash`), returns an error and leaves the
+    /// channel untouched.
+    ///
+    /// All past jobs are cleared, and either kept as stale jobs or dropped according to the job
+    /// store's [`StaleRetention`](crate::server::jobs::job_store::StaleRetention).
     ///
-    /// All past jobs are cleared.
+    /// With [`TemplateReplayPolicy::ReuseJob`], a replay of the `SetNewPrevHash` that made the
+    /// current chain tip and active job is ignored.
     ///
     /// The chain tip information is not kept in the channel state.
     pub fn on_set_new_prev_hash(
         &mut self,
         set_new_prev_hash: SetNewPrevHashTdp<'a>,
     ) -> Result<(), ExtendedChannelError> {
+        if self.template_replay_policy == TemplateReplayPolicy::ReuseJob
+            && self.is_current_prev_hash(&set_new_prev_hash)
+        {
+            return Ok(());
+        }
+
+        // the jobs mined on the current chain tip, before they become stale
+        let job_ids: HashSet<u32> = match self.retain_previous_chain_tip {
+            true => self
+                .job_store
+                .get_active_job()
+                .map(|job| job.get_job_id())
+                .into_iter()
+                .chain(self.job_store.get_past_jobs().keys().copied())
+                .collect(),
+            false => HashSet::new(),
+        };
+
+        let set_new_prev_hash = set_new_prev_hash.into_static();
+        let new_chain_tip = ChainTip::new(
+            set_new_prev_hash.prev_hash,
+            set_new_prev_hash.n_bits,
+            set_new_prev_hash.header_timestamp,
+        )
+        .map_err(ExtendedChannelError::InvalidPrevHash)?;
+
         match self.job_store.get_future_jobs().is_empty() {
             true => {
                 return Err(ExtendedChannelError::TemplateIdNotFound);
             }
             false => {
-                // the SetNewPrevHash message was addressed to a specific future template
-                self.job_store.activate_future_job(
+                // the SetNewPrevHash message was addressed to a specific future template, which
+                // may have been superseded by an earlier one
+                if !self.job_store.activate_future_job(
                     set_new_prev_hash.template_id,
                     set_new_prev_hash.header_timestamp,
-                );
+                ) {
+                    return Err(ExtendedChannelError::TemplateIdNotFound);
+                }
             }
         }
+        let orphans = self.job_store.gc();
+        if orphans > 0 {
+            debug!(
+                "dropped {} orphan future jobs or template mappings",
+                orphans
+            );
+        }
 
         // clear seen shares, as shares for past chain tip will be rejected as stale
         self.share_accounting.flush_seen_shares();
+        self.share_accounting
+            .start_new_era(set_new_prev_hash.template_id);
 
         // update the chain tip
-        let set_new_prev_hash_static = set_new_prev_hash.into_static();
-        let new_chain_tip = ChainTip::new(
-            set_new_prev_hash_static.prev_hash,
-            set_new_prev_hash_static.n_bits,
-            set_new_prev_hash_static.header_timestamp,
-        );
-        self.chain_tip = Some(new_chain_tip);
+        let previous_chain_tip = self.chain_tip.replace(new_chain_tip);
+        if self.retain_previous_chain_tip {
+            self.previous_chain_tip = previous_chain_tip.map(|chain_tip| (chain_tip, job_ids));
+        }
 
         Ok(())
     }
@@ -373,7 +616,9 @@ impl<'a> ExtendedChannel<'a> {
 
         let job_id = new_job.get_job_id();
 
-        self.job_store.add_active_job(new_job);
+        self.job_store
+            .add_active_job(new_job)
+            .map_err(ExtendedChannelError::JobStoreError)?;
 
         Ok(job_id)
     }
@@ -387,85 +632,106 @@ impl<'a> ExtendedChannel<'a> {
     ) -> Result<ShareValidationResult, ShareValidationError> {
         let job_id = share.job_id;
 
-        // check if job_id is active job
-        let is_active_job = self
-            .job_store
-            .get_active_job()
-            .is_some_and(|job| job.get_job_id() == job_id);
-
-        // check if job_id is past job
-        let is_past_job = self.job_store.get_past_jobs().contains_key(&job_id);
-
-        // check if job_id is stale job
-        let is_stale_job = self.job_store.get_stale_jobs().contains_key(&job_id);
-
-        if is_stale_job {
-            return Err(ShareValidationError::Stale);
+        // jobs of previous chain tips are either kept as stale or dropped, depending on the
+        // job store's `StaleRetention`
+        if let Some(job) = self.job_store.get_stale_jobs().get(&job_id) {
+            // unless it's a block on the branch of the previous chain tip
+            let (chain_tip, job_ids) = match &self.previous_chain_tip {
+                Some(previous_chain_tip) => previous_chain_tip,
+                None => return Err(ShareValidationError::Stale),
+            };
+            if !job_ids.contains(&job_id)
+                || check_share(
+                    job.get_version(),
+                    job.activation_ntime()
+                        .unwrap_or_else(|| chain_tip.min_ntime()),
+                    share.version,
+                    share.ntime,
+                    job.version_rolling_allowed(),
+                )
+                .is_err()
+            {
+                return Err(ShareValidationError::Stale);
+            }
+            let (full_extranonce, merkle_root) =
+                share_merkle_root(job, share.extranonce.inner_as_ref())?;
+            let mut header = chain_tip.header_template(
+                share.version,
+                WireU256::from_wire_bytes(merkle_root),
+                share.ntime,
+            );
+            header.nonce = share.nonce;
+            let hash = block_hash(self.header_hasher.as_ref(), &header);
+            if !header.target().is_met_by(hash) {
+                return Err(ShareValidationError::Stale);
+            }
+            if self.share_accounting.is_share_seen(hash.to_raw_hash()) {
+                return Err(ShareValidationError::DuplicateShare);
+            }
+            self.share_accounting.update_share_accounting(
+                target_to_difficulty(self.target.clone()) as u64,
+                share.sequence_number,
+                hash.to_raw_hash(),
+            );
+            let (template_id, coinbase) = block_coinbase(job, full_extranonce);
+            return Ok(ShareValidationResult::StaleBlockCandidate(
+                template_id,
+                BlockSolution { header, coinbase },
+            ));
         }
 
-        // if job_id is not active, past or stale, return error
-        if !is_active_job && !is_past_job && !is_stale_job {
-            return Err(ShareValidationError::InvalidJobId);
+        if self.job_store.is_empty() {
+            debug!(
+                "share for job {} on channel {} submitted before any job was sent",
+                job_id, self.channel_id
+            );
+            return Err(ShareValidationError::NoActiveJob);
         }
 
-        let job = if is_active_job {
-            self.job_store
-                .get_active_job()
-                .expect("active job must exist")
-        } else if is_past_job {
-            self.job_store
-                .get_past_jobs()
-                .get(&job_id)
-                .expect("past job must exist")
-        } else {
-            self.job_store
-                .get_stale_jobs()
-                .get(&job_id)
-                .expect("stale job must exist")
+        // only the active job and the past jobs under the current chain tip can be mined on
+        let job_store = &self.job_store;
+        let job = match job_store
+            .get_active_job()
+            .filter(|job| job.get_job_id() == job_id)
+            .or_else(|| job_store.get_past_jobs().get(&job_id))
+        {
+            Some(job) => job,
+            None => return Err(ShareValidationError::InvalidJobId),
         };
+        if let Err(e) = validate_share_job_id(job_id, job.get_job_id()) {
+            error!(
+                "job store of channel {} returned job {} for job {}",
+                self.channel_id,
+                job.get_job_id(),
+                job_id
+            );
+            return Err(e);
+        }
 
-        let extranonce_prefix = job.get_extranonce_prefix();
-        let mut full_extranonce = vec![];
-        full_extranonce.extend(extranonce_prefix.clone());
-        full_extranonce.extend(share.extranonce.inner_as_ref());
-
-        // calculate the merkle root from:
-        // - job coinbase_tx_prefix
-        // - full extranonce
-        // - job coinbase_tx_suffix
-        // - job merkle_path
-        let merkle_root: [u8; 32] = merkle_root_from_path(
-            job.get_coinbase_tx_prefix().inner_as_ref(),
-            job.get_coinbase_tx_suffix().inner_as_ref(),
-            full_extranonce.as_ref(),
-            &job.get_merkle_path().inner_as_ref(),
-        )
-        .ok_or(ShareValidationError::Invalid)?
-        .try_into()
-        .expect("merkle root must be 32 bytes");
+        let (full_extranonce, merkle_root) =
+            share_merkle_root(job, share.extranonce.inner_as_ref())?;
 
         let chain_tip = self
             .chain_tip
             .as_ref()
             .ok_or(ShareValidationError::NoChainTip)?;
 
-        let prev_hash = chain_tip.prev_hash();
         let nbits = CompactTarget::from_consensus(chain_tip.nbits());
 
-        // validate when version rolling is not allowed
-        if !job.version_rolling_allowed() {
-            // If version rolling is not allowed, ensure bits 13-28 are 0
-            // This is done by checking if the version & 0x1fffe000 == 0
-            // ref: https://github.com/bitcoin/bips/blob/master/bip-0320.mediawiki
-            if (share.version & 0x1fffe000) != 0 {
-                return Err(ShareValidationError::VersionRollingNotAllowed);
-            }
-        }
+        // jobs created on top of the current chain tip carry its timestamp as well
+        check_share(
+            job.get_version(),
+            job.activation_ntime()
+                .unwrap_or_else(|| chain_tip.min_ntime()),
+            share.version,
+            share.ntime,
+            job.version_rolling_allowed(),
+        )?;
 
         // create the header for validation
         let header = Header {
             version: Version::from_consensus(share.version as i32),
-            prev_blockhash: u256_to_block_hash(prev_hash.clone()),
+            prev_blockhash: chain_tip.prev_block_hash(),
             merkle_root: (*Hash::from_bytes_ref(&merkle_root)).into(),
             time: share.ntime,
             bits: nbits,
@@ -473,27 +739,20 @@ impl<'a> ExtendedChannel<'a> {
         };
 
         // convert the header hash to a target type for easy comparison
-        let hash = header.block_hash();
-        let raw_hash: [u8; 32] = *hash.to_raw_hash().as_ref();
-        let hash_as_target: Target = raw_hash.into();
+        let hash = block_hash(self.header_hasher.as_ref(), &header);
+        let hash_as_target: Target = WireU256::from(hash).into();
         let hash_as_diff = target_to_difficulty(hash_as_target.clone());
 
         let network_target = BitcoinTarget::from_compact(nbits);
 
-        // print hash_as_target and self.target as human readable hex
-        let hash_as_u256: binary_sv2::U256 = hash_as_target.clone().into();
-        let mut hash_bytes = hash_as_u256.to_vec();
-        hash_bytes.reverse(); // Convert to big-endian for display
-        let target_u256: binary_sv2::U256 = self.target.clone().into();
-        let mut target_bytes = target_u256.to_vec();
-        target_bytes.reverse(); // Convert to big-endian for display
-
-        debug!(
-            "share validation \nshare:\t\t{}\nchannel target:\t{}\nnetwork target:\t{}",
-            bytes_to_hex(&hash_bytes),
-            bytes_to_hex(&target_bytes),
-            format!("{:x}", network_target)
-        );
+        if debug_enabled!() {
+            debug!(
+                "share validation \nshare:\t\t{}\nchannel target:\t{}\nnetwork target:\t{}",
+                DisplayU256::from(&hash_as_target),
+                DisplayU256::from(&self.target),
+                format!("{:x}", network_target)
+            );
+        }
 
         // check if a block was found
         if network_target.is_met_by(hash) {
@@ -503,23 +762,22 @@ impl<'a> ExtendedChannel<'a> {
                 hash.to_raw_hash(),
             );
 
-            let mut coinbase = vec![];
-            coinbase.extend(job.get_coinbase_tx_prefix().inner_as_ref());
-            coinbase.extend(full_extranonce);
-            coinbase.extend(job.get_coinbase_tx_suffix().inner_as_ref());
-
-            match job.get_origin() {
-                JobOrigin::NewTemplate(template) => {
-                    let template_id = template.template_id;
-                    return Ok(ShareValidationResult::BlockFound(
-                        Some(template_id),
-                        coinbase,
-                    ));
-                }
-                JobOrigin::SetCustomMiningJob(_set_custom_mining_job) => {
-                    return Ok(ShareValidationResult::BlockFound(None, coinbase));
-                }
-            }
+            let (template_id, coinbase) = block_coinbase(job, full_extranonce);
+            let event = BlockFoundEvent::new(
+                self.channel_id,
+                &self.user_identity,
+                job_id,
+                share.sequence_number,
+                template_id,
+                &header,
+                hash,
+                unix_timestamp(self.clock.as_ref()),
+            );
+            return Ok(ShareValidationResult::BlockFound(
+                template_id,
+                coinbase,
+                event,
+            ));
         }
 
         // check if the share hash meets the channel target
@@ -537,40 +795,95 @@ impl<'a> ExtendedChannel<'a> {
             // update the best diff
             self.share_accounting.update_best_diff(hash_as_diff);
 
-            let last_sequence_number = self.share_accounting.get_last_share_sequence_number();
-            let new_submits_accepted_count = self.share_accounting.get_shares_accepted();
-            let new_shares_sum = self.share_accounting.get_share_work_sum();
-
-            // if sequence number is a multiple of share_batch_size
-            // it's time to send a SubmitShares.Success
-            if self.share_accounting.should_acknowledge() {
-                Ok(ShareValidationResult::ValidWithAcknowledgement(
-                    last_sequence_number,
-                    new_submits_accepted_count,
-                    new_shares_sum,
-                ))
-            } else {
-                Ok(ShareValidationResult::Valid)
-            }
+            // every share_batch_size accepted shares, it's time to send a SubmitShares.Success
+            Ok(self.share_accounting.validation_result())
         } else {
             Err(ShareValidationError::DoesNotMeetTarget)
         }
     }
 }
 
+// Returns the full extranonce of a share for `job` along with the merkle root it yields, computed
+// from:
+// - job coinbase_tx_prefix
+// - full extranonce
+// - job coinbase_tx_suffix
+// - job merkle_path
+fn share_merkle_root(
+    job: &ExtendedJob,
+    extranonce: &[u8],
+) -> Result<(Vec<u8>, [u8; 32]), ShareValidationError> {
+    // the share's extranonce must fill all the space left by the job's extranonce prefix, which
+    // leaves no space at all (and an empty share extranonce) on channels opened with a
+    // `min_extranonce_size` of 0 and handed a full-size prefix, e.g. for header-only proxies
+    // relying on version rolling alone, unless it's the full extranonce, prefix included
+    let extranonce_prefix = job.get_extranonce_prefix();
+    let full_extranonce = ExtranonceLayout::from_prefix_len(extranonce_prefix.len())
+        .and_then(|layout| layout.decode_share_extranonce(extranonce_prefix, extranonce))
+        .map_err(|e| match e {
+            ExtranonceLayoutError::PrefixMismatch => ShareValidationError::ExtranoncePrefixMismatch,
+            _ => ShareValidationError::Invalid,
+        })?;
+
+    let merkle_root: [u8; 32] = merkle_root_from_path(
+        job.get_coinbase_tx_prefix().inner_as_ref(),
+        job.get_coinbase_tx_suffix().inner_as_ref(),
+        full_extranonce.as_ref(),
+        &job.get_merkle_path().inner_as_ref(),
+    )
+    .ok_or(ShareValidationError::Invalid)?
+    .try_into()
+    .map_err(|_| {
+        error!("merkle root of job {} is malformed", job.get_job_id());
+        ShareValidationError::Internal(InternalInconsistency::MalformedMerkleRoot)
+    })?;
+
+    Ok((full_extranonce, merkle_root))
+}
+
+// Returns the template `job` was created from, `None` for custom jobs.
+fn job_template<'b>(job: &'b ExtendedJob) -> Option<&'b NewTemplate<'b>> {
+    match job.get_origin() {
+        JobOrigin::NewTemplate(template) => Some(template),
+        JobOrigin::SetCustomMiningJob(_set_custom_mining_job) => None,
+    }
+}
+
+// Returns the `template_id` of `job` (`None` for custom jobs) along with its serialized coinbase.
+fn block_coinbase(job: &ExtendedJob, full_extranonce: Vec<u8>) -> (Option<u64>, Vec<u8>) {
+    let mut coinbase = vec![];
+    coinbase.extend(job.get_coinbase_tx_prefix().inner_as_ref());
+    coinbase.extend(full_extranonce);
+    coinbase.extend(job.get_coinbase_tx_suffix().inner_as_ref());
+
+    let template_id = match job.get_origin() {
+        JobOrigin::NewTemplate(template) => Some(template.template_id),
+        JobOrigin::SetCustomMiningJob(_set_custom_mining_job) => None,
+    };
+    (template_id, coinbase)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         chain_tip::ChainTip,
+        connection::ConnectionFlags,
         server::{
             error::ExtendedChannelError,
-            extended::ExtendedChannel,
-            jobs::{job_store::DefaultJobStore, JobOrigin},
-            share_accounting::{ShareValidationError, ShareValidationResult},
+            extended::{share_merkle_root, ExtendedChannel},
+            jobs::{job_store::DefaultJobStore, JobOrigin, TemplateReplayPolicy},
+            share_validation::{ShareValidationError, ShareValidationResult},
         },
+        testing::fixture,
     };
     use binary_sv2::Sv2Option;
-    use bitcoin::{transaction::TxOut, Amount, ScriptBuf};
+    use bitcoin::{
+        consensus,
+        hashes::{sha256d, Hash},
+        transaction::TxOut,
+        Amount, ScriptBuf, Transaction,
+    };
+    use common_messages_sv2::has_version_rolling;
     use mining_sv2::{NewExtendedMiningJob, SubmitSharesExtended, Target, MAX_EXTRANONCE_LEN};
     use std::convert::TryInto;
     use template_distribution_sv2::{NewTemplate, SetNewPrevHash};
@@ -589,7 +902,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let max_target = [0xff; 32].into();
+        let max_target = Target::MAX;
         let expected_share_per_minute = 1.0;
         let nominal_hashrate = 1.0;
         let version_rolling_allowed = true;
@@ -739,7 +1052,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let max_target = [0xff; 32].into();
+        let max_target = Target::MAX;
         let expected_share_per_minute = 1.0;
         let nominal_hashrate = 1.0;
         let version_rolling_allowed = true;
@@ -769,7 +1082,7 @@ mod tests {
         .into();
         let n_bits = 503543726;
 
-        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime);
+        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime).unwrap();
         channel.set_chain_tip(chain_tip);
 
         let template = NewTemplate {
@@ -855,7 +1168,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let max_target = [0xff; 32].into();
+        let max_target = Target::MAX;
         let expected_share_per_minute = 1.0;
         let nominal_hashrate = 1.0;
         let version_rolling_allowed = true;
@@ -924,7 +1237,7 @@ mod tests {
         ]
         .into();
         let n_bits = 503543726;
-        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime);
+        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime).unwrap();
         let set_new_prev_hash = SetNewPrevHash {
             template_id: 1,
             prev_hash: [
@@ -961,7 +1274,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let max_target = [0xff; 32].into();
+        let max_target = Target::MAX;
         let expected_share_per_minute = 1.0;
         let nominal_hashrate = 1.0;
         let version_rolling_allowed = true;
@@ -1020,7 +1333,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let max_target = [0xff; 32].into();
+        let max_target = Target::MAX;
         let expected_share_per_minute = 1.0;
         let nominal_hashrate = 1.0;
         let version_rolling_allowed = true;
@@ -1097,7 +1410,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let max_target = [0xff; 32].into();
+        let max_target = Target::MAX;
         let expected_share_per_minute = 1.0;
         let nominal_hashrate = 1.0;
         let version_rolling_allowed = true;
@@ -1165,7 +1478,7 @@ mod tests {
         ]
         .into();
         let n_bits = 545259519;
-        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime);
+        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime).unwrap();
         channel.set_chain_tip(chain_tip);
 
         // prepare channel with non-future job
@@ -1188,7 +1501,7 @@ mod tests {
 
         let res = channel.validate_share(share_valid_block);
 
-        assert!(matches!(res, Ok(ShareValidationResult::BlockFound(_, _))));
+        assert!(matches!(res, Ok(ShareValidationResult::BlockFound(..))));
     }
 
     #[test]
@@ -1205,7 +1518,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let max_target = [0xff; 32].into();
+        let max_target = Target::MAX;
         let expected_share_per_minute = 1.0;
         let nominal_hashrate = 100.0; // bigger hashrate to get higher difficulty
         let version_rolling_allowed = true;
@@ -1273,7 +1586,7 @@ mod tests {
         ]
         .into();
         let n_bits = 453040064;
-        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime);
+        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime).unwrap();
         channel.set_chain_tip(chain_tip);
 
         // prepare channel with non-future job
@@ -1316,7 +1629,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let max_target = [0xff; 32].into();
+        let max_target = Target::MAX;
         let expected_share_per_minute = 1.0;
         let nominal_hashrate = 1_000.0; // bigger hashrate to get higher difficulty
         let version_rolling_allowed = true;
@@ -1385,7 +1698,7 @@ mod tests {
             160, 163, 128, 59, 139, 190, 158, 62, 0, 0, 0, 0, 0, 0,
         ]
         .into();
-        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime);
+        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime).unwrap();
         channel.set_chain_tip(chain_tip);
 
         // prepare channel with non-future job
@@ -1429,6 +1742,112 @@ mod tests {
         assert!(matches!(res, Err(ShareValidationError::DuplicateShare)));
     }
 
+    #[test]
+    fn test_share_validation_version_rolling() {
+        let channel_id = 1;
+        let extranonce_prefix = [
+            83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0,
+            0, 0, 0, 0, 0, 0, 1,
+        ]
+        .to_vec();
+        let rollable_extranonce_size = (MAX_EXTRANONCE_LEN - extranonce_prefix.len()) as u16;
+
+        let template = NewTemplate {
+            template_id: 1,
+            future_template: false,
+            version: 536870912,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![82, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: 4294967295,
+            coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
+            coinbase_tx_outputs_count: 1,
+            coinbase_tx_outputs: vec![
+                0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
+                222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
+                139, 235, 216, 54, 151, 78, 140, 249,
+            ]
+            .try_into()
+            .unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: vec![].try_into().unwrap(),
+        };
+        let coinbase_reward_outputs = vec![TxOut {
+            value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
+            script_pubkey: ScriptBuf::new(),
+        }];
+        let prev_hash = [
+            23, 205, 72, 134, 153, 86, 220, 153, 224, 28, 216, 146, 228, 120, 227, 157, 213, 99,
+            160, 163, 128, 59, 139, 190, 158, 62, 0, 0, 0, 0, 0, 0,
+        ]
+        .into();
+        let chain_tip = ChainTip::new(prev_hash, 453040064, 1745611105).unwrap();
+
+        let share = |sequence_number, version| SubmitSharesExtended {
+            channel_id,
+            sequence_number,
+            job_id: 1,
+            nonce: 159386,
+            ntime: 1745611105,
+            version,
+            extranonce: vec![1, 0, 0, 0, 0].try_into().unwrap(),
+        };
+
+        // SetupConnection.flags with and without REQUIRES_VERSION_ROLLING
+        for flags in [0u32, 0b10] {
+            let version_rolling_allowed = has_version_rolling(flags);
+            assert_eq!(
+                ConnectionFlags::from(flags).requires_version_rolling(),
+                version_rolling_allowed
+            );
+
+            let mut channel = ExtendedChannel::new(
+                channel_id,
+                "user_identity".to_string(),
+                extranonce_prefix.clone(),
+                Target::MAX,
+                1_000.0,
+                version_rolling_allowed,
+                rollable_extranonce_size,
+                100,
+                1.0,
+                Box::new(DefaultJobStore::new()),
+            )
+            .unwrap();
+            channel.set_chain_tip(chain_tip.clone());
+            channel
+                .on_new_template(template.clone(), coinbase_reward_outputs.clone())
+                .unwrap();
+
+            // the flag is advertised to the client on the job message
+            let job = channel.get_active_job().unwrap();
+            assert_eq!(
+                job.get_job_message().version_rolling_allowed,
+                version_rolling_allowed
+            );
+
+            // bit 13 is the lowest bit of the BIP320 mask
+            let res = channel.validate_share(share(1, 536870912 | (1 << 13)));
+            if version_rolling_allowed {
+                assert!(!matches!(
+                    res,
+                    Err(ShareValidationError::VersionRollingNotAllowed)
+                ));
+            } else {
+                assert!(matches!(
+                    res,
+                    Err(ShareValidationError::VersionRollingNotAllowed)
+                ));
+            }
+
+            // bits outside the BIP320 mask can never be rolled
+            let res = channel.validate_share(share(2, 536870912 | 1));
+            assert!(matches!(
+                res,
+                Err(ShareValidationError::VersionRollingNotAllowed)
+            ));
+        }
+    }
+
     #[test]
     fn test_update_channel() {
         let channel_id = 1;
@@ -1446,7 +1865,7 @@ mod tests {
         let job_store = Box::new(DefaultJobStore::new());
 
         // this is the most permissive possible max_target
-        let max_target: Target = [0xff; 32].into();
+        let max_target = Target::MAX;
 
         // Create a channel with initial hashrate
         let mut channel = ExtendedChannel::new(
@@ -1533,7 +1952,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let max_target = [0xff; 32].into();
+        let max_target = Target::MAX;
         let expected_share_per_minute = 1.0;
         let nominal_hashrate = 1_000.0;
         let version_rolling_allowed = true;
@@ -1584,4 +2003,267 @@ mod tests {
             Err(ExtendedChannelError::NewExtranoncePrefixTooLarge)
         ));
     }
+
+    #[test]
+    fn test_share_before_any_job() {
+        let mut channel = fixture::extended_channel(vec![0; 8], 8);
+        let share = SubmitSharesExtended {
+            channel_id: 1,
+            sequence_number: 0,
+            job_id: 1,
+            nonce: 0,
+            ntime: 1747092633,
+            version: 536870912,
+            extranonce: vec![0; 8].try_into().unwrap(),
+        };
+
+        assert_eq!(
+            channel.validate_share(share).unwrap_err(),
+            ShareValidationError::NoActiveJob
+        );
+    }
+
+    #[test]
+    fn test_share_ntime() {
+        let mut channel = fixture::extended_channel(vec![0; 8], 8);
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        assert_eq!(
+            channel.get_active_job().unwrap().activation_ntime(),
+            Some(fixture::NTIME)
+        );
+
+        let share = |ntime| SubmitSharesExtended {
+            channel_id: 1,
+            sequence_number: 0,
+            job_id: 1,
+            nonce: 0,
+            ntime,
+            version: 536870912,
+            extranonce: vec![0; 24].try_into().unwrap(),
+        };
+        assert!(matches!(
+            channel.validate_share(share(fixture::NTIME - 1)),
+            Err(ShareValidationError::InvalidNtime)
+        ));
+        for ntime in [fixture::NTIME, fixture::NTIME + 1] {
+            assert!(matches!(
+                channel.validate_share(share(ntime)),
+                Ok(_) | Err(ShareValidationError::DoesNotMeetTarget)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_template_replay() {
+        let mut channel = fixture::extended_channel(vec![0; 8], 8);
+
+        for _ in 0..2 {
+            channel
+                .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+                .unwrap();
+            channel
+                .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+                .unwrap();
+        }
+        assert_eq!(channel.get_active_job().unwrap().get_job_id(), 1);
+        assert!(channel.get_past_jobs().is_empty());
+
+        let mut mutated = fixture::template(false);
+        mutated.merkle_path = vec![[0x11; 32].into()].try_into().unwrap();
+        assert!(matches!(
+            channel.on_new_template(mutated, fixture::coinbase_reward_outputs()),
+            Err(ExtendedChannelError::TemplateIdReusedWithDifferentContent(
+                1
+            ))
+        ));
+
+        channel.set_template_replay_policy(TemplateReplayPolicy::NewJob);
+        channel
+            .on_new_template(fixture::template(false), fixture::coinbase_reward_outputs())
+            .unwrap();
+        assert_eq!(channel.get_active_job().unwrap().get_job_id(), 2);
+    }
+
+    #[test]
+    fn test_extranonce_prefix_boundaries() {
+        let new_channel = |prefix_len: usize, min_rollable_extranonce_size: u16| {
+            ExtendedChannel::new(
+                1,
+                "user_identity".to_string(),
+                vec![0xab; prefix_len],
+                Target::MAX,
+                1.0,
+                true,
+                min_rollable_extranonce_size,
+                100,
+                1.0,
+                Box::new(DefaultJobStore::new()),
+            )
+        };
+
+        // the prefix leaves exactly the requested rollable space
+        let channel = new_channel(MAX_EXTRANONCE_LEN - 8, 8).unwrap();
+        assert_eq!(channel.get_rollable_extranonce_size(), 8);
+        assert!(matches!(
+            new_channel(MAX_EXTRANONCE_LEN - 7, 8),
+            Err(ExtendedChannelError::RequestedMinExtranonceSizeTooLarge)
+        ));
+
+        // a prefix taking the whole extranonce only fits a channel that rolls nothing
+        let channel = new_channel(MAX_EXTRANONCE_LEN, 0).unwrap();
+        assert_eq!(channel.get_rollable_extranonce_size(), 0);
+        assert!(matches!(
+            new_channel(MAX_EXTRANONCE_LEN, 1),
+            Err(ExtendedChannelError::RequestedMinExtranonceSizeTooLarge)
+        ));
+        assert!(matches!(
+            new_channel(MAX_EXTRANONCE_LEN + 1, 0),
+            Err(ExtendedChannelError::NewExtranoncePrefixTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_share_extranonce_sizes() {
+        // no rollable extranonce at all, a single byte and the whole extranonce
+        for rollable_extranonce_size in [0, 1, MAX_EXTRANONCE_LEN] {
+            let extranonce_prefix = vec![0xab; MAX_EXTRANONCE_LEN - rollable_extranonce_size];
+            let mut channel = fixture::extended_channel(
+                extranonce_prefix.clone(),
+                rollable_extranonce_size as u16,
+            );
+            assert_eq!(
+                channel.get_rollable_extranonce_size() as usize,
+                rollable_extranonce_size
+            );
+
+            let merkle_path = [[0x11; 32], [0x22; 32]];
+            let mut template = fixture::template(true);
+            template.merkle_path = merkle_path
+                .iter()
+                .map(|node| (*node).into())
+                .collect::<Vec<_>>()
+                .into();
+            channel
+                .on_new_template(template, fixture::coinbase_reward_outputs())
+                .unwrap();
+            channel
+                .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+                .unwrap();
+            let job = channel.get_active_job().unwrap();
+
+            let extranonce = vec![0xcd; rollable_extranonce_size];
+            let (_, merkle_root) = share_merkle_root(job, &extranonce).unwrap();
+
+            // splice the coinbase and fold the merkle path by hand
+            let mut coinbase = job.get_coinbase_tx_prefix().inner_as_ref().to_vec();
+            coinbase.extend_from_slice(&extranonce_prefix);
+            coinbase.extend_from_slice(&extranonce);
+            coinbase.extend_from_slice(job.get_coinbase_tx_suffix().inner_as_ref());
+            let coinbase: Transaction = consensus::deserialize(&coinbase).unwrap();
+            let mut expected_merkle_root = coinbase.compute_txid().to_byte_array();
+            for node in merkle_path {
+                expected_merkle_root =
+                    sha256d::Hash::hash(&[expected_merkle_root, node].concat()).to_byte_array();
+            }
+            assert_eq!(merkle_root, expected_merkle_root);
+
+            let share = SubmitSharesExtended {
+                channel_id: 1,
+                sequence_number: 0,
+                job_id: job.get_job_id(),
+                nonce: 0,
+                ntime: fixture::NTIME,
+                version: 536870912,
+                extranonce: extranonce.try_into().unwrap(),
+            };
+            let res = (0..)
+                .map(|nonce| {
+                    channel.validate_share(SubmitSharesExtended {
+                        nonce,
+                        ..share.clone()
+                    })
+                })
+                .find(|res| !matches!(res, Err(ShareValidationError::DoesNotMeetTarget)))
+                .unwrap();
+            assert!(matches!(
+                res,
+                Ok(ShareValidationResult::Valid) | Ok(ShareValidationResult::BlockFound(..))
+            ));
+
+            // an extranonce not filling the rollable space exactly is invalid
+            let wrong_size = (rollable_extranonce_size + 1) % (MAX_EXTRANONCE_LEN + 1);
+            let share = SubmitSharesExtended {
+                extranonce: vec![0xcd; wrong_size].try_into().unwrap(),
+                ..share
+            };
+            assert!(matches!(
+                channel.validate_share(share),
+                Err(ShareValidationError::Invalid)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_share_extranonce_prefix() {
+        let extranonce_prefix = vec![0xab; MAX_EXTRANONCE_LEN - 8];
+        let mut channel = fixture::extended_channel(extranonce_prefix.clone(), 8);
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        let share = |nonce, extranonce: Vec<u8>| SubmitSharesExtended {
+            channel_id: 1,
+            sequence_number: 0,
+            job_id: 1,
+            nonce,
+            ntime: fixture::NTIME,
+            version: 536870912,
+            extranonce: extranonce.try_into().unwrap(),
+        };
+        let mut full_extranonce = extranonce_prefix;
+        full_extranonce.extend_from_slice(&[0xcd; 8]);
+
+        // the full extranonce with the prefix of the channel is the same share as its rolled part
+        let (nonce, res) = (0..)
+            .map(|nonce| {
+                let res = channel.validate_share(share(nonce, full_extranonce.clone()));
+                (nonce, res)
+            })
+            .find(|(_, res)| !matches!(res, Err(ShareValidationError::DoesNotMeetTarget)))
+            .unwrap();
+        assert!(matches!(
+            res,
+            Ok(ShareValidationResult::Valid) | Ok(ShareValidationResult::BlockFound(..))
+        ));
+        assert!(matches!(
+            channel.validate_share(share(nonce, vec![0xcd; 8])),
+            Err(ShareValidationError::DuplicateShare)
+        ));
+
+        // the prefix of another channel, one byte off
+        let mut poaching_extranonce = full_extranonce.clone();
+        poaching_extranonce[0] ^= 1;
+        let res = channel.validate_share(share(0, poaching_extranonce));
+        assert_eq!(
+            res.unwrap_err(),
+            ShareValidationError::ExtranoncePrefixMismatch
+        );
+        assert_eq!(
+            ShareValidationError::ExtranoncePrefixMismatch.error_code(),
+            "invalid-extranonce-prefix"
+        );
+
+        // a truncated full extranonce
+        assert!(matches!(
+            channel.validate_share(share(0, full_extranonce[..31].to_vec())),
+            Err(ShareValidationError::Invalid)
+        ));
+    }
 }
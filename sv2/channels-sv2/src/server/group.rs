@@ -1,15 +1,35 @@
 //! Abstraction over the state of a Sv2 Group Channel, as seen by a Mining Server
 use crate::{
     chain_tip::ChainTip,
+    collections::{HashMap, HashSet},
     server::{
         error::GroupChannelError,
         jobs::{extended::ExtendedJob, factory::JobFactory, job_store::JobStore},
     },
 };
+#[cfg(feature = "std")]
+use crate::{
+    clock::{Clock, SystemClock},
+    server::share_validation::ShareValidationResult,
+};
+#[cfg(feature = "std")]
+use alloc::sync::Arc;
+use alloc::{boxed::Box, vec::Vec};
 use bitcoin::transaction::TxOut;
+#[cfg(feature = "std")]
+use mining_sv2::SubmitSharesSuccess;
+#[cfg(feature = "std")]
+use std::time::Duration;
 use template_distribution_sv2::{NewTemplate, SetNewPrevHash as SetNewPrevHashTdp};
 
-use std::collections::{HashMap, HashSet};
+// The acknowledgement of a member channel, waiting for the next flush.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct PendingAcknowledgement {
+    success: SubmitSharesSuccess,
+    // milliseconds since the Unix epoch, as told by the clock of the group channel
+    queued_at: u64,
+}
 
 /// Abstraction of a Group Channel.
 ///
@@ -21,6 +41,8 @@ use std::collections::{HashMap, HashSet};
 ///   `SetNewPrevHash` message)
 /// - the group channel's active job
 /// - the group channel's chain tip
+/// - the acknowledgements of its standard channels waiting to be flushed together, see
+///   [`GroupChannel::poll_acknowledgements`]
 ///
 /// Since share validation happens at the Standard Channel level, we don't really keep track of:
 /// - the group channel's past jobs
@@ -33,6 +55,13 @@ pub struct GroupChannel<'a> {
     job_factory: JobFactory,
     job_store: Box<dyn JobStore<ExtendedJob<'a>>>,
     chain_tip: Option<ChainTip>,
+    #[cfg(feature = "std")]
+    acknowledgement_interval: Duration,
+    #[cfg(feature = "std")]
+    clock: Arc<dyn Clock>,
+    // at most one per standard channel, in the order they were first queued
+    #[cfg(feature = "std")]
+    pending_acknowledgements: Vec<PendingAcknowledgement>,
 }
 
 impl<'a> GroupChannel<'a> {
@@ -43,6 +72,12 @@ impl<'a> GroupChannel<'a> {
             job_factory: JobFactory::new(true),
             job_store,
             chain_tip: None,
+            #[cfg(feature = "std")]
+            acknowledgement_interval: Duration::ZERO,
+            #[cfg(feature = "std")]
+            clock: Arc::new(SystemClock),
+            #[cfg(feature = "std")]
+            pending_acknowledgements: Vec::new(),
         }
     }
 
@@ -50,8 +85,12 @@ impl<'a> GroupChannel<'a> {
         self.standard_channel_ids.insert(standard_channel_id);
     }
 
+    /// Removes a standard channel from the group, dropping its pending acknowledgement.
     pub fn remove_standard_channel_id(&mut self, standard_channel_id: u32) {
         self.standard_channel_ids.remove(&standard_channel_id);
+        #[cfg(feature = "std")]
+        self.pending_acknowledgements
+            .retain(|pending| pending.success.channel_id != standard_channel_id);
     }
 
     pub fn get_group_channel_id(&self) -> u32 {
@@ -76,6 +115,105 @@ impl<'a> GroupChannel<'a> {
         self.job_store.get_active_job()
     }
 
+    /// How long the first acknowledgement queued since the last flush waits for the ones of
+    /// other standard channels, see [`GroupChannel::poll_acknowledgements`].
+    ///
+    /// Defaults to zero, i.e. acknowledgements are flushed on the next poll.
+    #[cfg(feature = "std")]
+    pub fn set_acknowledgement_interval(&mut self, acknowledgement_interval: Duration) {
+        self.acknowledgement_interval = acknowledgement_interval;
+    }
+
+    #[cfg(feature = "std")]
+    pub fn get_acknowledgement_interval(&self) -> Duration {
+        self.acknowledgement_interval
+    }
+
+    /// Sets the [`Clock`] the acknowledgement interval is measured with, replacing the
+    /// [`SystemClock`].
+    #[cfg(feature = "std")]
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Queues the acknowledgement carried by the result of a share validated on the standard
+    /// channel `channel_id`, to be flushed by [`GroupChannel::poll_acknowledgements`].
+    ///
+    /// If the channel already has an acknowledgement pending, both are merged into a single
+    /// `SubmitShares.Success`, acknowledging the shares of both up to the latest sequence number.
+    ///
+    /// Returns `false` if `result` carries no acknowledgement, or if the channel is not a member
+    /// of the group.
+    #[cfg(feature = "std")]
+    pub fn queue_acknowledgement(
+        &mut self,
+        channel_id: u32,
+        result: &ShareValidationResult,
+    ) -> bool {
+        let (last_sequence_number, new_submits_accepted_count, new_shares_sum) = match result {
+            ShareValidationResult::ValidWithAcknowledgement(
+                last_sequence_number,
+                new_submits_accepted_count,
+                new_shares_sum,
+            ) => (
+                *last_sequence_number,
+                *new_submits_accepted_count,
+                *new_shares_sum,
+            ),
+            _ => return false,
+        };
+        if !self.standard_channel_ids.contains(&channel_id) {
+            return false;
+        }
+        match self
+            .pending_acknowledgements
+            .iter_mut()
+            .find(|pending| pending.success.channel_id == channel_id)
+        {
+            Some(pending) => {
+                let success = &mut pending.success;
+                success.last_sequence_number = last_sequence_number;
+                success.new_submits_accepted_count = success
+                    .new_submits_accepted_count
+                    .saturating_add(new_submits_accepted_count);
+                success.new_shares_sum = success.new_shares_sum.saturating_add(new_shares_sum);
+            }
+            None => self.pending_acknowledgements.push(PendingAcknowledgement {
+                success: SubmitSharesSuccess {
+                    channel_id,
+                    last_sequence_number,
+                    new_submits_accepted_count,
+                    new_shares_sum,
+                },
+                queued_at: self.clock.now_millis(),
+            }),
+        }
+        true
+    }
+
+    /// Drains the pending acknowledgements of the standard channels, once the oldest one has
+    /// been waiting for the acknowledgement interval, so that they can be written downstream in
+    /// a single burst.
+    ///
+    /// Returns one `SubmitShares.Success` per channel, along with its `channel_id`, in the order
+    /// the channels crossed their batch thresholds. Returns nothing while the interval has not
+    /// elapsed.
+    #[cfg(feature = "std")]
+    pub fn poll_acknowledgements(&mut self) -> Vec<(u32, SubmitSharesSuccess)> {
+        let oldest = match self.pending_acknowledgements.first() {
+            Some(pending) => pending.queued_at,
+            None => return Vec::new(),
+        };
+        let waited = self.clock.now_millis().saturating_sub(oldest);
+        if u128::from(waited) < self.acknowledgement_interval.as_millis() {
+            return Vec::new();
+        }
+        self.pending_acknowledgements
+            .drain(..)
+            .map(|pending| (pending.success.channel_id, pending.success))
+            .collect()
+    }
+
     pub fn get_future_template_to_job_id(&self) -> &HashMap<u64, u32> {
         self.job_store.get_future_template_to_job_id()
     }
@@ -107,7 +245,9 @@ impl<'a> GroupChannel<'a> {
                         coinbase_reward_outputs,
                     )
                     .map_err(GroupChannelError::JobFactoryError)?;
-                self.job_store.add_future_job(template.template_id, new_job);
+                self.job_store
+                    .add_future_job(template.template_id, new_job)
+                    .map_err(GroupChannelError::JobStoreError)?;
             }
             false => {
                 match self.chain_tip.clone() {
@@ -145,6 +285,14 @@ impl<'a> GroupChannel<'a> {
         &mut self,
         set_new_prev_hash: SetNewPrevHashTdp<'a>,
     ) -> Result<(), GroupChannelError> {
+        let set_new_prev_hash = set_new_prev_hash.into_static();
+        let new_chain_tip = ChainTip::new(
+            set_new_prev_hash.prev_hash,
+            set_new_prev_hash.n_bits,
+            set_new_prev_hash.header_timestamp,
+        )
+        .map_err(GroupChannelError::InvalidPrevHash)?;
+
         match self.job_store.get_future_jobs().is_empty() {
             true => {
                 return Err(GroupChannelError::TemplateIdNotFound);
@@ -158,12 +306,6 @@ impl<'a> GroupChannel<'a> {
         }
 
         // update the chain tip
-        let set_new_prev_hash_static = set_new_prev_hash.into_static();
-        let new_chain_tip = ChainTip::new(
-            set_new_prev_hash_static.prev_hash,
-            set_new_prev_hash_static.n_bits,
-            set_new_prev_hash_static.header_timestamp,
-        );
         self.chain_tip = Some(new_chain_tip);
 
         Ok(())
@@ -329,7 +471,7 @@ mod tests {
         .into();
         let n_bits = 503543726;
 
-        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime);
+        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime).unwrap();
         let template = NewTemplate {
             template_id: 1,
             future_template: false,
@@ -453,4 +595,133 @@ mod tests {
 
         assert!(group_channel.get_future_jobs().is_empty());
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_poll_acknowledgements() {
+        use crate::{
+            server::{
+                share_validation::ShareValidationResult,
+                standard::{StandardChannel, StandardChannelConfig},
+            },
+            testing::{clock::TestClock, fixture},
+        };
+        use alloc::sync::Arc;
+        use mining_sv2::Target;
+        use std::time::Duration;
+
+        // milliseconds since the start of the test
+        let clock = TestClock::new(0);
+        let mut group_channel = GroupChannel::new(1, Box::new(DefaultJobStore::new()));
+        group_channel.set_acknowledgement_interval(Duration::from_millis(100));
+        group_channel.set_clock(Arc::new(clock.clone()));
+        // every other share is acknowledged
+        let mut channels: Vec<StandardChannel> = (1..=3)
+            .map(|channel_id| {
+                let mut extranonce_prefix = fixture::extranonce_prefix();
+                extranonce_prefix[0] = channel_id as u8;
+                let mut channel = StandardChannel::from_config(
+                    StandardChannelConfig::default()
+                        .channel_id(channel_id)
+                        .user_identity("user_identity")
+                        .extranonce_prefix(extranonce_prefix)
+                        .requested_max_target(Target::MAX)
+                        .nominal_hashrate(1.0)
+                        .expected_share_per_minute(1.0)
+                        .share_batch_size(2),
+                    Box::new(DefaultJobStore::new()),
+                )
+                .unwrap();
+                // any share is accepted
+                channel.set_target(Target::MAX);
+                channel
+                    .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+                    .unwrap();
+                channel
+                    .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+                    .unwrap();
+                group_channel.add_standard_channel_id(channel_id);
+                channel
+            })
+            .collect();
+
+        // submits 2 shares to `channel`, the second one being acknowledged
+        let mut sequence_number = 0;
+        let mut cross_threshold =
+            |group_channel: &mut GroupChannel, channel: &mut StandardChannel| {
+                for _ in 0..2 {
+                    sequence_number += 1;
+                    let mut share = fixture::submit_shares_standard(
+                        channel.get_channel_id(),
+                        1,
+                        sequence_number,
+                    );
+                    share.sequence_number = sequence_number;
+                    let result = channel.validate_share(share).unwrap();
+                    assert_eq!(
+                        group_channel.queue_acknowledgement(channel.get_channel_id(), &result),
+                        matches!(result, ShareValidationResult::ValidWithAcknowledgement(..))
+                    );
+                }
+                sequence_number
+            };
+
+        assert!(group_channel.poll_acknowledgements().is_empty());
+
+        // channels 1 and 2 cross their thresholds within the interval, channel 1 twice
+        let first = cross_threshold(&mut group_channel, &mut channels[0]);
+        clock.set(50);
+        cross_threshold(&mut group_channel, &mut channels[1]);
+        assert!(group_channel.poll_acknowledgements().is_empty());
+        clock.set(60);
+        let latest = cross_threshold(&mut group_channel, &mut channels[0]);
+        clock.set(99);
+        assert!(group_channel.poll_acknowledgements().is_empty());
+
+        clock.set(100);
+        let flushed = group_channel.poll_acknowledgements();
+        assert_eq!(
+            flushed.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        let (_, success) = &flushed[0];
+        assert_eq!(success.channel_id, 1);
+        assert_eq!(success.last_sequence_number, latest);
+        assert_ne!(success.last_sequence_number, first);
+        assert_eq!(success.new_submits_accepted_count, 4);
+        assert_eq!(
+            success.new_shares_sum,
+            channels[0].get_share_accounting().get_share_work_sum()
+        );
+        let (_, success) = &flushed[1];
+        assert_eq!(success.new_submits_accepted_count, 2);
+        assert_eq!(
+            success.new_shares_sum,
+            channels[1].get_share_accounting().get_share_work_sum()
+        );
+        clock.set(150);
+        assert!(group_channel.poll_acknowledgements().is_empty());
+
+        // channel 3 crosses its threshold later on, and gets flushed on its own
+        cross_threshold(&mut group_channel, &mut channels[2]);
+        clock.set(200);
+        assert!(group_channel.poll_acknowledgements().is_empty());
+        clock.set(250);
+        let flushed = group_channel.poll_acknowledgements();
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(flushed[0].0, 3);
+        assert_eq!(flushed[0].1.new_submits_accepted_count, 2);
+
+        // acknowledgements of channels that left the group are dropped, or never queued
+        clock.set(300);
+        cross_threshold(&mut group_channel, &mut channels[2]);
+        group_channel.remove_standard_channel_id(3);
+        clock.set(400);
+        assert!(group_channel.poll_acknowledgements().is_empty());
+        let result = ShareValidationResult::ValidWithAcknowledgement(1, 2, 3);
+        assert!(!group_channel.queue_acknowledgement(3, &result));
+        assert!(!group_channel.queue_acknowledgement(1, &ShareValidationResult::Valid));
+        clock.set(500);
+        assert!(group_channel.poll_acknowledgements().is_empty());
+    }
 }
@@ -0,0 +1,90 @@
+//! Pluggable double SHA256 of block headers, the hot path of share validation.
+//!
+//! Server channels hash the header of every share with a [`HeaderHasher`], which defaults to
+//! [`DefaultHeaderHasher`]. Pools validating lots of shares can swap it for a hardware
+//! accelerated implementation (e.g. SHA-NI or an FPGA offload) via `set_header_hasher` on the
+//! [`StandardChannel`](crate::server::standard::StandardChannel) or
+//! [`ExtendedChannel`](crate::server::extended::ExtendedChannel).
+use bitcoin::{
+    block::Header,
+    hashes::{sha256d, Hash},
+    BlockHash,
+};
+use core::fmt::Debug;
+
+/// Size in bytes of a consensus encoded block header.
+pub const HEADER_SIZE: usize = 80;
+
+/// Computes the hash of block headers.
+pub trait HeaderHasher: Send + Sync + Debug {
+    /// Returns the double SHA256 of the consensus encoded `header`, in the byte order of
+    /// [`BlockHash::to_byte_array`].
+    fn hash_header(&self, header: &[u8; HEADER_SIZE]) -> [u8; 32];
+}
+
+/// The [`HeaderHasher`] of `bitcoin_hashes`, which `Header::block_hash` goes through as well.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultHeaderHasher;
+
+impl HeaderHasher for DefaultHeaderHasher {
+    fn hash_header(&self, header: &[u8; HEADER_SIZE]) -> [u8; 32] {
+        sha256d::Hash::hash(header).to_byte_array()
+    }
+}
+
+/// Consensus encodes `header`, without allocating.
+pub fn serialize_header(header: &Header) -> [u8; HEADER_SIZE] {
+    let mut bytes = [0; HEADER_SIZE];
+    bytes[0..4].copy_from_slice(&header.version.to_consensus().to_le_bytes());
+    bytes[4..36].copy_from_slice(header.prev_blockhash.as_byte_array());
+    bytes[36..68].copy_from_slice(header.merkle_root.as_byte_array());
+    bytes[68..72].copy_from_slice(&header.time.to_le_bytes());
+    bytes[72..76].copy_from_slice(&header.bits.to_consensus().to_le_bytes());
+    bytes[76..80].copy_from_slice(&header.nonce.to_le_bytes());
+    bytes
+}
+
+// `header.block_hash()`, through `hasher`
+pub(crate) fn block_hash(hasher: &dyn HeaderHasher, header: &Header) -> BlockHash {
+    BlockHash::from_byte_array(hasher.hash_header(&serialize_header(header)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{
+        blockdata::{block::Version, constants::genesis_block},
+        consensus::serialize,
+        CompactTarget, Network, TxMerkleNode,
+    };
+
+    #[test]
+    fn test_default_header_hasher() {
+        let mut headers: Vec<Header> = [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ]
+        .iter()
+        .map(|network| genesis_block(*network).header)
+        .collect();
+        // every field set, with the sign bit of the version
+        headers.push(Header {
+            version: Version::from_consensus(-0x1fffe000),
+            prev_blockhash: BlockHash::from_byte_array([0xab; 32]),
+            merkle_root: TxMerkleNode::from_byte_array([0xcd; 32]),
+            time: 1745596932,
+            bits: CompactTarget::from_consensus(545259519),
+            nonce: u32::MAX,
+        });
+
+        for header in headers {
+            assert_eq!(serialize_header(&header)[..], serialize(&header)[..]);
+            assert_eq!(
+                block_hash(&DefaultHeaderHasher, &header),
+                header.block_hash()
+            );
+        }
+    }
+}
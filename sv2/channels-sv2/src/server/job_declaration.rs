@@ -0,0 +1,463 @@
+//! Abstraction for validating jobs declared via the Job Declaration Protocol.
+//!
+//! A Job Declaration Server must validate every `DeclareMiningJob` before the declaring client is
+//! allowed to mine on it via `SetCustomMiningJob`.
+use crate::{
+    collections::{HashMap, HashSet},
+    server::error::JobDeclarationError,
+};
+use alloc::{boxed::Box, string::ToString, vec::Vec};
+use binary_sv2::U256;
+use bitcoin::{
+    consensus::{deserialize, serialize},
+    transaction::Transaction,
+    Amount,
+};
+use core::{convert::TryInto, fmt::Debug};
+use job_declaration_sv2::{DeclareMiningJob, DeclareMiningJobError, DeclareMiningJobSuccess};
+use mining_sv2::SetCustomMiningJob;
+
+/// Verifies the transactions of a declared job against the mempool.
+///
+/// Meant to be implemented by the embedding application, which owns the mempool snapshot.
+pub trait MempoolVerifier: Send + Sync + Debug {
+    /// Returns the positions (on `tx_ids`) of the transactions that are not known.
+    fn unknown_tx_positions(&self, tx_ids: &[U256<'static>]) -> Vec<u16>;
+}
+
+/// The outcome of the validation of a `DeclareMiningJob` message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeclareMiningJobDecision {
+    Success(DeclareMiningJobSuccess<'static>),
+    Error(DeclareMiningJobError<'static>),
+}
+
+/// A job that was successfully declared, indexed by its `mining_job_token`.
+#[derive(Debug, Clone)]
+pub struct DeclaredJob {
+    declare_mining_job: DeclareMiningJob<'static>,
+    coinbase: Transaction,
+}
+
+impl DeclaredJob {
+    pub fn get_declare_mining_job(&self) -> &DeclareMiningJob<'static> {
+        &self.declare_mining_job
+    }
+
+    pub fn get_mining_job_token(&self) -> &[u8] {
+        self.declare_mining_job.mining_job_token.inner_as_ref()
+    }
+
+    /// Returns the declared coinbase, with the extranonce bytes zeroed.
+    pub fn get_coinbase(&self) -> &Transaction {
+        &self.coinbase
+    }
+
+    /// Checks whether a `SetCustomMiningJob` commits to the same coinbase and version as this
+    /// declared job.
+    pub fn matches_custom_job(&self, set_custom_mining_job: &SetCustomMiningJob<'_>) -> bool {
+        let input = &self.coinbase.input[0];
+        set_custom_mining_job.token.inner_as_ref() == self.get_mining_job_token()
+            && set_custom_mining_job.version == self.declare_mining_job.version
+            && set_custom_mining_job.coinbase_tx_version == self.coinbase.version.0 as u32
+            && set_custom_mining_job.coinbase_tx_locktime
+                == self.coinbase.lock_time.to_consensus_u32()
+            && set_custom_mining_job.coinbase_tx_input_n_sequence == input.sequence.0
+            && input
+                .script_sig
+                .as_bytes()
+                .starts_with(set_custom_mining_job.coinbase_prefix.inner_as_ref())
+            && set_custom_mining_job.coinbase_tx_outputs.inner_as_ref()
+                == serialize(&self.coinbase.output).as_slice()
+    }
+}
+
+/// Validates `DeclareMiningJob` messages and keeps track of the resulting declared jobs.
+///
+/// Only tokens previously allocated via [`DeclaredJobValidator::add_mining_job_token`] are
+/// accepted, and each token can only be used for one declaration.
+#[derive(Debug)]
+pub struct DeclaredJobValidator {
+    // the size of the extranonce between coinbase_prefix and coinbase_suffix
+    full_extranonce_size: usize,
+    mempool_verifier: Box<dyn MempoolVerifier>,
+    allocated_tokens: HashSet<Vec<u8>>,
+    declared_jobs: HashMap<Vec<u8>, DeclaredJob>,
+}
+
+impl DeclaredJobValidator {
+    pub fn new(full_extranonce_size: usize, mempool_verifier: Box<dyn MempoolVerifier>) -> Self {
+        Self {
+            full_extranonce_size,
+            mempool_verifier,
+            allocated_tokens: HashSet::new(),
+            declared_jobs: HashMap::new(),
+        }
+    }
+
+    /// Registers a token sent on an `AllocateMiningJobToken.Success` message.
+    pub fn add_mining_job_token(&mut self, mining_job_token: Vec<u8>) {
+        self.allocated_tokens.insert(mining_job_token);
+    }
+
+    pub fn get_declared_job(&self, mining_job_token: &[u8]) -> Option<&DeclaredJob> {
+        self.declared_jobs.get(mining_job_token)
+    }
+
+    pub fn remove_declared_job(&mut self, mining_job_token: &[u8]) -> Option<DeclaredJob> {
+        self.declared_jobs.remove(mining_job_token)
+    }
+
+    /// Validates a `DeclareMiningJob` message.
+    ///
+    /// Checks that:
+    /// - the `mining_job_token` was allocated and not used yet
+    /// - `coinbase_prefix` and `coinbase_suffix` form a valid coinbase transaction
+    /// - the coinbase outputs do not exceed `coinbase_tx_value_remaining`
+    /// - all transactions in `tx_ids_list` are known to the [`MempoolVerifier`]
+    ///
+    /// On success, the job is stored and can later be retrieved by its `mining_job_token`.
+    pub fn validate(
+        &mut self,
+        declare_mining_job: DeclareMiningJob<'_>,
+        coinbase_tx_value_remaining: u64,
+    ) -> DeclareMiningJobDecision {
+        let declare_mining_job = declare_mining_job.into_static();
+        let request_id = declare_mining_job.request_id;
+
+        match self.check(&declare_mining_job, coinbase_tx_value_remaining) {
+            Ok(coinbase) => {
+                let mining_job_token = declare_mining_job.mining_job_token.inner_as_ref().to_vec();
+                self.allocated_tokens.remove(&mining_job_token);
+
+                let new_mining_job_token = declare_mining_job.mining_job_token.clone();
+                self.declared_jobs.insert(
+                    mining_job_token,
+                    DeclaredJob {
+                        declare_mining_job,
+                        coinbase,
+                    },
+                );
+
+                DeclareMiningJobDecision::Success(DeclareMiningJobSuccess {
+                    request_id,
+                    new_mining_job_token,
+                })
+            }
+            Err(e) => DeclareMiningJobDecision::Error(DeclareMiningJobError {
+                request_id,
+                error_code: e
+                    .error_code()
+                    .to_string()
+                    .try_into()
+                    .expect("error code must fit into Str0255"),
+                error_details: Vec::new()
+                    .try_into()
+                    .expect("empty vec must fit into B064K"),
+            }),
+        }
+    }
+}
+
+// impl block with private methods
+impl DeclaredJobValidator {
+    fn check(
+        &self,
+        declare_mining_job: &DeclareMiningJob<'static>,
+        coinbase_tx_value_remaining: u64,
+    ) -> Result<Transaction, JobDeclarationError> {
+        if !self
+            .allocated_tokens
+            .contains(declare_mining_job.mining_job_token.inner_as_ref())
+        {
+            return Err(JobDeclarationError::InvalidMiningJobToken);
+        }
+
+        let coinbase = self.coinbase(declare_mining_job)?;
+
+        let mut coinbase_outputs_sum = Amount::from_sat(0);
+        for output in coinbase.output.iter() {
+            coinbase_outputs_sum = coinbase_outputs_sum
+                .checked_add(output.value)
+                .ok_or(JobDeclarationError::CoinbaseOutputsSumOverflow)?;
+        }
+        if coinbase_outputs_sum.to_sat() > coinbase_tx_value_remaining {
+            return Err(JobDeclarationError::InvalidCoinbaseOutputsSum);
+        }
+
+        let tx_ids = declare_mining_job.tx_ids_list.clone().into_inner();
+        let unknown_tx_positions = self.mempool_verifier.unknown_tx_positions(&tx_ids);
+        if !unknown_tx_positions.is_empty() {
+            return Err(JobDeclarationError::UnknownTransactions(
+                unknown_tx_positions,
+            ));
+        }
+
+        Ok(coinbase)
+    }
+
+    // rebuild the coinbase from its prefix and suffix, with the extranonce bytes zeroed
+    fn coinbase(
+        &self,
+        declare_mining_job: &DeclareMiningJob<'static>,
+    ) -> Result<Transaction, JobDeclarationError> {
+        let mut serialized_coinbase = declare_mining_job.coinbase_prefix.inner_as_ref().to_vec();
+        serialized_coinbase.extend_from_slice(&vec![0; self.full_extranonce_size]);
+        serialized_coinbase.extend_from_slice(declare_mining_job.coinbase_suffix.inner_as_ref());
+
+        let coinbase: Transaction =
+            deserialize(&serialized_coinbase).map_err(|_| JobDeclarationError::InvalidCoinbase)?;
+
+        // the coinbase must have a single null input, and its script_sig must be between 2 and
+        // 100 bytes (consensus rule)
+        let script_sig_len = coinbase
+            .input
+            .first()
+            .map(|input| input.script_sig.len())
+            .unwrap_or(0);
+        if !coinbase.is_coinbase() || !(2..=100).contains(&script_sig_len) {
+            return Err(JobDeclarationError::InvalidCoinbase);
+        }
+
+        Ok(coinbase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binary_sv2::Seq064K;
+    use bitcoin::{
+        absolute::LockTime,
+        blockdata::witness::Witness,
+        transaction::{OutPoint, TxIn, TxOut, Version},
+        ScriptBuf, Sequence,
+    };
+    use mining_sv2::MAX_EXTRANONCE_LEN;
+
+    const SATS_AVAILABLE_IN_TEMPLATE: u64 = 5000000000;
+
+    #[derive(Debug)]
+    struct TestMempool {
+        known_tx_ids: Vec<[u8; 32]>,
+    }
+
+    impl MempoolVerifier for TestMempool {
+        fn unknown_tx_positions(&self, tx_ids: &[U256<'static>]) -> Vec<u16> {
+            tx_ids
+                .iter()
+                .enumerate()
+                .filter(|(_, tx_id)| {
+                    !self
+                        .known_tx_ids
+                        .iter()
+                        .any(|known| known.as_slice() == tx_id.inner_as_ref())
+                })
+                .map(|(i, _)| i as u16)
+                .collect()
+        }
+    }
+
+    fn validator() -> DeclaredJobValidator {
+        let mut validator = DeclaredJobValidator::new(
+            MAX_EXTRANONCE_LEN,
+            Box::new(TestMempool {
+                known_tx_ids: vec![[1; 32]],
+            }),
+        );
+        validator.add_mining_job_token(vec![1, 2, 3]);
+        validator
+    }
+
+    fn new_coinbase(script_sig_prefix: Vec<u8>, value: u64) -> Transaction {
+        let mut script_sig = script_sig_prefix;
+        script_sig.extend_from_slice(&[0; MAX_EXTRANONCE_LEN]);
+
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::from_consensus(158),
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: script_sig.into(),
+                sequence: Sequence(4294967294),
+                witness: Witness::from(vec![vec![0; 32]]),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::from(vec![0; 22]),
+            }],
+        }
+    }
+
+    // split a coinbase around the extranonce, the same way JobFactory does
+    fn new_declare_mining_job(
+        coinbase: Transaction,
+        tx_ids: Vec<[u8; 32]>,
+    ) -> DeclareMiningJob<'static> {
+        let serialized_coinbase = serialize(&coinbase);
+        let script_sig_prefix_len = coinbase.input[0].script_sig.len() - MAX_EXTRANONCE_LEN;
+
+        let index = 4 // tx version
+            + 2 // segwit bytes
+            + 1 // number of inputs
+            + 32 // prev OutPoint
+            + 4 // index
+            + 1 // bytes in script
+            + script_sig_prefix_len;
+
+        let tx_ids: Vec<U256<'static>> = tx_ids.into_iter().map(|tx_id| tx_id.into()).collect();
+
+        DeclareMiningJob {
+            request_id: 7,
+            mining_job_token: vec![1, 2, 3].try_into().unwrap(),
+            version: 536870912,
+            coinbase_prefix: serialized_coinbase[..index].to_vec().try_into().unwrap(),
+            coinbase_suffix: serialized_coinbase[index + MAX_EXTRANONCE_LEN..]
+                .to_vec()
+                .try_into()
+                .unwrap(),
+            tx_ids_list: Seq064K::new(tx_ids).unwrap(),
+            excess_data: vec![].try_into().unwrap(),
+        }
+    }
+
+    fn error_code(decision: DeclareMiningJobDecision) -> String {
+        match decision {
+            DeclareMiningJobDecision::Error(e) => e.error_code.as_utf8_or_hex(),
+            DeclareMiningJobDecision::Success(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn test_valid_declaration() {
+        let mut validator = validator();
+        let coinbase = new_coinbase(vec![2, 159, 0, 0], SATS_AVAILABLE_IN_TEMPLATE);
+        let declare_mining_job = new_declare_mining_job(coinbase.clone(), vec![[1; 32]]);
+
+        let decision = validator.validate(declare_mining_job.clone(), SATS_AVAILABLE_IN_TEMPLATE);
+        assert_eq!(
+            decision,
+            DeclareMiningJobDecision::Success(DeclareMiningJobSuccess {
+                request_id: 7,
+                new_mining_job_token: vec![1, 2, 3].try_into().unwrap(),
+            })
+        );
+
+        let declared_job = validator.get_declared_job(&[1, 2, 3]).unwrap();
+        assert_eq!(declared_job.get_declare_mining_job(), &declare_mining_job);
+        assert_eq!(declared_job.get_coinbase(), &coinbase);
+
+        // the token can't be used twice
+        let decision = validator.validate(declare_mining_job, SATS_AVAILABLE_IN_TEMPLATE);
+        assert_eq!(error_code(decision), "invalid-mining-job-token");
+    }
+
+    #[test]
+    fn test_declared_job_matches_custom_job() {
+        let mut validator = validator();
+        let coinbase = new_coinbase(vec![2, 159, 0, 0], SATS_AVAILABLE_IN_TEMPLATE);
+        let declare_mining_job = new_declare_mining_job(coinbase.clone(), vec![]);
+        validator.validate(declare_mining_job, SATS_AVAILABLE_IN_TEMPLATE);
+
+        let mut set_custom_mining_job = SetCustomMiningJob {
+            channel_id: 1,
+            request_id: 0,
+            token: vec![1, 2, 3].try_into().unwrap(),
+            version: 536870912,
+            prev_hash: [0; 32].into(),
+            min_ntime: 1745596910,
+            nbits: 545259519,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
+            coinbase_tx_input_n_sequence: 4294967294,
+            coinbase_tx_outputs: serialize(&coinbase.output).try_into().unwrap(),
+            coinbase_tx_locktime: 158,
+            merkle_path: vec![].try_into().unwrap(),
+        };
+
+        let declared_job = validator.get_declared_job(&[1, 2, 3]).unwrap();
+        assert!(declared_job.matches_custom_job(&set_custom_mining_job));
+
+        set_custom_mining_job.coinbase_tx_locktime = 159;
+        assert!(!declared_job.matches_custom_job(&set_custom_mining_job));
+    }
+
+    #[test]
+    fn test_unknown_mining_job_token() {
+        let mut validator = validator();
+        let mut declare_mining_job = new_declare_mining_job(
+            new_coinbase(vec![2, 159, 0, 0], SATS_AVAILABLE_IN_TEMPLATE),
+            vec![],
+        );
+        declare_mining_job.mining_job_token = vec![4, 5, 6].try_into().unwrap();
+
+        let decision = validator.validate(declare_mining_job, SATS_AVAILABLE_IN_TEMPLATE);
+        assert_eq!(error_code(decision), "invalid-mining-job-token");
+    }
+
+    #[test]
+    fn test_malformed_coinbase() {
+        let mut validator = validator();
+        let mut declare_mining_job = new_declare_mining_job(
+            new_coinbase(vec![2, 159, 0, 0], SATS_AVAILABLE_IN_TEMPLATE),
+            vec![],
+        );
+        // truncate the suffix so the coinbase can't be deserialized
+        let suffix = declare_mining_job.coinbase_suffix.inner_as_ref().to_vec();
+        declare_mining_job.coinbase_suffix =
+            suffix[..suffix.len() - 1].to_vec().try_into().unwrap();
+
+        let decision = validator.validate(declare_mining_job, SATS_AVAILABLE_IN_TEMPLATE);
+        assert_eq!(
+            error_code(decision),
+            "invalid-job-param-value-coinbase-prefix"
+        );
+
+        // a transaction spending a real outpoint is not a coinbase
+        let mut not_coinbase = new_coinbase(vec![2, 159, 0, 0], SATS_AVAILABLE_IN_TEMPLATE);
+        not_coinbase.input[0].previous_output.vout = 0;
+        let decision = validator.validate(
+            new_declare_mining_job(not_coinbase, vec![]),
+            SATS_AVAILABLE_IN_TEMPLATE,
+        );
+        assert_eq!(
+            error_code(decision),
+            "invalid-job-param-value-coinbase-prefix"
+        );
+    }
+
+    #[test]
+    fn test_coinbase_exceeds_value_remaining() {
+        let mut validator = validator();
+        let declare_mining_job = new_declare_mining_job(
+            new_coinbase(vec![2, 159, 0, 0], SATS_AVAILABLE_IN_TEMPLATE + 1),
+            vec![],
+        );
+
+        let decision = validator.validate(declare_mining_job, SATS_AVAILABLE_IN_TEMPLATE);
+        assert_eq!(
+            error_code(decision),
+            "invalid-job-param-value-coinbase-suffix"
+        );
+        assert!(validator.get_declared_job(&[1, 2, 3]).is_none());
+    }
+
+    #[test]
+    fn test_unknown_transactions() {
+        let mut validator = validator();
+        let declare_mining_job = new_declare_mining_job(
+            new_coinbase(vec![2, 159, 0, 0], SATS_AVAILABLE_IN_TEMPLATE),
+            vec![[1; 32], [2; 32], [3; 32]],
+        );
+
+        assert_eq!(
+            validator
+                .check(&declare_mining_job, SATS_AVAILABLE_IN_TEMPLATE)
+                .unwrap_err(),
+            JobDeclarationError::UnknownTransactions(vec![1, 2])
+        );
+
+        let decision = validator.validate(declare_mining_job, SATS_AVAILABLE_IN_TEMPLATE);
+        assert_eq!(error_code(decision), "invalid-job-param-value-tx-ids-list");
+    }
+}
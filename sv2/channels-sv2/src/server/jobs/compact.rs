@@ -0,0 +1,82 @@
+//! The compact form past jobs are demoted to, see
+//! [`PastJobRetention`](crate::server::jobs::job_store::PastJobRetention).
+use crate::{redact::RedactedExtranoncePrefix, target::WireU256};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// What a job store keeps of a past job once demoted: enough to validate shares that don't find
+/// a block.
+///
+/// The coinbase of the job is gone, so a share finding a block on a compact job is reported as
+/// [`ShareValidationResult::BlockFoundNeedsJobData`](crate::server::share_validation::ShareValidationResult::BlockFoundNeedsJobData),
+/// for the caller to rebuild the block out of the persisted template.
+#[derive(Clone, PartialEq, Eq)]
+pub struct CompactJob {
+    job_id: u32,
+    template_id: u64,
+    version: u32,
+    min_ntime: Option<u32>,
+    merkle_root: WireU256,
+    extranonce_prefix: Vec<u8>,
+}
+
+impl fmt::Debug for CompactJob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompactJob")
+            .field("job_id", &self.job_id)
+            .field("template_id", &self.template_id)
+            .field("version", &self.version)
+            .field("min_ntime", &self.min_ntime)
+            .field("merkle_root", &self.merkle_root)
+            .field(
+                "extranonce_prefix",
+                &RedactedExtranoncePrefix(&self.extranonce_prefix),
+            )
+            .finish()
+    }
+}
+
+impl CompactJob {
+    pub(crate) fn new(
+        job_id: u32,
+        template_id: u64,
+        version: u32,
+        min_ntime: Option<u32>,
+        merkle_root: WireU256,
+        extranonce_prefix: Vec<u8>,
+    ) -> Self {
+        Self {
+            job_id,
+            template_id,
+            version,
+            min_ntime,
+            merkle_root,
+            extranonce_prefix,
+        }
+    }
+
+    pub fn get_job_id(&self) -> u32 {
+        self.job_id
+    }
+
+    pub fn get_template_id(&self) -> u64 {
+        self.template_id
+    }
+
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    /// The `min_ntime` of the job, `None` if it was never activated.
+    pub fn activation_ntime(&self) -> Option<u32> {
+        self.min_ntime
+    }
+
+    pub fn get_merkle_root(&self) -> WireU256 {
+        self.merkle_root
+    }
+
+    pub fn get_extranonce_prefix(&self) -> &Vec<u8> {
+        &self.extranonce_prefix
+    }
+}
@@ -1,3 +1,7 @@
+use crate::template::TemplateValidationError;
+use alloc::string::String;
+use mining_sv2::TargetError;
+
 #[derive(Debug)]
 pub enum ExtendedJobError {
     FailedToDeserializeCoinbase,
@@ -9,17 +13,45 @@ pub enum ExtendedJobError {
     InvalidMinNTime,
 }
 
+#[derive(Debug)]
 pub enum StandardJobError {
     FailedToDeserializeCoinbaseOutputs,
+    FailedToDeserializeState,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobError {
+    /// The job was created on, or activated by, another chain tip.
+    ChainTipMismatch,
+    /// The `merkle_root` of the job doesn't hold 32 bytes.
+    InvalidMerkleRoot(TargetError),
 }
 
 #[derive(Debug)]
 pub enum JobFactoryError {
     InvalidTemplate(String),
+    TemplateValidationError(TemplateValidationError),
     DeserializeCoinbaseOutputsError,
     CoinbaseTxPrefixError,
     CoinbaseTxSuffixError,
     CoinbaseOutputsSumOverflow,
     InvalidCoinbaseOutputsSum,
     ChainTipRequired,
+    InvalidExtranoncePrefixLength(usize),
+    InvalidExtranoncePadding(usize),
+    /// A coinbase reward output, at this index, looks like a witness commitment, which must only
+    /// come from the template.
+    WitnessCommitmentInCoinbaseRewardOutputs(usize),
+    /// The template carries more than one output looking like a witness commitment.
+    MultipleWitnessCommitments,
+    /// No 32 bytes merkle root could be computed out of the coinbase and the merkle path of the
+    /// template.
+    InvalidMerkleRoot,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobStoreError {
+    /// A job with this `job_id` is already in the store, e.g. because it was created by another
+    /// job id allocator.
+    JobIdCollision(u32),
 }
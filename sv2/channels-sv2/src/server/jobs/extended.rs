@@ -1,16 +1,18 @@
 use super::Job;
 use crate::{
     chain_tip::ChainTip,
+    redact::RedactedExtranoncePrefix,
     server::jobs::{error::ExtendedJobError, JobOrigin},
     template::deserialize_template_outputs,
 };
+use alloc::vec::Vec;
 use binary_sv2::{Seq0255, Sv2Option, B0255, B064K, U256};
 use bitcoin::{
     consensus::{deserialize, serialize},
     transaction::{Transaction, TxOut},
 };
+use core::{convert::TryInto, fmt};
 use mining_sv2::{NewExtendedMiningJob, SetCustomMiningJob, MAX_EXTRANONCE_LEN};
-use std::convert::TryInto;
 use template_distribution_sv2::NewTemplate;
 
 /// Abstraction of an extended mining job with:
@@ -18,7 +20,7 @@ use template_distribution_sv2::NewTemplate;
 /// - the extranonce prefix associated with the channel at the time of job creation
 /// - all coinbase outputs (spendable + unspendable) associated with the job
 /// - the `NewExtendedMiningJob` message to be sent across the wire
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct ExtendedJob<'a> {
     origin: JobOrigin<'a>,
     extranonce_prefix: Vec<u8>,
@@ -26,6 +28,20 @@ pub struct ExtendedJob<'a> {
     job_message: NewExtendedMiningJob<'a>,
 }
 
+impl fmt::Debug for ExtendedJob<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtendedJob")
+            .field("origin", &self.origin)
+            .field(
+                "extranonce_prefix",
+                &RedactedExtranoncePrefix(&self.extranonce_prefix),
+            )
+            .field("coinbase_outputs", &self.coinbase_outputs)
+            .field("job_message", &self.job_message)
+            .finish()
+    }
+}
+
 impl Job for ExtendedJob<'_> {
     fn get_job_id(&self) -> u32 {
         self.job_message.job_id
@@ -56,12 +72,27 @@ impl<'a> ExtendedJob<'a> {
         coinbase_outputs.extend(additional_coinbase_outputs);
         coinbase_outputs.extend(template_coinbase_outputs);
 
-        Ok(Self {
+        Ok(Self::with_coinbase_outputs(
+            template,
+            extranonce_prefix,
+            coinbase_outputs,
+            job_message,
+        ))
+    }
+
+    // `coinbase_outputs` are all the outputs of the coinbase, already parsed out of `template`
+    pub(crate) fn with_coinbase_outputs(
+        template: NewTemplate<'a>,
+        extranonce_prefix: Vec<u8>,
+        coinbase_outputs: Vec<TxOut>,
+        job_message: NewExtendedMiningJob<'a>,
+    ) -> Self {
+        Self {
             origin: JobOrigin::NewTemplate(template),
             extranonce_prefix,
             coinbase_outputs,
             job_message,
-        })
+        }
     }
 
     pub fn from_custom_job(
@@ -210,4 +241,11 @@ impl<'a> ExtendedJob<'a> {
     pub fn activate(&mut self, min_ntime: u32) {
         self.job_message.min_ntime = Sv2Option::new(Some(min_ntime));
     }
+
+    /// Returns the `min_ntime` the job was activated with, or created with on top of a chain tip.
+    ///
+    /// Shares for the job must have an `ntime` at least as high. `None` for future jobs.
+    pub fn activation_ntime(&self) -> Option<u32> {
+        self.job_message.min_ntime.clone().into_inner()
+    }
 }
@@ -2,9 +2,11 @@
 use crate::{
     chain_tip::ChainTip,
     merkle_root::merkle_root_from_path,
-    server::jobs::{error::*, extended::ExtendedJob, standard::StandardJob},
+    server::jobs::{error::*, extended::ExtendedJob, standard::StandardJob, SharedTemplate},
+    target::u256_from_slice,
     template::deserialize_template_outputs,
 };
+use alloc::vec::Vec;
 use binary_sv2::{Sv2Option, B064K};
 use bitcoin::{
     absolute::LockTime,
@@ -13,25 +15,161 @@ use bitcoin::{
     transaction::{OutPoint, Transaction, TxIn, TxOut, Version},
     Amount, Sequence,
 };
+use core::{convert::TryInto, fmt};
 use mining_sv2::{NewExtendedMiningJob, NewMiningJob, SetCustomMiningJob, MAX_EXTRANONCE_LEN};
-use std::convert::TryInto;
+#[cfg(feature = "std")]
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
 use template_distribution_sv2::NewTemplate;
 
+/// Start of the `script_pubkey` of a BIP141 witness commitment output: `OP_RETURN`, a push of 36
+/// bytes, and the commitment header.
+const WITNESS_COMMITMENT_HEADER: [u8; 6] = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+
+/// Number of rounds of the Feistel network behind [`JobIdKey`].
+const JOB_ID_PERMUTATION_ROUNDS: u64 = 6;
+
+/// Key of the permutation applied to job ids, see [`JobFactory::with_job_id_key`].
+///
+/// The same key always maps the same counter to the same job id. The key itself is not shown by
+/// `Debug`, as it allows recovering how often jobs are created.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct JobIdKey(u64);
+
+impl JobIdKey {
+    pub fn new(key: u64) -> Self {
+        Self(key)
+    }
+
+    /// Draws a new key from the OS-seeded randomness behind `std`'s hash maps.
+    #[cfg(feature = "std")]
+    pub fn random() -> Self {
+        Self(RandomState::new().build_hasher().finish())
+    }
+
+    pub fn to_u64(&self) -> u64 {
+        self.0
+    }
+
+    // A bijection over u32, as a Feistel network over the two 16 bit halves.
+    fn permute(&self, counter: u32) -> u32 {
+        let (mut left, mut right) = ((counter >> 16) as u16, counter as u16);
+        for round in 0..JOB_ID_PERMUTATION_ROUNDS {
+            let next = left ^ self.round_function(round, right);
+            left = right;
+            right = next;
+        }
+        (left as u32) << 16 | right as u32
+    }
+
+    // The inverse of `permute`.
+    fn unpermute(&self, job_id: u32) -> u32 {
+        let (mut left, mut right) = ((job_id >> 16) as u16, job_id as u16);
+        for round in (0..JOB_ID_PERMUTATION_ROUNDS).rev() {
+            let previous = right ^ self.round_function(round, left);
+            right = left;
+            left = previous;
+        }
+        (left as u32) << 16 | right as u32
+    }
+
+    // splitmix64 over the key, round and half block
+    fn round_function(&self, round: u64, half: u16) -> u16 {
+        let mut z = self
+            .0
+            .wrapping_add((round << 16 | half as u64).wrapping_mul(0x9e3779b97f4a7c15));
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        (z ^ (z >> 31)) as u16
+    }
+}
+
+impl fmt::Debug for JobIdKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("JobIdKey(..)")
+    }
+}
+
+/// How the extranonce prefix of a standard job fills the extranonce reserved in its coinbase.
+///
+/// Miners on a standard channel don't roll any extranonce, so the extranonce prefix of the
+/// channel is all that goes in the coinbase, at the same position for the merkle root of the job
+/// and for the coinbase of a found block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExtranoncePadding {
+    /// The extranonce prefix is the whole extranonce, so it must be exactly
+    /// [`MAX_EXTRANONCE_LEN`] bytes long.
+    #[default]
+    None,
+    /// The extranonce prefix is followed by zeros up to `total_len` bytes, which can't exceed
+    /// [`MAX_EXTRANONCE_LEN`].
+    ZeroFill { total_len: usize },
+}
+
+impl ExtranoncePadding {
+    /// Number of bytes reserved for the extranonce in the coinbase.
+    pub fn total_len(&self) -> usize {
+        match self {
+            ExtranoncePadding::None => MAX_EXTRANONCE_LEN,
+            ExtranoncePadding::ZeroFill { total_len } => *total_len,
+        }
+    }
+
+    /// Returns the extranonce spliced into the coinbase for `extranonce_prefix`.
+    ///
+    /// Fails with [`JobFactoryError::InvalidExtranoncePrefixLength`] if the prefix doesn't fit
+    /// the policy, or with [`JobFactoryError::InvalidExtranoncePadding`] if `total_len` is above
+    /// [`MAX_EXTRANONCE_LEN`].
+    pub fn pad(&self, extranonce_prefix: &[u8]) -> Result<Vec<u8>, JobFactoryError> {
+        let total_len = self.total_len();
+        if total_len > MAX_EXTRANONCE_LEN {
+            return Err(JobFactoryError::InvalidExtranoncePadding(total_len));
+        }
+        let fits = match self {
+            ExtranoncePadding::None => extranonce_prefix.len() == total_len,
+            ExtranoncePadding::ZeroFill { .. } => extranonce_prefix.len() <= total_len,
+        };
+        if !fits {
+            return Err(JobFactoryError::InvalidExtranoncePrefixLength(
+                extranonce_prefix.len(),
+            ));
+        }
+        let mut extranonce = extranonce_prefix.to_vec();
+        extranonce.resize(total_len, 0);
+        Ok(extranonce)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 struct JobIdFactory {
     state: u32,
+    key: Option<JobIdKey>,
 }
 
 impl JobIdFactory {
     /// Creates a new [`Id`] instance initialized to `0`.
     fn new() -> Self {
-        Self { state: 0 }
+        Self {
+            state: 0,
+            key: None,
+        }
     }
 
     /// Increments then returns the internal state on a new ID.
     fn next(&mut self) -> u32 {
         self.state += 1;
-        self.state
+        self.last()
+    }
+
+    /// Returns the last ID handed out.
+    fn last(&self) -> u32 {
+        match self.key {
+            Some(key) => key.permute(self.state),
+            None => self.state,
+        }
     }
 }
 
@@ -46,6 +184,8 @@ impl JobIdFactory {
 pub struct JobFactory {
     job_id_factory: JobIdFactory,
     version_rolling_allowed: bool,
+    deterministic: bool,
+    extranonce_padding: ExtranoncePadding,
 }
 
 impl JobFactory {
@@ -53,26 +193,111 @@ impl JobFactory {
         Self {
             job_id_factory: JobIdFactory::new(),
             version_rolling_allowed,
+            deterministic: false,
+            extranonce_padding: ExtranoncePadding::None,
+        }
+    }
+
+    /// Creates a factory whose jobs only depend on the inputs of each call and on the sequence
+    /// of job ids.
+    ///
+    /// This is a compatibility contract, meant for reproducible test fixtures: two deterministic
+    /// factories fed with the same inputs create byte-for-byte identical jobs (coinbase, merkle
+    /// root and job messages), across versions of this crate. Anything that would make jobs vary
+    /// between calls (e.g. timestamps or random padding in the coinbase) must be disabled on
+    /// deterministic factories.
+    pub fn deterministic(version_rolling_allowed: bool) -> Self {
+        Self {
+            deterministic: true,
+            ..Self::new(version_rolling_allowed)
+        }
+    }
+
+    /// Creates a factory that resumes handing out job ids after `last_job_id`.
+    ///
+    /// Useful when restoring a channel, so new jobs don't collide with the restored ones.
+    pub fn with_last_job_id(version_rolling_allowed: bool, last_job_id: u32) -> Self {
+        Self {
+            job_id_factory: JobIdFactory {
+                state: last_job_id,
+                key: None,
+            },
+            version_rolling_allowed,
+            deterministic: false,
+            extranonce_padding: ExtranoncePadding::None,
         }
     }
 
+    /// Makes the factory hand out job ids permuted under `key`, so that they look random
+    /// (hiding how often jobs are created) while still never colliding.
+    ///
+    /// Ids keep following the last one handed out, so a factory created via
+    /// [`JobFactory::with_last_job_id`] resumes after `last_job_id` under `key`.
+    pub fn with_job_id_key(mut self, key: JobIdKey) -> Self {
+        let last_job_id = self.job_id_factory.last();
+        self.job_id_factory = JobIdFactory {
+            state: key.unpermute(last_job_id),
+            key: Some(key),
+        };
+        self
+    }
+
+    /// Makes standard jobs pad their extranonce prefix according to `extranonce_padding`, e.g.
+    /// to serve channels whose extranonce prefix is shorter than [`MAX_EXTRANONCE_LEN`].
+    ///
+    /// Extended jobs always reserve [`MAX_EXTRANONCE_LEN`] bytes, as their extranonce is rolled
+    /// by the miner.
+    pub fn with_extranonce_padding(mut self, extranonce_padding: ExtranoncePadding) -> Self {
+        self.extranonce_padding = extranonce_padding;
+        self
+    }
+
+    pub fn get_extranonce_padding(&self) -> ExtranoncePadding {
+        self.extranonce_padding
+    }
+
+    /// Returns the key job ids are permuted under, if any.
+    pub fn get_job_id_key(&self) -> Option<JobIdKey> {
+        self.job_id_factory.key
+    }
+
+    /// Returns the id of the last job created by this factory.
+    pub fn get_last_job_id(&self) -> u32 {
+        self.job_id_factory.last()
+    }
+
+    /// Whether this factory was created via [`JobFactory::deterministic`].
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    pub fn is_version_rolling_allowed(&self) -> bool {
+        self.version_rolling_allowed
+    }
+
+    /// Sets whether version rolling is allowed on jobs created from now on.
+    pub fn set_version_rolling_allowed(&mut self, version_rolling_allowed: bool) {
+        self.version_rolling_allowed = version_rolling_allowed;
+    }
+
     /// Creates a new job from a template.
     ///
     /// This job (and related shares) is fully committed to:
-    /// - The template
+    /// - The template, which the job keeps a reference to rather than a copy of
     /// - The additional coinbase outputs (added to the outputs coming from the template)
-    /// - The extranonce prefix of the channel at the time of job creation
+    /// - The extranonce prefix of the channel at the time of job creation, padded according to
+    ///   the [`ExtranoncePadding`] of the factory
     ///
     /// The optional `ChainTip` defines whether the job will be future or not.
     ///
-    /// Note: version rolling is always allowed for standard jobs, so the `version_rolling_allowed`
-    /// parameter is ignored.
+    /// Note: `NewMiningJob` has no `version_rolling_allowed` field, so it's up to the Standard
+    /// Channel to enforce it while validating shares.
     pub fn new_standard_job<'a>(
         &mut self,
         channel_id: u32,
         chain_tip: Option<ChainTip>,
         extranonce_prefix: Vec<u8>,
-        template: NewTemplate<'a>,
+        template: SharedTemplate,
         additional_coinbase_outputs: Vec<TxOut>,
     ) -> Result<StandardJob<'a>, JobFactoryError> {
         let coinbase_outputs_sum = additional_coinbase_outputs
@@ -83,57 +308,69 @@ impl JobFactory {
             return Err(JobFactoryError::InvalidCoinbaseOutputsSum);
         }
 
+        // a standard job fills the extranonce reserved in the coinbase with the channel's
+        // extranonce prefix alone, padded as `StandardJob::get_extranonce` does for found blocks
+        let extranonce = self.extranonce_padding.pad(&extranonce_prefix)?;
+
+        // parsed once, so that the job message and the coinbase kept for block propagation
+        // commit to the same outputs
+        let coinbase_outputs = coinbase_outputs(&template, additional_coinbase_outputs)?;
+
         let job_id = self.job_id_factory.next();
 
         let version = template.version;
 
-        let coinbase_tx_prefix =
-            self.coinbase_tx_prefix(template.clone(), additional_coinbase_outputs.clone())?;
-        let coinbase_tx_suffix =
-            self.coinbase_tx_suffix(template.clone(), additional_coinbase_outputs.clone())?;
-        let merkle_path = template.merkle_path.clone();
+        let coinbase = self.coinbase(&template, coinbase_outputs.clone(), extranonce.len())?;
+        let coinbase_tx_prefix = self.coinbase_tx_prefix(&template, &coinbase)?;
+        let coinbase_tx_suffix = self.coinbase_tx_suffix(&template, &coinbase, extranonce.len())?;
         let merkle_root = merkle_root_from_path(
             coinbase_tx_prefix.inner_as_ref(),
             coinbase_tx_suffix.inner_as_ref(),
-            &extranonce_prefix,
-            &merkle_path.inner_as_ref(),
+            &extranonce,
+            &template.merkle_path.inner_as_ref(),
         )
-        .expect("merkle root must be valid")
-        .try_into()
-        .expect("merkle root must be 32 bytes");
-
-        let job_message = match template.future_template {
-            true => NewMiningJob {
-                channel_id,
-                job_id,
-                min_ntime: Sv2Option::new(None),
-                version,
-                merkle_root,
-            },
-            false => {
-                let min_ntime = match chain_tip {
-                    Some(chain_tip) => Some(chain_tip.min_ntime()),
-                    None => return Err(JobFactoryError::ChainTipRequired),
-                };
+        .ok_or(JobFactoryError::InvalidMerkleRoot)?;
+        let merkle_root =
+            u256_from_slice(&merkle_root).map_err(|_| JobFactoryError::InvalidMerkleRoot)?;
 
+        // a future job gets its chain tip once activated
+        let (job_message, prev_hash) = match template.future_template {
+            true => (
                 NewMiningJob {
                     channel_id,
                     job_id,
-                    min_ntime: Sv2Option::new(min_ntime),
+                    min_ntime: Sv2Option::new(None),
                     version,
                     merkle_root,
-                }
+                },
+                None,
+            ),
+            false => {
+                let chain_tip = chain_tip.ok_or(JobFactoryError::ChainTipRequired)?;
+
+                (
+                    NewMiningJob {
+                        channel_id,
+                        job_id,
+                        min_ntime: Sv2Option::new(Some(chain_tip.min_ntime())),
+                        version,
+                        merkle_root,
+                    },
+                    Some(chain_tip.prev_block_hash()),
+                )
             }
         };
 
-        let job = StandardJob::from_template(
+        let mut job = StandardJob::with_coinbase_outputs(
             template,
             extranonce_prefix,
-            additional_coinbase_outputs,
+            self.extranonce_padding,
+            coinbase_outputs,
             job_message,
-        )
-        .map_err(|_| JobFactoryError::DeserializeCoinbaseOutputsError)?;
-
+        );
+        if let Some(prev_hash) = prev_hash {
+            job.set_prev_hash(prev_hash);
+        }
         Ok(job)
     }
 
@@ -161,14 +398,18 @@ impl JobFactory {
             return Err(JobFactoryError::InvalidCoinbaseOutputsSum);
         }
 
+        // parsed once, so that the job message and the coinbase kept for block propagation
+        // commit to the same outputs
+        let coinbase_outputs = coinbase_outputs(&template, additional_coinbase_outputs)?;
+
         let job_id = self.job_id_factory.next();
 
         let version = template.version;
 
-        let coinbase_tx_prefix =
-            self.coinbase_tx_prefix(template.clone(), additional_coinbase_outputs.clone())?;
+        let coinbase = self.coinbase(&template, coinbase_outputs.clone(), MAX_EXTRANONCE_LEN)?;
+        let coinbase_tx_prefix = self.coinbase_tx_prefix(&template, &coinbase)?;
         let coinbase_tx_suffix =
-            self.coinbase_tx_suffix(template.clone(), additional_coinbase_outputs.clone())?;
+            self.coinbase_tx_suffix(&template, &coinbase, MAX_EXTRANONCE_LEN)?;
         let merkle_path = template.merkle_path.clone();
 
         let job_message = match template.future_template {
@@ -200,15 +441,12 @@ impl JobFactory {
             }
         };
 
-        let job = ExtendedJob::from_template(
+        Ok(ExtendedJob::with_coinbase_outputs(
             template,
             extranonce_prefix,
-            additional_coinbase_outputs,
+            coinbase_outputs,
             job_message,
-        )
-        .map_err(|_| JobFactoryError::DeserializeCoinbaseOutputsError)?;
-
-        Ok(job)
+        ))
     }
 
     /// Creates a new job from a SetCustomMiningJob message.
@@ -335,42 +573,18 @@ impl JobFactory {
             .map_err(|_| JobFactoryError::CoinbaseTxSuffixError)
     }
 
-    // build a coinbase transaction from some template in the JobFactory
+    // build a coinbase transaction from some template in the JobFactory, along with all the
+    // outputs of the coinbase (see `coinbase_outputs`), reserving `extranonce_len` bytes for the
+    // extranonce
     fn coinbase(
         &self,
-        template: NewTemplate<'_>,
-        coinbase_reward_outputs: Vec<TxOut>,
+        template: &NewTemplate<'_>,
+        outputs: Vec<TxOut>,
+        extranonce_len: usize,
     ) -> Result<Transaction, JobFactoryError> {
-        // check that the sum of the additional coinbase outputs is equal to the value remaining in
-        // the active template
-        let mut coinbase_reward_outputs_sum = Amount::from_sat(0);
-        for output in coinbase_reward_outputs.iter() {
-            coinbase_reward_outputs_sum = coinbase_reward_outputs_sum
-                .checked_add(output.value)
-                .ok_or(JobFactoryError::CoinbaseOutputsSumOverflow)?;
-        }
-
-        if template.coinbase_tx_value_remaining < coinbase_reward_outputs_sum.to_sat() {
-            return Err(JobFactoryError::InvalidCoinbaseOutputsSum);
-        }
-
-        let mut outputs = vec![];
-
-        for output in coinbase_reward_outputs.iter() {
-            outputs.push(output.clone());
-        }
-
-        let mut template_outputs = deserialize_template_outputs(
-            template.coinbase_tx_outputs.to_vec(),
-            template.coinbase_tx_outputs_count,
-        )
-        .map_err(|_| JobFactoryError::DeserializeCoinbaseOutputsError)?;
-
-        outputs.append(&mut template_outputs);
-
         let mut script_sig = vec![];
         script_sig.extend_from_slice(&template.coinbase_prefix.to_vec());
-        script_sig.extend_from_slice(&[0; MAX_EXTRANONCE_LEN]);
+        script_sig.resize(script_sig.len() + extranonce_len, 0);
 
         let tx_in = TxIn {
             previous_output: OutPoint::null(),
@@ -389,11 +603,10 @@ impl JobFactory {
 
     fn coinbase_tx_prefix(
         &self,
-        template: NewTemplate<'_>,
-        coinbase_reward_outputs: Vec<TxOut>,
+        template: &NewTemplate<'_>,
+        coinbase: &Transaction,
     ) -> Result<B064K<'static>, JobFactoryError> {
-        let coinbase = self.coinbase(template.clone(), coinbase_reward_outputs)?;
-        let serialized_coinbase = serialize(&coinbase);
+        let serialized_coinbase = serialize(coinbase);
 
         let index = 4 // tx version
             + 2 // segwit bytes
@@ -411,13 +624,11 @@ impl JobFactory {
 
     fn coinbase_tx_suffix(
         &self,
-        template: NewTemplate<'_>,
-        coinbase_reward_outputs: Vec<TxOut>,
+        template: &NewTemplate<'_>,
+        coinbase: &Transaction,
+        full_extranonce_size: usize,
     ) -> Result<B064K<'static>, JobFactoryError> {
-        let coinbase = self.coinbase(template.clone(), coinbase_reward_outputs)?;
-        let serialized_coinbase = serialize(&coinbase);
-
-        let full_extranonce_size = MAX_EXTRANONCE_LEN;
+        let serialized_coinbase = serialize(coinbase);
 
         let r = serialized_coinbase[4 // tx version
             + 2 // segwit bytes
@@ -434,12 +645,182 @@ impl JobFactory {
     }
 }
 
+// Returns all the outputs of the coinbase for `template`: the coinbase reward outputs, followed
+// by the outputs of the template, which are strictly checked against their declared count.
+//
+// BIP141 takes the last output that looks like a witness commitment as the commitment, so the
+// one of the template (if any) must be the only one: the template can't carry several of them,
+// and the reward outputs, which come first, can't carry any.
+fn coinbase_outputs(
+    template: &NewTemplate<'_>,
+    coinbase_reward_outputs: Vec<TxOut>,
+) -> Result<Vec<TxOut>, JobFactoryError> {
+    // check that the sum of the additional coinbase outputs is equal to the value remaining in
+    // the active template
+    let mut coinbase_reward_outputs_sum = Amount::from_sat(0);
+    for output in coinbase_reward_outputs.iter() {
+        coinbase_reward_outputs_sum = coinbase_reward_outputs_sum
+            .checked_add(output.value)
+            .ok_or(JobFactoryError::CoinbaseOutputsSumOverflow)?;
+    }
+
+    if template.coinbase_tx_value_remaining < coinbase_reward_outputs_sum.to_sat() {
+        return Err(JobFactoryError::InvalidCoinbaseOutputsSum);
+    }
+
+    let template_outputs = deserialize_template_outputs(
+        template.coinbase_tx_outputs.to_vec(),
+        template.coinbase_tx_outputs_count,
+    )
+    .map_err(JobFactoryError::TemplateValidationError)?;
+
+    if let Some(index) = witness_commitment_index(&coinbase_reward_outputs) {
+        return Err(JobFactoryError::WitnessCommitmentInCoinbaseRewardOutputs(
+            index,
+        ));
+    }
+    if template_outputs
+        .iter()
+        .filter(|output| is_witness_commitment(output))
+        .count()
+        > 1
+    {
+        return Err(JobFactoryError::MultipleWitnessCommitments);
+    }
+
+    let mut outputs = coinbase_reward_outputs;
+    outputs.extend(template_outputs);
+    Ok(outputs)
+}
+
+// Whether `output` has the `script_pubkey` of a BIP141 witness commitment.
+fn is_witness_commitment(output: &TxOut) -> bool {
+    let script = output.script_pubkey.as_bytes();
+    script.len() >= 38 && script.starts_with(&WITNESS_COMMITMENT_HEADER)
+}
+
+// The index of the output BIP141 takes as the witness commitment of a coinbase with `outputs`,
+// i.e. the last one that looks like a witness commitment.
+fn witness_commitment_index(outputs: &[TxOut]) -> Option<usize> {
+    outputs.iter().rposition(is_witness_commitment)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::template::TemplateValidationError;
+    use alloc::sync::Arc;
     use bitcoin::ScriptBuf;
+    use std::collections::HashSet;
     use template_distribution_sv2::NewTemplate;
 
+    #[test]
+    fn test_job_id_key() {
+        let key = JobIdKey::new(0x0123456789abcdef);
+
+        let mut job_ids = HashSet::new();
+        let mut job_factory = JobFactory::new(true).with_job_id_key(key);
+        for _ in 0..100_000 {
+            assert!(job_ids.insert(job_factory.job_id_factory.next()));
+        }
+
+        // the same key always yields the same ids, across versions of this crate
+        let mut first = JobFactory::new(true).with_job_id_key(key);
+        let mut second = JobFactory::new(true).with_job_id_key(key);
+        let first_ids: Vec<u32> = (0..3).map(|_| first.job_id_factory.next()).collect();
+        let second_ids: Vec<u32> = (0..3).map(|_| second.job_id_factory.next()).collect();
+        assert_eq!(first_ids, second_ids);
+        assert_eq!(first_ids, [289246846, 2922704620, 3800404849]);
+
+        // a restored factory resumes after the last id
+        let mut restored =
+            JobFactory::with_last_job_id(true, first.get_last_job_id()).with_job_id_key(key);
+        assert_eq!(restored.job_id_factory.next(), first.job_id_factory.next());
+
+        // a different key yields different ids
+        let mut other = JobFactory::new(true).with_job_id_key(JobIdKey::new(1));
+        assert_ne!(other.job_id_factory.next(), first_ids[0]);
+    }
+
+    #[test]
+    fn test_extranonce_padding() {
+        use crate::testing::fixture;
+
+        let prefix = [1, 2, 3, 4];
+        let padding = ExtranoncePadding::ZeroFill { total_len: 16 };
+        assert_eq!(padding.total_len(), 16);
+        let mut expected = prefix.to_vec();
+        expected.resize(16, 0);
+        assert_eq!(padding.pad(&prefix).unwrap(), expected);
+        assert_eq!(
+            ExtranoncePadding::ZeroFill { total_len: 4 }
+                .pad(&prefix)
+                .unwrap(),
+            prefix
+        );
+        assert!(matches!(
+            ExtranoncePadding::ZeroFill { total_len: 3 }.pad(&prefix),
+            Err(JobFactoryError::InvalidExtranoncePrefixLength(4))
+        ));
+        assert!(matches!(
+            ExtranoncePadding::ZeroFill { total_len: 33 }.pad(&prefix),
+            Err(JobFactoryError::InvalidExtranoncePadding(33))
+        ));
+        assert!(matches!(
+            ExtranoncePadding::None.pad(&prefix),
+            Err(JobFactoryError::InvalidExtranoncePrefixLength(4))
+        ));
+
+        // zero-filling a full length prefix is the same as not padding it
+        let new_job = |padding| {
+            JobFactory::new(true)
+                .with_extranonce_padding(padding)
+                .new_standard_job(
+                    1,
+                    None,
+                    fixture::extranonce_prefix(),
+                    Arc::new(fixture::template(true)),
+                    fixture::coinbase_reward_outputs(),
+                )
+                .unwrap()
+        };
+        let padded = new_job(ExtranoncePadding::ZeroFill {
+            total_len: MAX_EXTRANONCE_LEN,
+        });
+        let unpadded = new_job(ExtranoncePadding::None);
+        assert_eq!(padded.get_merkle_root(), unpadded.get_merkle_root());
+        assert_eq!(
+            padded.get_extranonce().unwrap(),
+            unpadded.get_extranonce().unwrap()
+        );
+
+        // a short prefix is only accepted once padded
+        let mut job_factory = JobFactory::new(true);
+        assert!(matches!(
+            job_factory.new_standard_job(
+                1,
+                None,
+                prefix.to_vec(),
+                Arc::new(fixture::template(true)),
+                fixture::coinbase_reward_outputs(),
+            ),
+            Err(JobFactoryError::InvalidExtranoncePrefixLength(4))
+        ));
+        let mut job_factory = job_factory.with_extranonce_padding(padding);
+        let job = job_factory
+            .new_standard_job(
+                1,
+                None,
+                prefix.to_vec(),
+                Arc::new(fixture::template(true)),
+                fixture::coinbase_reward_outputs(),
+            )
+            .unwrap();
+        assert_eq!(job.get_extranonce_prefix(), &prefix.to_vec());
+        assert_eq!(job.get_extranonce().unwrap(), expected);
+        assert_ne!(job.get_merkle_root(), unpadded.get_merkle_root());
+    }
+
     #[test]
     fn test_new_job() {
         let mut job_factory = JobFactory::new(true);
@@ -529,6 +910,247 @@ mod tests {
         assert_eq!(job.get_job_message(), &expected_job);
     }
 
+    // golden vectors for the contract of `JobFactory::deterministic`, must never change
+    #[test]
+    fn test_deterministic_jobs() {
+        use crate::testing::fixture;
+
+        let jobs = || {
+            let mut job_factory = JobFactory::deterministic(true);
+            assert!(job_factory.is_deterministic());
+            let standard_job = job_factory
+                .new_standard_job(
+                    1,
+                    Some(fixture::chain_tip()),
+                    fixture::extranonce_prefix(),
+                    Arc::new(fixture::template(false)),
+                    fixture::coinbase_reward_outputs(),
+                )
+                .unwrap();
+            let extended_job = job_factory
+                .new_extended_job(
+                    1,
+                    Some(fixture::chain_tip()),
+                    fixture::extranonce_prefix()[..8].to_vec(),
+                    fixture::template(false),
+                    fixture::coinbase_reward_outputs(),
+                )
+                .unwrap();
+            (standard_job, extended_job)
+        };
+
+        let (standard_job, extended_job) = jobs();
+        assert_eq!(
+            standard_job.get_job_message(),
+            &NewMiningJob {
+                channel_id: 1,
+                job_id: 1,
+                min_ntime: Sv2Option::new(Some(fixture::NTIME)),
+                version: 536870912,
+                merkle_root: fixture::MERKLE_ROOT.into(),
+            }
+        );
+        let expected_extended_job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            min_ntime: Sv2Option::new(Some(fixture::NTIME)),
+            version: 536870912,
+            version_rolling_allowed: true,
+            coinbase_tx_prefix: vec![
+                2, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 255, 255, 36, 2, 159, 0, 0,
+            ]
+            .try_into()
+            .unwrap(),
+            coinbase_tx_suffix: vec![
+                254, 255, 255, 255, 2, 0, 242, 5, 42, 1, 0, 0, 0, 22, 0, 20, 235, 225, 183, 220,
+                194, 147, 204, 170, 14, 231, 67, 168, 111, 137, 223, 130, 88, 194, 8, 252, 0, 0, 0,
+                0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209, 222,
+                253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180, 139,
+                235, 216, 54, 151, 78, 140, 249, 1, 32, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 158, 0, 0, 0,
+            ]
+            .try_into()
+            .unwrap(),
+            merkle_path: vec![].try_into().unwrap(),
+        };
+        assert_eq!(extended_job.get_job_message(), &expected_extended_job);
+
+        // identical inputs, identical jobs
+        let (other_standard_job, other_extended_job) = jobs();
+        assert_eq!(
+            other_standard_job.get_job_message(),
+            standard_job.get_job_message()
+        );
+        assert_eq!(
+            other_standard_job.get_coinbase_outputs(),
+            standard_job.get_coinbase_outputs()
+        );
+        assert_eq!(
+            other_extended_job.get_job_message(),
+            extended_job.get_job_message()
+        );
+    }
+
+    #[test]
+    fn test_template_output_count_mismatch() {
+        let mut job_factory = JobFactory::new(true);
+
+        // declares 2 outputs, but only carries 1
+        let template = NewTemplate {
+            template_id: 1,
+            future_template: true,
+            version: 536870912,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![82, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: 4294967295,
+            coinbase_tx_value_remaining: 5000000000,
+            coinbase_tx_outputs_count: 2,
+            coinbase_tx_outputs: vec![
+                0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
+                222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
+                139, 235, 216, 54, 151, 78, 140, 249,
+            ]
+            .try_into()
+            .unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: vec![].try_into().unwrap(),
+        };
+        let coinbase_reward_outputs = vec![TxOut {
+            value: Amount::from_sat(5000000000),
+            script_pubkey: ScriptBuf::new(),
+        }];
+
+        let res = job_factory.new_standard_job(
+            1,
+            None,
+            vec![0; 32],
+            Arc::new(template.clone()),
+            coinbase_reward_outputs.clone(),
+        );
+        assert!(matches!(
+            res,
+            Err(JobFactoryError::TemplateValidationError(
+                TemplateValidationError::OutputCountMismatch {
+                    declared: 2,
+                    parsed: 1
+                }
+            ))
+        ));
+        let res =
+            job_factory.new_extended_job(1, None, vec![0; 8], template, coinbase_reward_outputs);
+        assert!(matches!(
+            res,
+            Err(JobFactoryError::TemplateValidationError(
+                TemplateValidationError::OutputCountMismatch { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_witness_commitment_placement() {
+        let mut job_factory = JobFactory::new(true);
+
+        let mut commitment_script = WITNESS_COMMITMENT_HEADER.to_vec();
+        commitment_script.extend_from_slice(&[0x11; 32]);
+        let commitment = TxOut {
+            value: Amount::from_sat(0),
+            script_pubkey: ScriptBuf::from(commitment_script),
+        };
+        let other = TxOut {
+            value: Amount::from_sat(0),
+            script_pubkey: ScriptBuf::from(vec![0x6a, 0x01, 0x42]),
+        };
+        let reward = TxOut {
+            value: Amount::from_sat(5000000000),
+            script_pubkey: ScriptBuf::from(vec![0; 22]),
+        };
+        let template = |outputs: Vec<TxOut>| NewTemplate {
+            template_id: 1,
+            future_template: true,
+            version: 536870912,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![82, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: 4294967295,
+            coinbase_tx_value_remaining: 5000000000,
+            coinbase_tx_outputs_count: outputs.len() as u32,
+            coinbase_tx_outputs: outputs
+                .iter()
+                .flat_map(serialize)
+                .collect::<Vec<u8>>()
+                .try_into()
+                .unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: vec![].try_into().unwrap(),
+        };
+
+        // the commitment is the only one wherever it is among the template outputs, and ends up
+        // after the reward outputs
+        for (template_outputs, index) in [
+            (vec![commitment.clone(), other.clone()], 1),
+            (vec![other.clone(), commitment.clone()], 2),
+        ] {
+            let job = job_factory
+                .new_standard_job(
+                    1,
+                    None,
+                    vec![0; 32],
+                    Arc::new(template(template_outputs.clone())),
+                    vec![reward.clone()],
+                )
+                .unwrap();
+            let outputs = job.get_coinbase_outputs();
+            assert_eq!(witness_commitment_index(outputs), Some(index));
+            assert_eq!(outputs[index], commitment);
+            let job = job_factory
+                .new_extended_job(
+                    1,
+                    None,
+                    vec![0; 8],
+                    template(template_outputs),
+                    vec![reward.clone()],
+                )
+                .unwrap();
+            assert_eq!(
+                witness_commitment_index(job.get_coinbase_outputs()),
+                Some(index)
+            );
+        }
+
+        // a duplicated commitment in the template
+        let res = job_factory.new_standard_job(
+            1,
+            None,
+            vec![0; 32],
+            Arc::new(template(vec![
+                commitment.clone(),
+                other.clone(),
+                commitment.clone(),
+            ])),
+            vec![reward.clone()],
+        );
+        assert!(matches!(
+            res,
+            Err(JobFactoryError::MultipleWitnessCommitments)
+        ));
+
+        // a commitment among the reward outputs, which BIP141 would ignore in favour of the one
+        // of the template, or take as the commitment of a template without one
+        for template_outputs in [vec![commitment.clone()], vec![other]] {
+            let res = job_factory.new_extended_job(
+                1,
+                None,
+                vec![0; 8],
+                template(template_outputs),
+                vec![reward.clone(), commitment.clone()],
+            );
+            assert!(matches!(
+                res,
+                Err(JobFactoryError::WitnessCommitmentInCoinbaseRewardOutputs(1))
+            ));
+        }
+    }
+
     #[test]
     fn test_new_custom_job() {
         let mut job_factory = JobFactory::new(true);
@@ -1,10 +1,67 @@
-use std::{collections::HashMap, fmt::Debug};
+use crate::collections::{HashMap, HashSet, VecDeque};
+use alloc::vec::Vec;
+use core::fmt::Debug;
 
-use super::Job;
+use super::{compact::CompactJob, error::JobStoreError, Job};
 
+/// What happens to the jobs of the previous chain tips once a new one is activated.
+///
+/// Shares for jobs kept as stale are rejected as `stale-share`, while shares for jobs that were
+/// dropped are rejected as `invalid-job-id`, which miners tend to read as a broken pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaleRetention {
+    /// Keep the jobs of the last `n` chain tips as stale jobs.
+    KeepAsStale(usize),
+    /// Forget the jobs of previous chain tips.
+    Drop,
+}
+
+impl Default for StaleRetention {
+    fn default() -> Self {
+        Self::KeepAsStale(1)
+    }
+}
+
+/// How the past jobs of the current chain tip are kept in memory.
+///
+/// Shares for past jobs rarely find a block, which is the only time the coinbase of the job is
+/// needed, so older past jobs can be demoted to a [`CompactJob`] to save memory on channels
+/// receiving many templates per block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PastJobRetention {
+    /// Keep every past job in full.
+    #[default]
+    Full,
+    /// Keep the last `n` past jobs in full, and demote the older ones to a [`CompactJob`].
+    ///
+    /// Jobs without a compact form (see [`Job::to_compact`]) are always kept in full. Demoted
+    /// jobs become compact stale jobs once the chain tip changes.
+    CompactAfter(usize),
+}
+
+/// Where a channel keeps its jobs, along the lifecycle future -> active -> past -> stale.
+///
+/// Implementations are expected to uphold the ordering guarantees of [`DefaultJobStore`]:
+/// - a template has at most one future job: adding a future job for a `template_id` that already
+///   has one replaces it, and the superseded job is dropped from the future jobs
+/// - activating a future job retires the active job (if any) to the past jobs, then moves every
+///   past job to the stale jobs and drops the other future jobs: their templates were superseded
+///   by the activated one, so they can never be activated, not even by a later `SetNewPrevHash`
+///   referencing them
+/// - adding an active job retires the previous active job to the past jobs, in the order the jobs
+///   were activated
+/// - a job id is held by at most one job: adding a job whose id is already in the store fails
+///   with [`JobStoreError::JobIdCollision`], leaving the store untouched, as shares for the job
+///   already there would otherwise be validated against the new one
 pub trait JobStore<T: Job>: Send + Sync + Debug {
-    fn add_future_job(&mut self, template_id: u64, job: T) -> u32;
-    fn add_active_job(&mut self, job: T);
+    /// Adds a future job for `template_id`, replacing any future job already added for it, and
+    /// returns its job id.
+    fn add_future_job(&mut self, template_id: u64, job: T) -> Result<u32, JobStoreError>;
+    fn add_active_job(&mut self, job: T) -> Result<(), JobStoreError>;
+    /// Activates the future job of `template_id`, see the [`JobStore`] guarantees.
+    ///
+    /// Returns `false`, leaving the store untouched, if no future job was added for
+    /// `template_id`.
     fn activate_future_job(&mut self, template_id: u64, prev_hash_header_timestamp: u32) -> bool;
     fn set_active_job(&mut self, job: T);
     fn get_future_template_to_job_id(&self) -> &HashMap<u64, u32>;
@@ -12,28 +69,136 @@ pub trait JobStore<T: Job>: Send + Sync + Debug {
     fn get_future_jobs(&self) -> &HashMap<u32, T>;
     fn get_past_jobs(&self) -> &HashMap<u32, T>;
     fn get_stale_jobs(&self) -> &HashMap<u32, T>;
+    /// The past job with `job_id`, if it was demoted to a [`CompactJob`], see
+    /// [`PastJobRetention`].
+    fn get_compact_past_job(&self, _job_id: u32) -> Option<&CompactJob> {
+        None
+    }
+    /// The stale job with `job_id`, if it was demoted to a [`CompactJob`] while a past job.
+    fn get_compact_stale_job(&self, _job_id: u32) -> Option<&CompactJob> {
+        None
+    }
+    /// How the jobs of previous chain tips are retained when a future job is activated.
+    fn get_stale_retention(&self) -> StaleRetention {
+        StaleRetention::default()
+    }
+    /// Reconciles the future jobs with their template mappings: drops the mappings of future
+    /// jobs that are gone, and the future jobs no template maps to, as those can never be
+    /// activated. Returns how many entries were removed.
+    ///
+    /// Channels call it whenever they move to a new chain tip, so that a store failing to keep
+    /// both in sync doesn't grow without bounds.
+    fn gc(&mut self) -> usize {
+        0
+    }
+    /// Whether any job in the store, whatever its state, has `job_id`.
+    fn contains_job_id(&self, job_id: u32) -> bool {
+        self.get_active_job()
+            .map(|job| job.get_job_id() == job_id)
+            .unwrap_or(false)
+            || self.get_future_jobs().contains_key(&job_id)
+            || self.get_past_jobs().contains_key(&job_id)
+            || self.get_stale_jobs().contains_key(&job_id)
+            || self.get_compact_past_job(job_id).is_some()
+            || self.get_compact_stale_job(job_id).is_some()
+    }
+    /// Whether the store holds no job at all, e.g. on a channel that was just opened.
+    fn is_empty(&self) -> bool {
+        self.get_active_job().is_none()
+            && self.get_future_jobs().is_empty()
+            && self.get_past_jobs().is_empty()
+            && self.get_stale_jobs().is_empty()
+    }
 }
 
 #[derive(Debug)]
 pub struct DefaultJobStore<T: Job + Clone> {
+    // number of future jobs activated so far, i.e. of chain tips the store moved to
+    tip_era: u64,
     future_template_to_job_id: HashMap<u64, u32>,
     // future jobs are indexed with job_id (u32)
     future_jobs: HashMap<u32, T>,
+    // the tip era each future job was added in, only the ones of the current era can be activated
+    future_job_tip_eras: HashMap<u32, u64>,
     active_job: Option<T>,
     // past jobs are indexed with job_id (u32)
     past_jobs: HashMap<u32, T>,
+    // job ids of the past jobs kept in full that can be demoted, oldest first
+    demotable_past_job_ids: VecDeque<u32>,
+    compact_past_jobs: HashMap<u32, CompactJob>,
     // stale jobs are indexed with job_id (u32)
     stale_jobs: HashMap<u32, T>,
+    compact_stale_jobs: HashMap<u32, CompactJob>,
+    // job ids of the stale jobs of each retained chain tip, oldest first
+    stale_job_ids_per_tip: VecDeque<Vec<u32>>,
+    stale_retention: StaleRetention,
+    past_job_retention: PastJobRetention,
 }
 
 impl<T: Job + Clone> DefaultJobStore<T> {
     pub fn new() -> Self {
+        Self::with_stale_retention(StaleRetention::default())
+    }
+
+    pub fn with_stale_retention(stale_retention: StaleRetention) -> Self {
         Self {
+            tip_era: 0,
             future_template_to_job_id: HashMap::new(),
             future_jobs: HashMap::new(),
+            future_job_tip_eras: HashMap::new(),
             active_job: None,
             past_jobs: HashMap::new(),
+            demotable_past_job_ids: VecDeque::new(),
+            compact_past_jobs: HashMap::new(),
             stale_jobs: HashMap::new(),
+            compact_stale_jobs: HashMap::new(),
+            stale_job_ids_per_tip: VecDeque::new(),
+            stale_retention,
+            past_job_retention: PastJobRetention::Full,
+        }
+    }
+
+    /// Sets how past jobs are kept in memory, see [`PastJobRetention`].
+    pub fn with_past_job_retention(mut self, past_job_retention: PastJobRetention) -> Self {
+        self.past_job_retention = past_job_retention;
+        self
+    }
+
+    pub fn get_past_job_retention(&self) -> PastJobRetention {
+        self.past_job_retention
+    }
+
+    /// Number of future jobs activated so far, i.e. of chain tips the store moved to.
+    ///
+    /// A future job can only be activated in the tip era it was added in.
+    pub fn get_tip_era(&self) -> u64 {
+        self.tip_era
+    }
+
+    // moves the active job, if any, to the past jobs, demoting the oldest ones as needed
+    fn retire_active_job(&mut self) {
+        let job = match self.active_job.take() {
+            Some(job) => job,
+            None => return,
+        };
+        let job_id = job.get_job_id();
+        self.past_jobs.insert(job_id, job);
+        let full_past_jobs = match self.past_job_retention {
+            PastJobRetention::Full => return,
+            PastJobRetention::CompactAfter(n) => n,
+        };
+        self.demotable_past_job_ids.push_back(job_id);
+        while self.demotable_past_job_ids.len() > full_past_jobs {
+            let oldest = match self.demotable_past_job_ids.pop_front() {
+                Some(job_id) => job_id,
+                None => break,
+            };
+            let compact = match self.past_jobs.get(&oldest).and_then(Job::to_compact) {
+                Some(compact) => compact,
+                None => continue,
+            };
+            self.past_jobs.remove(&oldest);
+            self.compact_past_jobs.insert(oldest, compact);
         }
     }
 }
@@ -45,21 +210,34 @@ impl<T: Job + Clone> Default for DefaultJobStore<T> {
 }
 
 impl<T: Job + Clone + Debug> JobStore<T> for DefaultJobStore<T> {
-    fn add_future_job(&mut self, template_id: u64, new_job: T) -> u32 {
+    fn add_future_job(&mut self, template_id: u64, new_job: T) -> Result<u32, JobStoreError> {
         let new_job_id = new_job.get_job_id();
+        if self.contains_job_id(new_job_id) {
+            return Err(JobStoreError::JobIdCollision(new_job_id));
+        }
         self.future_jobs.insert(new_job_id, new_job);
-        self.future_template_to_job_id
-            .insert(template_id, new_job_id);
-        new_job_id
+        self.future_job_tip_eras.insert(new_job_id, self.tip_era);
+        // the superseded job could never be activated, as activation goes by template_id
+        if let Some(superseded_job_id) = self
+            .future_template_to_job_id
+            .insert(template_id, new_job_id)
+        {
+            self.future_jobs.remove(&superseded_job_id);
+            self.future_job_tip_eras.remove(&superseded_job_id);
+        }
+        Ok(new_job_id)
     }
 
-    fn add_active_job(&mut self, job: T) {
-        // move currently active job to past jobs (so it can be marked as stale)
-        if let Some(active_job) = self.active_job.take() {
-            self.past_jobs.insert(active_job.get_job_id(), active_job);
+    fn add_active_job(&mut self, job: T) -> Result<(), JobStoreError> {
+        let job_id = job.get_job_id();
+        if self.contains_job_id(job_id) {
+            return Err(JobStoreError::JobIdCollision(job_id));
         }
+        // move currently active job to past jobs (so it can be marked as stale)
+        self.retire_active_job();
         // set the new active job
         self.active_job = Some(job);
+        Ok(())
     }
 
     fn set_active_job(&mut self, job: T) {
@@ -67,36 +245,75 @@ impl<T: Job + Clone + Debug> JobStore<T> for DefaultJobStore<T> {
     }
 
     fn activate_future_job(&mut self, template_id: u64, prev_hash_header_timestamp: u32) -> bool {
-        let mut future_job =
-            if let Some(job_id) = self.future_template_to_job_id.remove(&template_id) {
-                if let Some(job) = self.future_jobs.remove(&job_id) {
-                    job
-                } else {
-                    return false;
-                }
-            } else {
-                return false;
-            };
+        let job_id = match self.future_template_to_job_id.get(&template_id) {
+            Some(job_id) => *job_id,
+            None => return false,
+        };
+        if self.future_job_tip_eras.get(&job_id) != Some(&self.tip_era) {
+            return false;
+        }
+        let mut future_job = match self.future_jobs.remove(&job_id) {
+            Some(job) => job,
+            None => return false,
+        };
+        self.tip_era += 1;
 
         // move currently active job to past jobs (so it can be marked as stale)
-        if let Some(active_job) = self.active_job.take() {
-            self.past_jobs.insert(active_job.get_job_id(), active_job);
-        }
+        self.retire_active_job();
 
         // activate the future job
         future_job.activate(prev_hash_header_timestamp);
         self.active_job = Some(future_job);
         self.future_jobs.clear();
         self.future_template_to_job_id.clear();
+        self.future_job_tip_eras.clear();
         // mark all past jobs as stale, so that shares can be rejected with the appropriate error
-        // code
-        self.stale_jobs = self.past_jobs.clone();
-
-        // clear past jobs, as we're no longer going to validate shares for them
-        self.past_jobs.clear();
+        // code, and clear them as we're no longer going to validate shares for them
+        let retained_tips = match self.stale_retention {
+            StaleRetention::KeepAsStale(n_tips) => n_tips,
+            StaleRetention::Drop => 0,
+        };
+        let past_jobs = core::mem::take(&mut self.past_jobs);
+        let compact_past_jobs = core::mem::take(&mut self.compact_past_jobs);
+        self.demotable_past_job_ids.clear();
+        if retained_tips > 0 {
+            self.stale_job_ids_per_tip.push_back(
+                past_jobs
+                    .keys()
+                    .chain(compact_past_jobs.keys())
+                    .copied()
+                    .collect(),
+            );
+            self.stale_jobs.extend(past_jobs);
+            self.compact_stale_jobs.extend(compact_past_jobs);
+        }
+        while self.stale_job_ids_per_tip.len() > retained_tips {
+            if let Some(job_ids) = self.stale_job_ids_per_tip.pop_front() {
+                for job_id in job_ids {
+                    self.stale_jobs.remove(&job_id);
+                    self.compact_stale_jobs.remove(&job_id);
+                }
+            }
+        }
         true
     }
 
+    fn gc(&mut self) -> usize {
+        let future_jobs = &self.future_jobs;
+        let mappings = self.future_template_to_job_id.len();
+        self.future_template_to_job_id
+            .retain(|_, job_id| future_jobs.contains_key(job_id));
+        let mapped_job_ids: HashSet<u32> =
+            self.future_template_to_job_id.values().copied().collect();
+        let jobs = self.future_jobs.len();
+        self.future_jobs
+            .retain(|job_id, _| mapped_job_ids.contains(job_id));
+        let future_jobs = &self.future_jobs;
+        self.future_job_tip_eras
+            .retain(|job_id, _| future_jobs.contains_key(job_id));
+        (mappings - self.future_template_to_job_id.len()) + (jobs - self.future_jobs.len())
+    }
+
     fn get_future_template_to_job_id(&self) -> &HashMap<u64, u32> {
         &self.future_template_to_job_id
     }
@@ -116,4 +333,16 @@ impl<T: Job + Clone + Debug> JobStore<T> for DefaultJobStore<T> {
     fn get_stale_jobs(&self) -> &HashMap<u32, T> {
         &self.stale_jobs
     }
+
+    fn get_compact_past_job(&self, job_id: u32) -> Option<&CompactJob> {
+        self.compact_past_jobs.get(&job_id)
+    }
+
+    fn get_compact_stale_job(&self, job_id: u32) -> Option<&CompactJob> {
+        self.compact_stale_jobs.get(&job_id)
+    }
+
+    fn get_stale_retention(&self) -> StaleRetention {
+        self.stale_retention
+    }
 }
@@ -1,12 +1,19 @@
+pub mod compact;
 pub mod error;
 pub mod extended;
 pub mod factory;
 pub mod job_store;
 pub mod standard;
 
+use alloc::sync::Arc;
+use compact::CompactJob;
 use mining_sv2::SetCustomMiningJob;
 use template_distribution_sv2::NewTemplate;
 
+/// A `NewTemplate` shared by the jobs of every channel it was applied to, so that the template
+/// (and its merkle path in particular) is allocated once rather than once per channel.
+pub type SharedTemplate = Arc<NewTemplate<'static>>;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum JobOrigin<'a> {
     NewTemplate(NewTemplate<'a>),
@@ -16,4 +23,105 @@ pub enum JobOrigin<'a> {
 pub trait Job: Send + Sync {
     fn get_job_id(&self) -> u32;
     fn activate(&mut self, prev_hash_header_timestamp: u32);
+    /// The form the job is demoted to once it gets old, see
+    /// [`PastJobRetention`](job_store::PastJobRetention).
+    ///
+    /// `None` for jobs that can't be validated out of a [`CompactJob`], which are kept in full.
+    fn to_compact(&self) -> Option<CompactJob> {
+        None
+    }
+}
+
+/// What a channel does with a `NewTemplate` whose `template_id` already produced a job, e.g. when
+/// a Template Provider re-announces its current template after a reconnection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TemplateReplayPolicy {
+    /// Keep the existing job if the template is identical (regardless of `future_template`) and
+    /// so are the coinbase reward outputs and the extranonce prefix, and fail with a
+    /// `TemplateIdReusedWithDifferentContent` error if the template differs.
+    ///
+    /// A replayed `SetNewPrevHash` for the current chain tip and active job is also ignored.
+    #[default]
+    ReuseJob,
+    /// Always create a new job, as if the template was new.
+    NewJob,
+}
+
+/// What a channel did with a `NewTemplate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateOutcome {
+    /// A job was created for the template.
+    JobCreated,
+    /// The job already created for the template was kept, see [`TemplateReplayPolicy::ReuseJob`].
+    JobReused,
+    /// No job was created, as the channel already created as many jobs on the current chain tip
+    /// as its [`JobRateLimit`] allows.
+    SuppressedByRateLimit,
+}
+
+/// Caps the jobs a channel creates for non-future templates between two chain tips, so that a
+/// Template Provider sending templates in quick succession doesn't flood the downstream with
+/// `NewMiningJob` messages.
+///
+/// Future templates are never suppressed, as a later `SetNewPrevHash` may activate them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JobRateLimit {
+    max_jobs_per_tip: usize,
+    fee_bump_threshold: u64,
+}
+
+impl JobRateLimit {
+    /// Allows `max_jobs_per_tip` jobs for non-future templates per chain tip, without fee bump
+    /// override.
+    pub fn new(max_jobs_per_tip: usize) -> Self {
+        Self {
+            max_jobs_per_tip,
+            fee_bump_threshold: u64::MAX,
+        }
+    }
+
+    /// Still creates a job over the limit for a template whose `coinbase_tx_value_remaining`
+    /// exceeds the one of the active job by at least `fee_bump_threshold` sats.
+    pub fn with_fee_bump_threshold(mut self, fee_bump_threshold: u64) -> Self {
+        self.fee_bump_threshold = fee_bump_threshold;
+        self
+    }
+
+    pub fn max_jobs_per_tip(&self) -> usize {
+        self.max_jobs_per_tip
+    }
+
+    pub fn fee_bump_threshold(&self) -> u64 {
+        self.fee_bump_threshold
+    }
+}
+
+// Whether `replayed` carries the same template as `template`, which it may announce as future
+// or not regardless of how `template` was announced.
+pub(crate) fn is_same_template(template: &NewTemplate, replayed: &NewTemplate) -> bool {
+    let NewTemplate {
+        template_id,
+        future_template: _,
+        version,
+        coinbase_tx_version,
+        coinbase_prefix,
+        coinbase_tx_input_sequence,
+        coinbase_tx_value_remaining,
+        coinbase_tx_outputs_count,
+        coinbase_tx_outputs,
+        coinbase_tx_locktime,
+        merkle_path,
+    } = template;
+    *template_id == replayed.template_id
+        && *version == replayed.version
+        && *coinbase_tx_version == replayed.coinbase_tx_version
+        && *coinbase_prefix == replayed.coinbase_prefix
+        && *coinbase_tx_input_sequence == replayed.coinbase_tx_input_sequence
+        && *coinbase_tx_value_remaining == replayed.coinbase_tx_value_remaining
+        && *coinbase_tx_outputs_count == replayed.coinbase_tx_outputs_count
+        && *coinbase_tx_outputs == replayed.coinbase_tx_outputs
+        && *coinbase_tx_locktime == replayed.coinbase_tx_locktime
+        && *merkle_path == replayed.merkle_path
 }
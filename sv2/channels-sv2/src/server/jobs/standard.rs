@@ -1,23 +1,62 @@
 use crate::{
-    server::jobs::{error::StandardJobError, Job},
+    chain_tip::ChainTip,
+    redact::RedactedExtranoncePrefix,
+    server::jobs::{
+        compact::CompactJob,
+        error::{JobError, JobFactoryError, StandardJobError},
+        factory::ExtranoncePadding,
+        Job, SharedTemplate,
+    },
+    target::WireU256,
     template::deserialize_template_outputs,
 };
+use alloc::{sync::Arc, vec::Vec};
 use binary_sv2::{Sv2Option, U256};
-use bitcoin::transaction::TxOut;
+use bitcoin::{
+    blockdata::block::Header,
+    consensus::{deserialize, serialize},
+    transaction::TxOut,
+    BlockHash,
+};
+use core::{
+    convert::{TryFrom, TryInto},
+    fmt,
+};
 use mining_sv2::NewMiningJob;
 use template_distribution_sv2::NewTemplate;
 
 /// Abstraction of a standard mining job with:
-/// - the `NewTemplate` message that originated it
-/// - the extranonce prefix associated with the channel at the time of job creation
+/// - the `NewTemplate` message that originated it, shared with the jobs other channels created
+///   out of the same template
+/// - the extranonce prefix associated with the channel at the time of job creation, along with
+///   how it is padded in the coinbase
 /// - all coinbase outputs (spendable + unspendable) associated with the job
 /// - the `NewMiningJob` message to be sent across the wire
-#[derive(Debug, Clone)]
+/// - the previous block hash of the chain tip the job was created on or activated by, once known
+#[derive(Clone)]
 pub struct StandardJob<'a> {
-    template: NewTemplate<'a>,
+    template: SharedTemplate,
     extranonce_prefix: Vec<u8>,
+    extranonce_padding: ExtranoncePadding,
     coinbase_outputs: Vec<TxOut>,
     job_message: NewMiningJob<'a>,
+    prev_hash: Option<BlockHash>,
+}
+
+impl fmt::Debug for StandardJob<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StandardJob")
+            .field("template", &self.template)
+            .field(
+                "extranonce_prefix",
+                &RedactedExtranoncePrefix(&self.extranonce_prefix),
+            )
+            .field("extranonce_padding", &self.extranonce_padding)
+            .field("coinbase_outputs", &self.coinbase_outputs)
+            .field("job_message", &self.job_message)
+            .field("prev_hash", &self.prev_hash)
+            .finish()
+    }
 }
 
 impl Job for StandardJob<'_> {
@@ -28,9 +67,27 @@ impl Job for StandardJob<'_> {
     fn activate(&mut self, min_ntime: u32) {
         self.activate(min_ntime);
     }
+
+    fn to_compact(&self) -> Option<CompactJob> {
+        let merkle_root = self
+            .job_message
+            .merkle_root
+            .inner_as_ref()
+            .try_into()
+            .ok()?;
+        Some(CompactJob::new(
+            self.job_message.job_id,
+            self.template.template_id,
+            self.job_message.version,
+            self.activation_ntime(),
+            WireU256::from_wire_bytes(merkle_root),
+            self.extranonce_prefix.clone(),
+        ))
+    }
 }
 
 impl<'a> StandardJob<'a> {
+    /// Creates a job whose extranonce prefix is not padded, see [`ExtranoncePadding::None`].
     pub fn from_template(
         template: NewTemplate<'a>,
         extranonce_prefix: Vec<u8>,
@@ -47,12 +104,31 @@ impl<'a> StandardJob<'a> {
         coinbase_outputs.extend(additional_coinbase_outputs);
         coinbase_outputs.extend(template_coinbase_outputs);
 
-        Ok(Self {
+        Ok(Self::with_coinbase_outputs(
+            Arc::new(template.into_static()),
+            extranonce_prefix,
+            ExtranoncePadding::None,
+            coinbase_outputs,
+            job_message,
+        ))
+    }
+
+    // `coinbase_outputs` are all the outputs of the coinbase, already parsed out of `template`
+    pub(crate) fn with_coinbase_outputs(
+        template: SharedTemplate,
+        extranonce_prefix: Vec<u8>,
+        extranonce_padding: ExtranoncePadding,
+        coinbase_outputs: Vec<TxOut>,
+        job_message: NewMiningJob<'a>,
+    ) -> Self {
+        Self {
             template,
             extranonce_prefix,
+            extranonce_padding,
             coinbase_outputs,
             job_message,
-        })
+            prev_hash: None,
+        }
     }
 
     pub fn get_job_id(&self) -> u32 {
@@ -63,15 +139,37 @@ impl<'a> StandardJob<'a> {
         &self.coinbase_outputs
     }
 
+    // the coinbase reward outputs the job was created with, which come before the outputs of the
+    // template
+    pub(crate) fn get_coinbase_reward_outputs(&self) -> &[TxOut] {
+        let template_outputs = self.template.coinbase_tx_outputs_count as usize;
+        &self.coinbase_outputs[..self.coinbase_outputs.len().saturating_sub(template_outputs)]
+    }
+
     pub fn get_extranonce_prefix(&self) -> &Vec<u8> {
         &self.extranonce_prefix
     }
 
+    pub fn get_extranonce_padding(&self) -> ExtranoncePadding {
+        self.extranonce_padding
+    }
+
+    /// Returns the extranonce in the coinbase of the job: the extranonce prefix, padded the same
+    /// way as when the merkle root of the job was computed.
+    pub fn get_extranonce(&self) -> Result<Vec<u8>, JobFactoryError> {
+        self.extranonce_padding.pad(&self.extranonce_prefix)
+    }
+
     pub fn get_job_message(&self) -> &NewMiningJob<'a> {
         &self.job_message
     }
 
-    pub fn get_template(&self) -> &NewTemplate<'a> {
+    pub fn get_template(&self) -> &NewTemplate<'static> {
+        &self.template
+    }
+
+    /// Returns the template of the job, as shared with the jobs of other channels.
+    pub fn get_shared_template(&self) -> &SharedTemplate {
         &self.template
     }
 
@@ -86,4 +184,117 @@ impl<'a> StandardJob<'a> {
     pub fn activate(&mut self, min_ntime: u32) {
         self.job_message.min_ntime = Sv2Option::new(Some(min_ntime));
     }
+
+    /// Returns the previous block hash of the chain tip the job was created on or activated by.
+    ///
+    /// `None` for future jobs, and for jobs whose chain tip is not known, e.g. created by
+    /// [`StandardJob::from_template`].
+    pub fn get_prev_hash(&self) -> Option<BlockHash> {
+        self.prev_hash
+    }
+
+    pub(crate) fn set_prev_hash(&mut self, prev_hash: BlockHash) {
+        self.prev_hash = Some(prev_hash);
+    }
+
+    /// Returns the `min_ntime` the job was activated with, or created with on top of a chain tip.
+    ///
+    /// Shares for the job must have an `ntime` at least as high. `None` for future jobs.
+    pub fn activation_ntime(&self) -> Option<u32> {
+        self.job_message.min_ntime.clone().into_inner()
+    }
+
+    /// Returns the header to be hashed for this job on top of `chain_tip`, e.g. by header-only
+    /// mining devices.
+    ///
+    /// The nonce is zeroed and `ntime` is the job's `min_ntime`, or the chain tip's for future
+    /// jobs.
+    ///
+    /// Fails if the job was created on, or activated by, another chain tip, see
+    /// [`StandardJob::get_prev_hash`], or if its merkle root doesn't hold 32 bytes.
+    pub fn header_template(&self, chain_tip: &ChainTip) -> Result<Header, JobError> {
+        if matches!(self.prev_hash, Some(prev_hash) if prev_hash != chain_tip.prev_block_hash()) {
+            return Err(JobError::ChainTipMismatch);
+        }
+        let ntime = self
+            .job_message
+            .min_ntime
+            .clone()
+            .into_inner()
+            .unwrap_or_else(|| chain_tip.min_ntime());
+        let merkle_root = WireU256::try_from(&self.job_message.merkle_root)
+            .map_err(JobError::InvalidMerkleRoot)?;
+        Ok(chain_tip.header_template(self.job_message.version, merkle_root, ntime))
+    }
+
+    /// Takes a snapshot of the job, so it can be restored elsewhere.
+    pub fn to_state(&self) -> StandardJobState {
+        StandardJobState {
+            template: binary_sv2::to_bytes(NewTemplate::clone(&self.template))
+                .expect("NewTemplate must be serializable"),
+            extranonce_prefix: self.extranonce_prefix.clone(),
+            extranonce_padding: self.extranonce_padding,
+            coinbase_outputs: serialize(&self.coinbase_outputs),
+            job_message: binary_sv2::to_bytes(self.job_message.clone())
+                .expect("NewMiningJob must be serializable"),
+            prev_hash: self.prev_hash.map(WireU256::from),
+        }
+    }
+}
+
+impl StandardJob<'static> {
+    /// Restores a job from a snapshot taken with [`StandardJob::to_state`].
+    pub fn from_state(mut state: StandardJobState) -> Result<Self, StandardJobError> {
+        let template: NewTemplate = binary_sv2::from_bytes(&mut state.template)
+            .map_err(|_| StandardJobError::FailedToDeserializeState)?;
+        let job_message: NewMiningJob = binary_sv2::from_bytes(&mut state.job_message)
+            .map_err(|_| StandardJobError::FailedToDeserializeState)?;
+        let coinbase_outputs = deserialize(&state.coinbase_outputs)
+            .map_err(|_| StandardJobError::FailedToDeserializeState)?;
+
+        Ok(Self {
+            template: Arc::new(template.into_static()),
+            extranonce_prefix: state.extranonce_prefix,
+            extranonce_padding: state.extranonce_padding,
+            coinbase_outputs,
+            job_message: job_message.into_static(),
+            prev_hash: state.prev_hash.map(BlockHash::from),
+        })
+    }
+}
+
+/// A serializable snapshot of a [`StandardJob`].
+///
+/// The `NewTemplate` and `NewMiningJob` messages are kept Sv2 encoded, while the coinbase outputs
+/// are consensus encoded.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StandardJobState {
+    pub template: Vec<u8>,
+    pub extranonce_prefix: Vec<u8>,
+    // missing from snapshots older than version 7 of `ChannelState`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub extranonce_padding: ExtranoncePadding,
+    pub coinbase_outputs: Vec<u8>,
+    pub job_message: Vec<u8>,
+    // see `StandardJob::get_prev_hash`, missing from snapshots older than version 11 of
+    // `ChannelState`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub prev_hash: Option<WireU256>,
+}
+
+impl fmt::Debug for StandardJobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StandardJobState")
+            .field("template", &self.template)
+            .field(
+                "extranonce_prefix",
+                &RedactedExtranoncePrefix(&self.extranonce_prefix),
+            )
+            .field("extranonce_padding", &self.extranonce_padding)
+            .field("coinbase_outputs", &self.coinbase_outputs)
+            .field("job_message", &self.job_message)
+            .field("prev_hash", &self.prev_hash)
+            .finish()
+    }
 }
@@ -1,8 +1,19 @@
 //! Abstractions for channels to be used by mining servers.
 
+pub mod block_found;
+pub mod channel_factory;
+pub mod channel_info;
+pub mod channel_set;
 pub mod error;
+#[cfg(feature = "event-log")]
+pub mod event_log;
 pub mod extended;
 pub mod group;
+pub mod header_hasher;
+pub mod job_declaration;
 pub mod jobs;
+pub mod pending_solution;
 pub mod share_accounting;
+pub mod share_policy;
+pub mod share_validation;
 pub mod standard;
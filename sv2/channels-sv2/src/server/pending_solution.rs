@@ -0,0 +1,507 @@
+//! Bookkeeping for blocks found on templates whose transaction data was not fetched yet.
+//!
+//! When a share meets the network target, the Pool needs the transactions of the template to
+//! assemble and propagate the block. If it didn't fetch them already, it must send a
+//! `RequestTransactionData` to the Template Provider and hold the solution until the
+//! `RequestTransactionData.Success` arrives.
+//!
+//! The block is then put together with [`assemble_block`] (or [`assemble_block_bytes`], out of
+//! raw bytes), which checks it against the commitments of its header and coinbase before it's
+//! submitted.
+use crate::server::error::PendingSolutionError;
+#[cfg(feature = "std")]
+use crate::{
+    clock::{Clock, SystemClock},
+    collections::HashMap,
+};
+#[cfg(feature = "std")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitcoin::{
+    block::Header,
+    consensus::{deserialize, serialize},
+    transaction::Transaction,
+    Block,
+};
+#[cfg(feature = "std")]
+use std::time::Duration;
+#[cfg(feature = "std")]
+use template_distribution_sv2::{
+    RequestTransactionData, RequestTransactionDataError, RequestTransactionDataSuccess,
+};
+
+/// A block solution waiting for the transactions of its template.
+///
+/// `coinbase` is the serialized coinbase, as carried by
+/// [`crate::server::share_validation::ShareValidationResult::BlockFound`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockSolution {
+    pub header: Header,
+    pub coinbase: Vec<u8>,
+}
+
+/// A block completed with the transactions of its template, along with the `excess_data` of the
+/// `RequestTransactionData.Success` they came with, which the Pool may need to validate the work.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembledBlock {
+    pub block: Block,
+    pub excess_data: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct PendingSolution {
+    solution: BlockSolution,
+    // milliseconds since the Unix epoch, as told by the clock of the tracker
+    requested_at: u64,
+}
+
+/// Holds [`BlockSolution`]s, indexed by `template_id`, until the transaction data of their
+/// template arrives.
+///
+/// Solutions that don't get their transaction data within `timeout` are dropped, as measured by
+/// the [`SystemClock`] unless another [`Clock`] is set. Only available with the `std` feature.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct PendingSolutionTracker {
+    timeout: Duration,
+    clock: Arc<dyn Clock>,
+    pending: HashMap<u64, PendingSolution>,
+}
+
+#[cfg(feature = "std")]
+impl PendingSolutionTracker {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            clock: Arc::new(SystemClock),
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    // Whether `pending` has been waiting for longer than the timeout.
+    fn is_expired(&self, pending: &PendingSolution) -> bool {
+        let waited = self.clock.now_millis().saturating_sub(pending.requested_at);
+        u128::from(waited) > self.timeout.as_millis()
+    }
+
+    /// Holds `solution` until the transaction data of `template_id` arrives.
+    ///
+    /// Returns the `RequestTransactionData` message to be sent to the Template Provider. Fails if
+    /// a solution for `template_id` is already waiting for its transaction data and has not timed
+    /// out, leaving it pending.
+    pub fn add(
+        &mut self,
+        template_id: u64,
+        solution: BlockSolution,
+    ) -> Result<RequestTransactionData, PendingSolutionError> {
+        if let Some(pending) = self.pending.get(&template_id) {
+            if !self.is_expired(pending) {
+                return Err(PendingSolutionError::AlreadyPending(template_id));
+            }
+        }
+        let requested_at = self.clock.now_millis();
+        self.pending.insert(
+            template_id,
+            PendingSolution {
+                solution,
+                requested_at,
+            },
+        );
+        Ok(RequestTransactionData { template_id })
+    }
+
+    pub fn is_pending(&self, template_id: u64) -> bool {
+        self.pending.contains_key(&template_id)
+    }
+
+    /// Completes the solution waiting for the transactions carried by `message` into a full
+    /// block, passing on the `excess_data` of `message`.
+    ///
+    /// The solution is no longer pending afterwards, whatever the outcome.
+    pub fn on_transaction_data(
+        &mut self,
+        message: RequestTransactionDataSuccess<'_>,
+    ) -> Result<AssembledBlock, PendingSolutionError> {
+        let template_id = message.template_id;
+        let pending = self
+            .pending
+            .remove(&template_id)
+            .ok_or(PendingSolutionError::UnknownTemplateId(template_id))?;
+        if self.is_expired(&pending) {
+            return Err(PendingSolutionError::TimedOut(template_id));
+        }
+
+        let block = assemble_block(&pending.solution, &message.transaction_list.inner_as_ref())?;
+        Ok(AssembledBlock {
+            block,
+            excess_data: message.excess_data.inner_as_ref().to_vec(),
+        })
+    }
+
+    /// Drops the solution whose transaction data could not be provided by the Template
+    /// Provider.
+    ///
+    /// Returns the error describing why the solution was dropped.
+    pub fn on_transaction_data_error(
+        &mut self,
+        message: RequestTransactionDataError<'_>,
+    ) -> PendingSolutionError {
+        let template_id = message.template_id;
+        match self.pending.remove(&template_id) {
+            Some(_) => PendingSolutionError::TransactionDataUnavailable {
+                template_id,
+                error_code: message.error_code.as_utf8_or_hex(),
+            },
+            None => PendingSolutionError::UnknownTemplateId(template_id),
+        }
+    }
+
+    /// Drops the solutions that have been waiting for longer than the timeout, returning their
+    /// `template_id`s.
+    pub fn remove_expired(&mut self) -> Vec<u64> {
+        let expired: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| self.is_expired(pending))
+            .map(|(template_id, _)| *template_id)
+            .collect();
+        for template_id in &expired {
+            self.pending.remove(template_id);
+        }
+        expired
+    }
+}
+
+/// Assembles a block out of a [`BlockSolution`] and the serialized transactions of its template
+/// (coinbase excluded), in template order.
+///
+/// Fails if the transactions don't match the merkle root committed to by the header, or if any
+/// of them has a witness the coinbase doesn't commit to (see
+/// [`PendingSolutionError::WitnessCommitmentMismatch`]).
+pub fn assemble_block<T: AsRef<[u8]>>(
+    solution: &BlockSolution,
+    transactions: &[T],
+) -> Result<Block, PendingSolutionError> {
+    let coinbase: Transaction =
+        deserialize(&solution.coinbase).map_err(|_| PendingSolutionError::InvalidCoinbase)?;
+
+    let mut txdata = Vec::with_capacity(transactions.len() + 1);
+    txdata.push(coinbase);
+    for (position, transaction) in transactions.iter().enumerate() {
+        let transaction: Transaction = deserialize(transaction.as_ref())
+            .map_err(|_| PendingSolutionError::InvalidTransaction(position))?;
+        txdata.push(transaction);
+    }
+
+    let block = Block {
+        header: solution.header,
+        txdata,
+    };
+    if !block.check_merkle_root() {
+        return Err(PendingSolutionError::MerkleRootMismatch);
+    }
+    if !block.check_witness_commitment() {
+        return Err(PendingSolutionError::WitnessCommitmentMismatch);
+    }
+    Ok(block)
+}
+
+/// Same as [`assemble_block`], out of the serialized header and coinbase, and returning the
+/// serialized block (witnesses included), e.g. for `submitblock`.
+pub fn assemble_block_bytes<T: AsRef<[u8]>>(
+    header: &[u8],
+    coinbase: &[u8],
+    transactions: &[T],
+) -> Result<Vec<u8>, PendingSolutionError> {
+    let header: Header = deserialize(header).map_err(|_| PendingSolutionError::InvalidHeader)?;
+    let solution = BlockSolution {
+        header,
+        coinbase: coinbase.to_vec(),
+    };
+    assemble_block(&solution, transactions).map(|block| serialize(&block))
+}
+
+// the tracker needs `std`
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::testing::clock::TestClock;
+    use binary_sv2::{Seq064K, B016M};
+    use bitcoin::{
+        absolute::LockTime,
+        block::Version,
+        consensus::Decodable,
+        hashes::Hash,
+        transaction::{OutPoint, TxIn, TxOut, Version as TxVersion},
+        Amount, BlockHash, CompactTarget, ScriptBuf, Sequence, TxMerkleNode, Witness,
+    };
+    use std::convert::TryInto;
+
+    fn transaction(previous_output: OutPoint, script_sig: Vec<u8>) -> Transaction {
+        Transaction {
+            version: TxVersion::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output,
+                script_sig: script_sig.into(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    // a regtest block with a coinbase and two more transactions
+    fn regtest_block() -> Block {
+        let coinbase = transaction(OutPoint::null(), vec![1, 1]);
+        let spent = OutPoint::new(coinbase.compute_txid(), 0);
+        let txdata = vec![
+            coinbase,
+            transaction(spent, vec![]),
+            transaction(OutPoint::new(spent.txid, 1), vec![]),
+        ];
+        let mut block = Block {
+            header: Header {
+                version: Version::from_consensus(0x20000000),
+                prev_blockhash: BlockHash::all_zeros(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 1745596910,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce: 0,
+            },
+            txdata,
+        };
+        block.header.merkle_root = block.compute_merkle_root().unwrap();
+        while block.header.validate_pow(block.header.target()).is_err() {
+            block.header.nonce += 1;
+        }
+        block
+    }
+
+    fn transaction_data(template_id: u64, block: &Block) -> RequestTransactionDataSuccess<'static> {
+        let transaction_list: Vec<B016M<'static>> = block.txdata[1..]
+            .iter()
+            .map(|transaction| serialize(transaction).try_into().unwrap())
+            .collect();
+        RequestTransactionDataSuccess {
+            template_id,
+            excess_data: vec![0xde, 0xad].try_into().unwrap(),
+            transaction_list: Seq064K::new(transaction_list).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_assemble_block() {
+        let block = regtest_block();
+        let solution = BlockSolution {
+            header: block.header,
+            coinbase: serialize(&block.txdata[0]),
+        };
+        let clock = TestClock::new(1745596910000);
+        let mut tracker = PendingSolutionTracker::new(Duration::from_secs(10));
+        tracker.set_clock(Arc::new(clock.clone()));
+
+        let request = tracker.add(1, solution.clone()).unwrap();
+        assert_eq!(request.template_id, 1);
+        assert!(tracker.is_pending(1));
+
+        // a second solution for the template doesn't replace the first one
+        assert_eq!(
+            tracker.add(1, solution),
+            Err(PendingSolutionError::AlreadyPending(1))
+        );
+
+        clock.advance(Duration::from_secs(1));
+        let assembled = tracker
+            .on_transaction_data(transaction_data(1, &block))
+            .unwrap();
+        assert_eq!(assembled.block, block);
+        assert_eq!(serialize(&assembled.block), serialize(&block));
+        assert_eq!(assembled.excess_data, vec![0xde, 0xad]);
+        assert!(!tracker.is_pending(1));
+
+        // the solution is not pending anymore
+        assert_eq!(
+            tracker.on_transaction_data(transaction_data(1, &block)),
+            Err(PendingSolutionError::UnknownTemplateId(1))
+        );
+    }
+
+    #[test]
+    fn test_assemble_block_errors() {
+        let block = regtest_block();
+        let solution = BlockSolution {
+            header: block.header,
+            coinbase: serialize(&block.txdata[0]),
+        };
+        let clock = TestClock::new(1745596910000);
+        let timeout = Duration::from_secs(10);
+        let mut tracker = PendingSolutionTracker::new(timeout);
+        tracker.set_clock(Arc::new(clock.clone()));
+
+        // transactions not matching the merkle root
+        let mut swapped = block.clone();
+        swapped.txdata.swap(1, 2);
+        tracker.add(1, solution.clone()).unwrap();
+        assert_eq!(
+            tracker.on_transaction_data(transaction_data(1, &swapped)),
+            Err(PendingSolutionError::MerkleRootMismatch)
+        );
+
+        // transaction data arriving too late
+        tracker.add(2, solution.clone()).unwrap();
+        clock.advance(timeout * 2);
+        assert_eq!(
+            tracker.on_transaction_data(transaction_data(2, &block)),
+            Err(PendingSolutionError::TimedOut(2))
+        );
+
+        // the template provider doesn't know the template
+        tracker.add(3, solution.clone()).unwrap();
+        let error = RequestTransactionDataError {
+            template_id: 3,
+            error_code: "template-id-not-found".to_string().try_into().unwrap(),
+        };
+        assert_eq!(
+            tracker.on_transaction_data_error(error.clone()),
+            PendingSolutionError::TransactionDataUnavailable {
+                template_id: 3,
+                error_code: "template-id-not-found".to_string(),
+            }
+        );
+        assert_eq!(
+            tracker.on_transaction_data_error(error),
+            PendingSolutionError::UnknownTemplateId(3)
+        );
+
+        // no answer at all
+        tracker.add(4, solution).unwrap();
+        clock.advance(timeout);
+        assert_eq!(tracker.remove_expired(), Vec::<u64>::new());
+        clock.advance(timeout);
+        assert_eq!(tracker.remove_expired(), vec![4]);
+        assert!(!tracker.is_pending(4));
+    }
+
+    #[test]
+    fn test_pending_solution_timeout_boundary() {
+        let block = regtest_block();
+        let solution = BlockSolution {
+            header: block.header,
+            coinbase: serialize(&block.txdata[0]),
+        };
+        let clock = TestClock::new(1745596910000);
+        let timeout = Duration::from_secs(10);
+        let mut tracker = PendingSolutionTracker::new(timeout);
+        tracker.set_clock(Arc::new(clock.clone()));
+
+        // the transaction data is still on time when it arrives right at the end of the timeout
+        tracker.add(1, solution.clone()).unwrap();
+        clock.advance(timeout);
+        assert_eq!(
+            tracker
+                .on_transaction_data(transaction_data(1, &block))
+                .map(|assembled| assembled.block),
+            Ok(block.clone())
+        );
+
+        // but not a millisecond later
+        tracker.add(2, solution.clone()).unwrap();
+        clock.advance(timeout + Duration::from_millis(1));
+        assert_eq!(
+            tracker.on_transaction_data(transaction_data(2, &block)),
+            Err(PendingSolutionError::TimedOut(2))
+        );
+
+        // solutions expire one by one, each after its own timeout
+        tracker.add(3, solution.clone()).unwrap();
+        clock.advance(timeout / 2);
+        tracker.add(4, solution.clone()).unwrap();
+        clock.advance(timeout / 2);
+        assert!(tracker.remove_expired().is_empty());
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(tracker.remove_expired(), vec![3]);
+        assert!(tracker.is_pending(4));
+        clock.advance(timeout / 2);
+        assert_eq!(tracker.remove_expired(), vec![4]);
+        assert!(!tracker.is_pending(4));
+
+        // a timed out solution doesn't hold back a new one for its template
+        tracker.add(5, solution.clone()).unwrap();
+        clock.advance(timeout + Duration::from_millis(1));
+        tracker.add(5, solution).unwrap();
+        assert!(tracker.remove_expired().is_empty());
+    }
+
+    // a regtest block with a coinbase committing to the witness of a segwit transaction, and two
+    // more transactions
+    fn regtest_segwit_block() -> Block {
+        let mut block = regtest_block();
+        block.txdata[0].input[0].witness = Witness::from(vec![vec![0; 32]]);
+        let spent = OutPoint::new(block.txdata[0].compute_txid(), 2);
+        let mut segwit = transaction(spent, vec![]);
+        segwit.input[0].witness = Witness::from(vec![vec![1; 72], vec![2; 33]]);
+        block.txdata.push(segwit);
+
+        let witness_root = block.witness_root().unwrap();
+        let commitment = Block::compute_witness_commitment(&witness_root, &[0; 32]);
+        let mut script_pubkey = vec![0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+        script_pubkey.extend_from_slice(commitment.as_byte_array());
+        block.txdata[0].output.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script_pubkey.into(),
+        });
+
+        block.header.merkle_root = block.compute_merkle_root().unwrap();
+        block.header.nonce = 0;
+        while block.header.validate_pow(block.header.target()).is_err() {
+            block.header.nonce += 1;
+        }
+        block
+    }
+
+    #[test]
+    fn test_assemble_block_bytes() {
+        let block = regtest_segwit_block();
+        assert_eq!(block.txdata.len(), 4);
+        let header = serialize(&block.header);
+        let coinbase = serialize(&block.txdata[0]);
+        let transactions: Vec<Vec<u8>> = block.txdata[1..].iter().map(serialize).collect();
+
+        let bytes = assemble_block_bytes(&header, &coinbase, &transactions).unwrap();
+        assert_eq!(bytes, serialize(&block));
+        let decoded = Block::consensus_decode(&mut bytes.as_slice()).unwrap();
+        assert_eq!(decoded, block);
+        assert!(decoded.check_merkle_root());
+        assert!(decoded.check_witness_commitment());
+        assert_eq!(decoded.txdata[3].input[0].witness.len(), 2);
+
+        // a witness the coinbase doesn't commit to
+        let mut malleated = block.txdata[3].clone();
+        malleated.input[0].witness = Witness::from(vec![vec![3; 72], vec![2; 33]]);
+        let mut malleated_transactions = transactions.clone();
+        malleated_transactions[2] = serialize(&malleated);
+        assert_eq!(
+            assemble_block_bytes(&header, &coinbase, &malleated_transactions),
+            Err(PendingSolutionError::WitnessCommitmentMismatch)
+        );
+
+        assert_eq!(
+            assemble_block_bytes(&header[..79], &coinbase, &transactions),
+            Err(PendingSolutionError::InvalidHeader)
+        );
+        assert_eq!(
+            assemble_block_bytes(&header, &coinbase[1..], &transactions),
+            Err(PendingSolutionError::InvalidCoinbase)
+        );
+    }
+}
@@ -1,43 +1,109 @@
-//! Abstractions for share validation for a Mining Server
+//! The state of share accounting of a channel on a Mining Server: sequence numbers, accepted
+//! shares and work, acknowledgements and seen shares.
+//!
+//! The outcome of share validation lives in
+//! [`share_validation`](crate::server::share_validation).
 
-use bitcoin::hashes::sha256d::Hash;
-use std::collections::HashSet;
+use crate::collections::{HashSet, VecDeque};
+use alloc::vec::Vec;
+use bitcoin::hashes::{sha256d::Hash, Hash as _};
 
-/// The outcome of share validation, from the perspective of a Mining Server.
-///
-/// The [`ShareValidationResult::ValidWithAcknowledgement`] variant carries:
-/// - `last_sequence_number` (as `u32`)
-/// - `new_submits_accepted_count` (as `u32`)
-/// - `new_shares_sum` (as `u64`)
-///
-/// which are used to craft `SubmitShares.Success` Sv2 messages.
+// Re-exported from `share_validation`, where they moved, until the next major release.
+pub use crate::server::share_validation::{
+    validate_share_job_id, validate_share_ntime, validate_share_version, InternalInconsistency,
+    ShareValidationError, ShareValidationResult, VERSION_ROLLING_MASK, VERSION_TOP_BITS,
+    VERSION_TOP_MASK,
+};
+
+/// Number of accepted shares between acknowledgements used by [`ShareAccountingConfig::default`].
+pub const DEFAULT_SHARE_BATCH_SIZE: usize = 100;
+
+/// Number of chain tip eras retained by [`ShareAccountingConfig::default`], see
+/// [`ShareAccounting::start_new_era`].
+pub const DEFAULT_MAX_RETAINED_ERAS: usize = 8;
+
+/// The policy of a [`ShareAccounting`], consumed by [`ShareAccounting::with_config`].
 ///
-/// The [`ShareValidationResult::BlockFound`] variant carries:
-/// - `template_id` (as `Option<u64>`)
-/// - `coinbase` (as `Vec<u8>`)
+/// Built from [`ShareAccountingConfig::default`] and adjusted via its `with_*` methods, e.g.
+/// `ShareAccountingConfig::default().with_share_batch_size(10)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShareAccountingConfig {
+    share_batch_size: usize,
+    max_retained_eras: usize,
+}
+
+impl Default for ShareAccountingConfig {
+    fn default() -> Self {
+        Self {
+            share_batch_size: DEFAULT_SHARE_BATCH_SIZE,
+            max_retained_eras: DEFAULT_MAX_RETAINED_ERAS,
+        }
+    }
+}
+
+impl ShareAccountingConfig {
+    /// Sets the number of accepted shares between acknowledgements.
+    pub fn with_share_batch_size(mut self, share_batch_size: usize) -> Self {
+        self.share_batch_size = share_batch_size;
+        self
+    }
+
+    pub fn share_batch_size(&self) -> usize {
+        self.share_batch_size
+    }
+
+    /// Sets the number of chain tip eras retained, the current one included, before the oldest
+    /// completed ones are dropped. At least one era is always retained.
+    pub fn with_max_retained_eras(mut self, max_retained_eras: usize) -> Self {
+        self.max_retained_eras = max_retained_eras;
+        self
+    }
+
+    pub fn max_retained_eras(&self) -> usize {
+        self.max_retained_eras
+    }
+}
+
+impl From<usize> for ShareAccountingConfig {
+    fn from(share_batch_size: usize) -> Self {
+        Self::default().with_share_batch_size(share_batch_size)
+    }
+}
+
+/// The shares received for a single job, as returned by
+/// [`StandardChannel::get_job_share_counts`](crate::server::standard::StandardChannel::get_job_share_counts).
 ///
-/// where `template_id` is `None` if the share is for a custom job.
-#[derive(Debug)]
-pub enum ShareValidationResult {
-    Valid,
-    // last_sequence_number, new_submits_accepted_count, new_shares_sum
-    ValidWithAcknowledgement(u32, u32, u64),
-    // template_id, coinbase
-    // template_id is None if custom job
-    BlockFound(Option<u64>, Vec<u8>),
+/// Shares that keep arriving for stale jobs are a sign of firmware that doesn't switch to new
+/// jobs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JobShareCounts {
+    /// Shares accepted while the job was active or past, including blocks.
+    pub accepted: u32,
+    /// Shares received after the job became stale, including stale block candidates.
+    pub stale: u32,
+}
+
+/// The shares accepted on a channel while it was on a single chain tip, as attributed by
+/// [`ShareAccounting::start_new_era`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EraWork {
+    pub era_id: u64,
+    pub shares_accepted: u32,
+    pub share_work: u64,
 }
 
-/// The error variants that can occur during share validation
-#[derive(Debug)]
-pub enum ShareValidationError {
-    Invalid,
-    Stale,
-    InvalidJobId,
-    DoesNotMeetTarget,
-    VersionRollingNotAllowed,
-    DuplicateShare,
-    InvalidCoinbase,
-    NoChainTip,
+/// A snapshot of the counters of a [`ShareAccounting`], returned by [`ShareAccounting::stats`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShareAccountingStats {
+    pub last_share_sequence_number: u32,
+    pub shares_accepted: u32,
+    pub shares_since_acknowledgement: u32,
+    pub share_work_sum: u64,
+    pub share_work_since_acknowledgement: u64,
+    pub share_batch_size: usize,
+    pub seen_shares: usize,
+    pub best_diff: f64,
 }
 
 /// The state of share validation on the context of some specific channel (either Extended or
@@ -48,21 +114,54 @@ pub enum ShareValidationError {
 pub struct ShareAccounting {
     last_share_sequence_number: u32,
     shares_accepted: u32,
+    // shares accepted since the last acknowledgement
+    shares_since_acknowledgement: u32,
     share_work_sum: u64,
+    // work of the shares accepted since the last acknowledgement
+    share_work_since_acknowledgement: u64,
+    // whether the last accepted share completed a batch, in which case the next one starts a new
+    // batch
+    last_share_acknowledged: bool,
     share_batch_size: usize,
     seen_shares: HashSet<Hash>,
     best_diff: f64,
+    // the work of the retained chain tip eras, oldest first, the last one being the current era
+    eras: VecDeque<EraWork>,
+    max_retained_eras: usize,
 }
 
 impl ShareAccounting {
     pub fn new(share_batch_size: usize) -> Self {
+        Self::with_config(ShareAccountingConfig::from(share_batch_size))
+    }
+
+    pub fn with_config(config: ShareAccountingConfig) -> Self {
         Self {
             last_share_sequence_number: 0,
             shares_accepted: 0,
+            shares_since_acknowledgement: 0,
             share_work_sum: 0,
-            share_batch_size,
+            share_work_since_acknowledgement: 0,
+            last_share_acknowledged: false,
+            share_batch_size: config.share_batch_size,
             seen_shares: HashSet::new(),
             best_diff: 0.0,
+            eras: VecDeque::new(),
+            max_retained_eras: config.max_retained_eras.max(1),
+        }
+    }
+
+    /// Returns all the counters at once.
+    pub fn stats(&self) -> ShareAccountingStats {
+        ShareAccountingStats {
+            last_share_sequence_number: self.last_share_sequence_number,
+            shares_accepted: self.shares_accepted,
+            shares_since_acknowledgement: self.shares_since_acknowledgement,
+            share_work_sum: self.share_work_sum,
+            share_work_since_acknowledgement: self.share_work_since_acknowledgement,
+            share_batch_size: self.share_batch_size,
+            seen_shares: self.seen_shares.len(),
+            best_diff: self.best_diff,
         }
     }
 
@@ -72,10 +171,64 @@ impl ShareAccounting {
         share_sequence_number: u32,
         share_hash: Hash,
     ) {
+        // the previous share completed a batch, so a new one starts
+        if self.last_share_acknowledged {
+            self.shares_since_acknowledgement = 0;
+            self.share_work_since_acknowledgement = 0;
+        }
         self.last_share_sequence_number = share_sequence_number;
         self.shares_accepted += 1;
+        self.shares_since_acknowledgement += 1;
         self.share_work_sum += share_work;
+        self.share_work_since_acknowledgement += share_work;
+        self.last_share_acknowledged =
+            self.shares_since_acknowledgement as usize >= self.share_batch_size;
         self.seen_shares.insert(share_hash);
+        if let Some(era) = self.eras.back_mut() {
+            era.shares_accepted += 1;
+            era.share_work += share_work;
+        }
+    }
+
+    /// Attributes the shares accepted from now on to the era `era_id`, e.g. when the channel
+    /// moves to a new chain tip, so that the work of a PPLNS window can be split at block
+    /// boundaries.
+    ///
+    /// Shares accepted before the first era starts are not attributed to any era. Starting the
+    /// current era again has no effect. Once more than the configured number of eras are
+    /// retained, the oldest completed ones are dropped, whether they were drained or not.
+    pub fn start_new_era(&mut self, era_id: u64) {
+        if self.get_current_era() == Some(era_id) {
+            return;
+        }
+        self.eras.push_back(EraWork {
+            era_id,
+            shares_accepted: 0,
+            share_work: 0,
+        });
+        while self.eras.len() > self.max_retained_eras {
+            self.eras.pop_front();
+        }
+    }
+
+    /// The era the shares accepted are currently attributed to, if any was started.
+    pub fn get_current_era(&self) -> Option<u64> {
+        self.eras.back().map(|era| era.era_id)
+    }
+
+    /// Returns the work of the shares accepted during the era `era_id`, if it's retained.
+    pub fn get_era_work(&self, era_id: u64) -> Option<u64> {
+        self.eras
+            .iter()
+            .rev()
+            .find(|era| era.era_id == era_id)
+            .map(|era| era.share_work)
+    }
+
+    /// Removes and returns the retained eras but the current one, oldest first.
+    pub fn drain_completed_eras(&mut self) -> Vec<EraWork> {
+        let completed = self.eras.len().saturating_sub(1);
+        self.eras.drain(..completed).collect()
     }
 
     /// clears the hashset of seen shares
@@ -94,16 +247,63 @@ impl ShareAccounting {
         self.shares_accepted
     }
 
+    /// Returns the number of shares accepted since the last acknowledgement, which is the
+    /// `new_submits_accepted_count` of the next `SubmitShares.Success`.
+    ///
+    /// Right after an acknowledged share, this is the number of shares acknowledged by it.
+    pub fn get_shares_since_acknowledgement(&self) -> u32 {
+        self.shares_since_acknowledgement
+    }
+
+    /// Returns the work of all the shares accepted on the channel.
     pub fn get_share_work_sum(&self) -> u64 {
         self.share_work_sum
     }
 
+    /// Returns the work of the shares accepted since the last acknowledgement, which is the
+    /// `new_shares_sum` of the next `SubmitShares.Success`.
+    ///
+    /// Right after an acknowledged share, this is the work acknowledged by it.
+    pub fn get_share_work_since_acknowledgement(&self) -> u64 {
+        self.share_work_since_acknowledgement
+    }
+
     pub fn get_share_batch_size(&self) -> usize {
         self.share_batch_size
     }
 
+    /// Changes the number of accepted shares between acknowledgements.
+    ///
+    /// Shares accepted since the last acknowledgement still count towards the next one. If there
+    /// are already `share_batch_size` of them or more, the next accepted share is acknowledged,
+    /// together with all of them.
+    ///
+    /// Panics if `share_batch_size` is zero.
+    pub fn set_share_batch_size(&mut self, share_batch_size: usize) {
+        assert!(share_batch_size > 0, "share_batch_size must not be zero");
+        self.share_batch_size = share_batch_size;
+    }
+
+    /// Whether the last accepted share completes a batch of `share_batch_size` accepted shares,
+    /// and should be acknowledged with a `SubmitShares.Success`.
+    ///
+    /// Only the shares accepted by the server count: sequence numbers are chosen by the client,
+    /// so they can't make acknowledgements more or less frequent.
     pub fn should_acknowledge(&self) -> bool {
-        self.shares_accepted % self.share_batch_size as u32 == 0
+        self.last_share_acknowledged
+    }
+
+    // The result of the validation of the last accepted share, which is acknowledged if it
+    // completes a batch.
+    pub(crate) fn validation_result(&self) -> ShareValidationResult {
+        match self.should_acknowledge() {
+            true => ShareValidationResult::ValidWithAcknowledgement(
+                self.last_share_sequence_number,
+                self.shares_since_acknowledgement,
+                self.share_work_since_acknowledgement,
+            ),
+            false => ShareValidationResult::Valid,
+        }
     }
 
     /// Checks if the share has been seen.
@@ -122,4 +322,83 @@ impl ShareAccounting {
             self.best_diff = diff;
         }
     }
+
+    /// Takes a snapshot of the share accounting, so it can be restored elsewhere.
+    pub fn to_state(&self) -> ShareAccountingState {
+        ShareAccountingState {
+            last_share_sequence_number: self.last_share_sequence_number,
+            shares_accepted: self.shares_accepted,
+            shares_since_acknowledgement: self.shares_since_acknowledgement,
+            share_work_sum: self.share_work_sum,
+            share_work_since_acknowledgement: self.share_work_since_acknowledgement,
+            last_share_acknowledged: Some(self.last_share_acknowledged),
+            share_batch_size: self.share_batch_size,
+            seen_shares: self
+                .seen_shares
+                .iter()
+                .map(|hash| hash.to_byte_array())
+                .collect(),
+            best_diff: self.best_diff,
+            eras: self.eras.iter().copied().collect(),
+            max_retained_eras: self.max_retained_eras,
+        }
+    }
+
+    /// Restores the share accounting from a snapshot taken with [`ShareAccounting::to_state`].
+    pub fn from_state(state: ShareAccountingState) -> Self {
+        Self {
+            last_share_sequence_number: state.last_share_sequence_number,
+            shares_accepted: state.shares_accepted,
+            shares_since_acknowledgement: state.shares_since_acknowledgement,
+            share_work_sum: state.share_work_sum,
+            share_work_since_acknowledgement: state.share_work_since_acknowledgement,
+            last_share_acknowledged: state.last_share_acknowledged.unwrap_or(
+                state.shares_since_acknowledgement > 0
+                    && state.shares_since_acknowledgement as usize >= state.share_batch_size,
+            ),
+            share_batch_size: state.share_batch_size,
+            seen_shares: state
+                .seen_shares
+                .into_iter()
+                .map(Hash::from_byte_array)
+                .collect(),
+            best_diff: state.best_diff,
+            eras: state.eras.into_iter().collect(),
+            max_retained_eras: state.max_retained_eras.max(1),
+        }
+    }
+}
+
+/// A serializable snapshot of [`ShareAccounting`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShareAccountingState {
+    pub last_share_sequence_number: u32,
+    pub shares_accepted: u32,
+    // missing from version 1 snapshots, which restart the acknowledgement batch
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub shares_since_acknowledgement: u32,
+    pub share_work_sum: u64,
+    // missing from version 1 to 3 snapshots, which only acknowledge the work of the shares
+    // accepted after the restore
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub share_work_since_acknowledgement: u64,
+    // missing from version 1 to 8 snapshots, whose last share was acknowledged if it completed a
+    // batch
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub last_share_acknowledged: Option<bool>,
+    pub share_batch_size: usize,
+    pub seen_shares: Vec<[u8; 32]>,
+    pub best_diff: f64,
+    // missing from version 1 to 6 snapshots, which attribute shares to eras from the next chain
+    // tip on
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub eras: Vec<EraWork>,
+    #[cfg_attr(feature = "serde", serde(default = "default_max_retained_eras"))]
+    pub max_retained_eras: usize,
+}
+
+#[cfg(feature = "serde")]
+fn default_max_retained_eras() -> usize {
+    DEFAULT_MAX_RETAINED_ERAS
 }
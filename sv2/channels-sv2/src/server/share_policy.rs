@@ -0,0 +1,152 @@
+//! Hooks for the policies a Mining Server layers onto the share validation of Standard Channels.
+//!
+//! A [`SharePolicy`] set on a [`StandardChannel`](crate::server::standard::StandardChannel) via
+//! [`StandardChannel::set_share_policy`](crate::server::standard::StandardChannel::set_share_policy)
+//! is invoked at two points of `validate_share`:
+//! - [`SharePolicy::pre_validate`], once the job of the share is found and before any other
+//!   check. A rejection is returned to the caller as any other [`ShareValidationError`].
+//!   [`SharePolicy::pre_validate_compact`] stands in for it on past jobs demoted to a
+//!   [`CompactJob`] by the job store.
+//! - [`SharePolicy::post_accept`], once the share is accepted (including block solutions).
+//! - [`SharePolicy::on_block_found`], right after [`SharePolicy::post_accept`] for a share that
+//!   found a block.
+//! - [`SharePolicy::on_share_rejected`], once any check rejected the share, including the ones
+//!   made before the job of the share is found and the rejections of the policy itself.
+//!
+//! Channels without a policy behave as with [`NoSharePolicy`].
+use crate::server::{
+    block_found::BlockFoundEvent,
+    jobs::{compact::CompactJob, standard::StandardJob},
+    share_validation::{ShareValidationError, VERSION_ROLLING_MASK},
+};
+use core::fmt::Debug;
+use mining_sv2::{SubmitSharesStandard, Target};
+
+/// An accepted share, as seen by [`SharePolicy::post_accept`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShareCheck<'s> {
+    pub channel_id: u32,
+    pub user_identity: &'s str,
+    pub job_id: u32,
+    pub sequence_number: u32,
+    /// The difficulty of the share hash.
+    pub difficulty: f64,
+    pub block_found: bool,
+}
+
+/// A rejected share, as seen by [`SharePolicy::on_share_rejected`].
+#[derive(Debug, Clone, Copy)]
+pub struct ShareRejectedEvent<'s> {
+    pub channel_id: u32,
+    pub user_identity: &'s str,
+    /// The share as submitted.
+    pub share: &'s SubmitSharesStandard,
+    pub error: &'s ShareValidationError,
+    /// The target of the channel when the share was rejected.
+    pub target: &'s Target,
+    /// When the share was rejected, as a Unix timestamp in seconds.
+    ///
+    /// Always `None` without the `std` feature, which provides the system clock.
+    pub timestamp: Option<u64>,
+}
+
+/// Custom checks run by a Standard Channel on every share it validates.
+///
+/// Every hook defaults to doing nothing.
+pub trait SharePolicy: Send + Sync + Debug {
+    /// Called before the share is checked against its job, which is the active job or a past
+    /// job of the current chain tip.
+    fn pre_validate(
+        &mut self,
+        _share: &SubmitSharesStandard,
+        _job: &StandardJob,
+    ) -> Result<(), ShareValidationError> {
+        Ok(())
+    }
+
+    /// Called instead of [`SharePolicy::pre_validate`] when the job of the share is a past job
+    /// demoted to a [`CompactJob`], see
+    /// [`PastJobRetention`](crate::server::jobs::job_store::PastJobRetention).
+    ///
+    /// Policies needing more than the compact job should reject the share, or be used along
+    /// with a job store keeping every past job in full.
+    fn pre_validate_compact(
+        &mut self,
+        _share: &SubmitSharesStandard,
+        _job: &CompactJob,
+    ) -> Result<(), ShareValidationError> {
+        Ok(())
+    }
+
+    /// Called after the share is accepted.
+    fn post_accept(&mut self, _check: &ShareCheck) {}
+
+    /// Called after [`SharePolicy::post_accept`] when the share found a block, with the event
+    /// returned in the [`ShareValidationResult::BlockFound`](crate::server::share_validation::ShareValidationResult::BlockFound).
+    ///
+    /// Also called for blocks found on compact jobs, which only return the job id.
+    fn on_block_found(&mut self, _event: &BlockFoundEvent) {}
+
+    /// Called once the share is rejected, whatever the check that rejected it.
+    ///
+    /// The channel is still being borrowed by `validate_share` at this point, so the policy
+    /// must not try to reach it, e.g. by locking a mutex around it: everything it can know
+    /// about the share is in `event`.
+    fn on_share_rejected(&mut self, _event: &ShareRejectedEvent) {}
+}
+
+/// The policy that accepts every share, i.e. the behavior of a channel without policy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoSharePolicy;
+
+impl SharePolicy for NoSharePolicy {}
+
+/// Restricts the version bits a user can roll to a subset of [`VERSION_ROLLING_MASK`].
+///
+/// Shares rolling other bits are rejected with [`ShareValidationError::VersionRollingNotAllowed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxVersionRollingMask {
+    mask: u32,
+}
+
+impl MaxVersionRollingMask {
+    /// Bits of `mask` outside of [`VERSION_ROLLING_MASK`] are ignored.
+    pub fn new(mask: u32) -> Self {
+        Self {
+            mask: mask & VERSION_ROLLING_MASK,
+        }
+    }
+
+    pub fn get_mask(&self) -> u32 {
+        self.mask
+    }
+
+    fn check_version(
+        &self,
+        job_version: u32,
+        share_version: u32,
+    ) -> Result<(), ShareValidationError> {
+        if (job_version ^ share_version) & VERSION_ROLLING_MASK & !self.mask != 0 {
+            return Err(ShareValidationError::VersionRollingNotAllowed);
+        }
+        Ok(())
+    }
+}
+
+impl SharePolicy for MaxVersionRollingMask {
+    fn pre_validate(
+        &mut self,
+        share: &SubmitSharesStandard,
+        job: &StandardJob,
+    ) -> Result<(), ShareValidationError> {
+        self.check_version(job.get_job_message().version, share.version)
+    }
+
+    fn pre_validate_compact(
+        &mut self,
+        share: &SubmitSharesStandard,
+        job: &CompactJob,
+    ) -> Result<(), ShareValidationError> {
+        self.check_version(job.get_version(), share.version)
+    }
+}
@@ -0,0 +1,246 @@
+//! The outcome of share validation on a Mining Server, and the checks of a share against its job
+//! that don't depend on the state of the channel.
+//!
+//! Kept apart from the [`ShareAccounting`] state, so that code only mapping results to Sv2
+//! messages (e.g. [`ShareValidationError::error_code`] for `SubmitShares.Error`) doesn't depend
+//! on it.
+
+#[cfg(doc)]
+use crate::server::share_accounting::ShareAccounting;
+use crate::server::{block_found::BlockFoundEvent, pending_solution::BlockSolution};
+use alloc::{string::String, vec::Vec};
+
+/// The outcome of share validation, from the perspective of a Mining Server.
+///
+/// The [`ShareValidationResult::ValidWithAcknowledgement`] variant carries:
+/// - `last_sequence_number` (as `u32`)
+/// - `new_submits_accepted_count` (as `u32`)
+/// - `new_shares_sum` (as `u64`)
+///
+/// which are used to craft `SubmitShares.Success` Sv2 messages. `last_sequence_number` is the
+/// sequence number chosen by the client for the acknowledged share, while
+/// `new_submits_accepted_count` and `new_shares_sum` are counted by the server over the shares
+/// accepted since the previous acknowledgement (including the acknowledged share). Summing them
+/// over every `SubmitShares.Success` yields [`ShareAccounting::get_shares_accepted`] and
+/// [`ShareAccounting::get_share_work_sum`].
+///
+/// Acknowledgements are sent every `share_batch_size` accepted shares (see
+/// [`ShareAccounting::should_acknowledge`]), whatever the sequence numbers of the shares.
+///
+/// The [`ShareValidationResult::BlockFound`] variant carries:
+/// - `template_id` (as `Option<u64>`)
+/// - `coinbase` (as `Vec<u8>`)
+/// - `event` (as [`BlockFoundEvent`]), the structured record of the share
+///
+/// where `template_id` is `None` if the share is for a custom job.
+///
+/// The [`ShareValidationResult::StaleBlockCandidate`] variant carries:
+/// - `template_id` (as `Option<u64>`)
+/// - `solution` (as [`BlockSolution`])
+///
+/// for a share on a job of the previous chain tip that meets the network target of that chain
+/// tip, when the channel retains it. The block is only valid on the branch of the previous chain
+/// tip, which may still win a block race.
+///
+/// The [`ShareValidationResult::BlockFoundNeedsJobData`] variant carries the `job_id` of a share
+/// meeting the network target on a past job the job store demoted to a
+/// [`CompactJob`](crate::server::jobs::compact::CompactJob). The coinbase is gone along with the
+/// rest of the job, so it's up to the caller to rebuild the block out of the template it
+/// persisted for the job.
+#[derive(Debug)]
+pub enum ShareValidationResult {
+    Valid,
+    // last_sequence_number, new_submits_accepted_count, new_shares_sum
+    ValidWithAcknowledgement(u32, u32, u64),
+    // template_id, coinbase, event
+    // template_id is None if custom job
+    BlockFound(Option<u64>, Vec<u8>, BlockFoundEvent),
+    // template_id, solution
+    // template_id is None if custom job
+    StaleBlockCandidate(Option<u64>, BlockSolution),
+    // job_id
+    BlockFoundNeedsJobData(u32),
+}
+
+/// The error variants that can occur during share validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShareValidationError {
+    Invalid,
+    Stale,
+    InvalidJobId,
+    DoesNotMeetTarget,
+    VersionRollingNotAllowed,
+    DuplicateShare,
+    InvalidCoinbase,
+    NoChainTip,
+    /// The channel was paused by the server, for the given reason.
+    ChannelPaused(String),
+    /// The channel never had a job, so the share can't be for any job of it.
+    NoActiveJob,
+    /// The share `ntime` is below the `min_ntime` of its job, so its header would be rejected
+    /// by Bitcoin.
+    InvalidNtime,
+    /// The share version doesn't have the top bits of [`VERSION_TOP_MASK`] set to
+    /// [`VERSION_TOP_BITS`], so its header would not be a standard block even if it met the
+    /// network target.
+    InvalidVersion,
+    /// The share carries a full extranonce that doesn't begin with the extranonce prefix of the
+    /// channel.
+    ExtranoncePrefixMismatch,
+    /// The state of the channel is inconsistent, so the share could not be validated. Never
+    /// caused by the share itself.
+    Internal(InternalInconsistency),
+}
+
+/// An inconsistency found in the state of a channel while validating a share, e.g. between the
+/// views returned by a faulty [`JobStore`](crate::server::jobs::job_store::JobStore).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InternalInconsistency {
+    /// The job found for the `requested` job id has the `found` job id.
+    JobIdMismatch { requested: u32, found: u32 },
+    /// The merkle root of the job is not 32 bytes long.
+    MalformedMerkleRoot,
+}
+
+impl ShareValidationError {
+    /// Returns the `error_code` to be sent on a `SubmitShares.Error` message.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            ShareValidationError::Invalid => "invalid-share",
+            ShareValidationError::Stale => "stale-share",
+            // miners only need to know the job id is wrong, which one it is only matters for
+            // debugging on the server side
+            ShareValidationError::InvalidJobId | ShareValidationError::NoActiveJob => {
+                "invalid-job-id"
+            }
+            ShareValidationError::DoesNotMeetTarget => "difficulty-too-low",
+            ShareValidationError::VersionRollingNotAllowed => "version-rolling-not-allowed",
+            ShareValidationError::DuplicateShare => "duplicate-share",
+            ShareValidationError::InvalidCoinbase => "invalid-coinbase",
+            ShareValidationError::NoChainTip => "no-chain-tip",
+            ShareValidationError::ChannelPaused(_) => "channel-paused",
+            ShareValidationError::InvalidNtime => "invalid-ntime",
+            ShareValidationError::InvalidVersion => "invalid-version",
+            ShareValidationError::ExtranoncePrefixMismatch => "invalid-extranonce-prefix",
+            // as far as the miner is concerned, the job can't be mined on
+            ShareValidationError::Internal(_) => "invalid-job-id",
+        }
+    }
+}
+
+/// The bits of the block header version that can be rolled by miners, as defined in
+/// [BIP320](https://github.com/bitcoin/bips/blob/master/bip-0320.mediawiki).
+pub const VERSION_ROLLING_MASK: u32 = 0x1fffe000;
+
+/// The top bits of the block header version, which the versionbits scheme of
+/// [BIP9](https://github.com/bitcoin/bips/blob/master/bip-0009.mediawiki) sets to
+/// [`VERSION_TOP_BITS`]. They are outside of [`VERSION_ROLLING_MASK`].
+pub const VERSION_TOP_MASK: u32 = 0xe0000000;
+
+/// The value of the [`VERSION_TOP_MASK`] bits of a block header version under BIP9, i.e. `001`.
+pub const VERSION_TOP_BITS: u32 = 0x20000000;
+
+/// Checks the version of a share against the version of the job it was submitted for.
+///
+/// The share version must have the BIP9 top bits (see [`VERSION_TOP_BITS`]), e.g. it can't be
+/// 0 or have the sign bit set, or the share fails with [`ShareValidationError::InvalidVersion`].
+/// Then, if version rolling is allowed, only the bits in [`VERSION_ROLLING_MASK`] can differ
+/// from the job version. Otherwise, the share version must be equal to the job version.
+pub fn validate_share_version(
+    job_version: u32,
+    share_version: u32,
+    version_rolling_allowed: bool,
+) -> Result<(), ShareValidationError> {
+    if share_version & VERSION_TOP_MASK != VERSION_TOP_BITS {
+        return Err(ShareValidationError::InvalidVersion);
+    }
+
+    let rollable_bits = if version_rolling_allowed {
+        VERSION_ROLLING_MASK
+    } else {
+        0
+    };
+
+    if (job_version ^ share_version) & !rollable_bits != 0 {
+        return Err(ShareValidationError::VersionRollingNotAllowed);
+    }
+    Ok(())
+}
+
+/// Checks that the job found for the `job_id` of a share is the job with that id.
+pub fn validate_share_job_id(share_job_id: u32, job_id: u32) -> Result<(), ShareValidationError> {
+    if share_job_id != job_id {
+        return Err(ShareValidationError::Internal(
+            InternalInconsistency::JobIdMismatch {
+                requested: share_job_id,
+                found: job_id,
+            },
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the `ntime` of a share against the `min_ntime` of the job it was submitted for.
+pub fn validate_share_ntime(min_ntime: u32, share_ntime: u32) -> Result<(), ShareValidationError> {
+    if share_ntime < min_ntime {
+        return Err(ShareValidationError::InvalidNtime);
+    }
+    Ok(())
+}
+
+/// Checks the version and `ntime` of a share against the job it was submitted for, see
+/// [`validate_share_version`] and [`validate_share_ntime`].
+///
+/// `min_ntime` is the `min_ntime` of the job, or of the chain tip for jobs created before it.
+pub fn check_share(
+    job_version: u32,
+    min_ntime: u32,
+    share_version: u32,
+    share_ntime: u32,
+    version_rolling_allowed: bool,
+) -> Result<(), ShareValidationError> {
+    validate_share_version(job_version, share_version, version_rolling_allowed)?;
+    validate_share_ntime(min_ntime, share_ntime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_share() {
+        let version = VERSION_TOP_BITS;
+        assert_eq!(check_share(version, 100, version, 100, false), Ok(()));
+        assert_eq!(
+            check_share(version, 100, version, 99, false),
+            Err(ShareValidationError::InvalidNtime)
+        );
+        // rolled bits, only allowed with version rolling
+        let rolled = version | 0x2000;
+        assert_eq!(check_share(version, 100, rolled, 100, true), Ok(()));
+        assert_eq!(
+            check_share(version, 100, rolled, 100, false),
+            Err(ShareValidationError::VersionRollingNotAllowed)
+        );
+        // the version is checked first
+        assert_eq!(
+            check_share(version, 100, 0, 99, true),
+            Err(ShareValidationError::InvalidVersion)
+        );
+    }
+
+    #[test]
+    fn test_share_accounting_reexports() {
+        use crate::server::share_accounting;
+
+        let error: share_accounting::ShareValidationError = ShareValidationError::Stale;
+        assert_eq!(error.error_code(), "stale-share");
+        assert!(matches!(
+            ShareValidationResult::Valid,
+            share_accounting::ShareValidationResult::Valid
+        ));
+        assert_eq!(share_accounting::VERSION_ROLLING_MASK, VERSION_ROLLING_MASK);
+    }
+}
@@ -1,14 +1,51 @@
 //! Abstraction over the state of a Sv2 Standard Channel, as seen by a Mining Server
 use crate::{
-    chain_tip::ChainTip,
+    chain_tip::{ChainTip, ChainTipState},
+    clock::{default_clock, unix_timestamp, Clock},
+    collections::{HashMap, HashSet},
+    redact::{RedactedExtranoncePrefix, RedactedIdentity},
     server::{
+        block_found::BlockFoundEvent,
+        channel_info::ChannelInfoHandle,
         error::StandardChannelError,
-        jobs::{factory::JobFactory, job_store::JobStore, standard::StandardJob},
-        share_accounting::{ShareAccounting, ShareValidationError, ShareValidationResult},
+        header_hasher::{block_hash, DefaultHeaderHasher, HeaderHasher},
+        jobs::{
+            compact::CompactJob,
+            error::JobFactoryError,
+            factory::{ExtranoncePadding, JobFactory, JobIdKey},
+            is_same_template,
+            job_store::JobStore,
+            standard::{StandardJob, StandardJobState},
+            JobRateLimit, SharedTemplate, TemplateOutcome, TemplateReplayPolicy,
+        },
+        pending_solution::BlockSolution,
+        share_accounting::{
+            JobShareCounts, ShareAccounting, ShareAccountingConfig, ShareAccountingState,
+        },
+        share_policy::{ShareCheck, SharePolicy, ShareRejectedEvent},
+        share_validation::{
+            check_share, validate_share_job_id, InternalInconsistency, ShareValidationError,
+            ShareValidationResult,
+        },
+    },
+    target::{
+        compact_tolerance_bits, hash_rate_to_target, target_to_difficulty, DisplayU256, WireU256,
     },
-    target::{bytes_to_hex, hash_rate_to_target, target_to_difficulty, u256_to_block_hash},
+    trace::{debug, debug_enabled, error},
+    user_identity::{UserIdentity, UserIdentityError, UserIdentityRules},
 };
-use binary_sv2::{self};
+#[cfg(feature = "event-log")]
+use crate::{
+    clock::system_time,
+    server::event_log::{ChannelEventKind, ChannelEventLog},
+};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use binary_sv2::Sv2Option;
 use bitcoin::{
     absolute::LockTime,
     blockdata::{
@@ -20,10 +57,345 @@ use bitcoin::{
     transaction::{OutPoint, Transaction, TxIn, TxOut, Version as TxVersion},
     CompactTarget, Sequence, Target as BitcoinTarget,
 };
-use mining_sv2::{SubmitSharesStandard, Target, MAX_EXTRANONCE_LEN};
-use std::{collections::HashMap, convert::TryInto};
+use core::{
+    convert::{TryFrom, TryInto},
+    fmt,
+};
+use mining_sv2::{
+    NewMiningJob, SetExtranoncePrefix, SetNewPrevHash as SetNewPrevHashMp, SetTarget,
+    SubmitSharesStandard, Target, MAX_EXTRANONCE_LEN,
+};
 use template_distribution_sv2::{NewTemplate, SetNewPrevHash};
-use tracing::debug;
+
+/// The version of the [`ChannelState`] format produced by [`StandardChannel::export_state`].
+///
+/// Must be bumped on every change to the format.
+pub const CHANNEL_STATE_VERSION: u16 = 11;
+
+/// A serializable snapshot of a [`StandardChannel`].
+///
+/// Allows moving a channel between server instances (e.g. while draining a gateway) without
+/// the client having to reopen it.
+///
+/// Stale jobs and the previous chain tip are not part of the snapshot, so shares for stale jobs
+/// will be rejected as having an invalid job id instead of being stale after the channel is
+/// imported. The same goes for the past jobs demoted to a [`CompactJob`] by the job store.
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelState {
+    pub version: u16,
+    pub channel_id: u32,
+    pub user_identity: String,
+    pub extranonce_prefix: Vec<u8>,
+    // missing from snapshots older than version 7
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub extranonce_padding: ExtranoncePadding,
+    pub requested_max_target: WireU256,
+    pub target: WireU256,
+    // narrowed from the channel's f64 hashrate, as on the wire
+    pub nominal_hashrate: f32,
+    pub expected_share_per_minute: f32,
+    // the share rate the channel was configured with, before any clamping to the requested max
+    // target, missing from snapshots older than version 2
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub configured_share_per_minute: Option<f32>,
+    pub share_accounting: ShareAccountingState,
+    pub last_job_id: u32,
+    // the key job ids are permuted under, missing from snapshots older than version 6
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub job_id_key: Option<u64>,
+    pub version_rolling_allowed: bool,
+    // the pause reason, missing from snapshots older than version 4
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub paused: Option<String>,
+    pub chain_tip: Option<ChainTipState>,
+    pub active_job: Option<StandardJobState>,
+    pub future_jobs: Vec<StandardJobState>,
+    pub past_jobs: Vec<StandardJobState>,
+    // the policies of the channel, missing from snapshots older than version 10
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max_target_policy: MaxTargetPolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub template_replay_policy: TemplateReplayPolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub retain_previous_chain_tip: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub job_rate_limit: Option<JobRateLimit>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub defer_target_changes: bool,
+}
+
+impl fmt::Debug for ChannelState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChannelState")
+            .field("version", &self.version)
+            .field("channel_id", &self.channel_id)
+            .field("user_identity", &RedactedIdentity(&self.user_identity))
+            .field(
+                "extranonce_prefix",
+                &RedactedExtranoncePrefix(&self.extranonce_prefix),
+            )
+            .field("extranonce_padding", &self.extranonce_padding)
+            .field("requested_max_target", &self.requested_max_target)
+            .field("target", &self.target)
+            .field("nominal_hashrate", &self.nominal_hashrate)
+            .field("expected_share_per_minute", &self.expected_share_per_minute)
+            .field(
+                "configured_share_per_minute",
+                &self.configured_share_per_minute,
+            )
+            .field("share_accounting", &self.share_accounting)
+            .field("last_job_id", &self.last_job_id)
+            .field("job_id_key", &self.job_id_key.map(JobIdKey::new))
+            .field("version_rolling_allowed", &self.version_rolling_allowed)
+            .field("paused", &self.paused)
+            .field("chain_tip", &self.chain_tip)
+            .field("active_job", &self.active_job)
+            .field("future_jobs", &self.future_jobs)
+            .field("past_jobs", &self.past_jobs)
+            .field("max_target_policy", &self.max_target_policy)
+            .field("template_replay_policy", &self.template_replay_policy)
+            .field("retain_previous_chain_tip", &self.retain_previous_chain_tip)
+            .field("job_rate_limit", &self.job_rate_limit)
+            .field("defer_target_changes", &self.defer_target_changes)
+            .finish()
+    }
+}
+
+/// A message to be sent to the downstream of a [`StandardChannel`], as returned by
+/// [`StandardChannel::initial_downstream_messages`].
+#[derive(Debug, Clone)]
+pub enum DownstreamMessage<'a> {
+    NewMiningJob(NewMiningJob<'a>),
+    SetNewPrevHash(SetNewPrevHashMp<'a>),
+}
+
+/// What a [`StandardChannel`] needs to pick up where a previous one left off, e.g. when the
+/// downstream reconnects after a dropped connection.
+///
+/// Exported via [`StandardChannel::resume_hint`] and consumed by
+/// [`StandardChannel::new_with_resume_hint`], so the miner doesn't restart vardiff from scratch.
+/// Persisting it across connections is up to the caller.
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelResumeHint {
+    pub user_identity: String,
+    pub last_target: WireU256,
+    // narrowed from the channel's f64 hashrate, as on the wire
+    pub last_nominal_hashrate: f32,
+    pub extranonce_prefix: Vec<u8>,
+}
+
+impl fmt::Debug for ChannelResumeHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChannelResumeHint")
+            .field("user_identity", &RedactedIdentity(&self.user_identity))
+            .field("last_target", &self.last_target)
+            .field("last_nominal_hashrate", &self.last_nominal_hashrate)
+            .field(
+                "extranonce_prefix",
+                &RedactedExtranoncePrefix(&self.extranonce_prefix),
+            )
+            .finish()
+    }
+}
+
+/// What a [`StandardChannel`] does when the target computed from the nominal hashrate is above
+/// the maximum target requested by the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MaxTargetPolicy {
+    /// Fail with [`StandardChannelError::RequestedMaxTargetOutOfRange`], so the caller can reply
+    /// with an `OpenMiningChannel.Error` (`max-target-out-of-range`).
+    #[default]
+    Reject,
+    /// Use the requested maximum target instead, lowering the expected share rate accordingly.
+    ClampToRequestedMax,
+}
+
+/// Everything needed to open a [`StandardChannel`] via [`StandardChannel::from_config`].
+///
+/// Built from [`StandardChannelConfig::default`] by chaining a setter per field, e.g.
+/// `StandardChannelConfig::default().channel_id(1).user_identity(..)`. The channel id, user
+/// identity, extranonce prefix, requested max target, nominal hashrate and expected share rate
+/// must be set, while the share accounting config and the [`MaxTargetPolicy`] default to
+/// [`ShareAccountingConfig::default`] and [`MaxTargetPolicy::Reject`], the user identity is
+/// checked against the default [`UserIdentityRules`], replayed templates are handled with
+/// [`TemplateReplayPolicy::ReuseJob`], the previous chain tip is not retained, job ids are
+/// sequential, the extranonce prefix is not padded, jobs are not rate limited and target changes
+/// are not deferred.
+#[derive(Clone, Default)]
+pub struct StandardChannelConfig {
+    channel_id: Option<u32>,
+    user_identity: Option<String>,
+    user_identity_rules: UserIdentityRules,
+    extranonce_prefix: Option<Vec<u8>>,
+    extranonce_padding: ExtranoncePadding,
+    requested_max_target: Option<Target>,
+    nominal_hashrate: Option<f64>,
+    expected_share_per_minute: Option<f32>,
+    share_accounting_config: ShareAccountingConfig,
+    max_target_policy: MaxTargetPolicy,
+    template_replay_policy: TemplateReplayPolicy,
+    retain_previous_chain_tip: bool,
+    job_rate_limit: Option<JobRateLimit>,
+    defer_target_changes: bool,
+    initial_target: Option<Target>,
+    #[cfg(feature = "std")]
+    obfuscate_job_ids: bool,
+}
+
+impl StandardChannelConfig {
+    pub fn channel_id(mut self, channel_id: u32) -> Self {
+        self.channel_id = Some(channel_id);
+        self
+    }
+
+    /// Takes a plain string or an already parsed [`UserIdentity`], which is (re)checked against
+    /// the configured [`UserIdentityRules`] by [`StandardChannel::from_config`].
+    pub fn user_identity(mut self, user_identity: impl Into<String>) -> Self {
+        self.user_identity = Some(user_identity.into());
+        self
+    }
+
+    pub fn user_identity_rules(mut self, user_identity_rules: UserIdentityRules) -> Self {
+        self.user_identity_rules = user_identity_rules;
+        self
+    }
+
+    /// At most `MAX_EXTRANONCE_LEN` bytes, as a Standard Channel leaves nothing of the extranonce
+    /// to roll (see the layouts in [`crate::extranonce`]). Checked by
+    /// [`StandardChannel::from_config`].
+    pub fn extranonce_prefix(mut self, extranonce_prefix: Vec<u8>) -> Self {
+        self.extranonce_prefix = Some(extranonce_prefix);
+        self
+    }
+
+    /// How the extranonce prefix fills the extranonce reserved in the coinbase of the jobs, see
+    /// [`JobFactory::with_extranonce_padding`].
+    ///
+    /// With [`ExtranoncePadding::ZeroFill`], the extranonce prefix (and any later one) can't be
+    /// longer than `total_len`.
+    pub fn extranonce_padding(mut self, extranonce_padding: ExtranoncePadding) -> Self {
+        self.extranonce_padding = extranonce_padding;
+        self
+    }
+
+    pub fn requested_max_target(mut self, requested_max_target: Target) -> Self {
+        self.requested_max_target = Some(requested_max_target);
+        self
+    }
+
+    /// Takes the `f32` hashrate of an `OpenStandardMiningChannel` as well as an `f64` one, which
+    /// is what the channel keeps: beyond ~1e17 H/s, consecutive `f32` values are further apart
+    /// than the hashrate steps of a large farm.
+    pub fn nominal_hashrate(mut self, nominal_hashrate: impl Into<f64>) -> Self {
+        self.nominal_hashrate = Some(nominal_hashrate.into());
+        self
+    }
+
+    pub fn expected_share_per_minute(mut self, expected_share_per_minute: f32) -> Self {
+        self.expected_share_per_minute = Some(expected_share_per_minute);
+        self
+    }
+
+    pub fn share_accounting_config(
+        mut self,
+        share_accounting_config: ShareAccountingConfig,
+    ) -> Self {
+        self.share_accounting_config = share_accounting_config;
+        self
+    }
+
+    /// Shorthand to only change the share batch size of the share accounting config.
+    pub fn share_batch_size(mut self, share_batch_size: usize) -> Self {
+        self.share_accounting_config = self
+            .share_accounting_config
+            .with_share_batch_size(share_batch_size);
+        self
+    }
+
+    pub fn max_target_policy(mut self, max_target_policy: MaxTargetPolicy) -> Self {
+        self.max_target_policy = max_target_policy;
+        self
+    }
+
+    pub fn template_replay_policy(mut self, template_replay_policy: TemplateReplayPolicy) -> Self {
+        self.template_replay_policy = template_replay_policy;
+        self
+    }
+
+    /// See [`StandardChannel::set_retain_previous_chain_tip`].
+    pub fn retain_previous_chain_tip(mut self, retain_previous_chain_tip: bool) -> Self {
+        self.retain_previous_chain_tip = retain_previous_chain_tip;
+        self
+    }
+
+    /// See [`StandardChannel::set_job_rate_limit`].
+    pub fn job_rate_limit(mut self, job_rate_limit: JobRateLimit) -> Self {
+        self.job_rate_limit = Some(job_rate_limit);
+        self
+    }
+
+    /// See [`StandardChannel::set_defer_target_changes`].
+    pub fn defer_target_changes(mut self, defer_target_changes: bool) -> Self {
+        self.defer_target_changes = defer_target_changes;
+        self
+    }
+
+    /// Opens the channel on `initial_target` instead of the target derived from the nominal
+    /// hashrate and the expected share rate, e.g. the last target of a resumed channel.
+    ///
+    /// The target is capped to the requested max target, whatever the [`MaxTargetPolicy`].
+    pub fn initial_target(mut self, initial_target: Target) -> Self {
+        self.initial_target = Some(initial_target);
+        self
+    }
+
+    /// Whether job ids are permuted under a key generated for the channel, see
+    /// [`JobFactory::with_job_id_key`].
+    ///
+    /// Only available with the `std` feature, which provides the randomness keys are drawn from.
+    #[cfg(feature = "std")]
+    pub fn obfuscate_job_ids(mut self, obfuscate_job_ids: bool) -> Self {
+        self.obfuscate_job_ids = obfuscate_job_ids;
+        self
+    }
+}
+
+impl fmt::Debug for StandardChannelConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("StandardChannelConfig");
+        debug
+            .field("channel_id", &self.channel_id)
+            .field(
+                "user_identity",
+                &self.user_identity.as_deref().map(RedactedIdentity),
+            )
+            .field("user_identity_rules", &self.user_identity_rules)
+            .field(
+                "extranonce_prefix",
+                &self
+                    .extranonce_prefix
+                    .as_deref()
+                    .map(RedactedExtranoncePrefix),
+            )
+            .field("extranonce_padding", &self.extranonce_padding)
+            .field("requested_max_target", &self.requested_max_target)
+            .field("nominal_hashrate", &self.nominal_hashrate)
+            .field("expected_share_per_minute", &self.expected_share_per_minute)
+            .field("share_accounting_config", &self.share_accounting_config)
+            .field("max_target_policy", &self.max_target_policy)
+            .field("template_replay_policy", &self.template_replay_policy)
+            .field("retain_previous_chain_tip", &self.retain_previous_chain_tip)
+            .field("job_rate_limit", &self.job_rate_limit)
+            .field("defer_target_changes", &self.defer_target_changes)
+            .field("initial_target", &self.initial_target);
+        #[cfg(feature = "std")]
+        debug.field("obfuscate_job_ids", &self.obfuscate_job_ids);
+        debug.finish()
+    }
+}
 
 /// Abstraction of a Sv2 Standard Channel.
 ///
@@ -32,7 +404,8 @@ use tracing::debug;
 /// - the channel's `user_identity`
 /// - the channel's unique `extranonce_prefix`
 /// - the channel's requested max target (limit established by the client)
-/// - the channel's target
+/// - the channel's target, and the target to apply on the next job if target changes are
+///   deferred (see [`StandardChannel::set_defer_target_changes`])
 /// - the channel's nominal hashrate
 /// - the channel's active job
 /// - the channel's future jobs (indexed by `template_id`, to be activated upon receipt of a
@@ -43,59 +416,483 @@ use tracing::debug;
 ///   indexed by `job_id`)
 /// - the channel's job factory
 /// - the channel's chain tip
-#[derive(Debug)]
+/// - the channel's previous chain tip, if it's retained (see
+///   [`StandardChannel::set_retain_previous_chain_tip`])
+/// - the channel's [`MaxTargetPolicy`]
+/// - the channel's [`JobRateLimit`], if any, and the jobs created on the current chain tip
+/// - whether the channel is paused, and why
+/// - the [`HeaderHasher`] shares are hashed with
+/// - the [`ChannelInfoHandle`] its target, nominal hashrate and share accounting are published to
+/// - the channel's event log, with the `event-log` feature
 pub struct StandardChannel<'a> {
     pub channel_id: u32,
-    user_identity: String,
+    user_identity: UserIdentity,
     extranonce_prefix: Vec<u8>,
     requested_max_target: Target,
-    target: Target,
-    nominal_hashrate: f32,
+    defer_target_changes: bool,
+    // the target computed while target changes are deferred, along with its share rate, until the
+    // next job is activated
+    pending_target: Option<(Target, f32)>,
+    // the deferred target applied by the latest job activation, until its `SetTarget` is taken
+    deferred_set_target: Option<Target>,
     share_accounting: ShareAccounting,
+    // the share rate the target is derived from, scaled down while the target is clamped to the
+    // requested max target
     expected_share_per_minute: f32,
+    // the share rate the channel was configured with, never scaled down
+    configured_share_per_minute: f32,
     job_store: Box<dyn JobStore<StandardJob<'a>>>,
     job_factory: JobFactory,
     chain_tip: Option<ChainTip>,
+    retain_previous_chain_tip: bool,
+    // the chain tip before the current one, along with the ids of the jobs mined on it
+    previous_chain_tip: Option<(ChainTip, HashSet<u32>)>,
+    max_target_policy: MaxTargetPolicy,
+    template_replay_policy: TemplateReplayPolicy,
+    job_rate_limit: Option<JobRateLimit>,
+    // the jobs created for non-future templates since the chain tip was replaced
+    jobs_on_tip: usize,
+    // the reason the channel is paused for, if it is
+    paused: Option<String>,
+    // shares received per job_id, for the jobs still in the job store
+    job_share_counts: HashMap<u32, JobShareCounts>,
+    // the target in force when each job was activated, as deferred target changes only apply to
+    // the jobs activated after them. Cleared when the target changes for all jobs at once.
+    job_targets: HashMap<u32, Target>,
+    // set when the job for the latest chain tip or template could not be created, so that shares
+    // are not validated against an outdated active job
+    job_missing: bool,
+    // the future templates the job factory failed on, until the next `SetNewPrevHash`
+    failed_future_templates: HashSet<u64>,
+    share_policy: Option<Box<dyn SharePolicy>>,
+    header_hasher: Arc<dyn HeaderHasher>,
+    // `None` without the `std` feature, unless set
+    clock: Option<Arc<dyn Clock>>,
+    info: ChannelInfoHandle,
+    #[cfg(feature = "event-log")]
+    event_log: ChannelEventLog,
+}
+
+impl fmt::Debug for StandardChannel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("StandardChannel");
+        debug
+            .field("channel_id", &self.channel_id)
+            .field("user_identity", &self.user_identity)
+            .field(
+                "extranonce_prefix",
+                &RedactedExtranoncePrefix(&self.extranonce_prefix),
+            )
+            .field("requested_max_target", &self.requested_max_target)
+            .field("target", &self.get_target())
+            .field("defer_target_changes", &self.defer_target_changes)
+            .field("pending_target", &self.pending_target)
+            .field("deferred_set_target", &self.deferred_set_target)
+            .field("nominal_hashrate", &self.get_nominal_hashrate())
+            .field("share_accounting", &self.share_accounting)
+            .field("expected_share_per_minute", &self.expected_share_per_minute)
+            .field(
+                "configured_share_per_minute",
+                &self.configured_share_per_minute,
+            )
+            .field("job_store", &self.job_store)
+            .field("job_factory", &self.job_factory)
+            .field("chain_tip", &self.chain_tip)
+            .field("retain_previous_chain_tip", &self.retain_previous_chain_tip)
+            .field("previous_chain_tip", &self.previous_chain_tip)
+            .field("max_target_policy", &self.max_target_policy)
+            .field("template_replay_policy", &self.template_replay_policy)
+            .field("job_rate_limit", &self.job_rate_limit)
+            .field("jobs_on_tip", &self.jobs_on_tip)
+            .field("paused", &self.paused)
+            .field("job_share_counts", &self.job_share_counts)
+            .field("job_targets", &self.job_targets)
+            .field("job_missing", &self.job_missing)
+            .field("failed_future_templates", &self.failed_future_templates)
+            .field("share_policy", &self.share_policy)
+            .field("header_hasher", &self.header_hasher)
+            .field("clock", &self.clock)
+            .field("info", &self.info);
+        #[cfg(feature = "event-log")]
+        debug.field("event_log", &self.event_log);
+        debug.finish()
+    }
 }
 
 impl<'a> StandardChannel<'a> {
+    /// Creates a channel with the [`MaxTargetPolicy::Reject`] policy.
+    ///
+    /// `share_accounting_config` is either a [`ShareAccountingConfig`] or just the share batch
+    /// size, the rest of the config being the default one.
+    #[deprecated(note = "use `StandardChannel::from_config` instead")]
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         channel_id: u32,
-        user_identity: String,
+        user_identity: impl TryInto<UserIdentity, Error = impl Into<UserIdentityError>>,
         extranonce_prefix: Vec<u8>,
         requested_max_target: Target,
         nominal_hashrate: f32,
-        share_batch_size: usize,
+        share_accounting_config: impl Into<ShareAccountingConfig>,
         expected_share_per_minute: f32,
         job_store: Box<dyn JobStore<StandardJob<'a>>>,
     ) -> Result<Self, StandardChannelError> {
-        let calculated_target =
-            match hash_rate_to_target(nominal_hashrate.into(), expected_share_per_minute.into()) {
-                Ok(target_u256) => target_u256,
-                Err(_) => {
-                    return Err(StandardChannelError::InvalidNominalHashrate);
-                }
-            };
+        let user_identity = user_identity
+            .try_into()
+            .map_err(|e| StandardChannelError::InvalidUserIdentity(e.into()))?;
+        Self::from_config(
+            StandardChannelConfig::default()
+                .channel_id(channel_id)
+                .user_identity(user_identity)
+                .extranonce_prefix(extranonce_prefix)
+                .requested_max_target(requested_max_target)
+                .nominal_hashrate(nominal_hashrate)
+                .share_accounting_config(share_accounting_config.into())
+                .expected_share_per_minute(expected_share_per_minute),
+            job_store,
+        )
+    }
 
-        let target: Target = calculated_target.into();
+    /// Creates a channel, handling a target above `requested_max_target` according to
+    /// `max_target_policy`.
+    #[deprecated(note = "use `StandardChannel::from_config` instead")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_max_target_policy(
+        channel_id: u32,
+        user_identity: impl TryInto<UserIdentity, Error = impl Into<UserIdentityError>>,
+        extranonce_prefix: Vec<u8>,
+        requested_max_target: Target,
+        nominal_hashrate: f32,
+        share_accounting_config: impl Into<ShareAccountingConfig>,
+        expected_share_per_minute: f32,
+        job_store: Box<dyn JobStore<StandardJob<'a>>>,
+        max_target_policy: MaxTargetPolicy,
+    ) -> Result<Self, StandardChannelError> {
+        let user_identity = user_identity
+            .try_into()
+            .map_err(|e| StandardChannelError::InvalidUserIdentity(e.into()))?;
+        Self::from_config(
+            StandardChannelConfig::default()
+                .channel_id(channel_id)
+                .user_identity(user_identity)
+                .extranonce_prefix(extranonce_prefix)
+                .requested_max_target(requested_max_target)
+                .nominal_hashrate(nominal_hashrate)
+                .share_accounting_config(share_accounting_config.into())
+                .expected_share_per_minute(expected_share_per_minute)
+                .max_target_policy(max_target_policy),
+            job_store,
+        )
+    }
 
-        if target > requested_max_target {
-            return Err(StandardChannelError::RequestedMaxTargetOutOfRange);
+    /// Creates a channel out of `config`, see [`StandardChannelConfig`].
+    ///
+    /// With [`MaxTargetPolicy::ClampToRequestedMax`], the expected share rate is scaled down to
+    /// what the nominal hashrate yields at the requested max target, so that the target can
+    /// still be derived from the nominal hashrate and the expected share rate.
+    pub fn from_config(
+        config: StandardChannelConfig,
+        job_store: Box<dyn JobStore<StandardJob<'a>>>,
+    ) -> Result<Self, StandardChannelError> {
+        let StandardChannelConfig {
+            channel_id,
+            user_identity,
+            user_identity_rules,
+            extranonce_prefix,
+            extranonce_padding,
+            requested_max_target,
+            nominal_hashrate,
+            expected_share_per_minute,
+            share_accounting_config,
+            max_target_policy,
+            template_replay_policy,
+            retain_previous_chain_tip,
+            job_rate_limit,
+            defer_target_changes,
+            initial_target,
+            #[cfg(feature = "std")]
+            obfuscate_job_ids,
+        } = config;
+
+        let channel_id =
+            channel_id.ok_or(StandardChannelError::MissingConfigField("channel_id"))?;
+        let user_identity =
+            user_identity.ok_or(StandardChannelError::MissingConfigField("user_identity"))?;
+        let user_identity = UserIdentity::parse(&user_identity, &user_identity_rules)
+            .map_err(StandardChannelError::InvalidUserIdentity)?;
+        let extranonce_prefix = extranonce_prefix.ok_or(
+            StandardChannelError::MissingConfigField("extranonce_prefix"),
+        )?;
+        let requested_max_target = requested_max_target.ok_or(
+            StandardChannelError::MissingConfigField("requested_max_target"),
+        )?;
+        let nominal_hashrate =
+            nominal_hashrate.ok_or(StandardChannelError::MissingConfigField("nominal_hashrate"))?;
+        let expected_share_per_minute = expected_share_per_minute.ok_or(
+            StandardChannelError::MissingConfigField("expected_share_per_minute"),
+        )?;
+
+        if extranonce_padding.total_len() > MAX_EXTRANONCE_LEN {
+            return Err(StandardChannelError::JobFactoryError(
+                JobFactoryError::InvalidExtranoncePadding(extranonce_padding.total_len()),
+            ));
+        }
+        if extranonce_prefix.len() > extranonce_padding.total_len() {
+            return Err(StandardChannelError::NewExtranoncePrefixTooLarge);
         }
+        if !nominal_hashrate.is_finite() || nominal_hashrate < 0.0 {
+            return Err(StandardChannelError::InvalidNominalHashrate);
+        }
+        if !expected_share_per_minute.is_finite() || expected_share_per_minute <= 0.0 {
+            return Err(StandardChannelError::InvalidExpectedSharePerMinute);
+        }
+        if share_accounting_config.share_batch_size() == 0 {
+            return Err(StandardChannelError::InvalidShareBatchSize);
+        }
+
+        let configured_share_per_minute = expected_share_per_minute;
+        let (target, expected_share_per_minute) = match initial_target {
+            Some(initial_target) if initial_target > requested_max_target => {
+                (requested_max_target.clone(), configured_share_per_minute)
+            }
+            Some(initial_target) => (initial_target, configured_share_per_minute),
+            None => {
+                let calculated_target =
+                    match hash_rate_to_target(nominal_hashrate, expected_share_per_minute.into()) {
+                        Ok(target_u256) => target_u256,
+                        Err(_) => {
+                            return Err(StandardChannelError::InvalidNominalHashrate);
+                        }
+                    };
+                apply_max_target_policy(
+                    calculated_target.into(),
+                    &requested_max_target,
+                    configured_share_per_minute,
+                    max_target_policy,
+                )?
+            }
+        };
+
+        let job_factory = JobFactory::new(true).with_extranonce_padding(extranonce_padding);
+        #[cfg(feature = "std")]
+        let job_factory = match obfuscate_job_ids {
+            true => job_factory.with_job_id_key(JobIdKey::random()),
+            false => job_factory,
+        };
+
+        let share_accounting = ShareAccounting::with_config(share_accounting_config);
+        let info = ChannelInfoHandle::new(&target, nominal_hashrate, &share_accounting);
 
         Ok(Self {
             channel_id,
             user_identity,
             extranonce_prefix,
             requested_max_target,
-            target,
-            nominal_hashrate,
-            share_accounting: ShareAccounting::new(share_batch_size),
+            defer_target_changes,
+            pending_target: None,
+            deferred_set_target: None,
+            share_accounting,
             expected_share_per_minute,
-            job_factory: JobFactory::new(true),
+            configured_share_per_minute,
+            job_factory,
             chain_tip: None,
+            retain_previous_chain_tip,
+            previous_chain_tip: None,
+            job_store,
+            max_target_policy,
+            template_replay_policy,
+            job_rate_limit,
+            jobs_on_tip: 0,
+            paused: None,
+            job_share_counts: HashMap::new(),
+            job_targets: HashMap::new(),
+            job_missing: false,
+            failed_future_templates: HashSet::new(),
+            share_policy: None,
+            header_hasher: Arc::new(DefaultHeaderHasher),
+            clock: default_clock(),
+            info,
+            #[cfg(feature = "event-log")]
+            event_log: ChannelEventLog::default(),
+        })
+    }
+
+    /// Creates a channel resuming a previous one, described by `hint`.
+    ///
+    /// The user identity, extranonce prefix, target and nominal hashrate are taken from `hint`.
+    /// The caller is responsible for making sure the extranonce prefix is still not in use by
+    /// another channel.
+    ///
+    /// If the previous target is above `requested_max_target`, the target is capped to
+    /// `requested_max_target`.
+    pub fn new_with_resume_hint(
+        channel_id: u32,
+        hint: ChannelResumeHint,
+        requested_max_target: Target,
+        share_accounting_config: impl Into<ShareAccountingConfig>,
+        expected_share_per_minute: f32,
+        job_store: Box<dyn JobStore<StandardJob<'a>>>,
+    ) -> Result<Self, StandardChannelError> {
+        Self::from_config(
+            StandardChannelConfig::default()
+                .channel_id(channel_id)
+                .user_identity(hint.user_identity)
+                .extranonce_prefix(hint.extranonce_prefix)
+                .requested_max_target(requested_max_target)
+                .nominal_hashrate(hint.last_nominal_hashrate)
+                .share_accounting_config(share_accounting_config.into())
+                .expected_share_per_minute(expected_share_per_minute)
+                .initial_target(hint.last_target.into()),
+            job_store,
+        )
+    }
+
+    /// Returns what's needed to resume this channel on a later connection via
+    /// [`StandardChannel::new_with_resume_hint`].
+    ///
+    /// The last target is the pending one, if any, as the channel resumes on a new job anyway.
+    pub fn resume_hint(&self) -> ChannelResumeHint {
+        ChannelResumeHint {
+            user_identity: self.user_identity.to_string(),
+            last_target: WireU256::from(
+                &self
+                    .pending_target
+                    .as_ref()
+                    .map(|(target, _)| target.clone())
+                    .unwrap_or_else(|| self.get_target()),
+            ),
+            last_nominal_hashrate: self.get_nominal_hashrate() as f32,
+            extranonce_prefix: self.extranonce_prefix.clone(),
+        }
+    }
+
+    /// Takes a snapshot of the channel state, to be imported on another server instance via
+    /// [`StandardChannel::import_state`].
+    pub fn export_state(&self) -> ChannelState {
+        let mut past_jobs: Vec<&StandardJob<'a>> =
+            self.job_store.get_past_jobs().values().collect();
+        past_jobs.sort_by_key(|job| job.get_job_id());
+
+        ChannelState {
+            version: CHANNEL_STATE_VERSION,
+            channel_id: self.channel_id,
+            user_identity: self.user_identity.to_string(),
+            extranonce_prefix: self.extranonce_prefix.clone(),
+            extranonce_padding: self.job_factory.get_extranonce_padding(),
+            requested_max_target: WireU256::from(&self.requested_max_target),
+            target: WireU256::from(&self.get_target()),
+            nominal_hashrate: self.get_nominal_hashrate() as f32,
+            expected_share_per_minute: self.expected_share_per_minute,
+            configured_share_per_minute: Some(self.configured_share_per_minute),
+            share_accounting: self.share_accounting.to_state(),
+            last_job_id: self.job_factory.get_last_job_id(),
+            job_id_key: self.job_factory.get_job_id_key().map(|key| key.to_u64()),
+            version_rolling_allowed: self.job_factory.is_version_rolling_allowed(),
+            paused: self.paused.clone(),
+            chain_tip: self
+                .chain_tip
+                .as_ref()
+                .map(|chain_tip| chain_tip.to_state()),
+            active_job: self.job_store.get_active_job().map(|job| job.to_state()),
+            future_jobs: self
+                .job_store
+                .get_future_jobs()
+                .values()
+                .map(|job| job.to_state())
+                .collect(),
+            past_jobs: past_jobs.into_iter().map(|job| job.to_state()).collect(),
+            max_target_policy: self.max_target_policy,
+            template_replay_policy: self.template_replay_policy,
+            retain_previous_chain_tip: self.retain_previous_chain_tip,
+            job_rate_limit: self.job_rate_limit,
+            defer_target_changes: self.defer_target_changes,
+        }
+    }
+
+    /// Restores a channel from a snapshot taken with [`StandardChannel::export_state`].
+    ///
+    /// `job_store` is expected to be empty, and is populated with the jobs from the snapshot.
+    ///
+    /// Fails if the snapshot was produced by a newer version of this crate.
+    pub fn import_state(
+        state: ChannelState,
+        mut job_store: Box<dyn JobStore<StandardJob<'a>>>,
+    ) -> Result<Self, StandardChannelError> {
+        if state.version > CHANNEL_STATE_VERSION {
+            return Err(StandardChannelError::UnsupportedStateVersion(state.version));
+        }
+        let user_identity = UserIdentity::try_from(state.user_identity)
+            .map_err(StandardChannelError::InvalidUserIdentity)?;
+
+        for job_state in state.future_jobs {
+            let job = StandardJob::from_state(job_state)
+                .map_err(|_| StandardChannelError::InvalidState)?;
+            job_store
+                .add_future_job(job.get_template().template_id, job)
+                .map_err(StandardChannelError::JobStoreError)?;
+        }
+
+        // past jobs can only exist alongside an active job
+        if let Some(active_job) = state.active_job {
+            // every job added as active moves the previous one into the past jobs
+            for job_state in state.past_jobs {
+                let job = StandardJob::from_state(job_state)
+                    .map_err(|_| StandardChannelError::InvalidState)?;
+                job_store
+                    .add_active_job(job)
+                    .map_err(StandardChannelError::JobStoreError)?;
+            }
+            let job = StandardJob::from_state(active_job)
+                .map_err(|_| StandardChannelError::InvalidState)?;
+            job_store
+                .add_active_job(job)
+                .map_err(StandardChannelError::JobStoreError)?;
+        }
+
+        let mut job_factory =
+            JobFactory::with_last_job_id(state.version_rolling_allowed, state.last_job_id)
+                .with_extranonce_padding(state.extranonce_padding);
+        if let Some(key) = state.job_id_key {
+            job_factory = job_factory.with_job_id_key(JobIdKey::new(key));
+        }
+
+        let target = Target::from(state.target);
+        let nominal_hashrate = state.nominal_hashrate.into();
+        let share_accounting = ShareAccounting::from_state(state.share_accounting);
+        let info = ChannelInfoHandle::new(&target, nominal_hashrate, &share_accounting);
+
+        Ok(Self {
+            channel_id: state.channel_id,
+            user_identity,
+            extranonce_prefix: state.extranonce_prefix,
+            requested_max_target: state.requested_max_target.into(),
+            defer_target_changes: state.defer_target_changes,
+            pending_target: None,
+            deferred_set_target: None,
+            share_accounting,
+            expected_share_per_minute: state.expected_share_per_minute,
+            configured_share_per_minute: state
+                .configured_share_per_minute
+                .unwrap_or(state.expected_share_per_minute),
+            job_factory,
+            chain_tip: state.chain_tip.map(ChainTip::from_state),
+            retain_previous_chain_tip: state.retain_previous_chain_tip,
+            previous_chain_tip: None,
             job_store,
+            max_target_policy: state.max_target_policy,
+            template_replay_policy: state.template_replay_policy,
+            job_rate_limit: state.job_rate_limit,
+            jobs_on_tip: 0,
+            paused: state.paused,
+            job_share_counts: HashMap::new(),
+            job_targets: HashMap::new(),
+            job_missing: false,
+            failed_future_templates: HashSet::new(),
+            share_policy: None,
+            header_hasher: Arc::new(DefaultHeaderHasher),
+            clock: default_clock(),
+            info,
+            #[cfg(feature = "event-log")]
+            event_log: ChannelEventLog::default(),
         })
     }
 
@@ -103,7 +900,7 @@ impl<'a> StandardChannel<'a> {
         self.channel_id
     }
 
-    pub fn get_user_identity(&self) -> &String {
+    pub fn get_user_identity(&self) -> &UserIdentity {
         &self.user_identity
     }
 
@@ -111,11 +908,27 @@ impl<'a> StandardChannel<'a> {
         &self.extranonce_prefix
     }
 
+    pub fn is_version_rolling_allowed(&self) -> bool {
+        self.job_factory.is_version_rolling_allowed()
+    }
+
+    /// Sets whether shares are allowed to roll the bits of the job version defined by BIP320.
+    ///
+    /// Should follow the version rolling policy of the connection, as decided by
+    /// [`crate::connection::ChannelFactory`] from the `SetupConnection` flags.
+    ///
+    /// Since `NewMiningJob` doesn't carry this information, it applies to shares for every job of
+    /// the channel, including the ones already sent.
+    pub fn set_version_rolling_allowed(&mut self, version_rolling_allowed: bool) {
+        self.job_factory
+            .set_version_rolling_allowed(version_rolling_allowed);
+    }
+
     pub fn set_extranonce_prefix(
         &mut self,
         extranonce_prefix: Vec<u8>,
     ) -> Result<(), StandardChannelError> {
-        if extranonce_prefix.len() > MAX_EXTRANONCE_LEN {
+        if extranonce_prefix.len() > self.job_factory.get_extranonce_padding().total_len() {
             return Err(StandardChannelError::NewExtranoncePrefixTooLarge);
         }
 
@@ -124,79 +937,302 @@ impl<'a> StandardChannel<'a> {
         Ok(())
     }
 
+    /// Changes the extranonce prefix of the channel mid-session, and returns the
+    /// `SetExtranoncePrefix` message to be sent downstream.
+    ///
+    /// As the merkle root of a standard job commits to the extranonce prefix, the active job and
+    /// the future jobs are recreated (with new job ids) out of the same templates and coinbase
+    /// reward outputs, and the replaced jobs become past jobs, still validated against the
+    /// previous extranonce prefix. The new jobs are to be sent right after the message, e.g. via
+    /// [`StandardChannel::initial_downstream_messages`].
+    ///
+    /// If a job can't be recreated, the channel is left untouched.
+    pub fn rotate_extranonce_prefix(
+        &mut self,
+        extranonce_prefix: Vec<u8>,
+    ) -> Result<SetExtranoncePrefix<'static>, StandardChannelError> {
+        if extranonce_prefix.len() > self.job_factory.get_extranonce_padding().total_len() {
+            return Err(StandardChannelError::NewExtranoncePrefixTooLarge);
+        }
+        let message = SetExtranoncePrefix {
+            channel_id: self.channel_id,
+            extranonce_prefix: extranonce_prefix
+                .clone()
+                .try_into()
+                .map_err(|_| StandardChannelError::NewExtranoncePrefixTooLarge)?,
+        };
+
+        let mut active_job = None;
+        if let (Some(job), Some(chain_tip)) =
+            (self.job_store.get_active_job(), self.chain_tip.clone())
+        {
+            let min_ntime = job
+                .activation_ntime()
+                .unwrap_or_else(|| chain_tip.min_ntime());
+            let mut new_job = self
+                .job_factory
+                .new_standard_job(
+                    self.channel_id,
+                    Some(chain_tip),
+                    extranonce_prefix.clone(),
+                    job.get_shared_template().clone(),
+                    job.get_coinbase_reward_outputs().to_vec(),
+                )
+                .map_err(StandardChannelError::JobFactoryError)?;
+            // a job out of a future template is future, until activated like the job it replaces
+            new_job.activate(min_ntime);
+            if let Some(prev_hash) = job.get_prev_hash() {
+                new_job.set_prev_hash(prev_hash);
+            }
+            active_job = Some(new_job);
+        }
+
+        let mut future_jobs: Vec<&StandardJob<'a>> =
+            self.job_store.get_future_jobs().values().collect();
+        future_jobs.sort_by_key(|job| job.get_job_id());
+        let mut new_future_jobs = Vec::with_capacity(future_jobs.len());
+        for job in future_jobs {
+            let new_job = self
+                .job_factory
+                .new_standard_job(
+                    self.channel_id,
+                    None,
+                    extranonce_prefix.clone(),
+                    job.get_shared_template().clone(),
+                    job.get_coinbase_reward_outputs().to_vec(),
+                )
+                .map_err(StandardChannelError::JobFactoryError)?;
+            new_future_jobs.push(new_job);
+        }
+
+        self.extranonce_prefix = extranonce_prefix;
+        if let Some(job) = active_job {
+            self.job_store
+                .add_active_job(job)
+                .map_err(StandardChannelError::JobStoreError)?;
+        }
+        for job in new_future_jobs {
+            let template_id = job.get_template().template_id;
+            self.job_store
+                .add_future_job(template_id, job)
+                .map_err(StandardChannelError::JobStoreError)?;
+        }
+
+        Ok(message)
+    }
+
+    /// Sets the target right away, dropping the target pending for the next job (if any).
     pub fn set_target(&mut self, target: Target) {
-        self.target = target;
+        self.pending_target = None;
+        self.deferred_set_target = None;
+        self.job_targets.clear();
+        self.apply_target(target);
+    }
+
+    fn apply_target(&mut self, target: Target) {
+        #[cfg(feature = "event-log")]
+        self.event_log.record_at(
+            system_time(self.clock.as_ref()),
+            ChannelEventKind::TargetChanged {
+                target: WireU256::from(&target),
+            },
+        );
+        self.info.store_target(&target);
+    }
+
+    // Applies the pending target, if any, as a job was just activated. The jobs activated before
+    // keep the target they were activated with.
+    fn apply_pending_target(&mut self) {
+        if let Some((target, expected_share_per_minute)) = self.pending_target.take() {
+            self.apply_target(target.clone());
+            self.expected_share_per_minute = expected_share_per_minute;
+            self.deferred_set_target = Some(target);
+        }
+        if let Some(job_id) = self.job_store.get_active_job().map(|job| job.get_job_id()) {
+            let target = self.get_target();
+            self.job_targets.insert(job_id, target);
+        }
+    }
+
+    // The target the shares of `job_id` are validated against.
+    fn job_target(&self, job_id: u32) -> Target {
+        self.job_targets
+            .get(&job_id)
+            .cloned()
+            .unwrap_or_else(|| self.get_target())
+    }
+
+    /// Changes the number of accepted shares between acknowledgements, e.g. to acknowledge
+    /// shares more often on unreliable connections.
+    ///
+    /// Shares accepted since the last acknowledgement still count towards the next one, see
+    /// [`ShareAccounting::set_share_batch_size`].
+    pub fn set_share_batch_size(
+        &mut self,
+        share_batch_size: usize,
+    ) -> Result<(), StandardChannelError> {
+        if share_batch_size == 0 {
+            return Err(StandardChannelError::InvalidShareBatchSize);
+        }
+        self.share_accounting.set_share_batch_size(share_batch_size);
+        Ok(())
     }
 
-    pub fn set_nominal_hashrate(&mut self, nominal_hashrate: f32) {
-        self.nominal_hashrate = nominal_hashrate;
+    pub fn set_nominal_hashrate(&mut self, nominal_hashrate: impl Into<f64>) {
+        self.info.store_nominal_hashrate(nominal_hashrate.into());
     }
 
     pub fn get_requested_max_target(&self) -> &Target {
         &self.requested_max_target
     }
 
-    pub fn get_target(&self) -> &Target {
-        &self.target
+    pub fn get_target(&self) -> Target {
+        self.info.get_target()
+    }
+
+    pub fn get_max_target_policy(&self) -> MaxTargetPolicy {
+        self.max_target_policy
+    }
+
+    pub fn get_template_replay_policy(&self) -> TemplateReplayPolicy {
+        self.template_replay_policy
+    }
+
+    pub fn set_template_replay_policy(&mut self, template_replay_policy: TemplateReplayPolicy) {
+        self.template_replay_policy = template_replay_policy;
+    }
+
+    pub fn get_job_rate_limit(&self) -> Option<JobRateLimit> {
+        self.job_rate_limit
+    }
+
+    /// Caps the jobs created for non-future templates on a chain tip, see [`JobRateLimit`].
+    ///
+    /// Jobs already created on the current chain tip count towards the new limit. `None` lifts
+    /// the limit.
+    pub fn set_job_rate_limit(&mut self, job_rate_limit: Option<JobRateLimit>) {
+        self.job_rate_limit = job_rate_limit;
+    }
+
+    pub fn get_defer_target_changes(&self) -> bool {
+        self.defer_target_changes
+    }
+
+    /// Whether [`StandardChannel::update_channel`] defers target changes to the next job.
+    ///
+    /// A miner keeps hashing the job it's on against the target it had when the job started, so
+    /// a target lowered in the middle of a job makes its shares in flight fail as
+    /// `DoesNotMeetTarget`. With deferred target changes, the new target is held as
+    /// [`StandardChannel::pending_target`] and shares are still validated against the current
+    /// one, until the next job is activated by a non-future template or a `SetNewPrevHash`. The
+    /// `SetTarget` for it is then sent along with the messages of that job, see
+    /// [`StandardChannel::take_deferred_set_target`].
+    ///
+    /// Turning it off applies the pending target right away, to the shares of every job.
+    pub fn set_defer_target_changes(&mut self, defer_target_changes: bool) {
+        self.defer_target_changes = defer_target_changes;
+        if !defer_target_changes {
+            self.job_targets.clear();
+            self.apply_pending_target();
+        }
+    }
+
+    /// Returns the target to be applied once the next job is activated, if target changes are
+    /// deferred and the target changed since the current job was activated.
+    ///
+    /// It's not part of the [`ChannelState`], the next `update_channel` computes it again.
+    pub fn pending_target(&self) -> Option<&Target> {
+        self.pending_target.as_ref().map(|(target, _)| target)
+    }
+
+    /// Returns the `SetTarget` for the deferred target applied by the latest job activation, only
+    /// once.
+    ///
+    /// It's to be sent right after the messages of the job (the `NewMiningJob` of a non-future
+    /// template, or the `SetNewPrevHash` activating a future job), as the target applies to the
+    /// shares of that job on.
+    pub fn take_deferred_set_target(&mut self) -> Option<SetTarget<'static>> {
+        self.deferred_set_target.take().map(|target| SetTarget {
+            channel_id: self.channel_id,
+            maximum_target: target.into(),
+        })
+    }
+
+    pub fn get_nominal_hashrate(&self) -> f64 {
+        self.info.get_nominal_hashrate()
     }
 
-    pub fn get_nominal_hashrate(&self) -> f32 {
-        self.nominal_hashrate
+    /// Returns a handle reading the target, the nominal hashrate and the share accounting
+    /// counters of the channel from other threads, while the channel is borrowed mutably, see
+    /// [`channel_info`](crate::server::channel_info).
+    pub fn get_info_handle(&self) -> ChannelInfoHandle {
+        self.info.clone()
     }
 
     /// Updates the channel's nominal hashrate and target.
     ///
     /// If requested_max_target is None, we use the cached value in the channel state.
+    ///
+    /// The target is kept if the new one only differs below the precision of a compact `nbits`
+    /// target (see [`Target::approx_eq`]), so a `SetTarget` is only needed when it changes.
+    ///
+    /// A new target above the requested max target is handled according to the channel's
+    /// [`MaxTargetPolicy`].
+    ///
+    /// If target changes are deferred (see [`StandardChannel::set_defer_target_changes`]), the
+    /// new target replaces the pending one instead, while the nominal hashrate is updated right
+    /// away.
     pub fn update_channel(
         &mut self,
-        nominal_hashrate: f32,
+        nominal_hashrate: impl Into<f64>,
         requested_max_target: Option<Target>,
     ) -> Result<(), StandardChannelError> {
-        let target_u256 = match hash_rate_to_target(
-            nominal_hashrate.into(),
-            self.expected_share_per_minute.into(),
-        ) {
-            Ok(target_u256) => target_u256,
-            Err(_) => {
-                return Err(StandardChannelError::InvalidNominalHashrate);
-            }
-        };
+        let nominal_hashrate = nominal_hashrate.into();
+        let target = self.get_target();
+        let target_u256 =
+            match hash_rate_to_target(nominal_hashrate, self.configured_share_per_minute.into()) {
+                Ok(target_u256) => target_u256,
+                Err(_) => {
+                    return Err(StandardChannelError::InvalidNominalHashrate);
+                }
+            };
 
         let requested_max_target = match requested_max_target {
             Some(ref requested_max_target) => requested_max_target.clone(),
             None => self.requested_max_target.clone(),
         };
 
-        // debug hex of target_u256 and max_target
-        // just like in share validation
-        let mut target_bytes = target_u256.to_vec();
-        target_bytes.reverse(); // Convert to big-endian for display
-        let max_target_u256: binary_sv2::U256 = requested_max_target.clone().into();
-        let mut max_target_bytes = max_target_u256.to_vec();
-        max_target_bytes.reverse(); // Convert to big-endian for display
-
-        // Get the old target for comparison on the debug log
-        // Not really needed for the actual method functionality
-        // But it's useful to have for debugging purposes
-        let old_target_u256: binary_sv2::U256 = self.target.clone().into();
-        let mut old_target_bytes = old_target_u256.to_vec();
-        old_target_bytes.reverse(); // Convert to big-endian for display
-
-        debug!(
-            "updating channel target \nold target:\t{}\nnew target:\t{}\nmax_target:\t{}",
-            bytes_to_hex(&old_target_bytes),
-            bytes_to_hex(&target_bytes),
-            bytes_to_hex(&max_target_bytes)
-        );
-
-        let new_target: Target = target_u256.into();
-
-        if new_target > requested_max_target {
-            return Err(StandardChannelError::RequestedMaxTargetOutOfRange);
+        if debug_enabled!() {
+            debug!(
+                "updating channel target \nold target:\t{}\nnew target:\t{}\nmax_target:\t{}",
+                DisplayU256::from(&target),
+                DisplayU256::from(&Target::from(target_u256.clone())),
+                DisplayU256::from(&requested_max_target)
+            );
         }
 
-        self.target = new_target;
-        self.nominal_hashrate = nominal_hashrate;
+        let (new_target, expected_share_per_minute) = apply_max_target_policy(
+            target_u256.into(),
+            &requested_max_target,
+            self.configured_share_per_minute,
+            self.max_target_policy,
+        )?;
+
+        // only replace the target if it changed beyond the precision of a compact target, so
+        // rounding noise doesn't cause unnecessary SetTarget messages
+        if new_target.approx_eq(&target, compact_tolerance_bits(&target)) {
+            // back to the current target, nothing to change on the next job
+            self.pending_target = None;
+            self.expected_share_per_minute = expected_share_per_minute;
+        } else if self.defer_target_changes {
+            // the share rate goes with the target it was computed for
+            self.pending_target = Some((new_target, expected_share_per_minute));
+        } else {
+            self.job_targets.clear();
+            self.apply_target(new_target);
+            self.expected_share_per_minute = expected_share_per_minute;
+        }
+        self.info.store_nominal_hashrate(nominal_hashrate);
         self.requested_max_target = requested_max_target;
         Ok(())
     }
@@ -229,15 +1265,190 @@ impl<'a> StandardChannel<'a> {
         self.chain_tip.as_ref()
     }
 
-    /// Only for testing purposes, not meant to be used in real apps.
-    #[cfg(test)]
-    fn set_chain_tip(&mut self, chain_tip: ChainTip) {
-        self.chain_tip = Some(chain_tip);
+    /// Returns the chain tip before the current one, if it's retained.
+    pub fn get_previous_chain_tip(&self) -> Option<&ChainTip> {
+        self.previous_chain_tip
+            .as_ref()
+            .map(|(chain_tip, _)| chain_tip)
     }
 
-    pub fn get_share_accounting(&self) -> &ShareAccounting {
-        &self.share_accounting
-    }
+    /// Sets whether the chain tip before the current one is retained, which is off by default.
+    ///
+    /// During a block race, the Template Provider may switch from one branch to another seconds
+    /// apart. Shares for jobs of the previous chain tip are still stale, but one that meets the
+    /// network target of the previous chain tip is returned as a
+    /// [`ShareValidationResult::StaleBlockCandidate`], so that the block can still be
+    /// propagated in case the previous branch wins the race.
+    ///
+    /// Only a single previous chain tip is retained, and only the jobs kept as stale by the job
+    /// store (see [`StaleRetention`](crate::server::jobs::job_store::StaleRetention)) can yield a
+    /// candidate.
+    pub fn set_retain_previous_chain_tip(&mut self, retain_previous_chain_tip: bool) {
+        self.retain_previous_chain_tip = retain_previous_chain_tip;
+        if !retain_previous_chain_tip {
+            self.previous_chain_tip = None;
+        }
+    }
+
+    /// Only for testing purposes, not meant to be used in real apps.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub(crate) fn set_chain_tip(&mut self, chain_tip: ChainTip) {
+        self.chain_tip = Some(chain_tip);
+    }
+
+    pub fn get_share_accounting(&self) -> &ShareAccounting {
+        &self.share_accounting
+    }
+
+    /// Returns the shares received for each job, keyed by `job_id`.
+    ///
+    /// Only shares for the active, past and stale jobs are counted, and the counts of a job are
+    /// dropped along with it once it leaves the job store. They are not part of the exported
+    /// state.
+    pub fn get_job_share_counts(&self) -> &HashMap<u32, JobShareCounts> {
+        &self.job_share_counts
+    }
+
+    /// Stops accepting shares on the channel, e.g. while the user is suspended, without closing
+    /// it.
+    ///
+    /// Until [`StandardChannel::resume`] is called, shares are rejected with
+    /// [`ShareValidationError::ChannelPaused`] carrying `reason`, and the share accounting is left
+    /// untouched. Templates and chain tips are still processed, so that the channel has
+    /// up-to-date jobs as soon as it's resumed.
+    pub fn pause(&mut self, reason: String) {
+        self.paused = Some(reason);
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = None;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    /// Returns the reason given to [`StandardChannel::pause`], if the channel is paused.
+    pub fn get_pause_reason(&self) -> Option<&str> {
+        self.paused.as_deref()
+    }
+
+    /// Sets the [`SharePolicy`] invoked by [`StandardChannel::validate_share`], replacing the
+    /// previous one.
+    pub fn set_share_policy(&mut self, share_policy: Box<dyn SharePolicy>) {
+        self.share_policy = Some(share_policy);
+    }
+
+    /// Removes the [`SharePolicy`] of the channel, if any, and returns it.
+    pub fn remove_share_policy(&mut self) -> Option<Box<dyn SharePolicy>> {
+        self.share_policy.take()
+    }
+
+    /// Sets the [`HeaderHasher`] the headers of shares are hashed with, replacing the
+    /// [`DefaultHeaderHasher`].
+    ///
+    /// Taken as an `Arc`, so that a single (e.g. hardware backed) hasher can serve every channel.
+    pub fn set_header_hasher(&mut self, header_hasher: Arc<dyn HeaderHasher>) {
+        self.header_hasher = header_hasher;
+    }
+
+    /// Sets the [`Clock`] the timestamps of the channel are taken from (block found and share
+    /// rejected events, and the event log), replacing the system clock.
+    ///
+    /// Without the `std` feature, timestamps are only recorded once a clock is set.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = Some(clock);
+    }
+
+    /// Whether the job factory failed to create the job for the current chain tip.
+    ///
+    /// Until a new job is activated, shares are rejected with
+    /// [`ShareValidationError::NoActiveJob`] instead of being validated against the previous
+    /// active job.
+    pub fn is_job_missing(&self) -> bool {
+        self.job_missing
+    }
+
+    /// Returns the log of the significant events of the channel.
+    #[cfg(feature = "event-log")]
+    pub fn get_event_log(&self) -> &ChannelEventLog {
+        &self.event_log
+    }
+
+    /// Returns the future, active or past job created for the template with `template_id`.
+    pub fn get_job_for_template(&self, template_id: u64) -> Option<&StandardJob<'a>> {
+        let job_store = &self.job_store;
+        job_store
+            .get_future_template_to_job_id()
+            .get(&template_id)
+            .and_then(|job_id| job_store.get_future_jobs().get(job_id))
+            .or_else(|| {
+                job_store
+                    .get_active_job()
+                    .filter(|job| job.get_template().template_id == template_id)
+            })
+            .or_else(|| {
+                job_store
+                    .get_past_jobs()
+                    .values()
+                    .find(|job| job.get_template().template_id == template_id)
+            })
+    }
+
+    /// Returns the messages bringing a downstream that just joined the channel up to date, in the
+    /// order they must be sent.
+    ///
+    /// As a `SetNewPrevHash` can only activate a future job, the active job (if any) is sent as a
+    /// future job, followed by a `SetNewPrevHash` activating it on the current chain tip. The
+    /// future jobs of the channel come last, ordered by `job_id`, so that they are not discarded
+    /// by that `SetNewPrevHash`. Past jobs are not sent, and a channel without jobs has nothing
+    /// to send.
+    pub fn initial_downstream_messages(&self) -> Vec<DownstreamMessage<'a>> {
+        let mut messages = vec![];
+
+        if let (Some(active_job), Some(chain_tip)) =
+            (self.job_store.get_active_job(), self.chain_tip.as_ref())
+        {
+            let mut job_message = active_job.get_job_message().clone();
+            job_message.min_ntime = Sv2Option::new(None);
+            messages.push(DownstreamMessage::NewMiningJob(job_message));
+            messages.push(DownstreamMessage::SetNewPrevHash(SetNewPrevHashMp {
+                channel_id: self.channel_id,
+                job_id: active_job.get_job_id(),
+                prev_hash: chain_tip.prev_hash(),
+                min_ntime: active_job
+                    .activation_ntime()
+                    .unwrap_or_else(|| chain_tip.min_ntime()),
+                nbits: chain_tip.nbits(),
+            }));
+        }
+
+        let mut future_jobs: Vec<&StandardJob<'a>> =
+            self.job_store.get_future_jobs().values().collect();
+        future_jobs.sort_by_key(|job| job.get_job_id());
+        messages.extend(
+            future_jobs
+                .into_iter()
+                .map(|job| DownstreamMessage::NewMiningJob(job.get_job_message().clone())),
+        );
+
+        messages
+    }
+
+    // Whether `set_new_prev_hash` already made the current chain tip and active job.
+    fn is_current_prev_hash(&self, set_new_prev_hash: &SetNewPrevHash) -> bool {
+        let chain_tip = match &self.chain_tip {
+            Some(chain_tip) => chain_tip,
+            None => return false,
+        };
+        chain_tip.prev_hash() == set_new_prev_hash.prev_hash
+            && chain_tip.nbits() == set_new_prev_hash.n_bits
+            && chain_tip.min_ntime() == set_new_prev_hash.header_timestamp
+            && self
+                .job_store
+                .get_active_job()
+                .is_some_and(|job| job.get_template().template_id == set_new_prev_hash.template_id)
+    }
 
     /// Updates the channel state with a new job.
     ///
@@ -246,11 +1457,56 @@ impl<'a> StandardChannel<'a> {
     ///
     /// Only meant for usage on a Sv2 Pool Server or a Sv2 Job Declaration Client,
     /// but not on mining clients such as Mining Devices or Proxies.
+    ///
+    /// A template whose `template_id` already produced a job is handled according to the
+    /// channel's [`TemplateReplayPolicy`]. When the existing job is kept, it can be retrieved via
+    /// [`StandardChannel::get_job_for_template`].
+    ///
+    /// If the job factory fails on a non-future template, or on the future template later
+    /// activated by a `SetNewPrevHash`, the channel is left without a job for its chain tip (see
+    /// [`StandardChannel::is_job_missing`]).
+    ///
+    /// Once the channel created as many jobs on the current chain tip as its [`JobRateLimit`]
+    /// allows, non-future templates are ignored with [`TemplateOutcome::SuppressedByRateLimit`],
+    /// unless they bump the fees enough.
     pub fn on_new_template(
         &mut self,
         template: NewTemplate<'a>,
         coinbase_reward_outputs: Vec<TxOut>,
-    ) -> Result<(), StandardChannelError> {
+    ) -> Result<TemplateOutcome, StandardChannelError> {
+        self.on_new_shared_template(Arc::new(template.into_static()), coinbase_reward_outputs)
+    }
+
+    /// Same as [`StandardChannel::on_new_template`], for a template already shared with other
+    /// channels.
+    ///
+    /// The job created for the template keeps a reference to it instead of a copy, which is how
+    /// [`ChannelSet`](crate::server::channel_set::ChannelSet) hands one template to all of its
+    /// channels.
+    pub fn on_new_shared_template(
+        &mut self,
+        template: SharedTemplate,
+        coinbase_reward_outputs: Vec<TxOut>,
+    ) -> Result<TemplateOutcome, StandardChannelError> {
+        if self.template_replay_policy == TemplateReplayPolicy::ReuseJob {
+            if let Some(job) = self.get_job_for_template(template.template_id) {
+                if !is_same_template(job.get_template(), &template) {
+                    return Err(StandardChannelError::TemplateIdReusedWithDifferentContent(
+                        template.template_id,
+                    ));
+                }
+                // the existing job is kept, unless it was created for other reward outputs or
+                // another extranonce prefix
+                if job
+                    .get_coinbase_outputs()
+                    .starts_with(&coinbase_reward_outputs)
+                    && job.get_extranonce_prefix() == &self.extranonce_prefix
+                {
+                    return Ok(TemplateOutcome::JobReused);
+                }
+            }
+        }
+
         match template.future_template {
             true => {
                 let new_job = self
@@ -262,204 +1518,587 @@ impl<'a> StandardChannel<'a> {
                         template.clone(),
                         coinbase_reward_outputs,
                     )
-                    .map_err(StandardChannelError::JobFactoryError)?;
-                self.job_store.add_future_job(template.template_id, new_job);
+                    .map_err(|e| {
+                        self.failed_future_templates.insert(template.template_id);
+                        StandardChannelError::JobFactoryError(e)
+                    })?;
+                self.job_store
+                    .add_future_job(template.template_id, new_job)
+                    .map_err(StandardChannelError::JobStoreError)?;
+                self.failed_future_templates.remove(&template.template_id);
             }
             false => {
                 match self.chain_tip.clone() {
                     // we can only create non-future jobs if we have a chain tip
                     None => return Err(StandardChannelError::ChainTipNotSet),
                     Some(chain_tip) => {
-                        let new_job = self
-                            .job_factory
-                            .new_standard_job(
-                                self.channel_id,
-                                Some(chain_tip),
-                                self.extranonce_prefix.clone(),
-                                template.clone(),
-                                coinbase_reward_outputs,
-                            )
-                            .map_err(StandardChannelError::JobFactoryError)?;
-                        self.job_store.add_active_job(new_job);
+                        if self.is_rate_limited(&template) {
+                            debug!(
+                                "no job for template {} on channel {}, already {} jobs on the chain tip",
+                                template.template_id, self.channel_id, self.jobs_on_tip
+                            );
+                            return Ok(TemplateOutcome::SuppressedByRateLimit);
+                        }
+                        let new_job = match self.new_job_on_chain_tip(
+                            template.clone(),
+                            coinbase_reward_outputs,
+                            chain_tip,
+                        ) {
+                            Ok(new_job) => new_job,
+                            Err(e) => {
+                                self.job_missing = true;
+                                return Err(e);
+                            }
+                        };
+                        self.add_active_job(new_job)?;
+                        self.jobs_on_tip += 1;
                     }
                 }
             }
         }
 
+        #[cfg(feature = "event-log")]
+        self.event_log.record_at(
+            system_time(self.clock.as_ref()),
+            ChannelEventKind::TemplateAccepted {
+                template_id: template.template_id,
+                future_template: template.future_template,
+            },
+        );
+        Ok(TemplateOutcome::JobCreated)
+    }
+
+    // Whether the job rate limit suppresses the non-future `template`, i.e. the channel already
+    // has its share of jobs on the current chain tip and `template` doesn't bump the fees of the
+    // active job enough.
+    fn is_rate_limited(&self, template: &NewTemplate) -> bool {
+        let job_rate_limit = match &self.job_rate_limit {
+            Some(job_rate_limit) => job_rate_limit,
+            None => return false,
+        };
+        if self.job_missing || self.jobs_on_tip < job_rate_limit.max_jobs_per_tip() {
+            return false;
+        }
+        match self.job_store.get_active_job() {
+            Some(job) => {
+                let fee_bump = template
+                    .coinbase_tx_value_remaining
+                    .saturating_sub(job.get_template().coinbase_tx_value_remaining);
+                fee_bump < job_rate_limit.fee_bump_threshold()
+            }
+            None => false,
+        }
+    }
+
+    /// Updates the channel with a non-future template along with the chain tip it was built on,
+    /// and returns the message of the resulting active job, to be sent downstream.
+    ///
+    /// This is the preferred path for non-future templates, e.g. for the first job after the
+    /// downstream connects: setting the chain tip and calling
+    /// [`StandardChannel::on_new_template`] separately leaves the channel with a chain tip but
+    /// no job in between, which other readers of the channel may observe. Here the job is
+    /// created first, and the channel is only updated once it's there; if the job factory
+    /// fails, the channel is left untouched.
+    ///
+    /// If `chain_tip` is the current chain tip, this is the same as
+    /// [`StandardChannel::on_new_template`], including the [`TemplateReplayPolicy`] and the
+    /// [`JobRateLimit`] (a suppressed template fails with `TemplateIdNotFound`). Otherwise,
+    /// the jobs of the current chain tip (if any) become stale and the future jobs are dropped,
+    /// as with [`StandardChannel::on_set_new_prev_hash`]. The job is built on top of `chain_tip`
+    /// even if `template` is a future template.
+    pub fn apply_template_with_chain_tip(
+        &mut self,
+        template: NewTemplate<'a>,
+        coinbase_reward_outputs: Vec<TxOut>,
+        chain_tip: ChainTip,
+    ) -> Result<NewMiningJob<'a>, StandardChannelError> {
+        let template = Arc::new(template.into_static());
+        let template_id = template.template_id;
+
+        if self.chain_tip.as_ref() == Some(&chain_tip) && !template.future_template {
+            self.on_new_shared_template(template, coinbase_reward_outputs)?;
+        } else {
+            let new_job =
+                self.new_job_on_chain_tip(template, coinbase_reward_outputs, chain_tip.clone())?;
+            if self.chain_tip.as_ref() == Some(&chain_tip) {
+                self.add_active_job(new_job)?;
+            } else {
+                let job_ids = self.current_chain_tip_job_ids();
+                // activating the job makes the jobs of the current chain tip stale
+                self.job_store
+                    .add_future_job(template_id, new_job)
+                    .map_err(StandardChannelError::JobStoreError)?;
+                self.failed_future_templates.clear();
+                self.job_store
+                    .activate_future_job(template_id, chain_tip.min_ntime());
+                self.job_missing = false;
+                self.apply_pending_target();
+                #[cfg(feature = "event-log")]
+                if let Some(job) = self.job_store.get_active_job() {
+                    self.event_log.record_at(
+                        system_time(self.clock.as_ref()),
+                        ChannelEventKind::JobActivated {
+                            job_id: job.get_job_id(),
+                        },
+                    );
+                }
+                self.replace_chain_tip(chain_tip, job_ids);
+            }
+            #[cfg(feature = "event-log")]
+            self.event_log.record_at(
+                system_time(self.clock.as_ref()),
+                ChannelEventKind::TemplateAccepted {
+                    template_id,
+                    future_template: false,
+                },
+            );
+        }
+
+        self.job_store
+            .get_active_job()
+            .filter(|job| job.get_template().template_id == template_id)
+            .map(|job| job.get_job_message().clone())
+            .ok_or(StandardChannelError::TemplateIdNotFound)
+    }
+
+    // Creates the job of `template` on top of `chain_tip`.
+    fn new_job_on_chain_tip(
+        &mut self,
+        template: SharedTemplate,
+        coinbase_reward_outputs: Vec<TxOut>,
+        chain_tip: ChainTip,
+    ) -> Result<StandardJob<'a>, StandardChannelError> {
+        let mut new_job = self
+            .job_factory
+            .new_standard_job(
+                self.channel_id,
+                Some(chain_tip.clone()),
+                self.extranonce_prefix.clone(),
+                template,
+                coinbase_reward_outputs,
+            )
+            .map_err(StandardChannelError::JobFactoryError)?;
+        if new_job.is_future() {
+            new_job.activate(chain_tip.min_ntime());
+            new_job.set_prev_hash(chain_tip.prev_block_hash());
+        }
+        Ok(new_job)
+    }
+
+    // Makes `new_job` the active job, on the current chain tip.
+    fn add_active_job(&mut self, new_job: StandardJob<'a>) -> Result<(), StandardChannelError> {
+        #[cfg(feature = "event-log")]
+        let job_id = new_job.get_job_id();
+        self.job_store
+            .add_active_job(new_job)
+            .map_err(StandardChannelError::JobStoreError)?;
+        self.job_missing = false;
+        #[cfg(feature = "event-log")]
+        self.event_log.record_at(
+            system_time(self.clock.as_ref()),
+            ChannelEventKind::JobActivated { job_id },
+        );
+        self.apply_pending_target();
         Ok(())
     }
 
+    // The jobs mined on the current chain tip, to be retained along with it once it's replaced.
+    fn current_chain_tip_job_ids(&self) -> HashSet<u32> {
+        match self.retain_previous_chain_tip {
+            true => self
+                .job_store
+                .get_active_job()
+                .map(|job| job.get_job_id())
+                .into_iter()
+                .chain(self.job_store.get_past_jobs().keys().copied())
+                .collect(),
+            false => HashSet::new(),
+        }
+    }
+
+    // Replaces the current chain tip, once the job store moved on to `chain_tip`. `job_ids` are
+    // the jobs mined on the replaced chain tip, see `current_chain_tip_job_ids`.
+    fn replace_chain_tip(&mut self, chain_tip: ChainTip, job_ids: HashSet<u32>) {
+        let previous_chain_tip = self.chain_tip.replace(chain_tip);
+        self.jobs_on_tip = 0;
+        if self.retain_previous_chain_tip {
+            self.previous_chain_tip = previous_chain_tip.map(|chain_tip| (chain_tip, job_ids));
+        }
+
+        let orphans = self.job_store.gc();
+        if orphans > 0 {
+            debug!(
+                "dropped {} orphan future jobs or template mappings",
+                orphans
+            );
+        }
+
+        // the counts and targets of the jobs dropped from the job store go with them
+        let job_store = &self.job_store;
+        let is_retained = |job_id: &u32| {
+            job_store
+                .get_active_job()
+                .is_some_and(|job| job.get_job_id() == *job_id)
+                || job_store.get_past_jobs().contains_key(job_id)
+                || job_store.get_stale_jobs().contains_key(job_id)
+        };
+        self.job_share_counts
+            .retain(|job_id, _| is_retained(job_id));
+        self.job_targets.retain(|job_id, _| {
+            is_retained(job_id)
+                || job_store.get_compact_past_job(*job_id).is_some()
+                || job_store.get_compact_stale_job(*job_id).is_some()
+        });
+    }
+
     /// Updates the channel state with a new `SetNewPrevHash` message.
     ///
     /// If there are no future jobs, returns an error.
     /// If there are future jobs, the active job is set to the job with the given `template_id`.
+    /// If none of them is for `template_id` (e.g. a template superseded by the one of an earlier
+    /// `SetNewPrevHash`), returns an error and leaves the channel untouched, unless the job for
+    /// the template could not be created.
     ///
-    /// All past jobs are cleared.
+    /// All past jobs are cleared, and either kept as stale jobs or dropped according to the job
+    /// store's [`StaleRetention`](crate::server::jobs::job_store::StaleRetention).
+    ///
+    /// With [`TemplateReplayPolicy::ReuseJob`], a replay of the `SetNewPrevHash` that made the
+    /// current chain tip and active job is ignored.
     ///
     /// The chain tip information is not kept in the channel state.
     pub fn on_set_new_prev_hash(
         &mut self,
         set_new_prev_hash: SetNewPrevHash<'a>,
     ) -> Result<(), StandardChannelError> {
+        if self.template_replay_policy == TemplateReplayPolicy::ReuseJob
+            && self.is_current_prev_hash(&set_new_prev_hash)
+        {
+            return Ok(());
+        }
+
+        // the jobs mined on the current chain tip, before they become stale
+        let job_ids = self.current_chain_tip_job_ids();
+
+        let set_new_prev_hash = set_new_prev_hash.into_static();
+        let new_chain_tip = ChainTip::new(
+            set_new_prev_hash.prev_hash,
+            set_new_prev_hash.n_bits,
+            set_new_prev_hash.header_timestamp,
+        )
+        .map_err(StandardChannelError::InvalidPrevHash)?;
+
+        // the job for the new chain tip could not be created
+        let job_failed = self
+            .failed_future_templates
+            .contains(&set_new_prev_hash.template_id);
+        if job_failed {
+            self.job_missing = true;
+        }
+
         match self.job_store.get_future_jobs().is_empty() {
             true => {
+                self.failed_future_templates.clear();
                 return Err(StandardChannelError::TemplateIdNotFound);
             }
             false => {
-                self.job_store.activate_future_job(
+                if self.job_store.activate_future_job(
                     set_new_prev_hash.template_id,
                     set_new_prev_hash.header_timestamp,
-                );
+                ) {
+                    // the job store only passes the timestamp of the chain tip on to the job
+                    if let Some(mut job) = self.job_store.get_active_job().cloned() {
+                        job.set_prev_hash(new_chain_tip.prev_block_hash());
+                        self.job_store.set_active_job(job);
+                    }
+                    self.job_missing = false;
+                    self.apply_pending_target();
+                    #[cfg(feature = "event-log")]
+                    if let Some(job) = self.job_store.get_active_job() {
+                        self.event_log.record_at(
+                            system_time(self.clock.as_ref()),
+                            ChannelEventKind::JobActivated {
+                                job_id: job.get_job_id(),
+                            },
+                        );
+                    }
+                } else if !job_failed {
+                    // e.g. a template superseded by an earlier `SetNewPrevHash`: the channel
+                    // stays on its chain tip, with its future jobs
+                    return Err(StandardChannelError::TemplateIdNotFound);
+                }
             }
         }
+        self.failed_future_templates.clear();
 
         // update the chain tip
-        let set_new_prev_hash_static = set_new_prev_hash.into_static();
-        let new_chain_tip = ChainTip::new(
-            set_new_prev_hash_static.prev_hash,
-            set_new_prev_hash_static.n_bits,
-            set_new_prev_hash_static.header_timestamp,
-        );
-        self.chain_tip = Some(new_chain_tip);
+        self.replace_chain_tip(new_chain_tip, job_ids);
+        self.share_accounting
+            .start_new_era(set_new_prev_hash.template_id);
 
         Ok(())
     }
 
     /// Validates a share.
     ///
-    /// Updates the channel state with the result of the share validation.
+    /// Updates the channel state with the result of the share validation. The
+    /// [`SharePolicy`] of the channel, if any, is invoked as described in
+    /// [`share_policy`](crate::server::share_policy).
     pub fn validate_share(
         &mut self,
         share: SubmitSharesStandard,
     ) -> Result<ShareValidationResult, ShareValidationError> {
+        #[cfg(feature = "event-log")]
+        let sequence_number = share.sequence_number;
         let job_id = share.job_id;
+        let result = self.validate_share_inner(&share);
+        self.info.store_share_accounting(&self.share_accounting);
+        match &result {
+            Ok(ShareValidationResult::StaleBlockCandidate(..))
+            | Err(ShareValidationError::Stale) => {
+                self.job_share_counts.entry(job_id).or_default().stale += 1;
+            }
+            Ok(_) => self.job_share_counts.entry(job_id).or_default().accepted += 1,
+            Err(_) => {}
+        }
+        let target = self.job_target(job_id);
+        if let (Err(error), Some(share_policy)) = (&result, self.share_policy.as_mut()) {
+            share_policy.on_share_rejected(&ShareRejectedEvent {
+                channel_id: self.channel_id,
+                user_identity: self.user_identity.as_str(),
+                share: &share,
+                error,
+                target: &target,
+                timestamp: unix_timestamp(self.clock.as_ref()),
+            });
+        }
+        #[cfg(feature = "event-log")]
+        self.event_log.record_share(
+            system_time(self.clock.as_ref()),
+            job_id,
+            sequence_number,
+            &result,
+        );
+        result
+    }
 
-        // check if job_id is active job
-        let is_active_job = self
-            .job_store
-            .get_active_job()
-            .is_some_and(|job| job.get_job_id() == job_id);
-
-        // check if job_id is past job
-        let is_past_job = self.job_store.get_past_jobs().contains_key(&job_id);
+    fn validate_share_inner(
+        &mut self,
+        share: &SubmitSharesStandard,
+    ) -> Result<ShareValidationResult, ShareValidationError> {
+        if let Some(reason) = &self.paused {
+            return Err(ShareValidationError::ChannelPaused(reason.clone()));
+        }
 
-        // check if job_id is stale job
-        let is_stale_job = self.job_store.get_stale_jobs().contains_key(&job_id);
+        if self.job_missing {
+            debug!(
+                "share for job {} on channel {} submitted while the job for the current chain tip is missing",
+                share.job_id, self.channel_id
+            );
+            return Err(ShareValidationError::NoActiveJob);
+        }
 
-        if is_stale_job {
+        let job_id = share.job_id;
+        let target = self.job_target(job_id);
+
+        // jobs of previous chain tips are either kept as stale or dropped, depending on the
+        // job store's `StaleRetention`
+        if let Some(job) = self.job_store.get_stale_jobs().get(&job_id) {
+            // unless it's a block on the branch of the previous chain tip
+            let (chain_tip, job_ids) = match &self.previous_chain_tip {
+                Some(previous_chain_tip) => previous_chain_tip,
+                None => return Err(ShareValidationError::Stale),
+            };
+            if !job_ids.contains(&job_id)
+                || check_share(
+                    job.get_job_message().version,
+                    job.activation_ntime()
+                        .unwrap_or_else(|| chain_tip.min_ntime()),
+                    share.version,
+                    share.ntime,
+                    self.job_factory.is_version_rolling_allowed(),
+                )
+                .is_err()
+            {
+                return Err(ShareValidationError::Stale);
+            }
+            let merkle_root = WireU256::try_from(job.get_merkle_root()).map_err(|_| {
+                error!(
+                    "job {} of channel {} has a malformed merkle root",
+                    job_id, self.channel_id
+                );
+                ShareValidationError::Internal(InternalInconsistency::MalformedMerkleRoot)
+            })?;
+            let mut header = chain_tip.header_template(share.version, merkle_root, share.ntime);
+            header.nonce = share.nonce;
+            let hash = block_hash(self.header_hasher.as_ref(), &header);
+            if !header.target().is_met_by(hash) {
+                return Err(ShareValidationError::Stale);
+            }
+            if self.share_accounting.is_share_seen(hash.to_raw_hash()) {
+                return Err(ShareValidationError::DuplicateShare);
+            }
+            self.share_accounting.update_share_accounting(
+                target_to_difficulty(target.clone()) as u64,
+                share.sequence_number,
+                hash.to_raw_hash(),
+            );
+            return Ok(ShareValidationResult::StaleBlockCandidate(
+                Some(job.get_template().template_id),
+                BlockSolution {
+                    header,
+                    coinbase: serialize_coinbase(job)?,
+                },
+            ));
+        }
+        // without its coinbase, a compact stale job can't yield a block candidate
+        if self.job_store.get_compact_stale_job(job_id).is_some() {
             return Err(ShareValidationError::Stale);
         }
 
-        // if job_id is not active, past or stale, return error
-        if !is_active_job && !is_past_job && !is_stale_job {
-            return Err(ShareValidationError::InvalidJobId);
+        if self.job_store.is_empty() {
+            debug!(
+                "share for job {} on channel {} submitted before any job was sent",
+                job_id, self.channel_id
+            );
+            return Err(ShareValidationError::NoActiveJob);
         }
 
-        let job = if is_active_job {
-            self.job_store
-                .get_active_job()
-                .expect("active job must exist")
-        } else if is_past_job {
-            self.job_store
-                .get_past_jobs()
-                .get(&job_id)
-                .expect("past job must exist")
-        } else {
-            self.job_store
-                .get_stale_jobs()
-                .get(&job_id)
-                .expect("stale job must exist")
+        // only the active job and the past jobs under the current chain tip can be mined on
+        let job_store = &self.job_store;
+        let job = match job_store
+            .get_active_job()
+            .filter(|job| job.get_job_id() == job_id)
+            .or_else(|| job_store.get_past_jobs().get(&job_id))
+        {
+            Some(job) => ShareJob::Full(job),
+            None => match job_store.get_compact_past_job(job_id) {
+                Some(job) => ShareJob::Compact(job),
+                None => return Err(ShareValidationError::InvalidJobId),
+            },
         };
+        if let Err(e) = validate_share_job_id(job_id, job.get_job_id()) {
+            error!(
+                "job store of channel {} returned job {} for job {}",
+                self.channel_id,
+                job.get_job_id(),
+                job_id
+            );
+            return Err(e);
+        }
 
-        let merkle_root: [u8; 32] = job
-            .get_merkle_root()
-            .inner_as_ref()
-            .try_into()
-            .expect("merkle root must be 32 bytes");
+        if let Some(share_policy) = self.share_policy.as_mut() {
+            match job {
+                ShareJob::Full(job) => share_policy.pre_validate(share, job)?,
+                ShareJob::Compact(job) => share_policy.pre_validate_compact(share, job)?,
+            }
+        }
+
+        let merkle_root = match job {
+            ShareJob::Full(job) => job
+                .get_merkle_root()
+                .inner_as_ref()
+                .try_into()
+                .map(WireU256::from_wire_bytes)
+                .map_err(|_| {
+                    error!(
+                        "job {} of channel {} has a malformed merkle root",
+                        job_id, self.channel_id
+                    );
+                    ShareValidationError::Internal(InternalInconsistency::MalformedMerkleRoot)
+                })?,
+            ShareJob::Compact(job) => job.get_merkle_root(),
+        };
 
         let chain_tip = self
             .chain_tip
             .as_ref()
             .ok_or(ShareValidationError::NoChainTip)?;
 
-        let prev_hash = chain_tip.prev_hash();
         let nbits = CompactTarget::from_consensus(chain_tip.nbits());
 
+        // jobs created on top of the current chain tip carry its timestamp as well
+        check_share(
+            job.get_version(),
+            job.activation_ntime()
+                .unwrap_or_else(|| chain_tip.min_ntime()),
+            share.version,
+            share.ntime,
+            self.job_factory.is_version_rolling_allowed(),
+        )?;
+
         // create the header for validation
         let header = Header {
             version: Version::from_consensus(share.version as i32),
-            prev_blockhash: u256_to_block_hash(prev_hash.clone()),
-            merkle_root: (*Hash::from_bytes_ref(&merkle_root)).into(),
+            prev_blockhash: chain_tip.prev_block_hash(),
+            merkle_root: (*Hash::from_bytes_ref(&merkle_root.to_wire_bytes())).into(),
             time: share.ntime,
             bits: nbits,
             nonce: share.nonce,
         };
 
         // convert the header hash to a target type for easy comparison
-        let hash = header.block_hash();
-        let raw_hash: [u8; 32] = *hash.to_raw_hash().as_ref();
-        let hash_as_target: Target = raw_hash.into();
+        let hash = block_hash(self.header_hasher.as_ref(), &header);
+        let hash_as_target: Target = WireU256::from(hash).into();
         let hash_as_diff = target_to_difficulty(hash_as_target.clone());
         let network_target = BitcoinTarget::from_compact(nbits);
 
-        // print hash_as_target and self.target as human readable hex
-        let hash_as_u256: binary_sv2::U256 = hash_as_target.clone().into();
-        let mut hash_bytes = hash_as_u256.to_vec();
-        hash_bytes.reverse(); // Convert to big-endian for display
-        let target_u256: binary_sv2::U256 = self.target.clone().into();
-        let mut target_bytes = target_u256.to_vec();
-        target_bytes.reverse(); // Convert to big-endian for display
-
-        debug!(
-            "share validation \nshare:\t\t{}\nchannel target:\t{}\nnetwork target:\t{}",
-            bytes_to_hex(&hash_bytes),
-            bytes_to_hex(&target_bytes),
-            format!("{:x}", network_target)
-        );
+        if debug_enabled!() {
+            debug!(
+                "share validation \nshare:\t\t{}\nchannel target:\t{}\nnetwork target:\t{}",
+                DisplayU256::from(&hash_as_target),
+                DisplayU256::from(&target),
+                format!("{:x}", network_target)
+            );
+        }
 
         // check if a block was found
         if network_target.is_met_by(hash) {
             self.share_accounting.update_share_accounting(
-                target_to_difficulty(self.target.clone()) as u64,
+                target_to_difficulty(target.clone()) as u64,
                 share.sequence_number,
                 hash.to_raw_hash(),
             );
 
-            let mut script_sig = job.get_template().coinbase_prefix.to_vec();
-            script_sig.extend(job.get_extranonce_prefix());
-
-            let tx_in = TxIn {
-                previous_output: OutPoint::null(),
-                script_sig: script_sig.into(),
-                sequence: Sequence(job.get_template().coinbase_tx_input_sequence),
-                witness: Witness::from(vec![vec![0; 32]]),
-            };
-
-            let coinbase = Transaction {
-                version: TxVersion::non_standard(job.get_template().coinbase_tx_version as i32),
-                lock_time: LockTime::from_consensus(job.get_template().coinbase_tx_locktime),
-                input: vec![tx_in],
-                output: job.get_coinbase_outputs().to_vec(),
+            let coinbase = match job {
+                ShareJob::Full(job) => Some(serialize_coinbase(job)?),
+                ShareJob::Compact(_) => None,
             };
-            let mut serialized_coinbase = Vec::new();
-            coinbase
-                .consensus_encode(&mut serialized_coinbase)
-                .map_err(|_| ShareValidationError::InvalidCoinbase)?;
-
-            return Ok(ShareValidationResult::BlockFound(
-                Some(job.get_template().template_id),
-                serialized_coinbase,
-            ));
+            let template_id = Some(job.get_template_id());
+            let event = BlockFoundEvent::new(
+                self.channel_id,
+                self.user_identity.as_str(),
+                job_id,
+                share.sequence_number,
+                template_id,
+                &header,
+                hash,
+                unix_timestamp(self.clock.as_ref()),
+            );
+            if let Some(share_policy) = self.share_policy.as_mut() {
+                share_policy.post_accept(&ShareCheck {
+                    channel_id: self.channel_id,
+                    user_identity: self.user_identity.as_str(),
+                    job_id,
+                    sequence_number: share.sequence_number,
+                    difficulty: hash_as_diff,
+                    block_found: true,
+                });
+                share_policy.on_block_found(&event);
+            }
+            return Ok(match coinbase {
+                Some(coinbase) => ShareValidationResult::BlockFound(template_id, coinbase, event),
+                None => ShareValidationResult::BlockFoundNeedsJobData(job_id),
+            });
         }
 
         // check if the share hash meets the channel target
-        if hash_as_target <= self.target {
+        if hash_as_target <= target {
             if self.share_accounting.is_share_seen(hash.to_raw_hash()) {
                 return Err(ShareValidationError::DuplicateShare);
             }
 
             self.share_accounting.update_share_accounting(
-                target_to_difficulty(self.target.clone()) as u64,
+                target_to_difficulty(target.clone()) as u64,
                 share.sequence_number,
                 hash.to_raw_hash(),
             );
@@ -467,45 +2106,149 @@ impl<'a> StandardChannel<'a> {
             // update the best diff
             self.share_accounting.update_best_diff(hash_as_diff);
 
-            let last_sequence_number = self.share_accounting.get_last_share_sequence_number();
-            let new_submits_accepted_count = self.share_accounting.get_shares_accepted();
-            let new_shares_sum = self.share_accounting.get_share_work_sum();
-
-            // if sequence number is a multiple of share_batch_size
-            // it's time to send a SubmitShares.Success
-            if self.share_accounting.should_acknowledge() {
-                Ok(ShareValidationResult::ValidWithAcknowledgement(
-                    last_sequence_number,
-                    new_submits_accepted_count,
-                    new_shares_sum,
-                ))
-            } else {
-                Ok(ShareValidationResult::Valid)
+            if let Some(share_policy) = self.share_policy.as_mut() {
+                share_policy.post_accept(&ShareCheck {
+                    channel_id: self.channel_id,
+                    user_identity: self.user_identity.as_str(),
+                    job_id,
+                    sequence_number: share.sequence_number,
+                    difficulty: hash_as_diff,
+                    block_found: false,
+                });
             }
+
+            // every share_batch_size accepted shares, it's time to send a SubmitShares.Success
+            Ok(self.share_accounting.validation_result())
         } else {
             Err(ShareValidationError::DoesNotMeetTarget)
         }
     }
 }
 
+// The job a share is validated against: either kept in full, or a past job demoted to a
+// `CompactJob` by the job store.
+#[derive(Clone, Copy)]
+enum ShareJob<'j, 'a> {
+    Full(&'j StandardJob<'a>),
+    Compact(&'j CompactJob),
+}
+
+impl ShareJob<'_, '_> {
+    fn get_job_id(&self) -> u32 {
+        match self {
+            ShareJob::Full(job) => job.get_job_id(),
+            ShareJob::Compact(job) => job.get_job_id(),
+        }
+    }
+
+    fn get_template_id(&self) -> u64 {
+        match self {
+            ShareJob::Full(job) => job.get_template().template_id,
+            ShareJob::Compact(job) => job.get_template_id(),
+        }
+    }
+
+    fn get_version(&self) -> u32 {
+        match self {
+            ShareJob::Full(job) => job.get_job_message().version,
+            ShareJob::Compact(job) => job.get_version(),
+        }
+    }
+
+    fn activation_ntime(&self) -> Option<u32> {
+        match self {
+            ShareJob::Full(job) => job.activation_ntime(),
+            ShareJob::Compact(job) => job.activation_ntime(),
+        }
+    }
+}
+
+// Serializes the coinbase of `job`, as it's committed to by the job's merkle root.
+fn serialize_coinbase(job: &StandardJob) -> Result<Vec<u8>, ShareValidationError> {
+    let mut script_sig = job.get_template().coinbase_prefix.to_vec();
+    script_sig.extend(
+        job.get_extranonce()
+            .map_err(|_| ShareValidationError::InvalidCoinbase)?,
+    );
+
+    let tx_in = TxIn {
+        previous_output: OutPoint::null(),
+        script_sig: script_sig.into(),
+        sequence: Sequence(job.get_template().coinbase_tx_input_sequence),
+        witness: Witness::from(vec![vec![0; 32]]),
+    };
+
+    let coinbase = Transaction {
+        version: TxVersion::non_standard(job.get_template().coinbase_tx_version as i32),
+        lock_time: LockTime::from_consensus(job.get_template().coinbase_tx_locktime),
+        input: vec![tx_in],
+        output: job.get_coinbase_outputs().to_vec(),
+    };
+    let mut serialized_coinbase = Vec::new();
+    coinbase
+        .consensus_encode(&mut serialized_coinbase)
+        .map_err(|_| ShareValidationError::InvalidCoinbase)?;
+    Ok(serialized_coinbase)
+}
+
+// Checks `target` against `requested_max_target`, returning the target to use along with the
+// matching expected share rate.
+//
+// The expected share rate is proportional to the target, so clamping scales it by the ratio of
+// the difficulties of the two targets.
+fn apply_max_target_policy(
+    target: Target,
+    requested_max_target: &Target,
+    expected_share_per_minute: f32,
+    max_target_policy: MaxTargetPolicy,
+) -> Result<(Target, f32), StandardChannelError> {
+    if &target <= requested_max_target {
+        return Ok((target, expected_share_per_minute));
+    }
+    match max_target_policy {
+        MaxTargetPolicy::Reject => Err(StandardChannelError::RequestedMaxTargetOutOfRange),
+        MaxTargetPolicy::ClampToRequestedMax => {
+            let ratio =
+                target_to_difficulty(target) / target_to_difficulty(requested_max_target.clone());
+            Ok((
+                requested_max_target.clone(),
+                (expected_share_per_minute as f64 * ratio) as f32,
+            ))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::collections::HashMap;
     use crate::{
-        chain_tip::ChainTip,
+        client::{
+            error::StandardChannelError as ClientStandardChannelError,
+            standard::StandardChannel as ClientStandardChannel,
+        },
+        connection::ConnectionFlags,
+        prelude::*,
         server::{
-            error::StandardChannelError,
-            jobs::{job_store::DefaultJobStore, standard::StandardJob},
-            share_accounting::{ShareValidationError, ShareValidationResult},
-            standard::StandardChannel,
+            jobs::{
+                error::{JobError, JobFactoryError},
+                factory::ExtranoncePadding,
+                job_store::StaleRetention,
+                JobRateLimit, TemplateReplayPolicy,
+            },
+            share_accounting::{JobShareCounts, ShareAccounting},
+            standard::{ChannelResumeHint, DownstreamMessage, MaxTargetPolicy},
         },
+        target::{compact_tolerance_bits, hash_rate_to_target, hex_to_u256, WireU256},
+        testing::{
+            fixture::{self, SATS_AVAILABLE_IN_TEMPLATE},
+            run_script, Event, Outcome,
+        },
+        user_identity::{UserIdentityError, UserIdentityRules},
     };
     use binary_sv2::Sv2Option;
-    use bitcoin::{transaction::TxOut, Amount, ScriptBuf};
-    use mining_sv2::{NewMiningJob, SubmitSharesStandard, Target};
+    use bitcoin::{Amount, ScriptBuf};
+    use common_messages_sv2::has_version_rolling;
     use std::convert::TryInto;
-    use template_distribution_sv2::{NewTemplate, SetNewPrevHash as SetNewPrevHashTdp};
-
-    const SATS_AVAILABLE_IN_TEMPLATE: u64 = 5000000000;
 
     #[test]
     fn test_future_job_activation_flow() {
@@ -515,64 +2258,30 @@ mod tests {
         let standard_channel_id = 1;
         let user_identity = "user_identity".to_string();
 
-        let extranonce_prefix = [
-            83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-        ]
-        .to_vec();
+        let extranonce_prefix = fixture::extranonce_prefix();
 
-        let max_target: Target = [0xff; 32].into();
+        let max_target = Target::MAX;
         let nominal_hashrate = 10.0;
         let share_batch_size = 100;
         let expected_share_per_minute = 1.0;
         let job_store = Box::new(DefaultJobStore::<StandardJob>::new());
 
-        let mut standard_channel = StandardChannel::new(
-            standard_channel_id,
-            user_identity,
-            extranonce_prefix.clone(),
-            max_target,
-            nominal_hashrate,
-            share_batch_size,
-            expected_share_per_minute,
+        let mut standard_channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(standard_channel_id)
+                .user_identity(user_identity)
+                .extranonce_prefix(extranonce_prefix.clone())
+                .requested_max_target(max_target)
+                .nominal_hashrate(nominal_hashrate)
+                .share_batch_size(share_batch_size)
+                .expected_share_per_minute(expected_share_per_minute),
             job_store,
         )
         .unwrap();
 
-        let template = NewTemplate {
-            template_id: 1,
-            future_template: true,
-            version: 536870912,
-            coinbase_tx_version: 2,
-            coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
-            coinbase_tx_input_sequence: 4294967294,
-            coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
-            coinbase_tx_outputs_count: 1,
-            coinbase_tx_outputs: vec![
-                0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
-                222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
-                139, 235, 216, 54, 151, 78, 140, 249,
-            ]
-            .try_into()
-            .unwrap(),
-            coinbase_tx_locktime: 158,
-            merkle_path: vec![].try_into().unwrap(),
-        };
+        let template = fixture::template(true);
 
-        // match the original script format used to generate the coinbase_reward_outputs for the
-        // expected job
-        let pubkey_hash = [
-            235, 225, 183, 220, 194, 147, 204, 170, 14, 231, 67, 168, 111, 137, 223, 130, 88, 194,
-            8, 252,
-        ];
-        let mut script_bytes = vec![0]; // SegWit version 0
-        script_bytes.push(20); // Push 20 bytes (length of pubkey hash)
-        script_bytes.extend_from_slice(&pubkey_hash);
-        let script = ScriptBuf::from(script_bytes);
-        let coinbase_reward_outputs = vec![TxOut {
-            value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
-            script_pubkey: script,
-        }];
+        let coinbase_reward_outputs = fixture::coinbase_reward_outputs();
 
         assert!(standard_channel.get_future_jobs().is_empty());
 
@@ -583,11 +2292,7 @@ mod tests {
         let expected_future_standard_job = NewMiningJob {
             channel_id: standard_channel_id,
             job_id: 1,
-            merkle_root: [
-                189, 200, 25, 246, 119, 73, 34, 42, 209, 112, 237, 50, 169, 71, 163, 192, 24, 84,
-                56, 86, 147, 71, 243, 44, 18, 107, 167, 169, 169, 66, 186, 98,
-            ]
-            .into(),
+            merkle_root: fixture::MERKLE_ROOT.into(),
             version: 536870912,
             min_ntime: Sv2Option::new(None),
         };
@@ -599,22 +2304,8 @@ mod tests {
             &expected_future_standard_job
         );
 
-        let ntime = 1747092633;
-        let set_new_prev_hash = SetNewPrevHashTdp {
-            template_id: template.template_id,
-            prev_hash: [
-                200, 53, 253, 129, 214, 31, 43, 84, 179, 58, 58, 76, 128, 213, 24, 53, 38, 144,
-                205, 88, 172, 20, 251, 22, 217, 141, 21, 221, 21, 0, 0, 0,
-            ]
-            .into(),
-            header_timestamp: ntime,
-            n_bits: 503543726,
-            target: [
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                174, 119, 3, 0, 0,
-            ]
-            .into(),
-        };
+        let ntime = fixture::NTIME;
+        let set_new_prev_hash = fixture::set_new_prev_hash(template.template_id);
 
         standard_channel
             .on_set_new_prev_hash(set_new_prev_hash)
@@ -640,74 +2331,33 @@ mod tests {
         let standard_channel_id = 1;
         let user_identity = "user_identity".to_string();
 
-        let extranonce_prefix = [
-            83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-        ]
-        .to_vec();
+        let extranonce_prefix = fixture::extranonce_prefix();
 
-        let max_target: Target = [0xff; 32].into();
+        let max_target = Target::MAX;
         let nominal_hashrate = 10.0;
         let share_batch_size = 100;
         let expected_share_per_minute = 1.0;
 
         let job_store = Box::new(DefaultJobStore::<StandardJob>::new());
 
-        let mut standard_channel = StandardChannel::new(
-            standard_channel_id,
-            user_identity,
-            extranonce_prefix.clone(),
-            max_target,
-            nominal_hashrate,
-            share_batch_size,
-            expected_share_per_minute,
+        let mut standard_channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(standard_channel_id)
+                .user_identity(user_identity)
+                .extranonce_prefix(extranonce_prefix.clone())
+                .requested_max_target(max_target)
+                .nominal_hashrate(nominal_hashrate)
+                .share_batch_size(share_batch_size)
+                .expected_share_per_minute(expected_share_per_minute),
             job_store,
         )
         .unwrap();
 
-        let ntime = 1747092633;
-        let prev_hash = [
-            200, 53, 253, 129, 214, 31, 43, 84, 179, 58, 58, 76, 128, 213, 24, 53, 38, 144, 205,
-            88, 172, 20, 251, 22, 217, 141, 21, 221, 21, 0, 0, 0,
-        ]
-        .into();
-        let nbits = 503543726;
-
-        let chain_tip = ChainTip::new(prev_hash, nbits, ntime);
-        let template = NewTemplate {
-            template_id: 1,
-            future_template: false,
-            version: 536870912,
-            coinbase_tx_version: 2,
-            coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
-            coinbase_tx_input_sequence: 4294967294,
-            coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
-            coinbase_tx_outputs_count: 1,
-            coinbase_tx_outputs: vec![
-                0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
-                222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
-                139, 235, 216, 54, 151, 78, 140, 249,
-            ]
-            .try_into()
-            .unwrap(),
-            coinbase_tx_locktime: 158,
-            merkle_path: vec![].try_into().unwrap(),
-        };
+        let ntime = fixture::NTIME;
+        let chain_tip = fixture::chain_tip();
+        let template = fixture::template(false);
 
-        // match the original script format used to generate the coinbase_reward_outputs for the
-        // expected job
-        let pubkey_hash = [
-            235, 225, 183, 220, 194, 147, 204, 170, 14, 231, 67, 168, 111, 137, 223, 130, 88, 194,
-            8, 252,
-        ];
-        let mut script_bytes = vec![0]; // SegWit version 0
-        script_bytes.push(20); // Push 20 bytes (length of pubkey hash)
-        script_bytes.extend_from_slice(&pubkey_hash);
-        let script = ScriptBuf::from(script_bytes);
-        let coinbase_reward_outputs = vec![TxOut {
-            value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
-            script_pubkey: script,
-        }];
+        let coinbase_reward_outputs = fixture::coinbase_reward_outputs();
 
         standard_channel.set_chain_tip(chain_tip);
         standard_channel
@@ -717,11 +2367,7 @@ mod tests {
         let expected_active_standard_job = NewMiningJob {
             channel_id: standard_channel_id,
             job_id: 1,
-            merkle_root: [
-                189, 200, 25, 246, 119, 73, 34, 42, 209, 112, 237, 50, 169, 71, 163, 192, 24, 84,
-                56, 86, 147, 71, 243, 44, 18, 107, 167, 169, 169, 66, 186, 98,
-            ]
-            .into(),
+            merkle_root: fixture::MERKLE_ROOT.into(),
             version: 536870912,
             min_ntime: Sv2Option::new(Some(ntime)),
         };
@@ -743,53 +2389,396 @@ mod tests {
         let standard_channel_id = 1;
         let user_identity = "user_identity".to_string();
 
-        let extranonce_prefix = [
-            83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-        ]
-        .to_vec();
-        let max_target: Target = [0xff; 32].into();
+        let extranonce_prefix = fixture::extranonce_prefix();
+        let max_target = Target::MAX;
         let nominal_hashrate = 1.0;
         let share_batch_size = 100;
         let expected_share_per_minute = 1.0;
 
         let job_store = Box::new(DefaultJobStore::<StandardJob>::new());
 
-        let mut standard_channel = StandardChannel::new(
-            standard_channel_id,
-            user_identity,
-            extranonce_prefix.clone(),
-            max_target,
-            nominal_hashrate,
-            share_batch_size,
-            expected_share_per_minute,
+        let mut standard_channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(standard_channel_id)
+                .user_identity(user_identity)
+                .extranonce_prefix(extranonce_prefix.clone())
+                .requested_max_target(max_target)
+                .nominal_hashrate(nominal_hashrate)
+                .share_batch_size(share_batch_size)
+                .expected_share_per_minute(expected_share_per_minute),
             job_store,
         )
         .unwrap();
 
         // channel target: 04325c53ef368eb04325c53ef368eb04325c53ef368eb04325c53ef368eb0431
-        let template = NewTemplate {
-            template_id: 1,
-            future_template: false,
+        let template = fixture::template(false);
+
+        let coinbase_reward_outputs = fixture::coinbase_reward_outputs();
+
+        // network target: 7fffff0000000000000000000000000000000000000000000000000000000000
+        let ntime = 1745596910;
+        let prev_hash =
+            hex_to_u256("fbaf6a2823577a5a3a334e20caece4249aaece9093c315e0c367d6bd33be1862")
+                .unwrap();
+        let n_bits = 545259519;
+        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime).unwrap();
+
+        // this share has hash 40b4c57b2c65052bbe1092e556146ad78cdd9e5ffaeff856a0eb54ee7b816da7
+        // which satisfied the network target
+        // 7fffff0000000000000000000000000000000000000000000000000000000000
+        let share_valid_block = SubmitSharesStandard {
+            channel_id: standard_channel_id,
+            sequence_number: 0,
+            // first job created by the channel
+            job_id: 1,
+            nonce: 3,
+            ntime: 1745596932,
             version: 536870912,
-            coinbase_tx_version: 2,
-            coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
-            coinbase_tx_input_sequence: 4294967294,
-            coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
-            coinbase_tx_outputs_count: 1,
-            coinbase_tx_outputs: vec![
-                0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
-                222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
-                139, 235, 216, 54, 151, 78, 140, 249,
-            ]
-            .try_into()
-            .unwrap(),
-            coinbase_tx_locktime: 158,
-            merkle_path: vec![].try_into().unwrap(),
         };
 
-        // match the original script format used to generate the coinbase_reward_outputs for the
-        // expected job
+        // prepare standard channel with non-future job, then submit the share
+        let script = vec![
+            Event::SetChainTip(chain_tip),
+            Event::NewTemplate(template, coinbase_reward_outputs),
+            Event::SubmitShare(share_valid_block),
+        ];
+        #[cfg(feature = "std")]
+        let shares = {
+            let (observer, shares) = crate::testing::doubles::RecordingShareObserver::new();
+            standard_channel.set_share_policy(Box::new(observer));
+            shares
+        };
+        #[cfg(feature = "std")]
+        let before = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut outcomes = run_script(&mut standard_channel, script);
+
+        assert!(matches!(outcomes[1], Outcome::Channel(Ok(()))));
+        let res = outcomes.pop().unwrap().unwrap_share();
+        let event = match res {
+            Ok(ShareValidationResult::BlockFound(template_id, _, event)) => {
+                assert_eq!(template_id, Some(1));
+                event
+            }
+            other => panic!("expected a block, got {:?}", other),
+        };
+        assert_eq!(event.channel_id, standard_channel_id);
+        assert_eq!(event.user_identity, "user_identity");
+        assert_eq!(event.job_id, 1);
+        assert_eq!(event.sequence_number, 0);
+        assert_eq!(event.template_id, Some(1));
+        assert_eq!(
+            event.block_hash().to_string(),
+            "40b4c57b2c65052bbe1092e556146ad78cdd9e5ffaeff856a0eb54ee7b816da7"
+        );
+        assert_eq!(event.network_difficulty, 4.6565423739069247e-10);
+        assert_eq!(event.nonce, 3);
+        assert_eq!(event.ntime, 1745596932);
+        assert_eq!(event.version, 536870912);
+        #[cfg(feature = "std")]
+        {
+            let timestamp = event.timestamp.unwrap();
+            assert!(timestamp >= before && timestamp <= before + 60);
+            assert_eq!(shares.blocks_found(), vec![event.clone()]);
+        }
+        #[cfg(not(feature = "std"))]
+        assert_eq!(event.timestamp, None);
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&event).unwrap();
+            let deserialized: crate::server::block_found::BlockFoundEvent =
+                serde_json::from_str(&json).unwrap();
+            assert_eq!(deserialized, event);
+        }
+
+        #[cfg(feature = "event-log")]
+        {
+            use crate::server::event_log::ChannelEventKind;
+
+            let kinds: Vec<_> = standard_channel
+                .get_event_log()
+                .events()
+                .map(|event| event.kind.clone())
+                .collect();
+            assert_eq!(
+                kinds,
+                vec![
+                    ChannelEventKind::JobActivated { job_id: 1 },
+                    ChannelEventKind::TemplateAccepted {
+                        template_id: 1,
+                        future_template: false,
+                    },
+                    ChannelEventKind::BlockFound {
+                        job_id: 1,
+                        sequence_number: 0,
+                        template_id: Some(1),
+                    },
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_share_validation_block_found_with_padded_extranonce() {
+        use bitcoin::hashes::Hash as _;
+
+        // same test vectors as test_share_validation_block_found, with a 4 bytes extranonce
+        // prefix zero-filled to different extranonce lengths
+        let extranonce_prefix = fixture::extranonce_prefix()[..4].to_vec();
+        let prev_hash =
+            hex_to_u256("fbaf6a2823577a5a3a334e20caece4249aaece9093c315e0c367d6bd33be1862")
+                .unwrap();
+        let chain_tip = ChainTip::new(prev_hash, 545259519, 1745596910).unwrap();
+
+        let mut merkle_roots = Vec::new();
+        for total_len in [4, 16, 32] {
+            let extranonce_padding = ExtranoncePadding::ZeroFill { total_len };
+            let mut standard_channel = StandardChannel::from_config(
+                fixture::standard_channel_config()
+                    .extranonce_prefix(extranonce_prefix.clone())
+                    .extranonce_padding(extranonce_padding)
+                    .share_batch_size(100),
+                Box::new(DefaultJobStore::<StandardJob>::new()),
+            )
+            .unwrap();
+            let script = vec![
+                Event::SetChainTip(chain_tip.clone()),
+                Event::NewTemplate(fixture::template(false), fixture::coinbase_reward_outputs()),
+            ];
+            let outcomes = run_script(&mut standard_channel, script);
+            assert!(matches!(outcomes[1], Outcome::Channel(Ok(()))));
+            let job = standard_channel.get_active_job().unwrap().clone();
+            assert_eq!(job.get_extranonce_padding(), extranonce_padding);
+            merkle_roots.push(job.get_merkle_root().clone());
+
+            // the network target is met by about every other nonce
+            let coinbase = (0..64)
+                .find_map(|nonce| {
+                    let share = SubmitSharesStandard {
+                        channel_id: 1,
+                        sequence_number: nonce,
+                        job_id: job.get_job_id(),
+                        nonce,
+                        ntime: 1745596932,
+                        version: 536870912,
+                    };
+                    match standard_channel.validate_share(share) {
+                        Ok(ShareValidationResult::BlockFound(_, coinbase, _)) => Some(coinbase),
+                        _ => None,
+                    }
+                })
+                .expect("no nonce found a block");
+
+            // the coinbase of the block commits to the merkle root of the job
+            let coinbase: bitcoin::Transaction =
+                bitcoin::consensus::deserialize(&coinbase).unwrap();
+            assert_eq!(
+                coinbase.compute_txid().to_byte_array(),
+                job.get_merkle_root().inner_as_ref()
+            );
+            let mut script_sig = job.get_template().coinbase_prefix.to_vec();
+            script_sig.extend(&extranonce_prefix);
+            script_sig.resize(script_sig.len() + total_len - extranonce_prefix.len(), 0);
+            assert_eq!(coinbase.input[0].script_sig.as_bytes(), &script_sig[..]);
+        }
+        merkle_roots.dedup();
+        assert_eq!(merkle_roots.len(), 3);
+
+        // the extranonce prefix must fit the padded extranonce
+        let config = StandardChannelConfig::default()
+            .channel_id(1)
+            .user_identity("user_identity".to_string())
+            .requested_max_target(Target::MAX)
+            .nominal_hashrate(1.0)
+            .expected_share_per_minute(1.0);
+        let result = StandardChannel::from_config(
+            config
+                .clone()
+                .extranonce_prefix(vec![0; 5])
+                .extranonce_padding(ExtranoncePadding::ZeroFill { total_len: 4 }),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        );
+        assert!(matches!(
+            result,
+            Err(StandardChannelError::NewExtranoncePrefixTooLarge)
+        ));
+        let result = StandardChannel::from_config(
+            config
+                .extranonce_prefix(vec![0; 4])
+                .extranonce_padding(ExtranoncePadding::ZeroFill { total_len: 33 }),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        );
+        assert!(matches!(
+            result,
+            Err(StandardChannelError::JobFactoryError(
+                JobFactoryError::InvalidExtranoncePadding(33)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_compact_past_jobs() {
+        use crate::{
+            collections::HashSet,
+            server::jobs::{compact::CompactJob, job_store::PastJobRetention},
+        };
+        use core::mem::size_of;
+
+        const PAST_JOBS: u64 = 500;
+        const FULL_PAST_JOBS: usize = 10;
+
+        // what a job costs the job store, counting its encoded size as its heap size
+        fn full_job_size(job: &StandardJob) -> usize {
+            let state = job.to_state();
+            size_of::<StandardJob>()
+                + state.template.len()
+                + state.extranonce_prefix.len()
+                + state.coinbase_outputs.len()
+                + state.job_message.len()
+        }
+        fn compact_job_size(job: &CompactJob) -> usize {
+            size_of::<CompactJob>() + job.get_extranonce_prefix().len()
+        }
+        fn outcome(result: Result<ShareValidationResult, ShareValidationError>) -> &'static str {
+            match result {
+                Ok(ShareValidationResult::Valid)
+                | Ok(ShareValidationResult::ValidWithAcknowledgement(..)) => "valid",
+                Ok(ShareValidationResult::BlockFound(..)) => "block",
+                Ok(ShareValidationResult::BlockFoundNeedsJobData(1)) => "block-needs-job-data",
+                Ok(ShareValidationResult::BlockFoundNeedsJobData(_)) => "block-needs-other-job",
+                Ok(ShareValidationResult::StaleBlockCandidate(..)) => "stale-block",
+                Err(ShareValidationError::Stale) => "stale",
+                Err(_) => "invalid",
+            }
+        }
+
+        let channel = |job_store: DefaultJobStore<StandardJob<'static>>| {
+            let mut channel = StandardChannel::from_config(
+                fixture::standard_channel_config(),
+                Box::new(job_store),
+            )
+            .unwrap();
+            // every share is valid, and the network target is met by about every other one
+            channel.set_target(Target::MAX);
+            let mut script = vec![Event::SetChainTip(
+                ChainTip::new(fixture::prev_hash(), 545259519, fixture::NTIME).unwrap(),
+            )];
+            for template_id in 1..=PAST_JOBS + 1 {
+                script.push(Event::NewTemplate(
+                    NewTemplate {
+                        template_id,
+                        ..fixture::template(false)
+                    },
+                    fixture::coinbase_reward_outputs(),
+                ));
+            }
+            for outcome in run_script(&mut channel, script) {
+                assert!(matches!(outcome, Outcome::Channel(Ok(()))));
+            }
+            channel
+        };
+        let mut full = channel(DefaultJobStore::new());
+        let mut compact = channel(
+            DefaultJobStore::new()
+                .with_past_job_retention(PastJobRetention::CompactAfter(FULL_PAST_JOBS)),
+        );
+
+        assert_eq!(full.job_store.get_past_jobs().len(), PAST_JOBS as usize);
+        assert_eq!(compact.job_store.get_past_jobs().len(), FULL_PAST_JOBS);
+        let compact_jobs: Vec<&CompactJob> = (1..=PAST_JOBS as u32)
+            .filter_map(|job_id| compact.job_store.get_compact_past_job(job_id))
+            .collect();
+        assert_eq!(compact_jobs.len(), PAST_JOBS as usize - FULL_PAST_JOBS);
+        // the oldest jobs are the compact ones
+        assert!(compact.job_store.get_compact_past_job(1).is_some());
+        assert!(compact
+            .job_store
+            .get_past_jobs()
+            .contains_key(&(PAST_JOBS as u32)));
+
+        let full_size: usize = full
+            .job_store
+            .get_past_jobs()
+            .values()
+            .map(full_job_size)
+            .sum();
+        let compact_size: usize = compact
+            .job_store
+            .get_past_jobs()
+            .values()
+            .map(full_job_size)
+            .sum::<usize>()
+            + compact_jobs
+                .iter()
+                .map(|job| compact_job_size(job))
+                .sum::<usize>();
+        assert!(
+            compact_size * 3 < full_size,
+            "{} bytes of past jobs with compaction, {} without",
+            compact_size,
+            full_size
+        );
+
+        // both channels validate shares for the oldest job alike, except for blocks
+        let mut outcomes = HashSet::new();
+        for nonce in 0..32 {
+            let share = SubmitSharesStandard {
+                sequence_number: nonce,
+                ..fixture::submit_shares_standard(1, 1, nonce)
+            };
+            let full_outcome = outcome(full.validate_share(share.clone()));
+            let compact_outcome = outcome(compact.validate_share(share));
+            match full_outcome {
+                "block" => assert_eq!(compact_outcome, "block-needs-job-data"),
+                _ => assert_eq!(compact_outcome, full_outcome),
+            }
+            outcomes.insert(full_outcome);
+        }
+        assert_eq!(outcomes, ["valid", "block"].iter().copied().collect());
+
+        // compact jobs become stale along with the full ones
+        let script = vec![
+            Event::NewTemplate(
+                NewTemplate {
+                    template_id: PAST_JOBS + 2,
+                    ..fixture::template(true)
+                },
+                fixture::coinbase_reward_outputs(),
+            ),
+            Event::SetNewPrevHash(fixture::set_new_prev_hash(PAST_JOBS + 2)),
+            Event::SubmitShare(fixture::submit_shares_standard(1, 1, 100)),
+        ];
+        let full_outcome = run_script(&mut full, script.clone()).pop().unwrap();
+        let compact_outcome = run_script(&mut compact, script).pop().unwrap();
+        assert_eq!(outcome(full_outcome.unwrap_share()), "stale");
+        assert_eq!(outcome(compact_outcome.unwrap_share()), "stale");
+        assert!(compact.job_store.get_compact_stale_job(1).is_some());
+        assert!(compact.job_store.get_compact_past_job(1).is_none());
+    }
+
+    #[test]
+    fn test_header_template() {
+        // same test vectors as test_share_validation_block_found
+        let standard_channel_id = 1;
+        let extranonce_prefix = fixture::extranonce_prefix();
+
+        let mut standard_channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(standard_channel_id)
+                .user_identity("user_identity".to_string())
+                .extranonce_prefix(extranonce_prefix.clone())
+                .requested_max_target(Target::MAX)
+                .nominal_hashrate(1.0)
+                .share_batch_size(100)
+                .expected_share_per_minute(1.0),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+
+        let template = fixture::template(false);
+
         let pubkey_hash = [
             235, 225, 183, 220, 194, 147, 204, 170, 14, 231, 67, 168, 111, 137, 223, 130, 88, 194,
             8, 252,
@@ -797,45 +2786,74 @@ mod tests {
         let mut script_bytes = vec![0]; // SegWit version 0
         script_bytes.push(20); // Push 20 bytes (length of pubkey hash)
         script_bytes.extend_from_slice(&pubkey_hash);
-        let script = ScriptBuf::from(script_bytes);
         let coinbase_reward_outputs = vec![TxOut {
             value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
-            script_pubkey: script,
+            script_pubkey: ScriptBuf::from(script_bytes),
         }];
 
-        // network target: 7fffff0000000000000000000000000000000000000000000000000000000000
         let ntime = 1745596910;
-        let prev_hash = [
-            251, 175, 106, 40, 35, 87, 122, 90, 58, 51, 78, 32, 202, 236, 228, 36, 154, 174, 206,
-            144, 147, 195, 21, 224, 195, 103, 214, 189, 51, 190, 24, 98,
-        ]
-        .into();
+        let prev_hash =
+            hex_to_u256("fbaf6a2823577a5a3a334e20caece4249aaece9093c315e0c367d6bd33be1862")
+                .unwrap();
         let n_bits = 545259519;
-        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime);
+        let chain_tip = ChainTip::new(prev_hash.clone(), n_bits, ntime).unwrap();
 
-        // prepare standard channel with non-future job
-        standard_channel.set_chain_tip(chain_tip);
+        standard_channel.set_chain_tip(chain_tip.clone());
         standard_channel
-            .on_new_template(template.clone(), coinbase_reward_outputs)
+            .on_new_template(template, coinbase_reward_outputs)
             .unwrap();
+        let job = standard_channel.get_active_job().unwrap();
+
+        let mut header = job.header_template(&chain_tip).unwrap();
+        assert_eq!(header.nonce, 0);
+        assert_eq!(header.time, ntime);
+        assert_eq!(header.version.to_consensus(), 536870912);
+        assert_eq!(header.bits.to_consensus(), n_bits);
+        assert_eq!(header.prev_blockhash, chain_tip.prev_block_hash());
+
+        // the share found in test_share_validation_block_found
+        header.nonce = 3;
+        header.time = 1745596932;
+        assert_eq!(
+            header.block_hash().to_string(),
+            "40b4c57b2c65052bbe1092e556146ad78cdd9e5ffaeff856a0eb54ee7b816da7"
+        );
 
-        let active_standard_job = standard_channel.get_active_job().unwrap();
-
-        // this share has hash 40b4c57b2c65052bbe1092e556146ad78cdd9e5ffaeff856a0eb54ee7b816da7
-        // which satisfied the network target
-        // 7fffff0000000000000000000000000000000000000000000000000000000000
-        let share_valid_block = SubmitSharesStandard {
-            channel_id: standard_channel_id,
-            sequence_number: 0,
-            job_id: active_standard_job.get_job_id(),
-            nonce: 3,
-            ntime: 1745596932,
-            version: 536870912,
-        };
-
-        let res = standard_channel.validate_share(share_valid_block);
+        // a client channel receiving the same job builds the same header
+        let mut client_channel = ClientStandardChannel::new(
+            standard_channel_id,
+            "user_identity".to_string(),
+            extranonce_prefix,
+            Target::MAX,
+            1.0,
+        );
+        let mut job_message = job.get_job_message().clone();
+        job_message.min_ntime = Sv2Option::new(None);
+        client_channel.on_new_mining_job(job_message.clone());
+        client_channel
+            .on_set_new_prev_hash(SetNewPrevHashMp {
+                channel_id: standard_channel_id,
+                job_id: job_message.job_id,
+                prev_hash,
+                min_ntime: ntime,
+                nbits: n_bits,
+            })
+            .unwrap();
+        assert!(matches!(
+            client_channel.header_template(2),
+            Err(ClientStandardChannelError::JobIdNotFound)
+        ));
+        assert_eq!(
+            client_channel.header_template(job_message.job_id).unwrap(),
+            job.header_template(&chain_tip).unwrap()
+        );
 
-        assert!(matches!(res, Ok(ShareValidationResult::BlockFound(_, _))));
+        // the job was created on another chain tip
+        let other_chain_tip = ChainTip::new([0; 32].into(), n_bits, ntime).unwrap();
+        assert_eq!(
+            job.header_template(&other_chain_tip),
+            Err(JobError::ChainTipMismatch)
+        );
     }
 
     #[test]
@@ -847,83 +2865,39 @@ mod tests {
         let standard_channel_id = 1;
         let user_identity = "user_identity".to_string();
 
-        let extranonce_prefix = [
-            83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-        ]
-        .to_vec();
-        let max_target: Target = [0xff; 32].into();
+        let extranonce_prefix = fixture::extranonce_prefix();
+        let max_target = Target::MAX;
         let nominal_hashrate = 100.0; // bigger hashrate to get higher difficulty
         let share_batch_size = 100;
         let expected_share_per_minute = 1.0;
 
         let job_store = Box::new(DefaultJobStore::<StandardJob>::new());
 
-        let mut standard_channel = StandardChannel::new(
-            standard_channel_id,
-            user_identity,
-            extranonce_prefix.clone(),
-            max_target,
-            nominal_hashrate,
-            share_batch_size,
-            expected_share_per_minute,
+        let mut standard_channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(standard_channel_id)
+                .user_identity(user_identity)
+                .extranonce_prefix(extranonce_prefix.clone())
+                .requested_max_target(max_target)
+                .nominal_hashrate(nominal_hashrate)
+                .share_batch_size(share_batch_size)
+                .expected_share_per_minute(expected_share_per_minute),
             job_store,
         )
         .unwrap();
 
         // channel target: 000aebbc990fff5144366f000aebbc990fff5144366f000aebbc990fff514435
-        let template = NewTemplate {
-            template_id: 1,
-            future_template: false,
-            version: 536870912,
-            coinbase_tx_version: 2,
-            coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
-            coinbase_tx_input_sequence: 4294967294,
-            coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
-            coinbase_tx_outputs_count: 1,
-            coinbase_tx_outputs: vec![
-                0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
-                222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
-                139, 235, 216, 54, 151, 78, 140, 249,
-            ]
-            .try_into()
-            .unwrap(),
-            coinbase_tx_locktime: 158,
-            merkle_path: vec![].try_into().unwrap(),
-        };
+        let template = fixture::template(false);
 
-        // match the original script format used to generate the coinbase_reward_outputs for the
-        // expected job
-        let pubkey_hash = [
-            235, 225, 183, 220, 194, 147, 204, 170, 14, 231, 67, 168, 111, 137, 223, 130, 88, 194,
-            8, 252,
-        ];
-        let mut script_bytes = vec![0]; // SegWit version 0
-        script_bytes.push(20); // Push 20 bytes (length of pubkey hash)
-        script_bytes.extend_from_slice(&pubkey_hash);
-        let script = ScriptBuf::from(script_bytes);
-        let coinbase_reward_outputs = vec![TxOut {
-            value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
-            script_pubkey: script,
-        }];
+        let coinbase_reward_outputs = fixture::coinbase_reward_outputs();
 
         // network target: 000000000000d7c0000000000000000000000000000000000000000000000000
         let ntime = 1745596910;
-        let prev_hash = [
-            154, 124, 239, 231, 221, 122, 160, 173, 164, 175, 87, 33, 74, 214, 191, 107, 73, 34, 0,
-            162, 227, 16, 44, 40, 33, 73, 0, 0, 0, 0, 0, 0,
-        ]
-        .into();
+        let prev_hash =
+            hex_to_u256("9a7cefe7dd7aa0ada4af57214ad6bf6b492200a2e3102c282149000000000000")
+                .unwrap();
         let n_bits = 453040064;
-        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime);
-
-        // prepare standard channel with non-future job
-        standard_channel.set_chain_tip(chain_tip);
-        standard_channel
-            .on_new_template(template.clone(), coinbase_reward_outputs)
-            .unwrap();
-
-        let active_standard_job = standard_channel.get_active_job().unwrap();
+        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime).unwrap();
 
         // this share has hash a5b65006d89dab9de2b23ececd3b0435f163607f7da1ba2f0bcde62b29e8cd44
         // which does not meet the channel target
@@ -931,14 +2905,23 @@ mod tests {
         let share_low_diff = SubmitSharesStandard {
             channel_id: standard_channel_id,
             sequence_number: 0,
-            job_id: active_standard_job.get_job_id(),
+            // first job created by the channel
+            job_id: 1,
             nonce: 3,
             ntime: 1745596932,
             version: 536870912,
         };
 
-        let res = standard_channel.validate_share(share_low_diff);
+        // prepare standard channel with non-future job, then submit the share
+        let script = vec![
+            Event::SetChainTip(chain_tip),
+            Event::NewTemplate(template, coinbase_reward_outputs),
+            Event::SubmitShare(share_low_diff),
+        ];
+        let mut outcomes = run_script(&mut standard_channel, script);
 
+        assert!(matches!(outcomes[1], Outcome::Channel(Ok(()))));
+        let res = outcomes.pop().unwrap().unwrap_share();
         assert!(matches!(
             res.unwrap_err(),
             ShareValidationError::DoesNotMeetTarget
@@ -954,26 +2937,23 @@ mod tests {
         let standard_channel_id = 1;
         let user_identity = "user_identity".to_string();
 
-        let extranonce_prefix = [
-            83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-        ]
-        .to_vec();
-        let max_target: Target = [0xff; 32].into();
+        let extranonce_prefix = fixture::extranonce_prefix();
+        let max_target = Target::MAX;
         let nominal_hashrate = 1_000.0; // bigger hashrate to get higher difficulty
         let share_batch_size = 100;
         let expected_share_per_minute = 1.0;
 
         let job_store = Box::new(DefaultJobStore::<StandardJob>::new());
 
-        let mut standard_channel = StandardChannel::new(
-            standard_channel_id,
-            user_identity,
-            extranonce_prefix.clone(),
-            max_target,
-            nominal_hashrate,
-            share_batch_size,
-            expected_share_per_minute,
+        let mut standard_channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(standard_channel_id)
+                .user_identity(user_identity)
+                .extranonce_prefix(extranonce_prefix.clone())
+                .requested_max_target(max_target)
+                .nominal_hashrate(nominal_hashrate)
+                .share_batch_size(share_batch_size)
+                .expected_share_per_minute(expected_share_per_minute),
             job_store,
         )
         .unwrap();
@@ -981,50 +2961,17 @@ mod tests {
         // channel target is:
         // 0001179d9861a761ffdadd11c307c4fc04eea3a418f7d687584e4434af158205
 
-        let template = NewTemplate {
-            template_id: 1,
-            future_template: false,
-            version: 536870912,
-            coinbase_tx_version: 2,
-            coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
-            coinbase_tx_input_sequence: 4294967294,
-            coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
-            coinbase_tx_outputs_count: 1,
-            coinbase_tx_outputs: vec![
-                0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
-                222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
-                139, 235, 216, 54, 151, 78, 140, 249,
-            ]
-            .try_into()
-            .unwrap(),
-            coinbase_tx_locktime: 158,
-            merkle_path: vec![].try_into().unwrap(),
-        };
+        let template = fixture::template(false);
 
-        // match the original script format used to generate the coinbase_reward_outputs for the
-        // expected job
-        let pubkey_hash = [
-            235, 225, 183, 220, 194, 147, 204, 170, 14, 231, 67, 168, 111, 137, 223, 130, 88, 194,
-            8, 252,
-        ];
-        let mut script_bytes = vec![0]; // SegWit version 0
-        script_bytes.push(20); // Push 20 bytes (length of pubkey hash)
-        script_bytes.extend_from_slice(&pubkey_hash);
-        let script = ScriptBuf::from(script_bytes);
-        let coinbase_reward_outputs = vec![TxOut {
-            value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
-            script_pubkey: script,
-        }];
+        let coinbase_reward_outputs = fixture::coinbase_reward_outputs();
 
         // network target: 000000000000d7c0000000000000000000000000000000000000000000000000
         let ntime = 1745596910;
-        let prev_hash = [
-            154, 124, 239, 231, 221, 122, 160, 173, 164, 175, 87, 33, 74, 214, 191, 107, 73, 34, 0,
-            162, 227, 16, 44, 40, 33, 73, 0, 0, 0, 0, 0, 0,
-        ]
-        .into();
+        let prev_hash =
+            hex_to_u256("9a7cefe7dd7aa0ada4af57214ad6bf6b492200a2e3102c282149000000000000")
+                .unwrap();
         let n_bits = 453040064;
-        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime);
+        let chain_tip = ChainTip::new(prev_hash, n_bits, ntime).unwrap();
 
         // prepare standard channel with non-future job
         standard_channel.set_chain_tip(chain_tip);
@@ -1050,6 +2997,148 @@ mod tests {
         assert!(matches!(res, Ok(ShareValidationResult::Valid)));
     }
 
+    #[test]
+    fn test_share_validation_version_rolling() {
+        let standard_channel_id = 1;
+        let extranonce_prefix = [
+            83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        ]
+        .to_vec();
+        let max_target = Target::MAX;
+
+        let template = NewTemplate {
+            template_id: 1,
+            future_template: false,
+            version: 536870912,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: 4294967294,
+            coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
+            coinbase_tx_outputs_count: 1,
+            coinbase_tx_outputs: vec![
+                0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
+                222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
+                139, 235, 216, 54, 151, 78, 140, 249,
+            ]
+            .try_into()
+            .unwrap(),
+            coinbase_tx_locktime: 158,
+            merkle_path: vec![].try_into().unwrap(),
+        };
+        let coinbase_reward_outputs = vec![TxOut {
+            value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
+            script_pubkey: ScriptBuf::new(),
+        }];
+        let prev_hash =
+            hex_to_u256("9a7cefe7dd7aa0ada4af57214ad6bf6b492200a2e3102c282149000000000000")
+                .unwrap();
+        let chain_tip = ChainTip::new(prev_hash, 453040064, 1745596910).unwrap();
+
+        let share = |sequence_number, version| SubmitSharesStandard {
+            channel_id: standard_channel_id,
+            sequence_number,
+            job_id: 1,
+            nonce: 31978,
+            ntime: 1745611105,
+            version,
+        };
+
+        // SetupConnection.flags with and without REQUIRES_VERSION_ROLLING
+        for flags in [0u32, 0b10] {
+            let version_rolling_allowed = has_version_rolling(flags);
+            assert_eq!(
+                ConnectionFlags::from(flags).requires_version_rolling(),
+                version_rolling_allowed
+            );
+
+            let mut standard_channel = StandardChannel::from_config(
+                StandardChannelConfig::default()
+                    .channel_id(standard_channel_id)
+                    .user_identity("user_identity".to_string())
+                    .extranonce_prefix(extranonce_prefix.clone())
+                    .requested_max_target(max_target.clone())
+                    .nominal_hashrate(1_000.0)
+                    .share_batch_size(100)
+                    .expected_share_per_minute(1.0),
+                Box::new(DefaultJobStore::<StandardJob>::new()),
+            )
+            .unwrap();
+            standard_channel.set_version_rolling_allowed(version_rolling_allowed);
+            assert_eq!(
+                standard_channel.is_version_rolling_allowed(),
+                version_rolling_allowed
+            );
+
+            standard_channel.set_chain_tip(chain_tip.clone());
+            standard_channel
+                .on_new_template(template.clone(), coinbase_reward_outputs.clone())
+                .unwrap();
+
+            // bit 13 is the lowest bit of the BIP320 mask
+            let res = standard_channel.validate_share(share(1, 536870912 | (1 << 13)));
+            if version_rolling_allowed {
+                assert!(!matches!(
+                    res,
+                    Err(ShareValidationError::VersionRollingNotAllowed)
+                ));
+            } else {
+                assert!(matches!(
+                    res,
+                    Err(ShareValidationError::VersionRollingNotAllowed)
+                ));
+            }
+
+            // bits outside the BIP320 mask can never be rolled
+            let res = standard_channel.validate_share(share(2, 536870912 | 1));
+            assert!(matches!(
+                res,
+                Err(ShareValidationError::VersionRollingNotAllowed)
+            ));
+
+            // the job version is always accepted
+            let res = standard_channel.validate_share(share(3, 536870912));
+            assert!(!matches!(
+                res,
+                Err(ShareValidationError::VersionRollingNotAllowed)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_export_import_policies() {
+        let job_rate_limit = JobRateLimit::new(2).with_fee_bump_threshold(1_000);
+        let channel = StandardChannel::from_config(
+            fixture::standard_channel_config()
+                .max_target_policy(MaxTargetPolicy::ClampToRequestedMax)
+                .template_replay_policy(TemplateReplayPolicy::NewJob)
+                .retain_previous_chain_tip(true)
+                .job_rate_limit(job_rate_limit)
+                .defer_target_changes(true),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+
+        let state = channel.export_state();
+        let imported_channel = StandardChannel::import_state(
+            state.clone(),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        assert_eq!(imported_channel.export_state(), state);
+        assert_eq!(
+            imported_channel.get_max_target_policy(),
+            MaxTargetPolicy::ClampToRequestedMax
+        );
+        assert_eq!(
+            imported_channel.get_template_replay_policy(),
+            TemplateReplayPolicy::NewJob
+        );
+        assert!(state.retain_previous_chain_tip);
+        assert_eq!(imported_channel.get_job_rate_limit(), Some(job_rate_limit));
+        assert!(imported_channel.get_defer_target_changes());
+    }
+
     #[test]
     fn test_update_channel() {
         let channel_id = 1;
@@ -1064,17 +3153,18 @@ mod tests {
         let share_batch_size = 100;
         let job_store = Box::new(DefaultJobStore::<StandardJob>::new());
         // this is the most permissive possible max_target
-        let max_target: Target = [0xff; 32].into();
+        let max_target = Target::MAX;
 
         // Create a channel with initial hashrate
-        let mut channel = StandardChannel::new(
-            channel_id,
-            user_identity,
-            extranonce_prefix,
-            max_target.clone(),
-            initial_hashrate,
-            share_batch_size,
-            expected_share_per_minute,
+        let mut channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(channel_id)
+                .user_identity(user_identity)
+                .extranonce_prefix(extranonce_prefix)
+                .requested_max_target(max_target.clone())
+                .nominal_hashrate(initial_hashrate)
+                .share_batch_size(share_batch_size)
+                .expected_share_per_minute(expected_share_per_minute),
             job_store,
         )
         .unwrap();
@@ -1140,6 +3230,90 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_update_channel_within_target_tolerance() {
+        let extranonce_prefix = [
+            83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        ]
+        .to_vec();
+        let max_target = Target::MAX;
+        let initial_hashrate = 1e12;
+
+        let mut channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(1)
+                .user_identity("user_identity".to_string())
+                .extranonce_prefix(extranonce_prefix)
+                .requested_max_target(max_target)
+                .nominal_hashrate(initial_hashrate)
+                .share_batch_size(100)
+                .expected_share_per_minute(1.0),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        let initial_target = channel.get_target().clone();
+
+        // the smallest possible f32 increment only changes the target below the precision of a
+        // compact target, so the target is kept
+        let slightly_different_hashrate = initial_hashrate + 65536.0;
+        channel
+            .update_channel(slightly_different_hashrate, None)
+            .unwrap();
+        assert_eq!(channel.get_target(), initial_target);
+        assert_eq!(channel.get_nominal_hashrate(), slightly_different_hashrate);
+
+        // a 1% change is beyond the tolerance
+        channel
+            .update_channel(initial_hashrate * 1.01, None)
+            .unwrap();
+        assert!(channel.get_target() < initial_target);
+    }
+
+    #[test]
+    fn test_update_channel_large_farm_hashrate() {
+        let extranonce_prefix = [
+            83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        ]
+        .to_vec();
+        // 1 EH/s, where consecutive f32 values are ~1.4e11 H/s apart
+        let initial_hashrate = 1e18;
+
+        // steps well below the f32 resolution still yield distinct targets
+        let targets: Vec<Target> = (0..10)
+            .map(|step| {
+                hash_rate_to_target(initial_hashrate + step as f64 * 1e6, 1.0)
+                    .ok()
+                    .unwrap()
+                    .into()
+            })
+            .collect();
+        assert!(targets.windows(2).all(|pair| pair[1] < pair[0]));
+
+        let mut channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(1)
+                .user_identity("user_identity")
+                .extranonce_prefix(extranonce_prefix)
+                .requested_max_target(Target::MAX)
+                .nominal_hashrate(initial_hashrate)
+                .expected_share_per_minute(1.0),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+
+        let mut previous_target = channel.get_target().clone();
+        for step in 1..10 {
+            // 0.01% steps, beyond the precision of a compact target
+            let hashrate = initial_hashrate * (1.0 + step as f64 * 1e-4);
+            channel.update_channel(hashrate, None).unwrap();
+            assert_eq!(channel.get_nominal_hashrate(), hashrate);
+            assert!(channel.get_target() < previous_target);
+            previous_target = channel.get_target().clone();
+        }
+    }
+
     #[test]
     fn test_update_extranonce_prefix() {
         let channel_id = 1;
@@ -1149,20 +3323,21 @@ mod tests {
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
         ]
         .to_vec();
-        let max_target = [0xff; 32].into();
+        let max_target = Target::MAX;
         let expected_share_per_minute = 1.0;
         let nominal_hashrate = 1_000.0;
         let share_batch_size = 100;
         let job_store = Box::new(DefaultJobStore::<StandardJob>::new());
 
-        let mut channel = StandardChannel::new(
-            channel_id,
-            user_identity,
-            extranonce_prefix.clone(),
-            max_target,
-            nominal_hashrate,
-            share_batch_size,
-            expected_share_per_minute,
+        let mut channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(channel_id)
+                .user_identity(user_identity)
+                .extranonce_prefix(extranonce_prefix.clone())
+                .requested_max_target(max_target)
+                .nominal_hashrate(nominal_hashrate)
+                .share_batch_size(share_batch_size)
+                .expected_share_per_minute(expected_share_per_minute),
             job_store,
         )
         .unwrap();
@@ -1191,4 +3366,2540 @@ mod tests {
             .set_extranonce_prefix(new_extranonce_prefix_too_long)
             .is_err());
     }
+
+    #[test]
+    fn test_resume_channel() {
+        let extranonce_prefix = [
+            83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        ]
+        .to_vec();
+        let max_target = Target::MAX;
+        let share_batch_size = 100;
+        let expected_share_per_minute = 1.0;
+
+        let mut channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(1)
+                .user_identity("user_identity".to_string())
+                .extranonce_prefix(extranonce_prefix.clone())
+                .requested_max_target(max_target.clone())
+                .nominal_hashrate(10.0)
+                .share_batch_size(share_batch_size)
+                .expected_share_per_minute(expected_share_per_minute),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        // vardiff moved the channel away from its initial target
+        channel.update_channel(1_000.0, None).unwrap();
+        let last_target = channel.get_target().clone();
+
+        let hint = channel.resume_hint();
+        assert_eq!(
+            hint,
+            ChannelResumeHint {
+                user_identity: "user_identity".to_string(),
+                last_target: WireU256::from(&last_target),
+                last_nominal_hashrate: 1_000.0,
+                extranonce_prefix: extranonce_prefix.clone(),
+            }
+        );
+        drop(channel);
+
+        // the connection drops, the channel is reopened with a new id
+        let resumed = StandardChannel::new_with_resume_hint(
+            2,
+            hint.clone(),
+            max_target,
+            share_batch_size,
+            expected_share_per_minute,
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        assert_eq!(resumed.get_channel_id(), 2);
+        assert_eq!(resumed.get_user_identity(), "user_identity");
+        assert_eq!(resumed.get_extranonce_prefix(), &extranonce_prefix);
+        assert_eq!(resumed.get_target(), last_target);
+        assert_eq!(resumed.get_nominal_hashrate(), 1_000.0);
+
+        // a stricter max target caps the resumed target
+        let mut stricter_max_target = [0xff; 32];
+        stricter_max_target[31] = 0;
+        stricter_max_target[30] = 0;
+        let stricter_max_target: Target = stricter_max_target.into();
+        assert!(last_target > stricter_max_target);
+        let resumed = StandardChannel::new_with_resume_hint(
+            3,
+            hint,
+            stricter_max_target.clone(),
+            share_batch_size,
+            expected_share_per_minute,
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        assert_eq!(resumed.get_target(), stricter_max_target);
+    }
+
+    #[test]
+    fn test_resume_hint_is_validated() {
+        let hint = ChannelResumeHint {
+            user_identity: "user_identity".to_string(),
+            last_target: WireU256::from(&Target::MAX),
+            last_nominal_hashrate: 1_000.0,
+            extranonce_prefix: vec![0; 8],
+        };
+        let resume = |hint: ChannelResumeHint, share_batch_size: usize, share_per_minute: f32| {
+            StandardChannel::new_with_resume_hint(
+                1,
+                hint,
+                Target::MAX,
+                share_batch_size,
+                share_per_minute,
+                Box::new(DefaultJobStore::<StandardJob>::new()),
+            )
+        };
+
+        assert!(resume(hint.clone(), 1, 1.0).is_ok());
+        for last_nominal_hashrate in [f32::NAN, f32::INFINITY, -1.0] {
+            let hint = ChannelResumeHint {
+                last_nominal_hashrate,
+                ..hint.clone()
+            };
+            assert!(matches!(
+                resume(hint, 1, 1.0),
+                Err(StandardChannelError::InvalidNominalHashrate)
+            ));
+        }
+        for share_per_minute in [0.0, -1.0, f32::NAN] {
+            assert!(matches!(
+                resume(hint.clone(), 1, share_per_minute),
+                Err(StandardChannelError::InvalidExpectedSharePerMinute)
+            ));
+        }
+        assert!(matches!(
+            resume(hint.clone(), 0, 1.0),
+            Err(StandardChannelError::InvalidShareBatchSize)
+        ));
+        let too_long_prefix = ChannelResumeHint {
+            extranonce_prefix: vec![0; 33],
+            ..hint.clone()
+        };
+        assert!(matches!(
+            resume(too_long_prefix, 1, 1.0),
+            Err(StandardChannelError::NewExtranoncePrefixTooLarge)
+        ));
+        let invalid_identity = ChannelResumeHint {
+            user_identity: String::new(),
+            ..hint
+        };
+        assert!(matches!(
+            resume(invalid_identity, 1, 1.0),
+            Err(StandardChannelError::InvalidUserIdentity(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(not(feature = "debug-full"))]
+    fn test_debug_redacts_user_identity() {
+        let user_identity = "alice.worker1";
+        let extranonce_prefix = vec![
+            83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        ];
+        let mut channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(1)
+                .user_identity(user_identity.to_string())
+                .extranonce_prefix(extranonce_prefix.clone())
+                .requested_max_target(Target::MAX)
+                .nominal_hashrate(10.0)
+                .share_batch_size(100)
+                .expected_share_per_minute(1.0),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        let assert_redacted = |channel: &StandardChannel| {
+            for debug in [
+                format!("{:?}", channel),
+                format!("{:?}", channel.export_state()),
+                format!("{:?}", channel.resume_hint()),
+            ] {
+                assert!(!debug.contains(user_identity));
+                assert!(!debug.contains(&format!("{:?}", extranonce_prefix)));
+                assert!(!debug.contains("83, 116, 114"));
+                assert!(debug.contains("extranonce_prefix: [83, 116, ..; 32]"));
+            }
+        };
+        assert_redacted(&channel);
+
+        // the jobs carry the extranonce prefix too: an active, a future and a past job
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        channel
+            .on_new_template(
+                NewTemplate {
+                    template_id: 2,
+                    ..fixture::template(false)
+                },
+                fixture::coinbase_reward_outputs(),
+            )
+            .unwrap();
+        channel
+            .on_new_template(
+                NewTemplate {
+                    template_id: 3,
+                    ..fixture::template(true)
+                },
+                fixture::coinbase_reward_outputs(),
+            )
+            .unwrap();
+        assert!(channel.get_active_job().is_some());
+        assert_eq!(channel.get_future_jobs().len(), 1);
+        assert_eq!(channel.get_past_jobs().len(), 1);
+        let state = channel.export_state();
+        assert!(state.active_job.is_some());
+        assert_eq!(state.future_jobs.len(), 1);
+        assert_eq!(state.past_jobs.len(), 1);
+        assert_redacted(&channel);
+    }
+
+    #[test]
+    fn test_max_target_policy() {
+        let extranonce_prefix = [
+            83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        ]
+        .to_vec();
+        // 00000000ffff0000000000000000000000000000000000000000000000000000
+        let requested_max_target: Target = [
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff,
+            0xff, 0, 0, 0, 0,
+        ]
+        .into();
+        // at 1 MH/s and 10 shares per minute, the target is above the requested max target
+        let nominal_hashrate = 1e6;
+        let expected_share_per_minute = 10.0;
+        let new_channel = |max_target_policy| {
+            StandardChannel::from_config(
+                StandardChannelConfig::default()
+                    .channel_id(1)
+                    .user_identity("user_identity".to_string())
+                    .extranonce_prefix(extranonce_prefix.clone())
+                    .requested_max_target(requested_max_target.clone())
+                    .nominal_hashrate(nominal_hashrate)
+                    .share_batch_size(100)
+                    .expected_share_per_minute(expected_share_per_minute)
+                    .max_target_policy(max_target_policy),
+                Box::new(DefaultJobStore::<StandardJob>::new()),
+            )
+        };
+
+        assert!(matches!(
+            new_channel(MaxTargetPolicy::Reject),
+            Err(StandardChannelError::RequestedMaxTargetOutOfRange)
+        ));
+
+        let mut channel = new_channel(MaxTargetPolicy::ClampToRequestedMax).unwrap();
+        assert_eq!(channel.get_target(), requested_max_target);
+        assert_eq!(
+            channel.get_max_target_policy(),
+            MaxTargetPolicy::ClampToRequestedMax
+        );
+        // the max target has difficulty 1, so 1 MH/s yields 1e6 * 60 / 2^32 shares per minute
+        let shares_per_minute = channel.get_shares_per_minute();
+        assert!((shares_per_minute - 0.01397).abs() < 0.00001);
+
+        // the target derived from the nominal hashrate and the adjusted share rate is the clamped
+        // one, up to the precision of a compact target
+        let target: Target = hash_rate_to_target(nominal_hashrate, shares_per_minute.into())
+            .ok()
+            .unwrap()
+            .into();
+        assert!(target.approx_eq(&requested_max_target, compact_tolerance_bits(&target)));
+
+        // updating the channel with a lower hashrate clamps the target again instead of failing
+        channel
+            .update_channel(nominal_hashrate / 2.0, None)
+            .unwrap();
+        assert_eq!(channel.get_target(), requested_max_target);
+        assert!((channel.get_shares_per_minute() - shares_per_minute / 2.0).abs() < 0.00001);
+
+        // the share rate is scaled down from the configured one, not from the previous one
+        channel
+            .update_channel(nominal_hashrate / 2.0, None)
+            .unwrap();
+        assert!((channel.get_shares_per_minute() - shares_per_minute / 2.0).abs() < 0.00001);
+
+        // once the target is below the requested max target, the configured share rate is back,
+        // along with the target when it's deferred
+        channel.set_defer_target_changes(true);
+        channel.update_channel(1e12, None).unwrap();
+        assert!(channel.pending_target().is_some());
+        assert!((channel.get_shares_per_minute() - shares_per_minute / 2.0).abs() < 0.00001);
+        channel.set_defer_target_changes(false);
+        assert!(channel.get_target() < requested_max_target);
+        assert_eq!(channel.get_shares_per_minute(), expected_share_per_minute);
+        let state = channel.export_state();
+        assert_eq!(
+            state.configured_share_per_minute,
+            Some(expected_share_per_minute)
+        );
+    }
+
+    #[test]
+    fn test_pause_resume() {
+        let mut standard_channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(1)
+                .user_identity("user_identity".to_string())
+                .extranonce_prefix(
+                    [
+                        83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111,
+                        111, 108, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+                    ]
+                    .to_vec(),
+                )
+                .requested_max_target(Target::MAX)
+                .nominal_hashrate(1_000.0)
+                .expected_share_per_minute(1.0),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+
+        let template = |template_id| NewTemplate {
+            template_id,
+            future_template: false,
+            version: 536870912,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: 4294967294,
+            coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
+            coinbase_tx_outputs_count: 1,
+            coinbase_tx_outputs: vec![
+                0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
+                222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
+                139, 235, 216, 54, 151, 78, 140, 249,
+            ]
+            .try_into()
+            .unwrap(),
+            coinbase_tx_locktime: 158,
+            merkle_path: vec![].try_into().unwrap(),
+        };
+        let coinbase_reward_outputs = vec![TxOut {
+            value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
+            script_pubkey: ScriptBuf::from(vec![
+                0, 20, 235, 225, 183, 220, 194, 147, 204, 170, 14, 231, 67, 168, 111, 137, 223,
+                130, 88, 194, 8, 252,
+            ]),
+        }];
+        let prev_hash =
+            hex_to_u256("9a7cefe7dd7aa0ada4af57214ad6bf6b492200a2e3102c282149000000000000")
+                .unwrap();
+        standard_channel.set_chain_tip(ChainTip::new(prev_hash, 453040064, 1745596910).unwrap());
+        standard_channel
+            .on_new_template(template(1), coinbase_reward_outputs.clone())
+            .unwrap();
+
+        // same share as in `test_share_validation_valid_share`
+        let valid_share = SubmitSharesStandard {
+            channel_id: 1,
+            sequence_number: 1,
+            job_id: 1,
+            nonce: 31978,
+            ntime: 1745611105,
+            version: 536870912,
+        };
+
+        assert!(!standard_channel.is_paused());
+        standard_channel.pause("payment issue".to_string());
+        assert!(standard_channel.is_paused());
+        assert_eq!(standard_channel.get_pause_reason(), Some("payment issue"));
+
+        let stats = standard_channel.get_share_accounting().stats();
+        assert_eq!(
+            standard_channel
+                .validate_share(valid_share.clone())
+                .unwrap_err(),
+            ShareValidationError::ChannelPaused("payment issue".to_string())
+        );
+        assert_eq!(standard_channel.get_share_accounting().stats(), stats);
+
+        // jobs are still kept up to date while paused
+        standard_channel
+            .on_new_template(template(2), coinbase_reward_outputs)
+            .unwrap();
+        assert_eq!(standard_channel.get_active_job().unwrap().get_job_id(), 2);
+
+        // the pause survives a move to another server
+        let state = standard_channel.export_state();
+        assert_eq!(state.paused, Some("payment issue".to_string()));
+        let imported =
+            StandardChannel::import_state(state, Box::new(DefaultJobStore::<StandardJob>::new()))
+                .unwrap();
+        assert!(imported.is_paused());
+
+        standard_channel.resume();
+        assert!(!standard_channel.is_paused());
+        assert!(matches!(
+            standard_channel.validate_share(valid_share),
+            Ok(ShareValidationResult::Valid)
+        ));
+        assert_eq!(
+            standard_channel
+                .get_share_accounting()
+                .get_shares_accepted(),
+            1
+        );
+        assert_eq!(
+            standard_channel
+                .get_share_accounting()
+                .get_last_share_sequence_number(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_set_share_batch_size() {
+        use bitcoin::hashes::{sha256d::Hash, Hash as _};
+
+        let mut channel = StandardChannel::from_config(
+            fixture::standard_channel_config()
+                .extranonce_prefix(vec![0; 32])
+                .share_batch_size(10),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+
+        // accepts a share of work 1 and returns the count and work it acknowledges, if any
+        let mut sequence_number = 0;
+        let mut accept_share = |channel: &mut StandardChannel| {
+            sequence_number += 1;
+            let share_accounting = &mut channel.share_accounting;
+            share_accounting.update_share_accounting(
+                1,
+                sequence_number,
+                Hash::hash(&sequence_number.to_le_bytes()),
+            );
+            match share_accounting.validation_result() {
+                ShareValidationResult::ValidWithAcknowledgement(_, count, work) => {
+                    assert!(share_accounting.should_acknowledge());
+                    Some((count, work))
+                }
+                ShareValidationResult::Valid => {
+                    assert!(!share_accounting.should_acknowledge());
+                    None
+                }
+                result => panic!("unexpected validation result: {:?}", result),
+            }
+        };
+
+        assert!(matches!(
+            channel.set_share_batch_size(0),
+            Err(StandardChannelError::InvalidShareBatchSize)
+        ));
+        assert_eq!(channel.get_share_accounting().get_share_batch_size(), 10);
+
+        for _ in 0..5 {
+            assert_eq!(accept_share(&mut channel), None);
+        }
+
+        // shrinking below the 5 shares already accepted acknowledges all of them with the next
+        // one
+        channel.set_share_batch_size(3).unwrap();
+        assert!(!channel.get_share_accounting().should_acknowledge());
+        assert_eq!(accept_share(&mut channel), Some((6, 6)));
+        assert_eq!(accept_share(&mut channel), None);
+        assert_eq!(accept_share(&mut channel), None);
+        assert_eq!(accept_share(&mut channel), Some((3, 3)));
+
+        // growing keeps the shares accepted since the last acknowledgement
+        assert_eq!(accept_share(&mut channel), None);
+        channel.set_share_batch_size(4).unwrap();
+        assert_eq!(accept_share(&mut channel), None);
+        assert_eq!(accept_share(&mut channel), None);
+        assert_eq!(accept_share(&mut channel), Some((4, 4)));
+
+        // changing the batch size right after an acknowledgement starts a new batch
+        channel.set_share_batch_size(2).unwrap();
+        assert_eq!(accept_share(&mut channel), None);
+        assert_eq!(accept_share(&mut channel), Some((2, 2)));
+
+        // a snapshot taken between a shrink and the next share keeps the pending shares
+        assert_eq!(accept_share(&mut channel), None);
+        assert_eq!(accept_share(&mut channel), Some((2, 2)));
+        channel.set_share_batch_size(10).unwrap();
+        for _ in 0..4 {
+            assert_eq!(accept_share(&mut channel), None);
+        }
+        channel.set_share_batch_size(2).unwrap();
+        channel.share_accounting =
+            ShareAccounting::from_state(channel.get_share_accounting().to_state());
+        assert_eq!(accept_share(&mut channel), Some((5, 5)));
+        assert_eq!(channel.get_share_accounting().get_shares_accepted(), 22);
+    }
+
+    #[test]
+    fn test_acknowledged_share_work() {
+        use bitcoin::hashes::{sha256d::Hash, Hash as _};
+
+        let mut channel = StandardChannel::from_config(
+            fixture::standard_channel_config()
+                .extranonce_prefix(vec![0; 32])
+                .share_batch_size(3),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+
+        // three acknowledgement cycles, with a different work for every share
+        let mut acknowledgements = vec![];
+        for sequence_number in 1..=9u32 {
+            let share_accounting = &mut channel.share_accounting;
+            share_accounting.update_share_accounting(
+                sequence_number as u64,
+                sequence_number,
+                Hash::hash(&sequence_number.to_le_bytes()),
+            );
+            if share_accounting.should_acknowledge() {
+                acknowledgements.push(share_accounting.get_share_work_since_acknowledgement());
+            }
+        }
+
+        // each acknowledgement only carries the work of its own batch
+        assert_eq!(acknowledgements, vec![1 + 2 + 3, 4 + 5 + 6, 7 + 8 + 9]);
+        let share_accounting = channel.get_share_accounting();
+        assert_eq!(share_accounting.get_share_work_sum(), 45);
+        assert_eq!(
+            acknowledgements.iter().sum::<u64>(),
+            share_accounting.get_share_work_sum()
+        );
+
+        // a snapshot keeps the work of the current batch
+        let mut share_accounting = ShareAccounting::from_state(share_accounting.to_state());
+        share_accounting.update_share_accounting(10, 10, Hash::hash(&10u32.to_le_bytes()));
+        assert_eq!(share_accounting.get_share_work_since_acknowledgement(), 10);
+        assert_eq!(share_accounting.get_share_work_sum(), 55);
+    }
+
+    #[test]
+    fn test_acknowledgement_ignores_sequence_numbers() {
+        use bitcoin::hashes::{sha256d::Hash, Hash as _};
+
+        let adversarial_sequence_numbers: [(&str, Vec<u32>); 4] = [
+            ("constant", vec![0; 10]),
+            ("decreasing", (0..10).map(|i| u32::MAX - i).collect()),
+            ("huge gaps", (0..10).map(|i| i * 400_000_000).collect()),
+            ("multiples of the batch size", vec![3; 10]),
+        ];
+        for (name, sequence_numbers) in adversarial_sequence_numbers {
+            let mut share_accounting = ShareAccounting::new(3);
+            let mut acknowledgements = vec![];
+            for (i, sequence_number) in sequence_numbers.into_iter().enumerate() {
+                share_accounting.update_share_accounting(
+                    1,
+                    sequence_number,
+                    Hash::hash(&(i as u32).to_le_bytes()),
+                );
+                match share_accounting.validation_result() {
+                    ShareValidationResult::ValidWithAcknowledgement(
+                        last_sequence_number,
+                        new_submits_accepted_count,
+                        new_shares_sum,
+                    ) => {
+                        // the client's sequence number is reported as is, next to the count
+                        assert_eq!(last_sequence_number, sequence_number, "{}", name);
+                        assert_eq!(new_submits_accepted_count, 3, "{}", name);
+                        assert_eq!(new_shares_sum, 3, "{}", name);
+                        acknowledgements.push(i + 1);
+                    }
+                    ShareValidationResult::Valid => {}
+                    result => panic!("{}: unexpected {:?}", name, result),
+                }
+            }
+            // every 3 accepted shares, whatever their sequence numbers
+            assert_eq!(acknowledgements, vec![3, 6, 9], "{}", name);
+            assert_eq!(share_accounting.get_shares_accepted(), 10, "{}", name);
+            assert_eq!(share_accounting.get_shares_since_acknowledgement(), 1);
+        }
+    }
+
+    #[test]
+    fn test_stale_retention() {
+        // activates three chain tips in a row, with one job each, then submits a share for the job
+        // of each tip
+        let share_errors = |stale_retention| {
+            let mut channel = StandardChannel::from_config(
+                fixture::standard_channel_config()
+                    .extranonce_prefix(vec![0; 32])
+                    .share_batch_size(100),
+                Box::new(DefaultJobStore::<StandardJob>::with_stale_retention(
+                    stale_retention,
+                )),
+            )
+            .unwrap();
+            let coinbase_reward_outputs = vec![TxOut {
+                value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
+                script_pubkey: ScriptBuf::from(vec![
+                    0, 20, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
+                ]),
+            }];
+            for tip in 1..=3u8 {
+                let template = NewTemplate {
+                    template_id: tip as u64,
+                    future_template: true,
+                    version: 536870912,
+                    coinbase_tx_version: 2,
+                    coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
+                    coinbase_tx_input_sequence: 4294967294,
+                    coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
+                    coinbase_tx_outputs_count: 0,
+                    coinbase_tx_outputs: vec![].try_into().unwrap(),
+                    coinbase_tx_locktime: 158,
+                    merkle_path: vec![].try_into().unwrap(),
+                };
+                channel
+                    .on_new_template(template, coinbase_reward_outputs.clone())
+                    .unwrap();
+                channel
+                    .on_set_new_prev_hash(SetNewPrevHashTdp {
+                        template_id: tip as u64,
+                        prev_hash: [tip; 32].into(),
+                        header_timestamp: 1747092633,
+                        n_bits: 503543726,
+                        target: [0xff; 32].into(),
+                    })
+                    .unwrap();
+            }
+            assert_eq!(channel.get_active_job().unwrap().get_job_id(), 3);
+
+            // job 2 is from one tip ago, job 1 from two tips ago
+            [2, 1].map(|job_id| {
+                channel
+                    .validate_share(SubmitSharesStandard {
+                        channel_id: 1,
+                        sequence_number: job_id,
+                        job_id,
+                        nonce: 0,
+                        ntime: 1747092633,
+                        version: 536870912,
+                    })
+                    .unwrap_err()
+            })
+        };
+
+        assert_eq!(
+            share_errors(StaleRetention::default()),
+            [
+                ShareValidationError::Stale,
+                ShareValidationError::InvalidJobId
+            ]
+        );
+        assert_eq!(
+            share_errors(StaleRetention::KeepAsStale(2)),
+            [ShareValidationError::Stale, ShareValidationError::Stale]
+        );
+        assert_eq!(
+            share_errors(StaleRetention::Drop),
+            [
+                ShareValidationError::InvalidJobId,
+                ShareValidationError::InvalidJobId
+            ]
+        );
+    }
+
+    #[test]
+    fn test_future_job_replaced() {
+        let mut channel = StandardChannel::from_config(
+            fixture::standard_channel_config().template_replay_policy(TemplateReplayPolicy::NewJob),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+
+        // the template is sent twice, then once more with other reward outputs
+        let mut other_outputs = fixture::coinbase_reward_outputs();
+        other_outputs[0].script_pubkey = ScriptBuf::from(vec![0x51]);
+        for outputs in [
+            fixture::coinbase_reward_outputs(),
+            fixture::coinbase_reward_outputs(),
+            other_outputs,
+        ] {
+            channel
+                .on_new_template(fixture::template(true), outputs)
+                .unwrap();
+        }
+        // only the last job is kept
+        let future_job_ids: Vec<u32> = channel.get_future_jobs().keys().copied().collect();
+        assert_eq!(future_job_ids, vec![3]);
+        assert_eq!(
+            channel.job_store.get_future_template_to_job_id().get(&1),
+            Some(&3)
+        );
+
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        assert_eq!(channel.get_active_job().unwrap().get_job_id(), 3);
+        assert!(channel.get_future_jobs().is_empty());
+        assert!(channel.get_past_jobs().is_empty());
+        assert!(channel.get_stale_jobs().is_empty());
+
+        // shares for the superseded jobs are rejected
+        assert!(matches!(
+            channel.validate_share(fixture::submit_shares_standard(1, 1, 0)),
+            Err(ShareValidationError::InvalidJobId)
+        ));
+    }
+
+    #[test]
+    fn test_job_share_counts() {
+        let mut channel = StandardChannel::from_config(
+            fixture::standard_channel_config().share_batch_size(100),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        // activates a new chain tip with a job for `template_id`
+        let new_chain_tip = |channel: &mut StandardChannel, template_id: u64| {
+            let template = NewTemplate {
+                template_id,
+                ..fixture::template(true)
+            };
+            channel
+                .on_new_template(template, fixture::coinbase_reward_outputs())
+                .unwrap();
+            channel
+                .on_set_new_prev_hash(SetNewPrevHashTdp {
+                    prev_hash: [template_id as u8; 32].into(),
+                    ..fixture::set_new_prev_hash(template_id)
+                })
+                .unwrap();
+        };
+        // grinds nonces until the share meets the channel target, rejected shares aren't counted
+        let mut nonce = 0;
+        let mut submit_share = |channel: &mut StandardChannel, job_id: u32| loop {
+            nonce += 1;
+            match channel.validate_share(SubmitSharesStandard {
+                channel_id: 1,
+                sequence_number: nonce,
+                job_id,
+                nonce,
+                ntime: fixture::NTIME,
+                version: 536870912,
+            }) {
+                Err(ShareValidationError::DoesNotMeetTarget) => continue,
+                result => break result,
+            }
+        };
+        let counts = |accepted, stale| JobShareCounts { accepted, stale };
+
+        // job 1 is past, job 2 is active
+        new_chain_tip(&mut channel, 1);
+        channel
+            .on_new_template(
+                NewTemplate {
+                    template_id: 2,
+                    ..fixture::template(false)
+                },
+                fixture::coinbase_reward_outputs(),
+            )
+            .unwrap();
+        for job_id in [1, 2, 2, 1, 2] {
+            submit_share(&mut channel, job_id).unwrap();
+        }
+        assert!(submit_share(&mut channel, 42).is_err());
+        assert_eq!(
+            channel.get_job_share_counts(),
+            &HashMap::from([(1, counts(2, 0)), (2, counts(3, 0))])
+        );
+
+        // the firmware keeps mining job 1 after the chain tip moved on
+        new_chain_tip(&mut channel, 3);
+        for job_id in [1, 1, 3] {
+            submit_share(&mut channel, job_id).ok();
+        }
+        assert_eq!(
+            channel.get_job_share_counts(),
+            &HashMap::from([(1, counts(2, 2)), (2, counts(3, 0)), (3, counts(1, 0))])
+        );
+
+        // jobs 1 and 2 are dropped from the job store, along with their counts
+        new_chain_tip(&mut channel, 4);
+        assert!(channel.get_stale_jobs().contains_key(&3));
+        assert_eq!(
+            channel.get_job_share_counts(),
+            &HashMap::from([(3, counts(1, 0))])
+        );
+    }
+
+    #[test]
+    fn test_stale_block_candidate() {
+        // regtest difficulty, so that about half of the hashes are blocks
+        let n_bits = 0x207fffff;
+        let chain_tip_a = ChainTip::new([0xaa; 32].into(), n_bits, 1747092633).unwrap();
+
+        // tip A is activated with job 1, then tip B with job 2, as in a block race
+        let race = |retain_previous_chain_tip| {
+            let mut channel = StandardChannel::from_config(
+                fixture::standard_channel_config()
+                    .extranonce_prefix(vec![0; 32])
+                    .retain_previous_chain_tip(retain_previous_chain_tip),
+                Box::new(DefaultJobStore::<StandardJob>::with_stale_retention(
+                    StaleRetention::KeepAsStale(2),
+                )),
+            )
+            .unwrap();
+            for (template_id, prev_hash) in [(1, 0xaa), (2, 0xbb)] {
+                let template = NewTemplate {
+                    template_id,
+                    future_template: true,
+                    version: 536870912,
+                    coinbase_tx_version: 2,
+                    coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
+                    coinbase_tx_input_sequence: 4294967294,
+                    coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
+                    coinbase_tx_outputs_count: 0,
+                    coinbase_tx_outputs: vec![].try_into().unwrap(),
+                    coinbase_tx_locktime: 158,
+                    merkle_path: vec![].try_into().unwrap(),
+                };
+                let coinbase_reward_outputs = vec![TxOut {
+                    value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
+                    script_pubkey: ScriptBuf::from(vec![
+                        0, 20, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19,
+                        20,
+                    ]),
+                }];
+                channel
+                    .on_new_template(template, coinbase_reward_outputs)
+                    .unwrap();
+                channel
+                    .on_set_new_prev_hash(SetNewPrevHashTdp {
+                        template_id,
+                        prev_hash: [prev_hash; 32].into(),
+                        header_timestamp: 1747092633,
+                        n_bits,
+                        target: [0xff; 32].into(),
+                    })
+                    .unwrap();
+            }
+            channel
+        };
+
+        let mut channel = race(true);
+        assert_eq!(
+            channel.get_previous_chain_tip().unwrap().prev_block_hash(),
+            chain_tip_a.prev_block_hash()
+        );
+
+        // a nonce that makes the job 1 header a block on tip A, and one that doesn't
+        let job = channel.get_stale_jobs().get(&1).unwrap();
+        assert_eq!(
+            job.header_template(channel.get_chain_tip().unwrap()),
+            Err(JobError::ChainTipMismatch)
+        );
+        let mut header = job.header_template(&chain_tip_a).unwrap();
+        let mut nonce_meeting = |meets_target: bool| {
+            (0..)
+                .find(|nonce| {
+                    header.nonce = *nonce;
+                    header.target().is_met_by(header.block_hash()) == meets_target
+                })
+                .unwrap()
+        };
+        let block_nonce = nonce_meeting(true);
+        let share_nonce = nonce_meeting(false);
+        header.nonce = block_nonce;
+        let share = |nonce| SubmitSharesStandard {
+            channel_id: 1,
+            sequence_number: nonce,
+            job_id: 1,
+            nonce,
+            ntime: 1747092633,
+            version: 536870912,
+        };
+
+        match channel.validate_share(share(block_nonce)) {
+            Ok(ShareValidationResult::StaleBlockCandidate(template_id, solution)) => {
+                assert_eq!(template_id, Some(1));
+                assert_eq!(solution.header, header);
+                assert!(!solution.coinbase.is_empty());
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(
+            channel.validate_share(share(block_nonce)).unwrap_err(),
+            ShareValidationError::DuplicateShare
+        );
+        assert_eq!(
+            channel.validate_share(share(share_nonce)).unwrap_err(),
+            ShareValidationError::Stale
+        );
+
+        // off by default, and only the jobs of the previous chain tip are candidates
+        assert_eq!(
+            race(false).validate_share(share(block_nonce)).unwrap_err(),
+            ShareValidationError::Stale
+        );
+        channel.set_retain_previous_chain_tip(false);
+        assert!(channel.get_previous_chain_tip().is_none());
+        assert_eq!(
+            channel.validate_share(share(block_nonce)).unwrap_err(),
+            ShareValidationError::Stale
+        );
+    }
+
+    #[test]
+    fn test_share_accounting_config() {
+        use crate::server::share_accounting::{ShareAccountingConfig, DEFAULT_SHARE_BATCH_SIZE};
+        use bitcoin::hashes::{sha256d::Hash, Hash as _};
+
+        let new_channel = |share_accounting_config: ShareAccountingConfig| {
+            StandardChannel::from_config(
+                fixture::standard_channel_config()
+                    .extranonce_prefix(vec![0; 32])
+                    .share_accounting_config(share_accounting_config),
+                Box::new(DefaultJobStore::<StandardJob>::new()),
+            )
+            .unwrap()
+        };
+        #[allow(deprecated)]
+        let legacy_channel = StandardChannel::new(
+            1,
+            "user_identity".to_string(),
+            vec![0; 32],
+            Target::MAX,
+            1.0,
+            100,
+            1.0,
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        let mut channels = [
+            new_channel(ShareAccountingConfig::default()),
+            new_channel(ShareAccountingConfig::default().with_share_batch_size(100)),
+            legacy_channel,
+        ];
+        assert_eq!(DEFAULT_SHARE_BATCH_SIZE, 100);
+
+        for channel in channels.iter_mut() {
+            for sequence_number in 0..150u32 {
+                let share_accounting = &mut channel.share_accounting;
+                share_accounting.update_share_accounting(
+                    2,
+                    sequence_number,
+                    Hash::hash(&sequence_number.to_le_bytes()),
+                );
+                assert_eq!(share_accounting.should_acknowledge(), sequence_number == 99);
+            }
+            channel.share_accounting.update_best_diff(3.0);
+        }
+
+        let stats = channels[0].get_share_accounting().stats();
+        assert_eq!(stats.last_share_sequence_number, 149);
+        assert_eq!(stats.shares_accepted, 150);
+        assert_eq!(stats.shares_since_acknowledgement, 50);
+        assert_eq!(stats.share_work_sum, 300);
+        assert_eq!(stats.share_work_since_acknowledgement, 100);
+        assert_eq!(stats.share_batch_size, 100);
+        assert_eq!(stats.seen_shares, 150);
+        assert_eq!(stats.best_diff, 3.0);
+        for channel in &channels[1..] {
+            assert_eq!(channel.get_share_accounting().stats(), stats);
+        }
+
+        assert_eq!(
+            new_channel(ShareAccountingConfig::default().with_share_batch_size(10))
+                .get_share_accounting()
+                .get_share_batch_size(),
+            10
+        );
+    }
+
+    #[test]
+    fn test_from_config_validation() {
+        let config = StandardChannelConfig::default()
+            .channel_id(1)
+            .user_identity("user_identity".to_string())
+            .extranonce_prefix(vec![0; 32])
+            .requested_max_target(Target::MAX)
+            .nominal_hashrate(1.0)
+            .expected_share_per_minute(1.0);
+        let from_config = |config| {
+            StandardChannel::from_config(config, Box::new(DefaultJobStore::<StandardJob>::new()))
+        };
+
+        let channel = from_config(config.clone()).unwrap();
+        assert_eq!(channel.get_max_target_policy(), MaxTargetPolicy::Reject);
+        assert_eq!(channel.get_share_accounting().get_share_batch_size(), 100);
+
+        assert!(matches!(
+            from_config(StandardChannelConfig::default()),
+            Err(StandardChannelError::MissingConfigField("channel_id"))
+        ));
+        assert!(matches!(
+            from_config(StandardChannelConfig::default().channel_id(1)),
+            Err(StandardChannelError::MissingConfigField("user_identity"))
+        ));
+        // a standard channel needs no rollable extranonce, so its prefix can take all of it
+        assert!(from_config(config.clone().extranonce_prefix(vec![0; 32])).is_ok());
+        assert!(matches!(
+            from_config(config.clone().extranonce_prefix(vec![0; 33])),
+            Err(StandardChannelError::NewExtranoncePrefixTooLarge)
+        ));
+        assert!(matches!(
+            from_config(config.clone().nominal_hashrate(f32::NAN)),
+            Err(StandardChannelError::InvalidNominalHashrate)
+        ));
+        assert!(matches!(
+            from_config(config.clone().expected_share_per_minute(0.0)),
+            Err(StandardChannelError::InvalidExpectedSharePerMinute)
+        ));
+        assert!(matches!(
+            from_config(config.share_batch_size(0)),
+            Err(StandardChannelError::InvalidShareBatchSize)
+        ));
+    }
+
+    #[test]
+    fn test_user_identity() {
+        let new_channel = |user_identity: &str| {
+            #[allow(deprecated)]
+            StandardChannel::new(
+                1,
+                user_identity,
+                vec![0; 32],
+                Target::MAX,
+                1.0,
+                100,
+                1.0,
+                Box::new(DefaultJobStore::<StandardJob>::new()),
+            )
+        };
+
+        // plain strings are still accepted, and parsed
+        let channel = new_channel("alice.worker1").unwrap();
+        assert_eq!(channel.get_user_identity(), "alice.worker1");
+        assert_eq!(channel.get_user_identity().account(), "alice");
+        assert_eq!(channel.get_user_identity().worker(), Some("worker1"));
+        let user_identity = channel.get_user_identity().clone();
+        #[allow(deprecated)]
+        let channel = StandardChannel::new(
+            1,
+            user_identity.clone(),
+            vec![0; 32],
+            Target::MAX,
+            1.0,
+            100,
+            1.0,
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        assert_eq!(channel.get_user_identity(), &user_identity);
+
+        let error = match new_channel("alice worker1") {
+            Err(StandardChannelError::InvalidUserIdentity(error)) => error,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        assert_eq!(error, UserIdentityError::InvalidCharacter(' '));
+        assert_eq!(error.error_code(), "unknown-user");
+
+        // custom rules are applied by from_config
+        let config = StandardChannelConfig::default()
+            .channel_id(1)
+            .user_identity("alice.worker1")
+            .extranonce_prefix(vec![0; 32])
+            .requested_max_target(Target::MAX)
+            .nominal_hashrate(1.0)
+            .expected_share_per_minute(1.0);
+        let from_config = |config| {
+            StandardChannel::from_config(config, Box::new(DefaultJobStore::<StandardJob>::new()))
+        };
+        assert!(from_config(config.clone()).is_ok());
+        assert!(matches!(
+            from_config(
+                config
+                    .clone()
+                    .user_identity_rules(UserIdentityRules::default().with_max_len(8))
+            ),
+            Err(StandardChannelError::InvalidUserIdentity(
+                UserIdentityError::TooLong { max_len: 8, .. }
+            ))
+        ));
+        assert!(from_config(
+            config
+                .user_identity("alice worker1".to_string())
+                .user_identity_rules(UserIdentityRules::default().with_charset(|c| c != '\0'))
+        )
+        .is_ok());
+
+        // invalid identities can't be smuggled in through a snapshot
+        let mut state = new_channel("alice.worker1").unwrap().export_state();
+        state.user_identity = String::new();
+        assert!(matches!(
+            StandardChannel::import_state(state, Box::new(DefaultJobStore::<StandardJob>::new())),
+            Err(StandardChannelError::InvalidUserIdentity(
+                UserIdentityError::Empty
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_share_before_any_job() {
+        let mut channel = fixture::standard_channel();
+        let share = SubmitSharesStandard {
+            channel_id: 1,
+            sequence_number: 0,
+            job_id: 1,
+            nonce: 0,
+            ntime: fixture::NTIME,
+            version: 536870912,
+        };
+
+        // the channel never had a job
+        let error = channel.validate_share(share.clone()).unwrap_err();
+        assert_eq!(error, ShareValidationError::NoActiveJob);
+        assert_eq!(error.error_code(), "invalid-job-id");
+
+        // the channel has a job, just not this one
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        let error = channel
+            .validate_share(SubmitSharesStandard { job_id: 2, ..share })
+            .unwrap_err();
+        assert_eq!(error, ShareValidationError::InvalidJobId);
+        assert_eq!(error.error_code(), "invalid-job-id");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_job_id_obfuscation() {
+        let mut channel = StandardChannel::from_config(
+            fixture::standard_channel_config().obfuscate_job_ids(true),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        let key = channel.job_factory.get_job_id_key().unwrap();
+
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        let job_id = channel.get_active_job().unwrap().get_job_id();
+        assert_eq!(channel.job_factory.get_last_job_id(), job_id);
+
+        // the key is migrated along with the channel, so new ids don't collide with the migrated
+        // ones
+        let state = channel.export_state();
+        assert_eq!(state.last_job_id, job_id);
+        assert_eq!(state.job_id_key, Some(key.to_u64()));
+        let mut imported_channel =
+            StandardChannel::import_state(state, Box::new(DefaultJobStore::<StandardJob>::new()))
+                .unwrap();
+        imported_channel
+            .on_new_template(
+                NewTemplate {
+                    template_id: 2,
+                    ..fixture::template(false)
+                },
+                fixture::coinbase_reward_outputs(),
+            )
+            .unwrap();
+        assert_eq!(imported_channel.job_factory.get_job_id_key(), Some(key));
+        assert_ne!(
+            imported_channel.get_active_job().unwrap().get_job_id(),
+            job_id
+        );
+        assert!(imported_channel.get_past_jobs().contains_key(&job_id));
+    }
+
+    #[test]
+    fn test_inconsistent_job_store() {
+        use crate::server::{
+            jobs::{error::JobStoreError, job_store::JobStore},
+            share_validation::InternalInconsistency,
+        };
+
+        // files each past job under its job id + 2
+        #[derive(Debug)]
+        struct MisfilingJobStore<'a> {
+            inner: DefaultJobStore<StandardJob<'a>>,
+            past_jobs: HashMap<u32, StandardJob<'a>>,
+        }
+
+        impl MisfilingJobStore<'_> {
+            fn refile(&mut self) {
+                self.past_jobs = self
+                    .inner
+                    .get_past_jobs()
+                    .values()
+                    .map(|job| (job.get_job_id() + 2, job.clone()))
+                    .collect();
+            }
+        }
+
+        impl<'a> JobStore<StandardJob<'a>> for MisfilingJobStore<'a> {
+            fn add_future_job(
+                &mut self,
+                template_id: u64,
+                job: StandardJob<'a>,
+            ) -> Result<u32, JobStoreError> {
+                self.inner.add_future_job(template_id, job)
+            }
+            fn add_active_job(&mut self, job: StandardJob<'a>) -> Result<(), JobStoreError> {
+                self.inner.add_active_job(job)?;
+                self.refile();
+                Ok(())
+            }
+            fn activate_future_job(&mut self, template_id: u64, timestamp: u32) -> bool {
+                let activated = self.inner.activate_future_job(template_id, timestamp);
+                self.refile();
+                activated
+            }
+            fn set_active_job(&mut self, job: StandardJob<'a>) {
+                self.inner.set_active_job(job);
+            }
+            fn get_future_template_to_job_id(&self) -> &HashMap<u64, u32> {
+                self.inner.get_future_template_to_job_id()
+            }
+            fn get_active_job(&self) -> Option<&StandardJob<'a>> {
+                self.inner.get_active_job()
+            }
+            fn get_future_jobs(&self) -> &HashMap<u32, StandardJob<'a>> {
+                self.inner.get_future_jobs()
+            }
+            fn get_past_jobs(&self) -> &HashMap<u32, StandardJob<'a>> {
+                &self.past_jobs
+            }
+            fn get_stale_jobs(&self) -> &HashMap<u32, StandardJob<'a>> {
+                self.inner.get_stale_jobs()
+            }
+        }
+
+        let mut channel = StandardChannel::from_config(
+            fixture::standard_channel_config(),
+            Box::new(MisfilingJobStore {
+                inner: DefaultJobStore::new(),
+                past_jobs: HashMap::new(),
+            }),
+        )
+        .unwrap();
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        // job 1 becomes a past job, filed under job id 3
+        channel
+            .on_new_template(
+                NewTemplate {
+                    template_id: 2,
+                    ..fixture::template(false)
+                },
+                fixture::coinbase_reward_outputs(),
+            )
+            .unwrap();
+
+        let share = |job_id| SubmitSharesStandard {
+            channel_id: 1,
+            sequence_number: 0,
+            job_id,
+            nonce: 0,
+            ntime: fixture::NTIME,
+            version: 536870912,
+        };
+        let error = channel.validate_share(share(3)).unwrap_err();
+        assert_eq!(
+            error,
+            ShareValidationError::Internal(InternalInconsistency::JobIdMismatch {
+                requested: 3,
+                found: 1,
+            })
+        );
+        assert_eq!(error.error_code(), "invalid-job-id");
+        assert!(matches!(
+            channel.validate_share(share(1)),
+            Err(ShareValidationError::InvalidJobId)
+        ));
+        // the active job is unaffected
+        assert!(matches!(
+            channel.validate_share(share(2)),
+            Ok(_) | Err(ShareValidationError::DoesNotMeetTarget)
+        ));
+    }
+
+    #[test]
+    fn test_share_ntime() {
+        let mut channel = fixture::standard_channel();
+
+        // a future job, activated with the chain tip timestamp
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        // a job created on top of the chain tip
+        channel
+            .on_new_template(
+                NewTemplate {
+                    template_id: 2,
+                    ..fixture::template(false)
+                },
+                fixture::coinbase_reward_outputs(),
+            )
+            .unwrap();
+
+        for job_id in [1, 2] {
+            let job = channel
+                .get_active_job()
+                .filter(|job| job.get_job_id() == job_id)
+                .or_else(|| channel.get_past_jobs().get(&job_id))
+                .unwrap();
+            assert_eq!(job.activation_ntime(), Some(fixture::NTIME));
+
+            let share = |ntime| SubmitSharesStandard {
+                channel_id: 1,
+                sequence_number: 0,
+                job_id,
+                nonce: 0,
+                ntime,
+                version: 536870912,
+            };
+            assert!(matches!(
+                channel.validate_share(share(fixture::NTIME - 1)),
+                Err(ShareValidationError::InvalidNtime)
+            ));
+            for ntime in [fixture::NTIME, fixture::NTIME + 1] {
+                assert!(matches!(
+                    channel.validate_share(share(ntime)),
+                    Ok(_) | Err(ShareValidationError::DoesNotMeetTarget)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_share_policy() {
+        use crate::server::share_policy::{MaxVersionRollingMask, ShareCheck, SharePolicy};
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        // counts the accepted shares, on top of the version mask
+        #[derive(Debug)]
+        struct CountingPolicy {
+            mask: MaxVersionRollingMask,
+            accepted: Arc<AtomicUsize>,
+        }
+
+        impl SharePolicy for CountingPolicy {
+            fn pre_validate(
+                &mut self,
+                share: &SubmitSharesStandard,
+                job: &StandardJob,
+            ) -> Result<(), ShareValidationError> {
+                self.mask.pre_validate(share, job)
+            }
+
+            fn post_accept(&mut self, check: &ShareCheck) {
+                assert_eq!(check.user_identity, "user_identity");
+                self.accepted.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let mut channel = fixture::standard_channel();
+        channel.set_version_rolling_allowed(true);
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+
+        let accepted = Arc::new(AtomicUsize::new(0));
+        channel.set_share_policy(Box::new(CountingPolicy {
+            // only bit 13, the lowest bit of the BIP320 mask
+            mask: MaxVersionRollingMask::new(1 << 13),
+            accepted: accepted.clone(),
+        }));
+
+        let share = |nonce, version| SubmitSharesStandard {
+            channel_id: 1,
+            sequence_number: nonce,
+            job_id: 1,
+            nonce,
+            ntime: fixture::NTIME,
+            version,
+        };
+
+        // allowed by the channel, but not by the policy
+        assert!(matches!(
+            channel.validate_share(share(0, 536870912 | (1 << 14))),
+            Err(ShareValidationError::VersionRollingNotAllowed)
+        ));
+        assert_eq!(accepted.load(Ordering::SeqCst), 0);
+
+        let mut accepted_shares = 0;
+        for nonce in 0..100 {
+            match channel.validate_share(share(nonce, 536870912 | (1 << 13))) {
+                Ok(_) => accepted_shares += 1,
+                Err(e) => assert_eq!(e, ShareValidationError::DoesNotMeetTarget),
+            }
+        }
+        assert!(accepted_shares > 0);
+        assert_eq!(accepted.load(Ordering::SeqCst), accepted_shares);
+
+        // without the policy, the channel rules alone apply
+        assert!(channel.remove_share_policy().is_some());
+        assert!(!matches!(
+            channel.validate_share(share(0, 536870912 | (1 << 14))),
+            Err(ShareValidationError::VersionRollingNotAllowed)
+        ));
+    }
+
+    #[test]
+    fn test_header_hasher() {
+        use crate::server::header_hasher::{HeaderHasher, HEADER_SIZE};
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        // returns a fixed hash, counting the headers it was given
+        #[derive(Debug)]
+        struct FixedHasher {
+            hash: [u8; 32],
+            calls: AtomicUsize,
+        }
+
+        impl HeaderHasher for FixedHasher {
+            fn hash_header(&self, _header: &[u8; HEADER_SIZE]) -> [u8; 32] {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                self.hash
+            }
+        }
+
+        let mut channel = fixture::standard_channel();
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+
+        // no share meets a target with the highest possible hash
+        let highest = Arc::new(FixedHasher {
+            hash: [0xff; 32],
+            calls: AtomicUsize::new(0),
+        });
+        channel.set_header_hasher(highest.clone());
+        for nonce in 0..10 {
+            assert!(matches!(
+                channel.validate_share(fixture::submit_shares_standard(1, 1, nonce)),
+                Err(ShareValidationError::DoesNotMeetTarget)
+            ));
+        }
+        assert_eq!(highest.calls.load(Ordering::SeqCst), 10);
+
+        // while every share finds a block with the lowest one
+        let lowest = Arc::new(FixedHasher {
+            hash: [0; 32],
+            calls: AtomicUsize::new(0),
+        });
+        channel.set_header_hasher(lowest.clone());
+        match channel.validate_share(fixture::submit_shares_standard(1, 1, 10)) {
+            Ok(ShareValidationResult::BlockFound(Some(1), _, event)) => {
+                assert_eq!(event.block_hash, [0; 32]);
+            }
+            other => panic!("expected a block, got {:?}", other),
+        }
+        assert_eq!(lowest.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(highest.calls.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_initial_downstream_messages() {
+        let mut channel = fixture::standard_channel();
+
+        // no jobs at all
+        assert!(channel.initial_downstream_messages().is_empty());
+
+        // only future jobs
+        for template_id in [1, 2] {
+            channel
+                .on_new_template(
+                    NewTemplate {
+                        template_id,
+                        ..fixture::template(true)
+                    },
+                    fixture::coinbase_reward_outputs(),
+                )
+                .unwrap();
+        }
+        let messages = channel.initial_downstream_messages();
+        assert_eq!(messages.len(), 2);
+        for (message, job_id) in messages.iter().zip([1, 2]) {
+            assert!(matches!(
+                message,
+                DownstreamMessage::NewMiningJob(job)
+                    if job.job_id == job_id && job.min_ntime.clone().into_inner().is_none()
+            ));
+        }
+
+        // an active job and a future job
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(2))
+            .unwrap();
+        channel
+            .on_new_template(
+                NewTemplate {
+                    template_id: 3,
+                    ..fixture::template(true)
+                },
+                fixture::coinbase_reward_outputs(),
+            )
+            .unwrap();
+        let messages = channel.initial_downstream_messages();
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(
+            &messages[0],
+            DownstreamMessage::NewMiningJob(job)
+                if job.job_id == 2 && job.min_ntime.clone().into_inner().is_none()
+        ));
+        assert!(matches!(
+            &messages[1],
+            DownstreamMessage::SetNewPrevHash(set_new_prev_hash)
+                if set_new_prev_hash.job_id == 2
+                    && set_new_prev_hash.min_ntime == fixture::NTIME
+                    && set_new_prev_hash.prev_hash == fixture::prev_hash()
+        ));
+        assert!(matches!(
+            &messages[2],
+            DownstreamMessage::NewMiningJob(job) if job.job_id == 3
+        ));
+
+        // a late-joining downstream ends up with the same jobs and chain tip
+        let mut client_channel = ClientStandardChannel::new(
+            1,
+            "user_identity".to_string(),
+            fixture::extranonce_prefix(),
+            Target::MAX,
+            1.0,
+        );
+        for message in messages {
+            match message {
+                DownstreamMessage::NewMiningJob(job) => client_channel.on_new_mining_job(job),
+                DownstreamMessage::SetNewPrevHash(set_new_prev_hash) => client_channel
+                    .on_set_new_prev_hash(set_new_prev_hash)
+                    .unwrap(),
+            }
+        }
+        assert_eq!(
+            client_channel.get_active_job(),
+            Some(channel.get_active_job().unwrap().get_job_message())
+        );
+        assert!(client_channel.get_future_jobs().contains_key(&3));
+        assert_eq!(
+            client_channel.get_chain_tip().unwrap().to_state(),
+            channel.get_chain_tip().unwrap().to_state()
+        );
+    }
+
+    #[test]
+    fn test_template_replay() {
+        let mut channel = fixture::standard_channel();
+        assert_eq!(
+            channel.get_template_replay_policy(),
+            TemplateReplayPolicy::ReuseJob
+        );
+
+        // the Template Provider reconnects and sends the same future template again
+        for _ in 0..2 {
+            channel
+                .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+                .unwrap();
+        }
+        assert_eq!(channel.get_future_jobs().len(), 1);
+        assert_eq!(channel.get_job_for_template(1).unwrap().get_job_id(), 1);
+
+        // ... and the same chain tip
+        for _ in 0..2 {
+            channel
+                .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+                .unwrap();
+        }
+        assert_eq!(channel.get_active_job().unwrap().get_job_id(), 1);
+        assert!(channel.get_future_jobs().is_empty());
+
+        // the template is replayed as a non-future template once activated
+        channel
+            .on_new_template(fixture::template(false), fixture::coinbase_reward_outputs())
+            .unwrap();
+        assert_eq!(channel.get_active_job().unwrap().get_job_id(), 1);
+        assert!(channel.get_past_jobs().is_empty());
+        assert_eq!(channel.get_job_for_template(1).unwrap().get_job_id(), 1);
+
+        // same template_id, different contents
+        let mut mutated = fixture::template(false);
+        mutated.coinbase_tx_locktime += 1;
+        assert!(matches!(
+            channel.on_new_template(mutated, fixture::coinbase_reward_outputs()),
+            Err(StandardChannelError::TemplateIdReusedWithDifferentContent(
+                1
+            ))
+        ));
+        assert_eq!(channel.get_active_job().unwrap().get_job_id(), 1);
+
+        // a replay for other reward outputs still creates a new job
+        let mut coinbase_reward_outputs = fixture::coinbase_reward_outputs();
+        coinbase_reward_outputs[0].script_pubkey = ScriptBuf::from(vec![0x51]);
+        channel
+            .on_new_template(fixture::template(false), coinbase_reward_outputs)
+            .unwrap();
+        assert_eq!(channel.get_active_job().unwrap().get_job_id(), 2);
+
+        // with NewJob, every replay creates a new job
+        channel.set_template_replay_policy(TemplateReplayPolicy::NewJob);
+        channel
+            .on_new_template(fixture::template(false), fixture::coinbase_reward_outputs())
+            .unwrap();
+        assert_eq!(channel.get_active_job().unwrap().get_job_id(), 3);
+        assert_eq!(channel.get_past_jobs().len(), 2);
+    }
+
+    #[test]
+    fn test_rotate_extranonce_prefix() {
+        let mut server_channel = fixture::standard_channel();
+        let mut client_channel = ClientStandardChannel::new(
+            1,
+            "user_identity".to_string(),
+            fixture::extranonce_prefix(),
+            Target::MAX,
+            1.0,
+        );
+        fn forward<'a>(
+            server_channel: &StandardChannel<'a>,
+            client_channel: &mut ClientStandardChannel<'a>,
+        ) {
+            for message in server_channel.initial_downstream_messages() {
+                match message {
+                    DownstreamMessage::NewMiningJob(job) => client_channel.on_new_mining_job(job),
+                    DownstreamMessage::SetNewPrevHash(set_new_prev_hash) => client_channel
+                        .on_set_new_prev_hash(set_new_prev_hash)
+                        .unwrap(),
+                }
+            }
+        }
+
+        // an active job 1, and a future job 2 for the next template
+        server_channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        server_channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        server_channel
+            .on_new_template(
+                NewTemplate {
+                    template_id: 2,
+                    ..fixture::template(true)
+                },
+                fixture::coinbase_reward_outputs(),
+            )
+            .unwrap();
+        forward(&server_channel, &mut client_channel);
+        let old_job = server_channel.get_active_job().unwrap().clone();
+
+        // a prefix that doesn't fit in the extranonce leaves the channel untouched
+        assert!(matches!(
+            server_channel.rotate_extranonce_prefix(vec![0; mining_sv2::MAX_EXTRANONCE_LEN + 1]),
+            Err(StandardChannelError::NewExtranoncePrefixTooLarge)
+        ));
+        assert_eq!(
+            server_channel.get_extranonce_prefix(),
+            &fixture::extranonce_prefix()
+        );
+
+        let mut extranonce_prefix = fixture::extranonce_prefix();
+        *extranonce_prefix.last_mut().unwrap() ^= 0xff;
+        let message = server_channel
+            .rotate_extranonce_prefix(extranonce_prefix.clone())
+            .unwrap();
+        assert_eq!(message.channel_id, 1);
+        assert_eq!(
+            message.extranonce_prefix.inner_as_ref(),
+            &extranonce_prefix[..]
+        );
+
+        // both jobs were recreated for the new prefix, the active one on the same chain tip
+        let active_job = server_channel.get_active_job().unwrap();
+        assert_eq!(active_job.get_job_id(), 3);
+        assert_eq!(active_job.get_extranonce_prefix(), &extranonce_prefix);
+        assert_eq!(active_job.activation_ntime(), old_job.activation_ntime());
+        assert_ne!(active_job.get_merkle_root(), old_job.get_merkle_root());
+        assert!(server_channel.get_past_jobs().contains_key(&1));
+        let future_job_ids: Vec<u32> = server_channel.get_future_jobs().keys().copied().collect();
+        assert_eq!(future_job_ids, vec![4]);
+        assert_eq!(
+            server_channel.get_future_template_to_job_id().get(&2),
+            Some(&4)
+        );
+
+        // the client flags the jobs it has, until it gets the new ones
+        let mut other_channel = message.clone();
+        other_channel.channel_id = 2;
+        assert!(matches!(
+            client_channel.on_set_extranonce_prefix(other_channel),
+            Err(ClientStandardChannelError::InvalidChannelId)
+        ));
+        client_channel.on_set_extranonce_prefix(message).unwrap();
+        assert_eq!(client_channel.get_extranonce_prefix(), &extranonce_prefix);
+        assert!(client_channel.is_active_job_outdated());
+        assert!(client_channel.requires_refresh(1));
+        assert!(client_channel.requires_refresh(2));
+
+        forward(&server_channel, &mut client_channel);
+        assert!(!client_channel.is_active_job_outdated());
+        assert_eq!(client_channel.get_active_job().unwrap().job_id, 3);
+        assert!(!client_channel.requires_refresh(1));
+        assert!(!client_channel.requires_refresh(2));
+        let future_job_ids: Vec<u32> = client_channel.get_future_jobs().keys().copied().collect();
+        assert_eq!(future_job_ids, vec![4]);
+
+        // shares for the new job and for the replaced one are both accepted
+        server_channel.set_target(Target::MAX);
+        let job = client_channel.get_active_job().unwrap().clone();
+        let share = client_channel
+            .build_share(&job, 0, fixture::NTIME, job.version, 0)
+            .unwrap();
+        assert!(server_channel.validate_share(share).is_ok());
+        assert!(server_channel
+            .validate_share(fixture::submit_shares_standard(1, 1, 1))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_apply_template_with_chain_tip() {
+        let template = |template_id| NewTemplate {
+            template_id,
+            ..fixture::template(false)
+        };
+        // the same, through the chain tip and the template set one after the other
+        let mut two_calls = fixture::standard_channel();
+        let mut one_call = fixture::standard_channel();
+
+        // the first job after connecting
+        two_calls.set_chain_tip(fixture::chain_tip());
+        two_calls
+            .on_new_template(template(1), fixture::coinbase_reward_outputs())
+            .unwrap();
+        let job_message = one_call
+            .apply_template_with_chain_tip(
+                template(1),
+                fixture::coinbase_reward_outputs(),
+                fixture::chain_tip(),
+            )
+            .unwrap();
+        assert_eq!(job_message.job_id, 1);
+        assert_eq!(
+            job_message.min_ntime.into_inner(),
+            Some(fixture::chain_tip().min_ntime())
+        );
+        assert_eq!(one_call.export_state(), two_calls.export_state());
+
+        // another template on the same chain tip
+        two_calls
+            .on_new_template(template(2), fixture::coinbase_reward_outputs())
+            .unwrap();
+        let job_message = one_call
+            .apply_template_with_chain_tip(
+                template(2),
+                fixture::coinbase_reward_outputs(),
+                fixture::chain_tip(),
+            )
+            .unwrap();
+        assert_eq!(job_message.job_id, 2);
+        assert_eq!(one_call.export_state(), two_calls.export_state());
+        assert!(one_call.get_past_jobs().contains_key(&1));
+
+        // a job that can't be created leaves the channel untouched
+        let state = one_call.export_state();
+        let new_chain_tip =
+            ChainTip::new([0x42; 32].into(), fixture::N_BITS, fixture::NTIME + 1).unwrap();
+        assert!(matches!(
+            one_call.apply_template_with_chain_tip(template(3), vec![], new_chain_tip.clone()),
+            Err(StandardChannelError::JobFactoryError(
+                JobFactoryError::InvalidCoinbaseOutputsSum
+            ))
+        ));
+        assert_eq!(one_call.export_state(), state);
+
+        // on a new chain tip, the jobs of the previous one become stale
+        let job_message = one_call
+            .apply_template_with_chain_tip(
+                template(3),
+                fixture::coinbase_reward_outputs(),
+                new_chain_tip.clone(),
+            )
+            .unwrap();
+        assert_eq!(job_message.job_id, 3);
+        assert_eq!(job_message.min_ntime.into_inner(), Some(fixture::NTIME + 1));
+        assert_eq!(one_call.get_chain_tip(), Some(&new_chain_tip));
+        assert!(one_call.get_past_jobs().is_empty());
+        let mut stale_job_ids: Vec<u32> = one_call.get_stale_jobs().keys().copied().collect();
+        stale_job_ids.sort();
+        assert_eq!(stale_job_ids, vec![1, 2]);
+        assert!(matches!(
+            one_call.validate_share(fixture::submit_shares_standard(1, 2, 0)),
+            Err(ShareValidationError::Stale)
+        ));
+    }
+
+    #[test]
+    fn test_share_version_top_bits() {
+        let new_channel = |template: NewTemplate<'static>| {
+            let mut channel = fixture::standard_channel();
+            channel.set_version_rolling_allowed(true);
+            channel.set_target(Target::MAX);
+            channel.set_chain_tip(fixture::chain_tip());
+            channel
+                .on_new_template(template, fixture::coinbase_reward_outputs())
+                .unwrap();
+            channel
+        };
+        let share = |version| SubmitSharesStandard {
+            version,
+            ..fixture::submit_shares_standard(1, 1, 0)
+        };
+
+        let mut channel = new_channel(fixture::template(false));
+        assert_eq!(
+            channel.validate_share(share(0)).unwrap_err(),
+            ShareValidationError::InvalidVersion
+        );
+        assert_eq!(
+            channel
+                .validate_share(share(536870912 | (1 << 31)))
+                .unwrap_err(),
+            ShareValidationError::InvalidVersion
+        );
+        assert_eq!(
+            ShareValidationError::InvalidVersion.error_code(),
+            "invalid-version"
+        );
+        // bit 13 is the lowest bit of the BIP320 mask
+        assert!(channel.validate_share(share(536870912 | (1 << 13))).is_ok());
+
+        // a job without the top bits can't yield a valid block, whatever the share
+        let mut channel = new_channel(NewTemplate {
+            version: 4,
+            ..fixture::template(false)
+        });
+        assert_eq!(
+            channel.validate_share(share(4)).unwrap_err(),
+            ShareValidationError::InvalidVersion
+        );
+    }
+
+    #[test]
+    fn test_future_template_mappings_stay_bounded() {
+        use crate::server::jobs::{error::JobStoreError, job_store::JobStore};
+
+        let mut channel = StandardChannel::from_config(
+            fixture::standard_channel_config().template_replay_policy(TemplateReplayPolicy::NewJob),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+
+        // every block, a future template is sent twice along with one that never gets mined
+        for block in 1..=500u64 {
+            for template_id in [block * 2, block * 2, block * 2 + 1] {
+                let mut template = fixture::template(true);
+                template.template_id = template_id;
+                channel
+                    .on_new_template(template, fixture::coinbase_reward_outputs())
+                    .unwrap();
+                assert_eq!(
+                    channel.get_future_template_to_job_id().len(),
+                    channel.get_future_jobs().len()
+                );
+            }
+            assert_eq!(channel.get_future_jobs().len(), 2);
+            let mut set_new_prev_hash = fixture::set_new_prev_hash(block * 2);
+            set_new_prev_hash.header_timestamp += block as u32;
+            channel.on_set_new_prev_hash(set_new_prev_hash).unwrap();
+            assert!(channel.get_future_template_to_job_id().is_empty());
+            assert!(channel.get_future_jobs().is_empty());
+        }
+        assert_eq!(channel.job_store.gc(), 0);
+
+        // a job id reused for another template is refused, the first template keeps its job
+        let job = channel.get_active_job().unwrap().clone();
+        let job_id = job.get_job_id();
+        let mut job_store = DefaultJobStore::<StandardJob>::new();
+        assert_eq!(job_store.add_future_job(1, job.clone()), Ok(job_id));
+        assert_eq!(
+            job_store.add_future_job(2, job),
+            Err(JobStoreError::JobIdCollision(job_id))
+        );
+        assert_eq!(job_store.get_future_template_to_job_id().len(), 1);
+        assert!(job_store.get_future_template_to_job_id().contains_key(&1));
+        assert_eq!(job_store.gc(), 0);
+        assert!(job_store.activate_future_job(1, fixture::NTIME));
+    }
+
+    #[test]
+    fn test_superseded_future_template_is_never_activated() {
+        let mut channel = fixture::standard_channel();
+        let future_template = |template_id| NewTemplate {
+            template_id,
+            ..fixture::template(true)
+        };
+
+        // two future templates, the second one gets mined on
+        for template_id in [1, 2] {
+            channel
+                .on_new_template(
+                    future_template(template_id),
+                    fixture::coinbase_reward_outputs(),
+                )
+                .unwrap();
+        }
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(2))
+            .unwrap();
+        assert_eq!(channel.get_active_job().unwrap().get_job_id(), 2);
+        assert!(channel.get_future_jobs().is_empty());
+
+        // a spurious SetNewPrevHash for the first one
+        let mut spurious = fixture::set_new_prev_hash(1);
+        spurious.header_timestamp += 1;
+        assert!(matches!(
+            channel.on_set_new_prev_hash(spurious.clone()),
+            Err(StandardChannelError::TemplateIdNotFound)
+        ));
+
+        // still, once a future template of the next chain tip arrived
+        channel
+            .on_new_template(future_template(3), fixture::coinbase_reward_outputs())
+            .unwrap();
+        let state = channel.export_state();
+        assert!(matches!(
+            channel.on_set_new_prev_hash(spurious),
+            Err(StandardChannelError::TemplateIdNotFound)
+        ));
+        assert_eq!(channel.export_state(), state);
+
+        let mut set_new_prev_hash = fixture::set_new_prev_hash(3);
+        set_new_prev_hash.header_timestamp += 2;
+        channel.on_set_new_prev_hash(set_new_prev_hash).unwrap();
+        assert_eq!(channel.get_active_job().unwrap().get_job_id(), 3);
+        assert!(channel.get_stale_jobs().contains_key(&2));
+    }
+
+    #[test]
+    fn test_job_store_tip_eras() {
+        use crate::server::jobs::job_store::JobStore;
+
+        let mut channel = fixture::standard_channel();
+        // jobs 1 to 3, for templates 1 to 3
+        for template_id in 1..=3 {
+            let template = NewTemplate {
+                template_id,
+                ..fixture::template(true)
+            };
+            channel
+                .on_new_template(template, fixture::coinbase_reward_outputs())
+                .unwrap();
+        }
+        let job = |job_id| channel.get_future_jobs().get(&job_id).unwrap().clone();
+
+        let mut job_store = DefaultJobStore::<StandardJob>::new();
+        job_store.add_future_job(1, job(1)).unwrap();
+        job_store.add_future_job(2, job(2)).unwrap();
+        assert_eq!(job_store.get_tip_era(), 0);
+        assert!(job_store.activate_future_job(2, fixture::NTIME));
+        assert_eq!(job_store.get_tip_era(), 1);
+        // the job of the superseded template was dropped along with its era
+        assert!(!job_store.activate_future_job(1, fixture::NTIME));
+        assert_eq!(job_store.get_tip_era(), 1);
+        assert_eq!(job_store.gc(), 0);
+
+        // jobs added in the new era can be activated
+        job_store.add_future_job(3, job(3)).unwrap();
+        assert!(job_store.activate_future_job(3, fixture::NTIME + 1));
+        assert_eq!(job_store.get_tip_era(), 2);
+        assert_eq!(job_store.get_active_job().unwrap().get_job_id(), 3);
+    }
+
+    #[test]
+    fn test_job_id_collision() {
+        use crate::server::jobs::{error::JobStoreError, job_store::JobStore};
+
+        let mut channel = fixture::standard_channel();
+        // jobs 1 and 2, for templates 1 and 2
+        for template_id in 1..=2 {
+            let template = NewTemplate {
+                template_id,
+                ..fixture::template(true)
+            };
+            channel
+                .on_new_template(template, fixture::coinbase_reward_outputs())
+                .unwrap();
+        }
+        let job = |job_id| channel.get_future_jobs().get(&job_id).unwrap().clone();
+        let (job_1, job_2) = (job(1), job(2));
+
+        // future, then active
+        let mut job_store = DefaultJobStore::<StandardJob>::new();
+        job_store.add_future_job(1, job_1.clone()).unwrap();
+        assert!(job_store.contains_job_id(1));
+        assert!(!job_store.contains_job_id(2));
+        assert_eq!(
+            job_store.add_active_job(job_1.clone()),
+            Err(JobStoreError::JobIdCollision(1))
+        );
+        assert!(job_store.get_active_job().is_none());
+        assert_eq!(job_store.get_future_template_to_job_id().get(&1), Some(&1));
+        assert!(job_store.activate_future_job(1, fixture::NTIME));
+
+        // active, then future
+        assert_eq!(
+            job_store.add_future_job(2, job_1.clone()),
+            Err(JobStoreError::JobIdCollision(1))
+        );
+        assert!(job_store.get_future_jobs().is_empty());
+        assert!(job_store.get_future_template_to_job_id().is_empty());
+        assert_eq!(job_store.get_active_job().unwrap().get_job_id(), 1);
+        assert!(!job_store.activate_future_job(2, fixture::NTIME));
+
+        // past, then active
+        job_store.add_active_job(job_2).unwrap();
+        assert!(job_store.get_past_jobs().contains_key(&1));
+        assert!(job_store.contains_job_id(1));
+        assert_eq!(
+            job_store.add_active_job(job_1),
+            Err(JobStoreError::JobIdCollision(1))
+        );
+        assert_eq!(job_store.get_active_job().unwrap().get_job_id(), 2);
+        assert_eq!(job_store.get_past_jobs().len(), 1);
+
+        // a snapshot restored into a store already holding one of its jobs
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        let active_job = channel.get_active_job().unwrap().clone();
+        let mut job_store = DefaultJobStore::<StandardJob>::new();
+        job_store.add_future_job(1, active_job).unwrap();
+        assert!(matches!(
+            StandardChannel::import_state(channel.export_state(), Box::new(job_store)),
+            Err(StandardChannelError::JobStoreError(
+                JobStoreError::JobIdCollision(1)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_share_accounting_eras() {
+        use crate::server::share_accounting::{EraWork, ShareAccountingConfig};
+        use bitcoin::hashes::{sha256d::Hash, Hash as _};
+
+        let mut channel = StandardChannel::from_config(
+            fixture::standard_channel_config()
+                .extranonce_prefix(vec![0; 32])
+                .share_accounting_config(
+                    ShareAccountingConfig::default().with_max_retained_eras(3),
+                ),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+
+        // shares accepted before the first chain tip belong to no era
+        let mut sequence_number = 0u32;
+        let mut accept_share = |channel: &mut StandardChannel, share_work: u64| {
+            sequence_number += 1;
+            channel.share_accounting.update_share_accounting(
+                share_work,
+                sequence_number,
+                Hash::hash(&sequence_number.to_le_bytes()),
+            );
+        };
+        accept_share(&mut channel, 100);
+        assert_eq!(channel.get_share_accounting().get_current_era(), None);
+
+        // three chain tips, each with its own shares
+        for template_id in 1..=3u64 {
+            let mut template = fixture::template(true);
+            template.template_id = template_id;
+            channel
+                .on_new_template(template, fixture::coinbase_reward_outputs())
+                .unwrap();
+            channel
+                .on_set_new_prev_hash(fixture::set_new_prev_hash(template_id))
+                .unwrap();
+            assert_eq!(
+                channel.get_share_accounting().get_current_era(),
+                Some(template_id)
+            );
+            for _ in 0..template_id {
+                accept_share(&mut channel, template_id * 10);
+            }
+        }
+
+        let share_accounting = &mut channel.share_accounting;
+        assert_eq!(share_accounting.get_era_work(1), Some(10));
+        assert_eq!(share_accounting.get_era_work(2), Some(2 * 20));
+        assert_eq!(share_accounting.get_era_work(3), Some(3 * 30));
+        assert_eq!(share_accounting.get_era_work(4), None);
+        assert_eq!(share_accounting.get_share_work_sum(), 100 + 10 + 40 + 90);
+
+        // eras survive a snapshot
+        let mut share_accounting = ShareAccounting::from_state(share_accounting.to_state());
+
+        // the current era keeps accumulating once the completed ones are drained
+        assert_eq!(
+            share_accounting.drain_completed_eras(),
+            vec![
+                EraWork {
+                    era_id: 1,
+                    shares_accepted: 1,
+                    share_work: 10,
+                },
+                EraWork {
+                    era_id: 2,
+                    shares_accepted: 2,
+                    share_work: 40,
+                },
+            ]
+        );
+        assert!(share_accounting.drain_completed_eras().is_empty());
+        share_accounting.start_new_era(3);
+        share_accounting.update_share_accounting(30, 100, Hash::hash(&100u32.to_le_bytes()));
+        assert_eq!(share_accounting.get_era_work(3), Some(4 * 30));
+
+        // only the 3 most recent eras are retained, drained or not
+        for era_id in 4..=7 {
+            share_accounting.start_new_era(era_id);
+        }
+        assert_eq!(share_accounting.get_era_work(3), None);
+        assert_eq!(
+            share_accounting
+                .drain_completed_eras()
+                .iter()
+                .map(|era| era.era_id)
+                .collect::<Vec<_>>(),
+            vec![5, 6]
+        );
+        assert_eq!(share_accounting.get_current_era(), Some(7));
+    }
+
+    #[test]
+    fn test_info_handle_concurrent_reads() {
+        use std::{
+            sync::{
+                atomic::{AtomicBool, Ordering},
+                Arc, Barrier,
+            },
+            thread,
+        };
+
+        let mut channel = fixture::standard_channel();
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        let job_id = channel.get_active_job().unwrap().get_job_id();
+        channel.set_target(Target::MAX);
+
+        // a target sharing no byte with `Target::MAX`, so that a torn read would mix both
+        let other_target = Target::from_le_bytes([0x5a; 32]);
+        let handle = channel.get_info_handle();
+        assert_eq!(handle.get_target(), Target::MAX);
+        assert_eq!(handle.get_nominal_hashrate(), 1.0);
+
+        let started = Arc::new(Barrier::new(2));
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let handle = handle.clone();
+            let other_target = other_target.clone();
+            let started = started.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let (mut shares_accepted, mut share_work_sum) = (0, 0);
+                started.wait();
+                loop {
+                    let target = handle.get_target();
+                    assert!(target == Target::MAX || target == other_target);
+                    // the counters only grow
+                    assert!(handle.get_shares_accepted() >= shares_accepted);
+                    assert!(handle.get_share_work_sum() >= share_work_sum);
+                    shares_accepted = handle.get_shares_accepted();
+                    share_work_sum = handle.get_share_work_sum();
+                    if done.load(Ordering::Acquire) {
+                        break;
+                    }
+                }
+            })
+        };
+
+        started.wait();
+
+        for nonce in 0..2000 {
+            channel.set_target(other_target.clone());
+            channel.set_target(Target::MAX);
+            channel
+                .validate_share(fixture::submit_shares_standard(1, job_id, nonce))
+                .unwrap();
+        }
+        done.store(true, Ordering::Release);
+        reader.join().unwrap();
+        channel.update_channel(2.0, None).unwrap();
+
+        let share_accounting = channel.get_share_accounting();
+        assert_eq!(share_accounting.get_shares_accepted(), 2000);
+        assert_eq!(
+            handle.get_shares_accepted(),
+            share_accounting.get_shares_accepted()
+        );
+        assert_eq!(
+            handle.get_share_work_sum(),
+            share_accounting.get_share_work_sum()
+        );
+        assert_eq!(handle.get_best_diff(), share_accounting.get_best_diff());
+        assert_eq!(handle.get_target(), channel.get_target());
+        assert_eq!(handle.get_nominal_hashrate(), 2.0);
+    }
+
+    #[test]
+    fn test_job_rate_limit() {
+        use crate::server::jobs::{JobRateLimit, TemplateOutcome};
+
+        let mut channel = StandardChannel::from_config(
+            fixture::standard_channel_config()
+                .job_rate_limit(JobRateLimit::new(2).with_fee_bump_threshold(1000)),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        let template = |template_id, future_template, fee_bump| NewTemplate {
+            template_id,
+            future_template,
+            coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE + fee_bump,
+            ..fixture::template(future_template)
+        };
+        // the reward outputs take the fees of each template
+        let on_new_template = |channel: &mut StandardChannel, template: NewTemplate<'static>| {
+            let mut coinbase_reward_outputs = fixture::coinbase_reward_outputs();
+            coinbase_reward_outputs[0].value =
+                Amount::from_sat(template.coinbase_tx_value_remaining);
+            channel
+                .on_new_template(template, coinbase_reward_outputs)
+                .unwrap()
+        };
+        let active_template_id = |channel: &StandardChannel| {
+            channel.get_active_job().unwrap().get_template().template_id
+        };
+
+        // the job of the future template activated by the prev hash doesn't count
+        assert_eq!(
+            on_new_template(&mut channel, template(1, true, 0)),
+            TemplateOutcome::JobCreated
+        );
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        for template_id in 2..=3 {
+            assert_eq!(
+                on_new_template(&mut channel, template(template_id, false, 0)),
+                TemplateOutcome::JobCreated
+            );
+        }
+
+        // over the limit, a template is suppressed unless it bumps the fees enough
+        assert_eq!(
+            on_new_template(&mut channel, template(4, false, 0)),
+            TemplateOutcome::SuppressedByRateLimit
+        );
+        assert_eq!(
+            on_new_template(&mut channel, template(5, false, 999)),
+            TemplateOutcome::SuppressedByRateLimit
+        );
+        assert_eq!(active_template_id(&channel), 3);
+        assert!(channel.get_job_for_template(4).is_none());
+        assert_eq!(
+            on_new_template(&mut channel, template(6, false, 1000)),
+            TemplateOutcome::JobCreated
+        );
+        assert_eq!(active_template_id(&channel), 6);
+        // the fee bump is relative to the active job
+        assert_eq!(
+            on_new_template(&mut channel, template(7, false, 1000)),
+            TemplateOutcome::SuppressedByRateLimit
+        );
+        // a replayed template keeps its job regardless of the limit
+        assert_eq!(
+            on_new_template(&mut channel, template(6, false, 1000)),
+            TemplateOutcome::JobReused
+        );
+
+        // future templates are never suppressed, and the prev hash resets the count
+        assert_eq!(
+            on_new_template(&mut channel, template(8, true, 0)),
+            TemplateOutcome::JobCreated
+        );
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(8))
+            .unwrap();
+        assert_eq!(
+            on_new_template(&mut channel, template(9, false, 0)),
+            TemplateOutcome::JobCreated
+        );
+        assert_eq!(active_template_id(&channel), 9);
+
+        // lifting the limit
+        on_new_template(&mut channel, template(10, false, 0));
+        assert_eq!(
+            on_new_template(&mut channel, template(11, false, 0)),
+            TemplateOutcome::SuppressedByRateLimit
+        );
+        channel.set_job_rate_limit(None);
+        assert_eq!(
+            on_new_template(&mut channel, template(12, false, 0)),
+            TemplateOutcome::JobCreated
+        );
+    }
+
+    #[test]
+    fn test_deferred_target_changes() {
+        let mut channel = StandardChannel::from_config(
+            fixture::standard_channel_config().defer_target_changes(true),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        channel.set_target(Target::MAX);
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        let first_job_id = channel.get_active_job().unwrap().get_job_id();
+
+        // vardiff lowers the target in the middle of the job: shares of the job still meet the
+        // current one
+        channel.update_channel(1e15, None).unwrap();
+        let lower_target = channel.pending_target().unwrap().clone();
+        assert!(lower_target < Target::MAX);
+        assert_eq!(channel.get_target(), Target::MAX);
+        assert_eq!(channel.get_info_handle().get_target(), Target::MAX);
+        assert!(channel.take_deferred_set_target().is_none());
+        assert!(channel
+            .validate_share(fixture::submit_shares_standard(1, first_job_id, 0))
+            .is_ok());
+
+        // the next job comes with the lower target
+        let template = NewTemplate {
+            template_id: 2,
+            ..fixture::template(false)
+        };
+        channel
+            .on_new_template(template, fixture::coinbase_reward_outputs())
+            .unwrap();
+        let second_job_id = channel.get_active_job().unwrap().get_job_id();
+        assert!(channel.pending_target().is_none());
+        assert_eq!(channel.get_target(), lower_target);
+        let set_target = channel.take_deferred_set_target().unwrap();
+        assert_eq!(set_target.channel_id, 1);
+        assert_eq!(Target::from(set_target.maximum_target), lower_target);
+        assert!(channel.take_deferred_set_target().is_none());
+        assert!(matches!(
+            channel.validate_share(fixture::submit_shares_standard(1, second_job_id, 1)),
+            Err(ShareValidationError::DoesNotMeetTarget)
+        ));
+        // shares of the first job in flight are still validated against its target
+        assert!(channel
+            .validate_share(fixture::submit_shares_standard(1, first_job_id, 2))
+            .is_ok());
+
+        // a future job only applies the pending target once activated by a `SetNewPrevHash`
+        channel.update_channel(1.0, None).unwrap();
+        let higher_target = channel.pending_target().unwrap().clone();
+        let template = NewTemplate {
+            template_id: 3,
+            ..fixture::template(true)
+        };
+        channel
+            .on_new_template(template, fixture::coinbase_reward_outputs())
+            .unwrap();
+        assert_eq!(channel.pending_target(), Some(&higher_target));
+        assert_eq!(channel.get_target(), lower_target);
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(3))
+            .unwrap();
+        assert!(channel.pending_target().is_none());
+        assert_eq!(channel.get_target(), higher_target);
+        assert!(channel.take_deferred_set_target().is_some());
+
+        // going back to the current target leaves nothing pending
+        channel.update_channel(1e15, None).unwrap();
+        assert!(channel.pending_target().is_some());
+        channel.update_channel(1.0, None).unwrap();
+        assert!(channel.pending_target().is_none());
+
+        // without deferral, the pending target is applied right away
+        channel.update_channel(1e15, None).unwrap();
+        channel.set_defer_target_changes(false);
+        assert!(channel.pending_target().is_none());
+        assert_eq!(channel.get_target(), lower_target);
+        assert!(channel.take_deferred_set_target().is_some());
+        channel.update_channel(1.0, None).unwrap();
+        assert_eq!(channel.get_target(), higher_target);
+        assert!(channel.take_deferred_set_target().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_share_rejected_events() {
+        use crate::testing::doubles::RecordingShareObserver;
+
+        let mut channel = fixture::standard_channel();
+        let (observer, shares) = RecordingShareObserver::new();
+        channel.set_share_policy(Box::new(observer));
+        let submit = |channel: &mut StandardChannel, job_id, nonce| {
+            channel
+                .validate_share(fixture::submit_shares_standard(1, job_id, nonce))
+                .is_ok()
+        };
+
+        // rejected before any job is found
+        assert!(!submit(&mut channel, 1, 0));
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        let job_id = channel.get_active_job().unwrap().get_job_id();
+        for unknown_job_id in [job_id + 1, job_id + 2] {
+            assert!(!submit(&mut channel, unknown_job_id, 0));
+        }
+
+        channel.set_target(Target::MAX);
+        assert!(submit(&mut channel, job_id, 0));
+        assert!(!submit(&mut channel, job_id, 0));
+
+        let impossible_target = Target::from_le_bytes([0; 32]);
+        channel.set_target(impossible_target.clone());
+        for nonce in 1..=3 {
+            assert!(!submit(&mut channel, job_id, nonce));
+        }
+
+        channel.pause("maintenance".to_string());
+        assert!(!submit(&mut channel, job_id, 4));
+
+        let rejected = shares.rejected();
+        let mut per_error_code = HashMap::new();
+        for rejection in &rejected {
+            *per_error_code
+                .entry(rejection.error.error_code())
+                .or_insert(0) += 1;
+        }
+        assert_eq!(
+            per_error_code,
+            [
+                ("invalid-job-id", 3),
+                ("duplicate-share", 1),
+                ("difficulty-too-low", 3),
+                ("channel-paused", 1),
+            ]
+            .iter()
+            .copied()
+            .collect()
+        );
+        assert_eq!(shares.accepted().len(), 1);
+
+        // along with the share and the target it was rejected at
+        assert!(rejected.iter().all(
+            |rejection| rejection.channel_id == 1 && rejection.user_identity == "user_identity"
+        ));
+        assert_eq!(rejected[0].error, ShareValidationError::NoActiveJob);
+        assert_eq!(
+            (rejected[3].error.clone(), rejected[3].target.clone()),
+            (ShareValidationError::DuplicateShare, Target::MAX)
+        );
+        for (rejection, nonce) in rejected[4..7].iter().zip(1..) {
+            assert_eq!(rejection.error, ShareValidationError::DoesNotMeetTarget);
+            assert_eq!((rejection.job_id, rejection.nonce), (job_id, nonce));
+            assert_eq!(rejection.target, impossible_target);
+        }
+        assert_eq!(
+            rejected[7].error,
+            ShareValidationError::ChannelPaused("maintenance".to_string())
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_clock_timestamps() {
+        use crate::testing::{clock::TestClock, doubles::RecordingShareObserver};
+        use alloc::sync::Arc;
+        use core::time::Duration;
+
+        let mut channel = fixture::standard_channel();
+        let (observer, shares) = RecordingShareObserver::new();
+        channel.set_share_policy(Box::new(observer));
+        let clock = TestClock::new(1745596910250);
+        channel.set_clock(Arc::new(clock.clone()));
+
+        // no job yet, so every share is rejected
+        let submit = |channel: &mut StandardChannel| {
+            channel
+                .validate_share(fixture::submit_shares_standard(1, 1, 0))
+                .unwrap_err();
+        };
+        submit(&mut channel);
+        clock.advance(Duration::from_millis(750));
+        submit(&mut channel);
+        clock.advance(Duration::from_secs(59));
+        submit(&mut channel);
+        let timestamps: Vec<_> = shares
+            .rejected()
+            .iter()
+            .map(|rejection| rejection.timestamp)
+            .collect();
+        assert_eq!(
+            timestamps,
+            vec![Some(1745596910), Some(1745596911), Some(1745596970)]
+        );
+
+        #[cfg(feature = "event-log")]
+        {
+            let at = |millis| std::time::UNIX_EPOCH + Duration::from_millis(millis);
+            let event_timestamps = |channel: &StandardChannel| {
+                channel
+                    .get_event_log()
+                    .events()
+                    .map(|event| event.timestamp)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                event_timestamps(&channel),
+                vec![at(1745596910250), at(1745596911000), at(1745596970000)]
+            );
+            clock.advance(Duration::from_secs(1));
+            channel.set_target(Target::MAX);
+            assert_eq!(event_timestamps(&channel)[3], at(1745596971000));
+        }
+    }
 }
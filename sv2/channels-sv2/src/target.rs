@@ -1,46 +1,212 @@
+use alloc::{string::String, vec::Vec};
 use binary_sv2::U256;
 use bitcoin::{hash_types::BlockHash, hashes::Hash};
-use mining_sv2::Target;
+use core::{
+    cmp::max,
+    convert::{TryFrom, TryInto},
+    fmt::{self, Write},
+    ops::Div,
+};
+use mining_sv2::{Target, TargetError};
 use primitive_types::U256 as U256Primitive;
-use std::{cmp::max, convert::TryInto, fmt::Write, ops::Div};
 
-/// Converts a `Target` to a `f64` difficulty.
+/// Converts a `Target` to a `f64` difficulty, same as [`Target::difficulty`].
 pub fn target_to_difficulty(target: Target) -> f64 {
-    // Genesis block target: 0x00000000ffff0000000000000000000000000000000000000000000000000000
-    // (in little endian)
-    let max_target_bytes = [
-        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00,
-        0x00, 0x00,
-    ];
-    let max_target = U256Primitive::from_little_endian(&max_target_bytes);
-
-    // Convert input target to U256Primitive
-    let target_u256: U256<'static> = target.into();
-    let mut target_bytes = [0u8; 32];
-    target_bytes.copy_from_slice(target_u256.inner_as_ref());
-    let target = U256Primitive::from_little_endian(&target_bytes);
-
-    // Calculate difficulty = max_target / target
-    // We need to handle the full 256-bit values properly
-    // Convert to f64 by taking the ratio of the most significant bits
-    let max_target_high = (max_target >> 128).low_u128() as f64;
-    let max_target_low = max_target.low_u128() as f64;
-    let target_high = (target >> 128).low_u128() as f64;
-    let target_low = target.low_u128() as f64;
-
-    // Combine high and low parts with appropriate scaling
-    let max_target_f64 = max_target_high * (2.0f64.powi(128)) + max_target_low;
-    let target_f64 = target_high * (2.0f64.powi(128)) + target_low;
-
-    max_target_f64 / target_f64
+    target.difficulty()
+}
+
+/// Rounds a target to the nearest value representable as a compact `nbits` target.
+///
+/// A compact target keeps only 23 bits of mantissa, so targets coming from other implementations
+/// (e.g. derived from difficulty floats) are often only equal to ours after both are rounded to
+/// that precision. Ties are rounded up, unless that would overflow 256 bits.
+pub fn canonicalize_target(target: Target) -> Target {
+    let value = U256Primitive::from_little_endian(&target.to_le_bytes());
+
+    let mut shift = compact_shift(value);
+    if shift == 0 {
+        return target;
+    }
+
+    let mut mantissa = value >> shift;
+    let remainder = value - (mantissa << shift);
+    let half = U256Primitive::one() << (shift - 1);
+    if remainder >= half {
+        mantissa += U256Primitive::one();
+        if mantissa > U256Primitive::from(MAX_COMPACT_MANTISSA) {
+            mantissa >>= 8;
+            shift += 8;
+        }
+        // rounding up would overflow, so round down instead
+        if mantissa.bits() + shift > 256 {
+            return Target::from_le_bytes(((value >> shift) << shift).to_little_endian());
+        }
+    }
+
+    Target::from_le_bytes((mantissa << shift).to_little_endian())
+}
+
+/// Returns the number of least significant bits of `target` that are lost when it is encoded as a
+/// compact `nbits` target, to be used as tolerance for [`Target::approx_eq`].
+///
+/// Targets closer than that are indistinguishable once encoded as `nbits`.
+pub fn compact_tolerance_bits(target: &Target) -> u8 {
+    let value = U256Primitive::from_little_endian(&target.to_le_bytes());
+    compact_shift(value) as u8
+}
+
+// the mantissa of a compact target has 23 bits, as the 24th is the sign bit
+const MAX_COMPACT_MANTISSA: u32 = 0x7fffff;
+
+// the smallest shift (in whole bytes, as the compact exponent) that fits `value` in the mantissa
+fn compact_shift(value: U256Primitive) -> usize {
+    let mut shift = 0;
+    while (value >> shift) > U256Primitive::from(MAX_COMPACT_MANTISSA) {
+        shift += 8;
+    }
+    shift
 }
 
 /// Converts a `u256` to a [`BlockHash`] type.
-pub fn u256_to_block_hash(v: U256<'static>) -> BlockHash {
-    let hash: [u8; 32] = v.to_vec().try_into().unwrap();
-    let hash = Hash::from_slice(&hash).unwrap();
-    BlockHash::from_raw_hash(hash)
+///
+/// Fails if `v` doesn't hold exactly 32 bytes.
+pub fn u256_to_block_hash(v: U256<'static>) -> Result<BlockHash, TargetError> {
+    WireU256::try_from(&v).map(BlockHash::from)
+}
+
+/// A 256 bit target or hash in wire byte order (little endian, least significant byte first).
+///
+/// This is how [`U256`] fields are laid out in Sv2 messages, how [`Target`] is serialized by
+/// [`Target::to_le_bytes`] and how bitcoin stores hashes internally. It's meant for computations
+/// and comparisons, use [`WireU256::to_display`] to print it.
+///
+/// Serialized as its 32 bytes, in wire byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct WireU256([u8; 32]);
+
+impl WireU256 {
+    pub fn from_wire_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn to_wire_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    pub fn to_display(&self) -> DisplayU256 {
+        let mut bytes = self.0;
+        bytes.reverse();
+        DisplayU256(bytes)
+    }
+}
+
+/// A 256 bit target or hash in display byte order (big endian, most significant byte first).
+///
+/// This is how block explorers and `bitcoind` print hashes and targets, so that leading zeros
+/// show up on the left. Its `Display` implementation prints it as lowercase hex.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DisplayU256([u8; 32]);
+
+impl DisplayU256 {
+    pub fn from_display_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn to_display_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Decodes a big endian hex string of exactly 32 bytes, e.g. a block hash as shown by a
+    /// block explorer.
+    pub fn from_hex(hex: &str) -> Result<Self, HexDecodeError> {
+        hex_to_hash32(hex).map(Self)
+    }
+
+    pub fn to_wire(&self) -> WireU256 {
+        let mut bytes = self.0;
+        bytes.reverse();
+        WireU256(bytes)
+    }
+}
+
+impl fmt::Display for DisplayU256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&bytes_to_hex(&self.0))
+    }
+}
+
+impl fmt::Debug for DisplayU256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DisplayU256({})", self)
+    }
+}
+
+impl From<WireU256> for DisplayU256 {
+    fn from(v: WireU256) -> Self {
+        v.to_display()
+    }
+}
+
+impl From<DisplayU256> for WireU256 {
+    fn from(v: DisplayU256) -> Self {
+        v.to_wire()
+    }
+}
+
+impl From<&Target> for WireU256 {
+    fn from(v: &Target) -> Self {
+        Self(v.to_le_bytes())
+    }
+}
+
+impl From<&Target> for DisplayU256 {
+    fn from(v: &Target) -> Self {
+        Self(v.to_be_bytes())
+    }
+}
+
+impl From<WireU256> for Target {
+    fn from(v: WireU256) -> Self {
+        Target::from_le_bytes(v.0)
+    }
+}
+
+impl TryFrom<&U256<'_>> for WireU256 {
+    type Error = TargetError;
+
+    // A `U256` decoded from the wire or built from an array always holds 32 bytes, one built by
+    // hand out of another number of bytes is rejected.
+    fn try_from(v: &U256<'_>) -> Result<Self, Self::Error> {
+        let inner = v.inner_as_ref();
+        let bytes: [u8; 32] = inner.try_into().map_err(|_| TargetError::InvalidLength {
+            expected: 32,
+            actual: inner.len(),
+        })?;
+        Ok(Self(bytes))
+    }
+}
+
+impl From<WireU256> for U256<'static> {
+    fn from(v: WireU256) -> Self {
+        U256::<'static>::from(v.0)
+    }
+}
+
+impl From<BlockHash> for WireU256 {
+    fn from(v: BlockHash) -> Self {
+        Self(v.to_byte_array())
+    }
+}
+
+impl From<WireU256> for BlockHash {
+    fn from(v: WireU256) -> Self {
+        BlockHash::from_byte_array(v.0)
+    }
 }
 
 // Helper function to format bytes as hex string
@@ -54,13 +220,87 @@ pub fn bytes_to_hex(bytes: &[u8]) -> String {
     s
 }
 
+/// Decodes a hex string into bytes, in the same order they are written.
+///
+/// This is the inverse of [`bytes_to_hex`]. Both lowercase and uppercase digits are accepted, but
+/// no `0x` prefix or whitespace is allowed.
+pub fn hex_to_bytes(hex: &str) -> Result<Vec<u8>, HexDecodeError> {
+    let pairs = hex.as_bytes().chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(HexDecodeError::OddLength(hex.len()));
+    }
+    pairs
+        .enumerate()
+        .map(|(i, pair)| {
+            let high = hex_digit(pair[0], 2 * i)?;
+            let low = hex_digit(pair[1], 2 * i + 1)?;
+            Ok((high << 4) | low)
+        })
+        .collect()
+}
+
+/// Decodes a hex string of exactly 32 bytes into an array, in the same order they are written.
+pub fn hex_to_hash32(hex: &str) -> Result<[u8; 32], HexDecodeError> {
+    let bytes = hex_to_bytes(hex)?;
+    bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| HexDecodeError::InvalidLength {
+            expected: 32,
+            actual: bytes.len(),
+        })
+}
+
+/// Decodes a hex string of exactly 32 bytes into a [`U256`], keeping the bytes in the order they
+/// are written.
+///
+/// The hex string is therefore expected in little endian, which is how a [`U256`] is laid out on
+/// the wire and what [`bytes_to_hex`] produces from it.
+pub fn hex_to_u256(hex: &str) -> Result<U256<'static>, HexDecodeError> {
+    Ok(U256::<'static>::from(hex_to_hash32(hex)?))
+}
+
+/// Copies a slice of exactly 32 bytes into a [`U256`], keeping the bytes in the same order.
+///
+/// Unlike the conversions from arrays, a slice of the wrong length is an error instead of a
+/// panic, e.g. for hashes read out of untrusted input.
+pub fn u256_from_slice(bytes: &[u8]) -> Result<U256<'static>, U256Error> {
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| U256Error::InvalidLength {
+        expected: 32,
+        actual: bytes.len(),
+    })?;
+    Ok(U256::<'static>::from(bytes))
+}
+
+/// Decodes a big endian hex string of exactly 32 bytes into a little endian [`U256`].
+///
+/// Useful for targets and hashes as they are usually displayed (most significant byte first),
+/// e.g. `00000000ffff0000000000000000000000000000000000000000000000000000`.
+pub fn u256_from_hex_be(hex: &str) -> Result<U256<'static>, HexDecodeError> {
+    Ok(DisplayU256::from_hex(hex)?.to_wire().into())
+}
+
+// Decodes a single ASCII hex digit, `index` is only used for error reporting
+fn hex_digit(c: u8, index: usize) -> Result<u8, HexDecodeError> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(HexDecodeError::InvalidCharacter {
+            index,
+            character: c as char,
+        }),
+    }
+}
+
 /// Calculates the mining target threshold for a mining device based on its hashrate (H/s) and
 /// desired share frequency (shares/min).
 ///
-/// Determines the maximum hash value (target), in big endian, that a mining device can produce to
-/// find a valid share. The target is derived from the miner's hashrate and the expected number of
-/// shares per minute, aligning the miner's workload with the upstream's (e.g. pool's) share
-/// frequency requirements.
+/// Determines the maximum hash value (target) that a mining device can produce to find a valid
+/// share. The target is computed in big endian and returned as a little-endian [`U256`], which is
+/// the byte order expected by [`Target`] (see [`Target::from_be_bytes`]). The target is derived
+/// from the miner's hashrate and the expected number of shares per minute, aligning the miner's
+/// workload with the upstream's (e.g. pool's) share frequency requirements.
 ///
 /// Typically used during connection setup to assign a starting target based on the mining device's
 /// reported hashrate and to recalculate during runtime when a mining device's hashrate changes,
@@ -143,9 +383,8 @@ pub fn hash_rate_to_target(
     h_times_s_array[16..].copy_from_slice(&h_times_s.to_be_bytes());
     let numerator = two_to_256_minus_one - U256Primitive::from_big_endian(h_times_s_array.as_ref());
 
-    let mut target = numerator.div(denominator).to_big_endian();
-    target.reverse();
-    Ok(U256::<'static>::from(target))
+    let target = numerator.div(denominator).to_big_endian();
+    Ok(DisplayU256::from_display_bytes(target).to_wire().into())
 }
 
 /// Converts a `u128` to a [`U256`].
@@ -158,7 +397,299 @@ pub fn from_u128_to_u256(input: u128) -> U256Primitive {
     U256Primitive::from_big_endian(be_bytes.as_ref())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HashRateToTargetError {
     DivisionByZero,
     NegativeInput,
 }
+
+impl fmt::Display for HashRateToTargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DivisionByZero => write!(f, "the expected shares per minute must not be zero"),
+            Self::NegativeInput => {
+                write!(
+                    f,
+                    "the hashrate and the expected shares per minute must not be negative"
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HexDecodeError {
+    /// The hex string has an odd number of digits.
+    OddLength(usize),
+    /// The hex string contains a non hex character at the given position.
+    InvalidCharacter { index: usize, character: char },
+    /// The decoded bytes do not have the expected length.
+    InvalidLength { expected: usize, actual: usize },
+}
+
+impl fmt::Display for HexDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OddLength(len) => write!(f, "odd number of hex digits: {}", len),
+            Self::InvalidCharacter { index, character } => {
+                write!(f, "invalid hex digit {:?} at position {}", character, index)
+            }
+            Self::InvalidLength { expected, actual } => {
+                write!(f, "expected {} bytes, got {} bytes", expected, actual)
+            }
+        }
+    }
+}
+
+/// Why [`u256_from_slice`] could not build a [`U256`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum U256Error {
+    /// The slice does not hold the 32 bytes of a `U256`.
+    InvalidLength { expected: usize, actual: usize },
+}
+
+impl fmt::Display for U256Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength { expected, actual } => {
+                write!(f, "a U256 is {} bytes long, got {} bytes", expected, actual)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_rate_to_target_ordering() {
+        // a higher hashrate must always result in a smaller (harder) target
+        let low_hashrate_target: Target = hash_rate_to_target(10.0, 1.0).ok().unwrap().into();
+        let high_hashrate_target: Target = hash_rate_to_target(1_000.0, 1.0).ok().unwrap().into();
+        assert!(high_hashrate_target < low_hashrate_target);
+
+        let expected: Target =
+            u256_from_hex_be("0001179d9861a761ffdadd11c307c4fc04eea3a418f7d687584e4434af158205")
+                .unwrap()
+                .into();
+        assert_eq!(high_hashrate_target, expected);
+
+        // the most permissive max target is never exceeded
+        let max_target = Target::MAX;
+        assert!(low_hashrate_target <= max_target);
+    }
+
+    #[test]
+    fn test_hex_to_bytes_round_trip() {
+        let bytes = hex_to_bytes("00ff10aB").unwrap();
+        assert_eq!(bytes, vec![0x00, 0xff, 0x10, 0xab]);
+        assert_eq!(bytes_to_hex(&bytes), "00ff10ab");
+        assert_eq!(hex_to_bytes("").unwrap(), Vec::<u8>::new());
+
+        let hash = "fbaf6a2823577a5a3a334e20caece4249aaece9093c315e0c367d6bd33be1862";
+        let u256 = hex_to_u256(hash).unwrap();
+        assert_eq!(bytes_to_hex(u256.inner_as_ref()), hash);
+        assert_eq!(hex_to_hash32(hash).unwrap().to_vec(), u256.to_vec());
+    }
+
+    #[test]
+    fn test_hex_to_bytes_errors() {
+        assert_eq!(hex_to_bytes("abc"), Err(HexDecodeError::OddLength(3)));
+        assert_eq!(
+            hex_to_bytes("0x00"),
+            Err(HexDecodeError::InvalidCharacter {
+                index: 1,
+                character: 'x'
+            })
+        );
+        assert_eq!(
+            hex_to_hash32("00ff"),
+            Err(HexDecodeError::InvalidLength {
+                expected: 32,
+                actual: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_u256_from_slice() {
+        let hash =
+            hex_to_hash32("fbaf6a2823577a5a3a334e20caece4249aaece9093c315e0c367d6bd33be1862")
+                .unwrap();
+        let u256 = u256_from_slice(&hash).unwrap();
+        assert_eq!(u256.inner_as_ref(), &hash[..]);
+
+        let mut long = hash.to_vec();
+        long.push(0);
+        assert_eq!(
+            u256_from_slice(&long).unwrap_err(),
+            U256Error::InvalidLength {
+                expected: 32,
+                actual: 33
+            }
+        );
+        let error = u256_from_slice(&hash[..31]).unwrap_err();
+        assert_eq!(
+            error,
+            U256Error::InvalidLength {
+                expected: 32,
+                actual: 31
+            }
+        );
+        assert_eq!(error.to_string(), "a U256 is 32 bytes long, got 31 bytes");
+
+        assert_eq!(
+            hex_to_hash32("00ff").unwrap_err().to_string(),
+            "expected 32 bytes, got 2 bytes"
+        );
+        assert_eq!(
+            hash_rate_to_target(1.0, 0.0).unwrap_err(),
+            HashRateToTargetError::DivisionByZero
+        );
+    }
+
+    #[test]
+    fn test_u256_from_hex_be() {
+        // genesis block target, see `target_to_difficulty`
+        let max_target =
+            u256_from_hex_be("00000000ffff0000000000000000000000000000000000000000000000000000")
+                .unwrap();
+        assert_eq!(max_target.inner_as_ref()[26..28], [0xff, 0xff]);
+        assert_eq!(target_to_difficulty(max_target.into()), 1.0);
+    }
+
+    #[test]
+    fn test_canonicalize_target() {
+        // already representable
+        let target = Target::from_be_bytes(
+            hex_to_hash32("00000000ffff0000000000000000000000000000000000000000000000000000")
+                .unwrap(),
+        );
+        assert_eq!(canonicalize_target(target.clone()), target);
+
+        // rounded down
+        let target = Target::from_be_bytes(
+            hex_to_hash32("00000000ffff0000000000000000000000000000000000000000000000000001")
+                .unwrap(),
+        );
+        let expected = Target::from_be_bytes(
+            hex_to_hash32("00000000ffff0000000000000000000000000000000000000000000000000000")
+                .unwrap(),
+        );
+        assert_eq!(canonicalize_target(target), expected);
+
+        // rounded up, with the mantissa overflowing into the exponent
+        let target = Target::from_be_bytes(
+            hex_to_hash32("000000007fffff80000000000000000000000000000000000000000000000000")
+                .unwrap(),
+        );
+        let expected = Target::from_be_bytes(
+            hex_to_hash32("0000000080000000000000000000000000000000000000000000000000000000")
+                .unwrap(),
+        );
+        assert_eq!(canonicalize_target(target), expected);
+
+        // can't round up past the max target
+        let max_target = Target::MAX;
+        let expected = Target::from_be_bytes(
+            hex_to_hash32("ffff000000000000000000000000000000000000000000000000000000000000")
+                .unwrap(),
+        );
+        assert_eq!(canonicalize_target(max_target), expected);
+
+        // small targets are always representable
+        let small = Target::new(0x7fffff, 0);
+        assert_eq!(canonicalize_target(small.clone()), small);
+    }
+
+    #[test]
+    fn test_compact_tolerance_bits() {
+        let target: Target = hash_rate_to_target(1_000.0, 1.0).ok().unwrap().into();
+        let tolerance_bits = compact_tolerance_bits(&target);
+        // the target for 1000 H/s has 241 significant bits, so only its 3 most significant bytes
+        // fit in the mantissa
+        assert_eq!(tolerance_bits, 224);
+
+        // targets that only differ below the compact precision are approximately equal
+        let canonical = canonicalize_target(target.clone());
+        assert_ne!(canonical, target);
+        assert!(canonical.approx_eq(&target, tolerance_bits));
+
+        // a slightly different hashrate produces a target beyond the tolerance
+        let other: Target = hash_rate_to_target(1_001.0, 1.0).ok().unwrap().into();
+        assert!(!other.approx_eq(&target, tolerance_bits));
+    }
+
+    #[test]
+    fn test_wire_display_genesis_block_hash() {
+        use bitcoin::{blockdata::constants::genesis_block, Network};
+
+        let genesis_hash = genesis_block(Network::Bitcoin).block_hash();
+        let display_hex = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+        let display = DisplayU256::from_hex(display_hex).unwrap();
+        assert_eq!(display.to_string(), display_hex);
+        assert_eq!(display.to_string(), genesis_hash.to_string());
+
+        // on the wire the hash starts with its least significant byte
+        let wire = WireU256::from(genesis_hash);
+        assert_eq!(wire.to_wire_bytes()[0], 0x6f);
+        assert_eq!(wire.to_wire_bytes()[31], 0x00);
+        assert_eq!(wire.to_display(), display);
+        assert_eq!(display.to_wire(), wire);
+        assert_eq!(BlockHash::from(wire), genesis_hash);
+
+        let u256: U256<'static> = wire.into();
+        assert_eq!(WireU256::try_from(&u256).unwrap(), wire);
+        assert_eq!(u256_to_block_hash(u256.clone()).unwrap(), genesis_hash);
+        assert_eq!(u256_from_hex_be(display_hex).unwrap(), u256);
+    }
+
+    #[test]
+    fn test_wire_u256_wrong_length() {
+        // a U256 built by hand out of the wrong number of bytes is rejected, not padded or
+        // truncated
+        let short = U256::Owned(vec![0xab; 31]);
+        assert_eq!(
+            WireU256::try_from(&short).unwrap_err(),
+            TargetError::InvalidLength {
+                expected: 32,
+                actual: 31
+            }
+        );
+        let long = U256::Owned(vec![0xab; 33]);
+        assert_eq!(
+            WireU256::try_from(&long).unwrap_err(),
+            TargetError::InvalidLength {
+                expected: 32,
+                actual: 33
+            }
+        );
+        assert_eq!(
+            u256_to_block_hash(long).unwrap_err(),
+            TargetError::InvalidLength {
+                expected: 32,
+                actual: 33
+            }
+        );
+    }
+
+    #[test]
+    fn test_wire_display_target() {
+        // difficulty 1 target
+        let display_hex = "00000000ffff0000000000000000000000000000000000000000000000000000";
+        let target: Target = WireU256::from(DisplayU256::from_hex(display_hex).unwrap()).into();
+
+        assert_eq!(DisplayU256::from(&target).to_string(), display_hex);
+        assert_eq!(
+            WireU256::from(&target).to_wire_bytes(),
+            target.to_le_bytes()
+        );
+        assert_eq!(
+            DisplayU256::from(&target).to_display_bytes(),
+            target.to_be_bytes()
+        );
+        assert_eq!(target_to_difficulty(target), 1.0);
+    }
+}
@@ -1,5 +1,8 @@
-use bitcoin::{consensus::Decodable, transaction::TxOut};
-use std::io::Cursor;
+use crate::{chain_tip::ChainTip, collections::HashMap};
+use alloc::vec::Vec;
+use bitcoin::{consensus::Decodable, io::Cursor, transaction::TxOut};
+use mining_sv2::TargetError;
+use template_distribution_sv2::{NewTemplate, SetNewPrevHash};
 
 /// Deserializes a vector of serialized outputs into a vector of TxOuts.
 ///
@@ -7,17 +10,294 @@ use std::io::Cursor;
 ///
 /// Not suitable for deserializing outputs from a SetCustomMiningJob message or
 /// AllocateMiningJobToken.Success.
+///
+/// The whole of `serialized_outputs` is parsed, and must hold exactly `coinbase_tx_outputs_count`
+/// outputs. Trusting either of them alone would yield a coinbase other than the one the Template
+/// Provider expects, and hence invalid blocks.
 pub fn deserialize_template_outputs(
     serialized_outputs: Vec<u8>,
     coinbase_tx_outputs_count: u32,
-) -> Result<Vec<TxOut>, TemplateOutputsDeserializationError> {
+) -> Result<Vec<TxOut>, TemplateValidationError> {
+    let len = serialized_outputs.len() as u64;
     let mut cursor = Cursor::new(serialized_outputs);
 
-    (0..coinbase_tx_outputs_count)
-        .map(|_| {
-            TxOut::consensus_decode(&mut cursor).map_err(|_| TemplateOutputsDeserializationError)
-        })
-        .collect()
+    let mut outputs = vec![];
+    while cursor.position() < len {
+        let output = TxOut::consensus_decode(&mut cursor)
+            .map_err(|_| TemplateValidationError::MalformedOutputs)?;
+        outputs.push(output);
+    }
+
+    if outputs.len() != coinbase_tx_outputs_count as usize {
+        return Err(TemplateValidationError::OutputCountMismatch {
+            declared: coinbase_tx_outputs_count,
+            parsed: outputs.len() as u32,
+        });
+    }
+    Ok(outputs)
+}
+
+/// The ways the coinbase outputs of a `NewTemplate` message can be invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateValidationError {
+    /// `coinbase_tx_outputs` doesn't hold `coinbase_tx_outputs_count` outputs.
+    OutputCountMismatch { declared: u32, parsed: u32 },
+    /// `coinbase_tx_outputs` is not a sequence of serialized outputs, e.g. it's truncated.
+    MalformedOutputs,
+}
+
+/// The outcome of pairing a `SetNewPrevHash` with the future template it references.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PairingOutcome {
+    /// The future template is known, both can be fanned out to the channels.
+    Paired {
+        template: NewTemplate<'static>,
+        set_new_prev_hash: SetNewPrevHash<'static>,
+    },
+    /// The referenced template was not received (yet).
+    ///
+    /// The chain tip is updated anyway, and the pairing is completed by
+    /// [`TemplateState::on_new_template`] if the template arrives later.
+    UnknownTemplate {
+        set_new_prev_hash: SetNewPrevHash<'static>,
+    },
+}
+
+/// Tracks the templates and chain tips received from a Template Provider, pairing every
+/// `SetNewPrevHash` with the future template it activates.
+///
+/// Meant to sit between the Template Provider connection and the channels, so that the channels
+/// only receive already paired messages.
+#[derive(Debug, Default)]
+pub struct TemplateState {
+    // future templates received since the last chain tip, indexed by `template_id`
+    future_templates: HashMap<u64, NewTemplate<'static>>,
+    latest_future_template_id: Option<u64>,
+    // a `SetNewPrevHash` referencing a template not received yet
+    pending_set_new_prev_hash: Option<SetNewPrevHash<'static>>,
+    current_tip: Option<ChainTip>,
+}
+
+impl TemplateState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a `NewTemplate` message.
+    ///
+    /// Returns [`PairingOutcome::Paired`] if `template` is the future template a previously
+    /// received `SetNewPrevHash` was waiting for.
+    ///
+    /// Non-future templates are built on the current tip, so there's nothing to track for them.
+    pub fn on_new_template(&mut self, template: NewTemplate<'static>) -> Option<PairingOutcome> {
+        if !template.future_template {
+            return None;
+        }
+
+        let is_pending = self
+            .pending_set_new_prev_hash
+            .as_ref()
+            .is_some_and(|pending| pending.template_id == template.template_id);
+        if is_pending {
+            let set_new_prev_hash = self
+                .pending_set_new_prev_hash
+                .take()
+                .expect("pending SetNewPrevHash must exist");
+            self.clear_future_templates();
+            return Some(PairingOutcome::Paired {
+                template,
+                set_new_prev_hash,
+            });
+        }
+
+        self.latest_future_template_id = Some(template.template_id);
+        self.future_templates.insert(template.template_id, template);
+        None
+    }
+
+    /// Records a `SetNewPrevHash` message, updating the current tip and pairing it with the
+    /// future template it references.
+    ///
+    /// Once paired, all the future templates received so far are discarded, since they were
+    /// built on the previous tip.
+    ///
+    /// Fails, leaving the state untouched, if `prev_hash` doesn't hold 32 bytes.
+    pub fn on_set_new_prev_hash(
+        &mut self,
+        set_new_prev_hash: SetNewPrevHash<'static>,
+    ) -> Result<PairingOutcome, TargetError> {
+        self.current_tip = Some(ChainTip::new(
+            set_new_prev_hash.prev_hash.clone(),
+            set_new_prev_hash.n_bits,
+            set_new_prev_hash.header_timestamp,
+        )?);
+
+        let outcome = match self.future_templates.remove(&set_new_prev_hash.template_id) {
+            Some(template) => {
+                self.pending_set_new_prev_hash = None;
+                self.clear_future_templates();
+                PairingOutcome::Paired {
+                    template,
+                    set_new_prev_hash,
+                }
+            }
+            None => {
+                self.pending_set_new_prev_hash = Some(set_new_prev_hash.clone());
+                PairingOutcome::UnknownTemplate { set_new_prev_hash }
+            }
+        };
+        Ok(outcome)
+    }
+
+    pub fn current_tip(&self) -> Option<&ChainTip> {
+        self.current_tip.as_ref()
+    }
+
+    /// Returns the most recent future template received since the last chain tip.
+    pub fn latest_future_template(&self) -> Option<&NewTemplate<'static>> {
+        self.latest_future_template_id
+            .and_then(|template_id| self.future_templates.get(&template_id))
+    }
+
+    fn clear_future_templates(&mut self) {
+        self.future_templates.clear();
+        self.latest_future_template_id = None;
+    }
 }
 
-pub struct TemplateOutputsDeserializationError;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn new_template(template_id: u64, future_template: bool) -> NewTemplate<'static> {
+        NewTemplate {
+            template_id,
+            future_template,
+            version: 536870912,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: 4294967294,
+            coinbase_tx_value_remaining: 5000000000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 158,
+            merkle_path: vec![].try_into().unwrap(),
+        }
+    }
+
+    fn set_new_prev_hash(template_id: u64, prev_hash: u8) -> SetNewPrevHash<'static> {
+        SetNewPrevHash {
+            template_id,
+            prev_hash: [prev_hash; 32].into(),
+            header_timestamp: 1745596910,
+            n_bits: 545259519,
+            target: [0xff; 32].into(),
+        }
+    }
+
+    #[test]
+    fn test_pairing() {
+        let mut state = TemplateState::new();
+        assert!(state.current_tip().is_none());
+
+        assert_eq!(state.on_new_template(new_template(1, true)), None);
+        // a newer future template replaces the previous one before activation
+        assert_eq!(state.on_new_template(new_template(2, true)), None);
+        assert_eq!(state.latest_future_template(), Some(&new_template(2, true)));
+        // non-future templates are not tracked
+        assert_eq!(state.on_new_template(new_template(3, false)), None);
+        assert_eq!(state.latest_future_template(), Some(&new_template(2, true)));
+
+        assert_eq!(
+            state.on_set_new_prev_hash(set_new_prev_hash(2, 1)),
+            Ok(PairingOutcome::Paired {
+                template: new_template(2, true),
+                set_new_prev_hash: set_new_prev_hash(2, 1),
+            })
+        );
+        assert_eq!(state.current_tip().unwrap().prev_hash(), [1; 32].into());
+        // the future templates built on the previous tip are gone
+        assert_eq!(state.latest_future_template(), None);
+        assert_eq!(
+            state.on_set_new_prev_hash(set_new_prev_hash(1, 2)),
+            Ok(PairingOutcome::UnknownTemplate {
+                set_new_prev_hash: set_new_prev_hash(1, 2),
+            })
+        );
+    }
+
+    #[test]
+    fn test_pairing_out_of_order() {
+        let mut state = TemplateState::new();
+
+        // the prev hash arrives before the template it references
+        assert_eq!(
+            state.on_set_new_prev_hash(set_new_prev_hash(1, 1)),
+            Ok(PairingOutcome::UnknownTemplate {
+                set_new_prev_hash: set_new_prev_hash(1, 1),
+            })
+        );
+        assert_eq!(state.current_tip().unwrap().prev_hash(), [1; 32].into());
+
+        // an unrelated template doesn't complete the pairing
+        assert_eq!(state.on_new_template(new_template(2, true)), None);
+        assert_eq!(
+            state.on_new_template(new_template(1, true)),
+            Some(PairingOutcome::Paired {
+                template: new_template(1, true),
+                set_new_prev_hash: set_new_prev_hash(1, 1),
+            })
+        );
+        assert_eq!(state.latest_future_template(), None);
+
+        // the pairing is only completed once
+        assert_eq!(state.on_new_template(new_template(1, true)), None);
+    }
+
+    #[test]
+    fn test_deserialize_template_outputs() {
+        // a single OP_RETURN output (witness commitment)
+        let output = vec![
+            0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
+            222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
+            139, 235, 216, 54, 151, 78, 140, 249,
+        ];
+        let two_outputs = [output.clone(), output.clone()].concat();
+
+        assert_eq!(
+            deserialize_template_outputs(output.clone(), 1)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            deserialize_template_outputs(two_outputs.clone(), 2)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(deserialize_template_outputs(vec![], 0).unwrap().is_empty());
+
+        assert_eq!(
+            deserialize_template_outputs(output.clone(), 2).unwrap_err(),
+            TemplateValidationError::OutputCountMismatch {
+                declared: 2,
+                parsed: 1
+            }
+        );
+        // outputs beyond the declared count are not silently dropped
+        assert_eq!(
+            deserialize_template_outputs(two_outputs.clone(), 1).unwrap_err(),
+            TemplateValidationError::OutputCountMismatch {
+                declared: 1,
+                parsed: 2
+            }
+        );
+        assert_eq!(
+            deserialize_template_outputs(two_outputs[..two_outputs.len() - 1].to_vec(), 2)
+                .unwrap_err(),
+            TemplateValidationError::MalformedOutputs
+        );
+    }
+}
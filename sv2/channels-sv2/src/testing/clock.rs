@@ -0,0 +1,42 @@
+//! A [`Clock`] moved forward by hand, so that time-dependent behaviors can be tested without
+//! waiting.
+use crate::clock::Clock;
+use alloc::sync::Arc;
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// A [`Clock`] standing still until it's moved.
+///
+/// Clones share the same time, so the test keeps a clone of the clock it set on a channel.
+#[derive(Debug, Clone, Default)]
+pub struct TestClock {
+    now_millis: Arc<AtomicU64>,
+}
+
+impl TestClock {
+    /// Returns a clock at `now_millis` milliseconds since the Unix epoch.
+    pub fn new(now_millis: u64) -> Self {
+        Self {
+            now_millis: Arc::new(AtomicU64::new(now_millis)),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.now_millis
+            .fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Sets the clock to `now_millis` milliseconds since the Unix epoch, possibly backwards.
+    pub fn set(&self, now_millis: u64) {
+        self.now_millis.store(now_millis, Ordering::Relaxed);
+    }
+}
+
+impl Clock for TestClock {
+    fn now_millis(&self) -> u64 {
+        self.now_millis.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,459 @@
+//! Test doubles for the extension points of server channels, for crates building a Mining Server
+//! on top of this one.
+//!
+//! Each double is moved into the channel, and hands out a handle to what it recorded.
+//!
+//! ```
+//! use channels_sv2::{
+//!     prelude::*,
+//!     testing::{
+//!         doubles::{JobStoreCall, MockJobStore, RecordingShareObserver},
+//!         fixture,
+//!     },
+//! };
+//!
+//! // a tiny server handler: validates a share, returning the `SubmitShares.Error` code on
+//! // rejection
+//! fn handle_share(
+//!     channel: &mut StandardChannel,
+//!     share: SubmitSharesStandard,
+//! ) -> Option<&'static str> {
+//!     channel.validate_share(share).err().map(|e| e.error_code())
+//! }
+//!
+//! let (job_store, calls) = MockJobStore::<StandardJob>::new();
+//! let mut channel = StandardChannel::from_config(
+//!     StandardChannelConfig::default()
+//!         .channel_id(1)
+//!         .user_identity("user_identity")
+//!         .extranonce_prefix(fixture::extranonce_prefix())
+//!         .requested_max_target(Target::MAX)
+//!         .nominal_hashrate(1.0)
+//!         .expected_share_per_minute(1.0),
+//!     Box::new(job_store),
+//! )
+//! .unwrap();
+//! let (observer, shares) = RecordingShareObserver::new();
+//! channel.set_share_policy(Box::new(observer));
+//!
+//! channel
+//!     .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+//!     .unwrap();
+//! channel
+//!     .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+//!     .unwrap();
+//! assert_eq!(
+//!     calls.take(),
+//!     vec![
+//!         JobStoreCall::AddFutureJob { template_id: 1 },
+//!         JobStoreCall::ActivateFutureJob {
+//!             template_id: 1,
+//!             prev_hash_header_timestamp: fixture::NTIME,
+//!         },
+//!         // the activated job is given the previous block hash of the new chain tip
+//!         JobStoreCall::SetActiveJob { job_id: 1 },
+//!     ]
+//! );
+//!
+//! // no job 2 was ever sent
+//! let share = SubmitSharesStandard {
+//!     job_id: 2,
+//!     ..fixture::submit_shares_standard(1, 1, 0)
+//! };
+//! assert_eq!(handle_share(&mut channel, share), Some("invalid-job-id"));
+//! // the share never reached the policy checks
+//! assert!(shares.validated().is_empty());
+//!
+//! handle_share(&mut channel, fixture::submit_shares_standard(1, 1, 0));
+//! assert_eq!(shares.validated(), vec![(1, 0)]);
+//! ```
+use crate::collections::HashMap;
+use crate::server::{
+    block_found::BlockFoundEvent,
+    jobs::{
+        compact::CompactJob,
+        error::JobStoreError,
+        job_store::{DefaultJobStore, JobStore, StaleRetention},
+        standard::StandardJob,
+        Job,
+    },
+    share_policy::{ShareCheck, SharePolicy, ShareRejectedEvent},
+    share_validation::ShareValidationError,
+};
+use mining_sv2::{SubmitSharesStandard, Target};
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+/// A shared, append-only record, cloned between a double and the test holding its handle.
+#[derive(Debug)]
+pub struct Recorded<T>(Arc<Mutex<Vec<T>>>);
+
+impl<T> Clone for Recorded<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Default for Recorded<T> {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(Vec::new())))
+    }
+}
+
+impl<T: Clone> Recorded<T> {
+    fn push(&self, item: T) {
+        self.0.lock().unwrap().push(item);
+    }
+
+    /// Returns what was recorded so far.
+    pub fn get(&self) -> Vec<T> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Returns what was recorded so far, and clears the record.
+    pub fn take(&self) -> Vec<T> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+/// A call made by a channel to its [`MockJobStore`], getters excluded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobStoreCall {
+    AddFutureJob {
+        template_id: u64,
+    },
+    AddActiveJob {
+        job_id: u32,
+    },
+    ActivateFutureJob {
+        template_id: u64,
+        prev_hash_header_timestamp: u32,
+    },
+    SetActiveJob {
+        job_id: u32,
+    },
+}
+
+/// A [`JobStore`] recording the calls it receives.
+///
+/// It behaves as a [`DefaultJobStore`], unless told otherwise via
+/// [`MockJobStore::refuse_activations`].
+#[derive(Debug)]
+pub struct MockJobStore<T: Job + Clone> {
+    inner: DefaultJobStore<T>,
+    calls: Recorded<JobStoreCall>,
+    refused_activations: usize,
+}
+
+impl<T: Job + Clone> MockJobStore<T> {
+    /// Returns the store, and the handle to the calls it receives.
+    pub fn new() -> (Self, Recorded<JobStoreCall>) {
+        Self::with_stale_retention(StaleRetention::default())
+    }
+
+    pub fn with_stale_retention(stale_retention: StaleRetention) -> (Self, Recorded<JobStoreCall>) {
+        let calls = Recorded::default();
+        let store = Self {
+            inner: DefaultJobStore::with_stale_retention(stale_retention),
+            calls: calls.clone(),
+            refused_activations: 0,
+        };
+        (store, calls)
+    }
+
+    /// Makes the next `count` calls to `activate_future_job` fail, leaving the store unchanged.
+    pub fn refuse_activations(mut self, count: usize) -> Self {
+        self.refused_activations = count;
+        self
+    }
+}
+
+impl<T: Job + Clone + Debug> JobStore<T> for MockJobStore<T> {
+    fn add_future_job(&mut self, template_id: u64, job: T) -> Result<u32, JobStoreError> {
+        self.calls.push(JobStoreCall::AddFutureJob { template_id });
+        self.inner.add_future_job(template_id, job)
+    }
+
+    fn add_active_job(&mut self, job: T) -> Result<(), JobStoreError> {
+        self.calls.push(JobStoreCall::AddActiveJob {
+            job_id: job.get_job_id(),
+        });
+        self.inner.add_active_job(job)
+    }
+
+    fn activate_future_job(&mut self, template_id: u64, prev_hash_header_timestamp: u32) -> bool {
+        self.calls.push(JobStoreCall::ActivateFutureJob {
+            template_id,
+            prev_hash_header_timestamp,
+        });
+        if self.refused_activations > 0 {
+            self.refused_activations -= 1;
+            return false;
+        }
+        self.inner
+            .activate_future_job(template_id, prev_hash_header_timestamp)
+    }
+
+    fn set_active_job(&mut self, job: T) {
+        self.calls.push(JobStoreCall::SetActiveJob {
+            job_id: job.get_job_id(),
+        });
+        self.inner.set_active_job(job)
+    }
+
+    fn get_future_template_to_job_id(&self) -> &HashMap<u64, u32> {
+        self.inner.get_future_template_to_job_id()
+    }
+
+    fn get_active_job(&self) -> Option<&T> {
+        self.inner.get_active_job()
+    }
+
+    fn get_future_jobs(&self) -> &HashMap<u32, T> {
+        self.inner.get_future_jobs()
+    }
+
+    fn get_past_jobs(&self) -> &HashMap<u32, T> {
+        self.inner.get_past_jobs()
+    }
+
+    fn get_stale_jobs(&self) -> &HashMap<u32, T> {
+        self.inner.get_stale_jobs()
+    }
+
+    fn get_compact_past_job(&self, job_id: u32) -> Option<&CompactJob> {
+        self.inner.get_compact_past_job(job_id)
+    }
+
+    fn get_compact_stale_job(&self, job_id: u32) -> Option<&CompactJob> {
+        self.inner.get_compact_stale_job(job_id)
+    }
+
+    fn get_stale_retention(&self) -> StaleRetention {
+        self.inner.get_stale_retention()
+    }
+}
+
+/// An accepted share, as recorded by a [`RecordingShareObserver`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedShare {
+    pub channel_id: u32,
+    pub user_identity: String,
+    pub job_id: u32,
+    pub sequence_number: u32,
+    pub difficulty: f64,
+    pub block_found: bool,
+}
+
+impl From<&ShareCheck<'_>> for RecordedShare {
+    fn from(check: &ShareCheck<'_>) -> Self {
+        Self {
+            channel_id: check.channel_id,
+            user_identity: check.user_identity.to_string(),
+            job_id: check.job_id,
+            sequence_number: check.sequence_number,
+            difficulty: check.difficulty,
+            block_found: check.block_found,
+        }
+    }
+}
+
+/// A rejected share, as recorded by a [`RecordingShareObserver`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedRejection {
+    pub channel_id: u32,
+    pub user_identity: String,
+    pub job_id: u32,
+    pub sequence_number: u32,
+    pub nonce: u32,
+    pub error: ShareValidationError,
+    pub target: Target,
+    pub timestamp: Option<u64>,
+}
+
+impl From<&ShareRejectedEvent<'_>> for RecordedRejection {
+    fn from(event: &ShareRejectedEvent<'_>) -> Self {
+        Self {
+            channel_id: event.channel_id,
+            user_identity: event.user_identity.to_string(),
+            job_id: event.share.job_id,
+            sequence_number: event.share.sequence_number,
+            nonce: event.share.nonce,
+            error: event.error.clone(),
+            target: event.target.clone(),
+            timestamp: event.timestamp,
+        }
+    }
+}
+
+/// A [`SharePolicy`] accepting every share, and recording the shares it sees.
+#[derive(Debug)]
+pub struct RecordingShareObserver {
+    shares: RecordedShares,
+}
+
+/// The handle to the shares seen by a [`RecordingShareObserver`].
+#[derive(Debug, Clone, Default)]
+pub struct RecordedShares {
+    validated: Recorded<(u32, u32)>,
+    accepted: Recorded<RecordedShare>,
+    blocks_found: Recorded<BlockFoundEvent>,
+    rejected: Recorded<RecordedRejection>,
+}
+
+impl RecordedShares {
+    /// The `(job_id, sequence_number)` of the shares that reached the policy checks, i.e. the
+    /// shares for a job the channel can validate.
+    pub fn validated(&self) -> Vec<(u32, u32)> {
+        self.validated.get()
+    }
+
+    /// The accepted shares, including block solutions.
+    pub fn accepted(&self) -> Vec<RecordedShare> {
+        self.accepted.get()
+    }
+
+    /// The events of the shares that found a block.
+    pub fn blocks_found(&self) -> Vec<BlockFoundEvent> {
+        self.blocks_found.get()
+    }
+
+    /// The rejected shares, whatever the check that rejected them.
+    pub fn rejected(&self) -> Vec<RecordedRejection> {
+        self.rejected.get()
+    }
+}
+
+impl RecordingShareObserver {
+    /// Returns the observer, and the handle to the shares it sees.
+    pub fn new() -> (Self, RecordedShares) {
+        let shares = RecordedShares::default();
+        (
+            Self {
+                shares: shares.clone(),
+            },
+            shares,
+        )
+    }
+}
+
+impl SharePolicy for RecordingShareObserver {
+    fn pre_validate(
+        &mut self,
+        share: &SubmitSharesStandard,
+        _job: &StandardJob,
+    ) -> Result<(), ShareValidationError> {
+        self.shares
+            .validated
+            .push((share.job_id, share.sequence_number));
+        Ok(())
+    }
+
+    fn pre_validate_compact(
+        &mut self,
+        share: &SubmitSharesStandard,
+        _job: &CompactJob,
+    ) -> Result<(), ShareValidationError> {
+        self.shares
+            .validated
+            .push((share.job_id, share.sequence_number));
+        Ok(())
+    }
+
+    fn post_accept(&mut self, check: &ShareCheck) {
+        self.shares.accepted.push(check.into());
+    }
+
+    fn on_block_found(&mut self, event: &BlockFoundEvent) {
+        self.shares.blocks_found.push(event.clone());
+    }
+
+    fn on_share_rejected(&mut self, event: &ShareRejectedEvent) {
+        self.shares.rejected.push(event.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        server::{error::StandardChannelError, standard::StandardChannel},
+        testing::fixture,
+    };
+    use template_distribution_sv2::NewTemplate;
+
+    fn channel(job_store: MockJobStore<StandardJob<'static>>) -> StandardChannel<'static> {
+        StandardChannel::from_config(fixture::standard_channel_config(), Box::new(job_store))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_refused_activation() {
+        let (job_store, calls) = MockJobStore::new();
+        let mut channel = channel(job_store.refuse_activations(1));
+        let (observer, shares) = RecordingShareObserver::new();
+        channel.set_share_policy(Box::new(observer));
+
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        // the channel stays on its chain tip, without any job
+        assert!(matches!(
+            channel.on_set_new_prev_hash(fixture::set_new_prev_hash(1)),
+            Err(StandardChannelError::TemplateIdNotFound)
+        ));
+        assert_eq!(calls.get().len(), 2);
+        assert!(channel.get_active_job().is_none());
+        assert!(channel.get_chain_tip().is_none());
+
+        assert!(channel
+            .validate_share(fixture::submit_shares_standard(1, 1, 0))
+            .is_err());
+        assert!(shares.validated().is_empty());
+        assert!(shares.accepted().is_empty());
+    }
+
+    #[test]
+    fn test_recorded_shares() {
+        let (job_store, calls) = MockJobStore::new();
+        let mut channel = channel(job_store);
+        let (observer, shares) = RecordingShareObserver::new();
+        channel.set_share_policy(Box::new(observer));
+
+        channel
+            .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+            .unwrap();
+        channel
+            .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+            .unwrap();
+        calls.take();
+        let template = NewTemplate {
+            template_id: 2,
+            ..fixture::template(false)
+        };
+        channel
+            .on_new_template(template, fixture::coinbase_reward_outputs())
+            .unwrap();
+        assert_eq!(calls.take(), vec![JobStoreCall::AddActiveJob { job_id: 2 }]);
+
+        // loop until a share is accepted
+        let mut nonce = 0;
+        loop {
+            let share = SubmitSharesStandard {
+                sequence_number: nonce,
+                ..fixture::submit_shares_standard(1, 2, nonce)
+            };
+            if channel.validate_share(share).is_ok() {
+                break;
+            }
+            nonce += 1;
+        }
+        assert_eq!(shares.validated().len() as u32, nonce + 1);
+        let accepted = shares.accepted();
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].user_identity, "user_identity");
+        assert_eq!(accepted[0].sequence_number, nonce);
+    }
+}
@@ -0,0 +1,174 @@
+//! Canonical test vectors, collected from a sane message flow between a Template Provider and a
+//! Pool.
+//!
+//! [`template`], [`coinbase_reward_outputs`] and [`extranonce_prefix`] yield jobs with the merkle
+//! root [`MERKLE_ROOT`] on factories created via
+//! [`JobFactory::deterministic`](crate::server::jobs::factory::JobFactory::deterministic).
+//!
+//! [`standard_channel`] and [`extended_channel`] open the channels most tests start from.
+use crate::{
+    chain_tip::ChainTip,
+    server::{
+        extended::ExtendedChannel,
+        jobs::{extended::ExtendedJob, job_store::DefaultJobStore, standard::StandardJob},
+        standard::{StandardChannel, StandardChannelConfig},
+    },
+};
+use alloc::{boxed::Box, vec::Vec};
+use binary_sv2::U256;
+use bitcoin::{transaction::TxOut, Amount, ScriptBuf};
+use core::convert::TryInto;
+use mining_sv2::{SubmitSharesStandard, Target};
+use template_distribution_sv2::{NewTemplate, SetNewPrevHash};
+
+/// The value of the coinbase of [`template`], all of it paid by [`coinbase_reward_outputs`].
+pub const SATS_AVAILABLE_IN_TEMPLATE: u64 = 5000000000;
+
+/// The `header_timestamp` of [`set_new_prev_hash`].
+pub const NTIME: u32 = 1747092633;
+
+/// The `n_bits` of [`set_new_prev_hash`].
+pub const N_BITS: u32 = 503543726;
+
+/// The merkle root of the job created out of the fixtures, for [`extranonce_prefix`].
+pub const MERKLE_ROOT: [u8; 32] = [
+    189, 200, 25, 246, 119, 73, 34, 42, 209, 112, 237, 50, 169, 71, 163, 192, 24, 84, 56, 86, 147,
+    71, 243, 44, 18, 107, 167, 169, 169, 66, 186, 98,
+];
+
+/// A 32 bytes extranonce prefix.
+pub fn extranonce_prefix() -> Vec<u8> {
+    [
+        83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    ]
+    .to_vec()
+}
+
+/// The config of [`standard_channel`]: channel id 1, the [`extranonce_prefix`], no max target, a
+/// nominal hashrate of 1 H/s and one share per minute.
+///
+/// Other settings are chained on top, e.g. `standard_channel_config().obfuscate_job_ids(true)`.
+pub fn standard_channel_config() -> StandardChannelConfig {
+    StandardChannelConfig::default()
+        .channel_id(1)
+        .user_identity("user_identity")
+        .extranonce_prefix(extranonce_prefix())
+        .requested_max_target(Target::MAX)
+        .nominal_hashrate(1.0)
+        .expected_share_per_minute(1.0)
+}
+
+/// A [`StandardChannel`] opened with [`standard_channel_config`] and a [`DefaultJobStore`].
+pub fn standard_channel() -> StandardChannel<'static> {
+    StandardChannel::from_config(
+        standard_channel_config(),
+        Box::new(DefaultJobStore::<StandardJob>::new()),
+    )
+    .unwrap()
+}
+
+/// An [`ExtendedChannel`] with id 1, version rolling allowed, no max target, a nominal hashrate of
+/// 1 H/s, one share per minute and a batch of 100 shares per acknowledgement, over a
+/// [`DefaultJobStore`].
+///
+/// Panics if `extranonce_prefix` doesn't leave `rollable_extranonce_size` bytes to roll.
+pub fn extended_channel(
+    extranonce_prefix: Vec<u8>,
+    rollable_extranonce_size: u16,
+) -> ExtendedChannel<'static> {
+    ExtendedChannel::new(
+        1,
+        "user_identity".into(),
+        extranonce_prefix,
+        Target::MAX,
+        1.0,
+        true,
+        rollable_extranonce_size,
+        100,
+        1.0,
+        Box::new(DefaultJobStore::<ExtendedJob>::new()),
+    )
+    .unwrap()
+}
+
+/// A template with id 1 and a single output, the segwit commitment.
+pub fn template(future_template: bool) -> NewTemplate<'static> {
+    NewTemplate {
+        template_id: 1,
+        future_template,
+        version: 536870912,
+        coinbase_tx_version: 2,
+        coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
+        coinbase_tx_input_sequence: 4294967294,
+        coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
+        coinbase_tx_outputs_count: 1,
+        coinbase_tx_outputs: vec![
+            0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
+            222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
+            139, 235, 216, 54, 151, 78, 140, 249,
+        ]
+        .try_into()
+        .unwrap(),
+        coinbase_tx_locktime: 158,
+        merkle_path: vec![].try_into().unwrap(),
+    }
+}
+
+/// A single P2WPKH output, paying the whole value of [`template`].
+pub fn coinbase_reward_outputs() -> Vec<TxOut> {
+    let pubkey_hash = [
+        235, 225, 183, 220, 194, 147, 204, 170, 14, 231, 67, 168, 111, 137, 223, 130, 88, 194, 8,
+        252,
+    ];
+    let mut script_bytes = vec![0]; // SegWit version 0
+    script_bytes.push(20); // Push 20 bytes (length of pubkey hash)
+    script_bytes.extend_from_slice(&pubkey_hash);
+    vec![TxOut {
+        value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
+        script_pubkey: ScriptBuf::from(script_bytes),
+    }]
+}
+
+pub fn prev_hash() -> U256<'static> {
+    [
+        200, 53, 253, 129, 214, 31, 43, 84, 179, 58, 58, 76, 128, 213, 24, 53, 38, 144, 205, 88,
+        172, 20, 251, 22, 217, 141, 21, 221, 21, 0, 0, 0,
+    ]
+    .into()
+}
+
+/// The chain tip of [`set_new_prev_hash`].
+pub fn chain_tip() -> ChainTip {
+    ChainTip::new(prev_hash(), N_BITS, NTIME).expect("the fixture prev hash is 32 bytes")
+}
+
+/// A `SetNewPrevHash` on top of [`prev_hash`], activating the template with `template_id`.
+pub fn set_new_prev_hash(template_id: u64) -> SetNewPrevHash<'static> {
+    SetNewPrevHash {
+        template_id,
+        prev_hash: prev_hash(),
+        header_timestamp: NTIME,
+        n_bits: N_BITS,
+        target: [
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 174,
+            119, 3, 0, 0,
+        ]
+        .into(),
+    }
+}
+
+/// A `SubmitSharesStandard` for the job of [`template`] at [`NTIME`], with sequence number 0.
+///
+/// Other fields are tweaked via struct update syntax, e.g.
+/// `SubmitSharesStandard { sequence_number: 1, ..submit_shares_standard(1, 1, 0) }`.
+pub fn submit_shares_standard(channel_id: u32, job_id: u32, nonce: u32) -> SubmitSharesStandard {
+    SubmitSharesStandard {
+        channel_id,
+        sequence_number: 0,
+        job_id,
+        nonce,
+        ntime: NTIME,
+        version: 536870912,
+    }
+}
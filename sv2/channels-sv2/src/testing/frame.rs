@@ -0,0 +1,216 @@
+//! Plaintext Sv2 frames of the Mining Protocol messages exchanged by Standard Channels.
+//!
+//! Enough framing to run a channel over a real transport in tests (e.g. through a `NoiseCodec`),
+//! without pulling a full message parser: [`to_frame`] prepends the 6 bytes frame header to the
+//! encoded message, and [`from_frame`] checks it and decodes the payload back.
+use alloc::vec::Vec;
+use binary_sv2::{from_bytes, to_bytes, GetSize, Serialize};
+use mining_sv2::*;
+
+/// Size of the header of an Sv2 frame.
+pub const FRAME_HEADER_SIZE: usize = 6;
+
+/// Extension type of the messages of the Mining Protocol.
+pub const MINING_EXTENSION_TYPE: u16 = 0;
+
+// set on the extension type of the messages addressed to a channel
+const CHANNEL_MSG_BIT: u16 = 0x8000;
+
+/// The Mining Protocol messages of the Standard Channel flow.
+#[derive(Debug, Clone)]
+pub enum MiningMessage<'a> {
+    OpenStandardMiningChannel(OpenStandardMiningChannel<'a>),
+    OpenStandardMiningChannelSuccess(OpenStandardMiningChannelSuccess<'a>),
+    OpenMiningChannelError(OpenMiningChannelError<'a>),
+    NewMiningJob(NewMiningJob<'a>),
+    SetNewPrevHash(SetNewPrevHash<'a>),
+    SubmitSharesStandard(SubmitSharesStandard),
+    SubmitSharesSuccess(SubmitSharesSuccess),
+    SubmitSharesError(SubmitSharesError<'a>),
+}
+
+impl MiningMessage<'_> {
+    pub fn message_type(&self) -> u8 {
+        match self {
+            Self::OpenStandardMiningChannel(_) => MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL,
+            Self::OpenStandardMiningChannelSuccess(_) => {
+                MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS
+            }
+            Self::OpenMiningChannelError(_) => MESSAGE_TYPE_OPEN_MINING_CHANNEL_ERROR,
+            Self::NewMiningJob(_) => MESSAGE_TYPE_NEW_MINING_JOB,
+            Self::SetNewPrevHash(_) => MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH,
+            Self::SubmitSharesStandard(_) => MESSAGE_TYPE_SUBMIT_SHARES_STANDARD,
+            Self::SubmitSharesSuccess(_) => MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS,
+            Self::SubmitSharesError(_) => MESSAGE_TYPE_SUBMIT_SHARES_ERROR,
+        }
+    }
+
+    /// Whether the message is addressed to a channel, i.e. the `channel_msg` bit of its frame.
+    pub fn channel_bit(&self) -> bool {
+        match self {
+            Self::OpenStandardMiningChannel(_) => CHANNEL_BIT_OPEN_STANDARD_MINING_CHANNEL,
+            Self::OpenStandardMiningChannelSuccess(_) => {
+                CHANNEL_BIT_OPEN_STANDARD_MINING_CHANNEL_SUCCESS
+            }
+            Self::OpenMiningChannelError(_) => CHANNEL_BIT_OPEN_MINING_CHANNEL_ERROR,
+            Self::NewMiningJob(_) => CHANNEL_BIT_NEW_MINING_JOB,
+            Self::SetNewPrevHash(_) => CHANNEL_BIT_MINING_SET_NEW_PREV_HASH,
+            Self::SubmitSharesStandard(_) => CHANNEL_BIT_SUBMIT_SHARES_STANDARD,
+            Self::SubmitSharesSuccess(_) => CHANNEL_BIT_SUBMIT_SHARES_SUCCESS,
+            Self::SubmitSharesError(_) => CHANNEL_BIT_SUBMIT_SHARES_ERROR,
+        }
+    }
+}
+
+/// Why [`from_frame`] could not decode a frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// Fewer bytes than a frame header.
+    MissingHeader(usize),
+    /// The extension type is not the one of the Mining Protocol.
+    UnexpectedExtensionType(u16),
+    /// The `channel_msg` bit does not match the message type.
+    InvalidChannelBit(u8),
+    /// The length in the header differs from the size of the payload.
+    LengthMismatch {
+        header: usize,
+        payload: usize,
+    },
+    /// A message type outside of [`MiningMessage`].
+    UnsupportedMessageType(u8),
+    Payload(binary_sv2::Error),
+}
+
+/// Encodes `message` as a plaintext Sv2 frame.
+pub fn to_frame(message: MiningMessage) -> Vec<u8> {
+    let extension_type = match message.channel_bit() {
+        true => MINING_EXTENSION_TYPE | CHANNEL_MSG_BIT,
+        false => MINING_EXTENSION_TYPE,
+    };
+    let message_type = message.message_type();
+    let payload = match message {
+        MiningMessage::OpenStandardMiningChannel(m) => encode(m),
+        MiningMessage::OpenStandardMiningChannelSuccess(m) => encode(m),
+        MiningMessage::OpenMiningChannelError(m) => encode(m),
+        MiningMessage::NewMiningJob(m) => encode(m),
+        MiningMessage::SetNewPrevHash(m) => encode(m),
+        MiningMessage::SubmitSharesStandard(m) => encode(m),
+        MiningMessage::SubmitSharesSuccess(m) => encode(m),
+        MiningMessage::SubmitSharesError(m) => encode(m),
+    };
+    assert!(
+        payload.len() < 1 << 24,
+        "payloads are at most 2^24 - 1 bytes"
+    );
+
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    frame.extend_from_slice(&extension_type.to_le_bytes());
+    frame.push(message_type);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes()[..3]);
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+/// Decodes a plaintext Sv2 frame, as built by [`to_frame`].
+pub fn from_frame(frame: &mut [u8]) -> Result<MiningMessage<'_>, FrameError> {
+    if frame.len() < FRAME_HEADER_SIZE {
+        return Err(FrameError::MissingHeader(frame.len()));
+    }
+    let (header, payload) = frame.split_at_mut(FRAME_HEADER_SIZE);
+    let extension_type = u16::from_le_bytes([header[0], header[1]]);
+    let message_type = header[2];
+    let length = u32::from_le_bytes([header[3], header[4], header[5], 0]) as usize;
+
+    if extension_type & !CHANNEL_MSG_BIT != MINING_EXTENSION_TYPE {
+        return Err(FrameError::UnexpectedExtensionType(extension_type));
+    }
+    if length != payload.len() {
+        return Err(FrameError::LengthMismatch {
+            header: length,
+            payload: payload.len(),
+        });
+    }
+
+    let message = match message_type {
+        MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL => {
+            MiningMessage::OpenStandardMiningChannel(decode(payload)?)
+        }
+        MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS => {
+            MiningMessage::OpenStandardMiningChannelSuccess(decode(payload)?)
+        }
+        MESSAGE_TYPE_OPEN_MINING_CHANNEL_ERROR => {
+            MiningMessage::OpenMiningChannelError(decode(payload)?)
+        }
+        MESSAGE_TYPE_NEW_MINING_JOB => MiningMessage::NewMiningJob(decode(payload)?),
+        MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH => MiningMessage::SetNewPrevHash(decode(payload)?),
+        MESSAGE_TYPE_SUBMIT_SHARES_STANDARD => {
+            MiningMessage::SubmitSharesStandard(decode(payload)?)
+        }
+        MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS => MiningMessage::SubmitSharesSuccess(decode(payload)?),
+        MESSAGE_TYPE_SUBMIT_SHARES_ERROR => MiningMessage::SubmitSharesError(decode(payload)?),
+        _ => return Err(FrameError::UnsupportedMessageType(message_type)),
+    };
+    if message.channel_bit() != (extension_type & CHANNEL_MSG_BIT != 0) {
+        return Err(FrameError::InvalidChannelBit(message_type));
+    }
+    Ok(message)
+}
+
+fn encode<T: Serialize + GetSize>(message: T) -> Vec<u8> {
+    to_bytes(message).expect("buffer is sized after the message")
+}
+
+fn decode<'a, T: binary_sv2::Deserialize<'a>>(payload: &'a mut [u8]) -> Result<T, FrameError> {
+    from_bytes(payload).map_err(FrameError::Payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+
+    #[test]
+    fn test_frame_round_trip() {
+        let share = fixture::submit_shares_standard(1, 1, 0);
+        let mut frame = to_frame(MiningMessage::SubmitSharesStandard(share.clone()));
+        // channel message of the Mining Protocol, with a 24 bytes payload
+        assert_eq!(frame[..FRAME_HEADER_SIZE], [0x00, 0x80, 0x1a, 24, 0, 0]);
+        match from_frame(&mut frame) {
+            Ok(MiningMessage::SubmitSharesStandard(decoded)) => {
+                assert_eq!(
+                    (decoded.job_id, decoded.nonce, decoded.ntime),
+                    (share.job_id, share.nonce, share.ntime)
+                );
+            }
+            other => panic!("expected a share, got {:?}", other),
+        }
+
+        let mut truncated = frame[..frame.len() - 1].to_vec();
+        assert_eq!(
+            from_frame(&mut truncated).unwrap_err(),
+            FrameError::LengthMismatch {
+                header: 24,
+                payload: 23
+            }
+        );
+
+        let mut no_channel_bit = frame.clone();
+        no_channel_bit[1] = 0;
+        assert_eq!(
+            from_frame(&mut no_channel_bit).unwrap_err(),
+            FrameError::InvalidChannelBit(MESSAGE_TYPE_SUBMIT_SHARES_STANDARD)
+        );
+
+        let mut unsupported = frame;
+        unsupported[2] = MESSAGE_TYPE_SET_TARGET;
+        assert_eq!(
+            from_frame(&mut unsupported).unwrap_err(),
+            FrameError::UnsupportedMessageType(MESSAGE_TYPE_SET_TARGET)
+        );
+
+        assert_eq!(
+            from_frame(&mut [0; 3]).unwrap_err(),
+            FrameError::MissingHeader(3)
+        );
+    }
+}
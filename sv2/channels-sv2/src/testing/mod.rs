@@ -0,0 +1,204 @@
+//! # Testing
+//!
+//! A small interpreter of protocol events, so that channel scenarios (reorgs, template floods,
+//! stale shares, etc.) can be expressed as data instead of long hand-written sequences of calls.
+//!
+//! The same [`EventScript`] format drives both Standard and Extended server channels, via the
+//! [`ScriptableChannel`] trait.
+//!
+//! The [`fixture`] module provides the canonical messages most channel tests are built from, the
+//! [`frame`] module their Sv2 framing, to run channels over a transport, the [`clock`] module a
+//! clock moved by hand, and the `doubles` module (with the `std` feature) mock implementations of
+//! the extension points of server channels.
+//!
+//! Only available with the `test-utils` feature.
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod doubles;
+pub mod fixture;
+pub mod frame;
+#[cfg(test)]
+mod wire;
+
+use crate::{
+    chain_tip::ChainTip,
+    server::{
+        error::{ExtendedChannelError, StandardChannelError},
+        extended::ExtendedChannel,
+        share_validation::{ShareValidationError, ShareValidationResult},
+        standard::StandardChannel,
+    },
+};
+use alloc::vec::Vec;
+use bitcoin::transaction::TxOut;
+use core::fmt::Debug;
+use mining_sv2::{SubmitSharesExtended, SubmitSharesStandard, Target};
+use template_distribution_sv2::{NewTemplate, SetNewPrevHash};
+
+/// A protocol event to be applied to a channel.
+#[derive(Debug, Clone)]
+pub enum Event<'a, S> {
+    /// Sets the chain tip directly, bypassing `SetNewPrevHash`.
+    SetChainTip(ChainTip),
+    /// A `NewTemplate` message, along with the coinbase reward outputs for the new job.
+    NewTemplate(NewTemplate<'a>, Vec<TxOut>),
+    SetNewPrevHash(SetNewPrevHash<'a>),
+    /// Nominal hashrate and optional requested max target.
+    UpdateChannel(f32, Option<Target>),
+    SubmitShare(S),
+}
+
+/// A sequence of events, applied in order by [`run_script`].
+pub type EventScript<'a, S> = Vec<Event<'a, S>>;
+
+/// The outcome of applying a single [`Event`].
+#[derive(Debug)]
+pub enum Outcome<E> {
+    /// The outcome of any event other than [`Event::SubmitShare`].
+    Channel(Result<(), E>),
+    /// The outcome of [`Event::SubmitShare`].
+    Share(Result<ShareValidationResult, ShareValidationError>),
+}
+
+impl<E: Debug> Outcome<E> {
+    /// Returns the share validation result, panicking if this is not the outcome of a share.
+    pub fn unwrap_share(self) -> Result<ShareValidationResult, ShareValidationError> {
+        match self {
+            Outcome::Share(res) => res,
+            Outcome::Channel(res) => panic!("expected a share outcome, got {:?}", res),
+        }
+    }
+}
+
+/// A channel that can be driven by an [`EventScript`].
+pub trait ScriptableChannel<'a> {
+    type Share;
+    type Error: Debug;
+
+    fn set_chain_tip(&mut self, chain_tip: ChainTip);
+    fn on_new_template(
+        &mut self,
+        template: NewTemplate<'a>,
+        coinbase_reward_outputs: Vec<TxOut>,
+    ) -> Result<(), Self::Error>;
+    fn on_set_new_prev_hash(
+        &mut self,
+        set_new_prev_hash: SetNewPrevHash<'a>,
+    ) -> Result<(), Self::Error>;
+    fn update_channel(
+        &mut self,
+        nominal_hashrate: f32,
+        requested_max_target: Option<Target>,
+    ) -> Result<(), Self::Error>;
+    fn validate_share(
+        &mut self,
+        share: Self::Share,
+    ) -> Result<ShareValidationResult, ShareValidationError>;
+}
+
+/// Applies every event of `script` to `channel`, in order, and collects one [`Outcome`] per
+/// event.
+///
+/// Errors don't stop the script, so scenarios can assert on failures halfway through.
+pub fn run_script<'a, C: ScriptableChannel<'a>>(
+    channel: &mut C,
+    script: EventScript<'a, C::Share>,
+) -> Vec<Outcome<C::Error>> {
+    script
+        .into_iter()
+        .map(|event| match event {
+            Event::SetChainTip(chain_tip) => {
+                channel.set_chain_tip(chain_tip);
+                Outcome::Channel(Ok(()))
+            }
+            Event::NewTemplate(template, coinbase_reward_outputs) => {
+                Outcome::Channel(channel.on_new_template(template, coinbase_reward_outputs))
+            }
+            Event::SetNewPrevHash(set_new_prev_hash) => {
+                Outcome::Channel(channel.on_set_new_prev_hash(set_new_prev_hash))
+            }
+            Event::UpdateChannel(nominal_hashrate, requested_max_target) => {
+                Outcome::Channel(channel.update_channel(nominal_hashrate, requested_max_target))
+            }
+            Event::SubmitShare(share) => Outcome::Share(channel.validate_share(share)),
+        })
+        .collect()
+}
+
+impl<'a> ScriptableChannel<'a> for StandardChannel<'a> {
+    type Share = SubmitSharesStandard;
+    type Error = StandardChannelError;
+
+    fn set_chain_tip(&mut self, chain_tip: ChainTip) {
+        StandardChannel::set_chain_tip(self, chain_tip)
+    }
+
+    fn on_new_template(
+        &mut self,
+        template: NewTemplate<'a>,
+        coinbase_reward_outputs: Vec<TxOut>,
+    ) -> Result<(), Self::Error> {
+        StandardChannel::on_new_template(self, template, coinbase_reward_outputs).map(|_| ())
+    }
+
+    fn on_set_new_prev_hash(
+        &mut self,
+        set_new_prev_hash: SetNewPrevHash<'a>,
+    ) -> Result<(), Self::Error> {
+        StandardChannel::on_set_new_prev_hash(self, set_new_prev_hash)
+    }
+
+    fn update_channel(
+        &mut self,
+        nominal_hashrate: f32,
+        requested_max_target: Option<Target>,
+    ) -> Result<(), Self::Error> {
+        StandardChannel::update_channel(self, nominal_hashrate, requested_max_target)
+    }
+
+    fn validate_share(
+        &mut self,
+        share: Self::Share,
+    ) -> Result<ShareValidationResult, ShareValidationError> {
+        StandardChannel::validate_share(self, share)
+    }
+}
+
+impl<'a> ScriptableChannel<'a> for ExtendedChannel<'a> {
+    type Share = SubmitSharesExtended<'a>;
+    type Error = ExtendedChannelError;
+
+    fn set_chain_tip(&mut self, chain_tip: ChainTip) {
+        ExtendedChannel::set_chain_tip(self, chain_tip)
+    }
+
+    fn on_new_template(
+        &mut self,
+        template: NewTemplate<'a>,
+        coinbase_reward_outputs: Vec<TxOut>,
+    ) -> Result<(), Self::Error> {
+        ExtendedChannel::on_new_template(self, template, coinbase_reward_outputs)
+    }
+
+    fn on_set_new_prev_hash(
+        &mut self,
+        set_new_prev_hash: SetNewPrevHash<'a>,
+    ) -> Result<(), Self::Error> {
+        ExtendedChannel::on_set_new_prev_hash(self, set_new_prev_hash)
+    }
+
+    fn update_channel(
+        &mut self,
+        nominal_hashrate: f32,
+        requested_max_target: Option<Target>,
+    ) -> Result<(), Self::Error> {
+        ExtendedChannel::update_channel(self, nominal_hashrate, requested_max_target)
+    }
+
+    fn validate_share(
+        &mut self,
+        share: Self::Share,
+    ) -> Result<ShareValidationResult, ShareValidationError> {
+        ExtendedChannel::validate_share(self, share)
+    }
+}
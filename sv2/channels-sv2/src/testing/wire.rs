@@ -0,0 +1,183 @@
+//! Frozen wire encodings of the messages exchanged by channels, for the flow of [`fixture`].
+//!
+//! Both ends of the channel tests share the `binary_sv2` codec, so an encoding regression would
+//! go unnoticed by them. The bytes below are laid out field by field as in the Sv2 spec, and are
+//! not to be regenerated from the codec: any intended encoding change must update them by hand.
+use super::fixture;
+use crate::{prelude::*, server::standard::DownstreamMessage};
+use alloc::vec::Vec;
+use binary_sv2::{from_bytes, to_bytes, GetSize, Serialize};
+
+const PREV_HASH: [u8; 32] = [
+    200, 53, 253, 129, 214, 31, 43, 84, 179, 58, 58, 76, 128, 213, 24, 53, 38, 144, 205, 88, 172,
+    20, 251, 22, 217, 141, 21, 221, 21, 0, 0, 0,
+];
+
+/// [`fixture::NTIME`]
+const NTIME: [u8; 4] = [153, 132, 34, 104];
+
+/// [`fixture::N_BITS`]
+const N_BITS: [u8; 4] = [174, 119, 3, 30];
+
+/// `0x20000000`
+const VERSION: [u8; 4] = [0, 0, 0, 32];
+
+/// [`fixture::template`], as a future template.
+fn new_template() -> Vec<u8> {
+    [
+        &[1, 0, 0, 0, 0, 0, 0, 0][..], // template_id: U64
+        &[1],                          // future_template: BOOL
+        &VERSION,                      // version: U32
+        &[2, 0, 0, 0],                 // coinbase_tx_version: U32
+        &[4, 2, 159, 0, 0],            // coinbase_prefix: B0_255
+        &[254, 255, 255, 255],         // coinbase_tx_input_sequence: U32
+        &[0, 242, 5, 42, 1, 0, 0, 0],  // coinbase_tx_value_remaining: U64
+        &[1, 0, 0, 0],                 // coinbase_tx_outputs_count: U32
+        &[47, 0],                      // coinbase_tx_outputs: B0_64K
+        &[
+            0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
+            222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
+            139, 235, 216, 54, 151, 78, 140, 249,
+        ],
+        &[158, 0, 0, 0], // coinbase_tx_locktime: U32
+        &[0],            // merkle_path: SEQ0_255[U256]
+    ]
+    .concat()
+}
+
+/// [`fixture::set_new_prev_hash`] for template 1.
+fn set_new_prev_hash_tdp() -> Vec<u8> {
+    [
+        &[1, 0, 0, 0, 0, 0, 0, 0][..], // template_id: U64
+        &PREV_HASH,                    // prev_hash: U256
+        &NTIME,                        // header_timestamp: U32
+        &N_BITS,                       // n_bits: U32
+        &[
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 174,
+            119, 3, 0, 0,
+        ], // target: U256
+    ]
+    .concat()
+}
+
+/// The job of channel 1 for [`fixture::template`], with `min_ntime` set once activated.
+fn new_mining_job(activated: bool) -> Vec<u8> {
+    let min_ntime: &[u8] = match activated {
+        true => &[1, 153, 132, 34, 104],
+        false => &[0],
+    };
+    [
+        &[1, 0, 0, 0][..],     // channel_id: U32
+        &[1, 0, 0, 0],         // job_id: U32
+        min_ntime,             // min_ntime: OPTION[U32]
+        &VERSION,              // version: U32
+        &fixture::MERKLE_ROOT, // merkle_root: U256
+    ]
+    .concat()
+}
+
+/// The `SetNewPrevHash` activating job 1 of channel 1 on [`fixture::chain_tip`].
+fn set_new_prev_hash_mp() -> Vec<u8> {
+    [
+        &[1, 0, 0, 0][..], // channel_id: U32
+        &[1, 0, 0, 0],     // job_id: U32
+        &PREV_HASH,        // prev_hash: U256
+        &NTIME,            // min_ntime: U32
+        &N_BITS,           // nbits: U32
+    ]
+    .concat()
+}
+
+/// [`fixture::submit_shares_standard`] for channel 1, job 1 and nonce 0.
+fn submit_shares_standard() -> Vec<u8> {
+    [
+        &[1, 0, 0, 0][..], // channel_id: U32
+        &[0, 0, 0, 0],     // sequence_number: U32
+        &[1, 0, 0, 0],     // job_id: U32
+        &[0, 0, 0, 0],     // nonce: U32
+        &NTIME,            // ntime: U32
+        &VERSION,          // version: U32
+    ]
+    .concat()
+}
+
+fn encode<T: Serialize + GetSize>(message: T) -> Vec<u8> {
+    to_bytes(message).unwrap()
+}
+
+#[test]
+fn test_fixtures_encoding() {
+    assert_eq!(encode(fixture::template(true)), new_template());
+    assert_eq!(
+        encode(fixture::set_new_prev_hash(1)),
+        set_new_prev_hash_tdp()
+    );
+    assert_eq!(
+        encode(fixture::submit_shares_standard(1, 1, 0)),
+        submit_shares_standard()
+    );
+}
+
+#[test]
+fn test_channel_flow_round_trip() {
+    let mut channel = fixture::standard_channel();
+
+    let mut bytes = new_template();
+    let template: NewTemplate = from_bytes(&mut bytes).unwrap();
+    assert_eq!(template, fixture::template(true));
+    channel
+        .on_new_template(
+            template.clone().into_static(),
+            fixture::coinbase_reward_outputs(),
+        )
+        .unwrap();
+    assert_eq!(encode(template), new_template());
+
+    let future_job = channel.get_future_jobs().get(&1).unwrap();
+    assert_eq!(
+        encode(future_job.get_job_message().clone()),
+        new_mining_job(false)
+    );
+
+    let mut bytes = set_new_prev_hash_tdp();
+    let set_new_prev_hash: SetNewPrevHashTdp = from_bytes(&mut bytes).unwrap();
+    assert_eq!(set_new_prev_hash, fixture::set_new_prev_hash(1));
+    channel
+        .on_set_new_prev_hash(set_new_prev_hash.clone().into_static())
+        .unwrap();
+    assert_eq!(encode(set_new_prev_hash), set_new_prev_hash_tdp());
+
+    let active_job = channel.get_active_job().unwrap();
+    assert_eq!(
+        encode(active_job.get_job_message().clone()),
+        new_mining_job(true)
+    );
+    let messages: Vec<Vec<u8>> = channel
+        .initial_downstream_messages()
+        .into_iter()
+        .map(|message| match message {
+            DownstreamMessage::NewMiningJob(m) => encode(m),
+            DownstreamMessage::SetNewPrevHash(m) => encode(m),
+        })
+        .collect();
+    assert_eq!(
+        messages,
+        vec![new_mining_job(false), set_new_prev_hash_mp()]
+    );
+
+    let mut bytes = submit_shares_standard();
+    let share: SubmitSharesStandard = from_bytes(&mut bytes).unwrap();
+    assert_eq!(
+        (share.channel_id, share.sequence_number, share.job_id),
+        (1, 0, 1)
+    );
+    assert_eq!(
+        (share.nonce, share.ntime, share.version),
+        (0, fixture::NTIME, 536870912)
+    );
+    assert!(matches!(
+        channel.validate_share(share.clone()),
+        Ok(_) | Err(ShareValidationError::DoesNotMeetTarget)
+    ));
+    assert_eq!(encode(share), submit_shares_standard());
+}
@@ -0,0 +1,54 @@
+//! Logging macros, forwarding to `tracing` unless logging is compiled out.
+//!
+//! With the `tracing` feature disabled, or the `no-trace` feature enabled, every log statement
+//! expands to dead code: its arguments are still type checked but never evaluated, and the crate
+//! doesn't reference the `tracing` dependency at all.
+
+#[cfg(all(feature = "tracing", not(feature = "no-trace")))]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        ::tracing::debug!($($arg)+)
+    };
+}
+
+#[cfg(not(all(feature = "tracing", not(feature = "no-trace"))))]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+#[cfg(all(feature = "tracing", not(feature = "no-trace")))]
+macro_rules! error {
+    ($($arg:tt)+) => {
+        ::tracing::error!($($arg)+)
+    };
+}
+
+#[cfg(not(all(feature = "tracing", not(feature = "no-trace"))))]
+macro_rules! error {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+/// Whether debug events are currently recorded, to skip building their arguments otherwise.
+#[cfg(all(feature = "tracing", not(feature = "no-trace")))]
+macro_rules! debug_enabled {
+    () => {
+        ::tracing::enabled!(::tracing::Level::DEBUG)
+    };
+}
+
+#[cfg(not(all(feature = "tracing", not(feature = "no-trace"))))]
+macro_rules! debug_enabled {
+    () => {
+        false
+    };
+}
+
+pub(crate) use {debug, debug_enabled, error};
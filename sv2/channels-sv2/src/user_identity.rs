@@ -0,0 +1,288 @@
+//! # User Identity
+//!
+//! The `user_identity` of `OpenStandardMiningChannel` and `OpenExtendedMiningChannel` messages is
+//! expected to be structured as `account.worker`, where the worker part is optional.
+//!
+//! [`UserIdentity`] is validated once, when the channel is opened, so that malformed identities
+//! are rejected with an `OpenMiningChannel.Error` instead of reaching accounting later on.
+use crate::redact::RedactedIdentity;
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{convert::Infallible, convert::TryFrom, fmt};
+
+/// The maximum length of a user identity in bytes, as carried by a `Str0255`.
+pub const MAX_USER_IDENTITY_LEN: usize = 255;
+
+/// The character separating the account from the worker.
+pub const WORKER_SEPARATOR: char = '.';
+
+/// The rules a [`UserIdentity`] is checked against by [`UserIdentity::parse`].
+///
+/// The default rules accept up to [`MAX_USER_IDENTITY_LEN`] bytes of printable ASCII (no
+/// whitespace).
+#[derive(Clone, Copy)]
+pub struct UserIdentityRules {
+    max_len: usize,
+    is_allowed_char: fn(char) -> bool,
+}
+
+impl Default for UserIdentityRules {
+    fn default() -> Self {
+        Self {
+            max_len: MAX_USER_IDENTITY_LEN,
+            is_allowed_char: |c| c.is_ascii_graphic(),
+        }
+    }
+}
+
+impl UserIdentityRules {
+    /// Sets the maximum length in bytes, capped to [`MAX_USER_IDENTITY_LEN`].
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len.min(MAX_USER_IDENTITY_LEN);
+        self
+    }
+
+    /// Sets the characters allowed in a user identity, e.g. `|c| c.is_ascii_alphanumeric() ||
+    /// c == '.'`.
+    pub fn with_charset(mut self, is_allowed_char: fn(char) -> bool) -> Self {
+        self.is_allowed_char = is_allowed_char;
+        self
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+}
+
+impl fmt::Debug for UserIdentityRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserIdentityRules")
+            .field("max_len", &self.max_len)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The ways a user identity can be invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserIdentityError {
+    Empty,
+    TooLong {
+        max_len: usize,
+        len: usize,
+    },
+    InvalidUtf8,
+    InvalidCharacter(char),
+    /// The identity starts with [`WORKER_SEPARATOR`].
+    EmptyAccount,
+}
+
+impl UserIdentityError {
+    /// Returns the `error_code` to be sent on an `OpenMiningChannel.Error` message.
+    pub fn error_code(&self) -> &'static str {
+        "unknown-user"
+    }
+}
+
+// allows plain `UserIdentity` values wherever `impl TryInto<UserIdentity>` is expected
+impl From<Infallible> for UserIdentityError {
+    fn from(infallible: Infallible) -> Self {
+        match infallible {}
+    }
+}
+
+/// A validated `account.worker` user identity.
+///
+/// Plain strings are converted via `TryFrom`, which checks them against the default
+/// [`UserIdentityRules`].
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct UserIdentity {
+    identity: String,
+    // the position of the first `WORKER_SEPARATOR`, if any
+    separator: Option<usize>,
+}
+
+impl UserIdentity {
+    /// Parses `identity`, checking it against `rules`.
+    pub fn parse(identity: &str, rules: &UserIdentityRules) -> Result<Self, UserIdentityError> {
+        if identity.is_empty() {
+            return Err(UserIdentityError::Empty);
+        }
+        if identity.len() > rules.max_len {
+            return Err(UserIdentityError::TooLong {
+                max_len: rules.max_len,
+                len: identity.len(),
+            });
+        }
+        if let Some(c) = identity.chars().find(|c| !(rules.is_allowed_char)(*c)) {
+            return Err(UserIdentityError::InvalidCharacter(c));
+        }
+
+        let separator = identity.find(WORKER_SEPARATOR);
+        if separator == Some(0) {
+            return Err(UserIdentityError::EmptyAccount);
+        }
+
+        Ok(Self {
+            identity: identity.to_string(),
+            separator,
+        })
+    }
+
+    /// Returns the part before the first [`WORKER_SEPARATOR`], or the whole identity.
+    pub fn account(&self) -> &str {
+        match self.separator {
+            Some(separator) => &self.identity[..separator],
+            None => &self.identity,
+        }
+    }
+
+    /// Returns the part after the first [`WORKER_SEPARATOR`], unless it's missing or empty.
+    pub fn worker(&self) -> Option<&str> {
+        self.separator
+            .map(|separator| &self.identity[separator + WORKER_SEPARATOR.len_utf8()..])
+            .filter(|worker| !worker.is_empty())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.identity
+    }
+}
+
+impl TryFrom<&str> for UserIdentity {
+    type Error = UserIdentityError;
+
+    fn try_from(identity: &str) -> Result<Self, Self::Error> {
+        Self::parse(identity, &UserIdentityRules::default())
+    }
+}
+
+impl TryFrom<String> for UserIdentity {
+    type Error = UserIdentityError;
+
+    fn try_from(identity: String) -> Result<Self, Self::Error> {
+        Self::try_from(identity.as_str())
+    }
+}
+
+/// For identities straight off the wire, e.g. the bytes of a `Str0255`.
+impl TryFrom<Vec<u8>> for UserIdentity {
+    type Error = UserIdentityError;
+
+    fn try_from(identity: Vec<u8>) -> Result<Self, Self::Error> {
+        let identity = String::from_utf8(identity).map_err(|_| UserIdentityError::InvalidUtf8)?;
+        Self::try_from(identity)
+    }
+}
+
+impl From<UserIdentity> for String {
+    fn from(user_identity: UserIdentity) -> Self {
+        user_identity.identity
+    }
+}
+
+impl AsRef<str> for UserIdentity {
+    fn as_ref(&self) -> &str {
+        &self.identity
+    }
+}
+
+impl PartialEq<str> for UserIdentity {
+    fn eq(&self, other: &str) -> bool {
+        self.identity == other
+    }
+}
+
+impl PartialEq<&str> for UserIdentity {
+    fn eq(&self, other: &&str) -> bool {
+        self.identity == *other
+    }
+}
+
+impl fmt::Display for UserIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.identity)
+    }
+}
+
+impl fmt::Debug for UserIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&RedactedIdentity(&self.identity), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_parse_rules() {
+        let user_identity: UserIdentity = "alice.worker1".try_into().unwrap();
+        assert_eq!(user_identity.account(), "alice");
+        assert_eq!(user_identity.worker(), Some("worker1"));
+        assert_eq!(user_identity, "alice.worker1");
+
+        // only the first separator splits the account from the worker
+        let user_identity: UserIdentity = "alice.rig.1".try_into().unwrap();
+        assert_eq!(user_identity.account(), "alice");
+        assert_eq!(user_identity.worker(), Some("rig.1"));
+
+        let user_identity: UserIdentity = "bc1qaccount".try_into().unwrap();
+        assert_eq!(user_identity.account(), "bc1qaccount");
+        assert_eq!(user_identity.worker(), None);
+        let user_identity: UserIdentity = "alice.".try_into().unwrap();
+        assert_eq!(user_identity.worker(), None);
+
+        assert_eq!(UserIdentity::try_from(""), Err(UserIdentityError::Empty));
+        assert_eq!(
+            UserIdentity::try_from(".worker1"),
+            Err(UserIdentityError::EmptyAccount)
+        );
+        assert_eq!(
+            UserIdentity::try_from("alice worker1"),
+            Err(UserIdentityError::InvalidCharacter(' '))
+        );
+        assert_eq!(
+            UserIdentity::try_from("alice\u{0}"),
+            Err(UserIdentityError::InvalidCharacter('\u{0}'))
+        );
+        assert_eq!(
+            UserIdentity::try_from("a".repeat(MAX_USER_IDENTITY_LEN + 1)),
+            Err(UserIdentityError::TooLong {
+                max_len: MAX_USER_IDENTITY_LEN,
+                len: MAX_USER_IDENTITY_LEN + 1
+            })
+        );
+        assert!(UserIdentity::try_from("a".repeat(MAX_USER_IDENTITY_LEN)).is_ok());
+        assert_eq!(
+            UserIdentity::try_from(vec![b'a', 0xff]),
+            Err(UserIdentityError::InvalidUtf8)
+        );
+        assert_eq!(UserIdentityError::InvalidUtf8.error_code(), "unknown-user");
+    }
+
+    #[test]
+    fn test_custom_rules() {
+        let rules = UserIdentityRules::default()
+            .with_max_len(8)
+            .with_charset(|c| c.is_ascii_lowercase() || c == WORKER_SEPARATOR);
+
+        assert!(UserIdentity::parse("alice.w", &rules).is_ok());
+        assert_eq!(
+            UserIdentity::parse("alice.w1", &rules),
+            Err(UserIdentityError::InvalidCharacter('1'))
+        );
+        assert!(matches!(
+            UserIdentity::parse("alice.wor", &rules),
+            Err(UserIdentityError::TooLong { max_len: 8, len: 9 })
+        ));
+
+        // non-ASCII identities can be allowed
+        let rules = UserIdentityRules::default().with_charset(|c| !c.is_control());
+        let user_identity = UserIdentity::parse("élodie.rig", &rules).unwrap();
+        assert_eq!(user_identity.account(), "élodie");
+        assert_eq!(user_identity.worker(), Some("rig"));
+    }
+}
@@ -0,0 +1,271 @@
+// Runs a Standard Channel end to end: a Mining Client and a Mining Server complete a Noise
+// handshake over an in-memory pipe, then open a channel, hand out a job and submit a share, all
+// through encrypted Sv2 frames.
+//
+// Each side only sees the bytes the other one wrote, so framing sizes and message ordering are
+// exercised as they would be over a socket.
+use channels_sv2::{
+    client::standard::StandardChannel as ClientStandardChannel,
+    prelude::*,
+    server::{share_accounting::ShareAccountingConfig, standard::DownstreamMessage},
+    testing::{
+        fixture,
+        frame::{from_frame, to_frame, MiningMessage, FRAME_HEADER_SIZE},
+    },
+};
+use mining_sv2::{ExtendedExtranonce, OpenStandardMiningChannel, SubmitSharesSuccess};
+use noise_sv2::{
+    encrypted_len, Initiator, NoiseCodec, Responder, ELLSWIFT_ENCODING_SIZE,
+    INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE, MAX_CHUNK_PLAINTEXT_SIZE,
+};
+use secp256k1::{Keypair, Parity, Secp256k1};
+use std::{cell::RefCell, collections::VecDeque, convert::TryInto, rc::Rc};
+
+// One end of an in-memory duplex byte stream.
+struct PipeEnd {
+    incoming: Rc<RefCell<VecDeque<u8>>>,
+    outgoing: Rc<RefCell<VecDeque<u8>>>,
+}
+
+fn pipe() -> (PipeEnd, PipeEnd) {
+    let a_to_b = Rc::new(RefCell::new(VecDeque::new()));
+    let b_to_a = Rc::new(RefCell::new(VecDeque::new()));
+    (
+        PipeEnd {
+            incoming: b_to_a.clone(),
+            outgoing: a_to_b.clone(),
+        },
+        PipeEnd {
+            incoming: a_to_b,
+            outgoing: b_to_a,
+        },
+    )
+}
+
+impl PipeEnd {
+    fn write(&self, bytes: &[u8]) {
+        self.outgoing.borrow_mut().extend(bytes);
+    }
+
+    fn read(&self, len: usize) -> Vec<u8> {
+        let mut incoming = self.incoming.borrow_mut();
+        assert!(
+            incoming.len() >= len,
+            "expected {} bytes, {} were written",
+            len,
+            incoming.len()
+        );
+        incoming.drain(..len).collect()
+    }
+
+    fn is_drained(&self) -> bool {
+        self.incoming.borrow().is_empty()
+    }
+}
+
+// A pipe end past the handshake, carrying encrypted frames.
+struct Connection {
+    pipe: PipeEnd,
+    codec: NoiseCodec,
+}
+
+impl Connection {
+    // The header and the payload of the frame are encrypted separately, the payload in a single
+    // chunk as all the messages of the flow are small.
+    fn send(&mut self, message: MiningMessage) {
+        let frame = to_frame(message);
+        let (header, payload) = frame.split_at(FRAME_HEADER_SIZE);
+        assert!(payload.len() <= MAX_CHUNK_PLAINTEXT_SIZE);
+        for part in [header, payload].iter() {
+            let mut part = part.to_vec();
+            self.codec.encrypt(&mut part).unwrap();
+            self.pipe.write(&part);
+        }
+    }
+
+    // Returns the next plaintext frame, to be decoded with `from_frame`.
+    fn receive(&mut self) -> Vec<u8> {
+        let mut frame = self.pipe.read(encrypted_len(FRAME_HEADER_SIZE));
+        self.codec.decrypt(&mut frame).unwrap();
+        let len = u32::from_le_bytes([frame[3], frame[4], frame[5], 0]) as usize;
+        let mut payload = self.pipe.read(encrypted_len(len));
+        self.codec.decrypt(&mut payload).unwrap();
+        frame.extend_from_slice(&payload);
+        frame
+    }
+}
+
+fn authority() -> Keypair {
+    let secp = Secp256k1::new();
+    (1..=u8::MAX)
+        .map(|byte| Keypair::from_seckey_slice(&secp, &[byte; 32]).unwrap())
+        .find(|kp| kp.x_only_public_key().1 == Parity::Even)
+        .unwrap()
+}
+
+fn handshake() -> (Connection, Connection) {
+    let (client, server) = pipe();
+    let authority = authority();
+    let mut initiator = Initiator::new(Some(authority.x_only_public_key().0));
+    let mut responder = Responder::new(authority, 31449600);
+
+    client.write(&initiator.step_0().unwrap());
+    let (message, server_codec) = responder
+        .step_1(server.read(ELLSWIFT_ENCODING_SIZE).try_into().unwrap())
+        .unwrap();
+    server.write(&message);
+    let client_codec = initiator
+        .step_2(
+            client
+                .read(INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE)
+                .try_into()
+                .unwrap(),
+        )
+        .unwrap();
+
+    (
+        Connection {
+            pipe: client,
+            codec: client_codec,
+        },
+        Connection {
+            pipe: server,
+            codec: server_codec,
+        },
+    )
+}
+
+#[test]
+fn test_standard_channel_over_noise() {
+    let (mut client, mut server) = handshake();
+
+    // the client opens a channel
+    client.send(MiningMessage::OpenStandardMiningChannel(
+        OpenStandardMiningChannel {
+            request_id: 1.into(),
+            user_identity: "user_identity".to_string().try_into().unwrap(),
+            nominal_hash_rate: 1.0,
+            max_target: [0xff; 32].into(),
+        },
+    ));
+
+    let mut factory =
+        ServerChannelFactory::new(ExtendedExtranonce::new(0..0, 0..0, 0..32, None).unwrap())
+            .unwrap();
+    // every share is acknowledged
+    let policy = OpenChannelPolicy::new(1.0)
+        .with_share_accounting_config(ShareAccountingConfig::default().with_share_batch_size(1));
+    let mut frame = server.receive();
+    let (mut server_channel, success) = match from_frame(&mut frame).unwrap() {
+        MiningMessage::OpenStandardMiningChannel(request) => {
+            factory.open_standard_channel(request, &policy).unwrap()
+        }
+        other => panic!("expected OpenStandardMiningChannel, got {:?}", other),
+    };
+    server.send(MiningMessage::OpenStandardMiningChannelSuccess(success));
+
+    let mut frame = client.receive();
+    let mut client_channel = match from_frame(&mut frame).unwrap() {
+        MiningMessage::OpenStandardMiningChannelSuccess(success) => {
+            assert_eq!(success.get_request_id_as_u32(), 1);
+            ClientStandardChannel::new(
+                success.channel_id,
+                "user_identity".to_string(),
+                success.extranonce_prefix.inner_as_ref().to_vec(),
+                success.target.into(),
+                1.0,
+            )
+        }
+        other => panic!(
+            "expected OpenStandardMiningChannel.Success, got {:?}",
+            other
+        ),
+    };
+    assert_eq!(client_channel.get_channel_id(), 1);
+    assert_eq!(
+        client_channel.get_extranonce_prefix(),
+        server_channel.get_extranonce_prefix()
+    );
+
+    // the pool gets the future template and its prev hash, and hands out the job
+    server_channel
+        .on_new_template(fixture::template(true), fixture::coinbase_reward_outputs())
+        .unwrap();
+    server_channel
+        .on_set_new_prev_hash(fixture::set_new_prev_hash(1))
+        .unwrap();
+    let messages = server_channel.initial_downstream_messages();
+    assert_eq!(messages.len(), 2);
+    for message in messages {
+        server.send(match message {
+            DownstreamMessage::NewMiningJob(job) => MiningMessage::NewMiningJob(job),
+            DownstreamMessage::SetNewPrevHash(set_new_prev_hash) => {
+                MiningMessage::SetNewPrevHash(set_new_prev_hash)
+            }
+        });
+    }
+
+    for _ in 0..2 {
+        let mut frame = client.receive();
+        match from_frame(&mut frame).unwrap() {
+            MiningMessage::NewMiningJob(job) => {
+                client_channel.on_new_mining_job(job.into_static());
+            }
+            MiningMessage::SetNewPrevHash(set_new_prev_hash) => client_channel
+                .on_set_new_prev_hash(set_new_prev_hash.into_static())
+                .unwrap(),
+            other => panic!("expected a job or its prev hash, got {:?}", other),
+        }
+    }
+
+    // the client submits a share meeting the channel target, the first one of the job being at
+    // nonce 61
+    let job = client_channel.get_active_job().unwrap().clone();
+    assert_eq!(job.job_id, 1);
+    let share = client_channel
+        .build_share(&job, 61, fixture::NTIME, job.version, 0)
+        .unwrap();
+    client_channel.precheck_against_target(&share).unwrap();
+    client.send(MiningMessage::SubmitSharesStandard(share));
+    client_channel.on_share_submitted(0);
+
+    let mut frame = server.receive();
+    let share = match from_frame(&mut frame).unwrap() {
+        MiningMessage::SubmitSharesStandard(share) => share,
+        other => panic!("expected SubmitSharesStandard, got {:?}", other),
+    };
+    let success = match server_channel.validate_share(share) {
+        Ok(ShareValidationResult::ValidWithAcknowledgement(
+            last_sequence_number,
+            new_submits_accepted_count,
+            new_shares_sum,
+        )) => SubmitSharesSuccess {
+            channel_id: server_channel.get_channel_id(),
+            last_sequence_number,
+            new_submits_accepted_count,
+            new_shares_sum,
+        },
+        other => panic!("expected an acknowledged share, got {:?}", other),
+    };
+    server.send(MiningMessage::SubmitSharesSuccess(success));
+
+    let mut frame = client.receive();
+    match from_frame(&mut frame).unwrap() {
+        MiningMessage::SubmitSharesSuccess(success) => {
+            assert_eq!(success.channel_id, 1);
+            assert_eq!(success.last_sequence_number, 0);
+            assert_eq!(success.new_submits_accepted_count, 1);
+            assert_eq!(
+                success.new_shares_sum,
+                server_channel.get_share_accounting().get_share_work_sum()
+            );
+            client_channel.on_submit_shares_success(&success);
+        }
+        other => panic!("expected SubmitShares.Success, got {:?}", other),
+    }
+    assert_eq!(client_channel.get_submit_stats().get_accepted(), 1);
+    assert_eq!(client_channel.reject_rate(1), Some(0.0));
+
+    assert!(client.pipe.is_drained());
+    assert!(server.pipe.is_drained());
+}
@@ -0,0 +1,147 @@
+// Migrates a Standard Channel to a new instance through `export_state` and `import_state`, as a
+// Mining Server moving a channel to another process would, and checks it keeps validating shares
+// where the original one left off.
+//
+// The messages were collected from a sane message flow, and are used as test vectors.
+use bitcoin::{Amount, ScriptBuf};
+use channels_sv2::{
+    prelude::*,
+    server::standard::{ChannelState, CHANNEL_STATE_VERSION},
+    target::{hex_to_u256, u256_from_hex_be},
+};
+use std::convert::TryInto;
+
+const SATS_AVAILABLE_IN_TEMPLATE: u64 = 5000000000;
+
+fn template(template_id: u64, future_template: bool) -> NewTemplate<'static> {
+    NewTemplate {
+        template_id,
+        future_template,
+        version: 536870912,
+        coinbase_tx_version: 2,
+        coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
+        coinbase_tx_input_sequence: 4294967294,
+        coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
+        coinbase_tx_outputs_count: 1,
+        coinbase_tx_outputs: vec![
+            0, 0, 0, 0, 0, 0, 0, 0, 38, 106, 36, 170, 33, 169, 237, 226, 246, 28, 63, 113, 209,
+            222, 253, 63, 169, 153, 223, 163, 105, 83, 117, 92, 105, 6, 137, 121, 153, 98, 180,
+            139, 235, 216, 54, 151, 78, 140, 249,
+        ]
+        .try_into()
+        .unwrap(),
+        coinbase_tx_locktime: 158,
+        merkle_path: vec![].try_into().unwrap(),
+    }
+}
+
+// a single P2WPKH output, paying the whole value of the template
+fn coinbase_reward_outputs() -> Vec<TxOut> {
+    let pubkey_hash = [
+        235, 225, 183, 220, 194, 147, 204, 170, 14, 231, 67, 168, 111, 137, 223, 130, 88, 194, 8,
+        252,
+    ];
+    let mut script_bytes = vec![0]; // SegWit version 0
+    script_bytes.push(20); // Push 20 bytes (length of pubkey hash)
+    script_bytes.extend_from_slice(&pubkey_hash);
+    vec![TxOut {
+        value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
+        script_pubkey: ScriptBuf::from(script_bytes),
+    }]
+}
+
+fn import(state: ChannelState) -> Result<StandardChannel<'static>, StandardChannelError> {
+    StandardChannel::import_state(state, Box::new(DefaultJobStore::<StandardJob>::new()))
+}
+
+#[test]
+fn export_import_state() {
+    let standard_channel_id = 1;
+    let mut standard_channel = StandardChannel::from_config(
+        StandardChannelConfig::default()
+            .channel_id(standard_channel_id)
+            .user_identity("user_identity")
+            .extranonce_prefix(vec![
+                83, 116, 114, 97, 116, 117, 109, 32, 86, 50, 32, 83, 82, 73, 32, 80, 111, 111, 108,
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+            ])
+            .requested_max_target(Target::MAX)
+            .nominal_hashrate(1_000.0) // bigger hashrate to get higher difficulty
+            .share_batch_size(100)
+            .expected_share_per_minute(1.0),
+        Box::new(DefaultJobStore::<StandardJob>::new()),
+    )
+    .unwrap();
+
+    // channel target is:
+    // 0001179d9861a761ffdadd11c307c4fc04eea3a418f7d687584e4434af158205
+
+    // the job is created on a future template, activated by the chain tip
+    standard_channel
+        .on_new_template(template(1, true), coinbase_reward_outputs())
+        .unwrap();
+    standard_channel
+        .on_set_new_prev_hash(SetNewPrevHashTdp {
+            template_id: 1,
+            prev_hash: hex_to_u256(
+                "9a7cefe7dd7aa0ada4af57214ad6bf6b492200a2e3102c282149000000000000",
+            )
+            .unwrap(),
+            header_timestamp: 1745596910,
+            n_bits: 453040064,
+            // network target
+            target: u256_from_hex_be(
+                "000000000000d7c0000000000000000000000000000000000000000000000000",
+            )
+            .unwrap(),
+        })
+        .unwrap();
+
+    // this share has hash 000010dcb838b589e5b0365350425ea82f368d330616f783d32dadf9b497bd02
+    // which does meet the channel target but does not meet the network target
+    let valid_share = SubmitSharesStandard {
+        channel_id: standard_channel_id,
+        sequence_number: 1,
+        job_id: 1,
+        nonce: 31978,
+        ntime: 1745611105,
+        version: 536870912,
+    };
+
+    // migrate the channel to a new instance
+    let state = standard_channel.export_state();
+    assert_eq!(state.version, CHANNEL_STATE_VERSION);
+    assert_eq!(state.last_job_id, 1);
+
+    let mut imported_channel = import(state.clone()).unwrap();
+    assert_eq!(imported_channel.export_state(), state);
+    assert_eq!(
+        imported_channel.get_active_job().unwrap().get_job_message(),
+        standard_channel.get_active_job().unwrap().get_job_message()
+    );
+
+    // the share was mined on the job created before the migration
+    let res = imported_channel.validate_share(valid_share.clone());
+    assert!(matches!(res, Ok(ShareValidationResult::Valid)));
+
+    // share accounting is migrated along with the channel
+    let state = imported_channel.export_state();
+    let mut imported_channel = import(state.clone()).unwrap();
+    let res = imported_channel.validate_share(valid_share);
+    assert!(matches!(res, Err(ShareValidationError::DuplicateShare)));
+
+    // new jobs don't collide with the migrated ones
+    imported_channel
+        .on_new_template(template(2, false), coinbase_reward_outputs())
+        .unwrap();
+    assert_eq!(imported_channel.get_active_job().unwrap().get_job_id(), 2);
+    assert!(imported_channel.get_past_jobs().contains_key(&1));
+
+    // snapshots from newer versions are rejected
+    let mut newer_state = state;
+    newer_state.version = CHANNEL_STATE_VERSION + 1;
+    assert!(matches!(
+        import(newer_state),
+        Err(StandardChannelError::UnsupportedStateVersion(v)) if v == CHANNEL_STATE_VERSION + 1
+    ));
+}
@@ -0,0 +1,126 @@
+// Measures the heap memory the jobs of many channels take for a single template.
+//
+// The counting allocator is global to this test binary, so it only counts on the thread that
+// enabled it.
+use bitcoin::{transaction::TxOut, Amount, ScriptBuf};
+use channels_sv2::server::{
+    channel_set::ChannelSet,
+    jobs::{job_store::DefaultJobStore, standard::StandardJob},
+    standard::{StandardChannel, StandardChannelConfig},
+};
+use mining_sv2::Target;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    convert::TryInto,
+};
+use template_distribution_sv2::NewTemplate;
+
+struct CountingAllocator;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    // bytes allocated minus bytes freed while counting
+    static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+}
+
+fn count(delta: isize) {
+    // `try_with`, as thread locals may already be destroyed while a thread exits
+    let _ = COUNTING.try_with(|counting| {
+        if counting.get() {
+            let _ = LIVE_BYTES.try_with(|live_bytes| live_bytes.set(live_bytes.get() + delta));
+        }
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size() as isize);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count(-(layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Returns the heap memory `f` allocated and did not free.
+fn retained_bytes(f: impl FnOnce()) -> usize {
+    LIVE_BYTES.with(|live_bytes| live_bytes.set(0));
+    COUNTING.with(|counting| counting.set(true));
+    f();
+    COUNTING.with(|counting| counting.set(false));
+    LIVE_BYTES.with(|live_bytes| live_bytes.get()) as usize
+}
+
+const CHANNELS: u32 = 1000;
+const SATS_AVAILABLE_IN_TEMPLATE: u64 = 5000000000;
+
+// A template for a block of `2^merkle_path_len` transactions.
+fn template(merkle_path_len: u8) -> NewTemplate<'static> {
+    NewTemplate {
+        template_id: 1,
+        future_template: true,
+        version: 536870912,
+        coinbase_tx_version: 2,
+        coinbase_prefix: vec![2, 159, 0, 0].try_into().unwrap(),
+        coinbase_tx_input_sequence: 4294967294,
+        coinbase_tx_value_remaining: SATS_AVAILABLE_IN_TEMPLATE,
+        coinbase_tx_outputs_count: 0,
+        coinbase_tx_outputs: vec![].try_into().unwrap(),
+        coinbase_tx_locktime: 158,
+        merkle_path: (0..merkle_path_len)
+            .map(|i| [i; 32].into())
+            .collect::<Vec<_>>()
+            .into(),
+    }
+}
+
+// Returns the heap memory retained per channel once `template` is applied to `CHANNELS` channels.
+fn bytes_per_channel(template: NewTemplate<'static>) -> usize {
+    let mut channel_set = ChannelSet::new();
+    for channel_id in 1..=CHANNELS {
+        let mut extranonce_prefix = vec![0; 32];
+        extranonce_prefix[28..].copy_from_slice(&channel_id.to_be_bytes());
+        let channel = StandardChannel::from_config(
+            StandardChannelConfig::default()
+                .channel_id(channel_id)
+                .user_identity("user_identity")
+                .extranonce_prefix(extranonce_prefix)
+                .requested_max_target(Target::MAX)
+                .nominal_hashrate(10.0)
+                .expected_share_per_minute(1.0),
+            Box::new(DefaultJobStore::<StandardJob>::new()),
+        )
+        .unwrap();
+        channel_set.insert(channel);
+    }
+    let coinbase_reward_outputs = vec![TxOut {
+        value: Amount::from_sat(SATS_AVAILABLE_IN_TEMPLATE),
+        script_pubkey: ScriptBuf::from(vec![0; 22]),
+    }];
+
+    let bytes = retained_bytes(|| {
+        let result = channel_set.on_new_template(template, coinbase_reward_outputs);
+        assert_eq!(result.jobs.len(), CHANNELS as usize);
+    });
+    bytes / CHANNELS as usize
+}
+
+#[test]
+fn test_channels_share_the_template() {
+    // the 50 hashes of the merkle path take 1600 bytes, which would be copied into the job of
+    // every channel if each held its own template
+    let small_template = bytes_per_channel(template(0));
+    let large_template = bytes_per_channel(template(50));
+    assert!(
+        large_template < small_template + 32,
+        "{} bytes per channel for the large template, {} for the small one",
+        large_template,
+        small_template
+    );
+}
@@ -39,9 +39,9 @@ use framing_sv2::{
 #[cfg(feature = "noise_sv2")]
 use framing_sv2::{ENCRYPTED_SV2_FRAME_HEADER_SIZE, SV2_FRAME_CHUNK_SIZE, SV2_FRAME_HEADER_SIZE};
 #[cfg(feature = "noise_sv2")]
-use noise_sv2::NoiseCodec;
-#[cfg(feature = "noise_sv2")]
 use noise_sv2::NOISE_FRAME_HEADER_SIZE;
+#[cfg(feature = "noise_sv2")]
+use noise_sv2::{Error as NoiseError, NoiseCodec};
 
 #[cfg(feature = "noise_sv2")]
 use crate::error::Error;
@@ -249,7 +249,13 @@ impl<'a, T: Serialize + GetSize + Deserialize<'a>, B: IsBuffer + AeadBuffer> Wit
                     .get_writable(ENCRYPTED_SV2_FRAME_HEADER_SIZE);
                 decrypted_header.copy_from_slice(src.as_ref());
                 self.sv2_buffer.as_ref();
-                noise_codec.decrypt(&mut self.sv2_buffer)?;
+                // tells a peer sending plaintext frames apart from a corrupted header
+                noise_codec
+                    .decrypt_frame(&mut self.sv2_buffer)
+                    .map_err(|e| match e {
+                        NoiseError::AesGcm(e) => Error::AeadError(e),
+                        e => Error::NoiseSv2Error(e),
+                    })?;
                 let header =
                     Header::from_bytes(self.sv2_buffer.get_data_by_ref(SV2_FRAME_HEADER_SIZE))?;
                 self.missing_noise_b = header.encrypted_len();
@@ -24,8 +24,7 @@ use alloc::vec::Vec;
 use binary_sv2::{binary_codec_sv2, Deserialize, Serialize, U24};
 use core::convert::TryInto;
 
-use crate::{SV2_FRAME_CHUNK_SIZE, SV2_FRAME_HEADER_SIZE};
-use noise_sv2::AEAD_MAC_LEN;
+use crate::SV2_FRAME_HEADER_SIZE;
 
 /// Abstraction for a Sv2 Frame Header.
 #[derive(Debug, Serialize, Deserialize, Copy, Clone)]
@@ -114,15 +113,9 @@ impl Header {
     /// their total length.
     ///
     /// The calculated length includes the full payload length and any additional space required
-    /// for the MACs.
+    /// for the MACs, see [`noise_sv2::encrypted_len`].
     pub fn encrypted_len(&self) -> usize {
-        let len = self.len();
-        let mut chunks = len / (SV2_FRAME_CHUNK_SIZE - AEAD_MAC_LEN);
-        if len % (SV2_FRAME_CHUNK_SIZE - AEAD_MAC_LEN) != 0 {
-            chunks += 1;
-        }
-        let mac_len = chunks * AEAD_MAC_LEN;
-        len + mac_len
+        noise_sv2::encrypted_len(self.len())
     }
 }
 
@@ -69,5 +69,5 @@ pub const SV2_FRAME_HEADER_SIZE: usize = 6;
 /// Size of the encrypted SV2 frame header, including the MAC.
 pub const ENCRYPTED_SV2_FRAME_HEADER_SIZE: usize = SV2_FRAME_HEADER_SIZE + AEAD_MAC_LEN;
 
-/// Maximum size of an SV2 frame chunk in bytes.
-pub const SV2_FRAME_CHUNK_SIZE: usize = 65535;
+/// Maximum size of an SV2 frame chunk in bytes, see [`noise_sv2::MAX_CHUNK_SIZE`].
+pub const SV2_FRAME_CHUNK_SIZE: usize = noise_sv2::MAX_CHUNK_SIZE;
@@ -0,0 +1,46 @@
+// Responder side of the handshake, as run for every incoming connection:
+//
+// cargo bench -p noise_sv2
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use noise_sv2::{Initiator, Responder};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use secp256k1::{Keypair, Parity, Secp256k1};
+
+fn authority() -> Keypair {
+    let secp = Secp256k1::new();
+    let mut rng = ChaCha20Rng::seed_from_u64(1);
+    loop {
+        let (secret_key, _) = secp.generate_keypair(&mut rng);
+        let kp = Keypair::from_secret_key(&secp, &secret_key);
+        if kp.x_only_public_key().1 == Parity::Even {
+            return kp;
+        }
+    }
+}
+
+fn step_1(c: &mut Criterion) {
+    let authority = authority();
+    let mut rng = ChaCha20Rng::seed_from_u64(2);
+    c.bench_function("step_1_with_now_rng", |b| {
+        b.iter_batched(
+            || {
+                let mut initiator =
+                    Initiator::new_with_rng(Some(authority.public_key().into()), &mut rng);
+                let first_message = initiator.step_0().unwrap();
+                let responder = Responder::new_with_rng(authority, 31449600, &mut rng);
+                (responder, first_message)
+            },
+            |(mut responder, first_message)| {
+                let mut rng = ChaCha20Rng::seed_from_u64(3);
+                responder
+                    .step_1_with_now_rng(first_message, 1_700_000_000, &mut rng)
+                    .unwrap()
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, step_1);
+criterion_main!(benches);
@@ -0,0 +1,99 @@
+// # Responder Certificate
+//
+// Details of the certificate presented by the [`crate::Responder`] during the handshake, as
+// verified by the [`crate::Initiator`]. Meant to be displayed to operators (e.g. "connected to
+// pool X, certificate valid until <date>, authority key <fingerprint>").
+
+use core::fmt;
+
+use secp256k1::{
+    hashes::{sha256::Hash as Sha256Hash, Hash},
+    XOnlyPublicKey,
+};
+
+use crate::{delegation::Delegation, signature_message::SignatureNoiseMessage};
+
+/// Size in bytes of a [`KeyFingerprint`].
+pub const KEY_FINGERPRINT_SIZE: usize = 8;
+
+/// A short, stable identifier of a public key.
+///
+/// Made of the first 8 bytes of the SHA256 of the 32 bytes x-only serialization of the key, or of
+/// the 64 bytes ElligatorSwift encoding for ephemeral keys. It's displayed as 16 lowercase hex
+/// characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyFingerprint([u8; KEY_FINGERPRINT_SIZE]);
+
+impl KeyFingerprint {
+    pub fn from_key(key: &XOnlyPublicKey) -> Self {
+        Self::from_bytes(&key.serialize())
+    }
+
+    // Fingerprint of arbitrary key material, e.g. a transport key.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        let hash = Sha256Hash::hash(bytes).to_byte_array();
+        let mut fingerprint = [0; KEY_FINGERPRINT_SIZE];
+        fingerprint.copy_from_slice(&hash[..KEY_FINGERPRINT_SIZE]);
+        Self(fingerprint)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; KEY_FINGERPRINT_SIZE] {
+        &self.0
+    }
+}
+
+impl fmt::Display for KeyFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// The certificate of the responder, as received by the initiator during the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponderCertificate {
+    /// Version of the certificate format.
+    pub version: u16,
+    /// Start of the validity period, as a Unix timestamp.
+    pub valid_from: u32,
+    /// End of the validity period, as a Unix timestamp.
+    pub not_valid_after: u32,
+    /// Fingerprint of the responder static key.
+    pub static_key_fingerprint: KeyFingerprint,
+    /// Fingerprint of the authority key that signed the certificate.
+    ///
+    /// `None` if the initiator doesn't know the authority key, in which case the certificate
+    /// signature is not verified.
+    pub authority_key_fingerprint: Option<KeyFingerprint>,
+    /// Position of the authority key that signed the certificate in the list of keys the
+    /// initiator was configured with, e.g. to notice clients still relying on an outgoing key
+    /// after a rotation.
+    ///
+    /// `None` under the same conditions as `authority_key_fingerprint`.
+    pub authority_key_index: Option<usize>,
+    /// Fingerprint of the delegate key that signed the certificate on behalf of the authority,
+    /// for certificates of version [`crate::DELEGATED_CERTIFICATE_VERSION`].
+    pub delegate_key_fingerprint: Option<KeyFingerprint>,
+}
+
+impl ResponderCertificate {
+    pub(crate) fn new(
+        signature_message: &SignatureNoiseMessage,
+        static_key: &XOnlyPublicKey,
+        authority_key: Option<(usize, &XOnlyPublicKey)>,
+        delegation: Option<&Delegation>,
+    ) -> Self {
+        Self {
+            version: signature_message.version,
+            valid_from: signature_message.valid_from,
+            not_valid_after: signature_message.not_valid_after,
+            static_key_fingerprint: KeyFingerprint::from_key(static_key),
+            authority_key_fingerprint: authority_key.map(|(_, key)| KeyFingerprint::from_key(key)),
+            authority_key_index: authority_key.map(|(index, _)| index),
+            delegate_key_fingerprint: delegation
+                .map(|delegation| KeyFingerprint::from_key(&delegation.delegate_key())),
+        }
+    }
+}
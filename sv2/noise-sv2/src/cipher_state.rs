@@ -38,30 +38,65 @@ use core::ptr;
 use crate::aed_cipher::AeadCipher;
 use aes_gcm::Aes256Gcm;
 use chacha20poly1305::{aead::Buffer, ChaCha20Poly1305};
+use zeroize::Zeroizing;
+
+// Installs `k` in the key slot of a [`CipherState`] implementation, erasing the key it replaces.
+pub(crate) fn install_key(slot: &mut Option<[u8; 32]>, k: [u8; 32]) {
+    erase_key(slot);
+    *slot = Some(k);
+}
+
+// Removes the key from the key slot of a [`CipherState`] implementation, see
+// [`CipherState::take_key`].
+pub(crate) fn take_key(slot: &mut Option<[u8; 32]>) -> Option<Zeroizing<[u8; 32]>> {
+    let k = slot.map(Zeroizing::new);
+    erase_key(slot);
+    k
+}
+
+// Overwrites the key in `slot` with zeros and leaves the slot empty.
+pub(crate) fn erase_key(slot: &mut Option<[u8; 32]>) {
+    if let Some(k) = slot.as_mut() {
+        for b in k {
+            unsafe { ptr::write_volatile(b, 0) };
+        }
+    }
+    *slot = None;
+}
 
 // The `CipherState` trait manages AEAD ciphers for secure communication, handling the encryption
 // key, nonce, and cipher instance. It supports encryption and decryption with ciphers like
 // [`ChaCha20Poly1305`] and [`Aes256Gcm`], ensuring proper key and nonce management.
 //
 // Key responsibilities:
-// - **Key management**: Set and retrieve the 32-byte encryption key.
+// - **Key management**: Install and remove the 32-byte encryption key, which is never handed out
+//   by reference.
 // - **Nonce management**: Track unique nonces for encryption operations.
 // - **Cipher handling**: Initialize and manage AEAD ciphers for secure data encryption.
 //
 // Used in protocols like Noise, `CipherState` ensures secure communication by managing
 // cryptographic material during and after handshakes.
+//
+// Migrating from `get_k`/`set_k`: check for a key with `has_key` instead of `get_k().is_some()`,
+// set one with `install_key(k)` instead of `set_k(Some(k))`, and remove it with `take_key()`
+// instead of `set_k(None)`. Implementations keep the key in a private `Option<[u8; 32]>` and
+// forward to the `install_key`, `take_key` and `erase_key` functions of this module.
 pub trait CipherState<Cipher_: AeadCipher>
 where
     Self: Sized,
 {
-    // Retrieves a mutable reference to the 32-byte encryption key (`k`).
-    fn get_k(&mut self) -> &mut Option<[u8; 32]>;
+    // Whether an encryption key (`k`) is installed.
+    fn has_key(&self) -> bool;
+
+    // Installs the 32-byte encryption key (`k`), erasing the previous one.
+    //
+    // Typically called after the key has been derived during the handshake process.
+    fn install_key(&mut self, k: [u8; 32]);
 
-    // Sets the 32-byte encryption key to the optionally provided value (`k`).
+    // Removes the encryption key (`k`), if any.
     //
-    // Allows the encryption key to be explicitly set, typically after it has been derived or
-    // initialized during the handshake process. If `None`, the encryption key is unset.
-    fn set_k(&mut self, k: Option<[u8; 32]>);
+    // The key is wiped from the cipher state, and the returned copy is erased once dropped.
+    fn take_key(&mut self) -> Option<Zeroizing<[u8; 32]>>;
 
     // Retrieves the current nonce (`n`) used for encryption.
     //
@@ -97,17 +132,15 @@ where
 
     #[allow(dead_code)]
     fn into_aesg(mut self) -> Option<Cipher<Aes256Gcm>> {
-        #[allow(clippy::clone_on_copy)]
-        let k = self.get_k().clone()?;
-        let c = Aes256Gcm::from_key(k);
+        let k = self.take_key()?;
+        let c = Aes256Gcm::from_key(*k);
         Some(Cipher::from_cipher(c))
     }
 
     #[allow(dead_code)]
     fn into_chacha(mut self) -> Option<Cipher<ChaCha20Poly1305>> {
-        #[allow(clippy::clone_on_copy)]
-        let k = self.get_k().clone()?;
-        let c = ChaCha20Poly1305::from_key(k);
+        let k = self.take_key()?;
+        let c = ChaCha20Poly1305::from_key(*k);
         Some(Cipher::from_cipher(c))
     }
 
@@ -217,6 +250,15 @@ impl GenericCipher {
         }
     }
 
+    // Retrieves the current nonce, whatever the underlying cipher.
+    #[cfg(feature = "insecure-debug")]
+    pub fn nonce(&self) -> u64 {
+        match self {
+            GenericCipher::ChaCha20Poly1305(c) => c.get_n(),
+            GenericCipher::Aes256Gcm(c) => c.get_n(),
+        }
+    }
+
     // Securely erases the encryption key (`k`) from memory.
     //
     // Overwrites the encryption key stored within the [`GenericCipher`] with zeros and sets it to
@@ -224,22 +266,8 @@ impl GenericCipher {
     // no longer needed.
     pub fn erase_k(&mut self) {
         match self {
-            GenericCipher::ChaCha20Poly1305(c) => {
-                if let Some(k) = c.k.as_mut() {
-                    for b in k {
-                        unsafe { ptr::write_volatile(b, 0) };
-                    }
-                    c.k = None;
-                }
-            }
-            GenericCipher::Aes256Gcm(c) => {
-                if let Some(k) = c.k.as_mut() {
-                    for b in k {
-                        unsafe { ptr::write_volatile(b, 0) };
-                    }
-                    c.k = None;
-                }
-            }
+            GenericCipher::ChaCha20Poly1305(c) => erase_key(&mut c.k),
+            GenericCipher::Aes256Gcm(c) => erase_key(&mut c.k),
         }
     }
 
@@ -247,7 +275,7 @@ impl GenericCipher {
     pub fn into_aesg(mut self) -> GenericCipher {
         match &mut self {
             GenericCipher::ChaCha20Poly1305(c) => {
-                let c = Cipher::from_cipher(Aes256Gcm::from_key(c.get_k().unwrap()));
+                let c = Cipher::from_cipher(Aes256Gcm::from_key(*c.take_key().unwrap()));
                 self.erase_k();
                 GenericCipher::Aes256Gcm(c)
             }
@@ -260,16 +288,23 @@ impl GenericCipher {
 }
 
 impl CipherState<Aes256Gcm> for GenericCipher {
-    fn get_k(&mut self) -> &mut Option<[u8; 32]> {
+    fn has_key(&self) -> bool {
         match self {
-            GenericCipher::Aes256Gcm(c) => c.get_k(),
+            GenericCipher::Aes256Gcm(c) => c.has_key(),
             _ => unreachable!(),
         }
     }
 
-    fn set_k(&mut self, k: Option<[u8; 32]>) {
+    fn install_key(&mut self, k: [u8; 32]) {
         match self {
-            GenericCipher::Aes256Gcm(c) => c.set_k(k),
+            GenericCipher::Aes256Gcm(c) => c.install_key(k),
+            _ => unreachable!(),
+        }
+    }
+
+    fn take_key(&mut self) -> Option<Zeroizing<[u8; 32]>> {
+        match self {
+            GenericCipher::Aes256Gcm(c) => c.take_key(),
             _ => unreachable!(),
         }
     }
@@ -346,8 +381,14 @@ impl<C: AeadCipher> Cipher<C> {
 }
 
 impl<C: AeadCipher> CipherState<C> for Cipher<C> {
-    fn get_k(&mut self) -> &mut Option<[u8; 32]> {
-        &mut self.k
+    fn has_key(&self) -> bool {
+        self.k.is_some()
+    }
+    fn install_key(&mut self, k: [u8; 32]) {
+        install_key(&mut self.k, k);
+    }
+    fn take_key(&mut self) -> Option<Zeroizing<[u8; 32]>> {
+        take_key(&mut self.k)
     }
     fn get_n(&self) -> u64 {
         self.n
@@ -358,8 +399,4 @@ impl<C: AeadCipher> CipherState<C> for Cipher<C> {
     fn get_cipher(&mut self) -> &mut Option<C> {
         &mut self.cipher
     }
-
-    fn set_k(&mut self, k: Option<[u8; 32]>) {
-        self.k = k;
-    }
 }
@@ -0,0 +1,31 @@
+// # Codec Debug State
+//
+// Redacted snapshot of the state of a [`crate::NoiseCodec`], only available with the
+// `insecure-debug` feature.
+//
+// When two implementations desync after the handshake, comparing the decryptor of one side with
+// the encryptor of the other tells whether the transport keys differ or whether the nonces have
+// diverged (e.g. because a frame was dropped). Keys are never exposed: each direction is
+// identified by the [`KeyFingerprint`] of its key.
+
+use crate::certificate::KeyFingerprint;
+
+/// State of one direction of a [`crate::NoiseCodec`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CipherDebugState {
+    /// Fingerprint of the transport key.
+    pub key_fingerprint: KeyFingerprint,
+    /// Nonce that will be used by the next encryption or decryption.
+    pub nonce: u64,
+}
+
+/// State of both directions of a [`crate::NoiseCodec`].
+///
+/// The `encryptor` of one side is expected to match the `decryptor` of its counterpart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodecDebugState {
+    /// State of the cipher encrypting outgoing messages.
+    pub encryptor: CipherDebugState,
+    /// State of the cipher decrypting incoming messages.
+    pub decryptor: CipherDebugState,
+}
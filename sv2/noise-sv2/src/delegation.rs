@@ -0,0 +1,199 @@
+// # Certificate Delegation
+//
+// Lets an authority key, kept offline, delegate the signing of responder certificates to an
+// intermediate per-server key, so that the authority secret key doesn't have to live on every
+// edge server.
+//
+// The authority signs a [`Delegation`] for the intermediate key once, offline. The
+// [`crate::Responder`] is then constructed with the intermediate keypair and the delegation: it
+// signs its certificates with the intermediate key, announces it with
+// [`DELEGATED_CERTIFICATE_VERSION`] and appends the delegation to the `SIGNATURE_NOISE_MESSAGE`.
+// The [`crate::Initiator`] verifies the chain: the certificate against the intermediate key, and
+// the delegation against the authority keys it was configured with.
+//
+// ## Format
+//
+// A delegation is [`DELEGATION_SIZE`] bytes long:
+//
+// | Field           | Size | Description                                                      |
+// |-----------------|------|------------------------------------------------------------------|
+// | valid_from      | 4    | Start of the validity period, as a little endian Unix timestamp  |
+// | not_valid_after | 4    | End of the validity period, as a little endian Unix timestamp    |
+// | delegate_key    | 32   | x-only serialization of the intermediate public key              |
+// | signature       | 64   | Schnorr signature of the authority over the fields above         |
+//
+// The signed message is `SHA-256(DELEGATION_TAG || valid_from || not_valid_after ||
+// delegate_key)`. The tag keeps it from ever matching the message signed for a certificate.
+
+use core::convert::TryInto;
+
+use secp256k1::{
+    hashes::{sha256, Hash, HashEngine},
+    schnorr::Signature,
+    Keypair, Message, Secp256k1, XOnlyPublicKey,
+};
+
+use crate::{error::Error, signature_message::sign_schnorr_with_rng};
+
+/// Size in bytes of a serialized [`Delegation`].
+pub const DELEGATION_SIZE: usize = 104;
+
+/// Version of the responder certificates signed by a delegate key, which carry a [`Delegation`]
+/// after the `SIGNATURE_NOISE_MESSAGE`.
+pub const DELEGATED_CERTIFICATE_VERSION: u16 = 1;
+
+const DELEGATION_TAG: &[u8] = b"Sv2 certificate delegation";
+
+/// An authority signature allowing an intermediate (delegate) key to sign responder certificates
+/// on its behalf, for a limited time.
+///
+/// See [`crate::Responder::with_delegation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delegation {
+    valid_from: u32,
+    not_valid_after: u32,
+    delegate_key: XOnlyPublicKey,
+    signature: [u8; 64],
+}
+
+impl Delegation {
+    /// Signs a delegation to `delegate_key` with the `authority` keypair, valid from
+    /// `valid_from` to `not_valid_after` (Unix timestamps).
+    #[cfg(feature = "std")]
+    pub fn sign(
+        authority: &Keypair,
+        delegate_key: &XOnlyPublicKey,
+        valid_from: u32,
+        not_valid_after: u32,
+    ) -> Self {
+        Self::sign_with_rng(
+            authority,
+            delegate_key,
+            valid_from,
+            not_valid_after,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    /// Signs a delegation with a custom random number generator.
+    ///
+    /// See [`Self::sign`] for more details.
+    pub fn sign_with_rng<R: rand::Rng + rand::CryptoRng>(
+        authority: &Keypair,
+        delegate_key: &XOnlyPublicKey,
+        valid_from: u32,
+        not_valid_after: u32,
+        rng: &mut R,
+    ) -> Self {
+        let m = Self::message(valid_from, not_valid_after, delegate_key);
+        let signature = sign_schnorr_with_rng(&m, authority, rng);
+        Self {
+            valid_from,
+            not_valid_after,
+            delegate_key: *delegate_key,
+            signature: *signature.as_ref(),
+        }
+    }
+
+    /// Parses a serialized delegation, failing with [`Error::InvalidRawPublicKey`] if the
+    /// delegate key is not a valid x-only public key.
+    ///
+    /// The signature is only checked by [`Self::verify_with_now`].
+    pub fn from_bytes(bytes: &[u8; DELEGATION_SIZE]) -> Result<Self, Error> {
+        let delegate_key =
+            XOnlyPublicKey::from_slice(&bytes[8..40]).map_err(|_| Error::InvalidRawPublicKey)?;
+        Ok(Self {
+            valid_from: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            not_valid_after: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            delegate_key,
+            signature: bytes[40..].try_into().unwrap(),
+        })
+    }
+
+    /// Serializes the delegation, e.g. to ship it to the server along with the delegate keypair.
+    pub fn to_bytes(&self) -> [u8; DELEGATION_SIZE] {
+        let mut bytes = [0; DELEGATION_SIZE];
+        bytes[0..4].copy_from_slice(&self.valid_from.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.not_valid_after.to_le_bytes());
+        bytes[8..40].copy_from_slice(&self.delegate_key.serialize());
+        bytes[40..].copy_from_slice(&self.signature);
+        bytes
+    }
+
+    /// The key allowed to sign responder certificates.
+    pub fn delegate_key(&self) -> XOnlyPublicKey {
+        self.delegate_key
+    }
+
+    /// Start of the validity period, as a Unix timestamp.
+    pub fn valid_from(&self) -> u32 {
+        self.valid_from
+    }
+
+    /// End of the validity period, as a Unix timestamp.
+    pub fn not_valid_after(&self) -> u32 {
+        self.not_valid_after
+    }
+
+    /// Checks that the delegation is valid at `now` and signed by `authority_pk`.
+    pub fn verify_with_now(&self, authority_pk: &XOnlyPublicKey, now: u32) -> bool {
+        if self.valid_from > now || self.not_valid_after < now {
+            return false;
+        }
+        let m = Self::message(self.valid_from, self.not_valid_after, &self.delegate_key);
+        match Signature::from_slice(&self.signature) {
+            Ok(s) => Secp256k1::verification_only()
+                .verify_schnorr(&s, &m, authority_pk)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    // m = SHA-256(DELEGATION_TAG || valid_from || not_valid_after || delegate_key)
+    fn message(valid_from: u32, not_valid_after: u32, delegate_key: &XOnlyPublicKey) -> Message {
+        let mut engine = sha256::Hash::engine();
+        engine.input(DELEGATION_TAG);
+        engine.input(&valid_from.to_le_bytes());
+        engine.input(&not_valid_after.to_le_bytes());
+        engine.input(&delegate_key.serialize());
+        Message::from_digest(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handshake::HandshakeOp, responder::Responder};
+
+    #[test]
+    fn test_delegation_round_trip() {
+        let authority = Responder::generate_key_with_rng(&mut rand::thread_rng());
+        let delegate = Responder::generate_key_with_rng(&mut rand::thread_rng());
+        let delegation = Delegation::sign_with_rng(
+            &authority,
+            &delegate.x_only_public_key().0,
+            10,
+            20,
+            &mut rand::thread_rng(),
+        );
+
+        let parsed = Delegation::from_bytes(&delegation.to_bytes()).unwrap();
+        assert_eq!(parsed, delegation);
+        assert_eq!(parsed.delegate_key(), delegate.x_only_public_key().0);
+        assert_eq!((parsed.valid_from(), parsed.not_valid_after()), (10, 20));
+
+        let authority_pk = authority.x_only_public_key().0;
+        assert!(parsed.verify_with_now(&authority_pk, 10));
+        assert!(parsed.verify_with_now(&authority_pk, 20));
+        assert!(!parsed.verify_with_now(&authority_pk, 9));
+        assert!(!parsed.verify_with_now(&authority_pk, 21));
+        // the delegate can't vouch for itself
+        assert!(!parsed.verify_with_now(&delegate.x_only_public_key().0, 15));
+
+        // any altered byte breaks the signature
+        let mut bytes = delegation.to_bytes();
+        bytes[DELEGATION_SIZE - 1] ^= 1;
+        let tampered = Delegation::from_bytes(&bytes).unwrap();
+        assert!(!tampered.verify_with_now(&authority_pk, 15));
+    }
+}
@@ -6,8 +6,13 @@ use alloc::vec::Vec;
 
 use aes_gcm::Error as AesGcm;
 
+use crate::layout::HandshakeStage;
+
 /// Noise protocol error handling.
+///
+/// New variants may be added in minor releases, so matches on it need a wildcard arm.
 #[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Error {
     /// The handshake has not been completed when a finalization step is executed.
     HandshakeNotFinalized,
@@ -15,6 +20,9 @@ pub enum Error {
     /// Error on an empty cipher list is provided where one is required.
     CipherListMustBeNonEmpty,
 
+    /// An empty list of authority public keys is provided where one is required.
+    AuthorityKeyListMustBeNonEmpty,
+
     /// Error on unsupported ciphers.
     UnsupportedCiphers(Vec<u8>),
 
@@ -44,6 +52,38 @@ pub enum Error {
 
     /// A message has an incorrect or unexpected length.
     InvalidMessageLength,
+
+    /// A handshake buffer doesn't match the [`crate::HandshakeLayout`], e.g. because the peer
+    /// speaks a different revision of the protocol.
+    UnexpectedHandshakeLength {
+        stage: HandshakeStage,
+        expected: usize,
+        got: usize,
+    },
+
+    /// The token passed to `Responder::step_1_finish` doesn't belong to the current handshake.
+    InvalidHandshakeToken,
+
+    /// A key confirmation message doesn't come from the peer codec of this session.
+    InvalidKeyConfirmation,
+
+    /// A handshake step is called on an [`crate::Initiator`] whose state has already been
+    /// advanced past it, e.g. by a previous attempt: see [`crate::Initiator::reset`]. Also
+    /// returned when cloning an [`crate::Initiator`] or a [`crate::Responder`] whose handshake
+    /// has started.
+    HandshakeAlreadyInProgress,
+
+    /// The keypair given to a [`crate::Responder`] is not the delegate key of its
+    /// [`crate::Delegation`].
+    DelegateKeyMismatch,
+
+    /// A chunk failed to decrypt and reads as the header of a plaintext Sv2 frame: the peer most
+    /// likely sends its frames unencrypted, see [`crate::NoiseCodec::decrypt_frame`].
+    PeerSentPlaintextFrame {
+        extension_type: u16,
+        msg_type: u8,
+        msg_length: u32,
+    },
 }
 
 impl From<AesGcm> for Error {
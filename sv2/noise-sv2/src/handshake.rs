@@ -30,16 +30,70 @@
 // mandatory for communication across external networks (e.g., between a local mining proxy and a
 // remote pool).
 
-use alloc::{string::String, vec::Vec};
+use alloc::string::String;
 
 use crate::{aed_cipher::AeadCipher, cipher_state::CipherState, NOISE_HASHED_PROTOCOL_NAME_CHACHA};
-use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::{aead::Buffer, ChaCha20Poly1305};
 use secp256k1::{
     ecdh::SharedSecret,
-    hashes::{sha256::Hash as Sha256Hash, Hash},
+    hashes::{sha256::Hash as Sha256Hash, Hash, HashEngine},
     rand, Keypair, Secp256k1, SecretKey, XOnlyPublicKey,
 };
 
+// A fixed-capacity buffer for the AEAD operations of the handshake.
+//
+// All the handshake payloads have a size known at compile time, so they are encrypted and
+// decrypted on the stack rather than in a `Vec`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HandshakeBuffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> HandshakeBuffer<N> {
+    pub(crate) fn new() -> Self {
+        Self {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    pub(crate) fn from_slice(data: &[u8]) -> Self {
+        let mut buffer = Self::new();
+        buffer.bytes[..data.len()].copy_from_slice(data);
+        buffer.len = data.len();
+        buffer
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for HandshakeBuffer<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl<const N: usize> AsMut<[u8]> for HandshakeBuffer<N> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes[..self.len]
+    }
+}
+
+impl<const N: usize> Buffer for HandshakeBuffer<N> {
+    fn extend_from_slice(&mut self, other: &[u8]) -> chacha20poly1305::aead::Result<()> {
+        let end = self.len + other.len();
+        if end > N {
+            return Err(chacha20poly1305::aead::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(other);
+        self.len = end;
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
+
 // Represents the operations needed during a Noise protocol handshake.
 //
 // The [`HandshakeOp`] trait defines the necessary functions for managing the state and
@@ -90,10 +144,10 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
     // data (e.g., a public key or ciphertext) needs to be incorporated into the handshake state.
     fn mix_hash(&mut self, data: &[u8]) {
         let h = self.get_h();
-        let mut to_hash = Vec::with_capacity(32 + data.len());
-        to_hash.extend_from_slice(h);
-        to_hash.extend_from_slice(data);
-        *h = Sha256Hash::hash(&to_hash).to_byte_array();
+        let mut engine = Sha256Hash::engine();
+        engine.input(h);
+        engine.input(data);
+        *h = Sha256Hash::from_engine(engine).to_byte_array();
     }
 
     // Generates a new cryptographic key pair using the [`Secp256k1`] curve.
@@ -141,16 +195,15 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
             opad[i] = key[i] ^ 0x5c;
         }
 
-        let mut to_hash = Vec::with_capacity(64 + data.len());
-        to_hash.extend_from_slice(&ipad);
-        to_hash.extend_from_slice(data);
-        let temp = Sha256Hash::hash(&to_hash).to_byte_array();
-
-        to_hash.clear();
-        to_hash.extend_from_slice(&opad);
-        to_hash.extend_from_slice(&temp);
+        let mut engine = Sha256Hash::engine();
+        engine.input(&ipad);
+        engine.input(data);
+        let temp = Sha256Hash::from_engine(engine).to_byte_array();
 
-        Sha256Hash::hash(&to_hash).to_byte_array()
+        let mut engine = Sha256Hash::engine();
+        engine.input(&opad);
+        engine.input(&temp);
+        Sha256Hash::from_engine(engine).to_byte_array()
     }
 
     // Derives two new keys using the HKDF (HMAC-based Key Derivation Function) process.
@@ -170,7 +223,7 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
     fn hkdf_2(chaining_key: &[u8; 32], input_key_material: &[u8]) -> ([u8; 32], [u8; 32]) {
         let temp_key = Self::hmac_hash(chaining_key, input_key_material);
         let out_1 = Self::hmac_hash(&temp_key, &[0x1]);
-        let out_2 = Self::hmac_hash(&temp_key, &[&out_1[..], &[0x2][..]].concat());
+        let out_2 = Self::hmac_hash(&temp_key, &Self::hkdf_info(&out_1, 0x2));
         (out_1, out_2)
     }
 
@@ -181,11 +234,18 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
     ) -> ([u8; 32], [u8; 32], [u8; 32]) {
         let temp_key = Self::hmac_hash(chaining_key, input_key_material);
         let out_1 = Self::hmac_hash(&temp_key, &[0x1]);
-        let out_2 = Self::hmac_hash(&temp_key, &[&out_1[..], &[0x2][..]].concat());
-        let out_3 = Self::hmac_hash(&temp_key, &[&out_2[..], &[0x3][..]].concat());
+        let out_2 = Self::hmac_hash(&temp_key, &Self::hkdf_info(&out_1, 0x2));
+        let out_3 = Self::hmac_hash(&temp_key, &Self::hkdf_info(&out_2, 0x3));
         (out_1, out_2, out_3)
     }
 
+    // Concatenates the previous HKDF output with the index of the next one.
+    fn hkdf_info(previous: &[u8; 32], index: u8) -> [u8; 33] {
+        let mut info = [index; 33];
+        info[..32].copy_from_slice(previous);
+        info
+    }
+
     // Mixes the input key material into the current chaining key (`ck`) and initializes the
     // handshake cipher with an updated encryption key (`k`).
     //
@@ -208,14 +268,14 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
     // using AEAD, where the associated data is the current hash value. After
     // encryption, the ciphertext is mixed into the hash to ensure integrity
     // and authenticity of the messages exchanged during the handshake.
-    fn encrypt_and_hash(&mut self, plaintext: &mut Vec<u8>) -> Result<(), aes_gcm::Error> {
-        if self.get_k().is_some() {
+    fn encrypt_and_hash<T: Buffer>(&mut self, plaintext: &mut T) -> Result<(), aes_gcm::Error> {
+        if self.has_key() {
             #[allow(clippy::clone_on_copy)]
             let h = self.get_h().clone();
             self.encrypt_with_ad(&h, plaintext)?;
         };
         let ciphertext = plaintext;
-        self.mix_hash(ciphertext);
+        self.mix_hash(ciphertext.as_ref());
         Ok(())
     }
 
@@ -227,14 +287,19 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
     // ensures that each decryption step is securely linked to the previous handshake state,
     // maintaining the integrity of the
     // handshake.
-    fn decrypt_and_hash(&mut self, ciphertext: &mut Vec<u8>) -> Result<(), aes_gcm::Error> {
-        let encrypted = ciphertext.clone();
-        if self.get_k().is_some() {
-            #[allow(clippy::clone_on_copy)]
-            let h = self.get_h().clone();
+    //
+    // The ciphertext is mixed into a copy of the hash before decrypting it in place, and the copy
+    // is only kept if decryption succeeds.
+    fn decrypt_and_hash<T: Buffer>(&mut self, ciphertext: &mut T) -> Result<(), aes_gcm::Error> {
+        let h = *self.get_h();
+        let mut engine = Sha256Hash::engine();
+        engine.input(&h);
+        engine.input(ciphertext.as_ref());
+        let mixed_h = Sha256Hash::from_engine(engine).to_byte_array();
+        if self.has_key() {
             self.decrypt_with_ad(&h, ciphertext)?;
         };
-        self.mix_hash(&encrypted);
+        self.set_h(mixed_h);
         Ok(())
     }
 
@@ -257,7 +322,7 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
         let h = Sha256Hash::hash(&ck[..]);
         self.set_h(h.to_byte_array());
         self.set_ck(ck);
-        self.set_k(None);
+        self.take_key();
     }
 
     // Initializes the handshake cipher with the provided encryption key (`k`).
@@ -269,12 +334,7 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
         self.set_n(0);
         let cipher = ChaCha20Poly1305::from_key(key);
         self.set_handshake_cipher(cipher);
-        if let Some(k) = self.get_k() {
-            *k = key;
-        } else {
-            let set_k = self.get_k();
-            *set_k = Some(key);
-        }
+        self.install_key(key);
     }
 
     fn set_handshake_cipher(&mut self, cipher: ChaCha20Poly1305);
@@ -283,11 +343,13 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use alloc::string::ToString;
+    use alloc::{string::ToString, vec::Vec};
     use core::convert::TryInto;
     use quickcheck::{Arbitrary, TestResult};
 
+    use crate::cipher_state;
     use secp256k1::SecretKey;
+    use zeroize::Zeroizing;
 
     struct TestHandShake {
         k: Option<[u8; 32]>,
@@ -312,12 +374,16 @@ mod test {
     }
 
     impl CipherState<ChaCha20Poly1305> for TestHandShake {
-        fn get_k(&mut self) -> &mut Option<[u8; 32]> {
-            &mut self.k
+        fn has_key(&self) -> bool {
+            self.k.is_some()
+        }
+
+        fn install_key(&mut self, k: [u8; 32]) {
+            cipher_state::install_key(&mut self.k, k);
         }
 
-        fn set_k(&mut self, k: Option<[u8; 32]>) {
-            self.k = k
+        fn take_key(&mut self) -> Option<Zeroizing<[u8; 32]>> {
+            cipher_state::take_key(&mut self.k)
         }
 
         fn get_n(&self) -> u64 {
@@ -433,7 +499,7 @@ mod test {
         tester.mix_key(&input_key_material);
 
         assert!(tester.get_ck() == &mut ck);
-        assert!(tester.get_k().unwrap() == temp_k);
+        assert!(*tester.take_key().unwrap() == temp_k);
     }
 
     #[test]
@@ -37,31 +37,37 @@
 use alloc::{
     boxed::Box,
     string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
 };
 use core::{convert::TryInto, ptr};
 
 use crate::{
-    cipher_state::{Cipher, CipherState, GenericCipher},
+    certificate::{KeyFingerprint, ResponderCertificate},
+    cipher_state::{self, CipherState, GenericCipher},
+    delegation::{Delegation, DELEGATED_CERTIFICATE_VERSION, DELEGATION_SIZE},
     error::Error,
-    handshake::HandshakeOp,
+    handshake::{HandshakeBuffer, HandshakeOp},
+    layout::{HandshakeLayout, HandshakeStage},
+    observer::{HandshakeObserver, ObserverState},
     signature_message::SignatureNoiseMessage,
-    NoiseCodec, ELLSWIFT_ENCODING_SIZE, ENCRYPTED_ELLSWIFT_ENCODING_SIZE,
-    ENCRYPTED_SIGNATURE_NOISE_MESSAGE_SIZE, INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
-    SIGNATURE_NOISE_MESSAGE_SIZE,
+    timings::{HandshakeStep, TimeProvider, TimingState},
+    trace::{debug, warning, TRANSPORT_CIPHER},
+    NoiseCodec, ELLSWIFT_ENCODING_SIZE, INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
+    LEGACY_HANDSHAKE_PRELUDE, SIGNATURE_NOISE_MESSAGE_SIZE,
 };
-use aes_gcm::KeyInit;
 use chacha20poly1305::ChaCha20Poly1305;
 use secp256k1::{
     ellswift::{ElligatorSwift, ElligatorSwiftParty},
     Keypair, PublicKey, XOnlyPublicKey,
 };
+use zeroize::Zeroizing;
 
 /// Manages the initiator's role in the Noise NX handshake, handling key exchange, encryption, and
 /// handshake state. It securely generates and manages cryptographic keys, performs Diffie-Hellman
 /// exchanges, and maintains the handshake hash, chaining key, and nonce for message encryption.
 /// After the handshake, it facilitates secure communication using either [`ChaCha20Poly1305`] or
 /// `AES-GCM` ciphers. Sensitive data is securely erased when no longer needed.
-#[derive(Clone)]
 pub struct Initiator {
     // Cipher used for encrypting and decrypting messages during the handshake.
     //
@@ -83,16 +89,53 @@ pub struct Initiator {
     // Ephemeral key pair generated by the initiator for this session, used for generating the
     // shared secret with the responder.
     e: Keypair,
-    // Optional public key of the responder, used to authenticate the responder during the
-    // handshake.
-    #[allow(unused)]
-    responder_authority_pk: Option<XOnlyPublicKey>,
+    // Authority public keys the responder certificate may be signed with, in order of
+    // preference, used to authenticate the responder during the handshake. Empty if the
+    // responder is not authenticated.
+    responder_authority_pks: Vec<XOnlyPublicKey>,
     // First [`CipherState`] used for encrypting messages from the initiator to the responder
     // after the handshake is complete.
     c1: Option<GenericCipher>,
     // Second [`CipherState`] used for encrypting messages from the responder to the initiator
     // after the handshake is complete.
     c2: Option<GenericCipher>,
+    // Optional observer notified about the progress of the handshake.
+    observer: ObserverState,
+    // Timings of the step functions, measured if a time provider is set.
+    timings: TimingState,
+    // How far the handshake went, the state must not be cloned once it has started.
+    stage: InitiatorStage,
+    // Whether [`Initiator::step_0_to_vec`] sends a [`LEGACY_HANDSHAKE_PRELUDE`] first.
+    legacy_prelude: bool,
+}
+
+// The progress of the handshake of an [`Initiator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InitiatorStage {
+    // [`Initiator::step_0`] has not been called yet.
+    NotStarted,
+    // The first message has been sent, waiting for the responder's one.
+    WaitingResponse,
+    // The responder's message has been processed, successfully or not: the handshake state is
+    // spent, and the initiator must be reset before another attempt.
+    Finished,
+}
+
+#[cfg(feature = "std")]
+impl Clone for Initiator {
+    /// Clones an [`Initiator`] that has not started a handshake yet, with a new ephemeral key, see
+    /// [`Initiator::try_clone_with_rng`].
+    ///
+    /// Only available with the `std` feature, which provides the randomness. Without it, use
+    /// [`Initiator::try_clone_with_rng`] with an rng of your own.
+    ///
+    /// # Panics
+    ///
+    /// If the handshake has already started, i.e. after [`Initiator::step_0`] has been called.
+    fn clone(&self) -> Self {
+        self.try_clone_with_rng(&mut rand::thread_rng())
+            .expect("an Initiator can't be cloned once the handshake has started")
+    }
 }
 
 impl core::fmt::Debug for Initiator {
@@ -113,8 +156,16 @@ impl core::fmt::Debug for Initiator {
 // the `AeadCipher` trait. This trait requires mutable access, making the entire struct non-`Sync`
 // and non-`Copy`, even though the key and nonce are simple types.
 impl CipherState<ChaCha20Poly1305> for Initiator {
-    fn get_k(&mut self) -> &mut Option<[u8; 32]> {
-        &mut self.k
+    fn has_key(&self) -> bool {
+        self.k.is_some()
+    }
+
+    fn install_key(&mut self, k: [u8; 32]) {
+        cipher_state::install_key(&mut self.k, k);
+    }
+
+    fn take_key(&mut self) -> Option<Zeroizing<[u8; 32]>> {
+        cipher_state::take_key(&mut self.k)
     }
 
     fn get_n(&self) -> u64 {
@@ -128,10 +179,6 @@ impl CipherState<ChaCha20Poly1305> for Initiator {
     fn get_cipher(&mut self) -> &mut Option<ChaCha20Poly1305> {
         &mut self.handshake_cipher
     }
-
-    fn set_k(&mut self, k: Option<[u8; 32]>) {
-        self.k = k;
-    }
 }
 
 impl HandshakeOp<ChaCha20Poly1305> for Initiator {
@@ -191,14 +238,54 @@ impl Initiator {
             ck: [0; 32],
             h: [0; 32],
             e: Self::generate_key_with_rng(rng),
-            responder_authority_pk: pk,
+            responder_authority_pks: pk.into_iter().collect(),
             c1: None,
             c2: None,
+            observer: ObserverState::default(),
+            timings: TimingState::default(),
+            stage: InitiatorStage::NotStarted,
+            legacy_prelude: false,
         };
         self_.initialize_self();
         Box::new(self_)
     }
 
+    /// Creates a new [`Initiator`] accepting a responder certificate signed by any of the given
+    /// authority public keys.
+    ///
+    /// Meant for authority key rotations: during the overlap period the initiator can be
+    /// configured with both the incoming and the outgoing key, and keep connecting whichever one
+    /// the responder uses. The key the certificate was verified with is reported in
+    /// [`crate::ResponderCertificate::authority_key_index`].
+    ///
+    /// Returns an [`Error::AuthorityKeyListMustBeNonEmpty`] if `pks` is empty, see
+    /// [`Self::without_pk`] to skip the authentication of the responder instead.
+    #[cfg(feature = "std")]
+    pub fn with_authority_keys(pks: Vec<XOnlyPublicKey>) -> Result<Box<Self>, Error> {
+        Self::with_authority_keys_with_rng(pks, &mut rand::thread_rng())
+    }
+
+    /// Creates a new [`Initiator`] accepting a responder certificate signed by any of the given
+    /// authority public keys, using a custom random number generator.
+    ///
+    /// See [`Self::with_authority_keys`] for more details.
+    ///
+    /// The custom random number generator should be provided in order to not implicitely rely on
+    /// `std` and allow `no_std` environments to provide a hardware random number generator for
+    /// example.
+    #[inline]
+    pub fn with_authority_keys_with_rng<R: rand::Rng + ?Sized>(
+        pks: Vec<XOnlyPublicKey>,
+        rng: &mut R,
+    ) -> Result<Box<Self>, Error> {
+        if pks.is_empty() {
+            return Err(Error::AuthorityKeyListMustBeNonEmpty);
+        }
+        let mut initiator = Self::new_with_rng(None, rng);
+        initiator.responder_authority_pks = pks;
+        Ok(initiator)
+    }
+
     /// Creates a new [`Initiator`] instance using a raw 32-byte public key.
     ///
     /// Constructs a [`XOnlyPublicKey`] from the provided raw key slice and initializes a new
@@ -252,6 +339,15 @@ impl Initiator {
         Ok(Self::new_with_rng(None, rng))
     }
 
+    /// Returns the fingerprint of the ephemeral key sent by [`Self::step_0`].
+    ///
+    /// Unique to the session and known before the handshake completes, it matches
+    /// [`NoiseCodec::initiator_ephemeral_key_fingerprint`] on both sides of the connection, so
+    /// that client and server logs can be correlated.
+    pub fn ephemeral_key_fingerprint(&self) -> KeyFingerprint {
+        KeyFingerprint::from_bytes(&ElligatorSwift::from_pubkey(self.e.public_key()).to_array())
+    }
+
     /// Executes the initial step of the Noise NX protocol handshake.
     ///
     /// This step involves generating an ephemeral keypair and encoding the public key using
@@ -262,15 +358,113 @@ impl Initiator {
     /// the responder.
     ///
     /// On success, the function returns a 64-byte array containing the encoded public key.
-    /// If an error occurs during encryption, it returns an [`aes_gcm::Error`].
-    pub fn step_0(&mut self) -> Result<[u8; ELLSWIFT_ENCODING_SIZE], aes_gcm::Error> {
+    /// If an error occurs during encryption, it returns an [`Error::AesGcm`].
+    ///
+    /// Fails with [`Error::HandshakeAlreadyInProgress`] if called more than once: a new attempt
+    /// needs [`Self::reset`] first.
+    pub fn step_0(&mut self) -> Result<[u8; ELLSWIFT_ENCODING_SIZE], Error> {
+        if self.stage != InitiatorStage::NotStarted {
+            return Err(Error::HandshakeAlreadyInProgress);
+        }
+        self.stage = InitiatorStage::WaitingResponse;
+        self.observer.start();
+        self.timings.begin(HandshakeStep::InitiatorStep0);
+
         let elliswift_enc_pubkey = ElligatorSwift::from_pubkey(self.e.public_key()).to_array();
         self.mix_hash(&elliswift_enc_pubkey);
-        self.encrypt_and_hash(&mut vec![])?;
+        if let Err(e) = self.encrypt_and_hash(&mut HandshakeBuffer::<0>::new()) {
+            let e = e.into();
+            warning!("Noise handshake failed at initiator step 0: {:?}", e);
+            self.observer.fail(&e);
+            self.timings.reset();
+            return Err(e);
+        }
+
+        self.timings.end();
+        debug!(
+            "Noise handshake initiator step 0: sent ephemeral key {} ({} bytes)",
+            self.ephemeral_key_fingerprint(),
+            elliswift_enc_pubkey.len()
+        );
+        Ok(elliswift_enc_pubkey)
+    }
+
+    /// Executes the initial step of the handshake as [`Self::step_0`], preceding the encoded
+    /// public key with a [`LEGACY_HANDSHAKE_PRELUDE`] if enabled with
+    /// [`Self::set_legacy_prelude`].
+    pub fn step_0_to_vec(&mut self) -> Result<Vec<u8>, Error> {
+        let message = self.step_0()?;
+        let mut out = Vec::with_capacity(LEGACY_HANDSHAKE_PRELUDE.len() + message.len());
+        if self.legacy_prelude {
+            debug!(
+                "Noise handshake initiator step 0: sending legacy prelude {:02x?}",
+                LEGACY_HANDSHAKE_PRELUDE
+            );
+            out.extend_from_slice(&LEGACY_HANDSHAKE_PRELUDE);
+        }
+        out.extend_from_slice(&message);
+        Ok(out)
+    }
 
-        let mut message = [0u8; ELLSWIFT_ENCODING_SIZE];
-        message[..64].copy_from_slice(&elliswift_enc_pubkey[..ELLSWIFT_ENCODING_SIZE]);
-        Ok(message)
+    /// Sends a [`LEGACY_HANDSHAKE_PRELUDE`] before the first handshake message of
+    /// [`Self::step_0_to_vec`], for legacy responders expecting it. Off by default.
+    ///
+    /// Responders following the protocol reject the prelude, unless they tolerate it (see
+    /// [`crate::Responder::set_legacy_prelude_tolerance`]).
+    pub fn set_legacy_prelude(&mut self, legacy_prelude: bool) {
+        self.legacy_prelude = legacy_prelude;
+    }
+
+    /// Clones an [`Initiator`] that has not started a handshake yet, drawing a new ephemeral key
+    /// from `rng`, so that the clone can run a handshake of its own.
+    ///
+    /// The authority keys, the observer, the time provider and the legacy prelude setting are
+    /// kept. Mid-handshake state (chaining key, session keys, nonces) is never duplicated: fails
+    /// with [`Error::HandshakeAlreadyInProgress`] once [`Self::step_0`] has been called.
+    pub fn try_clone_with_rng<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> Result<Self, Error> {
+        if self.stage != InitiatorStage::NotStarted {
+            return Err(Error::HandshakeAlreadyInProgress);
+        }
+        Ok(Self {
+            handshake_cipher: self.handshake_cipher.clone(),
+            k: self.k,
+            n: self.n,
+            ck: self.ck,
+            h: self.h,
+            e: Self::generate_key_with_rng(rng),
+            responder_authority_pks: self.responder_authority_pks.clone(),
+            c1: self.c1.clone(),
+            c2: self.c2.clone(),
+            observer: self.observer.clone(),
+            timings: self.timings.clone(),
+            stage: InitiatorStage::NotStarted,
+            legacy_prelude: self.legacy_prelude,
+        })
+    }
+
+    /// Resets the handshake state, so that the initiator can be used for a new attempt, e.g.
+    /// after a network error in the middle of a handshake.
+    ///
+    /// The ephemeral key is regenerated, while the authority keys, the observer and the time
+    /// provider are kept.
+    #[cfg(feature = "std")]
+    pub fn reset(&mut self) {
+        self.reset_with_rng(&mut rand::thread_rng());
+    }
+
+    /// Resets the handshake state with a custom random number generator.
+    ///
+    /// See [`Self::reset`] for more details.
+    pub fn reset_with_rng<R: rand::Rng + ?Sized>(&mut self, rng: &mut R) {
+        self.erase();
+        self.handshake_cipher = None;
+        self.n = 0;
+        self.e = Self::generate_key_with_rng(rng);
+        self.c1 = None;
+        self.c2 = None;
+        self.stage = InitiatorStage::NotStarted;
+        self.timings.reset();
+        self.initialize_self();
     }
 
     /// Processes the second step of the Noise NX protocol handshake for the initiator.
@@ -288,9 +482,14 @@ impl Initiator {
     /// authenticity.
     ///
     /// On success, this method returns a [`NoiseCodec`] instance initialized with session ciphers
-    /// for secure communication. If the provided `message` has an incorrect length, it returns an
+    /// for secure communication, which also carries the details of the responder certificate (see
+    /// [`NoiseCodec::responder_certificate`]). If the provided `message` has an incorrect length, it returns an
     /// [`Error::InvalidMessageLength`]. If decryption or signature verification fails, it returns
     /// an [`Error::InvalidCertificate`].
+    ///
+    /// Only one responder message is processed per attempt: once one has been (successfully or
+    /// not, except for a length mismatch), or if [`Self::step_0`] has not been called,
+    /// [`Error::HandshakeAlreadyInProgress`] is returned until [`Self::reset`] is called.
     #[cfg(feature = "std")]
     pub fn step_2(
         &mut self,
@@ -315,11 +514,135 @@ impl Initiator {
         message: [u8; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE],
         now: u32,
     ) -> Result<NoiseCodec, Error> {
+        self.step_2_from_slice(&message, now)
+    }
+
+    /// Processes the second step of the Noise NX protocol handshake for the initiator given the
+    /// current system time, accepting a message of any length.
+    ///
+    /// See [`Self::step_2_with_now`] for more details.
+    ///
+    /// The length of `message` is checked against the [`HandshakeLayout`], returning an
+    /// [`Error::UnexpectedHandshakeLength`] if the responder speaks a different revision of the
+    /// protocol.
+    ///
+    /// Unlike the fixed size variants, it also accepts the longer message of a responder whose
+    /// certificate is signed by a delegate key ([`HandshakeLayout::DELEGATED`]): the delegation
+    /// is then verified against the authority keys, and the certificate against the delegate key.
+    pub fn step_2_from_slice(&mut self, message: &[u8], now: u32) -> Result<NoiseCodec, Error> {
+        debug!(
+            "Noise handshake initiator step 2: received responder message ({} bytes)",
+            message.len()
+        );
+        self.timings.begin(HandshakeStep::InitiatorStep2);
+        let mut result = self.step_2_inner(message, now);
+        self.timings.end();
+        match &mut result {
+            Ok(codec) => {
+                if let Some(certificate) = codec.responder_certificate() {
+                    debug!(
+                        "Noise handshake initiator step 2: completed with responder static key {}, \
+                         certificate version {} valid from {} to {}, authority key {}, cipher {}",
+                        certificate.static_key_fingerprint,
+                        certificate.version,
+                        certificate.valid_from,
+                        certificate.not_valid_after,
+                        certificate
+                            .authority_key_fingerprint
+                            .map_or_else(|| "not verified".to_string(), |key| key.to_string()),
+                        TRANSPORT_CIPHER
+                    );
+                }
+                self.observer.complete();
+                codec.handshake_timings = self.timings.take();
+                if let Some(timings) = &codec.handshake_timings {
+                    self.observer.timings(timings);
+                }
+            }
+            Err(e) => {
+                warning!("Noise handshake failed at initiator step 2: {:?}", e);
+                self.observer.fail(e);
+                self.timings.reset();
+            }
+        }
+        result
+    }
+
+    /// Processes the second step of the handshake out of `bytes` read from the transport, which
+    /// may go on past the responder message: a responder can send its first encrypted frames
+    /// right after the handshake, and a single read then returns both.
+    ///
+    /// Returns the [`NoiseCodec`] along with the bytes following the responder message, to be
+    /// decrypted with it. Once more bytes follow, the length no longer tells a plain responder
+    /// message from a delegated one, so the `layout` of the message has to be known upfront:
+    /// [`HandshakeLayout::CURRENT`], unless the responder certificate is signed by a delegate key.
+    ///
+    /// Fails with [`Error::UnexpectedHandshakeLength`] if `bytes` is shorter than the responder
+    /// message. See [`Self::step_2_from_slice`] for more details.
+    pub fn step_2_with_leftover(
+        &mut self,
+        bytes: &[u8],
+        layout: HandshakeLayout,
+        now: u32,
+    ) -> Result<(NoiseCodec, Vec<u8>), Error> {
+        let message_len = layout.responder_message_size();
+        if bytes.len() < message_len {
+            let e = Error::UnexpectedHandshakeLength {
+                stage: HandshakeStage::ResponderMessage,
+                expected: message_len,
+                got: bytes.len(),
+            };
+            warning!("Noise handshake failed at initiator step 2: {:?}", e);
+            self.observer.fail(&e);
+            return Err(e);
+        }
+        let (message, leftover) = bytes.split_at(message_len);
+        if !leftover.is_empty() {
+            debug!(
+                "Noise handshake initiator step 2: {} bytes of transport data after the responder \
+                 message",
+                leftover.len()
+            );
+        }
+        let codec = self.step_2_from_slice(message, now)?;
+        Ok((codec, leftover.to_vec()))
+    }
+
+    /// Sets an observer to be notified about the progress of the handshake.
+    ///
+    /// See [`HandshakeObserver`] for more details.
+    pub fn set_handshake_observer(&mut self, observer: Arc<dyn HandshakeObserver>) {
+        self.observer.set_observer(observer);
+    }
+
+    /// Sets the clock used to measure the step functions, whose timings are then returned by
+    /// [`NoiseCodec::handshake_timings`].
+    ///
+    /// See [`TimeProvider`] for more details.
+    pub fn set_time_provider(&mut self, clock: Arc<dyn TimeProvider>) {
+        self.timings.set_time_provider(clock);
+    }
+
+    // Processes the second step of the handshake, see [`Self::step_2_with_now`].
+    fn step_2_inner(&mut self, message: &[u8], now: u32) -> Result<NoiseCodec, Error> {
+        if self.stage != InitiatorStage::WaitingResponse {
+            return Err(Error::HandshakeAlreadyInProgress);
+        }
+        let layout = HandshakeLayout::for_responder_message_len(message.len());
+        layout.check_len(HandshakeStage::ResponderMessage, message.len())?;
+        // from here on the handshake state is mixed with the message, whatever the outcome
+        self.stage = InitiatorStage::Finished;
+
         // 2. interprets first 64 bytes as ElligatorSwift encoding of x-coordinate of public key
         // from this is derived the 32-bytes remote ephemeral public key `re.public_key`
-        let mut elliswift_theirs_ephemeral_serialized: [u8; ELLSWIFT_ENCODING_SIZE] =
-            [0; ELLSWIFT_ENCODING_SIZE];
-        elliswift_theirs_ephemeral_serialized.clone_from_slice(&message[0..ELLSWIFT_ENCODING_SIZE]);
+        let elliswift_theirs_ephemeral_serialized: [u8; ELLSWIFT_ENCODING_SIZE] = message
+            [layout.responder_ephemeral_key_range()]
+        .try_into()
+        .map_err(|_| Error::UnexpectedHandshakeLength {
+            stage: HandshakeStage::ResponderMessage,
+            expected: layout.responder_message_size(),
+            got: message.len(),
+        })?;
         self.mix_hash(&elliswift_theirs_ephemeral_serialized);
 
         // 3. calls `MixHash(re.public_key)`
@@ -341,15 +664,18 @@ impl Initiator {
         // 5. decrypts next 80 bytes with `DecryptAndHash()` and stores the results as
         // `rs.public_key` which is **server's static public key** (note that 64 bytes is the
         // elligatorswift encoded public key and 16 bytes is MAC)
-        let mut to_decrypt = message
-            [ELLSWIFT_ENCODING_SIZE..ELLSWIFT_ENCODING_SIZE + ENCRYPTED_ELLSWIFT_ENCODING_SIZE]
-            .to_vec();
+        let mut to_decrypt = message[layout.encrypted_static_key_range()].to_vec();
         self.decrypt_and_hash(&mut to_decrypt)?;
 
         // 6. calls `MixKey(ECDH(e.private_key, rs.public_key)`
+        layout.check_len(HandshakeStage::ResponderStaticKey, to_decrypt.len())?;
         let elligatorswift_theirs_static_serialized: [u8; ELLSWIFT_ENCODING_SIZE] = to_decrypt[..]
             .try_into()
-            .expect("slice with incorrect length");
+            .map_err(|_| Error::UnexpectedHandshakeLength {
+                stage: HandshakeStage::ResponderStaticKey,
+                expected: ELLSWIFT_ENCODING_SIZE,
+                got: to_decrypt.len(),
+            })?;
         let elligatorswift_theirs_static =
             ElligatorSwift::from_array(elligatorswift_theirs_static_serialized);
         let ecdh_static: [u8; 32] = ElligatorSwift::shared_secret(
@@ -363,41 +689,83 @@ impl Initiator {
         self.mix_key(&ecdh_static);
 
         // Decrypt and verify the SignatureNoiseMessage
-        let mut to_decrypt = message[ELLSWIFT_ENCODING_SIZE + ENCRYPTED_ELLSWIFT_ENCODING_SIZE
-            ..INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE]
-            .to_vec();
-        if to_decrypt.len() != ENCRYPTED_SIGNATURE_NOISE_MESSAGE_SIZE {
-            return Err(Error::InvalidMessageLength);
-        }
-
+        let mut to_decrypt = message[layout.encrypted_signature_noise_message_range()].to_vec();
         self.decrypt_and_hash(&mut to_decrypt)?;
-        let plaintext: [u8; SIGNATURE_NOISE_MESSAGE_SIZE] = to_decrypt.try_into().unwrap();
+        layout.check_len(HandshakeStage::SignatureNoiseMessage, to_decrypt.len())?;
+        let plaintext: [u8; SIGNATURE_NOISE_MESSAGE_SIZE] = to_decrypt
+            [..SIGNATURE_NOISE_MESSAGE_SIZE]
+            .try_into()
+            .map_err(|_| Error::UnexpectedHandshakeLength {
+                stage: HandshakeStage::SignatureNoiseMessage,
+                expected: SIGNATURE_NOISE_MESSAGE_SIZE,
+                got: to_decrypt.len(),
+            })?;
         let signature_message: SignatureNoiseMessage = plaintext.into();
+        // the (authenticated) version must agree with the size of the message: a delegated
+        // certificate is followed by the delegation of the authority
+        let delegated = layout == HandshakeLayout::DELEGATED;
+        if delegated != (signature_message.version == DELEGATED_CERTIFICATE_VERSION) {
+            return Err(Error::InvalidCertificate(plaintext));
+        }
+        let delegation = match delegated {
+            true => {
+                let bytes: [u8; DELEGATION_SIZE] = to_decrypt[SIGNATURE_NOISE_MESSAGE_SIZE..]
+                    .try_into()
+                    .map_err(|_| Error::InvalidCertificate(plaintext))?;
+                Some(
+                    Delegation::from_bytes(&bytes)
+                        .map_err(|_| Error::InvalidCertificate(plaintext))?,
+                )
+            }
+            false => None,
+        };
         let rs_pub_key = PublicKey::from_ellswift(elligatorswift_theirs_static)
             .x_only_public_key()
             .0
             .serialize();
         let rs_pk_xonly = XOnlyPublicKey::from_slice(&rs_pub_key).unwrap();
-        if signature_message.verify_with_now(&rs_pk_xonly, &self.responder_authority_pk, now) {
-            let (temp_k1, temp_k2) = Self::hkdf_2(self.get_ck(), &[]);
-            let c1 = ChaCha20Poly1305::new(&temp_k1.into());
-            let c2 = ChaCha20Poly1305::new(&temp_k2.into());
-            let c1: Cipher<ChaCha20Poly1305> = Cipher::from_key_and_cipher(temp_k1, c1);
-            let c2: Cipher<ChaCha20Poly1305> = Cipher::from_key_and_cipher(temp_k2, c2);
-            self.c1 = None;
-            self.c2 = None;
-            let mut encryptor = GenericCipher::ChaCha20Poly1305(c1);
-            let mut decryptor = GenericCipher::ChaCha20Poly1305(c2);
-            encryptor.erase_k();
-            decryptor.erase_k();
-            let codec = crate::NoiseCodec {
-                encryptor,
-                decryptor,
-            };
-            Ok(codec)
-        } else {
-            Err(Error::InvalidCertificate(plaintext))
-        }
+        // the first of the authority keys the certificate is signed with, directly or through
+        // the delegate key, unless the responder is not authenticated
+        let authority_key = match self.responder_authority_pks.is_empty() {
+            true => None,
+            false => Some(
+                self.responder_authority_pks
+                    .iter()
+                    .enumerate()
+                    .find(|(_, pk)| match &delegation {
+                        Some(delegation) => {
+                            delegation.verify_with_now(pk, now)
+                                && SignatureNoiseMessage::from(plaintext).verify_with_now(
+                                    &rs_pk_xonly,
+                                    &Some(delegation.delegate_key()),
+                                    now,
+                                )
+                        }
+                        None => SignatureNoiseMessage::from(plaintext).verify_with_now(
+                            &rs_pk_xonly,
+                            &Some(**pk),
+                            now,
+                        ),
+                    })
+                    .ok_or(Error::InvalidCertificate(plaintext))?,
+            ),
+        };
+        let responder_certificate = ResponderCertificate::new(
+            &signature_message,
+            &rs_pk_xonly,
+            authority_key,
+            delegation.as_ref(),
+        );
+        let (temp_k1, temp_k2) = Self::hkdf_2(self.get_ck(), &[]);
+        self.c1 = None;
+        self.c2 = None;
+        let codec = NoiseCodec::from_transport_keys(
+            temp_k1,
+            temp_k2,
+            self.ephemeral_key_fingerprint(),
+            Some(responder_certificate),
+        );
+        Ok(codec)
     }
 
     // Securely erases sensitive data from the [`Initiator`] memory.
@@ -0,0 +1,65 @@
+// # Key Confirmation
+//
+// Optional application-level confirmation that both sides of a session derived the same transport
+// keys, exchanged right after the handshake and before any Sv2 frame (e.g. `SetupConnection`).
+//
+// The handshake alone doesn't tell the responder whether the initiator completed it: if the
+// initiator rejects the certificate, or the connection is half-open, the responder only finds out
+// when the first Sv2 frame fails to arrive or to decrypt. Exchanging key confirmations lets both
+// sides detect it up front, at the cost of one extra round trip. Both sides must agree to use it,
+// since producing and verifying a confirmation advance the nonces of the codec.
+//
+// A confirmation is made of [`KEY_CONFIRMATION_PREFIX`] followed by a fixed plaintext encrypted
+// with the codec. Its [`KEY_CONFIRMATION_SIZE`] is longer than an encrypted Sv2 frame header
+// alone and shorter than any encrypted Sv2 frame with a payload, so it can't be mistaken for one.
+
+use alloc::vec::Vec;
+
+use crate::{error::Error, NoiseCodec, AEAD_MAC_LEN};
+
+/// Plaintext prefix of a key confirmation message.
+pub const KEY_CONFIRMATION_PREFIX: [u8; 4] = *b"KCNF";
+
+// Plaintext encrypted in a key confirmation message.
+const KEY_CONFIRMATION_PLAINTEXT: [u8; 16] = *b"noise_sv2 keycnf";
+
+/// Size in bytes of a key confirmation message.
+pub const KEY_CONFIRMATION_SIZE: usize =
+    KEY_CONFIRMATION_PREFIX.len() + KEY_CONFIRMATION_PLAINTEXT.len() + AEAD_MAC_LEN;
+
+impl NoiseCodec {
+    /// Produces the key confirmation message to send to the peer, which checks it with
+    /// [`Self::verify_key_confirmation`].
+    ///
+    /// Must be called before encrypting any other message.
+    pub fn make_key_confirmation(&mut self) -> Result<[u8; KEY_CONFIRMATION_SIZE], Error> {
+        let mut encrypted = Vec::with_capacity(KEY_CONFIRMATION_PLAINTEXT.len() + AEAD_MAC_LEN);
+        encrypted.extend_from_slice(&KEY_CONFIRMATION_PLAINTEXT);
+        self.encrypt(&mut encrypted)?;
+
+        let mut message = [0; KEY_CONFIRMATION_SIZE];
+        message[..KEY_CONFIRMATION_PREFIX.len()].copy_from_slice(&KEY_CONFIRMATION_PREFIX);
+        message[KEY_CONFIRMATION_PREFIX.len()..].copy_from_slice(&encrypted);
+        Ok(message)
+    }
+
+    /// Checks the key confirmation message received from the peer.
+    ///
+    /// Must be called before decrypting any other message. Fails with
+    /// [`Error::InvalidKeyConfirmation`] if `message` is not a key confirmation produced by the
+    /// peer codec of this session.
+    pub fn verify_key_confirmation(&mut self, message: &[u8]) -> Result<(), Error> {
+        if message.len() != KEY_CONFIRMATION_SIZE
+            || message[..KEY_CONFIRMATION_PREFIX.len()] != KEY_CONFIRMATION_PREFIX
+        {
+            return Err(Error::InvalidKeyConfirmation);
+        }
+        let mut decrypted = message[KEY_CONFIRMATION_PREFIX.len()..].to_vec();
+        self.decrypt(&mut decrypted)
+            .map_err(|_| Error::InvalidKeyConfirmation)?;
+        if decrypted[..] != KEY_CONFIRMATION_PLAINTEXT {
+            return Err(Error::InvalidKeyConfirmation);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+// # Handshake Layout
+//
+// Keeps the layout of the Noise NX handshake messages (sizes and offsets of every part) in a
+// single place, derived from the size constants defined in the crate root.
+//
+// Both the [`crate::Initiator`] and the [`crate::Responder`] build and parse the handshake
+// messages through [`HandshakeLayout`], and every received buffer is checked against it, so that a
+// peer speaking a different revision of the protocol results in an
+// [`Error::UnexpectedHandshakeLength`] instead of a panic or a mis-parse.
+
+use core::ops::Range;
+
+use crate::{
+    error::Error, AEAD_MAC_LEN, DELEGATED_SIGNATURE_NOISE_MESSAGE_SIZE, ELLSWIFT_ENCODING_SIZE,
+    SIGNATURE_NOISE_MESSAGE_SIZE,
+};
+
+/// The part of the handshake a length check refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeStage {
+    /// The first handshake message, sent by the initiator: its ElligatorSwift encoded ephemeral
+    /// public key.
+    InitiatorEphemeralKey,
+    /// The second handshake message, sent by the responder.
+    ResponderMessage,
+    /// The responder static public key, once decrypted from the second handshake message.
+    ResponderStaticKey,
+    /// The `SIGNATURE_NOISE_MESSAGE`, once decrypted from the second handshake message.
+    SignatureNoiseMessage,
+}
+
+/// Sizes and offsets of the parts of the handshake messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandshakeLayout {
+    ellswift_encoding_size: usize,
+    mac_len: usize,
+    signature_noise_message_size: usize,
+}
+
+impl HandshakeLayout {
+    /// The layout of the handshake messages defined by the current protocol revision.
+    pub const CURRENT: Self = Self {
+        ellswift_encoding_size: ELLSWIFT_ENCODING_SIZE,
+        mac_len: AEAD_MAC_LEN,
+        signature_noise_message_size: SIGNATURE_NOISE_MESSAGE_SIZE,
+    };
+
+    /// The layout of the handshake messages of a responder whose certificate is signed by a
+    /// delegate key, i.e. followed by a [`crate::Delegation`].
+    pub const DELEGATED: Self = Self {
+        ellswift_encoding_size: ELLSWIFT_ENCODING_SIZE,
+        mac_len: AEAD_MAC_LEN,
+        signature_noise_message_size: DELEGATED_SIGNATURE_NOISE_MESSAGE_SIZE,
+    };
+
+    /// The layout of a second handshake message of `len` bytes, as seen by the initiator.
+    ///
+    /// Falls back to [`Self::CURRENT`] for unknown lengths, so that the length check reports
+    /// what a plain responder would have sent.
+    pub fn for_responder_message_len(len: usize) -> Self {
+        if len == Self::DELEGATED.responder_message_size() {
+            Self::DELEGATED
+        } else {
+            Self::CURRENT
+        }
+    }
+
+    /// Size of the first handshake message (initiator -> responder).
+    pub const fn initiator_message_size(&self) -> usize {
+        self.ellswift_encoding_size
+    }
+
+    /// Size of the second handshake message (responder -> initiator).
+    pub const fn responder_message_size(&self) -> usize {
+        self.ellswift_encoding_size
+            + self.encrypted_static_key_size()
+            + self.encrypted_signature_noise_message_size()
+    }
+
+    /// Size of the ElligatorSwift encoded static key of the responder.
+    pub const fn static_key_size(&self) -> usize {
+        self.ellswift_encoding_size
+    }
+
+    /// Size of the encrypted static key of the responder, MAC included.
+    pub const fn encrypted_static_key_size(&self) -> usize {
+        self.ellswift_encoding_size + self.mac_len
+    }
+
+    pub const fn signature_noise_message_size(&self) -> usize {
+        self.signature_noise_message_size
+    }
+
+    /// Size of the encrypted `SIGNATURE_NOISE_MESSAGE`, MAC included.
+    pub const fn encrypted_signature_noise_message_size(&self) -> usize {
+        self.signature_noise_message_size + self.mac_len
+    }
+
+    /// Position of the responder ephemeral key in the second handshake message.
+    pub fn responder_ephemeral_key_range(&self) -> Range<usize> {
+        0..self.ellswift_encoding_size
+    }
+
+    /// Position of the encrypted responder static key in the second handshake message.
+    pub fn encrypted_static_key_range(&self) -> Range<usize> {
+        let start = self.responder_ephemeral_key_range().end;
+        start..start + self.encrypted_static_key_size()
+    }
+
+    /// Position of the encrypted `SIGNATURE_NOISE_MESSAGE` in the second handshake message.
+    pub fn encrypted_signature_noise_message_range(&self) -> Range<usize> {
+        let start = self.encrypted_static_key_range().end;
+        start..start + self.encrypted_signature_noise_message_size()
+    }
+
+    /// The expected length of the buffer handled at `stage`.
+    pub const fn expected_len(&self, stage: HandshakeStage) -> usize {
+        match stage {
+            HandshakeStage::InitiatorEphemeralKey => self.initiator_message_size(),
+            HandshakeStage::ResponderMessage => self.responder_message_size(),
+            HandshakeStage::ResponderStaticKey => self.static_key_size(),
+            HandshakeStage::SignatureNoiseMessage => self.signature_noise_message_size(),
+        }
+    }
+
+    /// Checks that a buffer handled at `stage` has the expected length.
+    pub fn check_len(&self, stage: HandshakeStage, got: usize) -> Result<(), Error> {
+        let expected = self.expected_len(stage);
+        if got != expected {
+            return Err(Error::UnexpectedHandshakeLength {
+                stage,
+                expected,
+                got,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        DELEGATED_HANDSHAKE_MESSAGE_SIZE, ENCRYPTED_ELLSWIFT_ENCODING_SIZE,
+        ENCRYPTED_SIGNATURE_NOISE_MESSAGE_SIZE, INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
+    };
+
+    #[test]
+    fn test_current_layout_matches_constants() {
+        let layout = HandshakeLayout::CURRENT;
+        assert_eq!(layout.initiator_message_size(), ELLSWIFT_ENCODING_SIZE);
+        assert_eq!(
+            layout.responder_message_size(),
+            INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE
+        );
+        assert_eq!(
+            layout.encrypted_static_key_size(),
+            ENCRYPTED_ELLSWIFT_ENCODING_SIZE
+        );
+        assert_eq!(
+            layout.encrypted_signature_noise_message_size(),
+            ENCRYPTED_SIGNATURE_NOISE_MESSAGE_SIZE
+        );
+
+        // the parts of the second message are contiguous and cover all of it
+        assert_eq!(layout.responder_ephemeral_key_range().start, 0);
+        assert_eq!(
+            layout.responder_ephemeral_key_range().end,
+            layout.encrypted_static_key_range().start
+        );
+        assert_eq!(
+            layout.encrypted_static_key_range().end,
+            layout.encrypted_signature_noise_message_range().start
+        );
+        assert_eq!(
+            layout.encrypted_signature_noise_message_range().end,
+            layout.responder_message_size()
+        );
+    }
+
+    #[test]
+    fn test_delegated_layout() {
+        let layout = HandshakeLayout::DELEGATED;
+        assert_eq!(
+            layout.responder_message_size(),
+            DELEGATED_HANDSHAKE_MESSAGE_SIZE
+        );
+        // only the signature noise message grows
+        assert_eq!(
+            layout.encrypted_static_key_range(),
+            HandshakeLayout::CURRENT.encrypted_static_key_range()
+        );
+        assert_eq!(
+            layout.encrypted_signature_noise_message_range().end,
+            layout.responder_message_size()
+        );
+
+        assert_eq!(
+            HandshakeLayout::for_responder_message_len(DELEGATED_HANDSHAKE_MESSAGE_SIZE),
+            HandshakeLayout::DELEGATED
+        );
+        for len in [
+            INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
+            DELEGATED_HANDSHAKE_MESSAGE_SIZE - 1,
+            0,
+        ] {
+            assert_eq!(
+                HandshakeLayout::for_responder_message_len(len),
+                HandshakeLayout::CURRENT
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_len_off_by_one() {
+        let layout = HandshakeLayout::CURRENT;
+        for stage in [
+            HandshakeStage::InitiatorEphemeralKey,
+            HandshakeStage::ResponderMessage,
+            HandshakeStage::ResponderStaticKey,
+            HandshakeStage::SignatureNoiseMessage,
+        ] {
+            let expected = layout.expected_len(stage);
+            assert_eq!(layout.check_len(stage, expected), Ok(()));
+            for got in [expected - 1, expected + 1] {
+                assert_eq!(
+                    layout.check_len(stage, got),
+                    Err(Error::UnexpectedHandshakeLength {
+                        stage,
+                        expected,
+                        got
+                    })
+                );
+            }
+        }
+    }
+}
@@ -34,21 +34,32 @@
 
 #![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
 
-#[macro_use]
 extern crate alloc;
 
 use aes_gcm::aead::Buffer;
 pub use aes_gcm::aead::Error as AeadError;
-use cipher_state::GenericCipher;
+use aes_gcm::KeyInit;
+use chacha20poly1305::ChaCha20Poly1305;
+use cipher_state::{Cipher, GenericCipher};
+use core::convert::TryInto;
 mod aed_cipher;
+mod certificate;
 mod cipher_state;
+#[cfg(feature = "insecure-debug")]
+mod debug_state;
+mod delegation;
 mod error;
 mod handshake;
 mod initiator;
+mod key_confirmation;
+mod layout;
+mod observer;
 mod responder;
 mod signature_message;
 #[cfg(test)]
 mod test;
+mod timings;
+mod trace;
 
 /// Size of the MAC for supported AEAD encryption algorithm (ChaChaPoly).
 pub const AEAD_MAC_LEN: usize = 16;
@@ -76,6 +87,14 @@ pub const ELLSWIFT_ENCODING_SIZE: usize = 64;
 /// verification.
 pub const ENCRYPTED_ELLSWIFT_ENCODING_SIZE: usize = ELLSWIFT_ENCODING_SIZE + AEAD_MAC_LEN;
 
+/// Plaintext protocol version (2, little endian) some legacy initiators send before the first
+/// handshake message.
+///
+/// Not part of the protocol: responders only accept it if opted in with
+/// [`Responder::set_legacy_prelude_tolerance`], and initiators only send it if opted in with
+/// [`Initiator::set_legacy_prelude`].
+pub const LEGACY_HANDSHAKE_PRELUDE: [u8; 2] = [0x02, 0x00];
+
 /// Size in bytes of the handshake message expected by the initiator,
 /// encompassing:
 /// - ElligatorSwift encoded public key
@@ -85,6 +104,93 @@ pub const INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE: usize = ELLSWIFT_ENCODING_S
     + ENCRYPTED_ELLSWIFT_ENCODING_SIZE
     + ENCRYPTED_SIGNATURE_NOISE_MESSAGE_SIZE;
 
+/// Size in bytes of the SIGNATURE_NOISE_MESSAGE of a certificate signed by a delegate key, i.e.
+/// followed by the [`Delegation`] of the authority.
+pub const DELEGATED_SIGNATURE_NOISE_MESSAGE_SIZE: usize =
+    SIGNATURE_NOISE_MESSAGE_SIZE + DELEGATION_SIZE;
+
+/// Size in bytes of the encrypted delegated signature noise message, MAC included.
+pub const ENCRYPTED_DELEGATED_SIGNATURE_NOISE_MESSAGE_SIZE: usize =
+    DELEGATED_SIGNATURE_NOISE_MESSAGE_SIZE + AEAD_MAC_LEN;
+
+/// Size in bytes of the handshake message sent by a responder using a delegate key, see
+/// [`HandshakeLayout::DELEGATED`].
+pub const DELEGATED_HANDSHAKE_MESSAGE_SIZE: usize = ELLSWIFT_ENCODING_SIZE
+    + ENCRYPTED_ELLSWIFT_ENCODING_SIZE
+    + ENCRYPTED_DELEGATED_SIGNATURE_NOISE_MESSAGE_SIZE;
+
+/// Maximum size in bytes of an encrypted chunk, MAC included.
+///
+/// A Noise transport message is at most 65535 bytes long, so a longer payload is encrypted as a
+/// sequence of chunks, each with its own MAC. See [`encrypted_len`].
+pub const MAX_CHUNK_SIZE: usize = 65535;
+
+/// Maximum size in bytes of the plaintext carried by an encrypted chunk.
+pub const MAX_CHUNK_PLAINTEXT_SIZE: usize = MAX_CHUNK_SIZE - AEAD_MAC_LEN;
+
+// Size in bytes of the header of a plaintext Sv2 frame.
+const SV2_FRAME_HEADER_SIZE: usize = 6;
+
+// Set on the extension type of the Sv2 frames of channel messages.
+const SV2_CHANNEL_MSG_BIT: u16 = 0x8000;
+
+/// Returns the size of a payload of `plaintext_len` bytes once encrypted.
+///
+/// The payload is split in chunks of [`MAX_CHUNK_PLAINTEXT_SIZE`] bytes (the last one possibly
+/// shorter), and every chunk gets an [`AEAD_MAC_LEN`] bytes MAC. An empty payload yields no
+/// chunk at all.
+///
+/// ```
+/// use noise_sv2::{encrypted_len, AEAD_MAC_LEN, MAX_CHUNK_PLAINTEXT_SIZE, MAX_CHUNK_SIZE};
+///
+/// assert_eq!(encrypted_len(100), 100 + AEAD_MAC_LEN);
+/// assert_eq!(encrypted_len(MAX_CHUNK_PLAINTEXT_SIZE), MAX_CHUNK_SIZE);
+/// assert_eq!(
+///     encrypted_len(MAX_CHUNK_PLAINTEXT_SIZE + 1),
+///     MAX_CHUNK_SIZE + 1 + AEAD_MAC_LEN
+/// );
+/// ```
+pub const fn encrypted_len(plaintext_len: usize) -> usize {
+    let chunks = plaintext_len / MAX_CHUNK_PLAINTEXT_SIZE
+        + !plaintext_len.is_multiple_of(MAX_CHUNK_PLAINTEXT_SIZE) as usize;
+    plaintext_len + chunks * AEAD_MAC_LEN
+}
+
+/// Returns the size of the plaintext carried by an encrypted payload of `ciphertext_len` bytes,
+/// the inverse of [`encrypted_len`].
+///
+/// Fails with [`Error::InvalidMessageLength`] if no payload encrypts to `ciphertext_len` bytes,
+/// i.e. if the last chunk is too short to hold a MAC and some plaintext.
+pub fn decrypted_len(ciphertext_len: usize) -> Result<usize, Error> {
+    let full_chunks = ciphertext_len / MAX_CHUNK_SIZE;
+    let last_chunk = ciphertext_len % MAX_CHUNK_SIZE;
+    if last_chunk != 0 && last_chunk <= AEAD_MAC_LEN {
+        return Err(Error::InvalidMessageLength);
+    }
+    let chunks = full_chunks + usize::from(last_chunk != 0);
+    Ok(ciphertext_len - chunks * AEAD_MAC_LEN)
+}
+
+// Reads `header` as the header of a plaintext Sv2 frame, if it's a plausible one: an extension
+// type below 0x100 (the channel_msg bit aside), a message type below 0x80 and a payload shorter
+// than 65536 bytes, which holds for the messages of the Sv2 protocols while random bytes pass
+// about once in 65536 times.
+fn plaintext_frame_header(header: &[u8; SV2_FRAME_HEADER_SIZE]) -> Option<Error> {
+    let extension_type = u16::from_le_bytes([header[0], header[1]]);
+    let msg_type = header[2];
+    let msg_length = u32::from_le_bytes([header[3], header[4], header[5], 0]);
+    let plausible =
+        extension_type & !SV2_CHANNEL_MSG_BIT < 0x100 && msg_type < 0x80 && msg_length < 1 << 16;
+    match plausible {
+        true => Some(Error::PeerSentPlaintextFrame {
+            extension_type,
+            msg_type,
+            msg_length,
+        }),
+        false => None,
+    }
+}
+
 /// If protocolName is less than or equal to 32 bytes in length, use
 /// protocolName with zero bytes appended to make 32 bytes. Otherwise, apply
 /// HASH to it. For name = "Noise_NX_Secp256k1+EllSwift_ChaChaPoly_SHA256", we
@@ -112,6 +218,19 @@ pub struct NoiseCodec {
 
     // Cipher to decrypt incoming messages.
     decryptor: GenericCipher,
+
+    // Fingerprint of the ephemeral key sent by the initiator, identifies the session.
+    initiator_ephemeral_key_fingerprint: KeyFingerprint,
+
+    // Certificate presented by the responder, only set on the initiator side.
+    responder_certificate: Option<ResponderCertificate>,
+
+    // Timings of the handshake, only set if a `TimeProvider` was given.
+    handshake_timings: Option<HandshakeTimings>,
+
+    // Fingerprints of the encryptor and decryptor keys, which are erased from the ciphers.
+    #[cfg(feature = "insecure-debug")]
+    key_fingerprints: (KeyFingerprint, KeyFingerprint),
 }
 
 impl core::fmt::Debug for NoiseCodec {
@@ -121,6 +240,40 @@ impl core::fmt::Debug for NoiseCodec {
 }
 
 impl NoiseCodec {
+    // Builds the codec out of the transport keys derived at the end of the handshake. The keys
+    // are erased from the ciphers once these are initialized.
+    pub(crate) fn from_transport_keys(
+        encryptor_k: [u8; 32],
+        decryptor_k: [u8; 32],
+        initiator_ephemeral_key_fingerprint: KeyFingerprint,
+        responder_certificate: Option<ResponderCertificate>,
+    ) -> Self {
+        #[cfg(feature = "insecure-debug")]
+        let key_fingerprints = (
+            KeyFingerprint::from_bytes(&encryptor_k),
+            KeyFingerprint::from_bytes(&decryptor_k),
+        );
+        let mut encryptor = GenericCipher::ChaCha20Poly1305(Cipher::from_key_and_cipher(
+            encryptor_k,
+            ChaCha20Poly1305::new(&encryptor_k.into()),
+        ));
+        let mut decryptor = GenericCipher::ChaCha20Poly1305(Cipher::from_key_and_cipher(
+            decryptor_k,
+            ChaCha20Poly1305::new(&decryptor_k.into()),
+        ));
+        encryptor.erase_k();
+        decryptor.erase_k();
+        Self {
+            encryptor,
+            decryptor,
+            initiator_ephemeral_key_fingerprint,
+            responder_certificate,
+            handshake_timings: None,
+            #[cfg(feature = "insecure-debug")]
+            key_fingerprints,
+        }
+    }
+
     /// Encrypts a message (`msg`) in place using the stored cipher.
     pub fn encrypt<T: Buffer>(&mut self, msg: &mut T) -> Result<(), aes_gcm::Error> {
         self.encryptor.encrypt(msg)
@@ -130,8 +283,80 @@ impl NoiseCodec {
     pub fn decrypt<T: Buffer>(&mut self, msg: &mut T) -> Result<(), aes_gcm::Error> {
         self.decryptor.decrypt(msg)
     }
+
+    /// Decrypts a chunk of an Sv2 frame (`msg`) in place, as [`Self::decrypt`].
+    ///
+    /// A peer that skipped encryption once the handshake completed sends plaintext Sv2 frames,
+    /// which fail to decrypt like a corrupted chunk would. So if the MAC doesn't match and `msg`
+    /// starts like a plaintext Sv2 frame header, this fails with
+    /// [`Error::PeerSentPlaintextFrame`] instead of [`Error::AesGcm`]. It's a heuristic on the
+    /// chunk starting the frame, and a corrupted chunk reads as a frame header about once in
+    /// 65536 times, but the chunk can't be decrypted either way.
+    pub fn decrypt_frame<T: Buffer>(&mut self, msg: &mut T) -> Result<(), Error> {
+        // kept aside, as the content of `msg` is unspecified once decryption failed
+        let header: Option<[u8; SV2_FRAME_HEADER_SIZE]> = msg
+            .as_ref()
+            .get(..SV2_FRAME_HEADER_SIZE)
+            .and_then(|bytes| bytes.try_into().ok());
+        self.decryptor.decrypt(msg).map_err(|e| {
+            header
+                .and_then(|header| plaintext_frame_header(&header))
+                .unwrap_or(Error::AesGcm(e))
+        })
+    }
+
+    /// Returns the fingerprint of the ephemeral key sent by the initiator during the handshake.
+    ///
+    /// It's the same on both sides of the connection and doesn't expose any secret, so it can be
+    /// logged to correlate a session between client and server logs.
+    pub fn initiator_ephemeral_key_fingerprint(&self) -> KeyFingerprint {
+        self.initiator_ephemeral_key_fingerprint
+    }
+
+    /// Returns the certificate presented by the responder during the handshake.
+    ///
+    /// Only available on the codec returned by the [`Initiator`].
+    pub fn responder_certificate(&self) -> Option<&ResponderCertificate> {
+        self.responder_certificate.as_ref()
+    }
+
+    /// Returns how long the handshake took on the side that returned this codec.
+    ///
+    /// Only available if a [`TimeProvider`] was set on the [`Initiator`] or [`Responder`] with
+    /// `set_time_provider`.
+    pub fn handshake_timings(&self) -> Option<&HandshakeTimings> {
+        self.handshake_timings.as_ref()
+    }
+
+    /// Returns a redacted snapshot of the state of both directions of the codec.
+    ///
+    /// Diagnostics only: meant to troubleshoot interop failures by comparing the snapshot of one
+    /// side with the one of its counterpart. Keys are never exposed, only their fingerprints.
+    #[cfg(feature = "insecure-debug")]
+    pub fn debug_state(&self) -> CodecDebugState {
+        CodecDebugState {
+            encryptor: CipherDebugState {
+                key_fingerprint: self.key_fingerprints.0,
+                nonce: self.encryptor.nonce(),
+            },
+            decryptor: CipherDebugState {
+                key_fingerprint: self.key_fingerprints.1,
+                nonce: self.decryptor.nonce(),
+            },
+        }
+    }
 }
 
+pub use certificate::{KeyFingerprint, ResponderCertificate, KEY_FINGERPRINT_SIZE};
+#[cfg(feature = "insecure-debug")]
+pub use debug_state::{CipherDebugState, CodecDebugState};
+pub use delegation::{Delegation, DELEGATED_CERTIFICATE_VERSION, DELEGATION_SIZE};
 pub use error::Error;
 pub use initiator::Initiator;
-pub use responder::Responder;
+pub use key_confirmation::{KEY_CONFIRMATION_PREFIX, KEY_CONFIRMATION_SIZE};
+pub use layout::{HandshakeLayout, HandshakeStage};
+pub use observer::HandshakeObserver;
+pub use responder::{Responder, Step1Token};
+#[cfg(feature = "std")]
+pub use timings::StdTimeProvider;
+pub use timings::{HandshakeStep, HandshakeTimings, StageTiming, TimeProvider};
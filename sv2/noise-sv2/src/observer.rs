@@ -0,0 +1,82 @@
+// # Handshake Observer
+//
+// Hooks for collecting metrics about handshakes (attempts, successes, failures by cause and
+// latency) without wrapping every call to the [`crate::Initiator`] and [`crate::Responder`] step
+// functions.
+//
+// No clock is assumed, so the crate stays `no_std` compatible: the observer itself provides the
+// timestamps used to measure the handshake duration.
+
+use alloc::sync::Arc;
+use core::time::Duration;
+
+use crate::{error::Error, timings::HandshakeTimings};
+
+/// Receives notifications about the progress of a handshake.
+///
+/// Set it with `set_handshake_observer` on an [`crate::Initiator`] or [`crate::Responder`].
+///
+/// - The [`crate::Initiator`] starts the handshake on `step_0`, and completes it on `step_2`.
+/// - The [`crate::Responder`] starts and completes the handshake on `step_1`.
+///
+/// Failures detected outside of the step functions (e.g. timeouts waiting for the other party) are
+/// not seen by the handshake, so the caller is expected to report them directly.
+pub trait HandshakeObserver: Send + Sync {
+    /// Returns the current time, as an offset from an arbitrary fixed point.
+    ///
+    /// Only differences between two values are used, to compute the handshake duration.
+    fn now(&self) -> Duration;
+
+    /// Called when the handshake starts.
+    fn on_start(&self) {}
+
+    /// Called when the handshake completes successfully.
+    fn on_complete(&self, _duration: Duration) {}
+
+    /// Called when a step of the handshake fails.
+    fn on_failure(&self, _error: &Error) {}
+
+    /// Called after [`Self::on_complete`] with the timings of the step functions, if a
+    /// [`crate::TimeProvider`] is set as well.
+    fn on_timings(&self, _timings: &HandshakeTimings) {}
+}
+
+// Keeps track of the observer of a handshake, along with the time the handshake started.
+#[derive(Clone, Default)]
+pub(crate) struct ObserverState {
+    observer: Option<Arc<dyn HandshakeObserver>>,
+    started_at: Option<Duration>,
+}
+
+impl ObserverState {
+    pub(crate) fn set_observer(&mut self, observer: Arc<dyn HandshakeObserver>) {
+        self.observer = Some(observer);
+    }
+
+    pub(crate) fn start(&mut self) {
+        if let Some(observer) = self.observer.as_ref() {
+            self.started_at = Some(observer.now());
+            observer.on_start();
+        }
+    }
+
+    pub(crate) fn complete(&mut self) {
+        if let Some(observer) = self.observer.as_ref() {
+            let started_at = self.started_at.take().unwrap_or_else(|| observer.now());
+            observer.on_complete(observer.now().saturating_sub(started_at));
+        }
+    }
+
+    pub(crate) fn timings(&self, timings: &HandshakeTimings) {
+        if let Some(observer) = self.observer.as_ref() {
+            observer.on_timings(timings);
+        }
+    }
+
+    pub(crate) fn fail(&mut self, error: &Error) {
+        if let Some(observer) = self.observer.as_ref() {
+            self.started_at = None;
+            observer.on_failure(error);
+        }
+    }
+}
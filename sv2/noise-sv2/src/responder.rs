@@ -34,24 +34,32 @@
 // The [`Drop`] trait is implemented to automatically trigger secure erasure when the [`Responder`]
 // instance goes out of scope, preventing potential misuse or leakage of cryptographic material.
 
-use core::{ptr, time::Duration};
+use core::{convert::TryInto, ptr, time::Duration};
 
 use crate::{
-    cipher_state::{Cipher, CipherState, GenericCipher},
+    certificate::KeyFingerprint,
+    cipher_state::{self, CipherState, GenericCipher},
+    delegation::{Delegation, DELEGATED_CERTIFICATE_VERSION},
     error::Error,
-    handshake::HandshakeOp,
+    handshake::{HandshakeBuffer, HandshakeOp},
+    layout::{HandshakeLayout, HandshakeStage},
+    observer::{HandshakeObserver, ObserverState},
     signature_message::SignatureNoiseMessage,
-    NoiseCodec, ELLSWIFT_ENCODING_SIZE, ENCRYPTED_ELLSWIFT_ENCODING_SIZE,
-    ENCRYPTED_SIGNATURE_NOISE_MESSAGE_SIZE, INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
+    timings::{HandshakeStep, TimeProvider, TimingState},
+    trace::{debug, warning, TRANSPORT_CIPHER},
+    NoiseCodec, ELLSWIFT_ENCODING_SIZE, ENCRYPTED_DELEGATED_SIGNATURE_NOISE_MESSAGE_SIZE,
+    ENCRYPTED_ELLSWIFT_ENCODING_SIZE, INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
+    LEGACY_HANDSHAKE_PRELUDE, SIGNATURE_NOISE_MESSAGE_SIZE,
 };
-use aes_gcm::KeyInit;
 use alloc::{
     boxed::Box,
     string::{String, ToString},
+    sync::Arc,
     vec::Vec,
 };
-use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::{aead::Buffer, ChaCha20Poly1305};
 use secp256k1::{ellswift::ElligatorSwift, Keypair, Secp256k1, SecretKey};
+use zeroize::Zeroizing;
 
 const VERSION: u16 = 0;
 
@@ -60,7 +68,6 @@ const VERSION: u16 = 0;
 /// a connection with the initiator. The responder manages key generation, Diffie-Hellman exchanges,
 /// message decryption, and state transitions, ensuring secure communication. Sensitive
 /// cryptographic material is securely erased when no longer needed.
-#[derive(Clone)]
 pub struct Responder {
     // Cipher used for encrypting and decrypting messages during the handshake.
     //
@@ -88,16 +95,72 @@ pub struct Responder {
     s: Keypair,
     // Authority key pair, representing the responder's authority credentials.
     //
-    // Used to sign messages and verify the identity of the responder.
+    // Used to sign messages and verify the identity of the responder. When `delegation` is set,
+    // it's the delegate key pair the authority signed the delegation for.
     a: Keypair,
+    // Delegation of the authority to `a`, appended to the certificates.
+    delegation: Option<Delegation>,
     // First [`CipherState`] used for encrypting messages from the initiator to the responder
     // after the handshake is complete.
     c1: Option<GenericCipher>,
     // Second [`CipherState`] used for encrypting messages from the responder to the initiator
     // after the handshake is complete.
     c2: Option<GenericCipher>,
+    // Optional observer notified about the progress of the handshake.
+    observer: ObserverState,
+    // Timings of the step functions, measured if a time provider is set.
+    timings: TimingState,
     // Validity duration of the responder's certificate, in seconds.
     cert_validity: u32,
+    // Whether the handshake has started, i.e. [`Self::step_1_prepare`] has been called, after which the state must not be cloned.
+    handshake_started: bool,
+    // Whether a [`LEGACY_HANDSHAKE_PRELUDE`] before the initiator's message is stripped.
+    legacy_prelude_tolerance: bool,
+}
+
+/// The state of a handshake between [`Responder::step_1_prepare`] and
+/// [`Responder::step_1_finish`].
+///
+/// Only holds public data, so it's cheap to keep around while the admission check runs.
+#[derive(Debug)]
+pub struct Step1Token {
+    theirs_ephemeral: [u8; ELLSWIFT_ENCODING_SIZE],
+    ours_ephemeral: [u8; ELLSWIFT_ENCODING_SIZE],
+    handshake_hash: [u8; 32],
+}
+
+impl Step1Token {
+    /// The handshake hash after the ephemeral ECDH, i.e. the prefix of the handshake hash of the
+    /// session that would be established.
+    ///
+    /// Can be used, for example, as a challenge for a proof of work admission check.
+    pub fn handshake_hash(&self) -> &[u8; 32] {
+        &self.handshake_hash
+    }
+
+    /// The fingerprint of the ephemeral key received from the initiator, see
+    /// [`crate::Initiator::ephemeral_key_fingerprint`].
+    pub fn initiator_ephemeral_key_fingerprint(&self) -> KeyFingerprint {
+        KeyFingerprint::from_bytes(&self.theirs_ephemeral)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clone for Responder {
+    /// Clones a [`Responder`] that has not started a handshake yet, with new ephemeral and static
+    /// keys, see [`Responder::try_clone_with_rng`].
+    ///
+    /// Only available with the `std` feature, which provides the randomness. Without it, use
+    /// [`Responder::try_clone_with_rng`] with an rng of your own.
+    ///
+    /// # Panics
+    ///
+    /// If the handshake has already started, i.e. after [`Responder::step_1_prepare`] (or any of
+    /// the `step_1` variants) has been called.
+    fn clone(&self) -> Self {
+        self.try_clone_with_rng(&mut rand::thread_rng())
+            .expect("a Responder can't be cloned once the handshake has started")
+    }
 }
 
 impl core::fmt::Debug for Responder {
@@ -119,8 +182,16 @@ impl core::fmt::Debug for Responder {
 // and non-`Copy`, even though the key and nonce are simple types.
 
 impl CipherState<ChaCha20Poly1305> for Responder {
-    fn get_k(&mut self) -> &mut Option<[u8; 32]> {
-        &mut self.k
+    fn has_key(&self) -> bool {
+        self.k.is_some()
+    }
+
+    fn install_key(&mut self, k: [u8; 32]) {
+        cipher_state::install_key(&mut self.k, k);
+    }
+
+    fn take_key(&mut self) -> Option<Zeroizing<[u8; 32]>> {
+        cipher_state::take_key(&mut self.k)
     }
 
     fn get_n(&self) -> u64 {
@@ -131,10 +202,6 @@ impl CipherState<ChaCha20Poly1305> for Responder {
         self.n = n;
     }
 
-    fn set_k(&mut self, k: Option<[u8; 32]>) {
-        self.k = k;
-    }
-
     fn get_cipher(&mut self) -> &mut Option<ChaCha20Poly1305> {
         &mut self.handshake_cipher
     }
@@ -202,9 +269,14 @@ impl Responder {
             e: Self::generate_key_with_rng(rng),
             s: Self::generate_key_with_rng(rng),
             a,
+            delegation: None,
             c1: None,
             c2: None,
+            observer: ObserverState::default(),
+            timings: TimingState::default(),
             cert_validity,
+            handshake_started: false,
+            legacy_prelude_tolerance: false,
         };
         Self::initialize_self(&mut self_);
         Box::new(self_)
@@ -251,6 +323,92 @@ impl Responder {
         }
     }
 
+    /// Creates a new [`Responder`] signing its certificates with a delegate keypair, on behalf of
+    /// the authority that signed `delegation`.
+    ///
+    /// The authority secret key is not needed: the authority signs the [`Delegation`] offline
+    /// (see [`Delegation::sign`]), and only the delegate keypair and the delegation are deployed
+    /// on the server. The certificates are sent with version [`DELEGATED_CERTIFICATE_VERSION`],
+    /// followed by the delegation, so the handshake messages follow
+    /// [`HandshakeLayout::DELEGATED`]: use [`Self::step_1_to_vec`] (or
+    /// [`Self::step_1_finish_to_vec`]), the fixed size `step_1` variants only fit the plain
+    /// layout. Initiators verify the delegation against their authority keys, and the certificate
+    /// against the delegate key.
+    ///
+    /// Fails with [`Error::DelegateKeyMismatch`] if `delegation` was not signed for `delegate`.
+    #[cfg(feature = "std")]
+    pub fn with_delegation(
+        delegate: Keypair,
+        delegation: Delegation,
+        cert_validity: u32,
+    ) -> Result<Box<Self>, Error> {
+        Self::with_delegation_with_rng(delegate, delegation, cert_validity, &mut rand::thread_rng())
+    }
+
+    /// Creates a new [`Responder`] signing its certificates with a delegate keypair, with a
+    /// custom random number generator.
+    ///
+    /// See [`Self::with_delegation`] for more details.
+    pub fn with_delegation_with_rng<R: rand::Rng + ?Sized>(
+        delegate: Keypair,
+        delegation: Delegation,
+        cert_validity: u32,
+        rng: &mut R,
+    ) -> Result<Box<Self>, Error> {
+        if delegate.x_only_public_key().0 != delegation.delegate_key() {
+            return Err(Error::DelegateKeyMismatch);
+        }
+        let mut self_ = Self::new_with_rng(delegate, cert_validity, rng);
+        self_.delegation = Some(delegation);
+        Ok(self_)
+    }
+
+    /// The layout of the handshake messages sent by this responder: [`HandshakeLayout::CURRENT`],
+    /// or [`HandshakeLayout::DELEGATED`] if it was constructed with a [`Delegation`].
+    pub fn handshake_layout(&self) -> HandshakeLayout {
+        match self.delegation {
+            Some(_) => HandshakeLayout::DELEGATED,
+            None => HandshakeLayout::CURRENT,
+        }
+    }
+
+    /// Clones a [`Responder`] that has not started a handshake yet, drawing new ephemeral and
+    /// static keys from `rng`, so that the clone can run a handshake of its own.
+    ///
+    /// The authority keypair (and its [`Delegation`], if any), the certificate validity, the
+    /// observer and the time provider are kept. Mid-handshake state (chaining key, session keys,
+    /// nonces) is never duplicated: fails with [`Error::HandshakeAlreadyInProgress`] once
+    /// [`Self::step_1_prepare`] (or any of the `step_1` variants) has been called.
+    pub fn try_clone_with_rng<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> Result<Self, Error> {
+        if self.handshake_started {
+            return Err(Error::HandshakeAlreadyInProgress);
+        }
+        Ok(Self {
+            handshake_cipher: self.handshake_cipher.clone(),
+            k: self.k,
+            n: self.n,
+            ck: self.ck,
+            h: self.h,
+            e: Self::generate_key_with_rng(rng),
+            s: Self::generate_key_with_rng(rng),
+            a: self.a,
+            delegation: self.delegation,
+            c1: self.c1.clone(),
+            c2: self.c2.clone(),
+            observer: self.observer.clone(),
+            timings: self.timings.clone(),
+            cert_validity: self.cert_validity,
+            handshake_started: false,
+            legacy_prelude_tolerance: self.legacy_prelude_tolerance,
+        })
+    }
+
+    /// Returns the fingerprint of the static key of this responder, as seen by initiators on
+    /// [`crate::ResponderCertificate::static_key_fingerprint`].
+    pub fn static_key_fingerprint(&self) -> KeyFingerprint {
+        KeyFingerprint::from_key(&self.s.x_only_public_key().0)
+    }
+
     /// Processes the first step of the Noise NX protocol handshake for the responder.
     ///
     /// This function manages the responder's side of the handshake after receiving the initiator's
@@ -298,17 +456,215 @@ impl Responder {
         now: u32,
         rng: &mut R,
     ) -> Result<([u8; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE], NoiseCodec), aes_gcm::Error> {
+        // the message has the expected size, so only encryption (or a delegated layout) can fail
+        self.step_1_from_slice(&elligatorswift_theirs_ephemeral_serialized, now, rng)
+            .map_err(|_| aes_gcm::Error)
+    }
+
+    /// Executes the first step of the Noise NX protocol handshake for the responder given the
+    /// current time and a custom random number generator, accepting a message of any length.
+    ///
+    /// See [`Self::step_1_with_now_rng`] for more details.
+    ///
+    /// The length of `message` is checked against the [`HandshakeLayout`], returning an
+    /// [`Error::UnexpectedHandshakeLength`] if the initiator speaks a different revision of the
+    /// protocol.
+    pub fn step_1_from_slice<R: rand::Rng + rand::CryptoRng>(
+        &mut self,
+        message: &[u8],
+        now: u32,
+        rng: &mut R,
+    ) -> Result<([u8; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE], NoiseCodec), Error> {
+        let token = self.step_1_prepare(message)?;
+        self.step_1_finish(token, now, rng)
+    }
+
+    /// Executes the cheap part of the first step of the handshake: it processes the initiator
+    /// ephemeral key and performs the ephemeral ECDH.
+    ///
+    /// The returned [`Step1Token`] is to be passed to [`Self::step_1_finish`], which does the
+    /// expensive work (static ECDH and certificate signing). In between, the server can run any
+    /// admission check (e.g. rate limiting or proof of work) and just drop the [`Responder`] if
+    /// the handshake should not go on.
+    ///
+    /// With [`Self::set_legacy_prelude_tolerance`], a [`LEGACY_HANDSHAKE_PRELUDE`] sent before the
+    /// initiator ephemeral key is stripped.
+    pub fn step_1_prepare(&mut self, message: &[u8]) -> Result<Step1Token, Error> {
+        self.handshake_started = true;
+        self.observer.start();
+        self.timings.begin(HandshakeStep::ResponderStep1Prepare);
+        let message = self.strip_legacy_prelude(message);
+        let result = self.step_1_prepare_inner(message);
+        self.timings.end();
+        match &result {
+            Ok(token) => debug!(
+                "Noise handshake responder step 1: received initiator ephemeral key {} ({} bytes)",
+                token.initiator_ephemeral_key_fingerprint(),
+                message.len()
+            ),
+            Err(e) => {
+                warning!(
+                    "Noise handshake failed at responder step 1 (prepare): {:?}",
+                    e
+                );
+                self.observer.fail(e);
+                self.timings.reset();
+            }
+        }
+        result
+    }
+
+    /// Same as [`Self::step_1_prepare`], for `bytes` read from the transport which may go on past
+    /// the initiator ephemeral key, e.g. when the initiator pipelines data right after it.
+    ///
+    /// Returns the [`Step1Token`] along with the bytes following the ephemeral key.
+    ///
+    /// With [`Self::set_legacy_prelude_tolerance`], `bytes` starting with a
+    /// [`LEGACY_HANDSHAKE_PRELUDE`] and long enough to hold it in front of the ephemeral key are
+    /// taken to carry it, as the prelude can't be told apart from the start of an ephemeral key
+    /// once more bytes follow. An initiator without the prelude whose key happens to start with
+    /// the same two bytes (one in 2^16 handshakes) and which pipelines data right after it then
+    /// fails the handshake, and has to reconnect.
+    pub fn step_1_prepare_with_leftover(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<(Step1Token, Vec<u8>), Error> {
+        let message_len = HandshakeLayout::CURRENT.initiator_message_size();
+        let bytes = match bytes.strip_prefix(&LEGACY_HANDSHAKE_PRELUDE[..]) {
+            Some(stripped) if self.legacy_prelude_tolerance && stripped.len() >= message_len => {
+                debug!(
+                    "Noise handshake responder step 1: stripped legacy prelude {:02x?}",
+                    LEGACY_HANDSHAKE_PRELUDE
+                );
+                stripped
+            }
+            _ => bytes,
+        };
+        let (message, leftover) = bytes.split_at(message_len.min(bytes.len()));
+        if !leftover.is_empty() {
+            debug!(
+                "Noise handshake responder step 1: {} bytes after the initiator ephemeral key",
+                leftover.len()
+            );
+        }
+        let token = self.step_1_prepare(message)?;
+        Ok((token, leftover.to_vec()))
+    }
+
+    /// Executes the first step of the handshake as [`Self::step_1_from_slice`], returning a
+    /// message of the size of [`Self::handshake_layout`].
+    ///
+    /// Required by responders constructed with [`Self::with_delegation`], whose message doesn't
+    /// fit the fixed size variants.
+    pub fn step_1_to_vec<R: rand::Rng + rand::CryptoRng>(
+        &mut self,
+        message: &[u8],
+        now: u32,
+        rng: &mut R,
+    ) -> Result<(Vec<u8>, NoiseCodec), Error> {
+        let token = self.step_1_prepare(message)?;
+        self.step_1_finish_to_vec(token, now, rng)
+    }
+
+    /// Completes the first step of the handshake started by [`Self::step_1_prepare`].
+    ///
+    /// Fails with [`Error::InvalidHandshakeToken`] if `token` was not produced by the last call
+    /// to [`Self::step_1_prepare`] on this [`Responder`], and with
+    /// [`Error::UnexpectedHandshakeLength`] if the responder follows
+    /// [`HandshakeLayout::DELEGATED`] (see [`Self::step_1_finish_to_vec`]).
+    pub fn step_1_finish<R: rand::Rng + rand::CryptoRng>(
+        &mut self,
+        token: Step1Token,
+        now: u32,
+        rng: &mut R,
+    ) -> Result<([u8; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE], NoiseCodec), Error> {
+        let mut out = [0; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE];
+        let codec = self.step_1_finish_into(token, now, rng, &mut out)?;
+        Ok((out, codec))
+    }
+
+    /// Completes the first step of the handshake started by [`Self::step_1_prepare`], returning
+    /// a message of the size of [`Self::handshake_layout`].
+    ///
+    /// See [`Self::step_1_finish`] for more details.
+    pub fn step_1_finish_to_vec<R: rand::Rng + rand::CryptoRng>(
+        &mut self,
+        token: Step1Token,
+        now: u32,
+        rng: &mut R,
+    ) -> Result<(Vec<u8>, NoiseCodec), Error> {
+        let mut out = alloc::vec![0; self.handshake_layout().responder_message_size()];
+        let codec = self.step_1_finish_into(token, now, rng, &mut out)?;
+        Ok((out, codec))
+    }
+
+    /// Sets an observer to be notified about the progress of the handshake.
+    ///
+    /// See [`HandshakeObserver`] for more details.
+    pub fn set_handshake_observer(&mut self, observer: Arc<dyn HandshakeObserver>) {
+        self.observer.set_observer(observer);
+    }
+
+    /// Accepts initiators sending a [`LEGACY_HANDSHAKE_PRELUDE`] before their first message, as
+    /// some older firmware does. Off by default, in which case such initiators fail with
+    /// [`Error::UnexpectedHandshakeLength`].
+    ///
+    /// Initiators without the prelude are accepted either way.
+    pub fn set_legacy_prelude_tolerance(&mut self, legacy_prelude_tolerance: bool) {
+        self.legacy_prelude_tolerance = legacy_prelude_tolerance;
+    }
+
+    // Returns the initiator's message without its legacy prelude, if tolerated and present.
+    //
+    // The prelude is only recognized in front of a message of the expected size, as the
+    // ephemeral key itself may start with the same bytes.
+    fn strip_legacy_prelude<'m>(&self, message: &'m [u8]) -> &'m [u8] {
+        if !self.legacy_prelude_tolerance
+            || message.len() != LEGACY_HANDSHAKE_PRELUDE.len() + ELLSWIFT_ENCODING_SIZE
+        {
+            return message;
+        }
+        match message.strip_prefix(&LEGACY_HANDSHAKE_PRELUDE[..]) {
+            Some(message) => {
+                debug!(
+                    "Noise handshake responder step 1: stripped legacy prelude {:02x?}",
+                    LEGACY_HANDSHAKE_PRELUDE
+                );
+                message
+            }
+            None => message,
+        }
+    }
+
+    /// Sets the clock used to measure the step functions, whose timings are then returned by
+    /// [`NoiseCodec::handshake_timings`].
+    ///
+    /// See [`TimeProvider`] for more details.
+    pub fn set_time_provider(&mut self, clock: Arc<dyn TimeProvider>) {
+        self.timings.set_time_provider(clock);
+    }
+
+    // Processes the cheap part of the first step of the handshake, see
+    // [`Self::step_1_prepare`].
+    fn step_1_prepare_inner(&mut self, message: &[u8]) -> Result<Step1Token, Error> {
+        let layout = HandshakeLayout::CURRENT;
+        layout.check_len(HandshakeStage::InitiatorEphemeralKey, message.len())?;
+        let elligatorswift_theirs_ephemeral_serialized: [u8; ELLSWIFT_ENCODING_SIZE] = message
+            .try_into()
+            .map_err(|_| Error::UnexpectedHandshakeLength {
+                stage: HandshakeStage::InitiatorEphemeralKey,
+                expected: ELLSWIFT_ENCODING_SIZE,
+                got: message.len(),
+            })?;
+
         // 4.5.1.2 Responder
         Self::mix_hash(self, &elligatorswift_theirs_ephemeral_serialized[..]);
-        Self::decrypt_and_hash(self, &mut vec![])?;
+        Self::decrypt_and_hash(self, &mut HandshakeBuffer::<0>::new())?;
 
         // 4.5.2.1 Responder
-        let mut out = [0; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE];
         let keypair = self.e;
         let elligatorswitf_ours_ephemeral = ElligatorSwift::from_pubkey(keypair.public_key());
         let elligatorswift_ours_ephemeral_serialized = elligatorswitf_ours_ephemeral.to_array();
-        out[..ELLSWIFT_ENCODING_SIZE]
-            .copy_from_slice(&elligatorswift_ours_ephemeral_serialized[..ELLSWIFT_ENCODING_SIZE]);
 
         // 3. calls `MixHash(e.public_key)`
         // what is here is not the public key encoded with ElligatorSwift, but the x-coordinate of
@@ -330,17 +686,82 @@ impl Responder {
         .to_secret_bytes();
         Self::mix_key(self, &ecdh_ephemeral);
 
+        Ok(Step1Token {
+            theirs_ephemeral: elligatorswift_theirs_ephemeral_serialized,
+            ours_ephemeral: elligatorswift_ours_ephemeral_serialized,
+            handshake_hash: self.h,
+        })
+    }
+
+    // Completes the first step of the handshake into `out`, notifying the observer.
+    fn step_1_finish_into<R: rand::Rng + rand::CryptoRng>(
+        &mut self,
+        token: Step1Token,
+        now: u32,
+        rng: &mut R,
+        out: &mut [u8],
+    ) -> Result<NoiseCodec, Error> {
+        self.timings.begin(HandshakeStep::ResponderStep1Finish);
+        let mut result = self.step_1_finish_inner(token, now, rng, out);
+        self.timings.end();
+        match &mut result {
+            Ok(codec) => {
+                debug!(
+                    "Noise handshake responder step 1: completed with static key {}, sent \
+                     {} bytes, certificate valid from {} to {}, delegated: {}, cipher {}",
+                    self.static_key_fingerprint(),
+                    out.len(),
+                    now,
+                    now + self.cert_validity,
+                    self.delegation.is_some(),
+                    TRANSPORT_CIPHER
+                );
+                self.observer.complete();
+                codec.handshake_timings = self.timings.take();
+                if let Some(timings) = &codec.handshake_timings {
+                    self.observer.timings(timings);
+                }
+            }
+            Err(e) => {
+                warning!(
+                    "Noise handshake failed at responder step 1 (finish): {:?}",
+                    e
+                );
+                self.observer.fail(e);
+                self.timings.reset();
+            }
+        }
+        result
+    }
+
+    // Processes the expensive part of the first step of the handshake, see
+    // [`Self::step_1_finish`].
+    fn step_1_finish_inner<R: rand::Rng + rand::CryptoRng>(
+        &mut self,
+        token: Step1Token,
+        now: u32,
+        rng: &mut R,
+        out: &mut [u8],
+    ) -> Result<NoiseCodec, Error> {
+        if token.handshake_hash != self.h {
+            return Err(Error::InvalidHandshakeToken);
+        }
+        let layout = self.handshake_layout();
+        layout.check_len(HandshakeStage::ResponderMessage, out.len())?;
+        let initiator_ephemeral_key_fingerprint = token.initiator_ephemeral_key_fingerprint();
+
+        out[layout.responder_ephemeral_key_range()].copy_from_slice(&token.ours_ephemeral);
+        let elligatorswift_theirs_ephemeral = ElligatorSwift::from_array(token.theirs_ephemeral);
+
         // 5. appends `EncryptAndHash(s.public_key)` (64 bytes encrypted elligatorswift  public key,
         //    16 bytes MAC)
-        let mut encrypted_static_pub_k = vec![0; ELLSWIFT_ENCODING_SIZE];
         let elligatorswift_ours_static = ElligatorSwift::from_pubkey(self.s.public_key());
-        let elligatorswift_ours_static_serialized: [u8; ELLSWIFT_ENCODING_SIZE] =
-            elligatorswift_ours_static.to_array();
-        encrypted_static_pub_k[..ELLSWIFT_ENCODING_SIZE]
-            .copy_from_slice(&elligatorswift_ours_static_serialized[0..ELLSWIFT_ENCODING_SIZE]);
+        let mut encrypted_static_pub_k =
+            HandshakeBuffer::<ENCRYPTED_ELLSWIFT_ENCODING_SIZE>::from_slice(
+                &elligatorswift_ours_static.to_array(),
+            );
         self.encrypt_and_hash(&mut encrypted_static_pub_k)?;
-        out[ELLSWIFT_ENCODING_SIZE..(ELLSWIFT_ENCODING_SIZE + ENCRYPTED_ELLSWIFT_ENCODING_SIZE)]
-            .copy_from_slice(&encrypted_static_pub_k[..(ENCRYPTED_ELLSWIFT_ENCODING_SIZE)]);
+        out[layout.encrypted_static_key_range()].copy_from_slice(encrypted_static_pub_k.as_ref());
         // note: 64+16+64 = 144
 
         // 6. calls `MixKey(ECDH(s.private_key, re.public_key))`
@@ -358,35 +779,24 @@ impl Responder {
         // 7. appends `EncryptAndHash(SIGNATURE_NOISE_MESSAGE)` to the buffer
         let valid_from = now;
         let not_valid_after = now + self.cert_validity;
-        let signature_noise_message = self.get_signature(VERSION, valid_from, not_valid_after, rng);
-        let mut signature_part = Vec::with_capacity(ENCRYPTED_SIGNATURE_NOISE_MESSAGE_SIZE);
-        signature_part.extend_from_slice(&signature_noise_message[..]);
+        let mut signature_part = self.get_signature(valid_from, not_valid_after, rng)?;
         Self::encrypt_and_hash(self, &mut signature_part)?;
-        let ephemeral_plus_static_encrypted_length =
-            ELLSWIFT_ENCODING_SIZE + ENCRYPTED_ELLSWIFT_ENCODING_SIZE;
-        out[ephemeral_plus_static_encrypted_length..(INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE)]
-            .copy_from_slice(&signature_part[..ENCRYPTED_SIGNATURE_NOISE_MESSAGE_SIZE]);
+        out[layout.encrypted_signature_noise_message_range()]
+            .copy_from_slice(signature_part.as_ref());
 
         // 9. return pair of CipherState objects, the first for encrypting transport messages from
         //    initiator to responder, and the second for messages in the other direction:
         let ck = Self::get_ck(self);
         let (temp_k1, temp_k2) = Self::hkdf_2(ck, &[]);
-        let c1 = ChaCha20Poly1305::new(&temp_k1.into());
-        let c2 = ChaCha20Poly1305::new(&temp_k2.into());
-        let c1: Cipher<ChaCha20Poly1305> = Cipher::from_key_and_cipher(temp_k1, c1);
-        let c2: Cipher<ChaCha20Poly1305> = Cipher::from_key_and_cipher(temp_k2, c2);
-        let to_send = out;
         self.c1 = None;
         self.c2 = None;
-        let mut encryptor = GenericCipher::ChaCha20Poly1305(c2);
-        let mut decryptor = GenericCipher::ChaCha20Poly1305(c1);
-        encryptor.erase_k();
-        decryptor.erase_k();
-        let codec = crate::NoiseCodec {
-            encryptor,
-            decryptor,
-        };
-        Ok((to_send, codec))
+        let codec = NoiseCodec::from_transport_keys(
+            temp_k2,
+            temp_k1,
+            initiator_ephemeral_key_fingerprint,
+            None,
+        );
+        Ok(codec)
     }
 
     // Generates a signature noise message for the responder's certificate.
@@ -394,16 +804,22 @@ impl Responder {
     // This method creates a signature noise message that includes the protocol version,
     // certificate validity period, and a cryptographic signature. The signature is created using
     // the responder's static public key and authority keypair, ensuring that the responder's
-    // identity and certificate validity are cryptographically verifiable.
+    // identity and certificate validity are cryptographically verifiable. A delegated responder
+    // appends its delegation, which chains the (delegate) signing key to the authority.
+    //
+    // The buffer leaves room for the MAC, to be encrypted in place.
     #[inline]
     fn get_signature<R: rand::Rng + rand::CryptoRng>(
         &self,
-        version: u16,
         valid_from: u32,
         not_valid_after: u32,
         rng: &mut R,
-    ) -> [u8; 74] {
-        let mut ret = [0; 74];
+    ) -> Result<HandshakeBuffer<ENCRYPTED_DELEGATED_SIGNATURE_NOISE_MESSAGE_SIZE>, Error> {
+        let version = match self.delegation {
+            Some(_) => DELEGATED_CERTIFICATE_VERSION,
+            None => VERSION,
+        };
+        let mut ret = [0; SIGNATURE_NOISE_MESSAGE_SIZE];
         let version = version.to_le_bytes();
         let valid_from = valid_from.to_le_bytes();
         let not_valid_after = not_valid_after.to_le_bytes();
@@ -418,7 +834,11 @@ impl Responder {
         ret[8] = not_valid_after[2];
         ret[9] = not_valid_after[3];
         SignatureNoiseMessage::sign_with_rng(&mut ret, &self.s.x_only_public_key().0, &self.a, rng);
-        ret
+        let mut signature_noise_message = HandshakeBuffer::from_slice(&ret);
+        if let Some(delegation) = &self.delegation {
+            signature_noise_message.extend_from_slice(&delegation.to_bytes())?;
+        }
+        Ok(signature_noise_message)
     }
 
     // Securely erases sensitive data in the responder's memory.
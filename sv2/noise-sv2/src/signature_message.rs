@@ -23,7 +23,33 @@
 
 use core::convert::TryInto;
 
-use secp256k1::{hashes::sha256, schnorr::Signature, Keypair, Message, Secp256k1, XOnlyPublicKey};
+use secp256k1::{
+    ffi::types::AlignedType,
+    hashes::{sha256, Hash, HashEngine},
+    schnorr::Signature,
+    Keypair, Message, Secp256k1, XOnlyPublicKey,
+};
+
+// Words of the stack buffer holding the signing context, well above what `libsecp256k1` needs
+// (see `Secp256k1::preallocate_signing_size`).
+const SIGNING_CONTEXT_WORDS: usize = 64;
+
+// Signs `m` with `kp`, the signing context living on the stack so that signing doesn't allocate.
+pub(crate) fn sign_schnorr_with_rng<R: rand::Rng + rand::CryptoRng>(
+    m: &Message,
+    kp: &Keypair,
+    rng: &mut R,
+) -> Signature {
+    let mut buf = [AlignedType::zeroed(); SIGNING_CONTEXT_WORDS];
+    let signature = match Secp256k1::preallocated_signing_only(&mut buf) {
+        Ok(mut secp) => {
+            secp.randomize(rng);
+            secp.sign_schnorr_with_rng(m, kp, rng)
+        }
+        Err(_) => Secp256k1::signing_only().sign_schnorr_with_rng(m, kp, rng),
+    };
+    signature
+}
 
 /// `SignatureNoiseMessage` represents a signed message used in the Noise NX protocol
 /// for authentication during the handshake process. It encapsulates the necessary
@@ -98,9 +124,7 @@ impl SignatureNoiseMessage {
             if self.valid_from <= now && self.not_valid_after >= now {
                 let secp = Secp256k1::verification_only();
                 let (m, s) = self.split();
-                // m = SHA-256(version || valid_from || not_valid_after || server_static_key)
-                let m = [&m[0..10], &pk.serialize()].concat();
-                let m = Message::from_hashed_data::<sha256::Hash>(&m);
+                let m = Self::message(&m, pk);
                 let s = match Signature::from_slice(&s) {
                     Ok(s) => s,
                     _ => return false,
@@ -140,15 +164,21 @@ impl SignatureNoiseMessage {
         kp: &Keypair,
         rng: &mut R,
     ) {
-        let secp = Secp256k1::signing_only();
-        let m = [&msg[0..10], &static_pk.serialize()].concat();
-        let m = Message::from_hashed_data::<sha256::Hash>(&m);
-        let signature = secp.sign_schnorr_with_rng(&m, kp, rng);
+        let m = Self::message(&msg[0..10], static_pk);
+        let signature = sign_schnorr_with_rng(&m, kp, rng);
         for (i, b) in signature.as_ref().iter().enumerate() {
             msg[10 + i] = *b;
         }
     }
 
+    // m = SHA-256(version || valid_from || not_valid_after || server_static_key)
+    fn message(header: &[u8], static_pk: &XOnlyPublicKey) -> Message {
+        let mut engine = sha256::Hash::engine();
+        engine.input(header);
+        engine.input(&static_pk.serialize());
+        Message::from_digest(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+
     // Splits the [`SignatureNoiseMessage`] into its component parts: the message hash and the
     // signature.
     //
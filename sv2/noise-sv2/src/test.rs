@@ -1,4 +1,29 @@
-use crate::{handshake::HandshakeOp, initiator::Initiator, responder::Responder};
+use crate::{
+    aed_cipher::AeadCipher,
+    certificate::{KeyFingerprint, ResponderCertificate},
+    cipher_state::{Cipher, CipherState},
+    decrypted_len,
+    delegation::{Delegation, DELEGATED_CERTIFICATE_VERSION},
+    encrypted_len,
+    error::Error,
+    handshake::HandshakeOp,
+    initiator::Initiator,
+    layout::{HandshakeLayout, HandshakeStage},
+    observer::HandshakeObserver,
+    responder::Responder,
+    timings::{HandshakeStep, HandshakeTimings, TimeProvider},
+    NoiseCodec, AEAD_MAC_LEN, DELEGATED_HANDSHAKE_MESSAGE_SIZE, ELLSWIFT_ENCODING_SIZE,
+    INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE, KEY_CONFIRMATION_PREFIX, KEY_CONFIRMATION_SIZE,
+    LEGACY_HANDSHAKE_PRELUDE, MAX_CHUNK_PLAINTEXT_SIZE, MAX_CHUNK_SIZE,
+};
+use chacha20poly1305::ChaCha20Poly1305;
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 #[test]
 #[cfg(feature = "std")]
@@ -44,3 +69,1316 @@ fn test_1_with_rng() {
 
     assert!(message == "ciao".as_bytes().to_vec());
 }
+
+#[derive(Default)]
+struct CountingObserver {
+    clock: AtomicU64,
+    started: AtomicUsize,
+    completed: AtomicUsize,
+    bad_certificates: AtomicUsize,
+    mac_failures: AtomicUsize,
+    last_duration: AtomicU64,
+}
+
+impl HandshakeObserver for CountingObserver {
+    // every call moves the clock forward by one millisecond
+    fn now(&self) -> Duration {
+        Duration::from_millis(self.clock.fetch_add(1, Ordering::SeqCst))
+    }
+
+    fn on_start(&self) {
+        self.started.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_complete(&self, duration: Duration) {
+        self.completed.fetch_add(1, Ordering::SeqCst);
+        self.last_duration
+            .store(duration.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    fn on_failure(&self, error: &Error) {
+        match error {
+            Error::InvalidCertificate(_) => self.bad_certificates.fetch_add(1, Ordering::SeqCst),
+            Error::AesGcm(_) => self.mac_failures.fetch_add(1, Ordering::SeqCst),
+            _ => panic!("unexpected failure: {:?}", error),
+        };
+    }
+}
+
+#[test]
+fn test_handshake_observer() {
+    let observer = Arc::new(CountingObserver::default());
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+
+    // successful handshake
+    let mut initiator =
+        Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    initiator.set_handshake_observer(observer.clone());
+    responder.set_handshake_observer(observer.clone());
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, _) = responder
+        .step_1_with_now_rng(first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    initiator.step_2_with_now(second_message, now).unwrap();
+    assert_eq!(observer.started.load(Ordering::SeqCst), 2);
+    assert_eq!(observer.completed.load(Ordering::SeqCst), 2);
+    // initiator clock reads: start (0), responder: start (1) and end (2), initiator: end (3)
+    assert_eq!(observer.last_duration.load(Ordering::SeqCst), 3);
+
+    // the responder is signed by an unexpected authority
+    let unknown_authority = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let mut initiator = Initiator::new_with_rng(
+        Some(unknown_authority.public_key().into()),
+        &mut rand::thread_rng(),
+    );
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    initiator.set_handshake_observer(observer.clone());
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, _) = responder
+        .step_1_with_now_rng(first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    assert!(initiator.step_2_with_now(second_message, now).is_err());
+    assert_eq!(observer.bad_certificates.load(Ordering::SeqCst), 1);
+
+    // the responder message is tampered with
+    let mut initiator =
+        Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    initiator.set_handshake_observer(observer.clone());
+    let first_message = initiator.step_0().unwrap();
+    let (mut second_message, _) = responder
+        .step_1_with_now_rng(first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    second_message[ELLSWIFT_ENCODING_SIZE] ^= 1;
+    assert!(initiator.step_2_with_now(second_message, now).is_err());
+    assert_eq!(observer.mac_failures.load(Ordering::SeqCst), 1);
+
+    assert_eq!(observer.started.load(Ordering::SeqCst), 4);
+    assert_eq!(observer.completed.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_handshake_off_by_one_messages() {
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+
+    // responder receiving the first message
+    let mut initiator =
+        Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+    let first_message = initiator.step_0().unwrap().to_vec();
+    let mut too_long = first_message.clone();
+    too_long.push(0);
+    for message in [&first_message[1..], &too_long[..]] {
+        let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+        let res = responder.step_1_from_slice(message, now, &mut rand::thread_rng());
+        assert_eq!(
+            res.err(),
+            Some(Error::UnexpectedHandshakeLength {
+                stage: HandshakeStage::InitiatorEphemeralKey,
+                expected: ELLSWIFT_ENCODING_SIZE,
+                got: message.len(),
+            })
+        );
+    }
+
+    // initiator receiving the second message
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    let (second_message, _) = responder
+        .step_1_from_slice(&first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    let mut too_long = second_message.to_vec();
+    too_long.push(0);
+    for message in [&second_message[1..], &too_long[..]] {
+        let res = initiator.step_2_from_slice(message, now);
+        assert_eq!(
+            res.err(),
+            Some(Error::UnexpectedHandshakeLength {
+                stage: HandshakeStage::ResponderMessage,
+                expected: INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
+                got: message.len(),
+            })
+        );
+    }
+
+    // the exact sizes still complete the handshake
+    assert!(initiator.step_2_from_slice(&second_message, now).is_ok());
+}
+
+#[test]
+fn test_legacy_handshake_prelude() {
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+
+    for (legacy_prelude, legacy_prelude_tolerance) in
+        [(false, false), (false, true), (true, false), (true, true)]
+    {
+        let mut initiator =
+            Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+        initiator.set_legacy_prelude(legacy_prelude);
+        let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+        responder.set_legacy_prelude_tolerance(legacy_prelude_tolerance);
+
+        let first_message = initiator.step_0_to_vec().unwrap();
+        assert_eq!(
+            first_message.starts_with(&LEGACY_HANDSHAKE_PRELUDE),
+            legacy_prelude
+        );
+        let res = responder.step_1_from_slice(&first_message, now, &mut rand::thread_rng());
+        if legacy_prelude && !legacy_prelude_tolerance {
+            // strict responders don't know about the prelude
+            assert_eq!(
+                res.err(),
+                Some(Error::UnexpectedHandshakeLength {
+                    stage: HandshakeStage::InitiatorEphemeralKey,
+                    expected: ELLSWIFT_ENCODING_SIZE,
+                    got: ELLSWIFT_ENCODING_SIZE + LEGACY_HANDSHAKE_PRELUDE.len(),
+                })
+            );
+            continue;
+        }
+        let (second_message, mut codec_responder) = res.unwrap();
+        let mut codec_initiator = initiator.step_2_with_now(second_message, now).unwrap();
+        let mut message = b"ciao".to_vec();
+        codec_initiator.encrypt(&mut message).unwrap();
+        codec_responder.decrypt(&mut message).unwrap();
+        assert_eq!(message, b"ciao");
+    }
+
+    // only the exact prelude is stripped
+    let mut initiator =
+        Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+    let mut first_message = vec![0x01, 0x00];
+    first_message.extend_from_slice(&initiator.step_0().unwrap());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    responder.set_legacy_prelude_tolerance(true);
+    assert!(matches!(
+        responder.step_1_from_slice(&first_message, now, &mut rand::thread_rng()),
+        Err(Error::UnexpectedHandshakeLength { .. })
+    ));
+}
+
+#[test]
+fn test_responder_certificate() {
+    let authority = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let cert_validity = 31449600;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+
+    let mut initiator =
+        Initiator::new_with_rng(Some(authority.public_key().into()), &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(authority, cert_validity, &mut rand::thread_rng());
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, codec_responder) = responder
+        .step_1_with_now_rng(first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    let codec_initiator = initiator.step_2_with_now(second_message, now).unwrap();
+
+    assert_eq!(codec_responder.responder_certificate(), None);
+    assert_eq!(
+        codec_initiator.responder_certificate(),
+        Some(&ResponderCertificate {
+            version: 0,
+            valid_from: now,
+            not_valid_after: now + cert_validity,
+            static_key_fingerprint: responder.static_key_fingerprint(),
+            authority_key_fingerprint: Some(KeyFingerprint::from_key(
+                &authority.x_only_public_key().0
+            )),
+            authority_key_index: Some(0),
+            delegate_key_fingerprint: None,
+        })
+    );
+
+    // without the authority key the certificate is still reported, but not attributed
+    let mut initiator = Initiator::without_pk_with_rng(&mut rand::thread_rng()).unwrap();
+    let first_message = initiator.step_0().unwrap();
+    let mut responder = Responder::new_with_rng(authority, cert_validity, &mut rand::thread_rng());
+    let (second_message, _) = responder
+        .step_1_with_now_rng(first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    let codec_initiator = initiator.step_2_with_now(second_message, now).unwrap();
+    let certificate = codec_initiator.responder_certificate().unwrap();
+    assert_eq!(certificate.authority_key_fingerprint, None);
+}
+
+#[test]
+fn test_authority_key_rotation() {
+    let old_authority = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let new_authority = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let old_pk = old_authority.x_only_public_key().0;
+    let new_pk = new_authority.x_only_public_key().0;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+
+    // runs a handshake against a responder signed by `authority`, returning the index and the
+    // fingerprint of the authority key that matched
+    let handshake = |pks: Vec<secp256k1::XOnlyPublicKey>, authority| {
+        let mut initiator =
+            Initiator::with_authority_keys_with_rng(pks, &mut rand::thread_rng()).unwrap();
+        let mut responder = Responder::new_with_rng(authority, 31449600, &mut rand::thread_rng());
+        let first_message = initiator.step_0().unwrap();
+        let (second_message, _) = responder
+            .step_1_with_now_rng(first_message, now, &mut rand::thread_rng())
+            .unwrap();
+        initiator.step_2_with_now(second_message, now).map(|codec| {
+            let certificate = codec.responder_certificate().unwrap();
+            (
+                certificate.authority_key_index.unwrap(),
+                certificate.authority_key_fingerprint.unwrap(),
+            )
+        })
+    };
+    let old = KeyFingerprint::from_key(&old_pk);
+    let new = KeyFingerprint::from_key(&new_pk);
+
+    // old key only: the initiator breaks once the responder is signed by the new key
+    assert_eq!(handshake(vec![old_pk], old_authority), Ok((0, old)));
+    assert!(matches!(
+        handshake(vec![old_pk], new_authority),
+        Err(Error::InvalidCertificate(_))
+    ));
+
+    // new key only: the initiator can't connect to a responder not rotated yet
+    assert!(matches!(
+        handshake(vec![new_pk], old_authority),
+        Err(Error::InvalidCertificate(_))
+    ));
+    assert_eq!(handshake(vec![new_pk], new_authority), Ok((0, new)));
+
+    // both keys: the initiator connects either way, and reports which key was used
+    assert_eq!(handshake(vec![new_pk, old_pk], new_authority), Ok((0, new)));
+    assert_eq!(handshake(vec![new_pk, old_pk], old_authority), Ok((1, old)));
+
+    assert_eq!(
+        Initiator::with_authority_keys_with_rng(vec![], &mut rand::thread_rng()).err(),
+        Some(Error::AuthorityKeyListMustBeNonEmpty)
+    );
+}
+
+#[test]
+fn test_key_fingerprint_format() {
+    // x-only serialization of the secp256k1 generator point
+    let key = secp256k1::XOnlyPublicKey::from_slice(&[
+        0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62, 0x95, 0xce, 0x87, 0x0b,
+        0x07, 0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9, 0x59, 0xf2, 0x81, 0x5b, 0x16, 0xf8,
+        0x17, 0x98,
+    ])
+    .unwrap();
+    let fingerprint = KeyFingerprint::from_key(&key);
+    assert_eq!(fingerprint.to_string(), "132f39a98c31baad");
+    assert_eq!(
+        fingerprint.as_bytes(),
+        &[0x13, 0x2f, 0x39, 0xa9, 0x8c, 0x31, 0xba, 0xad]
+    );
+}
+
+#[test]
+fn test_two_stage_step_1_matches_one_shot() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    let authority = Responder::generate_key_with_rng(&mut ChaCha20Rng::seed_from_u64(1));
+    let now = 1_700_000_000;
+    let mut initiator = Initiator::new_with_rng(
+        Some(authority.public_key().into()),
+        &mut ChaCha20Rng::seed_from_u64(2),
+    );
+    let first_message = initiator.step_0().unwrap();
+
+    let mut one_shot =
+        Responder::new_with_rng(authority, 31449600, &mut ChaCha20Rng::seed_from_u64(3));
+    let (one_shot_message, mut one_shot_codec) = one_shot
+        .step_1_with_now_rng(first_message, now, &mut ChaCha20Rng::seed_from_u64(4))
+        .unwrap();
+
+    let mut two_stage =
+        Responder::new_with_rng(authority, 31449600, &mut ChaCha20Rng::seed_from_u64(3));
+    let token = two_stage.step_1_prepare(&first_message).unwrap();
+    let (two_stage_message, mut two_stage_codec) = two_stage
+        .step_1_finish(token, now, &mut ChaCha20Rng::seed_from_u64(4))
+        .unwrap();
+
+    assert_eq!(one_shot_message, two_stage_message);
+    let mut one_shot_encrypted = "ciao".as_bytes().to_vec();
+    one_shot_codec.encrypt(&mut one_shot_encrypted).unwrap();
+    let mut two_stage_encrypted = "ciao".as_bytes().to_vec();
+    two_stage_codec.encrypt(&mut two_stage_encrypted).unwrap();
+    assert_eq!(one_shot_encrypted, two_stage_encrypted);
+
+    // the initiator completes the handshake with the two-stage message
+    let mut codec_initiator = initiator.step_2_with_now(two_stage_message, now).unwrap();
+    codec_initiator.decrypt(&mut two_stage_encrypted).unwrap();
+    assert_eq!(two_stage_encrypted, "ciao".as_bytes().to_vec());
+}
+
+#[test]
+fn test_step_1_finish_with_foreign_token() {
+    let authority = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let mut initiator = Initiator::new_with_rng(None, &mut rand::thread_rng());
+    let first_message = initiator.step_0().unwrap();
+
+    let mut responder = Responder::new_with_rng(authority, 31449600, &mut rand::thread_rng());
+    let mut other_responder = Responder::new_with_rng(authority, 31449600, &mut rand::thread_rng());
+    responder.step_1_prepare(&first_message).unwrap();
+    let foreign_token = other_responder.step_1_prepare(&first_message).unwrap();
+
+    let res = responder.step_1_finish(foreign_token, 0, &mut rand::thread_rng());
+    assert_eq!(res.err(), Some(Error::InvalidHandshakeToken));
+}
+
+#[test]
+#[cfg(feature = "insecure-debug")]
+fn test_codec_debug_state_desync() {
+    let authority = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let mut initiator = Initiator::new_with_rng(None, &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(authority, 31449600, &mut rand::thread_rng());
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, mut codec_responder) = responder.step_1(first_message).unwrap();
+    let mut codec_initiator = initiator.step_2(second_message).unwrap();
+
+    // the initiator sends two frames, the first one is lost on the way
+    let mut lost = "lost".as_bytes().to_vec();
+    codec_initiator.encrypt(&mut lost).unwrap();
+    let mut received = "received".as_bytes().to_vec();
+    codec_initiator.encrypt(&mut received).unwrap();
+    assert!(codec_responder.decrypt(&mut received).is_err());
+
+    let initiator_state = codec_initiator.debug_state();
+    let responder_state = codec_responder.debug_state();
+    // keys match in both directions...
+    assert_eq!(
+        initiator_state.encryptor.key_fingerprint,
+        responder_state.decryptor.key_fingerprint
+    );
+    assert_eq!(
+        initiator_state.decryptor.key_fingerprint,
+        responder_state.encryptor.key_fingerprint
+    );
+    assert_ne!(
+        initiator_state.encryptor.key_fingerprint,
+        initiator_state.decryptor.key_fingerprint
+    );
+    // ...while the nonces diverged
+    assert_eq!(initiator_state.encryptor.nonce, 2);
+    assert_eq!(responder_state.decryptor.nonce, 0);
+    assert_eq!(initiator_state.decryptor, responder_state.encryptor);
+}
+
+#[test]
+fn test_clone_before_handshake() {
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let initiator = Initiator::new_with_rng(None, &mut rand::thread_rng());
+    let responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+
+    let mut initiator_clone = initiator.clone();
+    let mut responder_clone = responder.clone();
+    // clones never reuse the keys of the original
+    assert_ne!(
+        initiator_clone.ephemeral_key_fingerprint(),
+        initiator.ephemeral_key_fingerprint()
+    );
+    assert_ne!(
+        responder_clone.static_key_fingerprint(),
+        responder.static_key_fingerprint()
+    );
+    let first_message = initiator_clone.step_0().unwrap();
+    let (second_message, _) = responder_clone.step_1(first_message).unwrap();
+    assert!(initiator_clone.step_2(second_message).is_ok());
+}
+
+#[test]
+fn test_try_clone_with_rng() {
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let mut initiator = Initiator::new_with_rng(None, &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+
+    let mut initiator_clone = initiator
+        .try_clone_with_rng(&mut rand::thread_rng())
+        .unwrap();
+    assert_ne!(
+        initiator_clone.ephemeral_key_fingerprint(),
+        initiator.ephemeral_key_fingerprint()
+    );
+    let mut responder_clone = responder
+        .try_clone_with_rng(&mut rand::thread_rng())
+        .unwrap();
+    let (second_message, _) = responder_clone
+        .step_1(initiator_clone.step_0().unwrap())
+        .unwrap();
+    assert!(initiator_clone.step_2(second_message).is_ok());
+
+    // once the handshake has started, there's nothing left to clone
+    let first_message = initiator.step_0().unwrap();
+    assert_eq!(
+        initiator
+            .try_clone_with_rng(&mut rand::thread_rng())
+            .unwrap_err(),
+        Error::HandshakeAlreadyInProgress
+    );
+    responder.step_1(first_message).unwrap();
+    assert_eq!(
+        responder
+            .try_clone_with_rng(&mut rand::thread_rng())
+            .unwrap_err(),
+        Error::HandshakeAlreadyInProgress
+    );
+}
+
+#[test]
+#[should_panic(expected = "a Responder can't be cloned once the handshake has started")]
+fn test_responder_clone_after_step_1() {
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let mut initiator = Initiator::new_with_rng(None, &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    let first_message = initiator.step_0().unwrap();
+    responder.step_1(first_message).unwrap();
+    let _ = responder.clone();
+}
+
+#[test]
+#[should_panic(expected = "an Initiator can't be cloned once the handshake has started")]
+fn test_initiator_clone_after_step_0() {
+    let mut initiator = Initiator::new_with_rng(None, &mut rand::thread_rng());
+    initiator.step_0().unwrap();
+    let _ = initiator.clone();
+}
+
+#[test]
+fn test_initiator_ephemeral_key_fingerprint() {
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let mut initiator =
+        Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    let now = 1_700_000_000;
+    let fingerprint = initiator.ephemeral_key_fingerprint();
+
+    let first_message = initiator.step_0().unwrap();
+    assert_eq!(fingerprint, KeyFingerprint::from_bytes(&first_message));
+    let token = responder.step_1_prepare(&first_message).unwrap();
+    assert_eq!(token.initiator_ephemeral_key_fingerprint(), fingerprint);
+    let (second_message, codec_responder) = responder
+        .step_1_finish(token, now, &mut rand::thread_rng())
+        .unwrap();
+    let codec_initiator = initiator.step_2_with_now(second_message, now).unwrap();
+
+    assert_eq!(
+        codec_initiator.initiator_ephemeral_key_fingerprint(),
+        fingerprint
+    );
+    assert_eq!(
+        codec_responder.initiator_ephemeral_key_fingerprint(),
+        fingerprint
+    );
+
+    // every session has its own fingerprint
+    let other_initiator = Initiator::new_with_rng(None, &mut rand::thread_rng());
+    assert_ne!(other_initiator.ephemeral_key_fingerprint(), fingerprint);
+}
+
+#[test]
+fn test_key_confirmation() {
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let mut initiator = Initiator::new_with_rng(None, &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, mut codec_responder) = responder.step_1(first_message).unwrap();
+    let mut codec_initiator = initiator.step_2(second_message).unwrap();
+
+    let ping = codec_initiator.make_key_confirmation().unwrap();
+    assert_eq!(ping.len(), KEY_CONFIRMATION_SIZE);
+    assert_eq!(
+        ping[..KEY_CONFIRMATION_PREFIX.len()],
+        KEY_CONFIRMATION_PREFIX
+    );
+    assert_eq!(codec_responder.verify_key_confirmation(&ping), Ok(()));
+    let pong = codec_responder.make_key_confirmation().unwrap();
+    assert_eq!(codec_initiator.verify_key_confirmation(&pong), Ok(()));
+
+    // the session goes on as usual afterwards
+    let mut message = "ciao".as_bytes().to_vec();
+    codec_initiator.encrypt(&mut message).unwrap();
+    codec_responder.decrypt(&mut message).unwrap();
+    assert_eq!(message, "ciao".as_bytes().to_vec());
+}
+
+#[test]
+fn test_key_confirmation_mismatched_codec() {
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let mut initiator = Initiator::new_with_rng(None, &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, mut codec_responder) = responder.step_1(first_message).unwrap();
+    let _ = initiator.step_2(second_message).unwrap();
+
+    // a codec from another session
+    let mut other_initiator = Initiator::new_with_rng(None, &mut rand::thread_rng());
+    let mut other_responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    let first_message = other_initiator.step_0().unwrap();
+    let (second_message, _) = other_responder.step_1(first_message).unwrap();
+    let mut other_codec = other_initiator.step_2(second_message).unwrap();
+
+    let ping = other_codec.make_key_confirmation().unwrap();
+    assert_eq!(
+        codec_responder.verify_key_confirmation(&ping),
+        Err(Error::InvalidKeyConfirmation)
+    );
+
+    // wrong prefix or length
+    let mut wrong_prefix = ping;
+    wrong_prefix[0] ^= 1;
+    assert_eq!(
+        codec_responder.verify_key_confirmation(&wrong_prefix),
+        Err(Error::InvalidKeyConfirmation)
+    );
+    assert_eq!(
+        codec_responder.verify_key_confirmation(&ping[1..]),
+        Err(Error::InvalidKeyConfirmation)
+    );
+}
+
+#[test]
+fn test_cipher_key_lifecycle() {
+    let mut cipher = Cipher::from_cipher(ChaCha20Poly1305::from_key([0; 32]));
+    assert!(!cipher.has_key());
+    assert_eq!(cipher.take_key(), None);
+
+    cipher.install_key([1; 32]);
+    cipher.install_key([2; 32]);
+    assert!(cipher.has_key());
+    assert_eq!(cipher.take_key().as_deref(), Some(&[2; 32]));
+    assert!(!cipher.has_key());
+    assert_eq!(cipher.take_key(), None);
+
+    // the handshake installs and removes the keys through the same methods
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let mut initiator =
+        Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    assert!(!initiator.has_key());
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, mut codec_responder) = responder
+        .step_1_with_now_rng(first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    assert!(responder.has_key());
+    let mut codec_initiator = initiator.step_2_with_now(second_message, now).unwrap();
+    let mut message = b"ciao".to_vec();
+    codec_initiator.encrypt(&mut message).unwrap();
+    codec_responder.decrypt(&mut message).unwrap();
+    assert_eq!(message, b"ciao");
+}
+
+// Runs a handshake, returning the codecs of the initiator and of the responder.
+fn handshake_codecs() -> (NoiseCodec, NoiseCodec) {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let mut initiator =
+        Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, codec_responder) = responder
+        .step_1_with_now_rng(first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    let codec_initiator = initiator.step_2_with_now(second_message, now).unwrap();
+    (codec_initiator, codec_responder)
+}
+
+// Encrypts and decrypts a payload of `len` bytes in chunks, as the Sv2 codec does, checking the
+// sizes against `encrypted_len` and `decrypted_len`.
+fn chunked_round_trip(len: usize) -> bool {
+    let (mut codec_initiator, mut codec_responder) = handshake_codecs();
+    let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+
+    let mut ciphertext = Vec::new();
+    for chunk in plaintext.chunks(MAX_CHUNK_PLAINTEXT_SIZE) {
+        let mut chunk = chunk.to_vec();
+        codec_initiator.encrypt(&mut chunk).unwrap();
+        ciphertext.extend_from_slice(&chunk);
+    }
+    let mut decrypted = Vec::new();
+    for chunk in ciphertext.chunks(MAX_CHUNK_SIZE) {
+        let mut chunk = chunk.to_vec();
+        codec_responder.decrypt(&mut chunk).unwrap();
+        decrypted.extend_from_slice(&chunk);
+    }
+
+    ciphertext.len() == encrypted_len(len)
+        && decrypted_len(ciphertext.len()) == Ok(len)
+        && decrypted == plaintext
+}
+
+#[test]
+fn test_chunk_boundaries_round_trip() {
+    for len in [
+        0,
+        1,
+        MAX_CHUNK_PLAINTEXT_SIZE - 1,
+        MAX_CHUNK_PLAINTEXT_SIZE,
+        MAX_CHUNK_PLAINTEXT_SIZE + 1,
+        2 * MAX_CHUNK_PLAINTEXT_SIZE,
+        2 * MAX_CHUNK_PLAINTEXT_SIZE + 1,
+    ] {
+        assert!(chunked_round_trip(len), "len {}", len);
+    }
+}
+
+#[quickcheck_macros::quickcheck]
+fn test_chunked_round_trip(len: u32) -> bool {
+    // spans up to three chunks
+    chunked_round_trip(len as usize % (3 * MAX_CHUNK_SIZE))
+}
+
+#[test]
+fn test_decrypted_len_rejects_short_chunk() {
+    assert_eq!(decrypted_len(0), Ok(0));
+    assert_eq!(
+        decrypted_len(AEAD_MAC_LEN),
+        Err(Error::InvalidMessageLength)
+    );
+    assert_eq!(decrypted_len(AEAD_MAC_LEN + 1), Ok(1));
+    assert_eq!(
+        decrypted_len(MAX_CHUNK_SIZE + AEAD_MAC_LEN),
+        Err(Error::InvalidMessageLength)
+    );
+    assert_eq!(decrypted_len(MAX_CHUNK_SIZE), Ok(MAX_CHUNK_PLAINTEXT_SIZE));
+}
+
+#[test]
+fn test_initiator_reset_after_failed_attempt() {
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    let mut initiator =
+        Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+
+    // the first message is lost, the caller tries again with the same initiator
+    let first_message = initiator.step_0().unwrap();
+    assert_eq!(initiator.step_0(), Err(Error::HandshakeAlreadyInProgress));
+
+    // a corrupted responder message spends the handshake state
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    let (second_message, _) = responder
+        .step_1_with_now_rng(first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    let mut corrupted = second_message;
+    corrupted[ELLSWIFT_ENCODING_SIZE] ^= 1;
+    assert!(initiator.step_2_with_now(corrupted, now).is_err());
+    assert_eq!(
+        initiator.step_2_with_now(second_message, now).err(),
+        Some(Error::HandshakeAlreadyInProgress)
+    );
+
+    // after a reset, a new attempt succeeds with a new ephemeral key
+    let fingerprint = initiator.ephemeral_key_fingerprint();
+    initiator.reset_with_rng(&mut rand::thread_rng());
+    assert_ne!(initiator.ephemeral_key_fingerprint(), fingerprint);
+    let first_message = initiator.step_0().unwrap();
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    let (second_message, mut codec_responder) = responder
+        .step_1_with_now_rng(first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    let mut codec_initiator = initiator.step_2_with_now(second_message, now).unwrap();
+    let mut message = b"ciao".to_vec();
+    codec_initiator.encrypt(&mut message).unwrap();
+    codec_responder.decrypt(&mut message).unwrap();
+    assert_eq!(message, b"ciao");
+
+    // the handshake is over, a reset is needed before starting another one
+    assert_eq!(initiator.step_0(), Err(Error::HandshakeAlreadyInProgress));
+}
+
+// Runs a handshake against a responder signing its certificates with `delegate`, on behalf of
+// the authority that signed `delegation`, through the slice based steps.
+fn delegated_handshake(
+    authority_pks: Vec<secp256k1::XOnlyPublicKey>,
+    delegate: secp256k1::Keypair,
+    delegation: Delegation,
+    now: u32,
+) -> Result<(NoiseCodec, NoiseCodec), Error> {
+    let mut initiator = match authority_pks.is_empty() {
+        true => Initiator::without_pk_with_rng(&mut rand::thread_rng()).unwrap(),
+        false => {
+            Initiator::with_authority_keys_with_rng(authority_pks, &mut rand::thread_rng()).unwrap()
+        }
+    };
+    let mut responder =
+        Responder::with_delegation_with_rng(delegate, delegation, 3600, &mut rand::thread_rng())
+            .unwrap();
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, codec_responder) = responder
+        .step_1_to_vec(&first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    assert_eq!(second_message.len(), DELEGATED_HANDSHAKE_MESSAGE_SIZE);
+    let codec_initiator = initiator.step_2_from_slice(&second_message, now)?;
+    Ok((codec_initiator, codec_responder))
+}
+
+#[test]
+fn test_delegated_handshake() {
+    let authority = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let delegate = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let authority_pk = authority.x_only_public_key().0;
+    let delegate_pk = delegate.x_only_public_key().0;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    let delegation = Delegation::sign_with_rng(
+        &authority,
+        &delegate_pk,
+        now - 60,
+        now + 86400,
+        &mut rand::thread_rng(),
+    );
+
+    let (mut codec_initiator, mut codec_responder) =
+        delegated_handshake(vec![authority_pk], delegate, delegation, now).unwrap();
+    let certificate = codec_initiator.responder_certificate().unwrap();
+    assert_eq!(certificate.version, DELEGATED_CERTIFICATE_VERSION);
+    assert_eq!(
+        certificate.authority_key_fingerprint,
+        Some(KeyFingerprint::from_key(&authority_pk))
+    );
+    assert_eq!(certificate.authority_key_index, Some(0));
+    assert_eq!(
+        certificate.delegate_key_fingerprint,
+        Some(KeyFingerprint::from_key(&delegate_pk))
+    );
+    let mut message = b"ciao".to_vec();
+    codec_initiator.encrypt(&mut message).unwrap();
+    codec_responder.decrypt(&mut message).unwrap();
+    assert_eq!(message, b"ciao");
+
+    // an initiator without authority keys connects too, without verifying the chain
+    let (codec_initiator, _) = delegated_handshake(vec![], delegate, delegation, now).unwrap();
+    let certificate = codec_initiator.responder_certificate().unwrap();
+    assert_eq!(certificate.authority_key_fingerprint, None);
+    assert_eq!(
+        certificate.delegate_key_fingerprint,
+        Some(KeyFingerprint::from_key(&delegate_pk))
+    );
+
+    // the fixed size step doesn't fit the delegated message
+    let mut initiator = Initiator::new_with_rng(Some(authority_pk), &mut rand::thread_rng());
+    let mut responder =
+        Responder::with_delegation_with_rng(delegate, delegation, 3600, &mut rand::thread_rng())
+            .unwrap();
+    let token = responder
+        .step_1_prepare(&initiator.step_0().unwrap())
+        .unwrap();
+    assert_eq!(
+        responder
+            .step_1_finish(token, now, &mut rand::thread_rng())
+            .err(),
+        Some(Error::UnexpectedHandshakeLength {
+            stage: HandshakeStage::ResponderMessage,
+            expected: DELEGATED_HANDSHAKE_MESSAGE_SIZE,
+            got: INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
+        })
+    );
+}
+
+#[test]
+fn test_plain_handshake_to_vec() {
+    let authority = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    let mut initiator =
+        Initiator::new_with_rng(Some(authority.public_key().into()), &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(authority, 3600, &mut rand::thread_rng());
+    assert_eq!(responder.handshake_layout(), HandshakeLayout::CURRENT);
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, _) = responder
+        .step_1_to_vec(&first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    assert_eq!(
+        second_message.len(),
+        INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE
+    );
+    let codec_initiator = initiator.step_2_from_slice(&second_message, now).unwrap();
+    let certificate = codec_initiator.responder_certificate().unwrap();
+    assert_eq!(certificate.version, 0);
+    assert_eq!(certificate.delegate_key_fingerprint, None);
+}
+
+#[test]
+fn test_delegation_chain_rejected() {
+    let authority = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let other_authority = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let delegate = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let authority_pk = authority.x_only_public_key().0;
+    let delegate_pk = delegate.x_only_public_key().0;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    let delegation = |authority: &secp256k1::Keypair, valid_from, not_valid_after| {
+        Delegation::sign_with_rng(
+            authority,
+            &delegate_pk,
+            valid_from,
+            not_valid_after,
+            &mut rand::thread_rng(),
+        )
+    };
+    let rejected = |result: Result<(NoiseCodec, NoiseCodec), Error>| {
+        matches!(result, Err(Error::InvalidCertificate(_)))
+    };
+
+    // signed by an authority the initiator doesn't know
+    assert!(rejected(delegated_handshake(
+        vec![authority_pk],
+        delegate,
+        delegation(&other_authority, now - 60, now + 60),
+        now
+    )));
+    // the delegation is expired, or not valid yet
+    assert!(rejected(delegated_handshake(
+        vec![authority_pk],
+        delegate,
+        delegation(&authority, now - 120, now - 60),
+        now
+    )));
+    assert!(rejected(delegated_handshake(
+        vec![authority_pk],
+        delegate,
+        delegation(&authority, now + 60, now + 120),
+        now
+    )));
+    // the delegate key is not an authority key by itself
+    assert!(rejected(delegated_handshake(
+        vec![delegate_pk],
+        delegate,
+        delegation(&authority, now - 60, now + 60),
+        now
+    )));
+    // among several authority keys, the one signing the delegation is found
+    let (codec_initiator, _) = delegated_handshake(
+        vec![other_authority.x_only_public_key().0, authority_pk],
+        delegate,
+        delegation(&authority, now - 60, now + 60),
+        now,
+    )
+    .unwrap();
+    assert_eq!(
+        codec_initiator
+            .responder_certificate()
+            .unwrap()
+            .authority_key_index,
+        Some(1)
+    );
+
+    // the delegation must be for the keypair the responder signs with
+    assert_eq!(
+        Responder::with_delegation_with_rng(
+            other_authority,
+            delegation(&authority, now - 60, now + 60),
+            3600,
+            &mut rand::thread_rng()
+        )
+        .err(),
+        Some(Error::DelegateKeyMismatch)
+    );
+}
+
+// A clock moving forward by one millisecond on every read.
+#[derive(Default)]
+struct SteppingClock(AtomicU64);
+
+impl TimeProvider for SteppingClock {
+    fn now(&self) -> Duration {
+        Duration::from_millis(self.0.fetch_add(1, Ordering::SeqCst))
+    }
+}
+
+#[derive(Default)]
+struct TimingsObserver(std::sync::Mutex<Vec<HandshakeTimings>>);
+
+impl HandshakeObserver for TimingsObserver {
+    fn now(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn on_timings(&self, timings: &HandshakeTimings) {
+        self.0.lock().unwrap().push(*timings);
+    }
+}
+
+// Checks that the stages of `timings` are the `steps`, one after the other.
+fn assert_monotonic(timings: &HandshakeTimings, steps: [HandshakeStep; 2]) {
+    let stages = timings.stages();
+    assert_eq!(
+        stages.iter().map(|stage| stage.step).collect::<Vec<_>>(),
+        steps
+    );
+    for stage in stages {
+        assert!(stage.started_at < stage.finished_at);
+    }
+    assert!(stages[0].finished_at <= stages[1].started_at);
+    assert_eq!(
+        timings.local_crypto,
+        stages[0].duration() + stages[1].duration()
+    );
+    assert_eq!(
+        timings.total(),
+        stages[1].finished_at - stages[0].started_at
+    );
+}
+
+#[test]
+fn test_handshake_timings() {
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    let clock = Arc::new(SteppingClock::default());
+    let observer = Arc::new(TimingsObserver::default());
+
+    let mut initiator =
+        Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    initiator.set_time_provider(clock.clone());
+    responder.set_time_provider(clock.clone());
+    initiator.set_handshake_observer(observer.clone());
+    let first_message = initiator.step_0().unwrap();
+    let token = responder.step_1_prepare(&first_message).unwrap();
+    // the admission check of the responder takes a while
+    clock.now();
+    let (second_message, codec_responder) = responder
+        .step_1_finish(token, now, &mut rand::thread_rng())
+        .unwrap();
+    let codec_initiator = initiator.step_2_with_now(second_message, now).unwrap();
+
+    // clock reads: initiator step 0 (0, 1), responder prepare (2, 3), admission check (4),
+    // responder finish (5, 6), initiator step 2 (7, 8)
+    let timings = codec_initiator.handshake_timings().unwrap();
+    assert_monotonic(
+        timings,
+        [HandshakeStep::InitiatorStep0, HandshakeStep::InitiatorStep2],
+    );
+    assert_eq!(timings.local_crypto, Duration::from_millis(2));
+    assert_eq!(timings.waiting(), Duration::from_millis(6));
+
+    let timings = codec_responder.handshake_timings().unwrap();
+    assert_monotonic(
+        timings,
+        [
+            HandshakeStep::ResponderStep1Prepare,
+            HandshakeStep::ResponderStep1Finish,
+        ],
+    );
+    assert_eq!(timings.waiting(), Duration::from_millis(2));
+
+    assert_eq!(
+        observer.0.lock().unwrap().as_slice(),
+        &[*codec_initiator.handshake_timings().unwrap()]
+    );
+
+    // nothing is measured without a time provider
+    let (codec_initiator, codec_responder) = handshake_codecs();
+    assert_eq!(codec_initiator.handshake_timings(), None);
+    assert_eq!(codec_responder.handshake_timings(), None);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_handshake_timings_with_std_clock() {
+    use crate::timings::StdTimeProvider;
+
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let clock = Arc::new(StdTimeProvider::new());
+    let mut initiator =
+        Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    initiator.set_time_provider(clock.clone());
+    responder.set_time_provider(clock);
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, codec_responder) = responder.step_1(first_message).unwrap();
+    let codec_initiator = initiator.step_2(second_message).unwrap();
+
+    for timings in [
+        codec_initiator.handshake_timings().unwrap(),
+        codec_responder.handshake_timings().unwrap(),
+    ] {
+        assert_eq!(timings.stages().len(), 2);
+        assert!(timings.local_crypto > Duration::ZERO);
+        assert!(timings.local_crypto <= timings.total());
+    }
+}
+
+#[test]
+#[cfg(all(feature = "std", feature = "tracing"))]
+#[tracing_test::traced_test]
+fn test_handshake_logs() {
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let mut initiator =
+        Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, _) = responder.step_1(first_message).unwrap();
+    initiator.step_2(second_message).unwrap();
+
+    let ephemeral_key = initiator.ephemeral_key_fingerprint().to_string();
+    let static_key = responder.static_key_fingerprint().to_string();
+    logs_assert(|lines: &[&str]| {
+        let stages = [
+            "initiator step 0: sent ephemeral key".to_string(),
+            "responder step 1: received initiator ephemeral key".to_string(),
+            "responder step 1: completed".to_string(),
+            format!(
+                "initiator step 2: received responder message ({} bytes)",
+                INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE
+            ),
+            "initiator step 2: completed".to_string(),
+        ];
+        let events: Vec<&str> = lines
+            .iter()
+            .copied()
+            .filter(|line| line.contains("Noise handshake"))
+            .collect();
+        if events.len() != stages.len() {
+            return Err(format!(
+                "expected {} events, got {:?}",
+                stages.len(),
+                events
+            ));
+        }
+        for (event, stage) in events.iter().zip(stages.iter()) {
+            if !event.contains("DEBUG") || !event.contains(stage.as_str()) {
+                return Err(format!(
+                    "expected a debug event for {:?}, got {:?}",
+                    stage, event
+                ));
+            }
+        }
+        // the ephemeral key is seen on both sides, the static key on both sides once completed
+        let ephemeral = events.iter().filter(|e| e.contains(&ephemeral_key)).count();
+        let static_ = events.iter().filter(|e| e.contains(&static_key)).count();
+        match (ephemeral, static_) {
+            (2, 2) => Ok(()),
+            _ => Err(format!("unexpected fingerprints in {:?}", events)),
+        }
+    });
+    assert!(logs_contain("cipher ChaCha20-Poly1305"));
+
+    // a failure is reported with its stage
+    let mut initiator = Initiator::new_with_rng(None, &mut rand::thread_rng());
+    initiator.step_0().unwrap();
+    assert!(initiator.step_2_from_slice(&[0; 10], 0).is_err());
+    assert!(logs_contain(
+        "Noise handshake failed at initiator step 2: UnexpectedHandshakeLength"
+    ));
+}
+
+#[test]
+fn test_handshake_messages_with_leftover() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    let authority = Responder::generate_key_with_rng(&mut ChaCha20Rng::seed_from_u64(1));
+    let now = 1_700_000_000;
+    // the same handshake every time, so that the codecs of two runs can be compared
+    let handshake = || {
+        let initiator = Initiator::new_with_rng(
+            Some(authority.public_key().into()),
+            &mut ChaCha20Rng::seed_from_u64(2),
+        );
+        let responder =
+            Responder::new_with_rng(authority, 31449600, &mut ChaCha20Rng::seed_from_u64(3));
+        (initiator, responder)
+    };
+    let pipelined = b"SetupConnection".to_vec();
+
+    // the responder message and the first transport frame delivered in a single read
+    let (mut initiator, mut responder) = handshake();
+    let first_message = initiator.step_0().unwrap();
+    let mut coalesced_first_message = first_message.to_vec();
+    coalesced_first_message.extend_from_slice(&pipelined);
+    let (token, leftover) = responder
+        .step_1_prepare_with_leftover(&coalesced_first_message)
+        .unwrap();
+    assert_eq!(leftover, pipelined);
+    let (second_message, mut coalesced_responder) = responder
+        .step_1_finish(token, now, &mut ChaCha20Rng::seed_from_u64(4))
+        .unwrap();
+    let mut frame = "ciao".as_bytes().to_vec();
+    coalesced_responder.encrypt(&mut frame).unwrap();
+    let mut coalesced = second_message.to_vec();
+    coalesced.extend_from_slice(&frame);
+    let (mut coalesced_initiator, mut leftover) = initiator
+        .step_2_with_leftover(&coalesced, HandshakeLayout::CURRENT, now)
+        .unwrap();
+    assert_eq!(leftover, frame);
+    coalesced_initiator.decrypt(&mut leftover).unwrap();
+    assert_eq!(leftover, "ciao".as_bytes().to_vec());
+
+    // the same messages delivered in separate reads
+    let (mut initiator, mut responder) = handshake();
+    let first_message = initiator.step_0().unwrap();
+    let token = responder.step_1_prepare(&first_message).unwrap();
+    let (split_second_message, mut split_responder) = responder
+        .step_1_finish(token, now, &mut ChaCha20Rng::seed_from_u64(4))
+        .unwrap();
+    assert_eq!(split_second_message, second_message);
+    let mut split_frame = "ciao".as_bytes().to_vec();
+    split_responder.encrypt(&mut split_frame).unwrap();
+    assert_eq!(split_frame, frame);
+    let mut split_initiator = initiator
+        .step_2_from_slice(&split_second_message, now)
+        .unwrap();
+    split_initiator.decrypt(&mut split_frame).unwrap();
+
+    // both initiators end up in the same state
+    let mut coalesced_next = "next".as_bytes().to_vec();
+    coalesced_initiator.encrypt(&mut coalesced_next).unwrap();
+    let mut split_next = "next".as_bytes().to_vec();
+    split_initiator.encrypt(&mut split_next).unwrap();
+    assert_eq!(coalesced_next, split_next);
+
+    // a tolerated legacy prelude, the responder message and the first transport frame delivered
+    // in a single read
+    let (mut initiator, mut responder) = handshake();
+    initiator.set_legacy_prelude(true);
+    responder.set_legacy_prelude_tolerance(true);
+    let mut coalesced_first_message = initiator.step_0_to_vec().unwrap();
+    assert!(coalesced_first_message.starts_with(&LEGACY_HANDSHAKE_PRELUDE));
+    coalesced_first_message.extend_from_slice(&pipelined);
+    let (token, leftover) = responder
+        .step_1_prepare_with_leftover(&coalesced_first_message)
+        .unwrap();
+    assert_eq!(leftover, pipelined);
+    let (prelude_second_message, _) = responder
+        .step_1_finish(token, now, &mut ChaCha20Rng::seed_from_u64(4))
+        .unwrap();
+    assert_eq!(prelude_second_message, second_message);
+    initiator
+        .step_2_from_slice(&prelude_second_message, now)
+        .unwrap();
+
+    // nothing left over on exact messages, and truncated messages are rejected
+    let (mut initiator, mut responder) = handshake();
+    let first_message = initiator.step_0().unwrap();
+    let (token, leftover) = responder
+        .step_1_prepare_with_leftover(&first_message)
+        .unwrap();
+    assert!(leftover.is_empty());
+    let (second_message, _) = responder
+        .step_1_finish(token, now, &mut ChaCha20Rng::seed_from_u64(4))
+        .unwrap();
+    assert!(matches!(
+        initiator.step_2_with_leftover(
+            &second_message[..second_message.len() - 1],
+            HandshakeLayout::CURRENT,
+            now
+        ),
+        Err(Error::UnexpectedHandshakeLength {
+            stage: HandshakeStage::ResponderMessage,
+            expected: INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
+            got,
+        }) if got == INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE - 1
+    ));
+    let (_, leftover) = initiator
+        .step_2_with_leftover(&second_message, HandshakeLayout::CURRENT, now)
+        .unwrap();
+    assert!(leftover.is_empty());
+    assert!(matches!(
+        handshake()
+            .1
+            .step_1_prepare_with_leftover(&first_message[..ELLSWIFT_ENCODING_SIZE - 1]),
+        Err(Error::UnexpectedHandshakeLength {
+            stage: HandshakeStage::InitiatorEphemeralKey,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_decrypt_frame_detects_plaintext_frames() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    // seeded, so that the ciphertexts below don't read as a frame header by chance
+    let authority = Responder::generate_key_with_rng(&mut ChaCha20Rng::seed_from_u64(1));
+    let now = 1_700_000_000;
+    let mut initiator = Initiator::new_with_rng(
+        Some(authority.public_key().into()),
+        &mut ChaCha20Rng::seed_from_u64(2),
+    );
+    let mut responder =
+        Responder::new_with_rng(authority, 31449600, &mut ChaCha20Rng::seed_from_u64(3));
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, mut codec_responder) = responder
+        .step_1_with_now_rng(first_message, now, &mut ChaCha20Rng::seed_from_u64(4))
+        .unwrap();
+    let mut codec_initiator = initiator.step_2_with_now(second_message, now).unwrap();
+
+    // a SetupConnection for the mining protocol, v2 only, to pool.example.com:3333
+    let mut setup_connection = vec![0, 2, 0, 2, 0, 0, 0, 0, 0, 16];
+    setup_connection.extend_from_slice(b"pool.example.com");
+    setup_connection.extend_from_slice(&[0x05, 0x0d, 0, 0, 0, 0]);
+    let mut frame = vec![0x00, 0x00, 0x00];
+    frame.extend_from_slice(&(setup_connection.len() as u32).to_le_bytes()[..3]);
+    frame.extend_from_slice(&setup_connection);
+
+    // the chunk of the encrypted frame header, as read by a decoder
+    let mut plaintext_chunk = frame[..6 + AEAD_MAC_LEN].to_vec();
+    assert_eq!(
+        codec_responder.decrypt_frame(&mut plaintext_chunk),
+        Err(Error::PeerSentPlaintextFrame {
+            extension_type: 0,
+            msg_type: 0,
+            msg_length: setup_connection.len() as u32,
+        })
+    );
+
+    // a tampered ciphertext is still a MAC failure
+    let mut header = frame[..6].to_vec();
+    codec_initiator.encrypt(&mut header).unwrap();
+    let mut tampered = header.clone();
+    tampered[6 + AEAD_MAC_LEN - 1] ^= 1;
+    assert!(matches!(
+        codec_responder.decrypt_frame(&mut tampered),
+        Err(Error::AesGcm(_))
+    ));
+    let mut tampered = header.clone();
+    tampered[0] ^= 1;
+    assert!(matches!(
+        codec_responder.decrypt_frame(&mut tampered),
+        Err(Error::AesGcm(_))
+    ));
+    // as is a chunk too short for a frame header
+    assert!(matches!(
+        codec_responder.decrypt_frame(&mut vec![0; 5]),
+        Err(Error::AesGcm(_))
+    ));
+
+    // the failures leave the codec in sync with its counterpart
+    codec_responder.decrypt_frame(&mut header).unwrap();
+    assert_eq!(header, frame[..6].to_vec());
+}
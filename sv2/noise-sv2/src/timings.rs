@@ -0,0 +1,188 @@
+// # Handshake Timings
+//
+// Measures how long the step functions of a handshake take, so that latency-sensitive deployments
+// (e.g. mining proxies on high-latency links) can tell the local cryptographic work apart from the
+// time spent waiting on the network or on the other party.
+//
+// As for the [`crate::HandshakeObserver`], no clock is assumed: nothing is measured until a
+// [`TimeProvider`] is set with `set_time_provider` on the [`crate::Initiator`] or
+// [`crate::Responder`]. The timings are then attached to the returned [`crate::NoiseCodec`].
+
+use alloc::sync::Arc;
+use core::time::Duration;
+
+/// A source of timestamps for [`HandshakeTimings`].
+pub trait TimeProvider: Send + Sync {
+    /// Returns the current time, as an offset from an arbitrary fixed point.
+    ///
+    /// Must be monotonic: only differences between two values are meaningful.
+    fn now(&self) -> Duration;
+}
+
+/// A [`TimeProvider`] backed by [`std::time::Instant`], counting from its creation.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct StdTimeProvider {
+    origin: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl StdTimeProvider {
+    pub fn new() -> Self {
+        Self {
+            origin: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for StdTimeProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl TimeProvider for StdTimeProvider {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// A step function of the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeStep {
+    /// `Initiator::step_0`.
+    InitiatorStep0,
+    /// `Responder::step_1_prepare`, run by every `step_1` variant.
+    ResponderStep1Prepare,
+    /// `Responder::step_1_finish`, run by every `step_1` variant.
+    ResponderStep1Finish,
+    /// `Initiator::step_2` and its variants.
+    InitiatorStep2,
+}
+
+/// When a step function of the handshake ran, as read from the [`TimeProvider`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageTiming {
+    pub step: HandshakeStep,
+    pub started_at: Duration,
+    pub finished_at: Duration,
+}
+
+impl StageTiming {
+    /// Time spent in the step function.
+    pub fn duration(&self) -> Duration {
+        self.finished_at.saturating_sub(self.started_at)
+    }
+}
+
+// Both sides run two step functions.
+const MAX_STAGES: usize = 2;
+
+const NO_STAGE: StageTiming = StageTiming {
+    step: HandshakeStep::InitiatorStep0,
+    started_at: Duration::ZERO,
+    finished_at: Duration::ZERO,
+};
+
+/// How long one side of a successful handshake took, see [`crate::NoiseCodec::handshake_timings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandshakeTimings {
+    /// Time spent in the step functions of this side, i.e. on the local cryptographic work.
+    pub local_crypto: Duration,
+    stages: [StageTiming; MAX_STAGES],
+    len: usize,
+}
+
+impl HandshakeTimings {
+    const EMPTY: Self = Self {
+        local_crypto: Duration::ZERO,
+        stages: [NO_STAGE; MAX_STAGES],
+        len: 0,
+    };
+
+    /// The step functions run by this side, in order.
+    pub fn stages(&self) -> &[StageTiming] {
+        &self.stages[..self.len]
+    }
+
+    /// Time from the start of the first step function to the end of the last one.
+    pub fn total(&self) -> Duration {
+        match (self.stages().first(), self.stages().last()) {
+            (Some(first), Some(last)) => last.finished_at.saturating_sub(first.started_at),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Time spent between the step functions: the network round trip and the responder work on
+    /// the initiator side, the admission check between `step_1_prepare` and `step_1_finish` on
+    /// the responder side.
+    pub fn waiting(&self) -> Duration {
+        self.total().saturating_sub(self.local_crypto)
+    }
+
+    fn push(&mut self, stage: StageTiming) {
+        if self.len < MAX_STAGES {
+            self.stages[self.len] = stage;
+            self.len += 1;
+            self.local_crypto += stage.duration();
+        }
+    }
+}
+
+// Records the timings of the step functions of a handshake, if a clock is set.
+#[derive(Clone)]
+pub(crate) struct TimingState {
+    clock: Option<Arc<dyn TimeProvider>>,
+    running: Option<(HandshakeStep, Duration)>,
+    timings: HandshakeTimings,
+}
+
+impl Default for TimingState {
+    fn default() -> Self {
+        Self {
+            clock: None,
+            running: None,
+            timings: HandshakeTimings::EMPTY,
+        }
+    }
+}
+
+impl TimingState {
+    pub(crate) fn set_time_provider(&mut self, clock: Arc<dyn TimeProvider>) {
+        self.clock = Some(clock);
+    }
+
+    pub(crate) fn begin(&mut self, step: HandshakeStep) {
+        if let Some(clock) = self.clock.as_ref() {
+            self.running = Some((step, clock.now()));
+        }
+    }
+
+    pub(crate) fn end(&mut self) {
+        if let (Some(clock), Some((step, started_at))) = (self.clock.as_ref(), self.running.take())
+        {
+            self.timings.push(StageTiming {
+                step,
+                started_at,
+                finished_at: clock.now(),
+            });
+        }
+    }
+
+    // Hands out the timings of the completed handshake, if measured.
+    pub(crate) fn take(&mut self) -> Option<HandshakeTimings> {
+        self.clock.as_ref()?;
+        self.running = None;
+        Some(core::mem::replace(
+            &mut self.timings,
+            HandshakeTimings::EMPTY,
+        ))
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.running = None;
+        self.timings = HandshakeTimings::EMPTY;
+    }
+}
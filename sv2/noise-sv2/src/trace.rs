@@ -0,0 +1,47 @@
+// # Handshake Logging
+//
+// Logging macros, forwarding to `tracing` when the `tracing` feature is enabled.
+//
+// Without the feature, every log statement expands to dead code: its arguments are still type
+// checked but never evaluated, and the crate doesn't depend on `tracing` at all.
+//
+// Only non-sensitive metadata is ever logged (stages, lengths, key fingerprints, validity
+// windows), never keys, nonces or handshake hashes.
+
+#[cfg(feature = "tracing")]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        ::tracing::debug!($($arg)+)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! warning {
+    ($($arg:tt)+) => {
+        ::tracing::warn!($($arg)+)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! warning {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+pub(crate) use {debug, warning};
+
+/// Name of the cipher the transport messages are encrypted with, the only one negotiated by the
+/// current protocol revision.
+pub(crate) const TRANSPORT_CIPHER: &str = "ChaCha20-Poly1305";
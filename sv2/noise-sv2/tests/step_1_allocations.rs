@@ -0,0 +1,80 @@
+// Counts the heap allocations made by the responder while answering a handshake.
+//
+// The counting allocator is global to this test binary, so it only counts on the thread that
+// enabled it.
+use noise_sv2::{Initiator, Responder};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use secp256k1::{Keypair, Secp256k1};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+struct CountingAllocator;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // `try_with`, as thread locals may already be destroyed while a thread exits
+        let _ = COUNTING.try_with(|counting| {
+            if counting.get() {
+                let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+            }
+        });
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    ALLOCATIONS.with(|allocations| allocations.set(0));
+    COUNTING.with(|counting| counting.set(true));
+    let result = f();
+    COUNTING.with(|counting| counting.set(false));
+    (result, ALLOCATIONS.with(|allocations| allocations.get()))
+}
+
+fn authority() -> Keypair {
+    let secp = Secp256k1::new();
+    let mut rng = ChaCha20Rng::seed_from_u64(1);
+    loop {
+        let (secret_key, _) = secp.generate_keypair(&mut rng);
+        let kp = Keypair::from_secret_key(&secp, &secret_key);
+        if kp.x_only_public_key().1 == secp256k1::Parity::Even {
+            return kp;
+        }
+    }
+}
+
+#[test]
+fn test_step_1_does_not_allocate() {
+    let authority = authority();
+    let now = 1_700_000_000;
+    let mut initiator = Initiator::new_with_rng(
+        Some(authority.public_key().into()),
+        &mut ChaCha20Rng::seed_from_u64(2),
+    );
+    let first_message = initiator.step_0().unwrap();
+    let mut responder =
+        Responder::new_with_rng(authority, 31449600, &mut ChaCha20Rng::seed_from_u64(3));
+    let mut rng = ChaCha20Rng::seed_from_u64(4);
+
+    let (result, allocations) =
+        count_allocations(|| responder.step_1_with_now_rng(first_message, now, &mut rng));
+    assert_eq!(allocations, 0);
+
+    // the handshake is still a valid one
+    let (second_message, _) = result.unwrap();
+    initiator.step_2_with_now(second_message, now).unwrap();
+}
@@ -28,6 +28,7 @@ use binary_sv2::{B032, U256};
 use core::{
     cmp::{Ord, PartialOrd},
     convert::TryInto,
+    fmt,
 };
 
 #[macro_use]
@@ -113,16 +114,149 @@ pub const CHANNEL_BIT_UPDATE_CHANNEL_ERROR: bool = true;
 pub const MAX_EXTRANONCE_LEN: usize = 32;
 
 /// Target is a 256-bit unsigned integer in little-endian
+///
+/// When built from a 32-byte array (or a [`U256`]), byte `0` is the least significant and byte
+/// `31` is the most significant. This is the same byte order used by Sv2 on the wire and by
+/// `bitcoin::BlockHash` internally, so a header hash can be compared against a `Target` directly.
+///
+/// Human readable representations (e.g. block explorers, logs) usually print targets in
+/// big-endian, so that leading zeros show up on the left. [`Target::from_be_bytes`] and
+/// [`Target::to_be_bytes`] should be used when dealing with that representation, instead of
+/// manually reversing arrays.
+///
+/// Ordering is numeric: a smaller `Target` is harder to meet.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Target {
     head: u128, // least significant bits
     tail: u128, // most significant bits
 }
 
+// 2^128, as `f64::powi` is not available in `core`
+const TWO_POW_128: f64 = 340282366920938463463374607431768211456.0;
+
 impl Target {
+    /// The difficulty-1 target, i.e. the target of the genesis block:
+    /// `0x00000000ffff0000000000000000000000000000000000000000000000000000` (big-endian).
+    pub const DIFF1: Target = Target {
+        head: 0,
+        tail: 0xffff << 80,
+    };
+
+    /// The highest (easiest to meet) target.
+    pub const MAX: Target = Target {
+        head: u128::MAX,
+        tail: u128::MAX,
+    };
+
     pub fn new(head: u128, tail: u128) -> Self {
         Self { head, tail }
     }
+
+    /// Creates a `Target` from its little-endian representation (byte `31` is the most
+    /// significant).
+    pub fn from_le_bytes(bytes: [u8; 32]) -> Self {
+        bytes.into()
+    }
+
+    /// Creates a `Target` from a slice of its little-endian representation, e.g. a field of a
+    /// message decoded by hand.
+    ///
+    /// Fails unless the slice is exactly 32 bytes long.
+    pub fn try_from_slice(bytes: &[u8]) -> Result<Self, TargetError> {
+        let bytes: [u8; 32] = bytes.try_into().map_err(|_| TargetError::InvalidLength {
+            expected: 32,
+            actual: bytes.len(),
+        })?;
+        Ok(Self::from_le_bytes(bytes))
+    }
+
+    /// Creates a `Target` from its big-endian representation (byte `0` is the most significant).
+    pub fn from_be_bytes(mut bytes: [u8; 32]) -> Self {
+        bytes.reverse();
+        Self::from_le_bytes(bytes)
+    }
+
+    /// Returns the little-endian representation of the `Target` (byte `31` is the most
+    /// significant).
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        bytes[0..16].copy_from_slice(&self.head.to_le_bytes());
+        bytes[16..32].copy_from_slice(&self.tail.to_le_bytes());
+        bytes
+    }
+
+    /// Returns the big-endian representation of the `Target` (byte `0` is the most significant).
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = self.to_le_bytes();
+        bytes.reverse();
+        bytes
+    }
+
+    /// Returns the difficulty of the `Target`, relative to [`Target::DIFF1`].
+    pub fn difficulty(&self) -> f64 {
+        Self::DIFF1.to_f64() / self.to_f64()
+    }
+
+    /// Creates the `Target` matching `difficulty`, relative to [`Target::DIFF1`].
+    ///
+    /// Difficulties too low to be represented, including non-positive ones, yield
+    /// [`Target::MAX`].
+    pub fn from_difficulty(difficulty: f64) -> Self {
+        if difficulty.is_nan() || difficulty <= 0.0 {
+            return Self::MAX;
+        }
+        let target = Self::DIFF1.to_f64() / difficulty;
+        // float to int casts saturate, so targets above `Target::MAX` yield `Target::MAX`
+        let tail = (target / TWO_POW_128) as u128;
+        let head = (target - tail as f64 * TWO_POW_128) as u128;
+        Self { head, tail }
+    }
+
+    fn to_f64(&self) -> f64 {
+        self.tail as f64 * TWO_POW_128 + self.head as f64
+    }
+
+    /// Returns `true` if `self` and `other` differ by less than `2^tolerance_bits`.
+    ///
+    /// Useful to ignore differences caused by lossy representations of a target, such as targets
+    /// derived from difficulty floats or rounded to a compact `nbits` value. A `tolerance_bits`
+    /// of `0` is the same as strict equality.
+    pub fn approx_eq(&self, other: &Target, tolerance_bits: u8) -> bool {
+        let (high, low) = if self >= other {
+            (self, other)
+        } else {
+            (other, self)
+        };
+
+        // 256-bit subtraction, borrowing from the tail if the head underflows
+        let (head, borrow) = high.head.overflowing_sub(low.head);
+        let tail = high.tail - low.tail - borrow as u128;
+
+        if tolerance_bits >= 128 {
+            tail >> (tolerance_bits - 128) == 0
+        } else {
+            tail == 0 && head >> tolerance_bits == 0
+        }
+    }
+}
+
+/// Why a [`Target`] could not be built out of a byte slice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetError {
+    /// The slice does not hold the 32 bytes of a target.
+    InvalidLength { expected: usize, actual: usize },
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength { expected, actual } => write!(
+                f,
+                "a target is {} bytes long, got {} bytes",
+                expected, actual
+            ),
+        }
+    }
 }
 
 impl From<[u8; 32]> for Target {
@@ -165,6 +299,7 @@ impl PartialOrd for Target {
     }
 }
 
+// `tail` holds the most significant bits, so it must be compared first.
 impl Ord for Target {
     fn cmp(&self, other: &Self) -> core::cmp::Ordering {
         if self.tail == other.tail && self.head == other.head {
@@ -740,7 +875,7 @@ fn increment_bytes_be(bs: &mut [u8]) -> Result<(), ()> {
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use alloc::vec::Vec;
+    use alloc::{string::ToString, vec::Vec};
     use quickcheck_macros;
 
     #[test]
@@ -1201,6 +1336,151 @@ pub mod tests {
         target_start == target_final
     }
 
+    #[test]
+    fn test_ord_for_target_boundaries() {
+        let zero = Target::from_be_bytes([0; 32]);
+        let max = Target::from_be_bytes([0xff; 32]);
+        assert!(zero < max);
+        assert_eq!(zero.cmp(&zero.clone()), core::cmp::Ordering::Equal);
+        assert_eq!(max.cmp(&max.clone()), core::cmp::Ordering::Equal);
+
+        // differing only in the least significant byte
+        let mut low = [0x11; 32];
+        let mut high = [0x11; 32];
+        low[31] = 0x00;
+        high[31] = 0x01;
+        assert!(Target::from_be_bytes(low) < Target::from_be_bytes(high));
+
+        // differing only in the most significant byte
+        let mut low = [0xff; 32];
+        let mut high = [0x00; 32];
+        low[0] = 0x00;
+        high[0] = 0x01;
+        assert!(Target::from_be_bytes(low) < Target::from_be_bytes(high));
+
+        // leading zero bytes make a target smaller (i.e. harder)
+        let mut easy = [0xff; 32];
+        let mut hard = [0xff; 32];
+        easy[..4].copy_from_slice(&[0, 0, 0, 0]);
+        hard[..5].copy_from_slice(&[0, 0, 0, 0, 0]);
+        assert!(Target::from_be_bytes(hard) < Target::from_be_bytes(easy));
+
+        // differing only across the head/tail boundary
+        let mut low = [0; 32];
+        let mut high = [0; 32];
+        low[16] = 0xff; // most significant byte of head
+        high[15] = 0x01; // least significant byte of tail
+        assert!(Target::from_be_bytes(low) < Target::from_be_bytes(high));
+    }
+
+    #[test]
+    fn test_target_little_endian_byte_order() {
+        // byte 31 is the most significant in the little-endian representation
+        let mut le = [0; 32];
+        le[31] = 0x01;
+        let mut be = [0; 32];
+        be[0] = 0x01;
+        assert_eq!(Target::from(le), Target::from_be_bytes(be));
+        assert_eq!(Target::from(le), Target::new(0, 1 << 120));
+
+        let u256: U256 = le.into();
+        assert_eq!(Target::from(u256), Target::from_le_bytes(le));
+    }
+
+    #[test]
+    fn test_target_try_from_slice() {
+        let mut bytes = [0; 33];
+        bytes[31] = 0x01;
+        assert_eq!(
+            Target::try_from_slice(&bytes[..32]),
+            Ok(Target::new(0, 1 << 120))
+        );
+
+        assert_eq!(
+            Target::try_from_slice(&bytes[..31]),
+            Err(TargetError::InvalidLength {
+                expected: 32,
+                actual: 31
+            })
+        );
+        let error = Target::try_from_slice(&bytes).unwrap_err();
+        assert_eq!(
+            error,
+            TargetError::InvalidLength {
+                expected: 32,
+                actual: 33
+            }
+        );
+        assert_eq!(error.to_string(), "a target is 32 bytes long, got 33 bytes");
+        assert!(Target::try_from_slice(&[]).is_err());
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn test_ord_for_target_matches_be_bytes_ord(a: Vec<u8>, b: Vec<u8>) -> bool {
+        let a = from_arbitrary_vec_to_array(a);
+        let b = from_arbitrary_vec_to_array(b);
+        // lexicographic ordering of big-endian bytes is numeric ordering
+        Target::from_be_bytes(a).cmp(&Target::from_be_bytes(b)) == a.cmp(&b)
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn test_target_be_le_bytes_round_trip(input: Vec<u8>) -> bool {
+        let bytes = from_arbitrary_vec_to_array(input);
+        let mut reversed = bytes;
+        reversed.reverse();
+        Target::from_be_bytes(bytes).to_be_bytes() == bytes
+            && Target::from_le_bytes(bytes).to_le_bytes() == bytes
+            && Target::from_be_bytes(bytes) == Target::from_le_bytes(reversed)
+    }
+
+    #[test]
+    fn test_target_constants() {
+        let mut diff1 = [0; 32];
+        diff1[4] = 0xff;
+        diff1[5] = 0xff;
+        assert_eq!(Target::DIFF1.to_be_bytes(), diff1);
+        assert_eq!(Target::MAX.to_le_bytes(), [0xff; 32]);
+
+        assert_eq!(Target::DIFF1.difficulty(), 1.0);
+        assert_eq!(Target::from_difficulty(1.0), Target::DIFF1);
+        assert_eq!(Target::from_difficulty(2.0).difficulty(), 2.0);
+        assert!(Target::from_difficulty(2.0) < Target::DIFF1);
+
+        assert_eq!(Target::from_difficulty(1e-80), Target::MAX);
+        assert_eq!(Target::from_difficulty(0.0), Target::MAX);
+        assert_eq!(Target::from_difficulty(-1.0), Target::MAX);
+        assert_eq!(Target::from_difficulty(f64::NAN), Target::MAX);
+    }
+
+    #[test]
+    fn test_target_approx_eq() {
+        let target = Target::new(1000, 1);
+
+        // strict equality
+        assert!(target.approx_eq(&target, 0));
+        assert!(!target.approx_eq(&Target::new(1001, 1), 0));
+
+        // differences below the tolerance, in both directions
+        assert!(target.approx_eq(&Target::new(1015, 1), 4));
+        assert!(Target::new(1015, 1).approx_eq(&target, 4));
+        assert!(!target.approx_eq(&Target::new(1016, 1), 4));
+
+        // differences across the head/tail boundary
+        let below = Target::new(u128::MAX, 0);
+        let above = Target::new(0, 1);
+        assert!(below.approx_eq(&above, 1));
+        assert!(!below.approx_eq(&Target::new(1, 1), 1));
+
+        // differences on the most significant bits
+        let max = Target::new(u128::MAX, u128::MAX);
+        let zero = Target::new(0, 0);
+        assert!(!max.approx_eq(&zero, 255));
+        let half_max = Target::new(u128::MAX, (u128::MAX >> 1) + 1);
+        assert!(max.approx_eq(&half_max, 255));
+        assert!(!max.approx_eq(&half_max, 254));
+        assert!(!Target::new(0, 1 << 127).approx_eq(&zero, 255));
+    }
+
     #[quickcheck_macros::quickcheck]
     fn test_vec_from_extranonce(input: Vec<u8>) -> bool {
         let input_start = from_arbitrary_vec_to_array(input).to_vec();